@@ -0,0 +1,258 @@
+//! Install of a cartridge in EmulationStation (RetroPie), as a game of the
+//! "ports" system: a launch script running the devkit in fullscreen, the
+//! cartridge and its label in a `unicorn` directory, and an entry of the
+//! `gamelist.xml` of the system. The ports system runs the scripts, so
+//! nothing has to be declared in `es_systems.cfg`.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use unicorn::cartridge::Cartridge;
+
+/// Roms of the ports system of RetroPie, in the home directory
+pub const PORTS_DIR: &'static str = "RetroPie/roms/ports";
+/// Directory of the cartridges and of their labels, in the ports
+const DATA_DIR: &'static str = "unicorn";
+/// The labels are 128x128, shown at 512x512
+const LABEL_SCALE: u32 = 4;
+
+const GAMELIST_HEADER: &'static str = "<?xml version=\"1.0\"?>\n<gameList>\n";
+const GAMELIST_FOOTER: &'static str = "</gameList>\n";
+
+/// Default directory of the ports, from the home directory
+pub fn default_ports_dir() -> Result<String, String> {
+    match env::var("HOME") {
+        Ok(home) => Ok(Path::new(&home).join(PORTS_DIR).to_string_lossy().into_owned()),
+        Err(_) => Err("No HOME directory to find the RetroPie roms, give the directory of the ports".to_string()),
+    }
+}
+
+fn xml_escape(value: &str) -> String {
+    value.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Entry of a game in a `gamelist.xml`, the paths are relative to the
+/// directory of the system
+fn game_entry(path: &str, name: &str, image: &str) -> String {
+    format!("\t<game>\n\t\t<path>{}</path>\n\t\t<name>{}</name>\n\t\t<image>{}</image>\n\t</game>\n",
+            xml_escape(path),
+            xml_escape(name),
+            xml_escape(image))
+}
+
+/// Start of the next `<game>` tag, with or without attributes
+fn find_game(gamelist: &str) -> Option<usize> {
+    gamelist.match_indices("<game")
+        .map(|(idx, _)| idx)
+        .find(|idx| match gamelist.as_bytes().get(idx + "<game".len()) {
+            Some(&b'>') | Some(&b' ') | Some(&b'\t') | Some(&b'\n') => true,
+            _ => false,
+        })
+}
+
+/// The gamelist with the entry, replacing the previous entry of the same
+/// path. The other games (and the scraped data) are kept as they are.
+fn update_gamelist(gamelist: &str, path: &str, name: &str, image: &str) -> String {
+    let entry = game_entry(path, name, image);
+    let path_tag = format!("<path>{}</path>", xml_escape(path));
+
+    let end = match gamelist.rfind("</gameList>") {
+        Some(end) => end,
+        None => return format!("{}{}{}", GAMELIST_HEADER, entry, GAMELIST_FOOTER),
+    };
+
+    // Games of the list, the text between them is copied
+    let mut output = String::new();
+    let mut rest = &gamelist[..end];
+    loop {
+        let start = match find_game(rest) {
+            Some(start) => start,
+            None => break,
+        };
+        let stop = match rest[start..].find("</game>") {
+            Some(stop) => start + stop + "</game>".len(),
+            None => break,
+        };
+
+        let game = &rest[start..stop];
+        if game.contains(&path_tag) {
+            // The indentation of the previous entry is replaced by the new one
+            output.push_str(rest[..start].trim_right_matches(|c: char| c == ' ' || c == '\t'));
+            rest = rest[stop..].trim_left_matches(|c: char| c == '\r' || c == '\n');
+        } else {
+            output.push_str(&rest[..stop]);
+            rest = &rest[stop..];
+        }
+    }
+    output.push_str(rest);
+
+    if !output.ends_with('\n') {
+        output.push('\n');
+    }
+    output.push_str(&entry);
+    output.push_str(&gamelist[end..]);
+    output
+}
+
+/// Word of the shell with nothing expanded in it, the quotes are closed
+/// around the ones of the path
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', "'\\''"))
+}
+
+/// Script of the ports system: the devkit finds its config files from the
+/// directory where it was installed
+fn launch_script(devkit: &Path, working_dir: &Path, cartridge: &Path) -> String {
+    format!("#!/bin/bash\n# Installed by the Unicorn Console devkit\ncd {} && {} -f {}\n",
+            shell_quote(working_dir),
+            shell_quote(devkit),
+            shell_quote(cartridge))
+}
+
+fn write_file(path: &Path, data: &[u8]) -> Result<(), String> {
+    match File::create(path) {
+        Ok(mut f) => {
+            match f.write_all(data) {
+                Ok(_) => Ok(()),
+                Err(e) => Err(format!("Failed to write {:?}: {}", path, e)),
+            }
+        }
+        Err(e) => Err(format!("Failed to create {:?}: {}", path, e)),
+    }
+}
+
+/// Copy the cartridge in `ports_dir` with its launch script, its label, and
+/// its entry in the gamelist. Return the path of the script.
+pub fn install(filename: &str, ports_dir: &str) -> Result<PathBuf, String> {
+    let path = Path::new(filename);
+    let extension = path.extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or(String::new());
+    let cartridge = match extension.as_str() {
+        "uni" => Cartridge::from_unicorn_file(filename),
+        "png" => Cartridge::from_png_file(filename),
+        _ => return Err("Only the .uni and .png cartridges could be installed in EmulationStation".to_string()),
+    };
+    let cartridge = match cartridge {
+        Ok(cartridge) => cartridge,
+        Err(e) => return Err(format!("Failed to load {:?}: {:?}", filename, e)),
+    };
+
+    let (devkit, working_dir) = match (env::current_exe(), env::current_dir()) {
+        (Ok(devkit), Ok(working_dir)) => (devkit, working_dir),
+        (Err(e), _) | (_, Err(e)) => return Err(format!("Failed to find the devkit: {}", e)),
+    };
+
+    let ports_dir = Path::new(ports_dir);
+    let data_dir = ports_dir.join(DATA_DIR);
+    if let Err(e) = fs::create_dir_all(data_dir.join("images")) {
+        return Err(format!("Failed to create {:?}: {}", data_dir, e));
+    }
+    info!("[EXPORT] Installing {:?} in {:?}", filename, ports_dir);
+
+    let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().into_owned();
+    let title = path.file_stem().unwrap_or(path.as_os_str()).to_string_lossy().into_owned();
+
+    // Absolute, the script is run from the devkit directory
+    let installed = data_dir.join(&name);
+    if let Err(e) = fs::copy(path, &installed) {
+        return Err(format!("Failed to copy {:?}: {}", filename, e));
+    }
+    let installed = match installed.canonicalize() {
+        Ok(installed) => installed,
+        Err(e) => return Err(format!("Failed to find {:?}: {}", installed, e)),
+    };
+
+    let label = format!("{}/images/{}.png", DATA_DIR, title);
+    try!(cartridge.save_label(&ports_dir.join(&label).to_string_lossy(), LABEL_SCALE));
+
+    let script_name = format!("{}.sh", title);
+    let script = ports_dir.join(&script_name);
+    try!(write_file(&script, launch_script(&devkit, &working_dir, &installed).as_bytes()));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Err(e) = fs::set_permissions(&script, fs::Permissions::from_mode(0o755)) {
+            return Err(format!("Failed to make {:?} executable: {}", script, e));
+        }
+    }
+
+    let gamelist_path = ports_dir.join("gamelist.xml");
+    let mut gamelist = String::new();
+    if let Ok(mut f) = File::open(&gamelist_path) {
+        if let Err(e) = f.read_to_string(&mut gamelist) {
+            return Err(format!("Failed to read {:?}: {}", gamelist_path, e));
+        }
+    }
+    let gamelist = update_gamelist(&gamelist,
+                                   &format!("./{}", script_name),
+                                   &title,
+                                   &format!("./{}", label));
+    try!(write_file(&gamelist_path, gamelist.as_bytes()));
+
+    Ok(script)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{find_game, launch_script, update_gamelist, xml_escape, GAMELIST_FOOTER,
+                GAMELIST_HEADER};
+
+    #[test]
+    fn test_xml_escape() {
+        assert_eq!(xml_escape("Tom & Jerry's <\"best\">"),
+                   "Tom &amp; Jerry&apos;s &lt;&quot;best&quot;&gt;");
+        assert_eq!(xml_escape("jump"), "jump");
+    }
+
+    #[test]
+    fn test_find_game() {
+        assert_eq!(find_game("<gameList>\n\t<game>"), Some(12));
+        assert_eq!(find_game("<gameList><game id=\"1\">"), Some(10));
+        // Not the tags starting with "game"
+        assert_eq!(find_game("<gameList><gamepad/>"), None);
+        assert_eq!(find_game("<gameList><gamepad/><game>"), Some(20));
+    }
+
+    #[test]
+    fn test_update_gamelist() {
+        let gamelist = update_gamelist("", "./jump.sh", "jump", "./unicorn/images/jump.png");
+        assert_eq!(gamelist,
+                   format!("{}\t<game>\n\t\t<path>./jump.sh</path>\n\t\t<name>jump</name>\n\t\t\
+                            <image>./unicorn/images/jump.png</image>\n\t</game>\n{}",
+                           GAMELIST_HEADER,
+                           GAMELIST_FOOTER));
+
+        // The scraped games are kept, the previous entry replaced
+        let scraped = "<?xml version=\"1.0\"?>\n<gameList>\n\t<game id=\"7\">\n\t\t<path>./doom.sh</path>\n\t\t\
+                       <rating>0.9</rating>\n\t</game>\n\t<game>\n\t\t<path>./jump.sh</path>\n\t\t\
+                       <name>old</name>\n\t</game>\n</gameList>\n";
+        let gamelist = update_gamelist(scraped, "./jump.sh", "jump & run", "./unicorn/images/jump.png");
+        assert!(gamelist.contains("<rating>0.9</rating>"));
+        assert!(!gamelist.contains("<name>old</name>"));
+        assert_eq!(gamelist.matches("<path>./jump.sh</path>").count(), 1);
+        assert!(gamelist.contains("<name>jump &amp; run</name>"));
+        assert!(gamelist.ends_with("\t</game>\n</gameList>\n"));
+
+        // Installed again, the same list
+        assert_eq!(update_gamelist(&gamelist, "./jump.sh", "jump & run", "./unicorn/images/jump.png"),
+                   gamelist);
+    }
+
+    #[test]
+    fn test_launch_script() {
+        let script = launch_script(Path::new("/opt/unicorn/uc-devkit"),
+                                   Path::new("/opt/unicorn"),
+                                   Path::new("/home/pi/ports/unicorn/$(rm -rf ~)'s \"`x`\".uni"));
+        assert_eq!(script.lines().last().unwrap(),
+                   "cd '/opt/unicorn' && '/opt/unicorn/uc-devkit' -f \
+                    '/home/pi/ports/unicorn/$(rm -rf ~)'\\''s \"`x`\".uni'");
+    }
+}
@@ -0,0 +1,421 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use unicorn::cartridge::Cartridge;
+use unicorn::unicorn::{VERSION, MAJOR_VERSION, MINOR_VERSION};
+use unicorn::unicorn::libraries::Libraries;
+use unicorn::unicorn::preview::render_label;
+
+/// Files of the emscripten build of the devkit copied next to the page
+pub const WEB_RUNTIME_FILES: [&'static str; 2] = ["uc-devkit.js", "uc-devkit.wasm"];
+
+/// Magic at the end of an executable with an embedded cartridge, after the
+/// data of the cartridge, its name, and their lengths
+pub const NATIVE_CART_MAGIC: &'static [u8] = b"UNICART1";
+
+const NATIVE_TRAILER_SIZE: u64 = 16;
+
+const BASE64_CHARS: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Page booting the cartridge embedded in base64: it is written in the
+/// emscripten file system before `main`, and given as argument
+const HTML_TEMPLATE: &'static str = r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8" />
+    <title>%TITLE%</title>
+    <style>
+        html, body { margin: 0; height: 100%; background-color: black; }
+        #canvas {
+            display: block;
+            margin: auto;
+            max-width: 100%;
+            max-height: 100%;
+            image-rendering: pixelated;
+            image-rendering: crisp-edges;
+        }
+    </style>
+</head>
+<body>
+    <canvas id="canvas" oncontextmenu="event.preventDefault()"></canvas>
+    <script>
+        var CARTRIDGE = "%CARTRIDGE%";
+        var CARTRIDGE_DATA = "%DATA%";
+
+        var Module = {
+            arguments: [CARTRIDGE],
+            canvas: document.getElementById("canvas"),
+            preRun: [function() {
+                var data = atob(CARTRIDGE_DATA);
+                var bytes = new Uint8Array(data.length);
+                for (var i = 0; i < data.length; i++) {
+                    bytes[i] = data.charCodeAt(i);
+                }
+                FS.writeFile(CARTRIDGE, bytes);
+            }],
+            print: function(text) { console.log(text); },
+            printErr: function(text) { console.error(text); }
+        };
+    </script>
+    <script src="uc-devkit.js"></script>
+</body>
+</html>
+"#;
+
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut output = String::with_capacity((data.len() + 2) / 3 * 4);
+
+    for chunk in data.chunks(3) {
+        let b0 = chunk[0] as u32;
+        let b1 = *chunk.get(1).unwrap_or(&0) as u32;
+        let b2 = *chunk.get(2).unwrap_or(&0) as u32;
+        let value = (b0 << 16) | (b1 << 8) | b2;
+
+        output.push(BASE64_CHARS[(value >> 18) as usize & 0x3f] as char);
+        output.push(BASE64_CHARS[(value >> 12) as usize & 0x3f] as char);
+        output.push(if chunk.len() > 1 { BASE64_CHARS[(value >> 6) as usize & 0x3f] as char } else { '=' });
+        output.push(if chunk.len() > 2 { BASE64_CHARS[value as usize & 0x3f] as char } else { '=' });
+    }
+
+    output
+}
+
+/// Export the cartridge in `output_dir` with the emscripten build of
+/// `runtime_dir`, the directory could be published as is
+pub fn export_html(filename: &str,
+                   runtime_dir: &str,
+                   library_dir: &str,
+                   output_dir: &str)
+                   -> Result<(), String> {
+    info!("[EXPORT] Exporting {:?} in {:?} with the runtime {:?}", filename, output_dir, runtime_dir);
+
+    let (cartridge, data) = try!(cartridge_data(filename, library_dir));
+
+    if let Err(e) = fs::create_dir_all(output_dir) {
+        return Err(format!("Failed to create {:?}: {}", output_dir, e));
+    }
+
+    for name in WEB_RUNTIME_FILES.iter() {
+        let source = Path::new(runtime_dir).join(name);
+        if !source.exists() {
+            return Err(format!("Missing {:?}, build the devkit with `cargo build --release --target wasm32-unknown-emscripten` first",
+                               source));
+        }
+
+        if let Err(e) = fs::copy(&source, Path::new(output_dir).join(name)) {
+            return Err(format!("Failed to copy {:?}: {}", source, e));
+        }
+    }
+
+    let path = Path::new(filename);
+    let title = path.file_stem().unwrap_or(path.as_os_str()).to_string_lossy().into_owned();
+
+    let page = HTML_TEMPLATE.replace("%TITLE%", &title)
+        .replace("%CARTRIDGE%", &cartridge)
+        .replace("%DATA%", &base64_encode(&data));
+
+    let index = Path::new(output_dir).join("index.html");
+    match File::create(&index) {
+        Ok(mut f) => {
+            if let Err(e) = f.write_all(page.as_bytes()) {
+                return Err(format!("Failed to write {:?}: {}", index, e));
+            }
+        }
+        Err(e) => return Err(format!("Failed to create {:?}: {}", index, e)),
+    }
+
+    Ok(())
+}
+
+/// Name and data of the exported cartridge. The shared libraries required
+/// by the code are embedded in it (the exports run without the library
+/// folder), and a cartridge without a label gets the one rendered from its
+/// first frames (like a save from the editor). A changed cartridge, and the
+/// .uc/.duc ones whose code and data are in other files, are exported in the
+/// .uni format.
+fn cartridge_data(filename: &str, library_dir: &str) -> Result<(String, Vec<u8>), String> {
+    let path = Path::new(filename);
+    let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().into_owned();
+    let data = try!(read_file(filename));
+
+    let (cartridge, split) = if Cartridge::is_png_file(filename) {
+        (Cartridge::from_png_raw(filename, data.clone()), false)
+    } else if filename.contains(".uni") {
+        (Cartridge::from_uni_raw(filename, data.clone()), false)
+    } else if filename.contains(".uc") {
+        (Cartridge::from_unicorn_splitted_file(filename), true)
+    } else if filename.contains(".duc") {
+        (Cartridge::from_dunicorn_file(filename), true)
+    } else {
+        return Ok((name, data));
+    };
+    let mut cartridge = match cartridge {
+        Ok(cartridge) => cartridge,
+        Err(e) => return Err(format!("Failed to load {:?}: {:?}", filename, e)),
+    };
+
+    let mut libraries = Libraries::new();
+    libraries.set_dir(library_dir);
+    let code = cartridge.code.get_data();
+    let mut changed = match libraries.embed(&code, &mut cartridge.libs) {
+        Ok(count) => split || count > 0,
+        Err(e) => return Err(format!("Failed to embed the libraries of {:?}: {}", filename, e)),
+    };
+
+    if cartridge.label.is_empty() {
+        match render_label(&mut cartridge) {
+            Some(label) => {
+                cartridge.label = label;
+                changed = true;
+            }
+            None => warn!("[EXPORT] No label rendered for {:?}", filename),
+        }
+    }
+
+    if !changed {
+        return Ok((name, data));
+    }
+
+    let stem = path.file_stem().unwrap_or(path.as_os_str()).to_string_lossy().into_owned();
+    let version = format!("{:?}.{:?}.{:?}", VERSION, MAJOR_VERSION, MINOR_VERSION);
+    Ok((format!("{}.uni", stem), cartridge.get_unicorn_data(&version).into_bytes()))
+}
+
+fn read_file(filename: &str) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    match File::open(filename) {
+        Ok(mut f) => {
+            if let Err(e) = f.read_to_end(&mut data) {
+                return Err(format!("Failed to read {:?}: {}", filename, e));
+            }
+        }
+        Err(e) => return Err(format!("Failed to open {:?}: {}", filename, e)),
+    }
+    Ok(data)
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    ((data[0] as u32) << 24) | ((data[1] as u32) << 16) | ((data[2] as u32) << 8) | (data[3] as u32)
+}
+
+fn write_u32(value: u32) -> [u8; 4] {
+    [(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]
+}
+
+/// Name and data of the cartridge appended to the file, with the size of the
+/// file without them
+fn read_embedded<R: Read + Seek>(f: &mut R) -> Option<(String, Vec<u8>, u64)> {
+    let size = match f.seek(SeekFrom::End(0)) {
+        Ok(size) => size,
+        Err(_) => return None,
+    };
+    if size < NATIVE_TRAILER_SIZE {
+        return None;
+    }
+
+    let mut trailer = [0u8; NATIVE_TRAILER_SIZE as usize];
+    if f.seek(SeekFrom::Start(size - NATIVE_TRAILER_SIZE)).is_err() || f.read_exact(&mut trailer).is_err() {
+        return None;
+    }
+    if &trailer[8..] != NATIVE_CART_MAGIC {
+        return None;
+    }
+
+    let name_len = read_u32(&trailer[0..4]) as u64;
+    let data_len = read_u32(&trailer[4..8]) as u64;
+    if name_len + data_len + NATIVE_TRAILER_SIZE > size {
+        return None;
+    }
+
+    let start = size - NATIVE_TRAILER_SIZE - name_len - data_len;
+    let mut content = vec![0u8; (name_len + data_len) as usize];
+    if f.seek(SeekFrom::Start(start)).is_err() || f.read_exact(&mut content).is_err() {
+        return None;
+    }
+
+    let name = content.split_off(data_len as usize);
+    Some((String::from_utf8_lossy(&name).into_owned(), content, start))
+}
+
+/// Cartridge embedded in the running executable by `export_native`
+pub fn embedded_cartridge() -> Option<(String, Vec<u8>)> {
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(_) => return None,
+    };
+
+    match File::open(&exe) {
+        Ok(mut f) => read_embedded(&mut f).map(|(name, data, _)| (name, data)),
+        Err(_) => None,
+    }
+}
+
+/// Export the cartridge as a standalone executable: the cartridge is appended
+/// to `runtime` (the running devkit by default, or a build of another OS)
+pub fn export_native(filename: &str,
+                     runtime: Option<&str>,
+                     library_dir: &str,
+                     output: &str)
+                     -> Result<(), String> {
+    let path = Path::new(filename);
+    let extension = path.extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or(String::new());
+    if !["uni", "png", "uc", "duc"].contains(&extension.as_str()) {
+        return Err("Only the .uni, .png, .uc and .duc cartridges could be embedded in an executable".to_string());
+    }
+
+    let runtime = match runtime {
+        Some(runtime) => runtime.to_string(),
+        None => {
+            match env::current_exe() {
+                Ok(exe) => exe.to_string_lossy().into_owned(),
+                Err(e) => return Err(format!("Failed to find the devkit executable: {}", e)),
+            }
+        }
+    };
+    info!("[EXPORT] Exporting {:?} in {:?} with the runtime {:?}", filename, output, runtime);
+
+    let (name, data) = try!(cartridge_data(filename, library_dir));
+    let mut binary = try!(read_file(&runtime));
+
+    // The runtime could already be an exported game
+    let size = match read_embedded(&mut Cursor::new(&binary)) {
+        Some((_, _, size)) => size as usize,
+        None => binary.len(),
+    };
+    binary.truncate(size);
+
+    binary.extend_from_slice(&data);
+    binary.extend_from_slice(name.as_bytes());
+    binary.extend_from_slice(&write_u32(name.len() as u32));
+    binary.extend_from_slice(&write_u32(data.len() as u32));
+    binary.extend_from_slice(NATIVE_CART_MAGIC);
+
+    match File::create(output) {
+        Ok(mut f) => {
+            if let Err(e) = f.write_all(&binary) {
+                return Err(format!("Failed to write {:?}: {}", output, e));
+            }
+        }
+        Err(e) => return Err(format!("Failed to create {:?}: {}", output, e)),
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Err(e) = fs::set_permissions(output, fs::Permissions::from_mode(0o755)) {
+            return Err(format!("Failed to make {:?} executable: {}", output, e));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Cursor, Write};
+
+    use unicorn::cartridge::Cartridge;
+
+    use super::{base64_encode, cartridge_data, export_native, read_embedded, read_file, write_u32,
+                NATIVE_CART_MAGIC};
+
+    fn write_file(path: &::std::path::Path, data: &str) {
+        File::create(path).unwrap().write_all(data.as_bytes()).unwrap();
+    }
+
+    fn uni(code: &str) -> String {
+        format!("Saved by unicorn\nVersion \"1\"\n__python__\n{}\n", code)
+    }
+
+    #[test]
+    fn test_base64_encode() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64_encode(&[0xff, 0xfe, 0x00]), "//4A");
+    }
+
+    #[test]
+    fn test_cartridge_data() {
+        let dir = env::temp_dir().join("unicorn-test-export");
+        fs::create_dir_all(&dir).unwrap();
+        let libs = dir.join("libs");
+
+        // The code and the data of a .uc cartridge are embedded
+        let code = dir.join("game.py");
+        let data = dir.join("game.dat");
+        let manifest = dir.join("game.uc");
+        write_file(&code, "x = 1\nprint(x)\n");
+        write_file(&data, "__gff__\n__map__\n");
+        write_file(&manifest,
+                   &format!(r#"{{"code": {:?}, "data": {:?}}}"#, code.to_string_lossy(), data.to_string_lossy()));
+
+        let (name, exported) = cartridge_data(&manifest.to_string_lossy(), &libs.to_string_lossy()).unwrap();
+        assert_eq!(name, "game.uni");
+        let cartridge = Cartridge::from_uni_raw("game.uni", exported.clone()).unwrap();
+        assert_eq!(cartridge.code.lines, vec!["x = 1".to_string(), "print(x)".to_string()]);
+
+        // A .uni cartridge without anything to add is exported as is
+        let uni = dir.join("other.uni");
+        File::create(&uni).unwrap().write_all(&exported).unwrap();
+        assert_eq!(cartridge_data(&uni.to_string_lossy(), &libs.to_string_lossy()),
+                   Ok(("other.uni".to_string(), exported)));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_native_trailer() {
+        let dir = env::temp_dir().join("unicorn-test-native");
+        fs::create_dir_all(&dir).unwrap();
+        let libs = dir.join("libs").to_string_lossy().into_owned();
+
+        let runtime = dir.join("runtime");
+        write_file(&runtime, "RUNTIME");
+        let cart = dir.join("game.uni");
+        write_file(&cart, &uni("print(1)"));
+        let output = dir.join("game");
+
+        export_native(&cart.to_string_lossy(),
+                      Some(&runtime.to_string_lossy()),
+                      &libs,
+                      &output.to_string_lossy())
+            .unwrap();
+        let binary = read_file(&output.to_string_lossy()).unwrap();
+        assert_eq!(read_embedded(&mut Cursor::new(&binary)),
+                   Some(("game.uni".to_string(), uni("print(1)").into_bytes(), 7)));
+
+        // An exported game as the runtime, its cartridge is replaced
+        write_file(&cart, &uni("print(2)"));
+        let again = dir.join("again");
+        export_native(&cart.to_string_lossy(),
+                      Some(&output.to_string_lossy()),
+                      &libs,
+                      &again.to_string_lossy())
+            .unwrap();
+        let binary = read_file(&again.to_string_lossy()).unwrap();
+        assert_eq!(&binary[..7], b"RUNTIME");
+        assert_eq!(read_embedded(&mut Cursor::new(&binary)),
+                   Some(("game.uni".to_string(), uni("print(2)").into_bytes(), 7)));
+
+        // Truncated: the lengths are longer than the file, or no trailer
+        let mut truncated = b"RUN".to_vec();
+        truncated.extend_from_slice(&write_u32(4));
+        truncated.extend_from_slice(&write_u32(100));
+        truncated.extend_from_slice(NATIVE_CART_MAGIC);
+        assert_eq!(read_embedded(&mut Cursor::new(&truncated)), None);
+        assert_eq!(read_embedded(&mut Cursor::new(&binary[..binary.len() - 1])), None);
+        assert_eq!(read_embedded(&mut Cursor::new(&NATIVE_CART_MAGIC[..])), None);
+        assert_eq!(read_embedded(&mut Cursor::new(b"RUNTIME")), None);
+
+        assert!(export_native("game.lua", None, &libs, &output.to_string_lossy()).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
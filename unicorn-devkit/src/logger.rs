@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+
+use log::{self, Log, LogLevelFilter, LogMetadata, LogRecord, SetLoggerError};
+use time;
+
+use unicorn::unicorn::crash;
+
+/// Modules with a configurable level, and the log targets they cover
+pub const MODULES: [(&'static str, &'static [&'static str]); 4] =
+    [("frontend",
+      &["unicorn_sdl::frontend", "unicorn_sdl::controllers", "unicorn_sdl::input", "uc_devkit"]),
+     ("renderer", &["unicorn_sdl::renderer", "unicorn::gfx"]),
+     ("cart",
+      &["unicorn::cartridge",
+        "unicorn::plugins",
+        "unicorn::unicorn::cartdata",
+        "unicorn::unicorn::libraries",
+        "unicorn::unicorn::script_cache"]),
+     ("audio", &["unicorn::sound", "chiptune"])];
+
+pub struct LoggerConfig {
+    pub level: LogLevelFilter,
+    pub modules: HashMap<String, LogLevelFilter>,
+    pub file: Option<String>,
+    pub max_file_size: u64,
+    pub max_files: usize,
+}
+
+impl LoggerConfig {
+    pub fn new() -> LoggerConfig {
+        LoggerConfig {
+            level: LogLevelFilter::Info,
+            modules: HashMap::new(),
+            file: None,
+            max_file_size: 1024 * 1024,
+            max_files: 3,
+        }
+    }
+
+    /// Parse the levels of the modules, like "frontend=debug,audio=off"
+    pub fn parse_modules(&mut self, value: &str) -> Result<(), String> {
+        for item in value.split(',') {
+            let mut parts = item.splitn(2, '=');
+            let name = parts.next().unwrap_or("").trim().to_lowercase();
+            let level = parts.next().unwrap_or("").trim();
+
+            if !MODULES.iter().any(|&(module, _)| module == name) {
+                return Err(format!("Unknown log module {:?}", name));
+            }
+
+            match level.parse::<LogLevelFilter>() {
+                Ok(level) => {
+                    self.modules.insert(name, level);
+                }
+                Err(_) => return Err(format!("Unknown log level {:?}", level)),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct LogFile {
+    filename: String,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: usize,
+}
+
+impl LogFile {
+    fn open(filename: &str, max_size: u64, max_files: usize) -> Option<LogFile> {
+        let file = match OpenOptions::new().create(true).append(true).open(filename) {
+            Ok(file) => file,
+            Err(e) => {
+                println!("Failed to open the log file {:?}: {}", filename, e);
+                return None;
+            }
+        };
+
+        let size = match file.metadata() {
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        };
+
+        Some(LogFile {
+                 filename: filename.to_string(),
+                 file: file,
+                 size: size,
+                 max_size: max_size,
+                 max_files: max_files,
+             })
+    }
+
+    /// file.log -> file.log.1 -> ... -> file.log.N, the oldest one is removed
+    fn rotate(&mut self) {
+        for i in (1..self.max_files).rev() {
+            let _ = fs::rename(format!("{}.{}", self.filename, i),
+                               format!("{}.{}", self.filename, i + 1));
+        }
+        let _ = fs::rename(&self.filename, format!("{}.1", self.filename));
+
+        if let Ok(file) = File::create(&self.filename) {
+            self.file = file;
+            self.size = 0;
+        }
+    }
+
+    fn write(&mut self, line: &str) {
+        if self.max_size > 0 && self.size >= self.max_size {
+            self.rotate();
+        }
+
+        if writeln!(self.file, "{}", line).is_ok() {
+            self.size += line.len() as u64 + 1;
+        }
+    }
+}
+
+struct Logger {
+    level: LogLevelFilter,
+    targets: Vec<(&'static str, LogLevelFilter)>,
+    file: Option<Mutex<LogFile>>,
+}
+
+impl Logger {
+    fn level(&self, target: &str) -> LogLevelFilter {
+        for &(prefix, level) in self.targets.iter() {
+            if target.starts_with(prefix) {
+                return level;
+            }
+        }
+        self.level
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &LogMetadata) -> bool {
+        metadata.level() <= self.level(metadata.target())
+    }
+
+    fn log(&self, record: &LogRecord) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = format!("[{}][{}] {}",
+                           time::now().strftime("%Y-%m-%d][%H:%M:%S").unwrap(),
+                           record.level(),
+                           record.args());
+
+        println!("{}", line);
+        crash::log_line(&line);
+
+        if let Some(ref file) = self.file {
+            if let Ok(mut file) = file.lock() {
+                file.write(&line);
+            }
+        }
+    }
+}
+
+pub fn init(config: LoggerConfig) -> Result<(), SetLoggerError> {
+    let mut targets = Vec::new();
+    let mut max_level = config.level;
+
+    for &(module, prefixes) in MODULES.iter() {
+        if let Some(&level) = config.modules.get(module) {
+            for prefix in prefixes.iter() {
+                targets.push((*prefix, level));
+            }
+
+            if level > max_level {
+                max_level = level;
+            }
+        }
+    }
+
+    let file = match config.file {
+        Some(ref filename) => {
+            LogFile::open(filename, config.max_file_size, config.max_files).map(Mutex::new)
+        }
+        None => None,
+    };
+
+    let logger = Logger {
+        level: config.level,
+        targets: targets,
+        file: file,
+    };
+
+    log::set_logger(|max_log_level| {
+                        max_log_level.set(max_level);
+                        Box::new(logger)
+                    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Read;
+    use std::path::Path;
+
+    use log::LogLevelFilter;
+
+    use super::{LogFile, Logger, LoggerConfig, MODULES};
+
+    fn read(filename: &str) -> String {
+        let mut data = String::new();
+        File::open(filename).unwrap().read_to_string(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_parse_modules() {
+        let mut config = LoggerConfig::new();
+        assert!(config.parse_modules("frontend=debug, Audio=off").is_ok());
+        assert_eq!(config.modules.get("frontend"), Some(&LogLevelFilter::Debug));
+        assert_eq!(config.modules.get("audio"), Some(&LogLevelFilter::Off));
+
+        assert!(config.parse_modules("network=debug").is_err());
+        assert!(config.parse_modules("cart=loud").is_err());
+        assert!(config.parse_modules("cart").is_err());
+        assert_eq!(config.modules.len(), 2);
+
+        let logger = Logger {
+            level: LogLevelFilter::Info,
+            targets: vec![("unicorn::sound", LogLevelFilter::Off)],
+            file: None,
+        };
+        assert_eq!(logger.level("unicorn::sound::sound"), LogLevelFilter::Off);
+        assert_eq!(logger.level("unicorn::gfx"), LogLevelFilter::Info);
+
+        // Only the scripts of the cartridge, not the whole core
+        let cart = MODULES.iter().find(|&&(module, _)| module == "cart").unwrap().1;
+        let covered = |target: &str| cart.iter().any(|prefix| target.starts_with(prefix));
+        assert!(covered("unicorn::plugins::lua_plugin::plugin"));
+        assert!(covered("unicorn::unicorn::script_cache"));
+        assert!(!covered("unicorn::unicorn"));
+        assert!(!covered("unicorn::unicorn::display"));
+    }
+
+    #[test]
+    fn test_log_rotation() {
+        let dir = env::temp_dir().join("unicorn-test-logger");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let filename = dir.join("unicorn.log").to_string_lossy().into_owned();
+
+        let mut file = LogFile::open(&filename, 10, 2).unwrap();
+        for line in ["first line", "second line", "third line", "fourth line"].iter() {
+            file.write(line);
+        }
+
+        // The oldest line is removed with the third file
+        assert_eq!(read(&filename), "fourth line\n");
+        assert_eq!(read(&format!("{}.1", filename)), "third line\n");
+        assert_eq!(read(&format!("{}.2", filename)), "second line\n");
+        assert!(!Path::new(&format!("{}.3", filename)).exists());
+
+        // The size of the existing file is kept when it's opened again
+        let file = LogFile::open(&filename, 10, 2).unwrap();
+        assert_eq!(file.size, 12);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
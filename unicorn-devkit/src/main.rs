@@ -4,16 +4,260 @@ extern crate unicorn;
 
 extern crate getopts;
 
+#[macro_use]
 extern crate log;
-extern crate fern;
 extern crate time;
 
+mod logger;
+mod export;
+mod emulationstation;
+
 use std::env;
+use std::path::Path;
+use std::process::{self, Command};
+use std::str::FromStr;
 use getopts::Options;
 
 use unicorn::gfx;
 use unicorn::gfx::Scale;
-use unicorn::cartridge::Cartridge;
+use unicorn::cartridge::{Cartridge, CartridgeFormat, CartridgeHooks};
+use unicorn::cartridge::stats::CartridgeStats;
+use unicorn::unicorn::{Unicorn, Palettes, VERSION, MAJOR_VERSION, MINOR_VERSION};
+use unicorn::unicorn::crash;
+use unicorn::unicorn::headless::InputScript;
+use unicorn::unicorn::determinism::{self, DeterminismReport};
+use unicorn::unicorn::overlay::OverlayPosition;
+use unicorn::unicorn::profile::{Profile, DEFAULT_PROFILE};
+use unicorn::unicorn::handheld::{Handheld, HANDHELDS};
+use unicorn::unicorn::display::DisplayConfig;
+use unicorn::unicorn::classroom::Classroom;
+use unicorn::unicorn::tutorial::Tutorial;
+use unicorn_sdl::frontend::Frontend;
+use unicorn_sdl::renderer::renderer::WindowOptions;
+use unicorn_sdl::frametimes::FrameLimiter;
+
+const WINDOW_GEOMETRY: &'static str = "../unicorn-sdl/sys/config/window.geometry";
+const PROFILES_DIR: &'static str = "../unicorn-sdl/sys/profiles";
+const RECORD_CONFIG: &'static str = "../unicorn-sdl/sys/config/record.json";
+const LIBRARY_DIR: &'static str = "../unicorn-sdl/sys/libs";
+const WEB_RUNTIME_DIR: &'static str = "target/wasm32-unknown-emscripten/release";
+
+/// Options of the command line applied on the frontend before running a cartridge
+pub struct FrontendOptions {
+    pub safe: bool,
+    /// The pre-run hooks of the project manifests are executed
+    pub allow_hooks: bool,
+    pub overlay: Option<OverlayPosition>,
+    pub overlay_opacity: Option<u8>,
+    pub window: WindowOptions,
+    pub remember_window: bool,
+    /// The size of the window saved in the profile is restored, without -s/-f
+    pub saved_display: bool,
+    /// Picked at boot among the existing profiles when none is given
+    pub profile: Option<String>,
+    pub classroom: Option<Classroom>,
+    pub tutorial: Option<Tutorial>,
+    /// Port of the local HTTP API, none by default
+    pub remote_port: Option<u16>,
+    /// Port of the protocol of the external editors, none by default
+    pub dev_port: Option<u16>,
+    /// Chrome trace of the frames, written at the exit
+    pub trace: Option<String>,
+    /// Expressions shown in the watch panel
+    pub watches: Vec<String>,
+    /// Preset of `--handheld`, or of the device running the console
+    pub handheld: Option<Handheld>,
+}
+
+impl FrontendOptions {
+    pub fn init(&self,
+                scale: gfx::Scale,
+                fullscreen: bool,
+                opengl: bool)
+                -> Frontend {
+        // The window is created in fullscreen on a handheld
+        let fullscreen = fullscreen || self.handheld.is_some();
+        match Frontend::init_with_window(scale, fullscreen, opengl, false, self.window.clone()) {
+            Err(error) => panic!("{:?}", error),
+            Ok(frontend) => frontend,
+        }
+    }
+
+    pub fn exit(&self, frontend: &mut Frontend) {
+        if self.remember_window {
+            frontend.save_window_geometry(WINDOW_GEOMETRY);
+        }
+
+        if self.trace.is_some() {
+            frontend.uc.stop_trace();
+        }
+    }
+
+    /// The saves of the students are collected in the folder of the classroom
+    fn profiles_dir(&self) -> String {
+        match self.classroom {
+            Some(ref classroom) => classroom.saves_dir(),
+            None => PROFILES_DIR.to_string(),
+        }
+    }
+
+    /// Frame limiter of display.json in the profile of the command line,
+    /// read before the window is created
+    pub fn saved_frame_limiter(&self) -> Option<FrameLimiter> {
+        let name = self.profile.clone().unwrap_or(DEFAULT_PROFILE.to_string());
+        if !Profile::is_valid_name(&name) {
+            return None;
+        }
+
+        let path = Path::new(&self.profiles_dir()).join(name).join("display.json");
+        if !path.exists() {
+            return None;
+        }
+
+        match DisplayConfig::from_file(&path.to_string_lossy()) {
+            Ok(config) => FrameLimiter::from_name(&config.frame_limiter),
+            Err(e) => {
+                warn!("{}", e);
+                None
+            }
+        }
+    }
+
+    pub fn apply(&self, frontend: &mut Frontend) {
+        frontend.set_safe_mode(self.safe);
+
+        let profiles_dir = self.profiles_dir();
+        let profile = match self.profile {
+            Some(ref name) => name.clone(),
+            None => {
+                match frontend.pick_profile(&profiles_dir) {
+                    Some(name) => name,
+                    None => process::exit(0),
+                }
+            }
+        };
+        if let Err(e) = frontend.init_profile(&profiles_dir, &profile) {
+            panic!("{}", e);
+        }
+
+        if self.saved_display {
+            frontend.init_display_config();
+        }
+
+        if let Some(ref handheld) = self.handheld {
+            frontend.set_handheld(handheld.clone());
+        }
+
+        if let Some(ref classroom) = self.classroom {
+            frontend.uc.set_classroom(classroom.clone());
+        }
+
+        if let Some(ref tutorial) = self.tutorial {
+            frontend.uc.set_tutorial(tutorial.clone());
+        }
+
+        if let Some(position) = self.overlay {
+            frontend.uc.stats_overlay.enabled = true;
+            frontend.uc.stats_overlay.position = position;
+        }
+
+        if let Some(opacity) = self.overlay_opacity {
+            frontend.uc.stats_overlay.set_opacity(opacity);
+        }
+
+        if let Some(port) = self.remote_port {
+            if self.safe {
+                warn!("The remote control is disabled in safe mode, ignoring --remote-port");
+            } else if self.classroom.is_none() {
+                frontend.uc.start_remote(port);
+                if let Some(ref remote) = frontend.uc.remote {
+                    println!("Remote control on http://127.0.0.1:{}, token {}", remote.port, remote.token);
+                }
+            }
+        }
+
+        if let Some(port) = self.dev_port {
+            if self.safe {
+                warn!("The editor protocol is disabled in safe mode, ignoring --dev-port");
+            } else if self.classroom.is_none() {
+                frontend.uc.start_dev_server(port);
+                if let Some(ref dev_server) = frontend.uc.dev_server {
+                    println!("Editor protocol on 127.0.0.1:{}, token {}", dev_server.port, dev_server.token);
+                }
+            }
+        }
+
+        if let Some(ref filename) = self.trace {
+            frontend.uc.start_trace(filename);
+        }
+
+        for expression in &self.watches {
+            if let Err(e) = frontend.uc.watches.add(expression) {
+                warn!("Ignoring --watch {:?}: {}", expression, e);
+            }
+        }
+    }
+}
+
+/// Execute the pre-run hooks of a project manifest, stop at the first failing one
+fn run_hooks(filename: &str, options: &FrontendOptions) -> bool {
+    let hooks = match CartridgeHooks::from_manifest(filename) {
+        Ok(hooks) => hooks,
+        Err(e) => {
+            error!("Failed to read the hooks of {:?}: {:?}", filename, e);
+            return false;
+        }
+    };
+
+    if hooks.pre_run.is_empty() {
+        return true;
+    }
+
+    if options.safe || options.classroom.is_some() {
+        warn!("Hooks are not executed in safe/classroom mode, ignoring {:?}", hooks.pre_run);
+        return true;
+    }
+
+    // A downloaded manifest doesn't run commands by itself
+    if !options.allow_hooks {
+        eprintln!("{:?} has pre_run hooks, not executed without --allow-hooks:", filename);
+        for hook in hooks.pre_run.iter() {
+            eprintln!("    {}", hook);
+        }
+        return true;
+    }
+
+    // Run from the folder of the manifest, like its paths
+    let dir = match Path::new(filename).parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+
+    for hook in hooks.pre_run.iter() {
+        info!("Running the hook {:?} in {:?}", hook, dir);
+
+        let status = if cfg!(target_os = "windows") {
+            Command::new("cmd").args(&["/C", hook]).current_dir(dir).status()
+        } else {
+            Command::new("sh").args(&["-c", hook]).current_dir(dir).status()
+        };
+
+        match status {
+            Ok(status) => {
+                if !status.success() {
+                    error!("The hook {:?} failed with {}", hook, status);
+                    return false;
+                }
+            }
+            Err(e) => {
+                error!("Failed to run the hook {:?}: {}", hook, e);
+                return false;
+            }
+        }
+    }
+
+    true
+}
 
 fn print_usage(program: &str, opts: &Options) {
     let brief = format!("Usage: {} FILE [options]", program);
@@ -21,16 +265,7 @@ fn print_usage(program: &str, opts: &Options) {
 }
 
 fn main() {
-    let logger_config = fern::DispatchConfig {
-        format: Box::new(|msg: &str, level: &log::LogLevel, _location: &log::LogLocation| {
-            format!("[{}][{}] {}",
-                    time::now().strftime("%Y-%m-%d][%H:%M:%S").unwrap(),
-                    level,
-                    msg)
-        }),
-        output: vec![fern::OutputConfig::stdout()],
-        level: log::LogLevelFilter::Trace,
-    };
+    crash::install(".");
 
     let args: Vec<String> = env::args().collect();
     let program = args[0].clone();
@@ -47,7 +282,114 @@ fn main() {
                     "FILE");
     opts.optflagopt("s", "scale", "scale the display", "VALUE");
     opts.optflagopt("b", "bind", "bind a server on a specific address", "ADDR");
+    opts.optopt("",
+                "remote-port",
+                "control the console with a HTTP API on 127.0.0.1 (load, screenshot, input, stat)",
+                "PORT");
+    opts.optopt("",
+                "dev-port",
+                "let an external editor push the code, reload it and get the errors (JSON over TCP on 127.0.0.1)",
+                "PORT");
+    opts.optopt("",
+                "trace",
+                "record the update/draw/audio/blit of the frames and the trace_begin/trace_end sections of the cartridge (Chrome trace format)",
+                "FILE");
+    opts.optmulti("",
+                  "watch",
+                  "show the value of a Lua/Python expression of the cartridge each frame, in a panel",
+                  "EXPRESSION");
+    opts.optflag("",
+                 "safe",
+                 "run an untrusted cartridge (sandboxed script, no network, writes only in a temporary directory, CPU/memory limits)");
+    opts.optflag("",
+                 "allow-hooks",
+                 "execute the pre_run commands of the .uc project manifest before running or exporting it");
+    opts.optopt("",
+                "overlay",
+                "show the cart title, FPS and play time in a corner (toggle with F7)",
+                "top-left|top-right|bottom-left|bottom-right");
+    opts.optopt("", "overlay-opacity", "opacity of the overlay background", "0-100");
+    opts.optopt("",
+                "handheld",
+                "preset of a handheld PC (fullscreen, pause menu and on-screen keyboard on the controller), detected on the Steam Deck",
+                "steam-deck|none");
+    opts.optopt("", "window-position", "initial position of the window", "X,Y");
+    opts.optflag("", "always-on-top", "keep the window above the other ones");
+    opts.optflag("", "remember-window", "restore the last position/size of the window");
+    opts.optopt("",
+                "frame-limiter",
+                "pace the frames with the vsync of the display (default) or a sleep",
+                "vsync|sleep");
+    opts.optopt("",
+                "profile",
+                "user profile of the saves, scores and editor settings (UNICORN_PROFILE by default)",
+                "NAME");
+    opts.optflag("", "profiles", "list the user profiles");
+    opts.optopt("",
+                "classroom",
+                "only run the cartridges of a teacher manifest, starting with its starter cartridge in the editor",
+                "FILE");
+    opts.optopt("",
+                "tutorial",
+                "follow the lessons of a tutorial, its cartridge is opened in the editor",
+                "FILE");
+    opts.optopt("",
+                "export-html",
+                "export the cartridge with the emscripten build in a directory to publish it",
+                "DIR");
+    opts.optopt("",
+                "web-runtime",
+                &format!("directory of the emscripten build used by --export-html ({} by default)",
+                         WEB_RUNTIME_DIR),
+                "DIR");
+    opts.optopt("",
+                "export-native",
+                "export the cartridge as a standalone executable, playable without the console installed",
+                "FILE");
+    opts.optopt("",
+                "native-runtime",
+                "executable used by --export-native, to export for another OS (the running devkit by default)",
+                "FILE");
+    opts.optflagopt("",
+                    "emulationstation",
+                    &format!("install the cartridge as a game of the ports of EmulationStation/RetroPie, with its label and a launch script (~/{} by default)",
+                             emulationstation::PORTS_DIR),
+                    "DIR");
+    opts.optopt("",
+                "preview-gif",
+                "record a GIF of the cartridge without a window, for its page",
+                "FILE");
+    opts.optopt("", "seconds", "length of the GIF of --preview-gif (5 by default)", "VALUE");
+    opts.optopt("",
+                "preview-input",
+                "buttons pressed during --preview-gif or --check-determinism, one \"frame player button [frames]\" per line (nothing by default)",
+                "FILE");
+    opts.optflag("",
+                 "check-determinism",
+                 "run the cartridge twice without a window and compare the frames (--seconds, 5 by default)");
+    opts.optopt("",
+                "check-interval",
+                "frames between two comparisons of --check-determinism (60 by default)",
+                "FRAMES");
+    opts.optopt("",
+                "import-image",
+                "draw a PNG/GIF image in the sprite sheet of the cartridge, from its top-left sprite",
+                "FILE");
+    opts.optflag("",
+                 "quantize",
+                 "reduce the colors of --import-image to a palette of 16 colors written in the cartridge, if they aren't the ones of its palette");
+    opts.optflag("", "dither", "dither the colors reduced by --quantize");
+    opts.optflag("",
+                 "stats",
+                 "print the size of the code, the sprites, map cells, sfx and music used by the cartridge");
+    opts.optflag("", "json", "print the --stats in JSON");
     opts.optflag("v", "verbose", "Debug mode level");
+    opts.optflag("q", "quiet", "only display the warnings and errors");
+    opts.optopt("",
+                "log",
+                "level of each module (frontend, renderer, cart, audio)",
+                "frontend=debug,audio=off");
+    opts.optopt("", "log-file", "write the logs in a file, rotated every 1MB", "FILE");
     opts.optflag("h", "help", "print this help menu");
 
     let matches = match opts.parse(&args[1..]) {
@@ -59,33 +401,147 @@ fn main() {
         return;
     }
 
-    if matches.opt_present("v") {
-        if let Err(e) = fern::init_global_logger(logger_config, log::LogLevelFilter::Debug) {
-            panic!("Failed to initialize global logger: {}", e);
+    if matches.opt_present("profiles") {
+        for name in Profile::list(PROFILES_DIR) {
+            println!("{}", name);
         }
-    } else {
-        if let Err(e) = fern::init_global_logger(logger_config, log::LogLevelFilter::Info) {
-            panic!("Failed to initialize global logger: {}", e);
+        return;
+    }
+
+    let mut logger_config = logger::LoggerConfig::new();
+    if matches.opt_present("v") {
+        logger_config.level = log::LogLevelFilter::Debug;
+    } else if matches.opt_present("q") {
+        logger_config.level = log::LogLevelFilter::Warn;
+    }
+
+    if let Some(modules) = matches.opt_str("log") {
+        if let Err(e) = logger_config.parse_modules(&modules) {
+            panic!("Invalid --log option: {}", e);
         }
     }
+    logger_config.file = matches.opt_str("log-file");
+
+    if let Err(e) = logger::init(logger_config) {
+        panic!("Failed to initialize global logger: {}", e);
+    }
 
     let mut scale = Scale::Scale1x;
     if matches.opt_present("s") {
         let value = matches.opt_str("s").unwrap().parse::<i32>().unwrap();
-        match value {
-            2 => scale = Scale::Scale2x,
-            3 => scale = Scale::Scale3x,
-            4 => scale = Scale::Scale4x,
-            5 => scale = Scale::Scale5x,
-            6 => scale = Scale::Scale6x,
-            8 => scale = Scale::Scale8x,
-            10 => scale = Scale::Scale10x,
-            _ => scale = Scale::Scale1x,
-        }
+        scale = Scale::from_factor(value as usize).unwrap_or(Scale::Scale1x);
     }
 
     let fullscreen = matches.opt_present("f");
     let opengl = matches.opt_present("o");
+    let mut window = WindowOptions::new();
+    if matches.opt_present("remember-window") {
+        window.load_geometry(WINDOW_GEOMETRY);
+    }
+    if let Some(value) = matches.opt_str("window-position") {
+        let values: Vec<i32> = value.split(',').map(|v| v.trim().parse::<i32>().unwrap()).collect();
+        if values.len() != 2 {
+            panic!("Invalid window position {:?}", value);
+        }
+        window.position = Some((values[0], values[1]));
+    }
+    window.always_on_top = matches.opt_present("always-on-top");
+    if let Some(value) = matches.opt_str("frame-limiter") {
+        match FrameLimiter::from_name(&value) {
+            Some(limiter) => window.vsync = limiter == FrameLimiter::VSync,
+            None => invalid_option("frame-limiter", &value, "vsync or sleep is"),
+        }
+    }
+
+    let mut options = FrontendOptions {
+        safe: matches.opt_present("safe"),
+        allow_hooks: matches.opt_present("allow-hooks"),
+        overlay: matches.opt_str("overlay").map(|value| match OverlayPosition::from_name(&value) {
+            Some(position) => position,
+            None => invalid_option("overlay", &value, "top-left, top-right, bottom-left or bottom-right is"),
+        }),
+        overlay_opacity: matches.opt_str("overlay-opacity").map(|value| match value.trim().parse::<u8>() {
+            Ok(opacity) if opacity <= 100 => opacity,
+            _ => invalid_option("overlay-opacity", &value, "a number from 0 to 100 is"),
+        }),
+        window: window,
+        remember_window: matches.opt_present("remember-window"),
+        saved_display: !matches.opt_present("s") && !matches.opt_present("f"),
+        profile: matches.opt_str("profile").or(env::var("UNICORN_PROFILE").ok()),
+        classroom: matches.opt_str("classroom").map(|filename| match Classroom::from_manifest(&filename) {
+            Ok(classroom) => classroom,
+            Err(e) => panic!("{}", e),
+        }),
+        tutorial: matches.opt_str("tutorial").map(|filename| match Tutorial::from_file(&filename) {
+            Ok(tutorial) => tutorial,
+            Err(e) => panic!("{}", e),
+        }),
+        remote_port: matches.opt_str("remote-port").map(|value| match value.trim().parse::<u16>() {
+            Ok(port) => port,
+            Err(_) => invalid_option("remote-port", &value, "a port number is"),
+        }),
+        dev_port: matches.opt_str("dev-port").map(|value| match value.trim().parse::<u16>() {
+            Ok(port) => port,
+            Err(_) => invalid_option("dev-port", &value, "a port number is"),
+        }),
+        trace: matches.opt_str("trace"),
+        watches: matches.opt_strs("watch"),
+        handheld: match matches.opt_str("handheld") {
+            Some(ref name) if name == "none" => None,
+            Some(name) => match Handheld::from_name(&name) {
+                Some(handheld) => Some(handheld),
+                None => {
+                    let names: Vec<&str> = HANDHELDS.iter().map(|handheld| handheld.name).collect();
+                    invalid_option("handheld", &name, &format!("{} or none is", names.join(", ")))
+                }
+            },
+            None => Handheld::detect(),
+        },
+    };
+    if !matches.opt_present("frame-limiter") {
+        if let Some(limiter) = options.saved_frame_limiter() {
+            options.window.vsync = limiter == FrameLimiter::VSync;
+        }
+    }
+
+    // Standalone game exported with --export-native
+    if let Some((name, data)) = export::embedded_cartridge() {
+        info!("[Frontend] Running the embedded cartridge {:?}", name);
+        run_cartridge_raw(scale, fullscreen, opengl, &name, data, false, &options);
+        return;
+    }
+
+    if let Some(ref classroom) = options.classroom {
+        for name in ["b", "c", "d", "t", "export-html", "export-native", "emulationstation", "preview-gif",
+                     "remote-port", "dev-port"].iter() {
+            if matches.opt_present(name) {
+                warn!("Export and network are disabled in classroom mode, ignoring {:?}", name);
+            }
+        }
+
+        if matches.free.is_empty() {
+            match classroom.starter_path() {
+                Some(starter) => {
+                    run_cartridge(scale, fullscreen, opengl, &starter, true, &options);
+                }
+                None => error!("No cartridge given and no starter cartridge in the classroom manifest"),
+            }
+            return;
+        }
+    }
+
+    if let Some(ref tutorial) = options.tutorial {
+        if matches.free.is_empty() {
+            if let Some(cart) = tutorial.cart_path() {
+                run_cartridge(scale, fullscreen, opengl, &cart, true, &options);
+                return;
+            }
+        }
+    }
+
+    if options.safe && matches.opt_present("b") {
+        warn!("Network access is denied in safe mode, ignoring --bind");
+    }
 
     let input = if !matches.free.is_empty() {
         matches.free[0].clone()
@@ -95,12 +551,116 @@ fn main() {
                           opengl,
                           "unicorn.uni",
                           include_bytes!("../../unicorn/sys/unicorn.uni").to_vec(),
-                          matches.opt_present("e"));
+                          matches.opt_present("e"),
+                          &options);
         return;
     };
 
-    if matches.opt_present("c") {
-        if input.contains(".uni") {
+    let (export_dir, export_native, preview_gif) = if options.classroom.is_none() {
+        (matches.opt_str("export-html"), matches.opt_str("export-native"), matches.opt_str("preview-gif"))
+    } else {
+        (None, None, None)
+    };
+    // The directory of the ports is optional
+    let ports_dir = if options.classroom.is_none() && matches.opt_present("emulationstation") {
+        Some(matches.opt_str("emulationstation"))
+    } else {
+        None
+    };
+
+    if let Some(image) = matches.opt_str("import-image") {
+        match import_image(&input,
+                           &image,
+                           matches.opt_present("quantize"),
+                           matches.opt_present("dither")) {
+            Ok(true) => println!("Imported {:?} in {:?} with a new palette", image, input),
+            Ok(false) => println!("Imported {:?} in {:?}", image, input),
+            Err(e) => {
+                error!("Failed to import {:?}: {}", image, e);
+                process::exit(1);
+            }
+        }
+    } else if matches.opt_present("stats") {
+        match cartridge_stats(&input) {
+            Ok(stats) => {
+                if matches.opt_present("json") {
+                    println!("{}", stats.to_json());
+                } else {
+                    print!("{}", stats.to_text());
+                }
+            }
+            Err(e) => {
+                error!("Failed to load {:?}: {}", input, e);
+                process::exit(1);
+            }
+        }
+    } else if let Some(output) = export_native {
+        if input.contains(".uc") && !run_hooks(&input, &options) {
+            return;
+        }
+
+        let runtime = matches.opt_str("native-runtime");
+        match export::export_native(&input, runtime.as_ref().map(|r| r.as_str()), LIBRARY_DIR, &output) {
+            Ok(()) => println!("Exported {:?} in {:?}", input, output),
+            Err(e) => error!("Failed to export {:?}: {}", input, e),
+        }
+    } else if let Some(ports_dir) = ports_dir {
+        let result = match ports_dir {
+            Some(ports_dir) => Ok(ports_dir),
+            None => emulationstation::default_ports_dir(),
+        };
+        match result.and_then(|ports_dir| emulationstation::install(&input, &ports_dir)) {
+            Ok(script) => println!("Installed {:?} in EmulationStation, launched by {:?}", input, script),
+            Err(e) => {
+                error!("Failed to install {:?}: {}", input, e);
+                process::exit(1);
+            }
+        }
+    } else if matches.opt_present("check-determinism") {
+        let seconds = seconds_option(&matches);
+        let interval = number_option(&matches, "check-interval", 60u32);
+        let script = input_script(&matches);
+
+        match check_determinism(&input, seconds, interval, &script, &options) {
+            Ok(report) => {
+                print!("{}", report.to_text());
+                if !report.is_deterministic() {
+                    process::exit(1);
+                }
+            }
+            Err(e) => {
+                error!("Failed to check {:?}: {}", input, e);
+                process::exit(2);
+            }
+        }
+    } else if let Some(output) = preview_gif {
+        let seconds = seconds_option(&matches);
+        let script = input_script(&matches);
+
+        if record_preview(&input, &output, seconds, &script, &options) {
+            println!("Recorded {:?} in {:?}", input, output);
+        } else {
+            error!("Failed to load {:?}", input);
+        }
+    } else if let Some(output_dir) = export_dir {
+        if input.contains(".uc") && !run_hooks(&input, &options) {
+            return;
+        }
+
+        let runtime_dir = matches.opt_str("web-runtime").unwrap_or(WEB_RUNTIME_DIR.to_string());
+        match export::export_html(&input, &runtime_dir, LIBRARY_DIR, &output_dir) {
+            Ok(()) => println!("Exported {:?} in {:?}", input, output_dir),
+            Err(e) => error!("Failed to export {:?}: {}", input, e),
+        }
+    } else if matches.opt_present("c") && options.classroom.is_none() {
+        if Cartridge::is_png_file(&input) {
+            match Cartridge::from_png_file(&input) {
+                Ok(c) => {
+                    println!("{:?}", c);
+                }
+                Err(e) => panic!(e),
+            }
+        } else if input.contains(".uni") {
             match Cartridge::from_unicorn_file(&input) {
                 Ok(c) => {
                     println!("{:?}", c);
@@ -123,7 +683,16 @@ fn main() {
             }
         }
     } else {
-        run_cartridge(scale, fullscreen, opengl, &input, matches.opt_present("e"));
+        if input.contains(".uc") && !run_hooks(&input, &options) {
+            return;
+        }
+
+        run_cartridge(scale,
+                      fullscreen,
+                      opengl,
+                      &input,
+                      matches.opt_present("e"),
+                      &options);
     }
 }
 
@@ -131,16 +700,19 @@ pub fn run_cartridge(scale: gfx::Scale,
                      fullscreen: bool,
                      opengl: bool,
                      filename: &str,
-                     editor: bool) {
-    let mut frontend = match unicorn_sdl::frontend::Frontend::init(scale, fullscreen, opengl, false) {
-        Err(error) => panic!("{:?}", error),
-        Ok(frontend) => frontend,
-    };
+                     editor: bool,
+                     options: &FrontendOptions) {
+    let mut frontend = options.init(scale, fullscreen, opengl);
 
+    options.apply(&mut frontend);
     frontend.start();
     frontend.init_controllers("../unicorn-sdl/sys/config/gamecontrollerdb.txt".to_string());
+    frontend.init_keybindings("../unicorn-sdl/sys/config/keybindings.json".to_string());
+    frontend.init_record_config("../unicorn-sdl/sys/config/record.json".to_string());
+    frontend.init_libraries(LIBRARY_DIR.to_string());
 
     frontend.run_cartridge(filename, editor);
+    options.exit(&mut frontend);
 }
 
 pub fn run_cartridge_raw(scale: gfx::Scale,
@@ -148,26 +720,204 @@ pub fn run_cartridge_raw(scale: gfx::Scale,
                          opengl: bool,
                          filename: &str,
                          data: Vec<u8>,
-                         editor: bool) {
-    let mut frontend = match unicorn_sdl::frontend::Frontend::init(scale, fullscreen, opengl, false) {
-        Err(error) => panic!("{:?}", error),
-        Ok(frontend) => frontend,
-    };
+                         editor: bool,
+                         options: &FrontendOptions) {
+    let mut frontend = options.init(scale, fullscreen, opengl);
     
+    options.apply(&mut frontend);
     frontend.start();
     frontend.init_controllers("../unicorn-sdl/sys/config/gamecontrollerdb.txt".to_string());
+    frontend.init_keybindings("../unicorn-sdl/sys/config/keybindings.json".to_string());
+    frontend.init_record_config("../unicorn-sdl/sys/config/record.json".to_string());
+    frontend.init_libraries(LIBRARY_DIR.to_string());
 
     frontend.run_cartridge_raw(filename, data, editor);
+    options.exit(&mut frontend);
 }
 
-pub fn run_interactive(scale: gfx::Scale, fullscreen: bool, opengl: bool) {
-    let mut frontend = match unicorn_sdl::frontend::Frontend::init(scale, fullscreen, opengl, false) {
-        Err(error) => panic!("{:?}", error),
-        Ok(frontend) => frontend,
-    };
+pub fn run_interactive(scale: gfx::Scale,
+                       fullscreen: bool,
+                       opengl: bool,
+                       options: &FrontendOptions) {
+    let mut frontend = options.init(scale, fullscreen, opengl);
 
+    options.apply(&mut frontend);
     frontend.start();
     frontend.init_controllers("../unicorn-sdl/sys/config/gamecontrollerdb.txt".to_string());
+    frontend.init_keybindings("../unicorn-sdl/sys/config/keybindings.json".to_string());
+    frontend.init_record_config("../unicorn-sdl/sys/config/record.json".to_string());
+    frontend.init_libraries(LIBRARY_DIR.to_string());
 
     frontend.run_interactive();
-}
\ No newline at end of file
+    options.exit(&mut frontend);
+}
+
+/// Buttons of `--preview-input`, nothing pressed without it
+fn input_script(matches: &getopts::Matches) -> InputScript {
+    match matches.opt_str("preview-input") {
+        Some(filename) => {
+            match InputScript::from_file(&filename) {
+                Ok(script) => script,
+                Err(e) => panic!("{}", e),
+            }
+        }
+        None => InputScript::new(),
+    }
+}
+
+/// Usage error of an option, the console isn't started
+fn invalid_option(name: &str, value: &str, expected: &str) -> ! {
+    eprintln!("Invalid --{} value {:?}, {} expected", name, value, expected);
+    process::exit(1);
+}
+
+/// Number of an option, or `default` without it. An invalid value is a
+/// usage error.
+fn number_option<T: FromStr>(matches: &getopts::Matches, name: &str, default: T) -> T {
+    match matches.opt_str(name) {
+        Some(value) => {
+            match value.trim().parse::<T>() {
+                Ok(number) => number,
+                Err(_) => invalid_option(name, &value, "a number is"),
+            }
+        }
+        None => default,
+    }
+}
+
+/// Length of --preview-gif and --check-determinism, 5 by default
+fn seconds_option(matches: &getopts::Matches) -> f64 {
+    let seconds = number_option(matches, "seconds", 5.0f64);
+    if !seconds.is_finite() || seconds <= 0.0 {
+        eprintln!("Invalid --seconds value {:?}, a positive number is expected", seconds);
+        process::exit(1);
+    }
+    seconds
+}
+
+/// Console without a window, the cartridge isn't loaded yet
+fn headless_unicorn(options: &FrontendOptions) -> Unicorn {
+    let mut uc = Unicorn::new();
+
+    uc.set_safe_mode(options.safe);
+    let profile = options.profile.clone().unwrap_or(DEFAULT_PROFILE.to_string());
+    if let Err(e) = uc.set_profile(PROFILES_DIR, &profile) {
+        panic!("{}", e);
+    }
+    uc.setup();
+    // The scale of the GIF follows the record config
+    let record_config = uc.profile.dir().join("record.json");
+    if record_config.exists() {
+        uc.load_record_config(&record_config.to_string_lossy());
+    } else if Path::new(RECORD_CONFIG).exists() {
+        uc.load_record_config(RECORD_CONFIG);
+    }
+    uc.set_library_dir(LIBRARY_DIR);
+
+    uc
+}
+
+/// GIF of the first `seconds` of the cartridge, recorded without a window
+pub fn record_preview(filename: &str,
+                      output: &str,
+                      seconds: f64,
+                      script: &InputScript,
+                      options: &FrontendOptions)
+                      -> bool {
+    let mut uc = headless_unicorn(options);
+
+    if !uc.load_cartridge(filename, filename, false) {
+        return false;
+    }
+
+    uc.record_preview(output, seconds, script);
+    uc.stop();
+    true
+}
+
+/// Image drawn in the sprite sheet of the cartridge, saved in its format.
+/// Returns if its palette was replaced by the quantized colors.
+pub fn import_image(filename: &str, image: &str, quantize: bool, dither: bool) -> Result<bool, String> {
+    let cartridge = if Cartridge::is_png_file(filename) {
+        Cartridge::from_png_file(filename)
+    } else if filename.contains(".uni") {
+        Cartridge::from_unicorn_file(filename)
+    } else if filename.contains(".uc") {
+        Cartridge::from_unicorn_splitted_file(filename)
+    } else {
+        return Err("Only the .uni, .uc and .png cartridges could be modified".to_string());
+    };
+    let mut cartridge = match cartridge {
+        Ok(cartridge) => cartridge,
+        Err(e) => return Err(format!("{:?}", e)),
+    };
+
+    let mut palettes = Palettes::new();
+    palettes.init();
+    let defaults = palettes.palettes["pico-8"].clone();
+
+    let replaced = match cartridge.import_image(image, &defaults, quantize, dither) {
+        Ok(replaced) => replaced,
+        Err(e) if quantize => return Err(format!("{:?}", e)),
+        Err(e) => return Err(format!("{:?}, run again with --quantize (and --dither)", e)),
+    };
+
+    let version = format!("{:?}.{:?}.{:?}", VERSION, MAJOR_VERSION, MINOR_VERSION);
+    match cartridge.format {
+        CartridgeFormat::UnicornFormat => cartridge.save_in_unicorn(filename, &version),
+        CartridgeFormat::UnicornSplittedFormat => cartridge.save_in_unicorn_splitted(),
+        CartridgeFormat::PngFormat => cartridge.save_in_png(filename, &version),
+    }
+
+    Ok(replaced)
+}
+
+/// Statistics of a cartridge, of any format
+pub fn cartridge_stats(filename: &str) -> Result<CartridgeStats, String> {
+    let cartridge = if Cartridge::is_png_file(filename) {
+        Cartridge::from_png_file(filename)
+    } else if filename.contains(".uni") {
+        Cartridge::from_unicorn_file(filename)
+    } else if filename.contains(".uc") {
+        Cartridge::from_unicorn_splitted_file(filename)
+    } else if filename.contains(".duc") {
+        Cartridge::from_dunicorn_file(filename)
+    } else {
+        return Err("Only the .uni, .uc, .duc and .png cartridges could be read".to_string());
+    };
+    let mut cartridge = match cartridge {
+        Ok(cartridge) => cartridge,
+        Err(e) => return Err(format!("{:?}", e)),
+    };
+
+    let version = format!("{:?}.{:?}.{:?}", VERSION, MAJOR_VERSION, MINOR_VERSION);
+    Ok(CartridgeStats::new(&mut cartridge, &version))
+}
+
+/// Two runs of the first `seconds` of the cartridge, with the same seed and
+/// buttons
+pub fn check_determinism(filename: &str,
+                         seconds: f64,
+                         interval: u32,
+                         script: &InputScript,
+                         options: &FrontendOptions)
+                         -> Result<DeterminismReport, String> {
+    let mut runs = Vec::new();
+    for _ in 0..2 {
+        let mut uc = headless_unicorn(options);
+        uc.set_deterministic(determinism::CHECK_SEED);
+        if !uc.load_cartridge(filename, filename, false) {
+            return Err("Failed to load the cartridge".to_string());
+        }
+        runs.push(uc);
+    }
+
+    let mut b = runs.pop().unwrap();
+    let mut a = runs.pop().unwrap();
+    let frames = (seconds * a.info.lock().unwrap().fps as f64).ceil() as u32;
+    let report = determinism::check(&mut a, &mut b, frames, interval, script);
+
+    a.stop();
+    b.stop();
+    report
+}
@@ -186,16 +186,44 @@ impl libretro_backend::Core for UnicornCore {
                                middle,
                                self.uc.info.lock().unwrap().elapsed_time);
 
+        self.uc
+            .players
+            .lock()
+            .unwrap()
+            .set_mouse_buttons(left, right, middle);
+
         self.uc.update();
         self.uc.draw();
         self.uc.update_sound();
 
         let mut palette = unicorn::unicorn::PALETTE.lock().unwrap();
-        let framebuffer = &self.uc.screen.lock().unwrap().frame_buffer;
+        let screen = &self.uc.screen.lock().unwrap();
 
-        for (pixel_in, pixel_out) in framebuffer.iter().zip(self.framebuffer.iter_mut()) {
-            let rgb = palette.get_rgb(*pixel_in as u32);
-            *pixel_out = ((rgb.r as u32) << 16) | ((rgb.g as u32) << 8) | ((rgb.b as u32));
+        if (screen.width, screen.height) == (400, 240) {
+            for (pixel_in, pixel_out) in screen.frame_buffer.iter().zip(self.framebuffer.iter_mut()) {
+                let rgb = palette.get_rgb(screen.display_color(*pixel_in));
+                *pixel_out = ((rgb.r as u32) << 16) | ((rgb.g as u32) << 8) | ((rgb.b as u32));
+            }
+        } else {
+            // The video of the core stays at 400x240: the resolution asked
+            // with mode() is scaled to fit, centered
+            let ratio = f64::min(400.0 / screen.width as f64, 240.0 / screen.height as f64);
+            let width = (screen.width as f64 * ratio) as usize;
+            let height = (screen.height as f64 * ratio) as usize;
+            let x0 = (400 - width) / 2;
+            let y0 = (240 - height) / 2;
+
+            for pixel in self.framebuffer.iter_mut() {
+                *pixel = 0;
+            }
+            for y in 0..height {
+                for x in 0..width {
+                    let src = x * screen.width / width + (y * screen.height / height) * screen.width;
+                    let rgb = palette.get_rgb(screen.display_color(screen.frame_buffer[src]));
+                    self.framebuffer[x0 + x + (y0 + y) * 400] =
+                        ((rgb.r as u32) << 16) | ((rgb.g as u32) << 8) | ((rgb.b as u32));
+                }
+            }
         }
 
         let video_frame = as_bytes(&self.framebuffer[..]);
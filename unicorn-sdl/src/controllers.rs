@@ -1,30 +1,127 @@
+use std::collections::HashMap;
+
+use sdl2::{GameControllerSubsystem, JoystickSubsystem};
 use sdl2::controller::GameController;
 use sdl2::joystick::Joystick;
 
+use unicorn::config::MAX_PLAYERS;
+
+/// The LEDs and the touchpads (DualShock 4, DualSense) aren't exposed by the
+/// sdl2 crate, they need SDL 2.0.14 and the "led_touchpad" feature
+#[cfg(feature = "led_touchpad")]
+#[allow(non_camel_case_types)]
+mod ffi {
+    use std::os::raw::{c_float, c_int};
+
+    pub enum SDL_GameController {}
+
+    extern "C" {
+        pub fn SDL_GameControllerFromInstanceID(joyid: i32) -> *mut SDL_GameController;
+        pub fn SDL_GameControllerSetLED(gamecontroller: *mut SDL_GameController,
+                                        red: u8,
+                                        green: u8,
+                                        blue: u8)
+                                        -> c_int;
+        pub fn SDL_GameControllerGetNumTouchpads(gamecontroller: *mut SDL_GameController) -> c_int;
+        pub fn SDL_GameControllerGetTouchpadFinger(gamecontroller: *mut SDL_GameController,
+                                                   touchpad: c_int,
+                                                   finger: c_int,
+                                                   state: *mut u8,
+                                                   x: *mut c_float,
+                                                   y: *mut c_float,
+                                                   pressure: *mut c_float)
+                                                   -> c_int;
+    }
+}
+
 pub struct Controllers {
-    controllers: Vec<GameController>,
-    joysticks: Vec<Joystick>,
+    game_controller_subsystem: Option<GameControllerSubsystem>,
+    joystick_subsystem: Option<JoystickSubsystem>,
+    controllers: HashMap<u32, GameController>,
+    joysticks: HashMap<u32, Joystick>,
     ids: Vec<u32>,
     j_ids: Vec<u32>,
     g_ids: Vec<u32>,
+    /// Player of each device, the first free slot when it was opened
+    players: HashMap<u32, u8>,
 }
 
 impl Controllers {
     pub fn new() -> Controllers {
         Controllers {
-            controllers: Vec::new(),
-            joysticks: Vec::new(),
+            game_controller_subsystem: None,
+            joystick_subsystem: None,
+            controllers: HashMap::new(),
+            joysticks: HashMap::new(),
             ids: Vec::new(),
             j_ids: Vec::new(),
             g_ids: Vec::new(),
+            players: HashMap::new(),
+        }
+    }
+
+    /// Keep the subsystems to open the devices plugged later
+    pub fn init(&mut self,
+                game_controller_subsystem: GameControllerSubsystem,
+                joystick_subsystem: JoystickSubsystem) {
+        self.game_controller_subsystem = Some(game_controller_subsystem);
+        self.joystick_subsystem = Some(joystick_subsystem);
+    }
+
+    /// Open the device `index` as a game controller if there is a mapping for
+    /// it, as a joystick otherwise; the player is returned
+    pub fn open(&mut self, index: u32) -> Option<u8> {
+        let (controller, joystick) = match (self.game_controller_subsystem.as_ref(),
+                                            self.joystick_subsystem.as_ref()) {
+            (Some(game_controller_subsystem), Some(joystick_subsystem)) => {
+                // The events give the instance id of the device, not its index
+                let joystick = match joystick_subsystem.open(index) {
+                    Ok(j) => j,
+                    Err(e) => {
+                        error!("[Frontend][JOYSTICK] failed: {:?}", e);
+                        return None;
+                    }
+                };
+
+                if game_controller_subsystem.is_game_controller(index) {
+                    info!("[Frontend][CONTROLLER] Attempting to open controller {}",
+                          index);
+
+                    match game_controller_subsystem.open(index) {
+                        Ok(c) => {
+                            info!("[Frontend][CONTROLLER] Success: opened \"{}\"", c.name());
+                            info!("[Frontend][CONTROLLER] Success: opened \"{}\"", c.mapping());
+                            (Some(c), joystick)
+                        }
+                        Err(e) => {
+                            error!("[Frontend][CONTROLLER] failed: {:?}", e);
+                            return None;
+                        }
+                    }
+                } else {
+                    info!("[Frontend][CONTROLLER] {} is not a game controller", index);
+                    info!("[Frontend][JOYSTICK] Success: opened \"{}\"", joystick.name());
+                    (None, joystick)
+                }
+            }
+            _ => return None,
+        };
+
+        let id = joystick.instance_id() as u32;
+        match controller {
+            Some(controller) => self.push_controller(id, controller),
+            None => self.push_joystick(id, joystick),
         }
+
+        self.players.get(&id).cloned()
     }
 
     pub fn push_controller(&mut self, id: u32, controller: GameController) {
         if !self.ids.contains(&id) {
-            info!("REGISTERED CONTROLLER {:?}", id);
+            self.register_player(id);
+            info!("REGISTERED CONTROLLER {:?} -> PLAYER {:?}", id, self.player(id));
 
-            self.controllers.push(controller);
+            self.controllers.insert(id, controller);
             self.ids.push(id);
             self.g_ids.push(id);
         }
@@ -32,14 +129,31 @@ impl Controllers {
 
     pub fn push_joystick(&mut self, id: u32, joystick: Joystick) {
         if !self.ids.contains(&id) {
-            info!("REGISTERED JOYSTICK {:?}", id);
+            self.register_player(id);
+            info!("REGISTERED JOYSTICK {:?} -> PLAYER {:?}", id, self.player(id));
 
-            self.joysticks.push(joystick);
+            self.joysticks.insert(id, joystick);
             self.ids.push(id);
             self.j_ids.push(id);
         }
     }
 
+    /// Close an unplugged device and release its player slot
+    pub fn remove(&mut self, id: u32) -> Option<u8> {
+        if !self.ids.contains(&id) {
+            return None;
+        }
+
+        info!("UNREGISTERED DEVICE {:?} -> PLAYER {:?}", id, self.player(id));
+
+        self.controllers.remove(&id);
+        self.joysticks.remove(&id);
+        self.ids.retain(|value| *value != id);
+        self.g_ids.retain(|value| *value != id);
+        self.j_ids.retain(|value| *value != id);
+        self.players.remove(&id)
+    }
+
     pub fn is_controller(&self, id: u32) -> bool {
         self.g_ids.contains(&id)
     }
@@ -47,4 +161,64 @@ impl Controllers {
     pub fn is_joystick(&self, id: u32) -> bool {
         self.j_ids.contains(&id)
     }
+
+    fn register_player(&mut self, id: u32) {
+        let player = (0..MAX_PLAYERS)
+            .find(|player| !self.players.values().any(|value| value == player))
+            .unwrap_or((self.ids.len() % MAX_PLAYERS as usize) as u8);
+        self.players.insert(id, player);
+    }
+
+    /// The controller N is the player N
+    pub fn player(&self, id: u32) -> u8 {
+        *self.players.get(&id).unwrap_or(&0)
+    }
+
+    /// Game controllers and their player
+    pub fn controller_players(&self) -> Vec<(u32, u8)> {
+        self.g_ids.iter().map(|id| (*id, self.player(*id))).collect()
+    }
+
+    /// Color of the LED of the game controller `id`, false without LED
+    #[cfg(feature = "led_touchpad")]
+    pub fn set_led(&self, id: u32, r: u8, g: u8, b: u8) -> bool {
+        unsafe {
+            let controller = ffi::SDL_GameControllerFromInstanceID(id as i32);
+            !controller.is_null() && ffi::SDL_GameControllerSetLED(controller, r, g, b) == 0
+        }
+    }
+
+    #[cfg(not(feature = "led_touchpad"))]
+    pub fn set_led(&self, _id: u32, _r: u8, _g: u8, _b: u8) -> bool {
+        false
+    }
+
+    /// First finger on the first touchpad of the game controller `id`, from
+    /// 0 to 1 in the touchpad
+    #[cfg(feature = "led_touchpad")]
+    pub fn touchpad(&self, id: u32) -> Option<(f64, f64)> {
+        unsafe {
+            let controller = ffi::SDL_GameControllerFromInstanceID(id as i32);
+            if controller.is_null() || ffi::SDL_GameControllerGetNumTouchpads(controller) < 1 {
+                return None;
+            }
+
+            let (mut state, mut x, mut y, mut pressure) = (0u8, 0.0, 0.0, 0.0);
+            if ffi::SDL_GameControllerGetTouchpadFinger(controller,
+                                                        0,
+                                                        0,
+                                                        &mut state,
+                                                        &mut x,
+                                                        &mut y,
+                                                        &mut pressure) != 0 || state == 0 {
+                return None;
+            }
+            Some((x as f64, y as f64))
+        }
+    }
+
+    #[cfg(not(feature = "led_touchpad"))]
+    pub fn touchpad(&self, _id: u32) -> Option<(f64, f64)> {
+        None
+    }
 }
@@ -0,0 +1,153 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use sdl2::VideoSubsystem;
+
+use unicorn::gfx::{Scale, Screen};
+use unicorn::unicorn::Unicorn;
+use unicorn::unicorn::crash;
+use unicorn::unicorn::output::Frame;
+
+use renderer::renderer::{Renderer, WindowOptions};
+
+const WIDTH: usize = 320;
+const HEIGHT: usize = 240;
+
+/// Number of frames displayed by the profiler
+const PROFILER_HISTORY: usize = 64;
+/// Number of lines of the log console
+const LOG_LINES: usize = 12;
+
+/// Second window with the profiler, the log console and the VM inspector,
+/// so the debug UI doesn't hide the game
+pub struct DebugWindow {
+    renderer: Renderer,
+    screen: Screen,
+    update_times: VecDeque<f64>,
+    draw_times: VecDeque<f64>,
+}
+
+fn duration_ms(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+}
+
+impl DebugWindow {
+    pub fn new(sdl_video: VideoSubsystem) -> DebugWindow {
+        info!("[Frontend][DEBUG] Opening the debug window");
+
+        let mut screen = Screen::new(WIDTH, HEIGHT);
+
+        let mut options = WindowOptions::new();
+        options.title = "Unicorn Console - Debug".to_string();
+        options.vsync = false;
+
+        let renderer =
+            Renderer::new_with_window(sdl_video, &mut screen, false, false, Scale::Scale2x, &options)
+                .unwrap();
+
+        DebugWindow {
+            renderer: renderer,
+            screen: screen,
+            update_times: VecDeque::with_capacity(PROFILER_HISTORY),
+            draw_times: VecDeque::with_capacity(PROFILER_HISTORY),
+        }
+    }
+
+    pub fn id(&mut self) -> u32 {
+        self.renderer.window_id()
+    }
+
+    pub fn push_times(&mut self, update: Duration, draw: Duration) {
+        if self.update_times.len() >= PROFILER_HISTORY {
+            self.update_times.pop_front();
+            self.draw_times.pop_front();
+        }
+
+        self.update_times.push_back(duration_ms(update));
+        self.draw_times.push_back(duration_ms(draw));
+    }
+
+    fn average(values: &VecDeque<f64>) -> f64 {
+        if values.is_empty() {
+            return 0.0;
+        }
+        values.iter().fold(0.0, |acc, &value| acc + value) / values.len() as f64
+    }
+
+    fn draw_profiler(&mut self, uc: &Unicorn) {
+        self.screen.print("PROFILER".to_string(), 2, 2, 8);
+        self.screen.print(format!("{:.0} FPS", uc.fps), 2, 10, 7);
+        self.screen.print(format!("UPDATE {:.2}MS", DebugWindow::average(&self.update_times)),
+                          2,
+                          18,
+                          11);
+        self.screen.print(format!("DRAW   {:.2}MS", DebugWindow::average(&self.draw_times)),
+                          2,
+                          26,
+                          12);
+
+        // One bar per frame, 16.6ms (60 FPS) is the top of the graph
+        let graph_x = 80;
+        let graph_height = 30.0;
+        for i in 0..self.update_times.len() {
+            let x = graph_x + (i * 2) as i32;
+
+            let update = (self.update_times[i] / 16.6 * graph_height) as i32;
+            let draw = (self.draw_times[i] / 16.6 * graph_height) as i32;
+
+            self.screen.rectfill(x, 34 - update, x + 1, 34, 11);
+            self.screen.rectfill(x, 34 - update - draw, x + 1, 34 - update, 12);
+        }
+        self.screen.line(graph_x, 4, graph_x + (PROFILER_HISTORY * 2) as i32, 4, 5);
+    }
+
+    fn draw_inspector(&mut self, uc: &Unicorn) {
+        let (filename, uuid) = match uc.cartridges.get(uc.current_cartridge) {
+            Some(cartridge) => (cartridge.filename.clone(), cartridge.cartridge.meta.uuid.clone()),
+            None => ("".to_string(), "".to_string()),
+        };
+
+        let camera = {
+            let screen = uc.screen.lock().unwrap();
+            format!("{},{}", screen.camera.x, screen.camera.y)
+        };
+
+        let lines = vec![format!("CART    {}", filename),
+                         format!("UUID    {}", uuid),
+                         format!("CODE    {:?}", uc.current_code_type),
+                         format!("STATE   {:?}", uc.state),
+                         format!("PALETTE {}", uc.palettes.lock().unwrap().name),
+                         format!("CAMERA  {}", camera),
+                         format!("TIME    {:.2} (REAL {:.2})",
+                                 uc.info.lock().unwrap().time_sec(),
+                                 uc.info.lock().unwrap().real_time_sec())];
+
+        self.screen.print("VM".to_string(), 2, 42, 8);
+        for (idx, line) in lines.iter().enumerate() {
+            self.screen.print(line.clone(), 2, 50 + idx as i32 * 8, 7);
+        }
+    }
+
+    fn draw_logs(&mut self) {
+        let y = HEIGHT as i32 - (LOG_LINES as i32 + 1) * 8 - 2;
+        let max_chars = (WIDTH as i32 / self.screen.text_width("X")) as usize;
+
+        self.screen.rectfill(0, y - 2, WIDTH as i32, HEIGHT as i32, 1);
+        self.screen.print("LOGS".to_string(), 2, y, 8);
+
+        for (idx, line) in crash::last_logs(LOG_LINES).iter().enumerate() {
+            let line: String = line.chars().take(max_chars).collect();
+            self.screen.print(line, 2, y + 8 + idx as i32 * 8, 6);
+        }
+    }
+
+    pub fn draw(&mut self, uc: &Unicorn) {
+        self.screen.cls(0);
+
+        self.draw_profiler(uc);
+        self.draw_inspector(uc);
+        self.draw_logs();
+
+        self.renderer.blit(&Frame::new(&self.screen));
+    }
+}
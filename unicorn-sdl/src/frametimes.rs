@@ -1,10 +1,34 @@
 use std::time::{Duration, Instant};
 use std::thread;
 
+/// How the frames are paced
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameLimiter {
+    /// `present` waits for the vertical blank of a 60 Hz display, the
+    /// cartridges at another rate are paced by the sleep
+    VSync,
+    /// The frontend sleeps until the next frame
+    Sleep,
+}
+
+impl FrameLimiter {
+    pub fn from_name(name: &str) -> Option<FrameLimiter> {
+        match name {
+            "vsync" => Some(FrameLimiter::VSync),
+            "sleep" => Some(FrameLimiter::Sleep),
+            _ => None,
+        }
+    }
+}
+
+// The sleep of the OS is not precise, the end of the wait is a busy loop
+const SPIN_DURATION_MS: u64 = 2;
+
 pub struct FrameTimes {
     frame_duration: Duration,
     last_time: Instant,
     target_time: Instant,
+    pub limiter: FrameLimiter,
 }
 
 impl FrameTimes {
@@ -14,6 +38,7 @@ impl FrameTimes {
             frame_duration: frame_duration,
             last_time: now,
             target_time: now + frame_duration,
+            limiter: FrameLimiter::Sleep,
         }
     }
 
@@ -23,18 +48,42 @@ impl FrameTimes {
         self.target_time = now + self.frame_duration;
     }
 
+    pub fn get_frame_duration(&self) -> Duration {
+        self.frame_duration
+    }
+
+    pub fn set_frame_duration(&mut self, frame_duration: Duration) {
+        self.frame_duration = frame_duration;
+        self.reset();
+    }
+
     pub fn update(&mut self) -> Duration {
         let now = Instant::now();
         let delta = now - self.last_time;
         self.last_time = now;
         self.target_time += self.frame_duration;
+
+        // More than a frame late (slow frame, window moved...), the next
+        // frames are not rushed to catch up
+        if now > self.target_time + self.frame_duration {
+            self.target_time = now + self.frame_duration;
+        }
         delta
     }
 
     pub fn limit(&self) {
+        if self.limiter == FrameLimiter::VSync && self.frame_duration == Duration::from_secs(1) / 60 {
+            return;
+        }
+
+        let spin = Duration::from_millis(SPIN_DURATION_MS);
         let now = Instant::now();
-        if now < self.target_time {
-            thread::sleep(self.target_time - now);
+        if now + spin < self.target_time {
+            thread::sleep(self.target_time - now - spin);
+        }
+
+        while Instant::now() < self.target_time {
+            thread::yield_now();
         }
     }
 
@@ -5,26 +5,35 @@ use std::time::Duration;
 use sdl2::event::{Event, WindowEvent};
 
 use std::path::Path;
+use std::process::Command;
+use std::thread;
 
 use chrono::prelude::*;
 use std::time::Instant;
 
-use sdl2::controller::Axis;
+use sdl2::controller::{Axis, Button};
 use sdl2::keyboard::Scancode;
 use sdl2::mouse::MouseButton;
 
 use renderer;
 use fps;
-use frametimes;
+use frametimes::{self, FrameLimiter};
 use controllers;
-use input::{map_axis, map_button, map_button_joystick, map_axis_joystick};
+use debug_window::DebugWindow;
+use input::{map_axis, map_button, map_hat};
 
 #[cfg(target_os = "emscripten")]
 use emscripten;
 
 use unicorn;
 use unicorn::gfx::Scale;
+use unicorn::unicorn::UnicornState;
+use unicorn::unicorn::display::DisplayConfig;
+use unicorn::unicorn::handheld::{self, Handheld};
+use unicorn::unicorn::overlay::ProfilePicker;
+use unicorn::unicorn::profile::{Profile, DEFAULT_PROFILE};
 use unicorn::config::scancode;
+use unicorn::config::hotkeys::{self, Hotkey};
 
 
 pub fn map_sdlscancode(code: Scancode) -> scancode::Scancode {
@@ -319,6 +328,11 @@ pub struct Frontend {
     pub uc: unicorn::unicorn::Unicorn,
     scale: Scale,
     fps_counter: fps::FpsCounter,
+    debug_window: Option<DebugWindow>,
+    /// Preset of `--handheld`, none on a desktop
+    handheld: Option<Handheld>,
+    /// The code editor was open at the last frame
+    editing: bool,
 }
 
 
@@ -328,12 +342,30 @@ impl Frontend {
                 opengl: bool,
                 show_mouse: bool)
                 -> FrontendResult<Frontend> {
+        Frontend::init_with_window(scale,
+                                   fullscreen,
+                                   opengl,
+                                   show_mouse,
+                                   renderer::renderer::WindowOptions::new())
+    }
+
+    pub fn init_with_window(scale: Scale,
+                            fullscreen: bool,
+                            opengl: bool,
+                            show_mouse: bool,
+                            window: renderer::renderer::WindowOptions)
+                            -> FrontendResult<Frontend> {
         info!("[Frontend] SDL2 init");
         let sdl_context = try!(sdl2::init());
 
         info!("[Frontend] SDL2 Video init");
         let sdl_video = try!(sdl_context.video());
 
+        // The text typed is given by the TextInput events, for
+        // `keyboard_text` and the code editor
+        info!("[Frontend] SDL2 text input");
+        sdl_video.text_input().start();
+
         info!("[Frontend] SDL2 event pump");
         let event_pump = try!(sdl_context.event_pump());
 
@@ -341,14 +373,33 @@ impl Frontend {
         try!(sdl_context.audio());
 
         let uc = unicorn::unicorn::Unicorn::new();
+        uc.info.lock().unwrap().display = DisplayConfig::new(scale, fullscreen);
 
         let renderer = {
             let screen = &mut uc.screen.lock().unwrap();
 
             info!("[Frontend] creating renderer");
-            renderer::renderer::Renderer::new(sdl_video, screen, fullscreen, opengl, scale).unwrap()
+            renderer::renderer::Renderer::new_with_window(sdl_video,
+                                                          screen,
+                                                          fullscreen,
+                                                          opengl,
+                                                          scale,
+                                                          &window)
+                    .unwrap()
         };
 
+        // Fallback on the sleep when the display is not at 60 Hz or the
+        // driver has no vsync
+        let mut times = frametimes::FrameTimes::new(Duration::from_secs(1) / 60);
+        times.limiter = if window.vsync && renderer.has_vsync() {
+            frametimes::FrameLimiter::VSync
+        } else {
+            frametimes::FrameLimiter::Sleep
+        };
+        info!("[Frontend] Frame limiter {:?}", times.limiter);
+        uc.info.lock().unwrap().display.frame_limiter =
+            if window.vsync { "vsync" } else { "sleep" }.to_string();
+
         info!("[Frontend] Disable mouse cursor ? {:?}", show_mouse);
         sdl_context.mouse().show_cursor(show_mouse);
 
@@ -357,10 +408,13 @@ impl Frontend {
             event_pump: event_pump,
             renderer: renderer,
             controllers: controllers::Controllers::new(),
-            times: frametimes::FrameTimes::new(Duration::from_secs(1) / 60),
+            times: times,
             uc: uc,
             scale: scale,
             fps_counter: fps::FpsCounter::new(),
+            debug_window: None,
+            handheld: None,
+            editing: false,
         })
     }
 
@@ -373,63 +427,249 @@ impl Frontend {
         self.uc.setup();
     }
 
-    pub fn init_controllers(&mut self, pathdb: String) {
-        info!("[Frontend] Init Controllers");
+    pub fn save_window_geometry(&mut self, filename: &str) {
+        self.renderer.save_geometry(filename);
+    }
 
-        let game_controller_subsystem = self.sdl.game_controller().unwrap();
+    pub fn toggle_debug_window(&mut self) {
+        if self.debug_window.is_some() {
+            info!("[Frontend] Closing the debug window");
+            self.debug_window = None;
+            return;
+        }
 
-        info!("[Frontend] Loading the database of Game Controller");
-        info!("[Frontend] -> {:?}",
-              game_controller_subsystem.load_mappings(Path::new(&pathdb)));
+        match self.sdl.video() {
+            Ok(sdl_video) => self.debug_window = Some(DebugWindow::new(sdl_video)),
+            Err(e) => error!("[Frontend] Failed to open the debug window: {}", e),
+        }
+    }
 
-        let available = match game_controller_subsystem.num_joysticks() {
-            Ok(n) => n,
-            Err(e) => panic!("[Frontend] can't enumerate joysticks: {}", e),
-        };
+    pub fn set_safe_mode(&mut self, value: bool) {
+        info!("[Frontend] Safe mode {:?}", value);
 
-        info!("[Frontend][CONTROLLER] {} joysticks available", available);
+        self.uc.set_safe_mode(value);
+    }
 
-        for id in 0..available {
-            if game_controller_subsystem.is_game_controller(id) {
-                info!("[Frontend][CONTROLLER] Attempting to open controller {}",
-                      id);
+    pub fn init_profile(&mut self, root: &str, name: &str) -> Result<(), String> {
+        info!("[Frontend] Init Profile {:?}", name);
+
+        self.uc.set_profile(root, name)
+    }
+
+    /// Profile chosen at boot among the existing ones: Up/Down select it,
+    /// Return confirms it and Escape keeps the default one. None when the
+    /// window is closed
+    #[cfg(not(target_os = "emscripten"))]
+    pub fn pick_profile(&mut self, root: &str) -> Option<String> {
+        let names = Profile::list(root);
+        if names.len() < 2 {
+            return Some(DEFAULT_PROFILE.to_string());
+        }
+
+        info!("[Frontend] Pick a profile in {:?}", names);
 
-                match game_controller_subsystem.open(id) {
-                    Ok(c) => {
-                        // We managed to find and open a game controller,
-                        // exit the loop
-                        info!("[Frontend][CONTROLLER] Success: opened \"{}\"", c.name());
-                        info!("[Frontend][CONTROLLER] Success: opened \"{}\"", c.mapping());
+        self.uc.palettes.lock().unwrap().init();
+        let mut picker = ProfilePicker::new(names);
 
-                        self.controllers.push_controller(id, Some(c).unwrap());
-                        break;
+        loop {
+            let (mut up, mut down, mut confirm) = (false, false, false);
+            for event in self.event_pump.poll_iter() {
+                match event {
+                    Event::Quit { .. } |
+                    Event::Window { win_event: WindowEvent::Close, .. } => return None,
+                    Event::Window { win_event: WindowEvent::SizeChanged(_, _), .. } => {
+                        self.renderer.invalidate();
+                    }
+                    Event::KeyDown { scancode: Some(scancode), .. } => {
+                        match scancode {
+                            Scancode::Up => up = true,
+                            Scancode::Down => down = true,
+                            Scancode::Return | Scancode::KpEnter => confirm = true,
+                            Scancode::Escape => return Some(DEFAULT_PROFILE.to_string()),
+                            _ => (),
+                        }
                     }
-                    Err(e) => error!("[Frontend][CONTROLLER] failed: {:?}", e),
+                    _ => (),
                 }
-            } else {
-                info!("[Frontend][CONTROLLER] {} is not a game controller", id);
+            }
+
+            if let Some(name) = picker.update(up, down, confirm) {
+                return Some(name);
+            }
+
+            {
+                let mut screen = self.uc.screen.lock().unwrap();
+                screen.cls(-1);
+                picker.draw(&mut screen);
+            }
+            let frame = self.uc.display_frame();
+            self.renderer.blit(&frame);
+
+            thread::sleep(Duration::from_millis(16));
+        }
+    }
+
+    #[cfg(target_os = "emscripten")]
+    pub fn pick_profile(&mut self, _root: &str) -> Option<String> {
+        Some(DEFAULT_PROFILE.to_string())
+    }
+
+    /// The keybindings of the profile replace the global ones
+    pub fn init_keybindings(&mut self, path: String) {
+        info!("[Frontend] Init Keybindings");
+
+        let profile_path = self.uc.profile.dir().join("keybindings.json");
+        let path = if profile_path.exists() {
+            profile_path.to_string_lossy().into_owned()
+        } else {
+            path
+        };
+
+        if Path::new(&path).exists() {
+            self.uc.load_keybindings(&path);
+        } else {
+            info!("[Frontend] No keybindings file {:?}, using the defaults", path);
+        }
+    }
+
+    /// Length, FPS and scale of the GIF recordings, the file of the profile
+    /// replaces the global one
+    pub fn init_record_config(&mut self, path: String) {
+        info!("[Frontend] Init Record config");
+
+        let profile_path = self.uc.profile.dir().join("record.json");
+        let path = if profile_path.exists() {
+            profile_path.to_string_lossy().into_owned()
+        } else {
+            path
+        };
+
+        if Path::new(&path).exists() {
+            self.uc.load_record_config(&path);
+        } else {
+            info!("[Frontend] No record config {:?}, using the defaults", path);
+        }
+    }
+
+    /// Size of the window chosen in the pause menu, saved in the profile
+    pub fn init_display_config(&mut self) {
+        let path = self.uc.profile.dir().join("display.json");
+        if !path.exists() {
+            return;
+        }
+
+        info!("[Frontend] Init Display config");
+
+        match DisplayConfig::from_file(&path.to_string_lossy()) {
+            Ok(config) => self.set_display(config, false),
+            Err(e) => error!("[Frontend] {}", e),
+        }
+    }
+
+    /// Preset of a handheld PC: fullscreen at the integer scale of its
+    /// screen, without the cursor of the mouse
+    pub fn set_handheld(&mut self, handheld: Handheld) {
+        info!("[Frontend] Handheld {:?}", handheld.name);
+
+        let config = {
+            let screen = self.uc.screen.lock().unwrap();
+            let current = self.uc.info.lock().unwrap().display.clone();
+            handheld.display(&current, screen.width, screen.height)
+        };
+        self.set_display(config, false);
+        self.sdl.mouse().show_cursor(false);
+        self.handheld = Some(handheld);
+    }
+
+    /// Resize the window or change the filter, and remember the choice in
+    /// the profile
+    pub fn set_display(&mut self, config: DisplayConfig, save: bool) {
+        if self.uc.info.lock().unwrap().display == config {
+            return;
+        }
+
+        let previous = self.uc.info.lock().unwrap().display.clone();
+        if !previous.same_window(&config) {
+            self.renderer.set_display(&self.uc.screen.lock().unwrap(),
+                                      config.get_scale(),
+                                      config.fullscreen);
+        }
+        if previous.filter != config.filter {
+            self.renderer.set_filter(&self.uc.screen.lock().unwrap(), &config.filter);
+        }
+        // The vsync of the window stays until the next start
+        if previous.frame_limiter != config.frame_limiter {
+            self.times.limiter = match FrameLimiter::from_name(&config.frame_limiter) {
+                Some(FrameLimiter::VSync) if self.renderer.has_vsync() => FrameLimiter::VSync,
+                _ => FrameLimiter::Sleep,
+            };
+            info!("[Frontend] Frame limiter {:?}", self.times.limiter);
+        }
+        self.uc.info.lock().unwrap().display = config.clone();
+
+        if save {
+            if let Err(e) = config.save(&self.uc.profile.path("display.json")) {
+                error!("[Frontend] Failed to save the display config: {}", e);
             }
         }
+    }
+
+    /// Apply the size asked in the pause menu or by the cartridge, and the
+    /// resolution of `mode(w, h)`: the window keeps its scale
+    fn update_display(&mut self) {
+        let request = self.uc.info.lock().unwrap().display_request.take();
+        if let Some(config) = request {
+            self.set_display(config, true);
+        }
 
+        let size = {
+            let screen = self.uc.screen.lock().unwrap();
+            (screen.width as u32, screen.height as u32)
+        };
+        if size != self.renderer.screen_size() {
+            info!("[Frontend] Resolution {:?}", size);
+            let config = self.uc.info.lock().unwrap().display.clone();
+            self.renderer.set_display(&self.uc.screen.lock().unwrap(),
+                                      config.get_scale(),
+                                      config.fullscreen);
+        }
+    }
+
+    /// Frames per second of the cartridge, from its metadata
+    fn update_rate(&mut self) {
+        let frame_duration = Duration::from_secs(1) / self.uc.info.lock().unwrap().fps;
+        if frame_duration != self.times.get_frame_duration() {
+            info!("[Frontend] Frame duration {:?}", frame_duration);
+            self.times.set_frame_duration(frame_duration);
+        }
+    }
+
+    pub fn init_libraries(&mut self, path: String) {
+        info!("[Frontend] Init Libraries");
+
+        self.uc.set_library_dir(&path);
+    }
+
+    pub fn init_controllers(&mut self, pathdb: String) {
+        info!("[Frontend] Init Controllers");
+
+        let game_controller_subsystem = self.sdl.game_controller().unwrap();
         let joystick_subsystem = self.sdl.joystick().unwrap();
 
+        info!("[Frontend] Loading the database of Game Controller");
+        info!("[Frontend] -> {:?}",
+              game_controller_subsystem.load_mappings(Path::new(&pathdb)));
+
         let available = match joystick_subsystem.num_joysticks() {
             Ok(n) => n,
             Err(e) => panic!("[Frontend][JOYSTICK] can't enumerate joysticks: {}", e),
         };
 
-        info!("[Frontend][JOYSTICK] {} joysticks available", available);
+        info!("[Frontend][CONTROLLER] {} joysticks available", available);
 
-        // Iterate over all available joysticks and stop once we manage to
-        // open one.
+        self.controllers.init(game_controller_subsystem, joystick_subsystem);
         for id in 0..available {
-            match joystick_subsystem.open(id) {
-                Ok(c) => {
-                    info!("[Frontend][JOYSTICK] Success: opened \"{}\"", c.name());
-                    self.controllers.push_joystick(id, Some(c).unwrap());
-                }
-                Err(e) => error!("[Frontend][JOYSTICK] failed: {:?}", e),
-            }
+            self.controllers.open(id);
         }
     }
 
@@ -483,10 +723,10 @@ impl Frontend {
             self.uc.fps = self.fps_counter.get_fps();
 
             let mouse_state = self.event_pump.mouse_state();
-            let (width, height) = self.renderer.get_dimensions();
-
-            let mouse_state_x = (mouse_state.x() as f32 * (400.0 / width as f32)) as i32;
-            let mouse_state_y = (mouse_state.y() as f32 * (240.0 / height as f32)) as i32;
+            let (mouse_state_x, mouse_state_y) =
+                self.renderer.mouse_to_screen(mouse_state.x(),
+                                              mouse_state.y(),
+                                              &self.uc.screen.lock().unwrap());
 
             self.uc
                 .players
@@ -500,17 +740,33 @@ impl Frontend {
                 .unwrap()
                 .set_mouse_y(mouse_state_y);
 
+            self.uc
+                .players
+                .lock()
+                .unwrap()
+                .set_mouse_buttons(mouse_state.left(), mouse_state.right(), mouse_state.middle());
+
             self.uc.players.lock().unwrap().clear_text();
+            self.uc.players.lock().unwrap().clear_mouse_wheel();
+
+            // The event pump is borrowed by the loop, the window is opened/closed after it
+            let mut toggle_debug_window = false;
+            let mut pressed_hotkeys = Vec::new();
 
             for event in self.event_pump.poll_iter() {
                 match event {
                     Event::Quit { .. } => break 'main,
                     Event::Window { win_event: WindowEvent::SizeChanged(_, _), .. } => {
-                        // self.renderer
-                        //    .update_viewport(&self.uc.screen.lock().unwrap());
+                        // Some drivers lose the content of the textures
+                        self.renderer.invalidate();
                     }
-                    Event::Window { win_event: WindowEvent::Close, .. } => {
-                        break 'main;
+                    Event::Window { window_id, win_event: WindowEvent::Close, .. } => {
+                        let debug_window_id = self.debug_window.as_mut().map(|w| w.id());
+                        if debug_window_id == Some(window_id) {
+                            toggle_debug_window = true;
+                        } else {
+                            break 'main;
+                        }
                     }
                     Event::MouseButtonDown { mouse_btn, .. } => {
                         let mut left = false;
@@ -540,11 +796,12 @@ impl Frontend {
                             .unwrap()
                             .mouse_button_up();
                     }
+                    Event::MouseWheel { y, .. } => {
+                        self.uc.players.lock().unwrap().mouse_wheel_moved(y);
+                    }
                     Event::TextInput { text, .. } => {
                         // info!("TEXT INPUT {:?}", text);
-                        if text.len() == 1 {
-                            self.uc.players.lock().unwrap().set_text(text.clone());
-                        }
+                        self.uc.players.lock().unwrap().push_text(&text);
                     }
                     Event::KeyDown { scancode: Some(scancode), keycode, keymod, repeat, .. } => {
                         // info!("KEY DOWN {:?} {:?} {:?}", scancode, keycode, keymod);
@@ -562,28 +819,15 @@ impl Frontend {
                                       repeat,
                                       self.uc.info.lock().unwrap().elapsed_time);
 
-                        if scancode == Scancode::F2 {
-                            self.uc.configuration.lock().unwrap().toggle_info_overlay();
-                        } else if scancode == Scancode::F3 {
-                            let dt = Utc::now();
-                            self.uc
-                                .screenshot(&("screenshot-".to_string() +
-                                              &dt.format("%Y-%m-%d-%H-%M-%S.png").to_string()));
-                        } else if scancode == Scancode::F4 {
-                            let record_screen = self.uc.is_recording();
-                            if !record_screen {
-                                let dt = Utc::now();
-                                self.uc
-                                    .start_record(&("record-".to_string() +
-                                                    &dt.format("%Y-%m-%d-%H-%M-%S.gif")
-                                        .to_string()));
-                            } else {
-                                self.uc.stop_record();
-                            }
-                        } else if scancode == Scancode::F5 {
-                            self.uc.save_current_cartridge();
-                        } else if scancode == Scancode::F6 || scancode == Scancode::AcBack {
-                            self.uc.switch_code();
+                        let hotkey = if scancode == Scancode::AcBack {
+                            Some(Hotkey::SwitchCode)
+                        } else {
+                            hotkeys::from_scancode(map_sdlscancode(scancode))
+                        };
+                        if let (Some(hotkey), false) = (hotkey, repeat) {
+                            let shift = keymod.intersects(sdl2::keyboard::LSHIFTMOD |
+                                                          sdl2::keyboard::RSHIFTMOD);
+                            pressed_hotkeys.push((hotkey, shift));
                         }
 
                         if self.uc.players.lock().unwrap().get_value_quick(0, 7) == 1 {
@@ -598,17 +842,43 @@ impl Frontend {
                             .key_up(map_sdlmod(keymod), map_sdlscancode(scancode));
                     }
 
+                    Event::ControllerDeviceAdded { which: index, .. } |
+                    Event::JoyDeviceAdded { which: index, .. } => {
+                        if let Some(player) = self.controllers.open(index as u32) {
+                            info!("[Frontend] Device {:?} plugged -> player {:?}", index, player);
+                        }
+                    }
+
+                    Event::ControllerDeviceRemoved { which: id, .. } |
+                    Event::JoyDeviceRemoved { which: id, .. } => {
+                        if let Some(player) = self.controllers.remove(id as u32) {
+                            info!("[Frontend] Device {:?} unplugged, releasing the player {:?}", id, player);
+                            self.uc.players.lock().unwrap().release(player);
+                        }
+                    }
+
                     Event::ControllerButtonDown { which: id, button, .. } => {
                         if !self.controllers.is_controller(id as u32) {
                             break;
                         }
 
+                        let player = self.controllers.player(id as u32);
+
+                        // Start opens the pause menu, Back the on-screen keyboard
+                        if self.handheld.is_some() {
+                            match button {
+                                Button::Start => self.uc.switch_pause(),
+                                Button::Back => self.uc.info.lock().unwrap().keyboard_request = true,
+                                _ => (),
+                            }
+                        }
+
                         if let Some(key) = map_button(button) {
                             self.uc
                                 .players
                                 .lock()
                                 .unwrap()
-                                .key_down_direct(0,
+                                .key_down_direct(player,
                                                  key,
                                                  false,
                                                  self.uc.info.lock().unwrap().elapsed_time)
@@ -620,8 +890,10 @@ impl Frontend {
                             break;
                         }
 
+                        let player = self.controllers.player(id as u32);
+
                         if let Some(key) = map_button(button) {
-                            self.uc.players.lock().unwrap().key_up_direct(0, key);
+                            self.uc.players.lock().unwrap().key_up_direct(player, key);
                         }
                     }
 
@@ -630,23 +902,25 @@ impl Frontend {
                             break;
                         }
 
+                        let player = self.controllers.player(id as u32);
+
                         if let Some((key, state)) = map_axis(axis, value) {
                             if axis == Axis::LeftX && value == 128 {
-                                self.uc.players.lock().unwrap().key_direc_hor_up(0);
+                                self.uc.players.lock().unwrap().key_direc_hor_up(player);
                             } else if axis == Axis::LeftY && value == -129 {
-                                self.uc.players.lock().unwrap().key_direc_ver_up(0);
+                                self.uc.players.lock().unwrap().key_direc_ver_up(player);
                             } else {
                                 if state {
                                     self.uc
                                         .players
                                         .lock()
                                         .unwrap()
-                                        .key_down_direct(0,
+                                        .key_down_direct(player,
                                                          key,
                                                          false,
                                                          self.uc.info.lock().unwrap().elapsed_time);
                                 } else {
-                                    self.uc.players.lock().unwrap().key_up_direct(0, key);
+                                    self.uc.players.lock().unwrap().key_up_direct(player, key);
                                 }
                             }
                         }
@@ -657,26 +931,24 @@ impl Frontend {
                             break;
                         }
 
-                        if let Some((key, state)) = map_axis_joystick(axis_idx, value) {
-                            if axis_idx == 0 && value == 128 {
-                                self.uc.players.lock().unwrap().key_direc_hor_up(0);
-                            } else if axis_idx == 1 && value == -129 {
-                                self.uc.players.lock().unwrap().key_direc_ver_up(0);
-                            } else {
-                                if state {
-                                    self.uc
-                                        .players
-                                        .lock()
-                                        .unwrap()
-                                        .key_down_direct(0,
-                                                         key,
-                                                         false,
-                                                         self.uc.info.lock().unwrap().elapsed_time);
-                                } else {
-                                    self.uc.players.lock().unwrap().key_up_direct(0, key);
-                                }
-                            }
+                        let player = self.controllers.player(id as u32);
+
+                        let mut players = self.uc.players.lock().unwrap();
+                        let keys = players.joystick.map_axis(axis_idx, value);
+                        players.set_keys(player, &keys, self.uc.info.lock().unwrap().elapsed_time);
+                    }
+
+                    Event::JoyHatMotion { which: id, state, .. } => {
+                        if !self.controllers.is_joystick(id as u32) {
+                            break;
                         }
+
+                        let player = self.controllers.player(id as u32);
+
+                        let (x, y) = map_hat(state);
+                        let mut players = self.uc.players.lock().unwrap();
+                        let keys = players.joystick.map_hat(x, y);
+                        players.set_keys(player, &keys, self.uc.info.lock().unwrap().elapsed_time);
                     }
 
                     Event::JoyButtonDown { which: id, button_idx, .. } => {
@@ -684,15 +956,14 @@ impl Frontend {
                             break;
                         }
 
-                        if let Some(key) = map_button_joystick(button_idx) {
-                            self.uc
-                                .players
-                                .lock()
-                                .unwrap()
-                                .key_down_direct(0,
-                                                 key,
-                                                 false,
-                                                 self.uc.info.lock().unwrap().elapsed_time);
+                        let player = self.controllers.player(id as u32);
+
+                        let mut players = self.uc.players.lock().unwrap();
+                        if let Some(key) = players.joystick.map_button(button_idx) {
+                            players.key_down_direct(player,
+                                                    key,
+                                                    false,
+                                                    self.uc.info.lock().unwrap().elapsed_time);
                         }
                     }
 
@@ -701,8 +972,11 @@ impl Frontend {
                             break;
                         }
 
-                        if let Some(key) = map_button_joystick(button_idx) {
-                            self.uc.players.lock().unwrap().key_up_direct(0, key);
+                        let player = self.controllers.player(id as u32);
+
+                        let mut players = self.uc.players.lock().unwrap();
+                        if let Some(key) = players.joystick.map_button(button_idx) {
+                            players.key_up_direct(player, key);
                         }
                     }
 
@@ -710,19 +984,52 @@ impl Frontend {
                 }
             }
 
+            if toggle_debug_window {
+                self.toggle_debug_window();
+            }
+
+            for (hotkey, shift) in pressed_hotkeys {
+                self.handle_hotkey(hotkey, shift);
+            }
+
+            self.update_controllers();
+
+            let update_start = Instant::now();
             if !self.uc.update() {
                 info!("[Frontend] End of requested");
                 self.uc.stop();
                 break 'main;
             }
 
+            let draw_start = Instant::now();
             self.uc.draw();
+            let draw_end = Instant::now();
+            self.set_frame_time(draw_end.duration_since(update_start));
+            self.uc.trace_span("update", update_start, draw_start);
+            self.uc.trace_span("draw", draw_start, draw_end);
+
+            if let Some(ref mut debug_window) = self.debug_window {
+                debug_window.push_times(draw_start.duration_since(update_start),
+                                        draw_end.duration_since(draw_start));
+                debug_window.draw(&self.uc);
+            }
+
+            let audio_start = Instant::now();
             self.uc.update_sound();
+            self.uc.trace_span("audio", audio_start, Instant::now());
 
             let now = Instant::now();
             let dt = now.duration_since(previous_frame_time);
             previous_frame_time = now;
+            let dt = if handheld::is_suspend(dt) {
+                self.resume(dt)
+            } else {
+                dt
+            };
             self.uc.update_time(dt);
+            self.update_display();
+            self.update_keyboard();
+            self.update_rate();
             self.blit();
         }
     }
@@ -740,10 +1047,10 @@ impl Frontend {
             self.uc.fps = self.fps_counter.get_fps();
 
             let mouse_state = self.event_pump.mouse_state();
-            let (width, height) = self.renderer.get_dimensions();
-
-            let mouse_state_x = (mouse_state.x() as f32 * (400.0 / width as f32)) as i32;
-            let mouse_state_y = (mouse_state.y() as f32 * (240.0 / height as f32)) as i32;
+            let (mouse_state_x, mouse_state_y) =
+                self.renderer.mouse_to_screen(mouse_state.x(),
+                                              mouse_state.y(),
+                                              &self.uc.screen.lock().unwrap());
 
             self.uc
                 .players
@@ -757,8 +1064,16 @@ impl Frontend {
                 .unwrap()
                 .set_mouse_y(mouse_state_y);
 
+            self.uc
+                .players
+                .lock()
+                .unwrap()
+                .set_mouse_buttons(mouse_state.left(), mouse_state.right(), mouse_state.middle());
+
             self.uc.players.lock().unwrap().clear_text();
+            self.uc.players.lock().unwrap().clear_mouse_wheel();
 
+            let mut pressed_hotkeys = Vec::new();
             for event in self.event_pump.poll_iter() {
                 match event {
                     Event::Quit { .. } => return,
@@ -793,10 +1108,11 @@ impl Frontend {
                             .unwrap()
                             .mouse_button_up();
                     }
+                    Event::MouseWheel { y, .. } => {
+                        self.uc.players.lock().unwrap().mouse_wheel_moved(y);
+                    }
                     Event::TextInput { text, .. } => {
-                        if text.len() == 1 {
-                            self.uc.players.lock().unwrap().set_text(text.clone());
-                        }
+                        self.uc.players.lock().unwrap().push_text(&text);
                     }
                     Event::KeyDown { scancode: Some(scancode), keycode, keymod, repeat, .. } => {
                         if scancode == Scancode::AcHome {
@@ -812,28 +1128,15 @@ impl Frontend {
                                       repeat,
                                       self.uc.info.lock().unwrap().elapsed_time);
 
-                        if scancode == Scancode::F2 {
-                            self.uc.configuration.lock().unwrap().toggle_info_overlay();
-                        } else if scancode == Scancode::F3 {
-                            let dt = Utc::now();
-                            self.uc
-                                .screenshot(&("screenshot-".to_string() +
-                                              &dt.format("%Y-%m-%d-%H-%M-%S.png").to_string()));
-                        } else if scancode == Scancode::F4 {
-                            let record_screen = self.uc.is_recording();
-                            if !record_screen {
-                                let dt = Utc::now();
-                                self.uc
-                                    .start_record(&("record-".to_string() +
-                                                    &dt.format("%Y-%m-%d-%H-%M-%S.gif")
-                                        .to_string()));
-                            } else {
-                                self.uc.stop_record();
-                            }
-                        } else if scancode == Scancode::F5 {
-                            self.uc.save_current_cartridge();
-                        } else if scancode == Scancode::F6 || scancode == Scancode::AcBack {
-                            self.uc.switch_code();
+                        let hotkey = if scancode == Scancode::AcBack {
+                            Some(Hotkey::SwitchCode)
+                        } else {
+                            hotkeys::from_scancode(map_sdlscancode(scancode))
+                        };
+                        if let (Some(hotkey), false) = (hotkey, repeat) {
+                            let shift = keymod.intersects(sdl2::keyboard::LSHIFTMOD |
+                                                          sdl2::keyboard::RSHIFTMOD);
+                            pressed_hotkeys.push((hotkey, shift));
                         }
 
                         if self.uc.players.lock().unwrap().get_value_quick(0, 7) == 1 {
@@ -848,17 +1151,34 @@ impl Frontend {
                             .key_up(map_sdlmod(keymod), map_sdlscancode(scancode));
                     }
 
+                    Event::ControllerDeviceAdded { which: index, .. } |
+                    Event::JoyDeviceAdded { which: index, .. } => {
+                        if let Some(player) = self.controllers.open(index as u32) {
+                            info!("[Frontend] Device {:?} plugged -> player {:?}", index, player);
+                        }
+                    }
+
+                    Event::ControllerDeviceRemoved { which: id, .. } |
+                    Event::JoyDeviceRemoved { which: id, .. } => {
+                        if let Some(player) = self.controllers.remove(id as u32) {
+                            info!("[Frontend] Device {:?} unplugged, releasing the player {:?}", id, player);
+                            self.uc.players.lock().unwrap().release(player);
+                        }
+                    }
+
                     Event::ControllerButtonDown { which: id, button, .. } => {
                         if !self.controllers.is_controller(id as u32) {
                             break;
                         }
 
+                        let player = self.controllers.player(id as u32);
+
                         if let Some(key) = map_button(button) {
                             self.uc
                                 .players
                                 .lock()
                                 .unwrap()
-                                .key_down_direct(0,
+                                .key_down_direct(player,
                                                  key,
                                                  false,
                                                  self.uc.info.lock().unwrap().elapsed_time)
@@ -870,8 +1190,10 @@ impl Frontend {
                             break;
                         }
 
+                        let player = self.controllers.player(id as u32);
+
                         if let Some(key) = map_button(button) {
-                            self.uc.players.lock().unwrap().key_up_direct(0, key);
+                            self.uc.players.lock().unwrap().key_up_direct(player, key);
                         }
                     }
 
@@ -880,23 +1202,25 @@ impl Frontend {
                             break;
                         }
 
+                        let player = self.controllers.player(id as u32);
+
                         if let Some((key, state)) = map_axis(axis, value) {
                             if axis == Axis::LeftX && value == 128 {
-                                self.uc.players.lock().unwrap().key_direc_hor_up(0);
+                                self.uc.players.lock().unwrap().key_direc_hor_up(player);
                             } else if axis == Axis::LeftY && value == -129 {
-                                self.uc.players.lock().unwrap().key_direc_ver_up(0);
+                                self.uc.players.lock().unwrap().key_direc_ver_up(player);
                             } else {
                                 if state {
                                     self.uc
                                         .players
                                         .lock()
                                         .unwrap()
-                                        .key_down_direct(0,
+                                        .key_down_direct(player,
                                                          key,
                                                          false,
                                                          self.uc.info.lock().unwrap().elapsed_time);
                                 } else {
-                                    self.uc.players.lock().unwrap().key_up_direct(0, key);
+                                    self.uc.players.lock().unwrap().key_up_direct(player, key);
                                 }
                             }
                         }
@@ -907,26 +1231,24 @@ impl Frontend {
                             break;
                         }
 
-                        if let Some((key, state)) = map_axis_joystick(axis_idx, value) {
-                            if axis_idx == 0 && value == 128 {
-                                self.uc.players.lock().unwrap().key_direc_hor_up(0);
-                            } else if axis_idx == 1 && value == -129 {
-                                self.uc.players.lock().unwrap().key_direc_ver_up(0);
-                            } else {
-                                if state {
-                                    self.uc
-                                        .players
-                                        .lock()
-                                        .unwrap()
-                                        .key_down_direct(0,
-                                                         key,
-                                                         false,
-                                                         self.uc.info.lock().unwrap().elapsed_time);
-                                } else {
-                                    self.uc.players.lock().unwrap().key_up_direct(0, key);
-                                }
-                            }
+                        let player = self.controllers.player(id as u32);
+
+                        let mut players = self.uc.players.lock().unwrap();
+                        let keys = players.joystick.map_axis(axis_idx, value);
+                        players.set_keys(player, &keys, self.uc.info.lock().unwrap().elapsed_time);
+                    }
+
+                    Event::JoyHatMotion { which: id, state, .. } => {
+                        if !self.controllers.is_joystick(id as u32) {
+                            break;
                         }
+
+                        let player = self.controllers.player(id as u32);
+
+                        let (x, y) = map_hat(state);
+                        let mut players = self.uc.players.lock().unwrap();
+                        let keys = players.joystick.map_hat(x, y);
+                        players.set_keys(player, &keys, self.uc.info.lock().unwrap().elapsed_time);
                     }
 
                     Event::JoyButtonDown { which: id, button_idx, .. } => {
@@ -934,15 +1256,14 @@ impl Frontend {
                             break;
                         }
 
-                        if let Some(key) = map_button_joystick(button_idx) {
-                            self.uc
-                                .players
-                                .lock()
-                                .unwrap()
-                                .key_down_direct(0,
-                                                 key,
-                                                 false,
-                                                 self.uc.info.lock().unwrap().elapsed_time);
+                        let player = self.controllers.player(id as u32);
+
+                        let mut players = self.uc.players.lock().unwrap();
+                        if let Some(key) = players.joystick.map_button(button_idx) {
+                            players.key_down_direct(player,
+                                                    key,
+                                                    false,
+                                                    self.uc.info.lock().unwrap().elapsed_time);
                         }
                     }
 
@@ -951,8 +1272,11 @@ impl Frontend {
                             break;
                         }
 
-                        if let Some(key) = map_button_joystick(button_idx) {
-                            self.uc.players.lock().unwrap().key_up_direct(0, key);
+                        let player = self.controllers.player(id as u32);
+
+                        let mut players = self.uc.players.lock().unwrap();
+                        if let Some(key) = players.joystick.map_button(button_idx) {
+                            players.key_up_direct(player, key);
                         }
                     }
 
@@ -960,6 +1284,13 @@ impl Frontend {
                 }
             }
 
+            for (hotkey, shift) in pressed_hotkeys {
+                self.handle_hotkey(hotkey, shift);
+            }
+
+            self.update_controllers();
+
+            let update_start = Instant::now();
             if !self.uc.update() {
                 info!("[Frontend] End of requested");
                 self.uc.stop();
@@ -967,18 +1298,172 @@ impl Frontend {
             }
 
             self.uc.draw();
+            self.set_frame_time(update_start.elapsed());
             self.uc.update_sound();
 
             let now = Instant::now();
             let dt = now.duration_since(previous_frame_time);
             previous_frame_time = now;
             self.uc.update_time(dt);
+            self.update_display();
+            self.update_rate();
             self.blit();
         });
     }
 
+    /// Action of a key of the console, `shift` is held
+    fn handle_hotkey(&mut self, hotkey: Hotkey, shift: bool) {
+        let stamp = Utc::now().format("%Y-%m-%d-%H-%M-%S").to_string();
+
+        match hotkey {
+            Hotkey::Help => self.uc.toggle_help(),
+            Hotkey::InfoOverlay => {
+                if shift {
+                    self.uc.toggle_inspector();
+                } else {
+                    self.uc.configuration.lock().unwrap().toggle_info_overlay();
+                }
+            }
+            Hotkey::Screenshot => {
+                // Shift inverts the scaling of the record config
+                let scaled = self.uc.info.lock().unwrap().record_config.screenshot_scaled != shift;
+                let filename = self.uc.capture_path("screenshot", &stamp, "png");
+                self.uc.screenshot_with_scale(&filename, scaled);
+            }
+            Hotkey::Record => {
+                if !self.uc.is_recording() {
+                    let filename = self.uc.capture_path("record", &stamp, "gif");
+                    self.uc.start_record(&filename);
+                } else {
+                    self.uc.stop_record();
+                }
+            }
+            Hotkey::Save => self.uc.save_current_cartridge(),
+            Hotkey::SwitchCode => self.uc.switch_code(),
+            Hotkey::StatsOverlay => {
+                if shift {
+                    self.uc.toggle_audio_viewer();
+                } else {
+                    self.uc.toggle_stats_overlay();
+                }
+            }
+            Hotkey::DebugWindow => self.toggle_debug_window(),
+            Hotkey::Video => {
+                if !self.uc.is_recording_video() {
+                    let extension = if shift { "webm" } else { "mp4" };
+                    let filename = self.uc.capture_path("video", &stamp, extension);
+                    self.uc.start_video(&filename);
+                } else {
+                    self.uc.stop_video();
+                }
+            }
+            Hotkey::Magnifier => {
+                if shift {
+                    self.uc.toggle_memory_viewer();
+                } else {
+                    self.uc.toggle_magnifier();
+                }
+            }
+            Hotkey::Macro => self.uc.toggle_macro_record(),
+            Hotkey::Console => {
+                // Shift+` types a ~ on the US layouts
+                if !shift {
+                    self.uc.toggle_console();
+                }
+            }
+            Hotkey::FrameDump => {
+                let filename = self.uc.capture_path("framedump", &stamp, "txt");
+                self.uc.frame_dump(&filename);
+            }
+        }
+    }
+
+    /// Touchpads of the controllers read for `touchpad`, and the colors of
+    /// `led` set
+    fn update_controllers(&mut self) {
+        let controllers = self.controllers.controller_players();
+
+        {
+            let mut players = self.uc.players.lock().unwrap();
+            for &(id, player) in controllers.iter() {
+                players.set_touchpad(player, self.controllers.touchpad(id));
+            }
+        }
+
+        let leds: Vec<(u8, (u8, u8, u8))> = self.uc.info.lock().unwrap().leds.drain().collect();
+        for (player, (r, g, b)) in leds {
+            for &(id, controller_player) in controllers.iter() {
+                if controller_player == player && !self.controllers.set_led(id, r, g, b) {
+                    debug!("[Frontend] No LED on the controller {:?}", id);
+                }
+            }
+        }
+    }
+
+    /// The device was suspended during the frame: its time is not given to
+    /// the timers of the cartridge, and the texture is written again in case
+    /// the driver lost it. On a handheld, the cartridge waits in the pause
+    /// menu.
+    fn resume(&mut self, dt: Duration) -> Duration {
+        info!("[Frontend] Resumed after {:?}", dt);
+
+        self.renderer.invalidate();
+        self.times.reset();
+        if self.handheld.is_some() && self.uc.state == UnicornState::RUN {
+            self.uc.switch_pause();
+        }
+
+        self.times.get_frame_duration()
+    }
+
+    /// On-screen keyboard of the handheld, asked by the cartridge or with
+    /// the Back button, and shown when the code editor opens
+    fn update_keyboard(&mut self) {
+        let request = {
+            let mut info = self.uc.info.lock().unwrap();
+            let request = info.keyboard_request;
+            info.keyboard_request = false;
+            request
+        };
+        let editing = self.uc.state == UnicornState::EDITOR;
+        let opened = editing && !self.editing;
+        self.editing = editing;
+
+        let url = match self.handheld {
+            Some(ref handheld) => handheld.keyboard_url,
+            None => {
+                if request {
+                    debug!("[Frontend] No on-screen keyboard outside of a handheld");
+                }
+                return;
+            }
+        };
+
+        if request || opened {
+            info!("[Frontend] Opening the on-screen keyboard {:?}", url);
+
+            match Command::new("xdg-open").arg(url).spawn() {
+                Ok(mut child) => {
+                    thread::spawn(move || child.wait());
+                }
+                Err(e) => error!("[Frontend] Failed to open the on-screen keyboard: {}", e),
+            }
+        }
+    }
+
+    /// Time of `_update` and `_draw`, for `stat("over_budget")`
+    fn set_frame_time(&mut self, duration: Duration) {
+        let seconds = duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1_000_000_000.0;
+        self.uc.info.lock().unwrap().set_frame_time(seconds);
+    }
+
+    /// Present the screen in the window. The GIF, the video and the other
+    /// sinks get the frame of the game as `Output`s of the console.
     pub fn blit(&mut self) {
-        self.renderer.blit(&mut self.uc.screen.lock().unwrap());
+        let blit_start = Instant::now();
+        let frame = self.uc.display_frame();
+        self.renderer.blit(&frame);
+        self.uc.trace_span("blit", blit_start, Instant::now());
         self.times.limit();
     }
 }
\ No newline at end of file
@@ -1,6 +1,7 @@
 use unicorn::config::keys::PX8Key;
 
 use sdl2::controller::{Axis, Button};
+use sdl2::joystick::HatState;
 
 pub fn map_button(button: Button) -> Option<PX8Key> {
     match button {
@@ -14,14 +15,6 @@ pub fn map_button(button: Button) -> Option<PX8Key> {
     }
 }
 
-pub fn map_button_joystick(button: u8) -> Option<PX8Key> {
-    match button {
-        0 => Some(PX8Key::A),
-        1 => Some(PX8Key::B),
-        _ => None,
-    }
-}
-
 pub fn map_axis(axis: Axis, value: i16) -> Option<(PX8Key, bool)> {
     match axis {
         Axis::LeftX => {
@@ -47,27 +40,17 @@ pub fn map_axis(axis: Axis, value: i16) -> Option<(PX8Key, bool)> {
     }
 }
 
-pub fn map_axis_joystick(axis: u8, value: i16) -> Option<(PX8Key, bool)> {
-    match axis {
-        0 => {
-            match value {
-                -32768...-16384 => Some((PX8Key::Left, true)),
-                -16383...-1 => Some((PX8Key::Left, false)),
-                1...16383 => Some((PX8Key::Right, false)),
-                16384...32767 => Some((PX8Key::Right, true)),
-                _ => None,
-            }
-        }
-
-        1 => {
-            match value {
-                -32768...-16384 => Some((PX8Key::Up, true)),
-                -16383...-1 => Some((PX8Key::Up, false)),
-                1...16383 => Some((PX8Key::Down, false)),
-                16384...32767 => Some((PX8Key::Down, true)),
-                _ => None,
-            }
-        }
-        _ => None,
+/// Direction of a hat of a joystick, `x`/`y` in -1..1
+pub fn map_hat(state: HatState) -> (i32, i32) {
+    match state {
+        HatState::Centered => (0, 0),
+        HatState::Up => (0, -1),
+        HatState::Right => (1, 0),
+        HatState::Down => (0, 1),
+        HatState::Left => (-1, 0),
+        HatState::RightUp => (1, -1),
+        HatState::RightDown => (1, 1),
+        HatState::LeftUp => (-1, -1),
+        HatState::LeftDown => (-1, 1),
     }
 }
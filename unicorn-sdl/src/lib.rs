@@ -14,6 +14,7 @@ pub mod frametimes;
 pub mod input;
 pub mod renderer;
 pub mod frontend;
+pub mod debug_window;
 
 #[cfg(target_os = "emscripten")]
 pub mod emscripten;
\ No newline at end of file
@@ -1,14 +1,21 @@
 pub mod renderer {
-    use unicorn;
     use unicorn::gfx::{Scale, Screen};
+    use unicorn::unicorn::output::Frame;
 
     use sdl2::surface::Surface;
 
     use sdl2::VideoSubsystem;
+    use sdl2::hint;
+    use sdl2::video::{FullscreenType, WindowPos};
     use sdl2::render;
-    use sdl2::pixels::PixelFormatEnum;
+    use sdl2::render::BlendMode;
+    use sdl2::rect::Rect;
+    use sdl2::pixels::{Color, PixelFormatEnum};
     use time::PreciseTime;
+    use std::cmp;
     use std::path::Path;
+    use std::fs::File;
+    use std::io::{Read, Write};
 
     #[derive(Clone, Debug)]
     pub enum RendererError {
@@ -19,10 +26,109 @@ pub mod renderer {
 
     pub type RendererResult<T> = Result<T, RendererError>;
 
+    // Not exposed by the sdl2 crate, available since SDL 2.0.5
+    const SDL_WINDOW_ALWAYS_ON_TOP: u32 = 0x00008000;
+    // The drawable has the resolution of the device on the HiDPI screens
+    const SDL_WINDOW_ALLOW_HIGHDPI: u32 = 0x00002000;
+    const SDL_RENDERER_PRESENTVSYNC: u32 = 0x00000004;
+
+    /// Position/size of the window, and how it stays above the other ones
+    #[derive(Clone, Debug)]
+    pub struct WindowOptions {
+        pub position: Option<(i32, i32)>,
+        pub size: Option<(u32, u32)>,
+        pub always_on_top: bool,
+        pub title: String,
+        pub vsync: bool,
+    }
+
+    impl WindowOptions {
+        pub fn new() -> WindowOptions {
+            WindowOptions {
+                position: None,
+                size: None,
+                always_on_top: false,
+                title: "Unicorn Console".to_string(),
+                vsync: true,
+            }
+        }
+
+        /// Restore the geometry saved by `save_geometry`: "x y width height"
+        pub fn load_geometry(&mut self, filename: &str) {
+            let mut data = String::new();
+            match File::open(filename) {
+                Ok(mut f) => {
+                    if f.read_to_string(&mut data).is_err() {
+                        return;
+                    }
+                }
+                Err(_) => return,
+            }
+
+            let values: Vec<i32> = data.split_whitespace()
+                .filter_map(|value| value.parse::<i32>().ok())
+                .collect();
+
+            if values.len() == 4 && values[2] > 0 && values[3] > 0 {
+                info!("[SDL] Restoring the window geometry {:?}", values);
+
+                self.position = Some((values[0], values[1]));
+                self.size = Some((values[2] as u32, values[3] as u32));
+            }
+        }
+    }
+
+    /// Opacity of the shade of a filter over each pixel of the viewport
+    /// (`width` x `height`), for a screen of `screen_width` x `screen_height`.
+    /// The curvature of a CRT would need shaders, only its scanlines and its
+    /// darker corners are drawn.
+    pub fn overlay_alpha(filter: &str,
+                         width: usize,
+                         height: usize,
+                         screen_width: usize,
+                         screen_height: usize)
+                         -> Vec<u8> {
+        let mut alpha = vec![0; width * height];
+
+        for y in 0..height {
+            // Position in the pixel of the screen, from 0 to 1
+            let fy = (y * screen_height) as f64 / height as f64;
+            let fy = fy - fy.floor();
+
+            for x in 0..width {
+                let fx = (x * screen_width) as f64 / width as f64;
+                let fx = fx - fx.floor();
+
+                let value = match filter {
+                    "scanlines" => if fy >= 0.5 { 110.0 } else { 0.0 },
+                    "lcd" => if fx >= 0.75 || fy >= 0.75 { 80.0 } else { 0.0 },
+                    "crt" => {
+                        let scanline = if fy >= 0.5 { 70.0 } else { 0.0 };
+
+                        let dx = x as f64 / width as f64 * 2.0 - 1.0;
+                        let dy = y as f64 / height as f64 * 2.0 - 1.0;
+                        let distance = (dx * dx + dy * dy).sqrt() / 2f64.sqrt();
+                        let vignette = ((distance - 0.6) / 0.4).max(0.0) * 180.0;
+
+                        scanline + vignette
+                    }
+                    _ => 0.0,
+                };
+                alpha[x + y * width] = value.min(255.0) as u8;
+            }
+        }
+        alpha
+    }
+
     pub struct Renderer {
         pub renderer: render::Renderer<'static>,
         pub texture: render::Texture,
-        buffer_rgb: Vec<u8>,
+        /// Colors of the frame in the texture
+        last_frame: Vec<u8>,
+        /// Post-processing of the screen, see `display::FILTERS`
+        filter: String,
+        /// Shade drawn over the viewport by the filter, and its size
+        overlay: Option<((u32, u32), render::Texture)>,
         frame: u32,
     }
 
@@ -33,18 +139,49 @@ pub mod renderer {
                    opengl: bool,
                    scale: Scale)
                    -> RendererResult<Renderer> {
-            info!("[SDL] Creating window fullscreen={:?} opengl={:?}",
+            Renderer::new_with_window(sdl_video,
+                                      screen,
+                                      fullscreen,
+                                      opengl,
+                                      scale,
+                                      &WindowOptions::new())
+        }
+
+        pub fn new_with_window(sdl_video: VideoSubsystem,
+                               screen: &mut Screen,
+                               fullscreen: bool,
+                               opengl: bool,
+                               scale: Scale,
+                               options: &WindowOptions)
+                               -> RendererResult<Renderer> {
+            info!("[SDL] Creating window fullscreen={:?} opengl={:?} {:?}",
                   fullscreen,
-                  opengl);
+                  opengl,
+                  options);
 
-            let mut window_builder = sdl_video.window("Unicorn Console",
-                        (screen.width as usize * scale.factor()) as u32,
-                        (screen.height as usize * scale.factor()) as u32);
+            let (width, height) = match options.size {
+                Some(size) => size,
+                None => {
+                    ((screen.width as usize * scale.factor()) as u32,
+                     (screen.height as usize * scale.factor()) as u32)
+                }
+            };
+
+            let mut window_builder = sdl_video.window(&options.title, width, height);
+
+            let mut flags = window_builder.window_flags() | SDL_WINDOW_ALLOW_HIGHDPI;
+            if options.always_on_top {
+                flags |= SDL_WINDOW_ALWAYS_ON_TOP;
+            }
+            window_builder.set_window_flags(flags);
 
             let wb = if fullscreen {
                 window_builder.fullscreen()
             } else {
-                window_builder.resizable().position_centered()
+                match options.position {
+                    Some((x, y)) => window_builder.resizable().position(x, y),
+                    None => window_builder.resizable().position_centered(),
+                }
             };
 
             let mut window = (if opengl { wb.opengl() } else { wb }).build().unwrap();
@@ -55,11 +192,14 @@ pub mod renderer {
             }
 
             info!("[SDL] Creating renderer");
-            let renderer = window.renderer()
-                .accelerated()
-                .present_vsync()
-                .build()
-                .unwrap();
+            // Only one window should wait for the vsync, or the frame rate is divided
+            let renderer_builder = window.renderer().accelerated();
+            let renderer_builder = if options.vsync {
+                renderer_builder.present_vsync()
+            } else {
+                renderer_builder
+            };
+            let renderer = renderer_builder.build().unwrap();
 
 
             info!("[SDL] Creating texture");
@@ -75,72 +215,273 @@ pub mod renderer {
             Ok(Renderer {
                 renderer: renderer,
                 texture: texture,
-                buffer_rgb: vec![0; 0],
+                last_frame: Vec::new(),
+                filter: "none".to_string(),
+                overlay: None,
                 frame: 0,
             })
         }
 
-        pub fn blit(&mut self, screen: &mut Screen) {
-            // Translate the pixel values to RGB colors.
-            let src_buffer = &screen.frame_buffer;
-            let rgb_buffer_len = src_buffer.len() * 3;
-            if self.buffer_rgb.len() != rgb_buffer_len {
-                self.buffer_rgb = vec![0; rgb_buffer_len];
-            }
-            let rgb_buffer = &mut self.buffer_rgb;
-            let mut palette = unicorn::unicorn::PALETTE.lock().unwrap();
-
-            let mut j = 0;
-            let mut cached_pixel: u32 = 0;
-            let mut rgb = palette.get_rgb(cached_pixel as u32);
+        /// The texture is written again at the next blit
+        pub fn invalidate(&mut self) {
+            self.last_frame.clear();
+        }
 
+        /// Show a frame converted by the console, like its other outputs
+        pub fn blit(&mut self, frame: &Frame) {
             let start = PreciseTime::now();
 
-            for pixel in src_buffer.iter() {
-                if *pixel != cached_pixel {
-                    rgb = palette.get_rgb(*pixel as u32);
-                    cached_pixel = *pixel;
-                }
-                rgb_buffer[j] = rgb.r;
-                rgb_buffer[j + 1] = rgb.g;
-                rgb_buffer[j + 2] = rgb.b;
-                j += 3;
-            }
+            // The colors come from the palette table of the frame, on the
+            // CPU. The streaming texture is kept between the frames and only
+            // written when they changed. A frame of another resolution waits
+            // for the texture of `set_display`.
+            let size = (frame.width as u32, frame.height as u32);
+            if size == self.screen_size() && self.last_frame != frame.rgb {
+                let row_len = frame.width * 3;
+                let rgb = &frame.rgb;
 
-            let t1 = PreciseTime::now();
+                self.texture
+                    .with_lock(None, |buffer: &mut [u8], pitch: usize| {
+                        for (y, row) in rgb.chunks(row_len).enumerate() {
+                            buffer[y * pitch..y * pitch + row_len].copy_from_slice(row);
+                        }
+                    })
+                    .unwrap();
 
-            // Update the texture with the RGB values.
-            self.texture
-                .update(None, &rgb_buffer, screen.width * 3)
-                .unwrap();
+                self.last_frame.clear();
+                self.last_frame.extend_from_slice(&frame.rgb);
+            }
 
-            let t2 = PreciseTime::now();
+            let t1 = PreciseTime::now();
 
+            let viewport = self.viewport(size.0, size.1);
+            self.renderer.set_draw_color(Color::RGB(0, 0, 0));
+            self.renderer.clear();
             self.renderer
-                .copy(&self.texture, None, None)
+                .copy(&self.texture, None, Some(viewport))
                 .unwrap();
+            self.draw_overlay(frame.width, frame.height, viewport);
 
-            let t3 = PreciseTime::now();
+            let t2 = PreciseTime::now();
 
             self.renderer.present();
 
-            let t4 = PreciseTime::now();
+            let t3 = PreciseTime::now();
 
             if cfg!(feature = "blit_perf") {
                 if self.frame % 60 == 0 {
-                    info!("gen_rgb:{} update_tex:{} copy_tex:{} present:{}",
+                    info!("update_tex:{} copy_tex:{} present:{}",
                           start.to(t1),
                           t1.to(t2),
-                          t2.to(t3),
-                          t3.to(t4))
+                          t2.to(t3))
                 }
             }
 
             self.frame += 1;
         }
 
+        /// Filter of the screen: the "smooth" one is the linear scaling of
+        /// the texture, the others a shade drawn over it
+        pub fn set_filter(&mut self, screen: &Screen, filter: &str) {
+            info!("[SDL] Filter {:?}", filter);
+
+            let smooth = filter == "smooth";
+            if smooth != (self.filter == "smooth") {
+                // Read by SDL when the texture is created
+                hint::set("SDL_RENDER_SCALE_QUALITY", if smooth { "1" } else { "0" });
+                self.create_texture(screen);
+            }
+
+            self.filter = filter.to_string();
+            self.overlay = None;
+        }
+
+        fn create_texture(&mut self, screen: &Screen) {
+            info!("[SDL] Creating texture {:?}x{:?}", screen.width, screen.height);
+            self.texture = self.renderer
+                .create_texture(PixelFormatEnum::RGB24,
+                                render::TextureAccess::Streaming,
+                                screen.width as u32,
+                                screen.height as u32)
+                .unwrap();
+            self.invalidate();
+            // The shade of the filter follows the lines of the screen
+            self.overlay = None;
+        }
+
+        /// Resolution of the texture, the one of the screen at the last
+        /// `set_display`
+        pub fn screen_size(&self) -> (u32, u32) {
+            let query = self.texture.query();
+            (query.width, query.height)
+        }
+
+        /// The shade is computed again when the viewport is resized
+        fn draw_overlay(&mut self, screen_width: usize, screen_height: usize, viewport: Rect) {
+            if self.filter == "none" || self.filter == "smooth" {
+                return;
+            }
+
+            let size = (viewport.width(), viewport.height());
+            let outdated = match self.overlay {
+                Some((overlay_size, _)) => overlay_size != size,
+                None => true,
+            };
+
+            if outdated {
+                let alpha = overlay_alpha(&self.filter,
+                                          size.0 as usize,
+                                          size.1 as usize,
+                                          screen_width,
+                                          screen_height);
+
+                // Black pixels, only the alpha changes (B, G, R, A in memory)
+                let mut pixels = vec![0; alpha.len() * 4];
+                for (pixel, value) in pixels.chunks_mut(4).zip(alpha.iter()) {
+                    pixel[3] = *value;
+                }
+
+                let mut texture = match self.renderer
+                          .create_texture_static(PixelFormatEnum::ARGB8888, size.0, size.1) {
+                    Ok(texture) => texture,
+                    Err(e) => {
+                        error!("[SDL] Failed to create the filter: {:?}", e);
+                        self.filter = "none".to_string();
+                        return;
+                    }
+                };
+                texture.set_blend_mode(BlendMode::Blend);
+                texture.update(None, &pixels, size.0 as usize * 4).unwrap();
+                self.overlay = Some((size, texture));
+            }
+
+            if let Some((_, ref texture)) = self.overlay {
+                self.renderer.copy(texture, None, Some(viewport)).unwrap();
+            }
+        }
+
+        /// Area of the drawable where the screen is displayed: the biggest
+        /// integer scale that fits, centered, for square pixels. The drawable
+        /// is bigger than the window on the HiDPI screens.
+        pub fn viewport(&self, width: u32, height: u32) -> Rect {
+            let (out_width, out_height) = self.renderer.output_size().unwrap_or((width, height));
+            let factor = cmp::min(out_width / width, out_height / height);
+
+            let (view_width, view_height) = if factor >= 1 {
+                (width * factor, height * factor)
+            } else {
+                // Smaller than the screen, the aspect ratio is kept
+                let ratio = f64::min(out_width as f64 / width as f64,
+                                     out_height as f64 / height as f64);
+                (cmp::max(1, (width as f64 * ratio) as u32),
+                 cmp::max(1, (height as f64 * ratio) as u32))
+            };
+
+            Rect::new(((out_width - view_width) / 2) as i32,
+                      ((out_height - view_height) / 2) as i32,
+                      view_width,
+                      view_height)
+        }
+
+        /// Position of the mouse, in the coordinates of the window, on the screen
+        pub fn mouse_to_screen(&self, x: i32, y: i32, screen: &Screen) -> (i32, i32) {
+            let (window_width, window_height) = self.renderer.window().unwrap().size();
+            let (out_width, out_height) = self.renderer
+                .output_size()
+                .unwrap_or((window_width, window_height));
+
+            // Points of the window to pixels of the drawable
+            let x = x as f64 * out_width as f64 / cmp::max(1, window_width) as f64;
+            let y = y as f64 * out_height as f64 / cmp::max(1, window_height) as f64;
+
+            let viewport = self.viewport(screen.width as u32, screen.height as u32);
+            let x = (x - viewport.x() as f64) * screen.width as f64 / viewport.width() as f64;
+            let y = (y - viewport.y() as f64) * screen.height as f64 / viewport.height() as f64;
+
+            (x.floor() as i32, y.floor() as i32)
+        }
+
+        /// The presentation waits for the vertical blank of a 60 Hz display,
+        /// so it paces the frames alone
+        pub fn has_vsync(&self) -> bool {
+            if self.renderer.info().flags & SDL_RENDERER_PRESENTVSYNC == 0 {
+                return false;
+            }
+
+            match self.renderer.window().unwrap().display_mode() {
+                Ok(mode) => {
+                    info!("[SDL] Refresh rate of the display {:?} Hz", mode.refresh_rate);
+                    mode.refresh_rate >= 59 && mode.refresh_rate <= 61
+                }
+                Err(e) => {
+                    warn!("[SDL] Unknown refresh rate of the display: {}", e);
+                    false
+                }
+            }
+        }
+
+        pub fn window_id(&mut self) -> u32 {
+            self.renderer.window().unwrap().id()
+        }
+
         pub fn get_dimensions(&mut self) -> (u32, u32) {
             self.renderer.window().unwrap().size()
         }
+
+        /// Resize the window to `scale` times the screen, or switch it to the
+        /// fullscreen. The texture keeps the size of the screen, it is only
+        /// created again when the resolution changed.
+        pub fn set_display(&mut self, screen: &Screen, scale: Scale, fullscreen: bool) {
+            info!("[SDL] Switching the window to scale={:?} fullscreen={:?}",
+                  scale.factor(),
+                  fullscreen);
+
+            {
+                let window = self.renderer.window_mut().unwrap();
+
+                if fullscreen {
+                    if let Err(e) = window.set_fullscreen(FullscreenType::Desktop) {
+                        error!("[SDL] Failed to switch to the fullscreen: {}", e);
+                    }
+                } else {
+                    if let Err(e) = window.set_fullscreen(FullscreenType::Off) {
+                        error!("[SDL] Failed to leave the fullscreen: {}", e);
+                    }
+
+                    let width = (screen.width * scale.factor()) as u32;
+                    let height = (screen.height * scale.factor()) as u32;
+                    if let Err(e) = window.set_size(width, height) {
+                        error!("[SDL] Failed to resize the window: {:?}", e);
+                    }
+                    window.set_position(WindowPos::Centered, WindowPos::Centered);
+                }
+            }
+
+            let query = self.texture.query();
+            if query.width != screen.width as u32 || query.height != screen.height as u32 {
+                self.create_texture(screen);
+            }
+        }
+
+        pub fn save_geometry(&mut self, filename: &str) {
+            let (x, y) = self.renderer.window().unwrap().position();
+            let (width, height) = self.get_dimensions();
+
+            info!("[SDL] Saving the window geometry {:?} {:?} {:?} {:?} in {:?}",
+                  x,
+                  y,
+                  width,
+                  height,
+                  filename);
+
+            match File::create(filename) {
+                Ok(mut f) => {
+                    if let Err(e) = write!(f, "{} {} {} {}", x, y, width, height) {
+                        error!("[SDL] Failed to save the window geometry: {}", e);
+                    }
+                }
+                Err(e) => error!("[SDL] Failed to save the window geometry: {}", e),
+            }
+        }
     }
 }
\ No newline at end of file
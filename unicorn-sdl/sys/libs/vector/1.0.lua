@@ -0,0 +1,25 @@
+-- 2D vectors
+local vector = {}
+vector.__index = vector
+
+function vector.new(x, y)
+  return setmetatable({x = x or 0, y = y or 0}, vector)
+end
+
+function vector.__add(a, b)
+  return vector.new(a.x + b.x, a.y + b.y)
+end
+
+function vector.__sub(a, b)
+  return vector.new(a.x - b.x, a.y - b.y)
+end
+
+function vector.__mul(a, k)
+  return vector.new(a.x * k, a.y * k)
+end
+
+function vector:len()
+  return math.sqrt(self.x * self.x + self.y * self.y)
+end
+
+return vector
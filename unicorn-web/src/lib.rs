@@ -299,11 +299,11 @@ impl UnicornWeb {
 
     fn draw( &mut self ) {
         let mut palette = unicorn::unicorn::PALETTE.lock().unwrap();
-        let framebuffer = &self.state.screen.lock().unwrap().frame_buffer;
+        let screen = &self.state.screen.lock().unwrap();
 
         let mut i = 0;
-        for pixel_in in framebuffer.iter() {
-            let rgb = palette.get_rgb(*pixel_in as u32);
+        for pixel_in in screen.frame_buffer.iter() {
+            let rgb = palette.get_rgb(screen.display_color(*pixel_in));
             unsafe {
                 FRAMEBUFFER[i] = ((rgb.b as u32) << 16) | ((rgb.g as u32) << 8) | ((rgb.r as u32)) | 0xFF000000;
             }
@@ -1,3 +1,7 @@
+pub mod png;
+pub mod quantize;
+pub mod stats;
+
 use std::fs::File;
 use std::io::BufReader;
 use std::io::Cursor;
@@ -15,11 +19,17 @@ use std::u32;
 use std::str;
 
 use serde_json;
+use rand;
+
+#[cfg(feature = "image")]
+use image;
 
 use regex::Regex;
 
 use unicorn;
 use unicorn::RGB;
+use unicorn::score::sha256;
+use unicorn::libraries;
 
 use gfx::Sprite;
 
@@ -49,8 +59,29 @@ __map__ 400*60 -> 24.000
 
 __sfx__
 
+WW SS N0 .. N31     => one sfx per line, in hexadecimal:
+                       WW: waveform (0: square, 1: triangle, 2: saw, 3: noise)
+                       SS: speed, number of frames of each note
+                       N0..N31: semitone of each note from C-0, "--" for a rest
+
 __music__
 
+FF DD S0 S1 S2 S3   => one pattern per line, in hexadecimal:
+                       FF: flags (1: loop start, 2: loop end, 4: stop)
+                       DD: duration in frames
+                       S0..S3: sfx of each channel, "--" for nothing
+
+__libs__
+
+NAME VERSION        => version of a shared library pinned by the cartridge
+=lib:NAME@VERSION   => code of a library embedded by the exports, then each
+|CODE                  line of the code after a '|'
+
+__meta__
+
+uuid XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX   => identity of the cartridge, kept when the file is renamed
+fps 30|60|120                               => frames per second of _update/_draw, 60 without it
+
 */
 
 
@@ -450,20 +481,493 @@ impl CartridgeGFF {
     }
 }
 
-pub struct CartridgeMusic {}
+pub const MUSIC_CHANNELS: usize = 4;
+
+pub const MUSIC_LOOP_START: u8 = 1;
+pub const MUSIC_LOOP_END: u8 = 2;
+pub const MUSIC_STOP: u8 = 4;
+
+/// One step of a song: the sfx played on each channel (-1 for nothing)
+/// during `duration` frames
+#[derive(Clone, Debug, PartialEq)]
+pub struct MusicPattern {
+    pub flags: u8,
+    pub duration: u32,
+    pub sfx: [i32; MUSIC_CHANNELS],
+}
+
+impl MusicPattern {
+    pub fn new() -> MusicPattern {
+        MusicPattern {
+            flags: 0,
+            duration: 60,
+            sfx: [-1; MUSIC_CHANNELS],
+        }
+    }
+
+    /// "flags duration sfx0 sfx1 sfx2 sfx3" in hexadecimal, "--" for an empty channel
+    pub fn from_line(line: &str) -> Option<MusicPattern> {
+        let values: Vec<&str> = line.split_whitespace().collect();
+        if values.len() != 2 + MUSIC_CHANNELS {
+            return None;
+        }
+
+        let mut pattern = MusicPattern::new();
+        pattern.flags = match u8::from_str_radix(values[0], 16) {
+            Ok(flags) => flags,
+            Err(_) => return None,
+        };
+        pattern.duration = match u32::from_str_radix(values[1], 16) {
+            Ok(duration) => duration,
+            Err(_) => return None,
+        };
+
+        for i in 0..MUSIC_CHANNELS {
+            pattern.sfx[i] = match values[2 + i] {
+                "--" => -1,
+                value => {
+                    match i32::from_str_radix(value, 16) {
+                        Ok(sfx) => sfx,
+                        Err(_) => return None,
+                    }
+                }
+            };
+        }
+
+        Some(pattern)
+    }
+
+    pub fn get_data(&self) -> String {
+        let mut data = format!("{:02x} {:02x}", self.flags, self.duration);
+        for sfx in self.sfx.iter() {
+            if *sfx < 0 {
+                data.push_str(" --");
+            } else {
+                data.push_str(&format!(" {:02x}", sfx));
+            }
+        }
+        data
+    }
+
+    pub fn is_loop_start(&self) -> bool {
+        self.flags & MUSIC_LOOP_START != 0
+    }
+
+    pub fn is_loop_end(&self) -> bool {
+        self.flags & MUSIC_LOOP_END != 0
+    }
+
+    pub fn is_stop(&self) -> bool {
+        self.flags & MUSIC_STOP != 0
+    }
+}
+
+pub struct CartridgeMusic {
+    pub patterns: Vec<MusicPattern>,
+}
 
 impl CartridgeMusic {
-    pub fn new(_lines: &[String]) -> CartridgeMusic {
+    pub fn new(lines: &[String]) -> CartridgeMusic {
         info!("[CARTRIDGE] CartridgeMusic");
-        CartridgeMusic {}
+
+        let mut patterns = Vec::new();
+        for line in lines {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match MusicPattern::from_line(line) {
+                Some(pattern) => patterns.push(pattern),
+                None => warn!("[CARTRIDGE] Invalid music pattern {:?}", line),
+            }
+        }
+
+        CartridgeMusic { patterns: patterns }
     }
 
     pub fn new_from_bytes(_v: &[u8]) -> CartridgeMusic {
-        CartridgeMusic {}
+        CartridgeMusic::empty()
     }
 
     pub fn empty() -> CartridgeMusic {
-        CartridgeMusic {}
+        CartridgeMusic { patterns: Vec::new() }
+    }
+
+    pub fn get_data(&mut self) -> String {
+        let mut data = String::new();
+
+        for pattern in self.patterns.iter() {
+            data.push_str(&pattern.get_data());
+            data.push('\n');
+        }
+
+        data
+    }
+}
+
+pub const SFX_NOTES: usize = 32;
+
+/// Waveforms of the sfx, in the order of their index in the cartridge
+pub static SFX_WAVEFORMS: [&'static str; 4] = ["square", "triangle", "saw", "noise"];
+
+/// Notes drawn in the piano roll of the editor, a note is a semitone from
+/// C-0 (-1 for a rest) played during `speed` frames
+#[derive(Clone, Debug, PartialEq)]
+pub struct SfxPattern {
+    pub waveform: u8,
+    pub speed: u8,
+    pub notes: [i32; SFX_NOTES],
+}
+
+impl SfxPattern {
+    pub fn new() -> SfxPattern {
+        SfxPattern {
+            waveform: 0,
+            speed: 8,
+            notes: [-1; SFX_NOTES],
+        }
+    }
+
+    /// "waveform speed note0 ... note31" in hexadecimal, "--" for a rest
+    pub fn from_line(line: &str) -> Option<SfxPattern> {
+        let values: Vec<&str> = line.split_whitespace().collect();
+        if values.len() != 2 + SFX_NOTES {
+            return None;
+        }
+
+        let mut pattern = SfxPattern::new();
+        pattern.waveform = match u8::from_str_radix(values[0], 16) {
+            Ok(waveform) if (waveform as usize) < SFX_WAVEFORMS.len() => waveform,
+            _ => return None,
+        };
+        pattern.speed = match u8::from_str_radix(values[1], 16) {
+            Ok(speed) if speed > 0 => speed,
+            _ => return None,
+        };
+
+        for i in 0..SFX_NOTES {
+            pattern.notes[i] = match values[2 + i] {
+                "--" => -1,
+                value => {
+                    match i32::from_str_radix(value, 16) {
+                        Ok(note) => note,
+                        Err(_) => return None,
+                    }
+                }
+            };
+        }
+
+        Some(pattern)
+    }
+
+    pub fn get_data(&self) -> String {
+        let mut data = format!("{:02x} {:02x}", self.waveform, self.speed);
+        for note in self.notes.iter() {
+            if *note < 0 {
+                data.push_str(" --");
+            } else {
+                data.push_str(&format!(" {:02x}", note));
+            }
+        }
+        data
+    }
+
+    pub fn waveform_name(&self) -> &'static str {
+        SFX_WAVEFORMS[self.waveform as usize % SFX_WAVEFORMS.len()]
+    }
+}
+
+pub struct CartridgeSfx {
+    pub sfx: Vec<SfxPattern>,
+}
+
+impl CartridgeSfx {
+    pub fn new(lines: &[String]) -> CartridgeSfx {
+        info!("[CARTRIDGE] CartridgeSfx");
+
+        let mut sfx = Vec::new();
+        for line in lines {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match SfxPattern::from_line(line) {
+                Some(pattern) => sfx.push(pattern),
+                None => warn!("[CARTRIDGE] Invalid sfx {:?}", line),
+            }
+        }
+
+        CartridgeSfx { sfx: sfx }
+    }
+
+    pub fn empty() -> CartridgeSfx {
+        CartridgeSfx { sfx: Vec::new() }
+    }
+
+    pub fn get_data(&mut self) -> String {
+        let mut data = String::new();
+
+        for pattern in self.sfx.iter() {
+            data.push_str(&pattern.get_data());
+            data.push('\n');
+        }
+
+        data
+    }
+}
+
+/// Versions of the shared libraries pinned by the cartridge, one "name version" per line,
+/// and the code of the libraries embedded by the exports: "=lib:name@version" then
+/// each line of the code after a '|'
+pub struct CartridgeLibs {
+    pub pins: Vec<(String, String)>,
+    /// Code of the libraries by their `require` value
+    pub embedded: Vec<(String, String)>,
+}
+
+impl CartridgeLibs {
+    pub fn empty() -> CartridgeLibs {
+        CartridgeLibs {
+            pins: Vec::new(),
+            embedded: Vec::new(),
+        }
+    }
+
+    pub fn new(lines: &[String]) -> CartridgeLibs {
+        info!("[CARTRIDGE] CartridgeLibs");
+
+        let mut libs = CartridgeLibs::empty();
+        for line in lines {
+            if line.starts_with('=') {
+                libs.embedded.push((line[1..].trim().to_string(), String::new()));
+                continue;
+            }
+            if line.starts_with('|') {
+                match libs.embedded.last_mut() {
+                    Some(&mut (_, ref mut code)) => {
+                        code.push_str(&line[1..]);
+                        code.push('\n');
+                    }
+                    None => warn!("[CARTRIDGE] Library code without its library {:?}", line),
+                }
+                continue;
+            }
+
+            let values: Vec<&str> = line.split_whitespace().collect();
+            if values.len() == 2 && libraries::is_valid_name(values[0]) &&
+               libraries::is_valid_version(values[1]) {
+                libs.set(values[0], values[1]);
+            } else if !values.is_empty() {
+                warn!("[CARTRIDGE] Invalid library pin {:?}", line);
+            }
+        }
+        libs
+    }
+
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.pins
+            .iter()
+            .find(|&&(ref pin, _)| pin == name)
+            .map(|&(_, ref version)| version.clone())
+    }
+
+    pub fn set(&mut self, name: &str, version: &str) {
+        for pin in self.pins.iter_mut() {
+            if pin.0 == name {
+                pin.1 = version.to_string();
+                return;
+            }
+        }
+        self.pins.push((name.to_string(), version.to_string()));
+    }
+
+    /// Code embedded for `require(value)`
+    pub fn embedded_code(&self, value: &str) -> Option<String> {
+        self.embedded
+            .iter()
+            .find(|&&(ref require, _)| require == value)
+            .map(|&(_, ref code)| code.clone())
+    }
+
+    pub fn embed(&mut self, value: &str, code: &str) {
+        self.embedded.retain(|&(ref require, _)| require != value);
+        self.embedded.push((value.to_string(), code.to_string()));
+    }
+
+    pub fn get_data(&mut self) -> String {
+        let mut data = String::new();
+
+        for &(ref name, ref version) in self.pins.iter() {
+            data.push_str(&format!("{} {}\n", name, version));
+        }
+
+        for &(ref value, ref code) in self.embedded.iter() {
+            data.push_str(&format!("={}\n", value));
+            for line in code.lines() {
+                data.push_str(&format!("|{}\n", line));
+            }
+        }
+
+        data
+    }
+}
+
+/// "xxxxxxxx-xxxx-4xxx-yxxx-xxxxxxxxxxxx" from 128 bits
+fn format_uuid(high: u64, low: u64) -> String {
+    let high = (high & 0xffffffff_ffff0fff) | 0x4000;
+    let low = (low & 0x3fffffff_ffffffff) | 0x80000000_00000000;
+
+    format!("{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            high >> 32,
+            (high >> 16) & 0xffff,
+            high & 0xffff,
+            low >> 48,
+            low & 0xffff_ffffffff)
+}
+
+fn u32_bytes(value: u32) -> [u8; 4] {
+    [(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]
+}
+
+/// Data hashed for a section of the cartridge, after its length so the
+/// sections can't shift into each other
+fn push_section(data: &mut Vec<u8>, section: &[u8]) {
+    data.extend_from_slice(&u32_bytes(section.len() as u32));
+    data.extend_from_slice(section);
+}
+
+/// Random UUID (version 4) of a new cartridge
+pub fn new_uuid() -> String {
+    format_uuid(rand::random::<u64>(), rand::random::<u64>())
+}
+
+/// Frames per second a cartridge could run at
+pub const UPDATE_RATES: [u32; 3] = [30, 60, 120];
+
+/// Metadata of the cartridge, one "key value" per line
+pub struct CartridgeMeta {
+    pub uuid: String,
+    /// One of `UPDATE_RATES`, 0 for the default of the console
+    pub fps: u32,
+}
+
+impl CartridgeMeta {
+    pub fn empty() -> CartridgeMeta {
+        CartridgeMeta {
+            uuid: "".to_string(),
+            fps: 0,
+        }
+    }
+
+    pub fn new(lines: &[String]) -> CartridgeMeta {
+        info!("[CARTRIDGE] CartridgeMeta");
+
+        let mut meta = CartridgeMeta::empty();
+        for line in lines {
+            let values: Vec<&str> = line.split_whitespace().collect();
+            match values.len() {
+                0 => (),
+                2 if values[0] == "uuid" => meta.uuid = values[1].to_string(),
+                2 if values[0] == "fps" => {
+                    match values[1].parse::<u32>() {
+                        Ok(fps) if UPDATE_RATES.contains(&fps) => meta.fps = fps,
+                        _ => warn!("[CARTRIDGE] Invalid fps {:?}, 30, 60 or 120", values[1]),
+                    }
+                }
+                _ => warn!("[CARTRIDGE] Invalid metadata {:?}", line),
+            }
+        }
+        meta
+    }
+
+    pub fn get_data(&mut self) -> String {
+        let mut data = String::new();
+        if !self.uuid.is_empty() {
+            data.push_str(&format!("uuid {}\n", self.uuid));
+        }
+        if self.fps != 0 {
+            data.push_str(&format!("fps {}\n", self.fps));
+        }
+        data
+    }
+}
+
+/// Width and height of the label
+pub const LABEL_SIZE: usize = 128;
+
+/// Image of the cartridge, for the listings and the PNG cartridges: the
+/// colors of 128x128 pixels, 2 hexadecimal digits each
+pub struct CartridgeLabel {
+    pub pixels: Vec<u8>,
+}
+
+impl CartridgeLabel {
+    pub fn empty() -> CartridgeLabel {
+        CartridgeLabel { pixels: Vec::new() }
+    }
+
+    pub fn new(lines: &[String]) -> CartridgeLabel {
+        info!("[CARTRIDGE] CartridgeLabel");
+
+        let mut pixels = Vec::new();
+        for line in lines.iter().take(LABEL_SIZE) {
+            let line = line.as_bytes();
+            for x in 0..LABEL_SIZE {
+                let value = line.get(2 * x..2 * x + 2)
+                    .and_then(|value| str::from_utf8(value).ok())
+                    .and_then(|value| u8::from_str_radix(value, 16).ok());
+                match value {
+                    Some(value) => pixels.push(value),
+                    None => {
+                        warn!("[CARTRIDGE] Invalid label, it is ignored");
+                        return CartridgeLabel::empty();
+                    }
+                }
+            }
+        }
+
+        if pixels.len() != LABEL_SIZE * LABEL_SIZE {
+            if !lines.is_empty() {
+                warn!("[CARTRIDGE] Invalid label size, it is ignored");
+            }
+            return CartridgeLabel::empty();
+        }
+
+        CartridgeLabel { pixels: pixels }
+    }
+
+    /// Label from the frame buffer of the screen, scaled to 128x128
+    pub fn from_frame_buffer(frame_buffer: &[u32], width: usize, height: usize) -> CartridgeLabel {
+        if width == 0 || height == 0 || frame_buffer.len() < width * height {
+            return CartridgeLabel::empty();
+        }
+
+        let mut pixels = Vec::with_capacity(LABEL_SIZE * LABEL_SIZE);
+        for y in 0..LABEL_SIZE {
+            for x in 0..LABEL_SIZE {
+                let value = frame_buffer[(x * width / LABEL_SIZE) + (y * height / LABEL_SIZE) * width];
+                pixels.push(if value > 255 { 255 } else { value as u8 });
+            }
+        }
+
+        CartridgeLabel { pixels: pixels }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pixels.is_empty()
+    }
+
+    pub fn get_data(&self) -> String {
+        let mut data = String::new();
+
+        for line in self.pixels.chunks(LABEL_SIZE) {
+            for value in line {
+                data.push_str(&format!("{:02x}", value));
+            }
+            data.push('\n');
+        }
+
+        data
     }
 }
 
@@ -496,7 +1000,7 @@ impl CartridgeMap {
 
             y += 1;
 
-            if y == 60 {
+            if y == unicorn::MAP_HEIGHT {
                 break;
             }
         }
@@ -509,7 +1013,7 @@ impl CartridgeMap {
 
         for y in 0..unicorn::MAP_HEIGHT {
             for x in 0..unicorn::MAP_WIDTH {
-                let idx_sprite = *self.map.get(x * unicorn::MAP_WIDTH + y).unwrap_or(&0);
+                let idx_sprite = *self.map.get(x + y * unicorn::MAP_WIDTH).unwrap_or(&0);
                 data.push_str(&format!("{:03x}", idx_sprite));
             }
             data.push('\n');
@@ -526,6 +1030,7 @@ impl CartridgeMap {
 pub enum CartridgeFormat {
     UnicornSplittedFormat = 0,
     UnicornFormat = 1,
+    PngFormat = 2,
 }
 
 pub struct Cartridge {
@@ -538,7 +1043,13 @@ pub struct Cartridge {
     pub gff: CartridgeGFF,
     pub code: CartridgeCode,
     pub palette: CartridgePalette,
+    pub sfx: CartridgeSfx,
     pub music: CartridgeMusic,
+    pub libs: CartridgeLibs,
+    pub meta: CartridgeMeta,
+    pub label: CartridgeLabel,
+    /// Hash of the content (code, gfx, map, sound, ...) computed at load
+    pub hash: u64,
     pub format: CartridgeFormat,
 }
 
@@ -637,27 +1148,55 @@ fn read_from_uniformat<R: io::BufRead>(filename: &str, buf: &mut R) -> Result<Ca
         _ => cartridge_gff = CartridgeGFF::empty(),
     }
 
+    let cartridge_sfx = match sections.get_mut("__sfx__") {
+        Some(vec_section) => CartridgeSfx::new(vec_section),
+        _ => CartridgeSfx::empty(),
+    };
+
     match sections.get_mut("__music__") {
         Some(vec_section) => cartridge_music = CartridgeMusic::new(vec_section),
         _ => cartridge_music = CartridgeMusic::empty(),
     }
 
+    let cartridge_libs = match sections.get_mut("__libs__") {
+        Some(vec_section) => CartridgeLibs::new(vec_section),
+        _ => CartridgeLibs::empty(),
+    };
+
+    let cartridge_meta = match sections.get_mut("__meta__") {
+        Some(vec_section) => CartridgeMeta::new(vec_section),
+        _ => CartridgeMeta::empty(),
+    };
+
+    let cartridge_label = match sections.get_mut("__label__") {
+        Some(vec_section) => CartridgeLabel::new(vec_section),
+        _ => CartridgeLabel::empty(),
+    };
+
 
     cartridge_code.set_filename(filename);
 
-    Ok(Cartridge {
-           filename: filename.to_string(),
-           data_filename: "".to_string(),
-           header: header.clone(),
-           version: version.clone(),
-           gfx: cartridge_gfx,
-           code: cartridge_code,
-           palette: cartridge_palette,
-           map: cartridge_map,
-           gff: cartridge_gff,
-           music: cartridge_music,
-           format: CartridgeFormat::UnicornFormat,
-       })
+    let mut cartridge = Cartridge {
+        filename: filename.to_string(),
+        data_filename: "".to_string(),
+        header: header.clone(),
+        version: version.clone(),
+        gfx: cartridge_gfx,
+        code: cartridge_code,
+        palette: cartridge_palette,
+        map: cartridge_map,
+        gff: cartridge_gff,
+        sfx: cartridge_sfx,
+        music: cartridge_music,
+        libs: cartridge_libs,
+        meta: cartridge_meta,
+        label: cartridge_label,
+        hash: 0,
+        format: CartridgeFormat::UnicornFormat,
+    };
+    cartridge.identify();
+
+    Ok(cartridge)
 }
 
 pub fn from_dunicorn_file_raw<R: io::BufRead>(buf_reader: &mut R) -> Result<Cartridge, Error> {
@@ -727,31 +1266,83 @@ pub fn from_dunicorn_file_raw<R: io::BufRead>(buf_reader: &mut R) -> Result<Cart
         _ => cartridge_gff = CartridgeGFF::empty(),
     }
 
+    let cartridge_sfx = match sections.get_mut("__sfx__") {
+        Some(vec_section) => CartridgeSfx::new(vec_section),
+        _ => CartridgeSfx::empty(),
+    };
+
     match sections.get_mut("__music__") {
         Some(vec_section) => cartridge_music = CartridgeMusic::new(vec_section),
         _ => cartridge_music = CartridgeMusic::empty(),
     }
 
+    let cartridge_libs = match sections.get_mut("__libs__") {
+        Some(vec_section) => CartridgeLibs::new(vec_section),
+        _ => CartridgeLibs::empty(),
+    };
+
+    let cartridge_meta = match sections.get_mut("__meta__") {
+        Some(vec_section) => CartridgeMeta::new(vec_section),
+        _ => CartridgeMeta::empty(),
+    };
+
+    let cartridge_label = match sections.get_mut("__label__") {
+        Some(vec_section) => CartridgeLabel::new(vec_section),
+        _ => CartridgeLabel::empty(),
+    };
+
+
+    let mut cartridge = Cartridge {
+        filename: "empty".to_string(),
+        data_filename: "empty.duc".to_string(),
+        header: "".to_string(),
+        version: "".to_string(),
+        gfx: cartridge_gfx,
+        code: cartridge_code,
+        palette: cartridge_palette,
+        map: cartridge_map,
+        gff: cartridge_gff,
+        sfx: cartridge_sfx,
+        music: cartridge_music,
+        libs: cartridge_libs,
+        meta: cartridge_meta,
+        label: cartridge_label,
+        hash: 0,
+        format: CartridgeFormat::UnicornSplittedFormat,
+    };
+    cartridge.identify();
+
+    Ok(cartridge)
+}
 
-    Ok(Cartridge {
-           filename: "empty".to_string(),
-           data_filename: "empty.duc".to_string(),
-           header: "".to_string(),
-           version: "".to_string(),
-           gfx: cartridge_gfx,
-           code: cartridge_code,
-           palette: cartridge_palette,
-           map: cartridge_map,
-           gff: cartridge_gff,
-           music: cartridge_music,
-           format: CartridgeFormat::UnicornSplittedFormat,
-       })
+/// Commands of the project manifest executed around the cartridge, like
+/// a transpiler or a level generator
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct CartridgeHooks {
+    #[serde(default)]
+    pub pre_run: Vec<String>,
+}
+
+impl CartridgeHooks {
+    pub fn from_manifest(filename: &str) -> Result<CartridgeHooks, Error> {
+        let mut f = try!(File::open(filename));
+
+        let mut data = String::new();
+        try!(f.read_to_string(&mut data));
+
+        match serde_json::from_str::<UnicornSplittedFormat>(&data) {
+            Ok(json) => Ok(json.hooks),
+            Err(e) => Err(Error::Err(format!("Invalid manifest {:?}: {}", filename, e))),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 struct UnicornSplittedFormat {
     code: String,
     data: String,
+    #[serde(default)]
+    hooks: CartridgeHooks,
 }
 
 
@@ -767,11 +1358,75 @@ impl Cartridge {
             gff: CartridgeGFF::empty(),
             code: CartridgeCode::empty(),
             palette: CartridgePalette::empty(),
+            sfx: CartridgeSfx::empty(),
             music: CartridgeMusic::empty(),
+            libs: CartridgeLibs::empty(),
+            meta: CartridgeMeta {
+                uuid: new_uuid(),
+                fps: 0,
+            },
+            label: CartridgeLabel::empty(),
+            hash: 0,
             format: CartridgeFormat::UnicornFormat,
         }
     }
 
+    /// Hash of everything but the metadata and the library pins, so the
+    /// same content gives the same hash whatever the name of the file: the
+    /// first 64 bits of the SHA-256 of the sections, the same with every
+    /// version of Rust and on every platform
+    pub fn content_hash(&mut self) -> u64 {
+        let mut data = Vec::new();
+
+        push_section(&mut data, self.code.lines.join("\n").as_bytes());
+
+        let mut colors: Vec<(&u32, &RGB)> = self.palette.colors.iter().collect();
+        colors.sort_by_key(|&(color, _)| *color);
+        let mut palette = Vec::new();
+        for (color, rgb) in colors {
+            palette.extend_from_slice(&u32_bytes(*color));
+            palette.extend_from_slice(&[rgb.r, rgb.g, rgb.b]);
+        }
+        push_section(&mut data, &palette);
+
+        let mut sprites = Vec::new();
+        for sprite in self.gfx.sprites.iter() {
+            for value in sprite.data.iter() {
+                sprites.extend_from_slice(&u32_bytes(*value));
+            }
+        }
+        push_section(&mut data, &sprites);
+        push_section(&mut data, &self.gff.flags);
+
+        let mut map = Vec::new();
+        for value in self.map.map.iter() {
+            map.extend_from_slice(&u32_bytes(*value));
+        }
+        push_section(&mut data, &map);
+        push_section(&mut data, self.sfx.get_data().as_bytes());
+        push_section(&mut data, self.music.get_data().as_bytes());
+
+        sha256(&data)[..8].iter().fold(0, |hash, byte| (hash << 8) | *byte as u64)
+    }
+
+    /// Compute the content hash, and give a UUID to the cartridges without one:
+    /// derived from the hash so it is the same at each load until the
+    /// cartridge is saved with it
+    pub fn identify(&mut self) {
+        self.hash = self.content_hash();
+
+        if self.meta.uuid.is_empty() {
+            self.meta.uuid = format_uuid(self.hash, self.hash.rotate_left(32) ^ 0x9e3779b9_7f4a7c15);
+        }
+
+        info!("[CARTRIDGE] UUID {} hash {:016x}", self.meta.uuid, self.hash);
+    }
+
+    /// "uuid:hash", to check that a file (save, replay, ...) was made with this cartridge
+    pub fn identity(&self) -> String {
+        format!("{}:{:016x}", self.meta.uuid, self.hash)
+    }
+
     pub fn from_uni_raw(filename: &str, data: Vec<u8>) -> Result<Cartridge, Error> {
         let mut buf_reader = Cursor::new(data);
         let cartridge = try!(read_from_uniformat(filename, &mut buf_reader));
@@ -881,25 +1536,53 @@ impl Cartridge {
             _ => cartridge_gff = CartridgeGFF::empty(),
         }
 
+        let cartridge_sfx = match sections.get_mut("__sfx__") {
+            Some(vec_section) => CartridgeSfx::new(vec_section),
+            _ => CartridgeSfx::empty(),
+        };
+
         match sections.get_mut("__music__") {
             Some(vec_section) => cartridge_music = CartridgeMusic::new(vec_section),
             _ => cartridge_music = CartridgeMusic::empty(),
         }
 
+        let cartridge_libs = match sections.get_mut("__libs__") {
+            Some(vec_section) => CartridgeLibs::new(vec_section),
+            _ => CartridgeLibs::empty(),
+        };
 
-        Ok(Cartridge {
-               filename: filename.to_string(),
-               data_filename: data_file.to_string(),
-               header: "".to_string(),
-               version: "".to_string(),
-               gfx: cartridge_gfx,
-               code: cartridge_code,
-               palette: cartridge_palette,
-               map: cartridge_map,
-               gff: cartridge_gff,
-               music: cartridge_music,
-               format: CartridgeFormat::UnicornSplittedFormat,
-           })
+        let cartridge_meta = match sections.get_mut("__meta__") {
+            Some(vec_section) => CartridgeMeta::new(vec_section),
+            _ => CartridgeMeta::empty(),
+        };
+
+        let cartridge_label = match sections.get_mut("__label__") {
+            Some(vec_section) => CartridgeLabel::new(vec_section),
+            _ => CartridgeLabel::empty(),
+        };
+
+
+        let mut cartridge = Cartridge {
+            filename: filename.to_string(),
+            data_filename: data_file.to_string(),
+            header: "".to_string(),
+            version: "".to_string(),
+            gfx: cartridge_gfx,
+            code: cartridge_code,
+            palette: cartridge_palette,
+            map: cartridge_map,
+            gff: cartridge_gff,
+            sfx: cartridge_sfx,
+            music: cartridge_music,
+            libs: cartridge_libs,
+            meta: cartridge_meta,
+            label: cartridge_label,
+            hash: 0,
+            format: CartridgeFormat::UnicornSplittedFormat,
+        };
+        cartridge.identify();
+
+        Ok(cartridge)
     }
 
     pub fn from_dunicorn_file(filename: &str) -> Result<Cartridge, Error> {
@@ -920,35 +1603,278 @@ impl Cartridge {
     }
 
 
+    /// Content of the cartridge in the Unicorn format
+    pub fn get_unicorn_data(&mut self, version: &str) -> String {
+        let mut data = String::new();
+
+        data.push_str("Saved by unicorn\n");
+        data.push_str(&format!("Version {:?}\n", version));
+
+        data.push_str(&format!("__{:}__\n", self.code.code_type));
+        data.push_str(&self.code.get_data());
+
+        data.push_str("__palette__\n");
+        data.push_str(&self.palette.get_data());
+
+        data.push_str("__gfx__\n");
+        data.push_str(&self.gfx.get_data());
+
+        data.push_str("__gff__\n");
+        data.push_str(&self.gff.get_data());
+
+        data.push_str("__map__\n");
+        data.push_str(&self.map.get_data());
+
+        data.push_str("__sfx__\n");
+        data.push_str(&self.sfx.get_data());
+
+        data.push_str("__music__\n");
+        data.push_str(&self.music.get_data());
+
+        data.push_str("__libs__\n");
+        data.push_str(&self.libs.get_data());
+
+        data.push_str("__meta__\n");
+        data.push_str(&self.meta.get_data());
+
+        if !self.label.is_empty() {
+            data.push_str("__label__\n");
+            data.push_str(&self.label.get_data());
+        }
+
+        data
+    }
+
     pub fn save_in_unicorn(&mut self, filename: &str, version: &str) {
         info!("Save the modified cartridge in Unicorn format {:?}", filename);
 
+        let data = self.get_unicorn_data(version);
+
         let mut f = File::create(filename).unwrap();
+        f.write_all(data.as_bytes()).unwrap();
+    }
 
-        f.write_all(b"Saved by unicorn\n").unwrap();
-        f.write_all(format!("Version {:?}\n", version).as_bytes())
-            .unwrap();
+    /// RGB label of the PNG cartridges, the first 16x16 sprites of the sheet
+    /// without a label
+    pub fn label_pixels(&self) -> (u32, u32, Vec<u8>) {
+        let size = LABEL_SIZE as u32;
+        let mut pixels = vec![0; (size * size * 3) as usize];
+
+        for y in 0..size {
+            for x in 0..size {
+                let value = if !self.label.is_empty() {
+                    self.label.pixels[(x + y * size) as usize] as u32
+                } else {
+                    let idx_sprite = (x / 8 + (y / 8) * 50) as usize;
+                    match self.gfx.sprites.get(idx_sprite) {
+                        Some(sprite) => sprite.data[((x % 8) + (y % 8) * 8) as usize],
+                        None => 0,
+                    }
+                };
 
-        f.write_all(format!("__{:}__\n", self.code.code_type).as_bytes())
-            .unwrap();
-        f.write_all(self.code.get_data().clone().as_bytes())
-            .unwrap();
+                let rgb = match self.palette.colors.get(&value) {
+                    Some(rgb) => rgb.clone(),
+                    None => unicorn::PALETTE.lock().unwrap().get_rgb(value),
+                };
+
+                let idx = ((x + y * size) * 3) as usize;
+                pixels[idx] = rgb.r;
+                pixels[idx + 1] = rgb.g;
+                pixels[idx + 2] = rgb.b;
+            }
+        }
+
+        (size, size, pixels)
+    }
+
+    /// Cartridge hidden in the pixels of a PNG image
+    #[cfg(feature = "image")]
+    pub fn from_png_raw(filename: &str, data: Vec<u8>) -> Result<Cartridge, Error> {
+        let image = match image::load_from_memory(&data) {
+            Ok(image) => image,
+            Err(e) => return Err(Error::Err(format!("Invalid PNG image {:?}: {}", filename, e))),
+        };
+
+        let pixels = image.to_rgba().into_raw();
+        let data = match png::decode(&pixels) {
+            Ok(data) => data,
+            Err(e) => return Err(Error::Err(format!("{:?}: {}", filename, e))),
+        };
+
+        let mut cartridge = try!(Cartridge::from_uni_raw(filename, data));
+        cartridge.format = CartridgeFormat::PngFormat;
+        Ok(cartridge)
+    }
+
+    #[cfg(not(feature = "image"))]
+    pub fn from_png_raw(filename: &str, _data: Vec<u8>) -> Result<Cartridge, Error> {
+        Err(Error::Err(format!("PNG cartridges are not supported, {:?} needs the image feature",
+                               filename)))
+    }
+
+    pub fn from_png_file(filename: &str) -> Result<Cartridge, Error> {
+        let mut f = try!(File::open(filename));
+
+        let mut data = Vec::new();
+        try!(f.read_to_end(&mut data));
+
+        Cartridge::from_png_raw(filename, data)
+    }
+
+    /// 16 colors of the sprites: the ones of the cartridge, else `defaults`
+    pub fn sheet_palette(&self, defaults: &[RGB]) -> Vec<RGB> {
+        (0..16)
+            .map(|idx| match self.palette.colors.get(&(idx as u32)) {
+                     Some(rgb) => rgb.clone(),
+                     None => defaults.get(idx).cloned().unwrap_or(RGB::new(0, 0, 0)),
+                 })
+            .collect()
+    }
+
+    /// Sprite sheet from a PNG/GIF image, from its top-left sprite. The image
+    /// must be drawn with the colors of the sprites (`sheet_palette`), else
+    /// with `quantize` it is reduced by median cut to 16 colors written in
+    /// the palette of the cartridge. The transparent pixels are the color 0.
+    /// Returns if the palette was replaced.
+    #[cfg(feature = "image")]
+    pub fn import_image(&mut self,
+                        filename: &str,
+                        defaults: &[RGB],
+                        quantize: bool,
+                        dither: bool)
+                        -> Result<bool, Error> {
+        let image = match image::open(filename) {
+            Ok(image) => image.to_rgba(),
+            Err(e) => return Err(Error::Err(format!("Invalid image {:?}: {}", filename, e))),
+        };
+
+        let width = (image.width() as usize).min(400);
+        let height = (image.height() as usize).min(240);
+
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = image.get_pixel(x as u32, y as u32).data;
+                pixels.push(if pixel[3] < 128 {
+                                None
+                            } else {
+                                Some(RGB::new(pixel[0], pixel[1], pixel[2]))
+                            });
+            }
+        }
+        let opaque: Vec<RGB> = pixels.iter().filter_map(|pixel| pixel.clone()).collect();
+
+        let palette = self.sheet_palette(defaults);
+        let (indexes, replaced): (Vec<Option<u32>>, bool) = match quantize::exact_indexes(&opaque, &palette) {
+            Some(exact) => {
+                let mut exact = exact.into_iter();
+                let indexes = pixels
+                    .iter()
+                    .map(|pixel| if pixel.is_some() { exact.next() } else { None })
+                    .collect();
+                (indexes, false)
+            }
+            None if !quantize => {
+                return Err(Error::Err(format!("{} colors of {:?} are not in the palette, it must be quantized",
+                                              quantize::missing_colors(&opaque, &palette),
+                                              filename)))
+            }
+            None => {
+                // The color 0 is kept for the transparent pixels
+                let offset = if opaque.len() < pixels.len() { 1 } else { 0 };
+                let colors = quantize::median_cut(&opaque, 16 - offset);
+
+                let indexes = quantize::map_pixels(&pixels, width, &colors, dither)
+                    .into_iter()
+                    .map(|idx| idx.map(|idx| idx + offset as u32))
+                    .collect();
+
+                for (idx, rgb) in colors.into_iter().enumerate() {
+                    self.palette.colors.insert((idx + offset) as u32, rgb);
+                }
+                (indexes, true)
+            }
+        };
+
+        while self.gfx.sprites.len() < 1500 {
+            self.gfx.sprites.push(Sprite::new([0; 64]));
+        }
+
+        for (idx, value) in indexes.iter().enumerate() {
+            let (x, y) = (idx % width, idx / width);
+            let sprite = &mut self.gfx.sprites[x / 8 + (y / 8) * 50];
+            sprite.data[x % 8 + (y % 8) * 8] = value.unwrap_or(0);
+        }
+
+        Ok(replaced)
+    }
+
+    #[cfg(not(feature = "image"))]
+    pub fn import_image(&mut self,
+                        filename: &str,
+                        _defaults: &[RGB],
+                        _quantize: bool,
+                        _dither: bool)
+                        -> Result<bool, Error> {
+        Err(Error::Err(format!("Images are not supported, {:?} needs the image feature", filename)))
+    }
+
+    /// A file is a PNG cartridge from its extension or its signature
+    pub fn is_png_file(filename: &str) -> bool {
+        if filename.ends_with(".png") {
+            return true;
+        }
+
+        let mut signature = [0; 8];
+        match File::open(filename) {
+            Ok(mut f) => f.read_exact(&mut signature).is_ok() && png::has_png_signature(&signature),
+            Err(_) => false,
+        }
+    }
+
+    #[cfg(feature = "image")]
+    pub fn save_in_png(&mut self, filename: &str, version: &str) {
+        info!("Save the modified cartridge in PNG format {:?}", filename);
 
-        f.write_all(b"__palette__\n").unwrap();
-        f.write_all(self.palette.get_data().clone().as_bytes()).unwrap();
+        let data = self.get_unicorn_data(version);
+        let (label_width, label_height, label) = self.label_pixels();
+        let (width, height, pixels) = png::encode(&label, label_width, label_height, data.as_bytes());
 
-        f.write_all(b"__gfx__\n").unwrap();
-        f.write_all(self.gfx.get_data().clone().as_bytes()).unwrap();
+        if let Err(e) = image::save_buffer(filename, &pixels, width, height, image::RGBA(8)) {
+            error!("Failed to save the PNG cartridge {:?}: {}", filename, e);
+        }
+    }
 
-        f.write_all(b"__gff__\n").unwrap();
-        f.write_all(self.gff.get_data().clone().as_bytes()).unwrap();
+    #[cfg(not(feature = "image"))]
+    pub fn save_in_png(&mut self, filename: &str, _version: &str) {
+        error!("PNG cartridges are not supported, {:?} needs the image feature", filename);
+    }
 
-        f.write_all(b"__map__\n").unwrap();
-        f.write_all(self.map.get_data().clone().as_bytes()).unwrap();
+    /// Label alone in a PNG image, `scale` times bigger with the nearest
+    /// pixels, for the launchers and the pages of the cartridge
+    #[cfg(feature = "image")]
+    pub fn save_label(&self, filename: &str, scale: u32) -> Result<(), String> {
+        let (label_width, label_height, label) = self.label_pixels();
+        let scale = scale.max(1);
+        let (width, height) = (label_width * scale, label_height * scale);
+
+        let mut pixels = Vec::with_capacity((width * height * 3) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let idx = (((x / scale) + (y / scale) * label_width) * 3) as usize;
+                pixels.extend_from_slice(&label[idx..idx + 3]);
+            }
+        }
 
-        f.write_all(b"__sfx__\n").unwrap();
+        match image::save_buffer(filename, &pixels, width, height, image::RGB(8)) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(format!("Failed to save the label {:?}: {}", filename, e)),
+        }
+    }
 
-        f.write_all(b"__music__\n").unwrap();
+    #[cfg(not(feature = "image"))]
+    pub fn save_label(&self, filename: &str, _scale: u32) -> Result<(), String> {
+        Err(format!("PNG images are not supported, {:?} needs the image feature", filename))
     }
 
     pub fn save_in_unicorn_splitted(&mut self) {
@@ -968,8 +1894,21 @@ impl Cartridge {
                 f.write_all(self.map.get_data().clone().as_bytes()).unwrap();
 
                 f.write_all(b"__sfx__\n").unwrap();
+                f.write_all(self.sfx.get_data().clone().as_bytes()).unwrap();
 
                 f.write_all(b"__music__\n").unwrap();
+                f.write_all(self.music.get_data().clone().as_bytes()).unwrap();
+
+                f.write_all(b"__libs__\n").unwrap();
+                f.write_all(self.libs.get_data().clone().as_bytes()).unwrap();
+
+                f.write_all(b"__meta__\n").unwrap();
+                f.write_all(self.meta.get_data().clone().as_bytes()).unwrap();
+
+                if !self.label.is_empty() {
+                    f.write_all(b"__label__\n").unwrap();
+                    f.write_all(self.label.get_data().as_bytes()).unwrap();
+                }
             }
             _ => (),
         }
@@ -993,3 +1932,195 @@ impl fmt::Debug for Cartridge {
                self.gfx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use gfx::Sprite;
+
+    use super::{format_uuid, new_uuid, png, push_section, Cartridge, CartridgeCode, CartridgeLabel,
+                CartridgeLibs, CartridgeMeta, CartridgeMusic, CartridgeSfx, MusicPattern, SfxPattern,
+                UnicornSplittedFormat, LABEL_SIZE};
+
+    #[test]
+    fn test_music_pattern() {
+        let pattern = MusicPattern::from_line("01 3c 00 0a -- --").unwrap();
+        assert_eq!(pattern.is_loop_start(), true);
+        assert_eq!(pattern.is_loop_end(), false);
+        assert_eq!(pattern.duration, 60);
+        assert_eq!(pattern.sfx, [0, 10, -1, -1]);
+        assert_eq!(pattern.get_data(), "01 3c 00 0a -- --");
+
+        assert_eq!(MusicPattern::from_line("01 3c 00"), None);
+        assert_eq!(MusicPattern::from_line("01 3c 00 zz -- --"), None);
+    }
+
+    #[test]
+    fn test_cartridge_music() {
+        let lines = vec!["01 20 00 01 -- --".to_string(),
+                         "".to_string(),
+                         "02 20 02 -- -- 03".to_string()];
+        let mut music = CartridgeMusic::new(&lines);
+        assert_eq!(music.patterns.len(), 2);
+        assert_eq!(music.get_data(), "01 20 00 01 -- --\n02 20 02 -- -- 03\n");
+    }
+
+    #[test]
+    fn test_manifest_hooks() {
+        let json: UnicornSplittedFormat =
+            serde_json::from_str(r#"{"code": "game.lua", "data": "game.dat",
+                                     "hooks": {"pre_run": ["tstl game.ts"]}}"#)
+                .unwrap();
+        assert_eq!(json.hooks.pre_run, vec!["tstl game.ts".to_string()]);
+
+        let json: UnicornSplittedFormat =
+            serde_json::from_str(r#"{"code": "game.lua", "data": "game.dat"}"#).unwrap();
+        assert!(json.hooks.pre_run.is_empty());
+    }
+
+    #[test]
+    fn test_sfx_pattern() {
+        let mut pattern = SfxPattern::new();
+        pattern.waveform = 1;
+        pattern.speed = 4;
+        pattern.notes[0] = 48;
+        pattern.notes[2] = 60;
+
+        let line = pattern.get_data();
+        assert!(line.starts_with("01 04 30 -- 3c --"));
+        assert_eq!(SfxPattern::from_line(&line), Some(pattern.clone()));
+        assert_eq!(pattern.waveform_name(), "triangle");
+
+        assert_eq!(SfxPattern::from_line("01 04 30"), None);
+        assert_eq!(SfxPattern::from_line(&line.replacen("01", "09", 1)), None);
+
+        let mut sfx = CartridgeSfx::new(&vec![line.clone(), "".to_string()]);
+        assert_eq!(sfx.sfx.len(), 1);
+        assert_eq!(sfx.get_data(), format!("{}\n", line));
+    }
+
+    #[test]
+    fn test_uuid() {
+        assert_eq!(format_uuid(0, 0), "00000000-0000-4000-8000-000000000000");
+        assert_eq!(format_uuid(!0, !0), "ffffffff-ffff-4fff-bfff-ffffffffffff");
+        assert_eq!(new_uuid().len(), 36);
+        assert!(new_uuid() != new_uuid());
+    }
+
+    #[test]
+    fn test_cartridge_meta() {
+        let mut meta = CartridgeMeta::new(&vec!["uuid 1234".to_string(), "".to_string()]);
+        assert_eq!(meta.uuid, "1234");
+        assert_eq!(meta.get_data(), "uuid 1234\n");
+        assert_eq!(CartridgeMeta::empty().get_data(), "");
+    }
+
+    #[test]
+    fn test_cartridge_meta_fps() {
+        let mut meta = CartridgeMeta::new(&vec!["fps 30".to_string()]);
+        assert_eq!(meta.fps, 30);
+        assert_eq!(meta.get_data(), "fps 30\n");
+
+        meta.uuid = "1234".to_string();
+        assert_eq!(meta.get_data(), "uuid 1234\nfps 30\n");
+
+        assert_eq!(CartridgeMeta::new(&vec!["fps 45".to_string()]).fps, 0);
+    }
+
+    #[test]
+    fn test_cartridge_identify() {
+        let mut cartridge = Cartridge::empty();
+        cartridge.meta.uuid.clear();
+        cartridge.identify();
+        let identity = cartridge.identity();
+
+        // Same content, same identity whatever the filename
+        let mut other = Cartridge::empty();
+        other.filename = "renamed.uni".to_string();
+        other.meta.uuid.clear();
+        other.identify();
+        assert_eq!(other.identity(), identity);
+
+        // The UUID is kept when the content changes
+        other.music.patterns.push(MusicPattern::new());
+        other.identify();
+        assert_eq!(other.meta.uuid, cartridge.meta.uuid);
+        assert!(other.hash != cartridge.hash);
+
+        // The sections can't shift into each other
+        let (mut a, mut b) = (Vec::new(), Vec::new());
+        push_section(&mut a, b"ab");
+        push_section(&mut a, b"c");
+        push_section(&mut b, b"a");
+        push_section(&mut b, b"bc");
+        assert_eq!(a, vec![0, 0, 0, 2, b'a', b'b', 0, 0, 0, 1, b'c']);
+        assert!(a != b);
+    }
+
+    #[test]
+    fn test_cartridge_png_data() {
+        let mut cartridge = Cartridge::empty();
+        cartridge.code = CartridgeCode::new("lua".to_string(), &vec!["print(1)".to_string()]);
+        cartridge.gfx.set_sprites(vec![Sprite::new([1; 64]); 50 * 30]);
+
+        let (label_width, label_height, label) = cartridge.label_pixels();
+        assert_eq!(label.len(), (label_width * label_height * 3) as usize);
+
+        let data = cartridge.get_unicorn_data("1");
+        let (_, _, pixels) = png::encode(&label, label_width, label_height, data.as_bytes());
+
+        let mut other = Cartridge::from_uni_raw("game.png", png::decode(&pixels).unwrap()).unwrap();
+        assert_eq!(other.code.get_data(), cartridge.code.get_data());
+        assert_eq!(other.meta.uuid, cartridge.meta.uuid);
+        assert_eq!(other.gfx.sprites[0].data[0], 1);
+    }
+
+    #[test]
+    fn test_cartridge_label() {
+        // A screen of 256x128 is scaled to 128x128
+        let mut frame_buffer = vec![0; 256 * 128];
+        frame_buffer[2 + 256] = 12;
+        let label = CartridgeLabel::from_frame_buffer(&frame_buffer, 256, 128);
+        assert_eq!(label.pixels.len(), LABEL_SIZE * LABEL_SIZE);
+        assert_eq!(label.pixels[1 + LABEL_SIZE], 12);
+
+        let lines: Vec<String> = label.get_data().lines().map(|line| line.to_string()).collect();
+        assert_eq!(lines.len(), LABEL_SIZE);
+        assert_eq!(CartridgeLabel::new(&lines).pixels, label.pixels);
+        assert!(CartridgeLabel::new(&lines[1..]).is_empty());
+
+        let mut cartridge = Cartridge::empty();
+        cartridge.code = CartridgeCode::new("lua".to_string(), &vec!["print(1)".to_string()]);
+        assert!(!cartridge.get_unicorn_data("1").contains("__label__"));
+
+        cartridge.label = label;
+        let other = Cartridge::from_uni_raw("game.uni", cartridge.get_unicorn_data("1").into_bytes()).unwrap();
+        assert_eq!(other.label.pixels, cartridge.label.pixels);
+    }
+
+    #[test]
+    fn test_cartridge_libs() {
+        let lines = vec!["vector 1.2".to_string(), "tween 0.3".to_string()];
+        let mut libs = CartridgeLibs::new(&lines);
+        assert_eq!(libs.get("vector"), Some("1.2".to_string()));
+        assert_eq!(libs.get("physics"), None);
+
+        libs.set("vector", "1.3");
+        libs.set("physics", "2.0");
+        assert_eq!(libs.get_data(), "vector 1.3\ntween 0.3\nphysics 2.0\n");
+
+        // Embedded code, with its empty lines
+        libs.embed("lib:vector@1.3", "local M = {}\n\nreturn M\n");
+        let data = libs.get_data();
+        assert_eq!(data,
+                   "vector 1.3\ntween 0.3\nphysics 2.0\n=lib:vector@1.3\n|local M = {}\n|\n|return M\n");
+
+        let lines: Vec<String> = data.lines().map(|line| line.to_string()).collect();
+        let libs = CartridgeLibs::new(&lines);
+        assert_eq!(libs.pins.len(), 3);
+        assert_eq!(libs.embedded_code("lib:vector@1.3"),
+                   Some("local M = {}\n\nreturn M\n".to_string()));
+        assert_eq!(libs.embedded_code("lib:vector"), None);
+    }
+}
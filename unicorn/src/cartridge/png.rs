@@ -0,0 +1,104 @@
+//! Cartridge hidden in the label of a PNG image: each byte of the Unicorn
+//! format is stored in the 2 lowest bits of the 4 channels of a pixel
+//! (ARGB, from the highest bits), after a header with a magic and the length.
+
+/// Signature of all the PNG files
+pub const PNG_SIGNATURE: &'static [u8] = b"\x89PNG\r\n\x1a\n";
+/// Magic of the data encoded in the pixels
+pub const PNG_CART_MAGIC: &'static [u8] = b"UNI1";
+/// Width of the image, the label is repeated to get enough pixels
+pub const PNG_CART_WIDTH: u32 = 128;
+
+const HEADER_SIZE: usize = 8;
+
+pub fn has_png_signature(data: &[u8]) -> bool {
+    data.starts_with(PNG_SIGNATURE)
+}
+
+/// Hide the data in the RGB label of `label_width`x`label_height` pixels,
+/// the RGBA image returned is taller than the label if needed
+pub fn encode(label: &[u8], label_width: u32, label_height: u32, data: &[u8]) -> (u32, u32, Vec<u8>) {
+    let mut payload = Vec::with_capacity(HEADER_SIZE + data.len());
+    payload.extend_from_slice(PNG_CART_MAGIC);
+    let len = data.len() as u32;
+    payload.extend_from_slice(&[(len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8, len as u8]);
+    payload.extend_from_slice(data);
+
+    let width = PNG_CART_WIDTH;
+    let rows = (payload.len() as u32 + width - 1) / width;
+    let height = if rows > label_height { rows } else { label_height };
+
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let (r, g, b) = if label_width > 0 && label_height > 0 {
+                let idx = (((y % label_height) * label_width + (x % label_width)) * 3) as usize;
+                (label[idx], label[idx + 1], label[idx + 2])
+            } else {
+                (0, 0, 0)
+            };
+
+            let value = *payload.get((x + y * width) as usize).unwrap_or(&0);
+
+            pixels.push((r & 0xfc) | ((value >> 4) & 3));
+            pixels.push((g & 0xfc) | ((value >> 2) & 3));
+            pixels.push((b & 0xfc) | (value & 3));
+            pixels.push(0xfc | ((value >> 6) & 3));
+        }
+    }
+
+    (width, height, pixels)
+}
+
+/// Read the data hidden in the RGBA pixels
+pub fn decode(pixels: &[u8]) -> Result<Vec<u8>, String> {
+    let bytes: Vec<u8> = pixels.chunks(4)
+        .filter(|pixel| pixel.len() == 4)
+        .map(|pixel| {
+                 ((pixel[3] & 3) << 6) | ((pixel[0] & 3) << 4) | ((pixel[1] & 3) << 2) |
+                 (pixel[2] & 3)
+             })
+        .collect();
+
+    if bytes.len() < HEADER_SIZE || &bytes[0..4] != PNG_CART_MAGIC {
+        return Err("No cartridge in the image".to_string());
+    }
+
+    let len = ((bytes[4] as usize) << 24) | ((bytes[5] as usize) << 16) |
+              ((bytes[6] as usize) << 8) | (bytes[7] as usize);
+    if HEADER_SIZE + len > bytes.len() {
+        return Err(format!("Truncated cartridge, {} bytes instead of {}",
+                           bytes.len() - HEADER_SIZE,
+                           len));
+    }
+
+    Ok(bytes[HEADER_SIZE..HEADER_SIZE + len].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, has_png_signature, PNG_CART_WIDTH};
+
+    #[test]
+    fn test_png_roundtrip() {
+        let label = vec![0xff; 4 * 2 * 3];
+        let data: Vec<u8> = (0..1000).map(|v| (v % 256) as u8).collect();
+
+        let (width, height, pixels) = encode(&label, 4, 2, &data);
+        assert_eq!(width, PNG_CART_WIDTH);
+        assert_eq!(height, 8);
+        assert_eq!(pixels.len(), (width * height * 4) as usize);
+        // The label is still visible
+        assert_eq!(pixels[0] & 0xfc, 0xfc);
+
+        assert_eq!(decode(&pixels).unwrap(), data);
+        assert!(decode(&pixels[0..16]).is_err());
+        assert!(decode(&vec![0; 64]).is_err());
+    }
+
+    #[test]
+    fn test_png_signature() {
+        assert!(has_png_signature(b"\x89PNG\r\n\x1a\nIHDR"));
+        assert!(!has_png_signature(b"Saved by unicorn\n"));
+    }
+}
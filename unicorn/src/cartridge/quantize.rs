@@ -0,0 +1,264 @@
+//! Colors of the images imported in the sprite sheet: an image drawn with
+//! the palette is mapped exactly, the other ones are reduced by median cut
+//! to the colors of a new palette, optionally with a Floyd-Steinberg
+//! dithering.
+
+use std::collections::{HashMap, HashSet};
+
+use unicorn::RGB;
+
+/// Index of each pixel in the palette, if all of them are in it
+pub fn exact_indexes(pixels: &[RGB], palette: &[RGB]) -> Option<Vec<u32>> {
+    let mut indexes = Vec::with_capacity(pixels.len());
+
+    for pixel in pixels {
+        match palette.iter().position(|color| color == pixel) {
+            Some(idx) => indexes.push(idx as u32),
+            None => return None,
+        }
+    }
+
+    Some(indexes)
+}
+
+/// Colors of the pixels missing in the palette
+pub fn missing_colors(pixels: &[RGB], palette: &[RGB]) -> usize {
+    let palette: HashSet<(u8, u8, u8)> = palette.iter()
+        .map(|color| (color.r, color.g, color.b))
+        .collect();
+
+    pixels.iter()
+        .map(|pixel| (pixel.r, pixel.g, pixel.b))
+        .filter(|color| !palette.contains(color))
+        .collect::<HashSet<(u8, u8, u8)>>()
+        .len()
+}
+
+fn distance(a: &RGB, r: f64, g: f64, b: f64) -> f64 {
+    let (dr, dg, db) = (a.r as f64 - r, a.g as f64 - g, a.b as f64 - b);
+    dr * dr + dg * dg + db * db
+}
+
+/// Index of the closest color of the palette
+pub fn nearest(palette: &[RGB], r: f64, g: f64, b: f64) -> usize {
+    let mut best = 0;
+    let mut best_distance = ::std::f64::MAX;
+
+    for (idx, color) in palette.iter().enumerate() {
+        let d = distance(color, r, g, b);
+        if d < best_distance {
+            best = idx;
+            best_distance = d;
+        }
+    }
+
+    best
+}
+
+fn channel(color: &(u8, u8, u8), idx: usize) -> u8 {
+    match idx {
+        0 => color.0,
+        1 => color.1,
+        _ => color.2,
+    }
+}
+
+/// Channel with the widest range of a box, and the range
+fn widest_channel(colors: &[((u8, u8, u8), usize)]) -> (usize, u8) {
+    let mut widest = (0, 0);
+
+    for idx in 0..3 {
+        let min = colors.iter().map(|&(color, _)| channel(&color, idx)).min().unwrap_or(0);
+        let max = colors.iter().map(|&(color, _)| channel(&color, idx)).max().unwrap_or(0);
+        if max - min > widest.1 {
+            widest = (idx, max - min);
+        }
+    }
+
+    widest
+}
+
+/// Palette of at most `count` colors of the pixels, from the darkest: the
+/// box of colors with the widest range is split at its median until there
+/// are enough boxes, each one giving the average of its pixels
+pub fn median_cut(pixels: &[RGB], count: usize) -> Vec<RGB> {
+    let mut histogram: HashMap<(u8, u8, u8), usize> = HashMap::new();
+    for pixel in pixels {
+        *histogram.entry((pixel.r, pixel.g, pixel.b)).or_insert(0) += 1;
+    }
+
+    let mut colors: Vec<((u8, u8, u8), usize)> = histogram.into_iter().collect();
+    colors.sort();
+
+    let mut boxes = Vec::new();
+    if !colors.is_empty() && count > 0 {
+        boxes.push(colors);
+    }
+
+    while boxes.len() < count {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .map(|(idx, colors)| (idx, widest_channel(colors)))
+            .filter(|&(_, (_, range))| range > 0)
+            .max_by_key(|&(idx, (_, range))| (range, ::std::usize::MAX - idx));
+
+        let (idx, channel_idx) = match widest {
+            Some((idx, (channel_idx, _))) => (idx, channel_idx),
+            None => break,
+        };
+
+        let mut colors = boxes.remove(idx);
+        colors.sort_by_key(|&(color, _)| channel(&color, channel_idx));
+
+        // Median of the pixels, keeping at least a color on each side
+        let total: usize = colors.iter().map(|&(_, n)| n).sum();
+        let mut seen = 0;
+        let mut split = 1;
+        for (i, &(_, n)) in colors.iter().enumerate() {
+            seen += n;
+            if seen * 2 >= total {
+                split = (i + 1).max(1).min(colors.len() - 1);
+                break;
+            }
+        }
+
+        let upper = colors.split_off(split);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    let mut palette: Vec<RGB> = boxes
+        .iter()
+        .map(|colors| {
+            let total: usize = colors.iter().map(|&(_, n)| n).sum();
+            let sum = |idx: usize| -> usize {
+                colors.iter().map(|&(color, n)| channel(&color, idx) as usize * n).sum()
+            };
+            let average = |idx: usize| ((sum(idx) + total / 2) / total) as u8;
+            RGB::new(average(0), average(1), average(2))
+        })
+        .collect();
+
+    palette.sort_by_key(|color| (color.r as u32 * 299 + color.g as u32 * 587 + color.b as u32 * 114,
+                                 color.r,
+                                 color.g,
+                                 color.b));
+    palette.dedup();
+    palette
+}
+
+/// Index of each pixel of a `width` image in the palette, the closest color,
+/// with the error spread on the next pixels when `dither`. The pixels
+/// without a value are left out of the dithering and get `None`.
+pub fn map_pixels(pixels: &[Option<RGB>],
+                  width: usize,
+                  palette: &[RGB],
+                  dither: bool)
+                  -> Vec<Option<u32>> {
+    let mut values: Vec<[f64; 3]> = pixels
+        .iter()
+        .map(|pixel| match *pixel {
+                 Some(ref rgb) => [rgb.r as f64, rgb.g as f64, rgb.b as f64],
+                 None => [0.0; 3],
+             })
+        .collect();
+    let mut indexes = Vec::with_capacity(pixels.len());
+
+    for idx in 0..pixels.len() {
+        if pixels[idx].is_none() {
+            indexes.push(None);
+            continue;
+        }
+
+        let r = values[idx][0].max(0.0).min(255.0);
+        let g = values[idx][1].max(0.0).min(255.0);
+        let b = values[idx][2].max(0.0).min(255.0);
+        let color = nearest(palette, r, g, b);
+        indexes.push(Some(color as u32));
+
+        if !dither {
+            continue;
+        }
+
+        let error = [r - palette[color].r as f64,
+                     g - palette[color].g as f64,
+                     b - palette[color].b as f64];
+        let (x, y) = (idx % width, idx / width);
+        let mut spread = |dx: i32, dy: i32, weight: f64| {
+            let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+            if nx < 0 || nx >= width as i32 {
+                return;
+            }
+            let next = ny as usize * width + nx as usize;
+            if next < values.len() && pixels[next].is_some() {
+                for c in 0..3 {
+                    values[next][c] += error[c] * weight;
+                }
+            }
+        };
+        spread(1, 0, 7.0 / 16.0);
+        spread(-1, 1, 3.0 / 16.0);
+        spread(0, 1, 5.0 / 16.0);
+        spread(1, 1, 1.0 / 16.0);
+    }
+
+    indexes
+}
+
+#[cfg(test)]
+mod tests {
+    use unicorn::RGB;
+
+    use super::{exact_indexes, map_pixels, median_cut, missing_colors, nearest};
+
+    #[test]
+    fn test_exact_indexes() {
+        let palette = vec![RGB::new(0, 0, 0), RGB::new(255, 0, 77), RGB::new(255, 241, 232)];
+        let pixels = vec![RGB::new(255, 241, 232), RGB::new(0, 0, 0), RGB::new(255, 0, 77)];
+
+        assert_eq!(exact_indexes(&pixels, &palette), Some(vec![2, 0, 1]));
+
+        let pixels = vec![RGB::new(0, 0, 0), RGB::new(1, 2, 3), RGB::new(1, 2, 3), RGB::new(9, 9, 9)];
+        assert_eq!(exact_indexes(&pixels, &palette), None);
+        assert_eq!(missing_colors(&pixels, &palette), 2);
+        assert_eq!(nearest(&palette, 250.0, 10.0, 70.0), 1);
+    }
+
+    #[test]
+    fn test_median_cut() {
+        // Few colors are kept as they are, from the darkest
+        let pixels = vec![RGB::new(200, 200, 200), RGB::new(10, 20, 30), RGB::new(200, 200, 200)];
+        assert_eq!(median_cut(&pixels, 16), vec![RGB::new(10, 20, 30), RGB::new(200, 200, 200)]);
+
+        // A gradient of red is cut in 4 boxes of the same size
+        let pixels: Vec<RGB> = (0..256).map(|r| RGB::new(r as u8, 0, 0)).collect();
+        let palette = median_cut(&pixels, 4);
+        assert_eq!(palette.len(), 4);
+        assert_eq!(palette[0], RGB::new(32, 0, 0));
+        assert_eq!(palette[3], RGB::new(224, 0, 0));
+
+        assert_eq!(median_cut(&pixels, 1), vec![RGB::new(128, 0, 0)]);
+        assert_eq!(median_cut(&[], 16), vec![]);
+    }
+
+    #[test]
+    fn test_map_pixels() {
+        let palette = vec![RGB::new(0, 0, 0), RGB::new(255, 255, 255)];
+        let grey = Some(RGB::new(128, 128, 128));
+        let pixels = vec![grey.clone(), grey.clone(), None, grey.clone(), grey.clone(), grey.clone()];
+
+        // Without dithering all the greys are the closest color
+        let indexes = map_pixels(&pixels, 3, &palette, false);
+        assert_eq!(indexes, vec![Some(1), Some(1), None, Some(1), Some(1), Some(1)]);
+
+        // With it, the greys alternate
+        let indexes = map_pixels(&pixels, 3, &palette, true);
+        assert_eq!(indexes[0], Some(1));
+        assert_eq!(indexes[1], Some(0));
+        assert_eq!(indexes[2], None);
+        let white = indexes.iter().filter(|idx| **idx == Some(1)).count();
+        let black = indexes.iter().filter(|idx| **idx == Some(0)).count();
+        assert!(white > 0 && black > 0);
+    }
+}
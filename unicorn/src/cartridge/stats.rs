@@ -0,0 +1,225 @@
+//! Statistics of `--stats`: the size of the code, the slots of the sprite
+//! sheet, of the map and of the sounds used by a cartridge, and an estimate
+//! of its size once compressed, for the limits of the game jams.
+
+use std::collections::HashMap;
+
+use regex::Regex;
+use serde_json;
+
+use cartridge::Cartridge;
+use gfx::Sprite;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CartridgeStats {
+    pub code_type: String,
+    pub code_bytes: usize,
+    pub code_lines: usize,
+    /// Definitions of functions, the anonymous ones included
+    pub functions: usize,
+    /// Sprites with at least a pixel which isn't the color 0
+    pub sprites: usize,
+    pub sprites_total: usize,
+    /// Cells of the map which aren't the sprite 0
+    pub map_cells: usize,
+    pub map_total: usize,
+    /// Sfx with at least a note
+    pub sfx: usize,
+    pub sfx_total: usize,
+    /// Music patterns playing at least a sfx
+    pub music: usize,
+    pub music_total: usize,
+    /// Size of the cartridge in the Unicorn format compressed by deflate
+    pub compressed_bytes: usize,
+}
+
+impl CartridgeStats {
+    pub fn new(cartridge: &mut Cartridge, version: &str) -> CartridgeStats {
+        let code = cartridge.code.get_data();
+        let data = cartridge.get_unicorn_data(version);
+
+        CartridgeStats {
+            code_type: cartridge.code.code_type.clone(),
+            code_bytes: code.len(),
+            code_lines: code.lines().count(),
+            functions: count_functions(&cartridge.code.code_type, &code),
+            sprites: cartridge.gfx.sprites.iter().filter(|sprite| !is_empty_sprite(sprite)).count(),
+            sprites_total: cartridge.gfx.sprites.len(),
+            map_cells: cartridge.map.map.iter().filter(|cell| **cell != 0).count(),
+            map_total: cartridge.map.map.len(),
+            sfx: cartridge.sfx.sfx.iter().filter(|sfx| sfx.notes.iter().any(|note| *note >= 0)).count(),
+            sfx_total: cartridge.sfx.sfx.len(),
+            music: cartridge
+                .music
+                .patterns
+                .iter()
+                .filter(|pattern| pattern.sfx.iter().any(|sfx| *sfx >= 0))
+                .count(),
+            music_total: cartridge.music.patterns.len(),
+            compressed_bytes: compressed_size(data.as_bytes()),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        text.push_str(&format!("Code ({}): {} bytes, {} lines, {} functions\n",
+                               self.code_type,
+                               self.code_bytes,
+                               self.code_lines,
+                               self.functions));
+        text.push_str(&format!("Sprites: {}/{}\n", self.sprites, self.sprites_total));
+        text.push_str(&format!("Map cells: {}/{}\n", self.map_cells, self.map_total));
+        text.push_str(&format!("Sfx: {}/{}\n", self.sfx, self.sfx_total));
+        text.push_str(&format!("Music patterns: {}/{}\n", self.music, self.music_total));
+        text.push_str(&format!("Compressed size: ~{} bytes\n", self.compressed_bytes));
+
+        text
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+fn is_empty_sprite(sprite: &Sprite) -> bool {
+    sprite.data.iter().all(|pixel| *pixel == 0)
+}
+
+/// Definitions of functions in the code, outside of the comments: the
+/// `function` keywords in Lua and Javascript, the `def` in Python
+pub fn count_functions(code_type: &str, code: &str) -> usize {
+    let (comment, keyword) = match code_type {
+        "python" => ("#", Regex::new(r"^\s*(async\s+)?def\s").unwrap()),
+        "javascript" => ("//", Regex::new(r"\bfunction\b").unwrap()),
+        _ => ("--", Regex::new(r"\bfunction\b").unwrap()),
+    };
+
+    code.lines()
+        .map(|line| match line.find(comment) {
+                 Some(idx) => &line[..idx],
+                 None => line,
+             })
+        .map(|line| keyword.find_iter(line).count())
+        .sum()
+}
+
+const WINDOW: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// Positions tried for a match, the latest ones first
+const MAX_CHAIN: usize = 32;
+
+fn bits(value: usize) -> usize {
+    let mut bits = 0;
+    while (value >> bits) > 0 {
+        bits += 1;
+    }
+    bits
+}
+
+/// Bits of a match with the fixed codes of deflate: the length code and its
+/// extra bits, then the distance code and its extra bits
+fn match_bits(length: usize, distance: usize) -> usize {
+    let length_code = if length < 115 { 7 } else { 8 };
+    let length_extra = if length < 11 || length == MAX_MATCH {
+        0
+    } else {
+        bits(length - 3) - 3
+    };
+    let distance_extra = if distance <= 4 { 0 } else { bits(distance - 1) - 2 };
+
+    length_code + length_extra + 5 + distance_extra
+}
+
+/// Estimated size of the data compressed by deflate with its fixed codes,
+/// the longest matches found greedily in the last 32KB. The real compressors
+/// with their own codes do a bit better.
+pub fn compressed_size(data: &[u8]) -> usize {
+    let mut chains: HashMap<(u8, u8, u8), Vec<usize>> = HashMap::new();
+    // Header of the block and end of block code
+    let mut total = 3 + 7;
+    let mut idx = 0;
+
+    while idx < data.len() {
+        let mut best = (0, 0);
+
+        if idx + MIN_MATCH <= data.len() {
+            let key = (data[idx], data[idx + 1], data[idx + 2]);
+            if let Some(positions) = chains.get(&key) {
+                for &start in positions.iter().rev().take(MAX_CHAIN) {
+                    if idx - start > WINDOW {
+                        break;
+                    }
+
+                    let mut length = 0;
+                    while length < MAX_MATCH && idx + length < data.len() &&
+                          data[start + length] == data[idx + length] {
+                        length += 1;
+                    }
+                    if length > best.0 {
+                        best = (length, idx - start);
+                    }
+                }
+            }
+        }
+
+        let step = if best.0 >= MIN_MATCH {
+            total += match_bits(best.0, best.1);
+            best.0
+        } else {
+            total += if data[idx] < 144 { 8 } else { 9 };
+            1
+        };
+
+        for pos in idx..idx + step {
+            if pos + MIN_MATCH <= data.len() {
+                chains.entry((data[pos], data[pos + 1], data[pos + 2])).or_insert_with(Vec::new).push(pos);
+            }
+        }
+        idx += step;
+    }
+
+    (total + 7) / 8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compressed_size, count_functions, match_bits};
+
+    #[test]
+    fn test_count_functions() {
+        let lua = "function _init()\n  f = function(x) return x end -- function\nend\n\
+                   local function _draw() end\n";
+        assert_eq!(count_functions("lua", lua), 3);
+
+        let python = "def _init():\n    pass\n# def old():\nclass A:\n    def draw(self):\n        \
+                      undefined()\n";
+        assert_eq!(count_functions("python", python), 2);
+
+        assert_eq!(count_functions("javascript", "function a() {}\n// function b"), 1);
+    }
+
+    #[test]
+    fn test_compressed_size() {
+        // 7 bits for a length of 3 and 5 bits for a distance of 1
+        assert_eq!(match_bits(3, 1), 12);
+        assert_eq!(match_bits(258, 1), 13);
+        assert_eq!(match_bits(11, 5), 7 + 1 + 5 + 1);
+        assert_eq!(match_bits(130, 32768), 8 + 4 + 5 + 13);
+
+        assert_eq!(compressed_size(b""), 2);
+        assert_eq!(compressed_size(b"abc"), 5);
+
+        // A repeated text is much smaller than random bytes
+        let repeated: Vec<u8> = b"__gfx__ 0000000000000000".iter().cycle().take(4096).cloned().collect();
+        assert!(compressed_size(&repeated) < 100);
+        let mut noise = Vec::new();
+        let mut value: u32 = 1;
+        for _ in 0..4096 {
+            value = value.wrapping_mul(1103515245).wrapping_add(12345);
+            noise.push((value >> 16) as u8);
+        }
+        assert!(compressed_size(&noise) > 4096);
+    }
+}
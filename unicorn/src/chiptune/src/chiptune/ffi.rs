@@ -11,6 +11,7 @@ pub const FREQ_TAB_SIZE : c_int = 96;
 pub const MAX_VOLUME: c_int = 128;
 pub const CYD_WAVE_MAX_ENTRIES: c_int = 128;
 pub const CYD_MAX_FX_CHANNELS: c_int = 8;
+pub const MUS_MAX_CHANNELS: c_int = 32;
 pub const CYD_CUTOFF_MAX: c_int = 2048;
 
 pub const MUS_FX_ARPEGGIO : c_int = 0x0000;
@@ -163,6 +164,7 @@ extern "C" {
   pub fn Chiptune_SetLooping(player: chiptune_player, looping: c_int);
   pub fn Chiptune_GetMusicPlayPosition(player: chiptune_player) -> c_int;
   pub fn Chiptune_GetSoundPlayPosition(player: chiptune_player, chan: c_int) -> c_int;
+  pub fn Chiptune_GetVUMeters(player: chiptune_player, dest: *mut c_int, n_channels: c_int);
   pub fn Chiptune_GetSongInfo(player: chiptune_player) -> c_int;
   pub fn Chiptune_GetInstrument(song : chiptune_song, idx: c_int) -> chiptune_sound;
 }
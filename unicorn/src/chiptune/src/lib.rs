@@ -35,6 +35,26 @@ pub mod chiptune {
     pub S: ffi::chiptune_sound,
   }
 
+  #[derive(Debug, Clone, Copy, PartialEq)]
+  pub enum ChiptuneWaveform {
+    Square,
+    Triangle,
+    Saw,
+    Noise,
+  }
+
+  impl ChiptuneWaveform {
+    pub fn from_name(name: &str) -> Option<ChiptuneWaveform> {
+      match name.to_lowercase().as_str() {
+        "square" | "pulse" => Some(ChiptuneWaveform::Square),
+        "triangle" | "tri" => Some(ChiptuneWaveform::Triangle),
+        "saw" => Some(ChiptuneWaveform::Saw),
+        "noise" => Some(ChiptuneWaveform::Noise),
+        _ => None,
+      }
+    }
+  }
+
   pub use self::ffi::{
     CYD_PAN_CENTER, CYD_PAN_LEFT, CYD_PAN_RIGHT, CYD_CUTOFF_MAX, CYD_MAX_FX_CHANNELS, CYD_WAVE_MAX_ENTRIES, MAX_VOLUME,
     FREQ_TAB_SIZE,
@@ -510,6 +530,14 @@ pub mod chiptune {
       }
     }
 
+    /// Envelope of the first channels, from 0 to MAX_VOLUME
+    pub fn get_vu_meters(&mut self, levels: &mut [c_int]) {
+      let len = cmp::min(levels.len(), ffi::MUS_MAX_CHANNELS as usize);
+      unsafe {
+        ffi::Chiptune_GetVUMeters(self.P, levels.as_mut_ptr(), len as c_int);
+      }
+    }
+
     pub fn set_sound_program(&mut self, sound: ChiptuneSound, value: u16, position: u32) -> bool {
       if position > ffi::MUS_PROG_LEN as u32 {
         return false;
@@ -568,6 +596,47 @@ pub mod chiptune {
         (*sound.S).musadsr_d = value;
       }
     }
+
+    pub fn get_sustain(&mut self, sound: ChiptuneSound) -> u8 {
+      unsafe {
+        (*sound.S).musadsr_s
+      }
+    }
+
+    pub fn set_sustain(&mut self, sound: ChiptuneSound, value: u8) {
+      unsafe {
+        (*sound.S).musadsr_s = value;
+      }
+    }
+
+    pub fn get_release(&mut self, sound: ChiptuneSound) -> u8 {
+      unsafe {
+        (*sound.S).musadsr_r
+      }
+    }
+
+    pub fn set_release(&mut self, sound: ChiptuneSound, value: u8) {
+      unsafe {
+        (*sound.S).musadsr_r = value;
+      }
+    }
+
+    /// Replace the oscillators of the sound by only one waveform
+    pub fn set_waveform(&mut self, sound: ChiptuneSound, waveform: ChiptuneWaveform) {
+      let all = (ffi::CYD_CHN_ENABLE_NOISE | ffi::CYD_CHN_ENABLE_PULSE |
+                 ffi::CYD_CHN_ENABLE_TRIANGLE | ffi::CYD_CHN_ENABLE_SAW) as u32;
+
+      let flag = match waveform {
+        ChiptuneWaveform::Square => ffi::CYD_CHN_ENABLE_PULSE,
+        ChiptuneWaveform::Triangle => ffi::CYD_CHN_ENABLE_TRIANGLE,
+        ChiptuneWaveform::Saw => ffi::CYD_CHN_ENABLE_SAW,
+        ChiptuneWaveform::Noise => ffi::CYD_CHN_ENABLE_NOISE,
+      } as u32;
+
+      unsafe {
+        (*sound.S).cydflags = ((*sound.S).cydflags & !all) | flag;
+      }
+    }
   }
 }
 
@@ -641,6 +710,27 @@ pub mod chiptune {
     pub fn get_music_position(&mut self) -> i32 {
       0
     }
+
+    pub fn get_vu_meters(&mut self, levels: &mut [i32]) {
+      for level in levels.iter_mut() {
+        *level = 0;
+      }
+    }
+
+    pub fn set_attack(&mut self, sound: ChiptuneSound, value: u8) {
+    }
+
+    pub fn set_decay(&mut self, sound: ChiptuneSound, value: u8) {
+    }
+
+    pub fn set_sustain(&mut self, sound: ChiptuneSound, value: u8) {
+    }
+
+    pub fn set_release(&mut self, sound: ChiptuneSound, value: u8) {
+    }
+
+    pub fn set_waveform(&mut self, sound: ChiptuneSound, waveform: ChiptuneWaveform) {
+    }
   
   }
 
@@ -651,4 +741,24 @@ pub mod chiptune {
   #[derive(Clone, Copy)]
   pub struct ChiptuneSound {
   }
+
+  #[derive(Debug, Clone, Copy, PartialEq)]
+  pub enum ChiptuneWaveform {
+    Square,
+    Triangle,
+    Saw,
+    Noise,
+  }
+
+  impl ChiptuneWaveform {
+    pub fn from_name(name: &str) -> Option<ChiptuneWaveform> {
+      match name.to_lowercase().as_str() {
+        "square" | "pulse" => Some(ChiptuneWaveform::Square),
+        "triangle" | "tri" => Some(ChiptuneWaveform::Triangle),
+        "saw" => Some(ChiptuneWaveform::Saw),
+        "noise" => Some(ChiptuneWaveform::Noise),
+        _ => None,
+      }
+    }
+  }
 }
\ No newline at end of file
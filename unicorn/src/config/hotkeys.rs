@@ -0,0 +1,54 @@
+//! Keys of the console itself, handled by the frontend before the cartridge:
+//! the frontends dispatch them from `HOTKEYS`, and the help overlay (F1)
+//! lists the same table, so the help follows the keys.
+
+use config::scancode::Scancode;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Hotkey {
+    Help,
+    InfoOverlay,
+    Screenshot,
+    Record,
+    Save,
+    SwitchCode,
+    StatsOverlay,
+    DebugWindow,
+    Video,
+    Magnifier,
+    Macro,
+    FrameDump,
+    Console,
+}
+
+impl Hotkey {
+    /// The debug window and the videos need a desktop
+    pub fn is_available(&self) -> bool {
+        match *self {
+            Hotkey::DebugWindow | Hotkey::Video => !cfg!(target_os = "emscripten"),
+            _ => true,
+        }
+    }
+}
+
+pub const HOTKEYS: [(Scancode, Hotkey, &'static str); 13] =
+    [(Scancode::F1, Hotkey::Help, "This help"),
+     (Scancode::F2, Hotkey::InfoOverlay, "Info (+Shift: variables)"),
+     (Scancode::F3, Hotkey::Screenshot, "Screenshot (+Shift: scale)"),
+     (Scancode::F4, Hotkey::Record, "Start/stop the GIF"),
+     (Scancode::F5, Hotkey::Save, "Save the cartridge"),
+     (Scancode::F6, Hotkey::SwitchCode, "Editor/game"),
+     (Scancode::F7, Hotkey::StatsOverlay, "Title/FPS (+Shift: audio)"),
+     (Scancode::F8, Hotkey::DebugWindow, "Debug window"),
+     (Scancode::F9, Hotkey::Video, "MP4 video (+Shift: WebM)"),
+     (Scancode::F10, Hotkey::Magnifier, "Magnifier (+Shift: memory)"),
+     (Scancode::F11, Hotkey::Macro, "Record/stop an input macro"),
+     (Scancode::F12, Hotkey::FrameDump, "Dump the frame"),
+     (Scancode::Grave, Hotkey::Console, "Developer console")];
+
+pub fn from_scancode(scancode: Scancode) -> Option<Hotkey> {
+    HOTKEYS
+        .iter()
+        .find(|&&(key, hotkey, _)| key == scancode && hotkey.is_available())
+        .map(|&(_, hotkey, _)| hotkey)
+}
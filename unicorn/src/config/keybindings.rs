@@ -0,0 +1,441 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+
+use serde_json;
+
+use config::Players;
+use config::keys::{PX8Key, DEFAULT_KEYS};
+use config::scancode::Scancode;
+
+/// Actions of the editor that could be bound to a key
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum EditorAction {
+    Copy,
+    Paste,
+    Cut,
+    Undo,
+    Redo,
+}
+
+impl EditorAction {
+    pub fn from_name(name: &str) -> Option<EditorAction> {
+        match name.to_lowercase().as_str() {
+            "copy" => Some(EditorAction::Copy),
+            "paste" => Some(EditorAction::Paste),
+            "cut" => Some(EditorAction::Cut),
+            "undo" => Some(EditorAction::Undo),
+            "redo" => Some(EditorAction::Redo),
+            _ => None,
+        }
+    }
+}
+
+/// A key with its modifier, written "Ctrl+C" or "F7" in the config file
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct KeyBinding {
+    pub ctrl: bool,
+    pub scancode: Scancode,
+}
+
+impl KeyBinding {
+    pub fn new(ctrl: bool, scancode: Scancode) -> KeyBinding {
+        KeyBinding {
+            ctrl: ctrl,
+            scancode: scancode,
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<KeyBinding> {
+        let mut ctrl = false;
+        let mut key = None;
+
+        for part in value.split('+') {
+            let part = part.trim();
+            match part.to_lowercase().as_str() {
+                "ctrl" | "control" | "cmd" | "gui" => ctrl = true,
+                _ => key = Scancode::from_name(part),
+            }
+        }
+
+        key.map(|scancode| KeyBinding::new(ctrl, scancode))
+    }
+
+    pub fn is_pressed(&self, players: &mut Players) -> bool {
+        players.is_ctrl_down() == self.ctrl && players.btnp3(self.scancode)
+    }
+}
+
+/// Content of the keybindings config file:
+/// { "editor": { "copy": ["Ctrl+C"], "undo": ["Ctrl+Z"] },
+///   "players": { "0": { "a": ["W"], "b": ["X"] }, "1": { "up": ["Z"] } },
+///   "joystick": { "buttons": { "2": "a", "3": "b" }, "axis_x": 0, "axis_y": 1, "dead_zone": 8000 },
+///   "switch": { "key": "Space", "rate": 1.0, "player": 0 } }
+#[derive(Deserialize, Default)]
+struct KeyBindingsFile {
+    #[serde(default)]
+    editor: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    players: HashMap<String, HashMap<String, Vec<String>>>,
+    #[serde(default)]
+    joystick: Option<JoystickBindingsFile>,
+}
+
+#[derive(Deserialize, Default)]
+struct JoystickBindingsFile {
+    #[serde(default)]
+    buttons: HashMap<String, String>,
+    axis_x: Option<u8>,
+    axis_y: Option<u8>,
+    dead_zone: Option<i16>,
+}
+
+pub fn read_file(filename: &str) -> Result<String, String> {
+    let mut f = match File::open(filename) {
+        Ok(f) => f,
+        Err(e) => return Err(format!("{}: {}", filename, e)),
+    };
+
+    let mut data = String::new();
+    if let Err(e) = f.read_to_string(&mut data) {
+        return Err(format!("{}: {}", filename, e));
+    }
+
+    Ok(data)
+}
+
+pub struct EditorKeyBindings {
+    bindings: HashMap<EditorAction, Vec<KeyBinding>>,
+}
+
+impl EditorKeyBindings {
+    pub fn new() -> EditorKeyBindings {
+        let mut bindings = HashMap::new();
+
+        bindings.insert(EditorAction::Copy,
+                        vec![KeyBinding::new(true, Scancode::C)]);
+        bindings.insert(EditorAction::Paste,
+                        vec![KeyBinding::new(true, Scancode::V)]);
+        bindings.insert(EditorAction::Cut, vec![KeyBinding::new(true, Scancode::X)]);
+        bindings.insert(EditorAction::Undo,
+                        vec![KeyBinding::new(true, Scancode::Z)]);
+        bindings.insert(EditorAction::Redo,
+                        vec![KeyBinding::new(true, Scancode::Y)]);
+
+        EditorKeyBindings { bindings: bindings }
+    }
+
+    pub fn load(&mut self, filename: &str) -> Result<(), String> {
+        let data = try!(read_file(filename));
+        self.load_str(&data)
+    }
+
+    /// Replace the bindings of every action present in the JSON data,
+    /// the other actions keep their current keys
+    pub fn load_str(&mut self, data: &str) -> Result<(), String> {
+        let file: KeyBindingsFile = match serde_json::from_str(data) {
+            Ok(file) => file,
+            Err(e) => return Err(format!("{}", e)),
+        };
+
+        for (name, keys) in file.editor {
+            let action = match EditorAction::from_name(&name) {
+                Some(action) => action,
+                None => return Err(format!("Unknown editor action {:?}", name)),
+            };
+
+            let mut bindings = Vec::new();
+            for key in keys {
+                match KeyBinding::parse(&key) {
+                    Some(binding) => bindings.push(binding),
+                    None => return Err(format!("Unknown key {:?} for {:?}", key, name)),
+                }
+            }
+
+            info!("[CONFIG][KEYBINDINGS] {:?} -> {:?}", action, bindings);
+            self.bindings.insert(action, bindings);
+        }
+
+        Ok(())
+    }
+
+    pub fn bind(&mut self, action: EditorAction, binding: KeyBinding) {
+        self.bindings.entry(action).or_insert_with(Vec::new).push(binding);
+    }
+
+    pub fn get(&self, action: EditorAction) -> Vec<KeyBinding> {
+        match self.bindings.get(&action) {
+            Some(bindings) => bindings.clone(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn is_pressed(&self, action: EditorAction, players: &mut Players) -> bool {
+        match self.bindings.get(&action) {
+            Some(bindings) => bindings.iter().any(|binding| binding.is_pressed(players)),
+            None => false,
+        }
+    }
+}
+
+/// Keys of the players (`btn`/`btnp`), the defaults are replaced for
+/// each button redefined in the config file
+pub struct PlayerKeyBindings {
+    keys: HashMap<Scancode, (PX8Key, u8)>,
+}
+
+impl PlayerKeyBindings {
+    pub fn new() -> PlayerKeyBindings {
+        let mut keys = HashMap::new();
+        for &(scancode, key, player) in DEFAULT_KEYS.iter() {
+            keys.insert(scancode, (key, player));
+        }
+
+        PlayerKeyBindings { keys: keys }
+    }
+
+    pub fn load(&mut self, filename: &str) -> Result<(), String> {
+        let data = try!(read_file(filename));
+        self.load_str(&data)
+    }
+
+    pub fn load_str(&mut self, data: &str) -> Result<(), String> {
+        let file: KeyBindingsFile = match serde_json::from_str(data) {
+            Ok(file) => file,
+            Err(e) => return Err(format!("{}", e)),
+        };
+
+        for (player_name, buttons) in file.players {
+            let player = match player_name.parse::<u8>() {
+                Ok(player) => player,
+                Err(_) => return Err(format!("Unknown player {:?}", player_name)),
+            };
+
+            for (name, keys) in buttons {
+                let key = match PX8Key::from_name(&name) {
+                    Some(key) => key,
+                    None => return Err(format!("Unknown button {:?} for the player {:?}", name, player)),
+                };
+
+                let mut scancodes = Vec::new();
+                for value in keys {
+                    match Scancode::from_name(&value) {
+                        Some(scancode) => scancodes.push(scancode),
+                        None => return Err(format!("Unknown key {:?} for {:?}", value, name)),
+                    }
+                }
+
+                info!("[CONFIG][KEYBINDINGS] Player {:?} {:?} -> {:?}", player, key, scancodes);
+                self.bind(player, key, &scancodes);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replace all the keys of the button of a player
+    pub fn bind(&mut self, player: u8, key: PX8Key, scancodes: &[Scancode]) {
+        self.keys.retain(|_, value| *value != (key, player));
+        for scancode in scancodes {
+            self.keys.insert(*scancode, (key, player));
+        }
+    }
+
+    pub fn map(&self, scancode: Scancode) -> (Option<PX8Key>, u8) {
+        match self.keys.get(&scancode) {
+            Some(&(key, player)) => (Some(key), player),
+            None => (None, 0),
+        }
+    }
+
+    /// Keys of the button of a player, sorted by name
+    pub fn keys(&self, player: u8, key: PX8Key) -> Vec<Scancode> {
+        let mut scancodes: Vec<Scancode> = self.keys
+            .iter()
+            .filter(|&(_, value)| *value == (key, player))
+            .map(|(scancode, _)| *scancode)
+            .collect();
+        scancodes.sort_by_key(|scancode| format!("{:?}", scancode));
+        scancodes
+    }
+}
+
+/// Mapping of the joysticks without a game controller mapping (arcade
+/// sticks, ...): the buttons, the two axes and the hats move the player
+pub struct JoystickBindings {
+    buttons: HashMap<u8, PX8Key>,
+    pub axis_x: u8,
+    pub axis_y: u8,
+    pub dead_zone: i16,
+}
+
+impl JoystickBindings {
+    pub fn new() -> JoystickBindings {
+        let mut buttons = HashMap::new();
+        buttons.insert(0, PX8Key::A);
+        buttons.insert(1, PX8Key::B);
+
+        JoystickBindings {
+            buttons: buttons,
+            axis_x: 0,
+            axis_y: 1,
+            dead_zone: 16384,
+        }
+    }
+
+    pub fn load(&mut self, filename: &str) -> Result<(), String> {
+        let data = try!(read_file(filename));
+        self.load_str(&data)
+    }
+
+    /// The buttons of the file replace all the default ones
+    pub fn load_str(&mut self, data: &str) -> Result<(), String> {
+        let file: KeyBindingsFile = match serde_json::from_str(data) {
+            Ok(file) => file,
+            Err(e) => return Err(format!("{}", e)),
+        };
+
+        let joystick = match file.joystick {
+            Some(joystick) => joystick,
+            None => return Ok(()),
+        };
+
+        // 0 would hold the directions pressed, and -32768 can't be negated
+        if let Some(dead_zone) = joystick.dead_zone {
+            if dead_zone <= 0 {
+                return Err(format!("Invalid joystick dead zone {:?}, from 1 to 32767", dead_zone));
+            }
+        }
+
+        if !joystick.buttons.is_empty() {
+            let mut buttons = HashMap::new();
+            for (button, name) in joystick.buttons {
+                let button = match button.parse::<u8>() {
+                    Ok(button) => button,
+                    Err(_) => return Err(format!("Unknown joystick button {:?}", button)),
+                };
+                match PX8Key::from_name(&name) {
+                    Some(key) => buttons.insert(button, key),
+                    None => return Err(format!("Unknown button {:?} for the joystick button {:?}", name, button)),
+                };
+            }
+
+            info!("[CONFIG][KEYBINDINGS] Joystick buttons -> {:?}", buttons);
+            self.buttons = buttons;
+        }
+
+        if let Some(axis_x) = joystick.axis_x {
+            self.axis_x = axis_x;
+        }
+        if let Some(axis_y) = joystick.axis_y {
+            self.axis_y = axis_y;
+        }
+        if let Some(dead_zone) = joystick.dead_zone {
+            self.dead_zone = dead_zone;
+        }
+
+        Ok(())
+    }
+
+    pub fn map_button(&self, button: u8) -> Option<PX8Key> {
+        self.buttons.get(&button).cloned()
+    }
+
+    /// New state of the two directions of the axis, both are released in
+    /// the dead zone (the digital sticks jump from -32768 to 0)
+    pub fn map_axis(&self, axis: u8, value: i16) -> Vec<(PX8Key, bool)> {
+        let (negative, positive) = if axis == self.axis_x {
+            (PX8Key::Left, PX8Key::Right)
+        } else if axis == self.axis_y {
+            (PX8Key::Up, PX8Key::Down)
+        } else {
+            return Vec::new();
+        };
+
+        if value <= -self.dead_zone {
+            vec![(negative, true), (positive, false)]
+        } else if value >= self.dead_zone {
+            vec![(positive, true), (negative, false)]
+        } else {
+            vec![(negative, false), (positive, false)]
+        }
+    }
+
+    /// State of the directions for the position of a hat, `x`/`y` in -1..1
+    pub fn map_hat(&self, x: i32, y: i32) -> Vec<(PX8Key, bool)> {
+        vec![(PX8Key::Left, x < 0),
+             (PX8Key::Right, x > 0),
+             (PX8Key::Up, y < 0),
+             (PX8Key::Down, y > 0)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_binding() {
+        assert_eq!(KeyBinding::parse("Ctrl+C"),
+                   Some(KeyBinding::new(true, Scancode::C)));
+        assert_eq!(KeyBinding::parse("f7"), Some(KeyBinding::new(false, Scancode::F7)));
+        assert_eq!(KeyBinding::parse("Ctrl+Nope"), None);
+    }
+
+    #[test]
+    fn test_load_str() {
+        let mut bindings = EditorKeyBindings::new();
+        bindings.load_str(r#"{"editor": {"copy": ["Ctrl+Insert", "F3"]}}"#).unwrap();
+
+        assert_eq!(bindings.get(EditorAction::Copy),
+                   vec![KeyBinding::new(true, Scancode::Insert),
+                        KeyBinding::new(false, Scancode::F3)]);
+        assert_eq!(bindings.get(EditorAction::Paste),
+                   vec![KeyBinding::new(true, Scancode::V)]);
+
+        assert!(bindings.load_str(r#"{"editor": {"jump": ["A"]}}"#).is_err());
+    }
+
+    #[test]
+    fn test_player_bindings() {
+        let mut bindings = PlayerKeyBindings::new();
+        assert_eq!(bindings.map(Scancode::Z), (Some(PX8Key::A), 0));
+
+        // AZERTY keyboard
+        bindings.load_str(r#"{"players": {"0": {"a": ["W"]}, "1": {"up": ["Z"]}}}"#).unwrap();
+        assert_eq!(bindings.map(Scancode::W), (Some(PX8Key::A), 0));
+        assert_eq!(bindings.map(Scancode::C), (None, 0));
+        assert_eq!(bindings.map(Scancode::Z), (Some(PX8Key::Up), 1));
+        assert_eq!(bindings.map(Scancode::E), (None, 0));
+        assert_eq!(bindings.map(Scancode::X), (Some(PX8Key::B), 0));
+        assert_eq!(bindings.keys(0, PX8Key::A), vec![Scancode::W]);
+        assert_eq!(bindings.keys(0, PX8Key::B), vec![Scancode::M, Scancode::V, Scancode::X]);
+        assert!(bindings.keys(7, PX8Key::A).is_empty());
+
+        assert!(bindings.load_str(r#"{"players": {"0": {"jump": ["A"]}}}"#).is_err());
+        assert!(bindings.load_str(r#"{"players": {"one": {"a": ["A"]}}}"#).is_err());
+    }
+
+    #[test]
+    fn test_joystick_bindings() {
+        let mut bindings = JoystickBindings::new();
+        assert_eq!(bindings.map_button(0), Some(PX8Key::A));
+        assert_eq!(bindings.map_axis(0, -32768), vec![(PX8Key::Left, true), (PX8Key::Right, false)]);
+        assert_eq!(bindings.map_axis(1, 0), vec![(PX8Key::Up, false), (PX8Key::Down, false)]);
+        assert!(bindings.map_axis(3, 0).is_empty());
+
+        bindings.load_str(r#"{"joystick": {"buttons": {"2": "a", "9": "pause"}, "axis_y": 4, "dead_zone": 8000}}"#)
+            .unwrap();
+        assert_eq!(bindings.map_button(0), None);
+        assert_eq!(bindings.map_button(9), Some(PX8Key::Pause));
+        assert_eq!(bindings.map_axis(4, 9000), vec![(PX8Key::Down, true), (PX8Key::Up, false)]);
+
+        assert!(bindings.load_str(r#"{"joystick": {"buttons": {"2": "jump"}}}"#).is_err());
+
+        // The bindings are kept with an invalid dead zone
+        assert!(bindings.load_str(r#"{"joystick": {"dead_zone": 0}}"#).is_err());
+        assert!(bindings.load_str(r#"{"joystick": {"buttons": {"3": "a"}, "dead_zone": -32768}}"#).is_err());
+        assert_eq!(bindings.dead_zone, 8000);
+        assert_eq!(bindings.map_button(3), None);
+    }
+}
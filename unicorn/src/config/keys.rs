@@ -34,30 +34,60 @@ impl fmt::Debug for PX8Key {
 }
 
 
-pub fn map_keycode(key: Scancode) -> (Option<PX8Key>, u8) {
-    match key {
-        Scancode::Right => (Some(PX8Key::Right), 0),
-        Scancode::Left => (Some(PX8Key::Left), 0),
-        Scancode::Up => (Some(PX8Key::Up), 0),
-        Scancode::Down => (Some(PX8Key::Down), 0),
+impl PX8Key {
+    pub fn from_name(name: &str) -> Option<PX8Key> {
+        match name.to_lowercase().as_str() {
+            "right" => Some(PX8Key::Right),
+            "left" => Some(PX8Key::Left),
+            "up" => Some(PX8Key::Up),
+            "down" => Some(PX8Key::Down),
+            "a" => Some(PX8Key::A),
+            "b" => Some(PX8Key::B),
+            "pause" => Some(PX8Key::Pause),
+            "enter" => Some(PX8Key::Enter),
+            _ => None,
+        }
+    }
+}
+
+/// Default keys of the players, used when the keybindings config file
+/// doesn't redefine them
+pub const DEFAULT_KEYS: [(Scancode, PX8Key, u8); 22] =
+    [(Scancode::Right, PX8Key::Right, 0),
+     (Scancode::Left, PX8Key::Left, 0),
+     (Scancode::Up, PX8Key::Up, 0),
+     (Scancode::Down, PX8Key::Down, 0),
 
-        Scancode::Z | Scancode::C | Scancode::N => (Some(PX8Key::A), 0),
-        Scancode::X | Scancode::V | Scancode::M => (Some(PX8Key::B), 0),
+     (Scancode::Z, PX8Key::A, 0),
+     (Scancode::C, PX8Key::A, 0),
+     (Scancode::N, PX8Key::A, 0),
+     (Scancode::X, PX8Key::B, 0),
+     (Scancode::V, PX8Key::B, 0),
+     (Scancode::M, PX8Key::B, 0),
 
-        Scancode::F => (Some(PX8Key::Right), 1),
-        Scancode::S => (Some(PX8Key::Left), 1),
-        Scancode::E => (Some(PX8Key::Up), 1),
-        Scancode::D => (Some(PX8Key::Down), 1),
+     (Scancode::F, PX8Key::Right, 1),
+     (Scancode::S, PX8Key::Left, 1),
+     (Scancode::E, PX8Key::Up, 1),
+     (Scancode::D, PX8Key::Down, 1),
 
-        Scancode::LShift | Scancode::Tab => (Some(PX8Key::A), 1),
+     (Scancode::LShift, PX8Key::A, 1),
+     (Scancode::Tab, PX8Key::A, 1),
 
-        Scancode::A | Scancode::Q => (Some(PX8Key::A), 1),
-        Scancode::B => (Some(PX8Key::B), 1),
+     (Scancode::A, PX8Key::A, 1),
+     (Scancode::Q, PX8Key::A, 1),
+     (Scancode::B, PX8Key::B, 1),
 
-        Scancode::Escape => (Some(PX8Key::Pause), 0),
+     (Scancode::Escape, PX8Key::Pause, 0),
 
-        Scancode::KpEnter | Scancode::Return => (Some(PX8Key::Enter), 0),
+     (Scancode::KpEnter, PX8Key::Enter, 0),
+     (Scancode::Return, PX8Key::Enter, 0)];
 
-        _ => (None, 0),
+pub fn map_keycode(key: Scancode) -> (Option<PX8Key>, u8) {
+    for &(scancode, px8_key, player) in DEFAULT_KEYS.iter() {
+        if scancode == key {
+            return (Some(px8_key), player);
+        }
     }
-}
\ No newline at end of file
+
+    (None, 0)
+}
@@ -0,0 +1,238 @@
+//! Input macros: a short sequence of the buttons of the players, recorded
+//! from the game and replayed by one key, to always do the same jump while
+//! testing or to play a combo with a single key. F11 starts the recording,
+//! F11 again stops it and the next key pressed plays the macro. The macros
+//! last for the session and are replayed through the keys of the players,
+//! like the buttons pressed by hand.
+
+use std::collections::HashMap;
+
+use config::keys::PX8Key;
+use config::scancode::Scancode;
+
+/// Longest macro, 10 seconds at 60 FPS
+pub const MAX_MACRO_FRAMES: u32 = 600;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MacroEvent {
+    /// Frame from the start of the macro
+    pub frame: u32,
+    pub player: u8,
+    pub key: PX8Key,
+    pub down: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Macro {
+    pub events: Vec<MacroEvent>,
+    /// Frames of the macro, all its keys are released at the end
+    pub frames: u32,
+}
+
+impl Macro {
+    /// Keys still held after the events of the macro
+    fn held(&self) -> Vec<(u8, PX8Key)> {
+        let mut held = Vec::new();
+        for event in self.events.iter() {
+            held.retain(|&(player, key)| player != event.player || key != event.key);
+            if event.down {
+                held.push((event.player, event.key));
+            }
+        }
+        held
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MacroState {
+    Idle,
+    Recording(Macro),
+    /// Recorded, waiting for its key
+    Binding(Macro),
+}
+
+pub struct Macros {
+    pub state: MacroState,
+    pub bindings: HashMap<Scancode, Macro>,
+    /// Macros being replayed, with their current frame
+    pub playing: Vec<(Macro, u32)>,
+}
+
+impl Macros {
+    pub fn new() -> Macros {
+        Macros {
+            state: MacroState::Idle,
+            bindings: HashMap::new(),
+            playing: Vec::new(),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        match self.state {
+            MacroState::Recording(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_binding(&self) -> bool {
+        match self.state {
+            MacroState::Binding(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Start the recording, stop it, or cancel the macro waiting for its key
+    pub fn toggle_record(&mut self) {
+        let state = match self.state {
+            MacroState::Idle => {
+                info!("[Unicorn][MACROS] Start the recording");
+                MacroState::Recording(Macro {
+                                          events: Vec::new(),
+                                          frames: 0,
+                                      })
+            }
+            MacroState::Recording(ref input_macro) => Macros::stop(input_macro.clone()),
+            MacroState::Binding(_) => {
+                info!("[Unicorn][MACROS] Cancel the macro");
+                MacroState::Idle
+            }
+        };
+        self.state = state;
+    }
+
+    fn stop(input_macro: Macro) -> MacroState {
+        if input_macro.events.is_empty() {
+            info!("[Unicorn][MACROS] Empty macro, nothing to bind");
+            return MacroState::Idle;
+        }
+
+        info!("[Unicorn][MACROS] Stop the recording, {:?} events in {:?} frames",
+              input_macro.events.len(),
+              input_macro.frames);
+        MacroState::Binding(input_macro)
+    }
+
+    /// A key of a player changed during the recording
+    pub fn record(&mut self, player: u8, key: PX8Key, down: bool) {
+        if let MacroState::Recording(ref mut input_macro) = self.state {
+            input_macro.events.push(MacroEvent {
+                                        frame: input_macro.frames,
+                                        player: player,
+                                        key: key,
+                                        down: down,
+                                    });
+        }
+    }
+
+    /// Bind the recorded macro to `scancode`
+    pub fn bind(&mut self, scancode: Scancode) -> bool {
+        let input_macro = match self.state {
+            MacroState::Binding(ref input_macro) => input_macro.clone(),
+            _ => return false,
+        };
+
+        info!("[Unicorn][MACROS] Bind the macro to {:?}", scancode);
+        self.bindings.insert(scancode, input_macro);
+        self.state = MacroState::Idle;
+        true
+    }
+
+    /// Replay the macro of `scancode`, if any
+    pub fn play(&mut self, scancode: Scancode) -> bool {
+        if self.state != MacroState::Idle {
+            return false;
+        }
+
+        match self.bindings.get(&scancode) {
+            Some(input_macro) => {
+                debug!("[Unicorn][MACROS] Play {:?}", scancode);
+                self.playing.push((input_macro.clone(), 0));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Next frame: the keys to press (true) or to release (false)
+    pub fn update(&mut self) -> Vec<(u8, PX8Key, bool)> {
+        let mut keys = Vec::new();
+
+        let full = match self.state {
+            MacroState::Recording(ref mut input_macro) => {
+                input_macro.frames += 1;
+                input_macro.frames >= MAX_MACRO_FRAMES
+            }
+            _ => false,
+        };
+        if full {
+            self.toggle_record();
+        }
+
+        for &mut (ref input_macro, ref mut frame) in self.playing.iter_mut() {
+            for event in input_macro.events.iter().filter(|event| event.frame == *frame) {
+                keys.push((event.player, event.key, event.down));
+            }
+            if *frame == input_macro.frames {
+                for (player, key) in input_macro.held() {
+                    keys.push((player, key, false));
+                }
+            }
+            *frame += 1;
+        }
+        self.playing
+            .retain(|&(ref input_macro, frame)| frame <= input_macro.frames);
+
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::keys::PX8Key;
+    use config::scancode::Scancode;
+
+    use super::{Macros, MacroState, MAX_MACRO_FRAMES};
+
+    #[test]
+    fn test_macros() {
+        let mut macros = Macros::new();
+
+        // Nothing recorded, nothing to bind
+        macros.toggle_record();
+        assert!(macros.is_recording());
+        macros.toggle_record();
+        assert_eq!(macros.state, MacroState::Idle);
+
+        // A held, then B pressed one frame later and never released
+        macros.toggle_record();
+        macros.record(0, PX8Key::A, true);
+        macros.update();
+        macros.record(0, PX8Key::B, true);
+        macros.update();
+        macros.record(0, PX8Key::A, false);
+        macros.toggle_record();
+        assert!(macros.is_binding());
+        assert!(!macros.play(Scancode::M));
+        assert!(macros.bind(Scancode::M));
+        assert!(!macros.bind(Scancode::N));
+
+        assert!(macros.play(Scancode::M));
+        assert!(!macros.play(Scancode::N));
+        assert_eq!(macros.update(), vec![(0, PX8Key::A, true)]);
+        assert_eq!(macros.update(), vec![(0, PX8Key::B, true)]);
+        assert_eq!(macros.update(),
+                   vec![(0, PX8Key::A, false), (0, PX8Key::B, false)]);
+        assert!(macros.playing.is_empty());
+        assert!(macros.update().is_empty());
+
+        // The recording stops by itself
+        macros.toggle_record();
+        macros.record(1, PX8Key::Left, true);
+        for _ in 0..MAX_MACRO_FRAMES {
+            macros.update();
+        }
+        assert!(macros.is_binding());
+        macros.toggle_record();
+        assert_eq!(macros.state, MacroState::Idle);
+    }
+}
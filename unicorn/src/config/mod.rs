@@ -1,17 +1,31 @@
 pub mod keys;
+pub mod keybindings;
+pub mod hotkeys;
+pub mod macros;
 pub mod scancode;
+pub mod switch;
 
 use self::keys::PX8Key;
+use self::keybindings::{JoystickBindings, PlayerKeyBindings};
+use self::macros::Macros;
 use self::scancode::{Scancode, Mod};
+use self::switch::SwitchAccess;
 
 use std::collections::HashMap;
 
+/// Players with their own keys, the controller N is the player N
+pub const MAX_PLAYERS: u8 = 8;
+
 pub struct Mouse {
     pub x: i32,
     pub y: i32,
     pub state: u32,
     pub state_quick: u32,
     pub delay: f64,
+    /// Buttons held: 1 left, 2 right, 4 middle
+    pub buttons: u32,
+    /// Wheel moves during the frame, positive upward
+    pub wheel: i32,
 }
 
 impl Mouse {
@@ -22,6 +36,8 @@ impl Mouse {
             state: 0,
             state_quick: 0,
             delay: 0.,
+            buttons: 0,
+            wheel: 0,
         }
     }
 }
@@ -65,27 +81,42 @@ impl PlayerKeys {
 pub struct Players {
     pub pkeys: HashMap<u8, PlayerKeys>,
     pub mouse: Mouse,
+    /// First finger on the touchpad of the controller of each player, from
+    /// 0 to 1 in the touchpad
+    pub touchpads: HashMap<u8, (f64, f64)>,
     pub akeys: HashMap<Scancode, bool>,
     pub akeys_quick: HashMap<Scancode, bool>,
     pub all_frames: HashMap<Scancode, f64>,
     pub text: String,
     pub delta: f64,
+    pub keymod: Mod,
+    pub keybindings: PlayerKeyBindings,
+    pub joystick: JoystickBindings,
+    pub macros: Macros,
+    pub switch: SwitchAccess,
 }
 
 impl Players {
     pub fn new() -> Players {
         let mut keys = HashMap::new();
-        keys.insert(0, PlayerKeys::new());
-        keys.insert(1, PlayerKeys::new());
+        for player in 0..MAX_PLAYERS {
+            keys.insert(player, PlayerKeys::new());
+        }
 
         Players {
             pkeys: keys,
             mouse: Mouse::new(),
+            touchpads: HashMap::new(),
             akeys: HashMap::new(),
             akeys_quick: HashMap::new(),
             all_frames: HashMap::new(),
             text: "".to_string(),
             delta: 0.1,
+            keymod: Mod::NONE,
+            keybindings: PlayerKeyBindings::new(),
+            joystick: JoystickBindings::new(),
+            macros: Macros::new(),
+            switch: SwitchAccess::new(),
         }
     }
 
@@ -97,10 +128,23 @@ impl Players {
         self.text = text;
     }
 
+    /// Text typed during the frame, several events could arrive
+    pub fn push_text(&mut self, text: &str) {
+        self.text.push_str(text);
+    }
+
     pub fn get_text(&mut self) -> String {
         self.text.clone()
     }
 
+    /// The key is pressed (repeated like `btnp`), from its name ("A", "BACKSPACE" ...)
+    pub fn key_pressed(&mut self, name: &str) -> bool {
+        match Scancode::from_name(name) {
+            Some(scancode) => self.btnp3(scancode),
+            None => false,
+        }
+    }
+
     pub fn set_mouse_x(&mut self, x: i32) {
         self.mouse.x = x;
     }
@@ -129,7 +173,29 @@ impl Players {
         self.mouse.state_quick = 0;
     }
 
+    pub fn set_mouse_buttons(&mut self, left: bool, right: bool, middle: bool) {
+        self.mouse.buttons = (left as u32) | ((right as u32) << 1) | ((middle as u32) << 2);
+    }
+
+    pub fn mouse_wheel_moved(&mut self, y: i32) {
+        self.mouse.wheel += y;
+    }
+
+    pub fn clear_mouse_wheel(&mut self) {
+        self.mouse.wheel = 0;
+    }
+
     pub fn update(&mut self, elapsed: f64) {
+        for (player, key, down) in self.macros.update() {
+            if down {
+                self.set_key_down(player, key, false, elapsed);
+            } else {
+                self.set_key_up(player, key);
+            }
+        }
+
+        self.switch.update(elapsed);
+
         if elapsed - self.mouse.delay > self.delta {
             self.mouse.state = 0;
         }
@@ -186,10 +252,29 @@ impl Players {
                repeat,
                elapsed);
 
+        self.keymod = keymod;
+
+        if self.macro_key(scancode, repeat) {
+            return;
+        }
+
+        // The switch only presses the highlighted button
+        if self.switch.key == Some(scancode) {
+            if let Some(key) = self.switch.press(scancode) {
+                let player = self.switch.player;
+                self.key_down_direct(player, key, false, elapsed);
+            }
+            return;
+        }
+
+        // Keep the raw key for the configurable keybindings
+        self.akeys.insert(scancode, true);
+        self.akeys_quick.insert(scancode, true);
+        self.all_frames.insert(scancode, elapsed);
+
         let mut scancode = scancode;
 
-        if keymod == Mod::LCTRLMOD || keymod == Mod::RCTRLMOD || keymod == Mod::LGUIMOD ||
-           keymod == Mod::RGUIMOD {
+        if self.is_ctrl_down() {
             if scancode == Scancode::C {
                 scancode = Scancode::Copy;
             } else if scancode == Scancode::V {
@@ -204,11 +289,48 @@ impl Players {
 
         self.all_frames.insert(scancode, elapsed);
 
-        if let (Some(key), player) = self::keys::map_keycode(scancode) {
+        if let (Some(key), player) = self.keybindings.map(scancode) {
             self.key_down_direct(player, key, repeat, elapsed);
         }
     }
 
+    /// The key binds the recorded macro or plays one, instead of its action
+    fn macro_key(&mut self, scancode: Scancode, repeat: bool) -> bool {
+        if self.macros.is_binding() {
+            if scancode == Scancode::Escape {
+                self.macros.toggle_record();
+                return true;
+            }
+
+            // The keys of the players and of the console keep their action
+            if repeat || hotkeys::from_scancode(scancode).is_some() ||
+               self.keybindings.map(scancode).0.is_some() {
+                return false;
+            }
+
+            return self.macros.bind(scancode);
+        }
+
+        if !self.macros.bindings.contains_key(&scancode) {
+            return false;
+        }
+        if !repeat {
+            self.macros.play(scancode);
+        }
+        true
+    }
+
+    pub fn is_ctrl_down(&self) -> bool {
+        self.keymod == Mod::LCTRLMOD || self.keymod == Mod::RCTRLMOD ||
+        self.keymod == Mod::LGUIMOD || self.keymod == Mod::RGUIMOD
+    }
+
+    /// A shift key is held, for the range selections of the editor
+    pub fn is_shift_down(&self) -> bool {
+        self.akeys.get(&Scancode::LShift) == Some(&true) ||
+        self.akeys.get(&Scancode::RShift) == Some(&true)
+    }
+
     pub fn key_down_direct(&mut self, player: u8, key: PX8Key, repeat: bool, elapsed: f64) {
         debug!("KEY {:?} {:?} {:?} Player {:?} -> DOWN",
                key,
@@ -216,9 +338,17 @@ impl Players {
                elapsed,
                player);
 
+        if self.set_key_down(player, key, repeat, elapsed) {
+            self.macros.record(player, key, true);
+        }
+    }
+
+    /// Press the key, true when it was up
+    fn set_key_down(&mut self, player: u8, key: PX8Key, repeat: bool, elapsed: f64) -> bool {
         match self.pkeys.get_mut(&player) {
             Some(keys) => {
-                if !keys.keys[&key] {
+                let pressed = !keys.keys[&key];
+                if pressed {
                     keys.keys_quick.insert(key, true);
                 }
 
@@ -226,26 +356,29 @@ impl Players {
                 if !repeat {
                     keys.frames.insert(key, elapsed);
                 }
+                pressed
             }
-            None => (),
+            None => false,
         }
     }
 
     pub fn key_direc_hor_up(&mut self, player: u8) {
-        match self.pkeys.get_mut(&player) {
-            Some(keys) => {
-                keys.keys.insert(PX8Key::Right, false);
-                keys.keys.insert(PX8Key::Left, false);
-            }
-            None => (),
-        }
+        self.key_direc_up(player, &[PX8Key::Right, PX8Key::Left]);
     }
 
     pub fn key_direc_ver_up(&mut self, player: u8) {
+        self.key_direc_up(player, &[PX8Key::Up, PX8Key::Down]);
+    }
+
+    fn key_direc_up(&mut self, player: u8, directions: &[PX8Key]) {
         match self.pkeys.get_mut(&player) {
             Some(keys) => {
-                keys.keys.insert(PX8Key::Up, false);
-                keys.keys.insert(PX8Key::Down, false);
+                for key in directions.iter() {
+                    if keys.keys[key] {
+                        self.macros.record(player, *key, false);
+                    }
+                    keys.keys.insert(*key, false);
+                }
             }
             None => (),
         }
@@ -254,10 +387,22 @@ impl Players {
     pub fn key_up(&mut self, keymod: Mod, scancode: Scancode) {
         debug!("SCANCODE {:?} UP", scancode);
 
+        self.keymod = keymod;
+
+        if self.switch.key == Some(scancode) {
+            if let Some(key) = self.switch.release(scancode) {
+                let player = self.switch.player;
+                self.key_up_direct(player, key);
+            }
+            return;
+        }
+
+        self.akeys.insert(scancode, false);
+        self.akeys_quick.insert(scancode, false);
+
         let mut scancode = scancode;
 
-        if keymod == Mod::LCTRLMOD || keymod == Mod::RCTRLMOD || keymod == Mod::LGUIMOD ||
-           keymod == Mod::RGUIMOD {
+        if self.is_ctrl_down() {
             if scancode == Scancode::C {
                 scancode = Scancode::Copy;
             } else if scancode == Scancode::V {
@@ -270,20 +415,59 @@ impl Players {
         self.akeys.insert(scancode, false);
         self.akeys_quick.insert(scancode, false);
 
-        if let (Some(key), player) = self::keys::map_keycode(scancode) {
+        if let (Some(key), player) = self.keybindings.map(scancode) {
             self.key_up_direct(player, key);
         }
     }
 
+    /// Press/release the keys, the keys already down are not pressed again
+    /// to keep their repeat delay
+    pub fn set_keys(&mut self, player: u8, keys: &[(PX8Key, bool)], elapsed: f64) {
+        for &(key, state) in keys.iter() {
+            let down = match self.pkeys.get(&player) {
+                Some(player_keys) => player_keys.keys[&key],
+                None => return,
+            };
+
+            if state && !down {
+                self.key_down_direct(player, key, false, elapsed);
+            } else if !state && down {
+                self.key_up_direct(player, key);
+            }
+        }
+    }
+
+    /// Release all the keys of a player, when its controller is unplugged
+    pub fn release(&mut self, player: u8) {
+        self.touchpads.remove(&player);
+        if let Some(keys) = self.pkeys.get_mut(&player) {
+            for value in keys.keys.values_mut() {
+                *value = false;
+            }
+            for value in keys.keys_quick.values_mut() {
+                *value = false;
+            }
+        }
+    }
+
     pub fn key_up_direct(&mut self, player: u8, key: PX8Key) {
         debug!("KEY {:?} Player {:?} -> UP", key, player);
 
+        if self.set_key_up(player, key) {
+            self.macros.record(player, key, false);
+        }
+    }
+
+    /// Release the key, true when it was down
+    fn set_key_up(&mut self, player: u8, key: PX8Key) -> bool {
         match self.pkeys.get_mut(&player) {
             Some(keys) => {
+                let released = keys.keys[&key];
                 keys.keys.insert(key, false);
                 keys.keys_quick.insert(key, false);
+                released
             }
-            None => (),
+            None => false,
         }
     }
 
@@ -400,4 +584,34 @@ impl Players {
     pub fn mouse_state_quick(&mut self) -> u32 {
         self.mouse.state_quick
     }
+
+    /// Button 0 left, 1 right, 2 middle
+    pub fn mouse_btn(&self, button: u8) -> bool {
+        button < 3 && self.mouse.buttons & (1 << button) != 0
+    }
+
+    pub fn mouse_wheel(&self) -> i32 {
+        self.mouse.wheel
+    }
+
+    /// None when the touchpad isn't touched
+    pub fn set_touchpad(&mut self, player: u8, position: Option<(f64, f64)>) {
+        match position {
+            Some(position) => {
+                self.touchpads.insert(player, position);
+            }
+            None => {
+                self.touchpads.remove(&player);
+            }
+        }
+    }
+
+    /// Position of the finger in the screen of `width` x `height`
+    pub fn touchpad(&self, player: u8, width: usize, height: usize) -> Option<(i32, i32)> {
+        self.touchpads.get(&player).map(|&(x, y)| {
+            let x = (x.max(0.0).min(1.0) * width as f64) as i32;
+            let y = (y.max(0.0).min(1.0) * height as f64) as i32;
+            (x.min(width as i32 - 1), y.min(height as i32 - 1))
+        })
+    }
 }
\ No newline at end of file
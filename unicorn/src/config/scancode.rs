@@ -297,6 +297,92 @@ impl Scancode {
             _ => "",
         }
     }
+
+    pub fn from_name(name: &str) -> Option<Scancode> {
+        let name = name.to_uppercase();
+
+        let scancode = match name.as_str() {
+            "A" => Scancode::A,
+            "B" => Scancode::B,
+            "C" => Scancode::C,
+            "D" => Scancode::D,
+            "E" => Scancode::E,
+            "F" => Scancode::F,
+            "G" => Scancode::G,
+            "H" => Scancode::H,
+            "I" => Scancode::I,
+            "J" => Scancode::J,
+            "K" => Scancode::K,
+            "L" => Scancode::L,
+            "M" => Scancode::M,
+            "N" => Scancode::N,
+            "O" => Scancode::O,
+            "P" => Scancode::P,
+            "Q" => Scancode::Q,
+            "R" => Scancode::R,
+            "S" => Scancode::S,
+            "T" => Scancode::T,
+            "U" => Scancode::U,
+            "V" => Scancode::V,
+            "W" => Scancode::W,
+            "X" => Scancode::X,
+            "Y" => Scancode::Y,
+            "Z" => Scancode::Z,
+            "0" => Scancode::Num0,
+            "1" => Scancode::Num1,
+            "2" => Scancode::Num2,
+            "3" => Scancode::Num3,
+            "4" => Scancode::Num4,
+            "5" => Scancode::Num5,
+            "6" => Scancode::Num6,
+            "7" => Scancode::Num7,
+            "8" => Scancode::Num8,
+            "9" => Scancode::Num9,
+            "F1" => Scancode::F1,
+            "F2" => Scancode::F2,
+            "F3" => Scancode::F3,
+            "F4" => Scancode::F4,
+            "F5" => Scancode::F5,
+            "F6" => Scancode::F6,
+            "F7" => Scancode::F7,
+            "F8" => Scancode::F8,
+            "F9" => Scancode::F9,
+            "F10" => Scancode::F10,
+            "F11" => Scancode::F11,
+            "F12" => Scancode::F12,
+            "RETURN" | "ENTER" => Scancode::Return,
+            "ESCAPE" | "ESC" => Scancode::Escape,
+            "BACKSPACE" => Scancode::Backspace,
+            "TAB" => Scancode::Tab,
+            "SPACE" => Scancode::Space,
+            "MINUS" => Scancode::Minus,
+            "EQUALS" => Scancode::Equals,
+            "COMMA" => Scancode::Comma,
+            "PERIOD" => Scancode::Period,
+            "SLASH" => Scancode::Slash,
+            "SEMICOLON" => Scancode::Semicolon,
+            "INSERT" => Scancode::Insert,
+            "DELETE" => Scancode::Delete,
+            "HOME" => Scancode::Home,
+            "END" => Scancode::End,
+            "PAGEUP" => Scancode::PageUp,
+            "PAGEDOWN" => Scancode::PageDown,
+            "LEFT" => Scancode::Left,
+            "RIGHT" => Scancode::Right,
+            "UP" => Scancode::Up,
+            "DOWN" => Scancode::Down,
+            "LSHIFT" => Scancode::LShift,
+            "RSHIFT" => Scancode::RShift,
+            "LCTRL" => Scancode::LCtrl,
+            "RCTRL" => Scancode::RCtrl,
+            "LALT" => Scancode::LAlt,
+            "RALT" => Scancode::RAlt,
+            "KPENTER" => Scancode::KpEnter,
+            _ => return None,
+        };
+
+        Some(scancode)
+    }
 }
 
 use std::fmt;
@@ -0,0 +1,209 @@
+//! One-button input, for the players with a single switch: the buttons of
+//! the player are highlighted one after the other, and the switch presses
+//! the highlighted button as long as it is held. Set in the `switch` section
+//! of the keybindings config file:
+//! ```text
+//! {"switch": {"key": "Space", "rate": 1.0, "player": 0}}
+//! ```
+
+use serde_json;
+
+use config::keybindings::read_file;
+use config::keys::PX8Key;
+use config::scancode::Scancode;
+
+/// Buttons scanned, in this order
+pub const SCAN_KEYS: [PX8Key; 6] =
+    [PX8Key::Up, PX8Key::Down, PX8Key::Left, PX8Key::Right, PX8Key::A, PX8Key::B];
+
+/// Seconds on each button by default
+pub const DEFAULT_SCAN_RATE: f64 = 1.0;
+pub const MIN_SCAN_RATE: f64 = 0.25;
+
+#[derive(Deserialize, Default)]
+struct SwitchBindingsFile {
+    switch: Option<SwitchFile>,
+}
+
+#[derive(Deserialize)]
+struct SwitchFile {
+    key: String,
+    rate: Option<f64>,
+    player: Option<u8>,
+}
+
+pub struct SwitchAccess {
+    /// Key of the switch, the scanning is off without it
+    pub key: Option<Scancode>,
+    /// Seconds on each button
+    pub rate: f64,
+    pub player: u8,
+    idx: usize,
+    /// Time of the last move of the highlight
+    last_step: Option<f64>,
+    /// Button pressed by the switch, held until the switch is released
+    pressed: Option<PX8Key>,
+}
+
+impl SwitchAccess {
+    pub fn new() -> SwitchAccess {
+        SwitchAccess {
+            key: None,
+            rate: DEFAULT_SCAN_RATE,
+            player: 0,
+            idx: 0,
+            last_step: None,
+            pressed: None,
+        }
+    }
+
+    pub fn load(&mut self, filename: &str) -> Result<(), String> {
+        let data = try!(read_file(filename));
+        self.load_str(&data)
+    }
+
+    /// The scanning stays off without a `switch` section
+    pub fn load_str(&mut self, data: &str) -> Result<(), String> {
+        let file: SwitchBindingsFile = match serde_json::from_str(data) {
+            Ok(file) => file,
+            Err(e) => return Err(format!("{}", e)),
+        };
+
+        let switch = match file.switch {
+            Some(switch) => switch,
+            None => return Ok(()),
+        };
+
+        let key = match Scancode::from_name(&switch.key) {
+            Some(key) => key,
+            None => return Err(format!("Unknown key {:?} for the switch", switch.key)),
+        };
+
+        self.set(Some(key),
+                 switch.rate.unwrap_or(DEFAULT_SCAN_RATE),
+                 switch.player.unwrap_or(0));
+        Ok(())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.key.is_some()
+    }
+
+    pub fn set(&mut self, key: Option<Scancode>, rate: f64, player: u8) {
+        info!("[CONFIG][SWITCH] {:?} every {:?}s for the player {:?}",
+              key,
+              rate,
+              player);
+
+        self.key = key;
+        self.rate = rate.max(MIN_SCAN_RATE);
+        self.player = player;
+        self.idx = 0;
+        self.last_step = None;
+        self.pressed = None;
+    }
+
+    /// Highlighted button
+    pub fn current(&self) -> PX8Key {
+        SCAN_KEYS[self.idx]
+    }
+
+    /// Button pressed by the switch, if any
+    pub fn pressed(&self) -> Option<PX8Key> {
+        self.pressed
+    }
+
+    /// Move the highlight, it stays on the button held by the switch
+    pub fn update(&mut self, elapsed: f64) {
+        if !self.is_enabled() || self.pressed.is_some() {
+            return;
+        }
+
+        match self.last_step {
+            Some(last_step) => {
+                if elapsed - last_step >= self.rate {
+                    self.idx = (self.idx + 1) % SCAN_KEYS.len();
+                    self.last_step = Some(elapsed);
+                }
+            }
+            None => self.last_step = Some(elapsed),
+        }
+    }
+
+    /// The switch is pressed, the button to press
+    pub fn press(&mut self, scancode: Scancode) -> Option<PX8Key> {
+        if self.key != Some(scancode) || self.pressed.is_some() {
+            return None;
+        }
+
+        self.pressed = Some(self.current());
+        self.pressed
+    }
+
+    /// The switch is released, the button to release. The highlight stays
+    /// on it for a whole step, to press it again.
+    pub fn release(&mut self, scancode: Scancode) -> Option<PX8Key> {
+        if self.key != Some(scancode) {
+            return None;
+        }
+
+        self.last_step = None;
+        self.pressed.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::keys::PX8Key;
+    use config::scancode::Scancode;
+
+    use super::{SwitchAccess, DEFAULT_SCAN_RATE, MIN_SCAN_RATE};
+
+    #[test]
+    fn test_switch_access() {
+        let mut switch = SwitchAccess::new();
+        switch.update(0.0);
+        switch.update(5.0);
+        assert_eq!(switch.current(), PX8Key::Up);
+        assert_eq!(switch.press(Scancode::Space), None);
+
+        switch.set(Some(Scancode::Space), 0.5, 1);
+        switch.update(0.0);
+        switch.update(0.4);
+        assert_eq!(switch.current(), PX8Key::Up);
+        switch.update(0.5);
+        switch.update(1.0);
+        switch.update(1.5);
+        assert_eq!(switch.current(), PX8Key::Right);
+
+        // Held, the highlight doesn't move
+        assert_eq!(switch.press(Scancode::Return), None);
+        assert_eq!(switch.press(Scancode::Space), Some(PX8Key::Right));
+        assert_eq!(switch.press(Scancode::Space), None);
+        switch.update(3.0);
+        assert_eq!(switch.pressed(), Some(PX8Key::Right));
+        assert_eq!(switch.release(Scancode::Space), Some(PX8Key::Right));
+        assert_eq!(switch.release(Scancode::Space), None);
+        switch.update(3.1);
+        switch.update(3.4);
+        assert_eq!(switch.current(), PX8Key::Right);
+
+        // After B, back to Up
+        switch.update(3.6);
+        switch.update(4.1);
+        switch.update(4.6);
+        assert_eq!(switch.current(), PX8Key::Up);
+
+        switch.set(Some(Scancode::Space), 0.0, 0);
+        assert_eq!(switch.rate, MIN_SCAN_RATE);
+
+        let mut switch = SwitchAccess::new();
+        switch.load_str(r#"{"players": {}}"#).unwrap();
+        assert!(!switch.is_enabled());
+        switch.load_str(r#"{"switch": {"key": "Return", "player": 2}}"#).unwrap();
+        assert_eq!(switch.key, Some(Scancode::Return));
+        assert_eq!(switch.rate, DEFAULT_SCAN_RATE);
+        assert_eq!(switch.player, 2);
+        assert!(switch.load_str(r#"{"switch": {"key": "Nope"}}"#).is_err());
+    }
+}
@@ -1,12 +1,13 @@
-use gfx::Screen;
+use gfx::{Screen, Sprite};
 use config::Players;
+use config::keybindings::{EditorAction, EditorKeyBindings};
 use std::sync::{Arc, Mutex};
 use std::cmp::{max, min};
 use std::collections::HashMap;
 
 use unicorn::UnicornConfig;
 
-use unicorn::utils::{Widget, point_in_rect};
+use unicorn::utils::{Button, Widget, point_in_rect};
 use unicorn::edit::edit::State;
 use unicorn;
 
@@ -127,6 +128,9 @@ pub struct Flags {
     flags: HashMap<u32, u32>,
     idx_flag: [i32; 2],
     size: i32,
+    /// Flag shown on the sheet, selected with a right click
+    pub view: Option<u8>,
+    last_mouse_state: u32,
 }
 
 impl Flags {
@@ -146,6 +150,8 @@ impl Flags {
             flags: flags,
             idx_flag: idx_flag,
             size: 4,
+            view: None,
+            last_mouse_state: 0,
         }
     }
 
@@ -153,6 +159,10 @@ impl Flags {
         let mut idx = 0;
         let idx_sprite = self.state.lock().unwrap().current_sprite;
 
+        let mouse_state = self.state.lock().unwrap().mouse_state;
+        let right_click = mouse_state == 2 && self.last_mouse_state != 2;
+        self.last_mouse_state = mouse_state;
+
         for i in self.values.iter() {
             let flag = screen.fget(idx_sprite, *i as u8);
             let mut color = 8;
@@ -176,6 +186,20 @@ impl Flags {
                 }
             }
 
+            if right_click {
+                let mouse_x = self.state.lock().unwrap().mouse_x;
+                let mouse_y = self.state.lock().unwrap().mouse_y;
+
+                if point_in_rect(mouse_x,
+                                 mouse_y,
+                                 self.idx_flag[0] + idx,
+                                 self.idx_flag[1],
+                                 self.idx_flag[0] + self.size + idx,
+                                 self.idx_flag[1] + self.size) {
+                    self.view = if self.view == Some(*i as u8) { None } else { Some(*i as u8) };
+                }
+            }
+
             idx += 8;
         }
     }
@@ -192,11 +216,32 @@ impl Flags {
                             self.idx_flag[1] + self.size,
                             color as i32);
 
+            if self.view == Some(*k as u8) {
+                screen.rect(self.idx_flag[0] + idx - 1,
+                            self.idx_flag[1] - 1,
+                            self.idx_flag[0] + self.size + idx + 1,
+                            self.idx_flag[1] + self.size + 1,
+                            7);
+            }
+
             idx += 8
         }
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub enum MapTool {
+    Pen,
+    Fill,
+}
+
+const MAP_LAYER_X: i32 = 204;
+const MAP_LAYER_Y: i32 = 64;
+
+/// Search of the sprites with the flag of the view, next to the flags
+const FIND_X: i32 = 104;
+const FIND_Y: i32 = 158;
+
 #[derive(Debug)]
 pub enum EditorState {
     SpriteEditor,
@@ -217,6 +262,11 @@ pub struct MapEditor {
     current_sprite: [u32; 2],
     sprites_per_x: f32,
     sprites_per_y: f32,
+    tool: MapTool,
+    pen: Button,
+    fill: Button,
+    /// Flags of the sprites displayed (0 for all), like the layer of `map`
+    layer: u8,
 }
 
 impl MapEditor {
@@ -235,6 +285,10 @@ impl MapEditor {
             current_sprite: [0, 0],
             sprites_per_x: 25.,
             sprites_per_y: 22.,
+            tool: MapTool::Pen,
+            pen: Button::new(204, 40, 220, 48, 6, "PEN".to_string(), false),
+            fill: Button::new(222, 40, 238, 48, 6, "FILL".to_string(), false),
+            layer: 0,
         }
     }
 
@@ -285,6 +339,25 @@ impl MapEditor {
         let mouse_x = self.state.lock().unwrap().mouse_x;
         let mouse_y = self.state.lock().unwrap().mouse_y;
 
+        if self.state.lock().unwrap().mouse_state == 1 {
+            self.pen.update(mouse_x, mouse_y);
+            self.fill.update(mouse_x, mouse_y);
+
+            if self.pen.is_click() {
+                self.tool = MapTool::Pen;
+            }
+            if self.fill.is_click() {
+                self.tool = MapTool::Fill;
+            }
+
+            for flag in 0..8 {
+                let x = MAP_LAYER_X + flag * 4;
+                if point_in_rect(mouse_x, mouse_y, x, MAP_LAYER_Y, x + 3, MAP_LAYER_Y + 3) {
+                    self.layer ^= 1 << flag;
+                }
+            }
+        }
+
         if point_in_rect(mouse_x,
                          mouse_y,
                          self.coord[0],
@@ -307,13 +380,28 @@ impl MapEditor {
                 self.select_field[0] = select_field_x;
                 self.select_field[1] = select_field_y;
 
-                if mouse_statep == 1 {
+                if mouse_statep == 1 && self.tool == MapTool::Fill {
+                    let current_sprite = self.state.lock().unwrap().current_sprite;
+
+                    for (x, y) in flood_fill(&mut self.cache[..],
+                                             unicorn::MAP_WIDTH,
+                                             unicorn::MAP_HEIGHT,
+                                             new_x as usize,
+                                             new_y as usize,
+                                             current_sprite) {
+                        screen.mset(x as i32, y as i32, current_sprite);
+                    }
+                } else if mouse_statep == 1 {
                     let zoom_sprite = self.state.lock().unwrap().zoom_sprite;
 
                     for x in 0u32..zoom_sprite as u32 {
                         for y in 0u32..zoom_sprite as u32 {
                             let current_sprite = self.state.lock().unwrap().current_sprite + x +
-                                                 y * 16;
+                                                 y * 50;
+
+                            if new_x + x >= unicorn::MAP_WIDTH as u32 || new_y + y >= unicorn::MAP_HEIGHT as u32 {
+                                continue;
+                            }
 
                             let idx = ((new_x + x) as f64 + (new_y + y) as f64 * unicorn::MAP_WIDTH as f64).floor() as
                                       usize;
@@ -350,9 +438,10 @@ impl MapEditor {
                 let offset = x + y * unicorn::MAP_WIDTH as u32;
 
                 let sprite_number = self.cache[offset as usize];
-                if sprite_number != 0 {
-                    let sprite_x = (sprite_number % 16) * 8;
-                    let sprite_y = (sprite_number as f32 / 16.).floor() as i32 * 8;
+                let visible = self.layer == 0 || (screen.fget_all(sprite_number) & self.layer) != 0;
+                if sprite_number != 0 && visible {
+                    let sprite_x = (sprite_number % 50) * 8;
+                    let sprite_y = (sprite_number as f32 / 50.).floor() as i32 * 8;
 
                     let dx = idx_x * ((8. * self.zoom).floor() as i32);
                     let dy = idx_y * ((8. * self.zoom).floor() as i32) + 9;
@@ -388,24 +477,122 @@ impl MapEditor {
         screen.print(format!("{:?} {:?}: {:?}",
                              self.current_sprite[0],
                              self.current_sprite[1],
-                             self.cache[(self.current_sprite[0] + self.current_sprite[1] * 128) as
+                             self.cache[(self.current_sprite[0] +
+                                         self.current_sprite[1] * unicorn::MAP_WIDTH as u32) as
                              usize]),
                      210,
                      25,
                      7);
+
+        // Draw tools
+        self.pen.draw(screen);
+        self.fill.draw(screen);
+        let (x1, x2) = match self.tool {
+            MapTool::Pen => (204, 220),
+            MapTool::Fill => (222, 238),
+        };
+        screen.rect(x1, 39, x2, 49, 7);
+
+        // Draw layers, all the sprites are displayed without selected flag
+        screen.print("LAYER".to_string(), 204, MAP_LAYER_Y - 8, 7);
+        for flag in 0..8 {
+            let x = MAP_LAYER_X + flag * 4;
+            let color = if self.layer & (1 << flag) != 0 { 11 } else { 8 };
+            screen.rectfill(x, MAP_LAYER_Y, x + 2, MAP_LAYER_Y + 2, color);
+        }
+        screen.print(format!("ZOOM {}", self.size_sprite), 204, MAP_LAYER_Y + 8, 7);
     }
 }
 
+/// Replace the cells connected to x/y having the same sprite, the changed
+/// cells are returned
+pub fn flood_fill(map: &mut [u32],
+                  width: usize,
+                  height: usize,
+                  x: usize,
+                  y: usize,
+                  value: u32)
+                  -> Vec<(usize, usize)> {
+    let mut changed = Vec::new();
+
+    if x >= width || y >= height {
+        return changed;
+    }
+
+    let target = map[x + y * width];
+    if target == value {
+        return changed;
+    }
+
+    let mut stack = vec![(x, y)];
+    while let Some((x, y)) = stack.pop() {
+        if map[x + y * width] != target {
+            continue;
+        }
+
+        map[x + y * width] = value;
+        changed.push((x, y));
+
+        if x > 0 {
+            stack.push((x - 1, y));
+        }
+        if x + 1 < width {
+            stack.push((x + 1, y));
+        }
+        if y > 0 {
+            stack.push((x, y - 1));
+        }
+        if y + 1 < height {
+            stack.push((x, y + 1));
+        }
+    }
+
+    changed
+}
+
+/// Sprites of the sheet with the flag
+pub fn sprites_with_flag(sprites: &[Sprite], flag: u8) -> Vec<u32> {
+    sprites
+        .iter()
+        .enumerate()
+        .filter(|&(_, sprite)| sprite.is_flags_set(flag))
+        .map(|(idx, _)| idx as u32)
+        .collect()
+}
+
+/// Set the flag of the sprites from `from` to `to` in the order of the sheet,
+/// both of them included
+pub fn set_flag_range(sprites: &mut [Sprite], from: u32, to: u32, flag: u8, value: bool) {
+    let first = min(from, to) as usize;
+    let last = max(from, to) as usize;
+
+    for sprite in sprites.iter_mut().skip(first).take(last + 1 - first) {
+        sprite.set_flag(flag, value);
+    }
+}
+
+/// Sprite found after `current`, the first one again after the last one
+pub fn next_found(found: &[u32], current: u32) -> Option<u32> {
+    found
+        .iter()
+        .cloned()
+        .find(|&idx| idx > current)
+        .or(found.first().cloned())
+}
+
 pub struct SpriteEditor {
     state: Arc<Mutex<State>>,
     pp: PalettePicker,
     widgets: Vec<Arc<Mutex<Widget>>>,
     buffer_copy: Vec<u32>,
     buffer_copy_size: [u32; 2],
+    keybindings: Arc<Mutex<EditorKeyBindings>>,
 }
 
 impl SpriteEditor {
-    pub fn new(state: Arc<Mutex<State>>) -> SpriteEditor {
+    pub fn new(state: Arc<Mutex<State>>,
+               keybindings: Arc<Mutex<EditorKeyBindings>>)
+               -> SpriteEditor {
 
         let mut widgets = Vec::new();
         let mut highlight = HashMap::new();
@@ -495,6 +682,7 @@ impl SpriteEditor {
             widgets: widgets,
             buffer_copy: Vec::new(),
             buffer_copy_size: [0, 0],
+            keybindings: keybindings,
         }
     }
 
@@ -566,15 +754,24 @@ impl SpriteEditor {
     pub fn update(&mut self, players: Arc<Mutex<Players>>, screen: &mut Screen) {
         self.pp.update(screen);
 
-        if players.lock().unwrap().btnp2(1073741948) {
+        let (copy, paste, cut) = {
+            let keybindings = self.keybindings.lock().unwrap();
+            let mut players = players.lock().unwrap();
+
+            (keybindings.is_pressed(EditorAction::Copy, &mut players),
+             keybindings.is_pressed(EditorAction::Paste, &mut players),
+             keybindings.is_pressed(EditorAction::Cut, &mut players))
+        };
+
+        if copy {
             self.copy(screen);
         }
 
-        if players.lock().unwrap().btnp2(1073741949) {
+        if paste {
             self.paste(screen);
         }
 
-        if players.lock().unwrap().btnp2(1073741947) {
+        if cut {
             self.cut(screen);
         }
 
@@ -863,6 +1060,11 @@ impl SpriteEditor {
 pub struct SpritesMap {
     state: Arc<Mutex<State>>,
     flags: Flags,
+    /// Last sprite clicked in the flag view and its new value, the start of
+    /// the shift-click ranges
+    anchor: Option<(u32, bool)>,
+    find: Button,
+    last_mouse_state: u32,
 }
 
 impl SpritesMap {
@@ -872,13 +1074,44 @@ impl SpritesMap {
         SpritesMap {
             state: state.clone(),
             flags: Flags::new(state.clone()),
+            anchor: None,
+            find: Button::new(FIND_X, FIND_Y, FIND_X + 17, FIND_Y + 8, 6, "FIND".to_string(), false),
+            last_mouse_state: 0,
+        }
+    }
+
+    fn select(&mut self, idx_sprite: u32) {
+        let mut state = self.state.lock().unwrap();
+        state.current_sprite = idx_sprite;
+        state.x_zoom_sprite = (idx_sprite % 50) * 8;
+        state.y_zoom_sprite = (idx_sprite / 50) * 8;
+    }
+
+    /// Click on a sprite with the flag view: its flag is toggled, or with
+    /// shift the sprites from the last click get the value of that click
+    fn click_flag(&mut self, screen: &mut Screen, idx_sprite: u32, flag: u8) {
+        let shift = self.state.lock().unwrap().shift;
+
+        match self.anchor {
+            Some((anchor, value)) if shift => {
+                set_flag_range(&mut screen.sprites, anchor, idx_sprite, flag, value);
+            }
+            _ => {
+                let value = !screen.fget(idx_sprite, flag);
+                screen.fset(idx_sprite, flag, value);
+                self.anchor = Some((idx_sprite, value));
+            }
         }
     }
 
     pub fn update(&mut self, screen: &mut Screen) {
         self.state.lock().unwrap().on_current_sprite = false;
 
-        if self.state.lock().unwrap().mouse_state == 1 {
+        let mouse_state = self.state.lock().unwrap().mouse_state;
+        let click = mouse_state == 1 && self.last_mouse_state != 1;
+        self.last_mouse_state = mouse_state;
+
+        if mouse_state == 1 {
             let mouse_x = self.state.lock().unwrap().mouse_x;
             let mouse_y = self.state.lock().unwrap().mouse_y;
 
@@ -896,7 +1129,28 @@ impl SpritesMap {
                 let current_sprite = self.state.lock().unwrap().current_sprite;
                 self.state.lock().unwrap().x_zoom_sprite = (current_sprite % 50) * 8;
                 self.state.lock().unwrap().y_zoom_sprite = (current_sprite as f64 / 50.).floor() as u32 * 8;
+
+                if let (true, Some(flag)) = (click, self.flags.view) {
+                    self.click_flag(screen, current_sprite, flag);
+                }
+            }
+        }
+
+        // Next sprite with the flag of the view
+        if let Some(flag) = self.flags.view {
+            let mouse_x = self.state.lock().unwrap().mouse_x;
+            let mouse_y = self.state.lock().unwrap().mouse_y;
+            self.find.update(if click { mouse_x } else { -1 }, mouse_y);
+
+            if self.find.is_click() {
+                let current_sprite = self.state.lock().unwrap().current_sprite;
+                let found = sprites_with_flag(&screen.sprites, flag);
+                if let Some(idx_sprite) = next_found(&found, current_sprite) {
+                    self.select(idx_sprite);
+                }
             }
+        } else {
+            self.anchor = None;
         }
 
         // Update flags
@@ -911,6 +1165,12 @@ impl SpritesMap {
 
     pub fn draw_sprite_flags(&mut self, screen: &mut Screen) {
         self.flags.draw(screen);
+
+        if let Some(flag) = self.flags.view {
+            self.find.draw(screen);
+            let count = sprites_with_flag(&screen.sprites, flag).len();
+            screen.print(format!("FLAG {}: {} SPRITES", flag, count), FIND_X + 21, FIND_Y + 1, 7);
+        }
     }
 
     pub fn draw_sprite_map(&mut self, screen: &mut Screen) {
@@ -935,6 +1195,18 @@ impl SpritesMap {
                 let mut x = idx_sprites_batch_x;
                 for _ in 0..50 {
                     screen.spr(idx, x, y, 1, 1, false, false, 0., 1.0, false);
+                    // The sprites without the flag of the view are darker
+                    if let Some(flag) = self.flags.view {
+                        if !screen.fget(idx, flag) {
+                            for j in 0..8 {
+                                for i in 0..8 {
+                                    if (i + j) % 2 == 0 {
+                                        screen.pset(x + i, y + j, 0);
+                                    }
+                                }
+                            }
+                        }
+                    }
                     if idx == self.state.lock().unwrap().current_sprite {
                         current_sprite_x = x;
                         current_sprite_y = y;
@@ -987,7 +1259,9 @@ pub struct GFXEditor {
 }
 
 impl GFXEditor {
-    pub fn new(state: Arc<Mutex<State>>) -> GFXEditor {
+    pub fn new(state: Arc<Mutex<State>>,
+               keybindings: Arc<Mutex<EditorKeyBindings>>)
+               -> GFXEditor {
         let mut widgets = Vec::new();
         let mut highlight = HashMap::new();
         highlight.insert(6, 10);
@@ -1044,7 +1318,7 @@ impl GFXEditor {
             state_editor: EditorState::SpriteEditor,
             sm: SpritesMap::new(state.clone()),
             me: MapEditor::new(state.clone()),
-            se: SpriteEditor::new(state.clone()),
+            se: SpriteEditor::new(state.clone(), keybindings),
             widgets: widgets,
         }
     }
@@ -1122,3 +1396,47 @@ impl GFXEditor {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use gfx::Sprite;
+
+    use super::{flood_fill, next_found, set_flag_range, sprites_with_flag};
+
+    #[test]
+    fn test_flood_fill() {
+        let mut map = vec![0, 0, 1,
+                           0, 1, 0,
+                           1, 0, 0];
+
+        let changed = flood_fill(&mut map, 3, 3, 0, 0, 2);
+        assert_eq!(changed.len(), 3);
+        assert_eq!(map, vec![2, 2, 1,
+                             2, 1, 0,
+                             1, 0, 0]);
+
+        assert!(flood_fill(&mut map, 3, 3, 0, 0, 2).is_empty());
+        assert!(flood_fill(&mut map, 3, 3, 5, 0, 3).is_empty());
+    }
+
+    #[test]
+    fn test_flag_range() {
+        let mut sprites = vec![Sprite::new([0; 64]); 8];
+        sprites[6].set_flag(2, true);
+
+        set_flag_range(&mut sprites, 4, 1, 2, true);
+        assert_eq!(sprites_with_flag(&sprites, 2), vec![1, 2, 3, 4, 6]);
+        assert_eq!(sprites_with_flag(&sprites, 0), vec![]);
+
+        set_flag_range(&mut sprites, 3, 6, 2, false);
+        assert_eq!(sprites_with_flag(&sprites, 2), vec![1, 2]);
+        // The other flags are kept
+        assert_eq!(sprites[0].flags, 0);
+
+        let found = vec![1, 2, 30];
+        assert_eq!(next_found(&found, 0), Some(1));
+        assert_eq!(next_found(&found, 2), Some(30));
+        assert_eq!(next_found(&found, 30), Some(1));
+        assert_eq!(next_found(&[], 30), None);
+    }
+}
@@ -10,7 +10,10 @@ use config::Players;
 use config::scancode::Scancode;
 
 use sound::sound::{SoundInternal, Sound};
+use sound::music::{SfxEvent, SfxPlayer};
+use cartridge::{SfxPattern, SFX_NOTES, SFX_WAVEFORMS};
 use chiptune::chiptune;
+use chiptune::chiptune::ChiptuneWaveform;
 
 static KEYS_NOTE: [Scancode; 29] = [
     Scancode::Z,
@@ -786,7 +789,7 @@ impl SFXEditor {
                             Err(_) => (),
                         }
                     },
-                    Err(e) => println!("Error {:?}", e),
+                    Err(e) => error!("[EDITOR][MUSIC] Error {:?}", e),
                 }
 
             }
@@ -898,10 +901,320 @@ impl SFXEditor {
     }
 }
 
+/// Semitones displayed by the piano roll, from the selected octave
+const ROLL_ROWS: i32 = 24;
+const ROLL_X: i32 = 24;
+const ROLL_Y: i32 = 44;
+const ROLL_CELL_W: i32 = 10;
+const ROLL_CELL_H: i32 = 6;
+/// Highest semitone of klystron (B-7)
+const ROLL_MAX_NOTE: i32 = 95;
+
+static NOTE_NAMES: [&'static str; 12] = ["C-", "C#", "D-", "D#", "E-", "F-", "F#", "G-", "G#", "A-", "A#", "B-"];
+static WAVEFORM_LABELS: [&'static str; 4] = ["SQUARE", "TRIANGLE", "SAW", "NOISE"];
+static WAVEFORM_COLORS: [i32; 4] = [12, 11, 9, 8];
+
+/// Draw the notes of the sfx of the cartridge (`__sfx__` section) with the mouse:
+/// left button to set a note, right button to erase it
+pub struct PianoRollEditor {
+    idx_sfx: usize,
+    octave: i32,
+    waveforms: SFXFlags,
+    speed: ButtonSlider,
+    octave_slider: ButtonSlider,
+    prev: Button,
+    next: Button,
+    new: Button,
+    play: Button,
+    stop: Button,
+    player: SfxPlayer,
+    channel: i32,
+    sounds: HashMap<u8, chiptune::ChiptuneSound>,
+    last_mouse_state: u32,
+    /// Copy of the edited sfx for the drawing
+    pattern: SfxPattern,
+    num_sfx: usize,
+}
+
+impl PianoRollEditor {
+    pub fn new(_state: Arc<Mutex<State>>) -> PianoRollEditor {
+        PianoRollEditor {
+            idx_sfx: 0,
+            octave: 3,
+            waveforms: SFXFlags::new(),
+            speed: ButtonSlider::new("SPD".to_string(), "08".to_string(), 108, 28, 7, 6, 5),
+            octave_slider: ButtonSlider::new("OCT".to_string(), "3".to_string(), 170, 28, 7, 6, 5),
+            prev: Button::new(0, 16, 20, 24, 5, "PREV".to_string(), false),
+            next: Button::new(22, 16, 42, 24, 5, "NEXT".to_string(), false),
+            new: Button::new(44, 16, 60, 24, 5, "NEW".to_string(), false),
+            play: Button::new(140, 16, 160, 24, 5, "PLAY".to_string(), false),
+            stop: Button::new(162, 16, 182, 24, 5, "STOP".to_string(), false),
+            player: SfxPlayer::new(),
+            channel: -1,
+            sounds: HashMap::new(),
+            last_mouse_state: 0,
+            pattern: SfxPattern::new(),
+            num_sfx: 0,
+        }
+    }
+
+    pub fn init(&mut self, _screen: &mut Screen) {
+        info!("[EDITOR][MUSIC][ROLL] Init");
+
+        if self.waveforms.flags.is_empty() {
+            let mut x = 0;
+            for label in WAVEFORM_LABELS.iter() {
+                let width = 4 * label.len() as i32;
+                self.waveforms.add(label.to_string(), x + 1, 29, vec![x, 28, x + width + 2, 36], 7, 6, 8);
+                x += width + 4;
+            }
+        }
+
+        self.player.stop();
+    }
+
+    /// klystron sound used to play the notes of a waveform, created the first time
+    fn get_sound(&mut self, sound_internal: &mut SoundInternal, waveform: u8) -> Option<chiptune::ChiptuneSound> {
+        if !self.sounds.contains_key(&waveform) {
+            let name = SFX_WAVEFORMS[waveform as usize % SFX_WAVEFORMS.len()];
+
+            match sound_internal.player.new_sound(format!("piano-roll-{}", name)) {
+                Ok(chip_sound) => {
+                    if let Some(value) = ChiptuneWaveform::from_name(name) {
+                        sound_internal.player.set_waveform(chip_sound, value);
+                    }
+                    self.sounds.insert(waveform, chip_sound);
+                }
+                Err(e) => {
+                    error!("[EDITOR][MUSIC][ROLL] Error to create the sound {:?}", e);
+                    return None;
+                }
+            }
+        }
+
+        self.sounds.get(&waveform).cloned()
+    }
+
+    fn stop_note(&mut self, sound_internal: &mut SoundInternal) {
+        if self.channel >= 0 {
+            sound_internal.stop_chan(self.channel);
+            self.channel = -1;
+        }
+    }
+
+    fn play_note(&mut self, sound_internal: &mut SoundInternal, waveform: u8, note: i32) {
+        self.stop_note(sound_internal);
+
+        if let Some(mut chip_sound) = self.get_sound(sound_internal, waveform) {
+            self.channel = sound_internal.player.play_sound(&mut chip_sound, -1, (note << 8) as u16, 64, 50);
+        }
+    }
+
+    /// Step and semitone of the cell under the mouse
+    fn cell(&self, mouse_x: i32, mouse_y: i32) -> Option<(usize, i32)> {
+        if !point_in_rect(mouse_x, mouse_y,
+                          ROLL_X, ROLL_Y,
+                          ROLL_X + SFX_NOTES as i32 * ROLL_CELL_W, ROLL_Y + ROLL_ROWS * ROLL_CELL_H) {
+            return None;
+        }
+
+        let step = ((mouse_x - ROLL_X) / ROLL_CELL_W) as usize;
+        let row = (mouse_y - ROLL_Y) / ROLL_CELL_H;
+        Some((step, self.octave * 12 + ROLL_ROWS - 1 - row))
+    }
+
+    pub fn update(&mut self,
+                  cartridge: &mut UnicornCartridge,
+                  players: Arc<Mutex<Players>>,
+                  sound_internal: Arc<Mutex<SoundInternal>>,
+                  _sound: Arc<Mutex<Sound>>) -> bool {
+        let mouse_state_quick = players.lock().unwrap().mouse_state_quick();
+        let mouse_x = players.lock().unwrap().mouse_coordinate(0);
+        let mouse_y = players.lock().unwrap().mouse_coordinate(1);
+
+        // Only the first frame of a click for the buttons
+        let clicked = mouse_state_quick == 1 && self.last_mouse_state != 1;
+        self.last_mouse_state = mouse_state_quick;
+
+        let mut sound_internal = sound_internal.lock().unwrap();
+
+        if cartridge.cartridge.sfx.sfx.len() == 0 {
+            info!("[EDITOR][MUSIC][ROLL] Create new sfx");
+            cartridge.cartridge.sfx.sfx.push(SfxPattern::new());
+        }
+
+        if self.idx_sfx >= cartridge.cartridge.sfx.sfx.len() {
+            self.idx_sfx = 0;
+        }
+
+        if clicked {
+            self.prev.update(mouse_x, mouse_y);
+            self.next.update(mouse_x, mouse_y);
+            self.new.update(mouse_x, mouse_y);
+            self.play.update(mouse_x, mouse_y);
+            self.stop.update(mouse_x, mouse_y);
+
+            let num_sfx = cartridge.cartridge.sfx.sfx.len();
+
+            if self.next.is_click() {
+                self.idx_sfx = (self.idx_sfx + 1) % num_sfx;
+            }
+
+            if self.prev.is_click() {
+                self.idx_sfx = (self.idx_sfx + num_sfx - 1) % num_sfx;
+            }
+
+            if self.new.is_click() {
+                cartridge.cartridge.sfx.sfx.push(SfxPattern::new());
+                self.idx_sfx = num_sfx;
+            }
+
+            if self.play.is_click() {
+                self.player.start();
+            }
+
+            if self.stop.is_click() {
+                self.player.stop();
+                self.stop_note(&mut sound_internal);
+            }
+        }
+
+        let idx_sfx = self.idx_sfx;
+        let mut pattern = cartridge.cartridge.sfx.sfx[idx_sfx].clone();
+
+        /* WAVEFORM */
+        for (idx, label) in WAVEFORM_LABELS.iter().enumerate() {
+            if clicked && self.waveforms.is_active(label.to_string(), mouse_x, mouse_y) {
+                pattern.waveform = idx as u8;
+            }
+        }
+        for (idx, label) in WAVEFORM_LABELS.iter().enumerate() {
+            self.waveforms.update_flag(label.to_string(), idx == pattern.waveform as usize);
+        }
+
+        /* SPEED */
+        self.speed.update(if clicked { 1 } else { 0 }, mouse_x, mouse_y, players.clone());
+        if self.speed.is_minus_click() && pattern.speed > 1 {
+            pattern.speed -= 1;
+        }
+        if self.speed.is_plus_click() && pattern.speed < 0xff {
+            pattern.speed += 1;
+        }
+        self.speed.update_value(format!("{:02X}", pattern.speed));
+
+        /* OCTAVE */
+        self.octave_slider.update(if clicked { 1 } else { 0 }, mouse_x, mouse_y, players.clone());
+        if self.octave_slider.is_minus_click() && self.octave > 0 {
+            self.octave -= 1;
+        }
+        if self.octave_slider.is_plus_click() && (self.octave * 12 + ROLL_ROWS - 1) < ROLL_MAX_NOTE {
+            self.octave += 1;
+        }
+        self.octave_slider.update_value(format!("{}", self.octave));
+
+        /* NOTES */
+        if let Some((step, note)) = self.cell(mouse_x, mouse_y) {
+            if mouse_state_quick == 1 && pattern.notes[step] != note {
+                pattern.notes[step] = note;
+                if !self.player.is_playing() {
+                    self.play_note(&mut sound_internal, pattern.waveform, note);
+                }
+            } else if mouse_state_quick == 2 {
+                pattern.notes[step] = -1;
+            }
+        }
+
+        /* PREVIEW */
+        match self.player.tick(&pattern) {
+            Some(SfxEvent::Note(note)) => self.play_note(&mut sound_internal, pattern.waveform, note),
+            Some(SfxEvent::Rest) | Some(SfxEvent::End) => self.stop_note(&mut sound_internal),
+            None => (),
+        }
+
+        cartridge.cartridge.sfx.sfx[idx_sfx] = pattern.clone();
+        self.pattern = pattern;
+        self.num_sfx = cartridge.cartridge.sfx.sfx.len();
+
+        true
+    }
+
+    pub fn draw(&mut self, screen: &mut Screen) {
+        let pattern = self.pattern.clone();
+
+        screen.print(format!("SFX {:02}/{:02}", self.idx_sfx, self.num_sfx), 66, 18, 7);
+        self.prev.draw(screen);
+        self.next.draw(screen);
+        self.new.draw(screen);
+        self.play.draw(screen);
+        self.stop.draw(screen);
+
+        self.waveforms.draw(screen);
+        self.speed.draw(screen);
+        self.octave_slider.draw(screen);
+
+        let width = SFX_NOTES as i32 * ROLL_CELL_W;
+
+        /* KEYS */
+        for row in 0..ROLL_ROWS {
+            let note = self.octave * 12 + ROLL_ROWS - 1 - row;
+            let y = ROLL_Y + row * ROLL_CELL_H;
+            let name = NOTE_NAMES[(note % 12) as usize];
+
+            let color = if name.ends_with('#') { 1 } else { 0 };
+            screen.rectfill(ROLL_X, y, ROLL_X + width - 1, y + ROLL_CELL_H - 1, color);
+
+            if note % 12 == 0 {
+                screen.print(format!("{}{}", name, note / 12), 0, y, 6);
+                screen.line(ROLL_X, y + ROLL_CELL_H - 1, ROLL_X + width - 1, y + ROLL_CELL_H - 1, 5);
+            }
+        }
+
+        for step in 0..SFX_NOTES as i32 {
+            if step % 4 == 0 {
+                let x = ROLL_X + step * ROLL_CELL_W;
+                screen.line(x, ROLL_Y, x, ROLL_Y + ROLL_ROWS * ROLL_CELL_H - 1, 5);
+            }
+        }
+
+        /* NOTES */
+        let color = WAVEFORM_COLORS[pattern.waveform as usize % WAVEFORM_COLORS.len()];
+        let lowest = self.octave * 12;
+        let highest = lowest + ROLL_ROWS - 1;
+
+        for (step, note) in pattern.notes.iter().enumerate() {
+            let note = *note;
+            if note < 0 {
+                continue;
+            }
+
+            let x = ROLL_X + step as i32 * ROLL_CELL_W;
+
+            if note < lowest {
+                // Out of the displayed octaves
+                let y = ROLL_Y + ROLL_ROWS * ROLL_CELL_H;
+                screen.rectfill(x + 2, y + 1, x + ROLL_CELL_W - 3, y + 2, 6);
+            } else if note > highest {
+                screen.rectfill(x + 2, ROLL_Y - 3, x + ROLL_CELL_W - 3, ROLL_Y - 2, 6);
+            } else {
+                let y = ROLL_Y + (highest - note) * ROLL_CELL_H;
+                screen.rectfill(x + 1, y, x + ROLL_CELL_W - 2, y + ROLL_CELL_H - 2, color);
+            }
+        }
+
+        if self.player.is_playing() {
+            let x = ROLL_X + self.player.step * ROLL_CELL_W;
+            screen.rect(x, ROLL_Y, x + ROLL_CELL_W - 1, ROLL_Y + ROLL_ROWS * ROLL_CELL_H - 1, 7);
+        }
+
+        screen.print("LEFT: DRAW  RIGHT: ERASE  F5: SAVE".to_string(), 0, 196, 6);
+    }
+}
+
 #[derive(Debug)]
 pub enum MusicState {
     SFXEditor,
     TrackEditor,
+    PianoRoll,
 }
 
 pub struct MusicEditor {
@@ -909,6 +1222,7 @@ pub struct MusicEditor {
     state_editor: MusicState,
     se: SFXEditor,
     te: TrackEditor,
+    pr: PianoRollEditor,
     widgets: Vec<Arc<Mutex<Widget>>>,
 }
 
@@ -966,11 +1280,36 @@ impl MusicEditor {
                                                      highlight.clone(),
                                                      false, true))));
 
+        widgets.push(Arc::new(Mutex::new(Widget::new("ROLL".to_string(),
+                                                     330,
+                                                     20,
+                                                     16,
+                                                     16,
+                                                     vec![ 6,  6,  6,  6,  6,  6,  6,  6,  6,  6,  6,  6,  6,  6,  6,  6,
+                                                           6,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  6,
+                                                           6,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  6,
+                                                           6,  5,  6,  6,  6,  6,  5,  5,  5,  5,  5,  5,  5,  5,  5,  6,
+                                                           6,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  6,
+                                                           6,  5,  5,  5,  5,  5,  6,  6,  6,  5,  5,  5,  5,  5,  5,  6,
+                                                           6,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  6,  6,  6,  5,  6,
+                                                           6,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  6,
+                                                           6,  5,  5,  5,  5,  5,  5,  5,  5,  6,  6,  6,  6,  5,  5,  6,
+                                                           6,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  6,
+                                                           6,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  6,
+                                                           6,  5,  5,  6,  6,  6,  6,  5,  5,  5,  5,  5,  5,  5,  5,  6,
+                                                           6,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  6,
+                                                           6,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  6,
+                                                           6,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  5,  6,
+                                                           6,  6,  6,  6,  6,  6,  6,  6,  6,  6,  6,  6,  6,  6,  6,  6],
+                                                     highlight.clone(),
+                                                     false, true))));
+
         MusicEditor {
             state: state.clone(),
             state_editor: MusicState::TrackEditor,
             se: SFXEditor::new(state.clone()),
             te: TrackEditor::new(state.clone()),
+            pr: PianoRollEditor::new(state.clone()),
             widgets: widgets,
         }
     }
@@ -1007,6 +1346,10 @@ impl MusicEditor {
                         self.state_editor = MusicState::SFXEditor;
                         self.se.init(screen);
                     }
+                    if widget.lock().unwrap().name == "ROLL" {
+                        self.state_editor = MusicState::PianoRoll;
+                        self.pr.init(screen);
+                    }
                 }
             }
         }
@@ -1018,6 +1361,9 @@ impl MusicEditor {
             MusicState::TrackEditor => {
                 self.te.update(cartridge, players.clone(), sound_internal.clone(), sound.clone());
             }
+            MusicState::PianoRoll => {
+                self.pr.update(cartridge, players.clone(), sound_internal.clone(), sound.clone());
+            }
         }
 
         true
@@ -1032,6 +1378,9 @@ impl MusicEditor {
             MusicState::TrackEditor => {
                 self.te.draw(screen);
             }
+            MusicState::PianoRoll => {
+                self.pr.draw(screen);
+            }
         }
 
         for widget in &self.widgets {
@@ -28,6 +28,7 @@ use unicorn::edit::edit::State;
 
 use gfx::Screen;
 use config::Players;
+use config::keybindings::{EditorAction, EditorKeyBindings};
 
 use editor::text_editor::input::Input;
 use editor::text_editor::keyboard::Key;
@@ -42,16 +43,20 @@ use unicorn::Palettes;
 
 pub struct TextEditor {
     editor: Editor,
+    keybindings: Arc<Mutex<EditorKeyBindings>>,
 }
 
 impl TextEditor {
-    pub fn new(state: Arc<Mutex<State>>) -> TextEditor {
+    pub fn new(state: Arc<Mutex<State>>,
+               keybindings: Arc<Mutex<EditorKeyBindings>>)
+               -> TextEditor {
         let options = Options { syntax_enabled: true };
 
         TextEditor {
             editor: Editor::new(Input::Code("".to_string()),
                                 Box::new(StandardMode::new()),
                                 options),
+            keybindings: keybindings,
         }
     }
 
@@ -67,6 +72,17 @@ impl TextEditor {
     }
 
     pub fn update(&mut self, players: Arc<Mutex<Players>>) -> bool {
+        let keybindings = self.keybindings.lock().unwrap();
+        let mut players = players.lock().unwrap();
+
+        if keybindings.is_pressed(EditorAction::Undo, &mut players) {
+            self.editor.queue_command(Command::undo());
+        }
+
+        if keybindings.is_pressed(EditorAction::Redo, &mut players) {
+            self.editor.queue_command(Command::redo());
+        }
+
         true
     }
 
@@ -186,6 +202,10 @@ impl Editor {
         self.view.reset();
     }
 
+    pub fn queue_command(&mut self, command: Command) {
+        let _ = self.command_sender.send(command);
+    }
+
     pub fn get_buffer(&mut self) -> Vec<String> {
         self.view.get_buffer()
     }
@@ -406,8 +426,8 @@ impl Editor {
         }
 
         let text = players.get_text();
-        if text.len() == 1 {
-            self.handle_key_event(screen, Some(Key::Char(text.chars().next().unwrap())));
+        for c in text.chars() {
+            self.handle_key_event(screen, Some(Key::Char(c)));
         }
 
         while let Ok(message) = self.command_queue.try_recv() {
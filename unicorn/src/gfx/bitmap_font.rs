@@ -0,0 +1,195 @@
+//! Proportional bitmap fonts registered by the cartridges, from a region of
+//! the sprite sheet (`font_load`) or from embedded data (`font_data`).
+
+use std::char;
+use std::collections::HashMap;
+
+/// Widest glyph, one bit per pixel in a row
+pub const MAX_GLYPH_WIDTH: i32 = 32;
+pub const MAX_GLYPH_HEIGHT: i32 = 64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Glyph {
+    pub width: i32,
+    /// One value per row, bit 0 is the leftmost pixel
+    pub rows: Vec<u32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BitmapFont {
+    pub height: i32,
+    /// Pixels between two glyphs
+    pub spacing: i32,
+    /// Vertical distance between the lines of a string with '\n'
+    pub line_height: i32,
+    glyphs: HashMap<char, Glyph>,
+}
+
+impl BitmapFont {
+    pub fn new(height: i32) -> BitmapFont {
+        BitmapFont {
+            height: height,
+            spacing: 1,
+            line_height: height + 1,
+            glyphs: HashMap::new(),
+        }
+    }
+
+    /// `count` glyphs from `first`, in cells of `cell_w`x`cell_h` pixels from
+    /// left to right with `columns` cells per row, on `rows` rows (the glyphs
+    /// after the last cell are left out). `pixel(x, y)` is true for a pixel of
+    /// a glyph. The width of a glyph is its entry in `widths`, or
+    /// the last column with a pixel when it is missing or 0.
+    pub fn from_sheet<F>(pixel: F,
+                         cell_w: i32,
+                         cell_h: i32,
+                         columns: i32,
+                         rows: i32,
+                         first: char,
+                         count: u32,
+                         widths: &[i32])
+                         -> Result<BitmapFont, String>
+        where F: Fn(i32, i32) -> bool
+    {
+        if cell_w <= 0 || cell_w > MAX_GLYPH_WIDTH || cell_h <= 0 || cell_h > MAX_GLYPH_HEIGHT {
+            return Err(format!("Invalid glyph size {:?}x{:?}", cell_w, cell_h));
+        }
+        if columns <= 0 || rows <= 0 {
+            return Err("The glyphs are outside of the sprite sheet".to_string());
+        }
+
+        let count = count.min(columns as u32 * rows as u32);
+        let mut font = BitmapFont::new(cell_h);
+        for i in 0..count {
+            let c = match (first as u32).checked_add(i).and_then(char::from_u32) {
+                Some(c) => c,
+                None => return Err(format!("Invalid character {:?} + {:?}", first as u32, i)),
+            };
+
+            let x = (i as i32 % columns) * cell_w;
+            let y = (i as i32 / columns) * cell_h;
+
+            let mut rows = Vec::with_capacity(cell_h as usize);
+            let mut last_column: i32 = 0;
+            for dy in 0..cell_h {
+                let mut row = 0;
+                for dx in 0..cell_w {
+                    if pixel(x + dx, y + dy) {
+                        row |= 1 << dx;
+                        last_column = last_column.max(dx + 1);
+                    }
+                }
+                rows.push(row);
+            }
+
+            let width = match widths.get(i as usize) {
+                Some(&width) if width > 0 => width.min(cell_w),
+                // The space
+                _ if last_column == 0 => (cell_w / 2).max(1),
+                _ => last_column,
+            };
+
+            font.glyphs.insert(c,
+                               Glyph {
+                                   width: width,
+                                   rows: rows,
+                               });
+        }
+
+        Ok(font)
+    }
+
+    /// Glyphs of `height` rows, for each glyph: its character, its width and
+    /// its rows (bit 0 is the leftmost pixel)
+    pub fn from_data(height: i32, data: &[u32]) -> Result<BitmapFont, String> {
+        if height <= 0 || height > MAX_GLYPH_HEIGHT {
+            return Err(format!("Invalid glyph height {:?}", height));
+        }
+
+        let mut font = BitmapFont::new(height);
+        for glyph in data.chunks(height as usize + 2) {
+            if glyph.len() != height as usize + 2 {
+                return Err(format!("Truncated glyph {:?}", glyph[0]));
+            }
+
+            let c = match char::from_u32(glyph[0]) {
+                Some(c) => c,
+                None => return Err(format!("Invalid character {:?}", glyph[0])),
+            };
+            let width = glyph[1] as i32;
+            if width <= 0 || width > MAX_GLYPH_WIDTH {
+                return Err(format!("Invalid width {:?} of {:?}", width, c));
+            }
+
+            font.glyphs.insert(c,
+                               Glyph {
+                                   width: width,
+                                   rows: glyph[2..].to_vec(),
+                               });
+        }
+
+        Ok(font)
+    }
+
+    pub fn glyph_count(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    /// Glyph of the character, or of its upper case for the fonts without
+    /// the lower case letters
+    pub fn glyph(&self, c: char) -> Option<&Glyph> {
+        self.glyphs
+            .get(&c)
+            .or_else(|| self.glyphs.get(&c.to_ascii_uppercase()))
+    }
+
+    /// Horizontal distance to the next character
+    pub fn advance(&self, c: char) -> i32 {
+        match self.glyph(c) {
+            Some(glyph) => glyph.width + self.spacing,
+            None => self.height / 2 + self.spacing,
+        }
+    }
+
+    /// Width in pixels of the longest line of the string
+    pub fn text_width(&self, string: &str) -> i32 {
+        string
+            .split('\n')
+            .map(|line| {
+                     let width: i32 = line.chars().map(|c| self.advance(c)).sum();
+                     // No spacing after the last glyph
+                     if width > 0 { width - self.spacing } else { 0 }
+                 })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Pixels of the string drawn at (0, 0)
+    pub fn pixels(&self, string: &str) -> Vec<(i32, i32)> {
+        let mut pixels = Vec::new();
+
+        let mut x = 0;
+        let mut y = 0;
+        for c in string.chars() {
+            if c == '\n' {
+                x = 0;
+                y += self.line_height;
+                continue;
+            }
+
+            if let Some(glyph) = self.glyph(c) {
+                for (dy, row) in glyph.rows.iter().enumerate() {
+                    for dx in 0..glyph.width {
+                        if row & (1 << dx) != 0 {
+                            pixels.push((x + dx, y + dy as i32));
+                        }
+                    }
+                }
+            }
+
+            x += self.advance(c);
+        }
+
+        pixels
+    }
+}
@@ -0,0 +1,93 @@
+//! Collisions of `collide_rect`, `collide_map` and `sprite_overlap`: the
+//! rectangles are in pixels of the map (8 pixels per cell), only the opaque
+//! pixels of the sprites count. The rectangles are compared in fixed-point
+//! numbers, like the bodies of the physics.
+
+use unicorn::fixed::{Fixed, FRAC_BITS, ZERO};
+
+/// Side of a cell of the map in pixels, as a shift
+const CELL_BITS: u32 = 3;
+
+fn fixed_rect(rect: (f64, f64, f64, f64)) -> (Fixed, Fixed, Fixed, Fixed) {
+    (Fixed::from_f64(rect.0), Fixed::from_f64(rect.1), Fixed::from_f64(rect.2), Fixed::from_f64(rect.3))
+}
+
+/// Rectangles `x`/`y`/`w`/`h` sharing at least a part of a pixel, the empty
+/// ones never collide
+pub fn rects_overlap(a: (f64, f64, f64, f64), b: (f64, f64, f64, f64)) -> bool {
+    let (a, b) = (fixed_rect(a), fixed_rect(b));
+    a.2 > ZERO && a.3 > ZERO && b.2 > ZERO && b.3 > ZERO && a.0 < b.0 + b.2 && b.0 < a.0 + a.2 &&
+    a.1 < b.1 + b.3 && b.1 < a.1 + a.3
+}
+
+/// Cells of the map under a rectangle of pixels: the top-left and the
+/// bottom-right ones
+pub fn rect_cells(x: f64, y: f64, w: f64, h: f64) -> Option<((i32, i32), (i32, i32))> {
+    let (x, y, w, h) = fixed_rect((x, y, w, h));
+    if w <= ZERO || h <= ZERO {
+        return None;
+    }
+
+    // The last cell is the one of the last fraction of pixel inside
+    let cell = |value: Fixed| value.0 >> (FRAC_BITS + CELL_BITS);
+    Some(((cell(x), cell(y)), (cell(x + w - Fixed(1)), cell(y + h - Fixed(1)))))
+}
+
+/// Pixels of two blocks `x`/`y`/`w`/`h` opaque in both at the same place,
+/// `opaque_a`/`opaque_b` tell if a pixel of a block (from its top-left) is
+/// opaque
+pub fn pixels_overlap<A, B>(a: (i32, i32, i32, i32),
+                            b: (i32, i32, i32, i32),
+                            opaque_a: A,
+                            opaque_b: B)
+                            -> bool
+    where A: Fn(i32, i32) -> bool,
+          B: Fn(i32, i32) -> bool
+{
+    let left = a.0.max(b.0);
+    let top = a.1.max(b.1);
+    let right = a.0.saturating_add(a.2).min(b.0.saturating_add(b.2));
+    let bottom = a.1.saturating_add(a.3).min(b.1.saturating_add(b.3));
+
+    for y in top..bottom {
+        for x in left..right {
+            if opaque_a(x - a.0, y - a.1) && opaque_b(x - b.0, y - b.1) {
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pixels_overlap, rect_cells, rects_overlap};
+
+    #[test]
+    fn test_rects() {
+        assert!(rects_overlap((0.0, 0.0, 8.0, 8.0), (7.5, 7.5, 8.0, 8.0)));
+        // Touching edges don't collide
+        assert!(!rects_overlap((0.0, 0.0, 8.0, 8.0), (8.0, 0.0, 8.0, 8.0)));
+        assert!(!rects_overlap((0.0, 0.0, 0.0, 8.0), (0.0, 0.0, 8.0, 8.0)));
+
+        assert_eq!(rect_cells(0.0, 0.0, 8.0, 8.0), Some(((0, 0), (0, 0))));
+        assert_eq!(rect_cells(4.0, 7.0, 8.0, 2.0), Some(((0, 0), (1, 1))));
+        assert_eq!(rect_cells(-1.0, 8.0, 1.0, 0.5), Some(((-1, 1), (-1, 1))));
+        assert_eq!(rect_cells(0.0, 0.0, 8.0, 0.0), None);
+    }
+
+    #[test]
+    fn test_pixels_overlap() {
+        // A diagonal line against a point
+        let diagonal = |x: i32, y: i32| x == y;
+        let point = |x: i32, y: i32| x == 0 && y == 0;
+
+        assert!(pixels_overlap((0, 0, 8, 8), (3, 3, 1, 1), &diagonal, &point));
+        assert!(!pixels_overlap((0, 0, 8, 8), (3, 4, 1, 1), &diagonal, &point));
+        // The blocks overlap without their pixels
+        assert!(!pixels_overlap((0, 0, 8, 8), (4, 0, 8, 8), &diagonal, &diagonal));
+        assert!(pixels_overlap((0, 0, 8, 8), (-2, -2, 8, 8), &diagonal, &diagonal));
+        assert!(!pixels_overlap((0, 0, 8, 8), (8, 8, 8, 8), &diagonal, &diagonal));
+    }
+}
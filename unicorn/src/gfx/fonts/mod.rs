@@ -3,3 +3,4 @@ pub mod bbc;
 pub mod cbmii;
 pub mod appleii;
 pub mod trollmini;
+pub mod unicode;
@@ -0,0 +1,472 @@
+//! Glyphs after ASCII for `print`: Latin-1, Cyrillic and Kana in the style of
+//! the pico-8 font. The letters like the ASCII ones (Cyrillic A, full width
+//! forms ...) are drawn with the ASCII glyphs of the current font.
+
+use std::char;
+use std::collections::HashMap;
+
+use gfx::fonts::pico8;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Glyph {
+    pub width: i32,
+    /// Row of the accents above the capital letters, then the 7 rows of the
+    /// pico-8 glyphs. The pixels are the `width` low bits, leftmost first.
+    pub rows: [u8; 8],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mark {
+    Grave,
+    Acute,
+    Circumflex,
+    Tilde,
+    Diaeresis,
+    Ring,
+    Breve,
+    Cedilla,
+    Dakuten,
+    Handakuten,
+}
+
+impl Mark {
+    /// Pixels of the mark over a glyph of `width` pixels
+    fn row(&self, width: i32) -> u8 {
+        let left = 1 << (width - 1);
+        let all = ((1u16 << width) - 1) as u8;
+        match *self {
+            Mark::Grave => left,
+            Mark::Acute => 1,
+            Mark::Circumflex | Mark::Cedilla => 1 << (width / 2),
+            Mark::Tilde => left | left >> 1,
+            Mark::Diaeresis => left | 1,
+            Mark::Ring => all,
+            Mark::Breve => all & !(left | 1),
+            Mark::Dakuten => 0b101,
+            Mark::Handakuten => 0b011,
+        }
+    }
+}
+
+lazy_static! {
+    static ref GLYPHS: HashMap<char, Glyph> = build();
+}
+
+/// Glyph of a character after ASCII, none if the font doesn't have it
+pub fn glyph(c: char) -> Option<&'static Glyph> {
+    GLYPHS.get(&c)
+}
+
+/// Character drawn instead of `c`, an ASCII one for the same letters
+pub fn alias(c: char) -> char {
+    match c {
+        'А' => 'A',
+        'В' => 'B',
+        'Е' => 'E',
+        'З' => '3',
+        'К' => 'K',
+        'М' => 'M',
+        'Н' => 'H',
+        'О' => 'O',
+        'Р' => 'P',
+        'С' => 'C',
+        'Т' => 'T',
+        'Х' => 'X',
+        'а' => 'a',
+        'е' => 'e',
+        'о' => 'o',
+        'р' => 'p',
+        'с' => 'c',
+        'у' => 'y',
+        'х' => 'x',
+        '\u{ad}' | '‐' | '–' | '—' => '-',
+        '‘' | '’' => '\'',
+        '“' | '”' => '"',
+        // Small kana
+        'ぁ' | 'ぃ' | 'ぅ' | 'ぇ' | 'ぉ' | 'っ' | 'ゃ' | 'ゅ' | 'ょ' | 'ゎ' | 'ァ' | 'ィ' | 'ゥ' |
+        'ェ' | 'ォ' | 'ッ' | 'ャ' | 'ュ' | 'ョ' | 'ヮ' => char::from_u32(c as u32 + 1).unwrap_or(c),
+        'ヵ' => 'カ',
+        'ヶ' => 'ケ',
+        // Full width forms of ASCII
+        _ if c >= '！' && c <= '～' => char::from_u32(c as u32 - 0xfee0).unwrap_or(c),
+        _ => c,
+    }
+}
+
+/// ASCII glyph of the pico-8 font, base of the accented letters
+fn ascii_glyph(c: char) -> Option<Glyph> {
+    if c < ' ' || c > '~' {
+        return None;
+    }
+
+    let height = pico8::FONT.glyph_height as usize;
+    let start = (c as usize - 32) * height;
+    let mut rows = [0; 8];
+    for (i, row) in pico8::FONT.glyph_data[start..start + height].iter().enumerate() {
+        rows[i + 1] = *row >> 5;
+    }
+
+    Some(Glyph {
+             width: pico8::FONT.glyph_width,
+             rows: rows,
+         })
+}
+
+/// The mark in the free row above the base glyph, or below it for a cedilla
+fn compose(base: &Glyph, mark: Mark) -> Glyph {
+    let mut glyph = base.clone();
+
+    let used: Vec<usize> = (0..8).filter(|&i| base.rows[i] != 0).collect();
+    let row = match (used.first(), used.last()) {
+        (Some(_), Some(&last)) if mark == Mark::Cedilla => (last + 1).min(7),
+        (Some(&first), Some(_)) => first.max(1) - 1,
+        _ => 0,
+    };
+    glyph.rows[row] |= mark.row(glyph.width);
+
+    glyph
+}
+
+fn build() -> HashMap<char, Glyph> {
+    let mut glyphs = HashMap::new();
+
+    for &(c, width, rows) in DRAWN.iter() {
+        glyphs.insert(c,
+                      Glyph {
+                          width: width,
+                          rows: rows,
+                      });
+    }
+
+    for &(c, base, mark) in COMPOSED.iter() {
+        let base = match glyphs.get(&base).cloned().or_else(|| ascii_glyph(base)) {
+            Some(base) => base,
+            None => continue,
+        };
+        glyphs.insert(c, compose(&base, mark));
+    }
+
+    glyphs
+}
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+static DRAWN: [(char, i32, [u8; 8]); 179] = [
+    ('\u{a0}', 3, [0b000, 0b000, 0b000, 0b000, 0b000, 0b000, 0b000, 0b000]),
+    ('¡', 3, [0b000, 0b010, 0b000, 0b010, 0b010, 0b010, 0b000, 0b000]),
+    ('¢', 3, [0b000, 0b010, 0b011, 0b100, 0b011, 0b010, 0b000, 0b000]),
+    ('£', 3, [0b000, 0b011, 0b010, 0b111, 0b010, 0b111, 0b000, 0b000]),
+    ('¤', 3, [0b000, 0b000, 0b101, 0b010, 0b101, 0b000, 0b000, 0b000]),
+    ('¥', 3, [0b000, 0b101, 0b101, 0b010, 0b111, 0b010, 0b000, 0b000]),
+    ('¦', 3, [0b000, 0b010, 0b010, 0b000, 0b010, 0b010, 0b000, 0b000]),
+    ('§', 3, [0b000, 0b011, 0b110, 0b101, 0b011, 0b110, 0b000, 0b000]),
+    ('¨', 3, [0b000, 0b101, 0b000, 0b000, 0b000, 0b000, 0b000, 0b000]),
+    ('ª', 3, [0b000, 0b110, 0b011, 0b101, 0b011, 0b000, 0b000, 0b000]),
+    ('«', 4, [0b0000, 0b0000, 0b0101, 0b1010, 0b0101, 0b0000, 0b0000, 0b0000]),
+    ('¬', 3, [0b000, 0b000, 0b111, 0b001, 0b000, 0b000, 0b000, 0b000]),
+    ('¯', 3, [0b000, 0b111, 0b000, 0b000, 0b000, 0b000, 0b000, 0b000]),
+    ('°', 3, [0b000, 0b010, 0b101, 0b010, 0b000, 0b000, 0b000, 0b000]),
+    ('±', 3, [0b000, 0b010, 0b111, 0b010, 0b000, 0b111, 0b000, 0b000]),
+    ('²', 3, [0b000, 0b110, 0b010, 0b011, 0b000, 0b000, 0b000, 0b000]),
+    ('³', 3, [0b000, 0b110, 0b011, 0b110, 0b000, 0b000, 0b000, 0b000]),
+    ('´', 3, [0b000, 0b001, 0b010, 0b000, 0b000, 0b000, 0b000, 0b000]),
+    ('µ', 3, [0b000, 0b000, 0b000, 0b101, 0b101, 0b111, 0b100, 0b000]),
+    ('·', 3, [0b000, 0b000, 0b000, 0b010, 0b000, 0b000, 0b000, 0b000]),
+    ('¸', 3, [0b000, 0b000, 0b000, 0b000, 0b000, 0b000, 0b010, 0b110]),
+    ('¹', 3, [0b000, 0b110, 0b010, 0b010, 0b000, 0b000, 0b000, 0b000]),
+    ('º', 3, [0b000, 0b010, 0b101, 0b010, 0b000, 0b111, 0b000, 0b000]),
+    ('»', 4, [0b0000, 0b0000, 0b1010, 0b0101, 0b1010, 0b0000, 0b0000, 0b0000]),
+    ('¿', 3, [0b000, 0b010, 0b000, 0b110, 0b100, 0b111, 0b000, 0b000]),
+    ('×', 3, [0b000, 0b000, 0b101, 0b010, 0b101, 0b000, 0b000, 0b000]),
+    ('÷', 3, [0b000, 0b010, 0b000, 0b111, 0b000, 0b010, 0b000, 0b000]),
+    ('Æ', 5, [0b00000, 0b11111, 0b10100, 0b11110, 0b10100, 0b10111, 0b00000, 0b00000]),
+    ('Ð', 4, [0b0000, 0b0110, 0b0101, 0b1111, 0b0101, 0b0111, 0b0000, 0b0000]),
+    ('Ø', 3, [0b000, 0b011, 0b101, 0b111, 0b101, 0b110, 0b000, 0b000]),
+    ('Þ', 3, [0b000, 0b100, 0b111, 0b101, 0b111, 0b100, 0b000, 0b000]),
+    ('ß', 3, [0b000, 0b110, 0b101, 0b110, 0b101, 0b110, 0b100, 0b000]),
+    ('æ', 5, [0b00000, 0b00000, 0b00000, 0b01111, 0b10110, 0b01011, 0b00000, 0b00000]),
+    ('ð', 3, [0b000, 0b011, 0b111, 0b011, 0b101, 0b010, 0b000, 0b000]),
+    ('ø', 3, [0b000, 0b000, 0b000, 0b011, 0b111, 0b110, 0b000, 0b000]),
+    ('þ', 3, [0b000, 0b100, 0b100, 0b110, 0b101, 0b111, 0b100, 0b100]),
+    ('ı', 3, [0b000, 0b000, 0b000, 0b010, 0b010, 0b010, 0b000, 0b000]),
+    ('Б', 3, [0b000, 0b111, 0b100, 0b110, 0b101, 0b110, 0b000, 0b000]),
+    ('Г', 3, [0b000, 0b111, 0b100, 0b100, 0b100, 0b100, 0b000, 0b000]),
+    ('Д', 3, [0b000, 0b011, 0b101, 0b101, 0b101, 0b111, 0b101, 0b000]),
+    ('Ж', 5, [0b00000, 0b10101, 0b10101, 0b01110, 0b10101, 0b10101, 0b00000, 0b00000]),
+    ('И', 4, [0b0000, 0b1001, 0b1001, 0b1011, 0b1101, 0b1001, 0b0000, 0b0000]),
+    ('Л', 3, [0b000, 0b011, 0b101, 0b101, 0b101, 0b101, 0b000, 0b000]),
+    ('П', 3, [0b000, 0b111, 0b101, 0b101, 0b101, 0b101, 0b000, 0b000]),
+    ('У', 3, [0b000, 0b101, 0b101, 0b011, 0b001, 0b110, 0b000, 0b000]),
+    ('Ф', 5, [0b00000, 0b00100, 0b01110, 0b10101, 0b01110, 0b00100, 0b00000, 0b00000]),
+    ('Ц', 3, [0b000, 0b101, 0b101, 0b101, 0b101, 0b111, 0b001, 0b000]),
+    ('Ч', 3, [0b000, 0b101, 0b101, 0b111, 0b001, 0b001, 0b000, 0b000]),
+    ('Ш', 5, [0b00000, 0b10101, 0b10101, 0b10101, 0b10101, 0b11111, 0b00000, 0b00000]),
+    ('Щ', 5, [0b00000, 0b10101, 0b10101, 0b10101, 0b10101, 0b11111, 0b00001, 0b00000]),
+    ('Ъ', 4, [0b0000, 0b1100, 0b0100, 0b0110, 0b0101, 0b0110, 0b0000, 0b0000]),
+    ('Ы', 5, [0b00000, 0b10001, 0b10001, 0b11001, 0b10101, 0b11001, 0b00000, 0b00000]),
+    ('Ь', 3, [0b000, 0b100, 0b100, 0b110, 0b101, 0b110, 0b000, 0b000]),
+    ('Э', 3, [0b000, 0b110, 0b001, 0b011, 0b001, 0b110, 0b000, 0b000]),
+    ('Ю', 5, [0b00000, 0b10010, 0b10101, 0b11101, 0b10101, 0b10010, 0b00000, 0b00000]),
+    ('Я', 3, [0b000, 0b111, 0b101, 0b011, 0b101, 0b101, 0b000, 0b000]),
+    ('б', 3, [0b000, 0b011, 0b100, 0b111, 0b101, 0b111, 0b000, 0b000]),
+    ('в', 3, [0b000, 0b000, 0b000, 0b110, 0b111, 0b111, 0b000, 0b000]),
+    ('г', 3, [0b000, 0b000, 0b000, 0b111, 0b100, 0b100, 0b000, 0b000]),
+    ('д', 3, [0b000, 0b000, 0b000, 0b011, 0b101, 0b111, 0b101, 0b000]),
+    ('ж', 5, [0b00000, 0b00000, 0b00000, 0b10101, 0b01110, 0b10101, 0b00000, 0b00000]),
+    ('з', 3, [0b000, 0b000, 0b000, 0b110, 0b011, 0b110, 0b000, 0b000]),
+    ('и', 4, [0b0000, 0b0000, 0b0000, 0b1001, 0b1011, 0b1101, 0b0000, 0b0000]),
+    ('к', 3, [0b000, 0b000, 0b000, 0b101, 0b110, 0b101, 0b000, 0b000]),
+    ('л', 3, [0b000, 0b000, 0b000, 0b011, 0b101, 0b101, 0b000, 0b000]),
+    ('м', 5, [0b00000, 0b00000, 0b00000, 0b11011, 0b10101, 0b10001, 0b00000, 0b00000]),
+    ('н', 3, [0b000, 0b000, 0b000, 0b101, 0b111, 0b101, 0b000, 0b000]),
+    ('п', 3, [0b000, 0b000, 0b000, 0b111, 0b101, 0b101, 0b000, 0b000]),
+    ('т', 3, [0b000, 0b000, 0b000, 0b111, 0b010, 0b010, 0b000, 0b000]),
+    ('ф', 5, [0b00000, 0b00000, 0b00100, 0b01110, 0b10101, 0b01110, 0b00100, 0b00000]),
+    ('ц', 3, [0b000, 0b000, 0b000, 0b101, 0b101, 0b111, 0b001, 0b000]),
+    ('ч', 3, [0b000, 0b000, 0b000, 0b101, 0b111, 0b001, 0b000, 0b000]),
+    ('ш', 5, [0b00000, 0b00000, 0b00000, 0b10101, 0b10101, 0b11111, 0b00000, 0b00000]),
+    ('щ', 5, [0b00000, 0b00000, 0b00000, 0b10101, 0b10101, 0b11111, 0b00001, 0b00000]),
+    ('ъ', 4, [0b0000, 0b0000, 0b0000, 0b1100, 0b0111, 0b0111, 0b0000, 0b0000]),
+    ('ы', 5, [0b00000, 0b00000, 0b00000, 0b10001, 0b11101, 0b11101, 0b00000, 0b00000]),
+    ('ь', 3, [0b000, 0b000, 0b000, 0b100, 0b111, 0b111, 0b000, 0b000]),
+    ('э', 3, [0b000, 0b000, 0b000, 0b111, 0b011, 0b111, 0b000, 0b000]),
+    ('ю', 5, [0b00000, 0b00000, 0b00000, 0b10010, 0b11101, 0b10010, 0b00000, 0b00000]),
+    ('я', 3, [0b000, 0b000, 0b000, 0b111, 0b011, 0b101, 0b000, 0b000]),
+    ('ア', 5, [0b00000, 0b11111, 0b00001, 0b00110, 0b00100, 0b01000, 0b00000, 0b00000]),
+    ('イ', 5, [0b00000, 0b00001, 0b00010, 0b00110, 0b01010, 0b00010, 0b00000, 0b00000]),
+    ('ウ', 5, [0b00000, 0b00100, 0b11111, 0b10001, 0b00010, 0b00100, 0b00000, 0b00000]),
+    ('エ', 5, [0b00000, 0b01110, 0b00100, 0b00100, 0b00100, 0b11111, 0b00000, 0b00000]),
+    ('オ', 5, [0b00000, 0b00010, 0b11111, 0b00110, 0b01010, 0b10010, 0b00000, 0b00000]),
+    ('カ', 5, [0b00000, 0b01000, 0b11111, 0b01001, 0b01001, 0b10010, 0b00000, 0b00000]),
+    ('キ', 5, [0b00000, 0b01000, 0b11111, 0b00100, 0b11111, 0b00010, 0b00000, 0b00000]),
+    ('ク', 5, [0b00000, 0b01000, 0b01111, 0b10001, 0b00010, 0b01100, 0b00000, 0b00000]),
+    ('ケ', 5, [0b00000, 0b01000, 0b01111, 0b10010, 0b00010, 0b00100, 0b00000, 0b00000]),
+    ('コ', 5, [0b00000, 0b11111, 0b00001, 0b00001, 0b00001, 0b11111, 0b00000, 0b00000]),
+    ('サ', 5, [0b00000, 0b01010, 0b11111, 0b01010, 0b00010, 0b00100, 0b00000, 0b00000]),
+    ('シ', 5, [0b00000, 0b11000, 0b00001, 0b11001, 0b00010, 0b01100, 0b00000, 0b00000]),
+    ('ス', 5, [0b00000, 0b11111, 0b00001, 0b00010, 0b00110, 0b11001, 0b00000, 0b00000]),
+    ('セ', 5, [0b00000, 0b01000, 0b11111, 0b01001, 0b01000, 0b00111, 0b00000, 0b00000]),
+    ('ソ', 5, [0b00000, 0b10001, 0b01001, 0b00001, 0b00010, 0b01100, 0b00000, 0b00000]),
+    ('タ', 5, [0b00000, 0b01000, 0b01111, 0b10101, 0b00010, 0b01100, 0b00000, 0b00000]),
+    ('チ', 5, [0b00000, 0b00110, 0b11000, 0b11111, 0b00100, 0b01000, 0b00000, 0b00000]),
+    ('ツ', 5, [0b00000, 0b10101, 0b10101, 0b00001, 0b00010, 0b01100, 0b00000, 0b00000]),
+    ('テ', 5, [0b00000, 0b11110, 0b00000, 0b11111, 0b00100, 0b01000, 0b00000, 0b00000]),
+    ('ト', 5, [0b00000, 0b01000, 0b01000, 0b01100, 0b01010, 0b01000, 0b00000, 0b00000]),
+    ('ナ', 5, [0b00000, 0b00100, 0b11111, 0b00100, 0b00100, 0b01000, 0b00000, 0b00000]),
+    ('ニ', 5, [0b00000, 0b00000, 0b01110, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000]),
+    ('ヌ', 5, [0b00000, 0b11111, 0b00001, 0b01010, 0b00100, 0b10010, 0b00000, 0b00000]),
+    ('ネ', 5, [0b00000, 0b00100, 0b11111, 0b00010, 0b01110, 0b10101, 0b00000, 0b00000]),
+    ('ノ', 5, [0b00000, 0b00001, 0b00010, 0b00010, 0b00100, 0b11000, 0b00000, 0b00000]),
+    ('ハ', 5, [0b00000, 0b00000, 0b01010, 0b01001, 0b10001, 0b10001, 0b00000, 0b00000]),
+    ('ヒ', 5, [0b00000, 0b10000, 0b10011, 0b11100, 0b10000, 0b01111, 0b00000, 0b00000]),
+    ('フ', 5, [0b00000, 0b11111, 0b00001, 0b00001, 0b00010, 0b01100, 0b00000, 0b00000]),
+    ('ヘ', 5, [0b00000, 0b00000, 0b01000, 0b10100, 0b00010, 0b00001, 0b00000, 0b00000]),
+    ('ホ', 5, [0b00000, 0b00100, 0b11111, 0b00100, 0b10101, 0b00100, 0b00000, 0b00000]),
+    ('マ', 5, [0b00000, 0b11111, 0b00001, 0b01010, 0b00100, 0b00010, 0b00000, 0b00000]),
+    ('ミ', 5, [0b00000, 0b01100, 0b00010, 0b01100, 0b00010, 0b11110, 0b00000, 0b00000]),
+    ('ム', 5, [0b00000, 0b00100, 0b00100, 0b01000, 0b01010, 0b11111, 0b00000, 0b00000]),
+    ('メ', 5, [0b00000, 0b00001, 0b01010, 0b00100, 0b01010, 0b10000, 0b00000, 0b00000]),
+    ('モ', 5, [0b00000, 0b11111, 0b00100, 0b11111, 0b00100, 0b00111, 0b00000, 0b00000]),
+    ('ヤ', 5, [0b00000, 0b01000, 0b11111, 0b01010, 0b00100, 0b00100, 0b00000, 0b00000]),
+    ('ユ', 5, [0b00000, 0b01110, 0b00010, 0b00010, 0b00010, 0b11111, 0b00000, 0b00000]),
+    ('ヨ', 5, [0b00000, 0b11111, 0b00001, 0b01111, 0b00001, 0b11111, 0b00000, 0b00000]),
+    ('ラ', 5, [0b00000, 0b01110, 0b00000, 0b11111, 0b00010, 0b01100, 0b00000, 0b00000]),
+    ('リ', 5, [0b00000, 0b10001, 0b10001, 0b10001, 0b00010, 0b00100, 0b00000, 0b00000]),
+    ('ル', 5, [0b00000, 0b01010, 0b01010, 0b01010, 0b01011, 0b10010, 0b00000, 0b00000]),
+    ('レ', 5, [0b00000, 0b10000, 0b10000, 0b10001, 0b10010, 0b11100, 0b00000, 0b00000]),
+    ('ロ', 5, [0b00000, 0b11111, 0b10001, 0b10001, 0b10001, 0b11111, 0b00000, 0b00000]),
+    ('ワ', 5, [0b00000, 0b11111, 0b10001, 0b00001, 0b00010, 0b01100, 0b00000, 0b00000]),
+    ('ヲ', 5, [0b00000, 0b11111, 0b00001, 0b11111, 0b00010, 0b01100, 0b00000, 0b00000]),
+    ('ン', 5, [0b00000, 0b11000, 0b00001, 0b00001, 0b00010, 0b11100, 0b00000, 0b00000]),
+    ('・', 5, [0b00000, 0b00000, 0b00000, 0b00100, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('ー', 5, [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('あ', 5, [0b00000, 0b01000, 0b11111, 0b01110, 0b10101, 0b01101, 0b00000, 0b00000]),
+    ('い', 5, [0b00000, 0b10000, 0b10010, 0b10001, 0b10001, 0b01000, 0b00000, 0b00000]),
+    ('う', 5, [0b00000, 0b01110, 0b00000, 0b01110, 0b00001, 0b00110, 0b00000, 0b00000]),
+    ('え', 5, [0b00000, 0b01110, 0b00000, 0b11111, 0b00100, 0b01011, 0b00000, 0b00000]),
+    ('お', 5, [0b00000, 0b01000, 0b11110, 0b01001, 0b01110, 0b11010, 0b00000, 0b00000]),
+    ('か', 5, [0b00000, 0b01000, 0b11111, 0b01010, 0b01001, 0b10010, 0b00000, 0b00000]),
+    ('き', 5, [0b00000, 0b01000, 0b11110, 0b00100, 0b11111, 0b01110, 0b00000, 0b00000]),
+    ('く', 5, [0b00000, 0b00010, 0b00100, 0b01000, 0b00100, 0b00010, 0b00000, 0b00000]),
+    ('け', 5, [0b00000, 0b10010, 0b11111, 0b10010, 0b10010, 0b10100, 0b00000, 0b00000]),
+    ('こ', 5, [0b00000, 0b01110, 0b00000, 0b00000, 0b10000, 0b01111, 0b00000, 0b00000]),
+    ('さ', 5, [0b00000, 0b00100, 0b11111, 0b00010, 0b10000, 0b01110, 0b00000, 0b00000]),
+    ('し', 5, [0b00000, 0b01000, 0b01000, 0b01000, 0b01001, 0b00110, 0b00000, 0b00000]),
+    ('す', 5, [0b00000, 0b00100, 0b11111, 0b00110, 0b00110, 0b01000, 0b00000, 0b00000]),
+    ('せ', 5, [0b00000, 0b01010, 0b11111, 0b01010, 0b01000, 0b00111, 0b00000, 0b00000]),
+    ('そ', 5, [0b00000, 0b11110, 0b00100, 0b11111, 0b01000, 0b00110, 0b00000, 0b00000]),
+    ('た', 5, [0b00000, 0b01000, 0b11100, 0b01011, 0b10000, 0b10011, 0b00000, 0b00000]),
+    ('ち', 5, [0b00000, 0b01000, 0b11111, 0b01000, 0b01111, 0b00010, 0b00000, 0b00000]),
+    ('つ', 5, [0b00000, 0b00000, 0b11110, 0b00001, 0b00001, 0b01110, 0b00000, 0b00000]),
+    ('て', 5, [0b00000, 0b11111, 0b00100, 0b01000, 0b01000, 0b00111, 0b00000, 0b00000]),
+    ('と', 5, [0b00000, 0b01000, 0b01010, 0b01100, 0b01000, 0b00111, 0b00000, 0b00000]),
+    ('な', 5, [0b00000, 0b01000, 0b11101, 0b01000, 0b10110, 0b00111, 0b00000, 0b00000]),
+    ('に', 5, [0b00000, 0b10000, 0b10110, 0b10000, 0b10100, 0b10011, 0b00000, 0b00000]),
+    ('ぬ', 5, [0b00000, 0b01000, 0b11110, 0b11010, 0b10101, 0b01011, 0b00000, 0b00000]),
+    ('ね', 5, [0b00000, 0b01000, 0b11110, 0b11010, 0b01001, 0b01011, 0b00000, 0b00000]),
+    ('の', 5, [0b00000, 0b01110, 0b10101, 0b10101, 0b10010, 0b01000, 0b00000, 0b00000]),
+    ('は', 5, [0b00000, 0b10010, 0b11111, 0b10010, 0b10110, 0b10111, 0b00000, 0b00000]),
+    ('ひ', 5, [0b00000, 0b11010, 0b01001, 0b10001, 0b10001, 0b01110, 0b00000, 0b00000]),
+    ('ふ', 5, [0b00000, 0b00100, 0b00010, 0b00100, 0b10101, 0b10001, 0b00000, 0b00000]),
+    ('へ', 5, [0b00000, 0b00000, 0b01000, 0b10100, 0b00010, 0b00001, 0b00000, 0b00000]),
+    ('ほ', 5, [0b00000, 0b10111, 0b10010, 0b10111, 0b10010, 0b10111, 0b00000, 0b00000]),
+    ('ま', 5, [0b00000, 0b00100, 0b11111, 0b00100, 0b01110, 0b10110, 0b00000, 0b00000]),
+    ('み', 5, [0b00000, 0b11100, 0b00100, 0b01111, 0b10101, 0b01001, 0b00000, 0b00000]),
+    ('む', 5, [0b00000, 0b01000, 0b11110, 0b01001, 0b11000, 0b01110, 0b00000, 0b00000]),
+    ('め', 5, [0b00000, 0b01010, 0b01110, 0b11011, 0b10101, 0b01010, 0b00000, 0b00000]),
+    ('も', 5, [0b00000, 0b01000, 0b11110, 0b01000, 0b11110, 0b00110, 0b00000, 0b00000]),
+    ('や', 5, [0b00000, 0b00100, 0b10111, 0b01001, 0b01010, 0b00100, 0b00000, 0b00000]),
+    ('ゆ', 5, [0b00000, 0b10100, 0b11111, 0b10101, 0b11101, 0b00110, 0b00000, 0b00000]),
+    ('よ', 5, [0b00000, 0b00100, 0b00111, 0b00100, 0b01110, 0b10101, 0b00000, 0b00000]),
+    ('ら', 5, [0b00000, 0b01100, 0b10000, 0b10110, 0b11001, 0b00110, 0b00000, 0b00000]),
+    ('り', 5, [0b00000, 0b10010, 0b10001, 0b10001, 0b00010, 0b00100, 0b00000, 0b00000]),
+    ('る', 5, [0b00000, 0b11110, 0b00100, 0b01110, 0b10001, 0b01101, 0b00000, 0b00000]),
+    ('れ', 5, [0b00000, 0b01000, 0b11110, 0b01010, 0b01101, 0b11001, 0b00000, 0b00000]),
+    ('ろ', 5, [0b00000, 0b11110, 0b00100, 0b01110, 0b00001, 0b01110, 0b00000, 0b00000]),
+    ('わ', 5, [0b00000, 0b01000, 0b11110, 0b01101, 0b01001, 0b11010, 0b00000, 0b00000]),
+    ('を', 5, [0b00000, 0b01000, 0b11110, 0b01100, 0b10110, 0b00111, 0b00000, 0b00000]),
+    ('ん', 5, [0b00000, 0b00100, 0b01000, 0b01100, 0b10010, 0b10001, 0b00000, 0b00000]),
+    ('、', 3, [0b000, 0b000, 0b000, 0b000, 0b100, 0b010, 0b000, 0b000]),
+    ('。', 3, [0b000, 0b000, 0b000, 0b010, 0b101, 0b010, 0b000, 0b000]),
+    ('「', 3, [0b000, 0b111, 0b100, 0b100, 0b100, 0b100, 0b000, 0b000]),
+    ('」', 3, [0b000, 0b001, 0b001, 0b001, 0b001, 0b111, 0b000, 0b000]),
+    ('\u{3000}', 5, [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000]),
+];
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+static COMPOSED: [(char, char, Mark); 108] = [
+    ('À', 'A', Mark::Grave),
+    ('Á', 'A', Mark::Acute),
+    ('Â', 'A', Mark::Circumflex),
+    ('Ã', 'A', Mark::Tilde),
+    ('Ä', 'A', Mark::Diaeresis),
+    ('Å', 'A', Mark::Ring),
+    ('à', 'a', Mark::Grave),
+    ('á', 'a', Mark::Acute),
+    ('â', 'a', Mark::Circumflex),
+    ('ã', 'a', Mark::Tilde),
+    ('ä', 'a', Mark::Diaeresis),
+    ('å', 'a', Mark::Ring),
+    ('Ç', 'C', Mark::Cedilla),
+    ('ç', 'c', Mark::Cedilla),
+    ('È', 'E', Mark::Grave),
+    ('É', 'E', Mark::Acute),
+    ('Ê', 'E', Mark::Circumflex),
+    ('Ë', 'E', Mark::Diaeresis),
+    ('Ì', 'I', Mark::Grave),
+    ('Í', 'I', Mark::Acute),
+    ('Î', 'I', Mark::Circumflex),
+    ('Ï', 'I', Mark::Diaeresis),
+    ('è', 'e', Mark::Grave),
+    ('é', 'e', Mark::Acute),
+    ('ê', 'e', Mark::Circumflex),
+    ('ë', 'e', Mark::Diaeresis),
+    ('ì', 'ı', Mark::Grave),
+    ('í', 'ı', Mark::Acute),
+    ('î', 'ı', Mark::Circumflex),
+    ('ï', 'ı', Mark::Diaeresis),
+    ('Ñ', 'N', Mark::Tilde),
+    ('ñ', 'n', Mark::Tilde),
+    ('Ò', 'O', Mark::Grave),
+    ('Ó', 'O', Mark::Acute),
+    ('Ô', 'O', Mark::Circumflex),
+    ('Õ', 'O', Mark::Tilde),
+    ('Ö', 'O', Mark::Diaeresis),
+    ('ò', 'o', Mark::Grave),
+    ('ó', 'o', Mark::Acute),
+    ('ô', 'o', Mark::Circumflex),
+    ('õ', 'o', Mark::Tilde),
+    ('ö', 'o', Mark::Diaeresis),
+    ('Ù', 'U', Mark::Grave),
+    ('Ú', 'U', Mark::Acute),
+    ('Û', 'U', Mark::Circumflex),
+    ('Ü', 'U', Mark::Diaeresis),
+    ('ù', 'u', Mark::Grave),
+    ('ú', 'u', Mark::Acute),
+    ('û', 'u', Mark::Circumflex),
+    ('ü', 'u', Mark::Diaeresis),
+    ('Ý', 'Y', Mark::Acute),
+    ('ý', 'y', Mark::Acute),
+    ('ÿ', 'y', Mark::Diaeresis),
+    ('Ё', 'E', Mark::Diaeresis),
+    ('ё', 'e', Mark::Diaeresis),
+    ('Й', 'И', Mark::Breve),
+    ('й', 'и', Mark::Breve),
+    ('が', 'か', Mark::Dakuten),
+    ('ぎ', 'き', Mark::Dakuten),
+    ('ぐ', 'く', Mark::Dakuten),
+    ('げ', 'け', Mark::Dakuten),
+    ('ご', 'こ', Mark::Dakuten),
+    ('ざ', 'さ', Mark::Dakuten),
+    ('じ', 'し', Mark::Dakuten),
+    ('ず', 'す', Mark::Dakuten),
+    ('ぜ', 'せ', Mark::Dakuten),
+    ('ぞ', 'そ', Mark::Dakuten),
+    ('だ', 'た', Mark::Dakuten),
+    ('ぢ', 'ち', Mark::Dakuten),
+    ('づ', 'つ', Mark::Dakuten),
+    ('で', 'て', Mark::Dakuten),
+    ('ど', 'と', Mark::Dakuten),
+    ('ば', 'は', Mark::Dakuten),
+    ('び', 'ひ', Mark::Dakuten),
+    ('ぶ', 'ふ', Mark::Dakuten),
+    ('べ', 'へ', Mark::Dakuten),
+    ('ぼ', 'ほ', Mark::Dakuten),
+    ('ぱ', 'は', Mark::Handakuten),
+    ('ぴ', 'ひ', Mark::Handakuten),
+    ('ぷ', 'ふ', Mark::Handakuten),
+    ('ぺ', 'へ', Mark::Handakuten),
+    ('ぽ', 'ほ', Mark::Handakuten),
+    ('ガ', 'カ', Mark::Dakuten),
+    ('ギ', 'キ', Mark::Dakuten),
+    ('グ', 'ク', Mark::Dakuten),
+    ('ゲ', 'ケ', Mark::Dakuten),
+    ('ゴ', 'コ', Mark::Dakuten),
+    ('ザ', 'サ', Mark::Dakuten),
+    ('ジ', 'シ', Mark::Dakuten),
+    ('ズ', 'ス', Mark::Dakuten),
+    ('ゼ', 'セ', Mark::Dakuten),
+    ('ゾ', 'ソ', Mark::Dakuten),
+    ('ダ', 'タ', Mark::Dakuten),
+    ('ヂ', 'チ', Mark::Dakuten),
+    ('ヅ', 'ツ', Mark::Dakuten),
+    ('デ', 'テ', Mark::Dakuten),
+    ('ド', 'ト', Mark::Dakuten),
+    ('バ', 'ハ', Mark::Dakuten),
+    ('ビ', 'ヒ', Mark::Dakuten),
+    ('ブ', 'フ', Mark::Dakuten),
+    ('ベ', 'ヘ', Mark::Dakuten),
+    ('ボ', 'ホ', Mark::Dakuten),
+    ('パ', 'ハ', Mark::Handakuten),
+    ('ピ', 'ヒ', Mark::Handakuten),
+    ('プ', 'フ', Mark::Handakuten),
+    ('ペ', 'ヘ', Mark::Handakuten),
+    ('ポ', 'ホ', Mark::Handakuten),
+    ('ヴ', 'ウ', Mark::Dakuten),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{alias, glyph};
+
+    #[test]
+    fn test_unicode_glyphs() {
+        // The accent of a capital letter is in the first row
+        let e = glyph('É').unwrap();
+        assert_eq!(e.rows[0], 0b001);
+        assert_eq!(&e.rows[1..], &[0b111, 0b100, 0b110, 0b100, 0b111, 0, 0]);
+        // Above the small letters, the cedilla under them
+        assert_eq!(glyph('é').unwrap().rows[2], 0b001);
+        assert_eq!(glyph('ç').unwrap().rows[6], 0b010);
+
+        assert_eq!(glyph('Ж').unwrap().width, 5);
+        assert_eq!(glyph('й').unwrap().rows[2], 0b0110);
+        assert_eq!(glyph('ガ').unwrap().rows[0], 0b101);
+        assert_eq!(glyph('ポ').unwrap().rows[0], 0b011);
+        assert!(glyph('€').is_none());
+
+        assert_eq!(alias('Р'), 'P');
+        assert_eq!(alias('ッ'), 'ツ');
+        assert_eq!(alias('Ａ'), 'A');
+        assert_eq!(alias('ß'), 'ß');
+    }
+}
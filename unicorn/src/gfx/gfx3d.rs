@@ -0,0 +1,564 @@
+//! Low-poly 3D of the cartridges: the meshes and the triangles drawn with
+//! `draw3d`/`tri3d` are moved by the matrix stack (`translate3d`,
+//! `rotate3d`, `scale3d`, `push3d`/`pop3d`), seen from `camera3d`, lit by
+//! the direction of `light3d` and queued; `render3d()` draws the queue with
+//! one shade per triangle, dithered toward a darker color. The triangles are
+//! sorted from the farthest one, or tested against a depth buffer of the
+//! screen with `mode3d("zbuffer")`.
+//!
+//! The space is right-handed, y up, and the camera looks toward its target.
+//! The front faces are counter-clockwise, the back ones aren't drawn unless
+//! the culling is disabled. The angles are in turns, like `cos`/`sin`.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use gfx::raster::{Bounds, Fill};
+
+/// Row-major, the points are column vectors: p' = m * p
+pub type Mat4 = [f64; 16];
+
+/// Matrices pushed without their pop
+pub const MAX_STACK: usize = 32;
+pub const MAX_MESHES: usize = 256;
+/// Triangles queued between two `render3d`, the next ones are dropped
+pub const MAX_TRIANGLES: usize = 16384;
+
+/// Darker color of each color, for the shading (the dark palette of the
+/// PICO-8 community)
+pub const DARKER: [u32; 16] = [0, 0, 1, 1, 2, 1, 5, 6, 2, 4, 9, 3, 1, 1, 2, 5];
+
+// Ordered dithering of the shades
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+pub fn identity() -> Mat4 {
+    [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0]
+}
+
+pub fn multiply(a: &Mat4, b: &Mat4) -> Mat4 {
+    let mut m = [0.0; 16];
+    for row in 0..4 {
+        for col in 0..4 {
+            m[row * 4 + col] = (0..4).map(|k| a[row * 4 + k] * b[k * 4 + col]).sum();
+        }
+    }
+    m
+}
+
+pub fn translation(x: f64, y: f64, z: f64) -> Mat4 {
+    [1.0, 0.0, 0.0, x, 0.0, 1.0, 0.0, y, 0.0, 0.0, 1.0, z, 0.0, 0.0, 0.0, 1.0]
+}
+
+pub fn scaling(x: f64, y: f64, z: f64) -> Mat4 {
+    [x, 0.0, 0.0, 0.0, 0.0, y, 0.0, 0.0, 0.0, 0.0, z, 0.0, 0.0, 0.0, 0.0, 1.0]
+}
+
+/// Rotation around the x axis, then y, then z
+pub fn rotation(x: f64, y: f64, z: f64) -> Mat4 {
+    let (sx, cx) = (x * 2.0 * PI).sin_cos();
+    let (sy, cy) = (y * 2.0 * PI).sin_cos();
+    let (sz, cz) = (z * 2.0 * PI).sin_cos();
+
+    let rx = [1.0, 0.0, 0.0, 0.0, 0.0, cx, -sx, 0.0, 0.0, sx, cx, 0.0, 0.0, 0.0, 0.0, 1.0];
+    let ry = [cy, 0.0, sy, 0.0, 0.0, 1.0, 0.0, 0.0, -sy, 0.0, cy, 0.0, 0.0, 0.0, 0.0, 1.0];
+    let rz = [cz, -sz, 0.0, 0.0, sz, cz, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+    multiply(&rz, &multiply(&ry, &rx))
+}
+
+pub fn transform(m: &Mat4, p: [f64; 3]) -> [f64; 3] {
+    [m[0] * p[0] + m[1] * p[1] + m[2] * p[2] + m[3],
+     m[4] * p[0] + m[5] * p[1] + m[6] * p[2] + m[7],
+     m[8] * p[0] + m[9] * p[1] + m[10] * p[2] + m[11]]
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn normalize(a: [f64; 3]) -> [f64; 3] {
+    let len = dot(a, a).sqrt();
+    if len > 0.0 { [a[0] / len, a[1] / len, a[2] / len] } else { a }
+}
+
+/// View of the camera at `eye` looking toward `target`, y up
+pub fn look_at(eye: [f64; 3], target: [f64; 3]) -> Mat4 {
+    let forward = normalize(sub(target, eye));
+    let mut up = [0.0, 1.0, 0.0];
+    if cross(forward, up) == [0.0, 0.0, 0.0] {
+        // Looking straight up or down
+        up = [0.0, 0.0, -1.0];
+    }
+    let right = normalize(cross(forward, up));
+    let up = cross(right, forward);
+
+    [right[0], right[1], right[2], -dot(right, eye),
+     up[0], up[1], up[2], -dot(up, eye),
+     -forward[0], -forward[1], -forward[2], dot(forward, eye),
+     0.0, 0.0, 0.0, 1.0]
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DepthMode {
+    /// Painter's algorithm, the farthest triangles first
+    Sort,
+    /// Depth of each pixel, for the triangles crossing each other
+    Buffer,
+}
+
+impl DepthMode {
+    pub fn from_name(name: &str) -> Option<DepthMode> {
+        match name {
+            "sort" => Some(DepthMode::Sort),
+            "zbuffer" => Some(DepthMode::Buffer),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mesh {
+    pub vertices: Vec<[f64; 3]>,
+    /// Indexes of the vertices, from 0, and color
+    pub faces: Vec<([usize; 3], u32)>,
+}
+
+/// Triangle projected on the screen, x/y in pixels and the distance to the
+/// camera
+#[derive(Clone, Debug, PartialEq)]
+pub struct Triangle {
+    pub points: [[f64; 3]; 3],
+    pub color: u32,
+    /// 0 in the shadow, 1 facing the light
+    pub light: f64,
+}
+
+impl Triangle {
+    fn depth(&self) -> f64 {
+        (self.points[0][2] + self.points[1][2] + self.points[2][2]) / 3.0
+    }
+
+    /// Color of the shade, the darker color dithered over it
+    pub fn fill(&self, color_map: &[u32]) -> Fill {
+        let color = self.color & 0xff;
+        let darker = DARKER[(color & 0xf) as usize];
+        let dark = ((1.0 - self.light.max(0.0).min(1.0)) * 16.0).round() as u8;
+
+        let mut pattern = 0;
+        for y in 0..4 {
+            for x in 0..4 {
+                if BAYER[y][x] < dark {
+                    pattern |= 1 << (15 - (y * 4 + x));
+                }
+            }
+        }
+
+        Fill {
+            color: color_map[color as usize],
+            pattern: pattern,
+            secondary: Some(color_map[darker as usize]),
+        }
+    }
+}
+
+pub struct Gfx3d {
+    /// The last one moves the triangles, never empty
+    pub stack: Vec<Mat4>,
+    pub view: Mat4,
+    /// Vertical field of view in turns
+    pub fov: f64,
+    /// The triangles are cut at this distance in front of the camera
+    pub near: f64,
+    /// Direction toward the light, in the world
+    pub light: [f64; 3],
+    pub ambient: f64,
+    pub mode: DepthMode,
+    pub cull: bool,
+    next_id: u32,
+    pub meshes: HashMap<u32, Mesh>,
+    pub queue: Vec<Triangle>,
+    depth: Vec<f32>,
+}
+
+impl Gfx3d {
+    pub fn new() -> Gfx3d {
+        Gfx3d {
+            stack: vec![identity()],
+            view: look_at([0.0, 0.0, 5.0], [0.0, 0.0, 0.0]),
+            fov: 1.0 / 6.0,
+            near: 0.1,
+            light: normalize([0.5, 1.0, 0.75]),
+            ambient: 0.3,
+            mode: DepthMode::Sort,
+            cull: true,
+            next_id: 1,
+            meshes: HashMap::new(),
+            queue: Vec::new(),
+            depth: Vec::new(),
+        }
+    }
+
+    /// Nothing of the last cartridge
+    pub fn clear(&mut self) {
+        *self = Gfx3d::new();
+    }
+
+    pub fn push(&mut self) -> bool {
+        if self.stack.len() >= MAX_STACK {
+            warn!("[GFX3D] Too many matrices, pop3d is missing");
+            return false;
+        }
+        let top = self.top();
+        self.stack.push(top);
+        true
+    }
+
+    pub fn pop(&mut self) {
+        if self.stack.len() > 1 {
+            self.stack.pop();
+        }
+    }
+
+    pub fn top(&self) -> Mat4 {
+        *self.stack.last().unwrap()
+    }
+
+    pub fn set_identity(&mut self) {
+        *self.stack.last_mut().unwrap() = identity();
+    }
+
+    /// The next triangles are moved by `m`, then by the current matrix
+    pub fn apply(&mut self, m: &Mat4) {
+        let top = multiply(&self.top(), m);
+        *self.stack.last_mut().unwrap() = top;
+    }
+
+    pub fn camera(&mut self, eye: [f64; 3], target: [f64; 3]) {
+        self.view = look_at(eye, target);
+    }
+
+    pub fn perspective(&mut self, fov: f64, near: f64) {
+        self.fov = fov.max(0.01).min(0.45);
+        self.near = near.max(0.001);
+    }
+
+    pub fn set_light(&mut self, x: f64, y: f64, z: f64, ambient: f64) {
+        self.light = normalize([x, y, z]);
+        self.ambient = ambient.max(0.0).min(1.0);
+    }
+
+    pub fn mesh_new(&mut self, mesh: Mesh) -> Option<u32> {
+        if self.meshes.len() >= MAX_MESHES {
+            warn!("[GFX3D] No more than {} meshes", MAX_MESHES);
+            return None;
+        }
+        if mesh.faces.iter().any(|&(face, _)| face.iter().any(|idx| *idx >= mesh.vertices.len())) {
+            warn!("[GFX3D] A face uses a missing vertex");
+            return None;
+        }
+
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        self.meshes.insert(id, mesh);
+        Some(id)
+    }
+
+    pub fn mesh_del(&mut self, id: u32) {
+        self.meshes.remove(&id);
+    }
+
+    /// Queue the faces of the mesh with the current matrix
+    pub fn draw(&mut self, id: u32, width: usize, height: usize) {
+        let mesh = match self.meshes.get(&id) {
+            Some(mesh) => mesh.clone(),
+            None => return,
+        };
+
+        let m = multiply(&self.view, &self.top());
+        let vertices: Vec<[f64; 3]> = mesh.vertices.iter().map(|p| transform(&m, *p)).collect();
+        for &(face, color) in &mesh.faces {
+            self.queue_view([vertices[face[0]], vertices[face[1]], vertices[face[2]]],
+                            color,
+                            width,
+                            height);
+        }
+    }
+
+    /// Queue one triangle with the current matrix
+    pub fn triangle(&mut self, points: [[f64; 3]; 3], color: u32, width: usize, height: usize) {
+        let m = multiply(&self.view, &self.top());
+        let points = [transform(&m, points[0]), transform(&m, points[1]), transform(&m, points[2])];
+        self.queue_view(points, color, width, height);
+    }
+
+    /// Triangle in the space of the camera, which looks toward -z
+    fn queue_view(&mut self, points: [[f64; 3]; 3], color: u32, width: usize, height: usize) {
+        if self.queue.len() >= MAX_TRIANGLES {
+            return;
+        }
+        let polygon = clip_near(&points, self.near);
+        if polygon.len() < 3 {
+            return;
+        }
+
+        let normal = normalize(cross(sub(points[1], points[0]), sub(points[2], points[0])));
+        let front = dot(normal, points[0]) < 0.0;
+        if self.cull && !front {
+            return;
+        }
+        let normal = if front { normal } else { [-normal[0], -normal[1], -normal[2]] };
+
+        // The light turns with the camera
+        let v = &self.view;
+        let light = [v[0] * self.light[0] + v[1] * self.light[1] + v[2] * self.light[2],
+                     v[4] * self.light[0] + v[5] * self.light[1] + v[6] * self.light[2],
+                     v[8] * self.light[0] + v[9] * self.light[1] + v[10] * self.light[2]];
+        let diffuse = dot(normal, light).max(0.0);
+
+        let focal = (height as f64 / 2.0) / (self.fov * PI).tan();
+        let project = |p: [f64; 3]| {
+            [width as f64 / 2.0 + p[0] * focal / -p[2],
+             height as f64 / 2.0 - p[1] * focal / -p[2],
+             -p[2]]
+        };
+
+        // The part in front of the near plane, a fan of 1 or 2 triangles
+        let light = self.ambient + (1.0 - self.ambient) * diffuse;
+        for idx in 1..polygon.len() - 1 {
+            if self.queue.len() >= MAX_TRIANGLES {
+                return;
+            }
+            self.queue.push(Triangle {
+                                points: [project(polygon[0]),
+                                         project(polygon[idx]),
+                                         project(polygon[idx + 1])],
+                                color: color,
+                                light: light,
+                            });
+        }
+    }
+
+    /// The queued triangles in the drawing order, the queue is emptied
+    pub fn take_queue(&mut self) -> Vec<Triangle> {
+        let mut queue = Vec::new();
+        ::std::mem::swap(&mut queue, &mut self.queue);
+
+        if self.mode == DepthMode::Sort {
+            queue.sort_by(|a, b| b.depth().partial_cmp(&a.depth()).unwrap_or(::std::cmp::Ordering::Equal));
+        }
+        queue
+    }
+
+    /// Depth buffer of the screen, cleared
+    pub fn depth_buffer(&mut self, len: usize) -> &mut [f32] {
+        self.depth.clear();
+        self.depth.resize(len, ::std::f32::INFINITY);
+        &mut self.depth
+    }
+}
+
+/// Polygon of the triangle (in the space of the camera) in front of the near
+/// plane, the edges crossing it are cut
+fn clip_near(points: &[[f64; 3]; 3], near: f64) -> Vec<[f64; 3]> {
+    let mut polygon = Vec::with_capacity(4);
+
+    for idx in 0..3 {
+        let a = points[idx];
+        let b = points[(idx + 1) % 3];
+        let (da, db) = (-a[2] - near, -b[2] - near);
+
+        if da >= 0.0 {
+            polygon.push(a);
+        }
+        if (da >= 0.0) != (db >= 0.0) {
+            let t = da / (da - db);
+            polygon.push([a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t, -near]);
+        }
+    }
+
+    polygon
+}
+
+/// Triangle of the screen with a depth test, the pixels whose center is
+/// inside are drawn. The depth is interpolated with its inverse, which is
+/// linear on the screen
+pub fn fill_triangle_depth(frame_buffer: &mut [u32],
+                           depth: &mut [f32],
+                           width: usize,
+                           bounds: Bounds,
+                           points: [[f64; 3]; 3],
+                           fill: Fill) {
+    let [a, b, c] = points;
+    if a[2] <= 0.0 || b[2] <= 0.0 || c[2] <= 0.0 {
+        return;
+    }
+    let area = (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0]);
+    if area == 0.0 {
+        return;
+    }
+
+    let min_x = a[0].min(b[0]).min(c[0]).floor().max(bounds.left as f64) as i32;
+    let max_x = a[0].max(b[0]).max(c[0]).ceil().min(bounds.right as f64) as i32;
+    let min_y = a[1].min(b[1]).min(c[1]).floor().max(bounds.top as f64) as i32;
+    let max_y = a[1].max(b[1]).max(c[1]).ceil().min(bounds.bottom as f64) as i32;
+
+    for y in min_y..max_y {
+        let py = y as f64 + 0.5;
+        for x in min_x..max_x {
+            let px = x as f64 + 0.5;
+            let w0 = ((b[0] - px) * (c[1] - py) - (b[1] - py) * (c[0] - px)) / area;
+            let w1 = ((c[0] - px) * (a[1] - py) - (c[1] - py) * (a[0] - px)) / area;
+            let w2 = 1.0 - w0 - w1;
+            if w0 < 0.0 || w1 < 0.0 || w2 < 0.0 {
+                continue;
+            }
+
+            let z = (1.0 / (w0 / a[2] + w1 / b[2] + w2 / c[2])) as f32;
+            let offset = x as usize + y as usize * width;
+            if z >= depth[offset] {
+                continue;
+            }
+            if let Some(color) = fill.color_at(x, y) {
+                depth[offset] = z;
+                frame_buffer[offset] = color;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gfx::raster::{Bounds, Fill};
+
+    use super::{fill_triangle_depth, multiply, rotation, transform, translation, DepthMode, Gfx3d,
+                Mesh, Triangle};
+
+    fn close(a: [f64; 3], b: [f64; 3]) -> bool {
+        (0..3).all(|i| (a[i] - b[i]).abs() < 1e-9)
+    }
+
+    #[test]
+    fn test_matrices() {
+        // A quarter of a turn around z
+        let m = rotation(0.0, 0.0, 0.25);
+        assert!(close(transform(&m, [1.0, 0.0, 0.0]), [0.0, 1.0, 0.0]));
+
+        let m = multiply(&translation(1.0, 2.0, 3.0), &m);
+        assert!(close(transform(&m, [1.0, 0.0, 0.0]), [1.0, 3.0, 3.0]));
+    }
+
+    #[test]
+    fn test_gfx3d_queue() {
+        let mut gfx3d = Gfx3d::new();
+        gfx3d.set_light(0.0, 0.0, 1.0, 0.25);
+
+        // Facing the camera at z = 5, in the middle of the screen
+        gfx3d.triangle([[-1.0, -1.0, 0.0], [1.0, -1.0, 0.0], [0.0, 1.0, 0.0]], 8, 128, 128);
+        assert_eq!(gfx3d.queue.len(), 1);
+        let triangle = gfx3d.queue[0].clone();
+        assert!((triangle.light - 1.0).abs() < 1e-9);
+        assert!((triangle.points[0][2] - 5.0).abs() < 1e-9);
+        assert!(triangle.points[2][0] == 64.0 && triangle.points[2][1] < 64.0);
+
+        // The back face isn't drawn without culling
+        gfx3d.triangle([[-1.0, -1.0, 0.0], [0.0, 1.0, 0.0], [1.0, -1.0, 0.0]], 8, 128, 128);
+        assert_eq!(gfx3d.queue.len(), 1);
+        gfx3d.cull = false;
+        gfx3d.triangle([[-1.0, -1.0, 0.0], [0.0, 1.0, 0.0], [1.0, -1.0, 0.0]], 8, 128, 128);
+        assert_eq!(gfx3d.queue.len(), 2);
+
+        // Behind the camera
+        gfx3d.push();
+        gfx3d.apply(&translation(0.0, 0.0, 10.0));
+        gfx3d.triangle([[-1.0, -1.0, 0.0], [1.0, -1.0, 0.0], [0.0, 1.0, 0.0]], 8, 128, 128);
+        gfx3d.pop();
+        assert_eq!(gfx3d.queue.len(), 2);
+
+        // The farthest first
+        let id = gfx3d.mesh_new(Mesh {
+                                    vertices: vec![[-1.0, -1.0, -2.0], [1.0, -1.0, -2.0], [0.0, 1.0, -2.0]],
+                                    faces: vec![([0, 1, 2], 9)],
+                                })
+            .unwrap();
+        gfx3d.draw(id, 128, 128);
+        let queue = gfx3d.take_queue();
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue[0].color, 9);
+        assert!(gfx3d.queue.is_empty());
+
+        assert!(gfx3d.mesh_new(Mesh {
+                                   vertices: vec![[0.0, 0.0, 0.0]],
+                                   faces: vec![([0, 1, 2], 9)],
+                               })
+                    .is_none());
+        assert_eq!(DepthMode::from_name("zbuffer"), Some(DepthMode::Buffer));
+    }
+
+    #[test]
+    fn test_gfx3d_near() {
+        let mut gfx3d = Gfx3d::new();
+        gfx3d.cull = false;
+
+        // One point behind the camera, the rest is cut in 2 triangles
+        gfx3d.triangle([[-1.0, -1.0, 0.0], [1.0, -1.0, 0.0], [0.0, 1.0, 10.0]], 8, 128, 128);
+        assert_eq!(gfx3d.queue.len(), 2);
+        for triangle in gfx3d.queue.iter() {
+            assert!(triangle.points.iter().all(|p| p[2] >= gfx3d.near - 1e-9));
+        }
+
+        // Two points behind, 1 triangle
+        gfx3d.triangle([[-1.0, -1.0, 10.0], [1.0, -1.0, 10.0], [0.0, 1.0, 0.0]], 8, 128, 128);
+        assert_eq!(gfx3d.queue.len(), 3);
+        let near = gfx3d.near;
+        assert_eq!(gfx3d.queue[2].points.iter().filter(|p| (p[2] - near).abs() < 1e-9).count(), 2);
+
+        // Just in front of the near plane, kept
+        gfx3d.triangle([[-1.0, -1.0, 4.85], [1.0, -1.0, 4.85], [0.0, 1.0, 4.85]], 8, 128, 128);
+        assert_eq!(gfx3d.queue.len(), 4);
+    }
+
+    #[test]
+    fn test_shade_depth() {
+        let color_map: Vec<u32> = (0..256).collect();
+        let mut triangle = Triangle {
+            points: [[0.0, 0.0, 1.0], [8.0, 0.0, 1.0], [0.0, 8.0, 1.0]],
+            color: 7,
+            light: 1.0,
+        };
+        assert_eq!(triangle.fill(&color_map).pattern, 0);
+        triangle.light = 0.5;
+        assert_eq!(triangle.fill(&color_map).pattern.count_ones(), 8);
+        assert_eq!(triangle.fill(&color_map).secondary, Some(6));
+
+        let bounds = Bounds {
+            left: 0,
+            top: 0,
+            right: 8,
+            bottom: 8,
+        };
+        let mut buffer = vec![0; 64];
+        let mut depth = vec![::std::f32::INFINITY; 64];
+        fill_triangle_depth(&mut buffer, &mut depth, 8, bounds, triangle.points, Fill::solid(1));
+        assert_eq!(buffer[0], 1);
+        assert_eq!(buffer[63], 0);
+
+        // Behind the first one
+        let far = [[0.0, 0.0, 2.0], [8.0, 0.0, 2.0], [8.0, 8.0, 2.0]];
+        fill_triangle_depth(&mut buffer, &mut depth, 8, bounds, far, Fill::solid(2));
+        assert_eq!(buffer[0], 1);
+        assert_eq!(buffer[7 + 6 * 8], 2);
+
+        // The depth of the pixels is the one of the perspective
+        let mut depth = vec![::std::f32::INFINITY; 64];
+        let tilted = [[0.0, 0.0, 1.0], [8.0, 0.0, 4.0], [0.0, 8.0, 1.0]];
+        fill_triangle_depth(&mut buffer, &mut depth, 8, bounds, tilted, Fill::solid(3));
+        assert!((depth[3] - 1.0 / 0.671875).abs() < 1e-5);
+    }
+}
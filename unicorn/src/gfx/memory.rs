@@ -0,0 +1,158 @@
+//! Memory of `peek`/`poke`/`memcpy`/`memset`: the data of the cartridge in a
+//! flat space of bytes, like the memory of PICO-8. Each region starts at a
+//! fixed address, a byte is a pixel of the sprite sheet or of the screen, the
+//! flags of a sprite, a byte of the user memory or half of a cell of the map.
+//! The addresses outside of the regions read 0 and ignore the writes.
+//!
+//! | Address   | Length  | Region                                              |
+//! |-----------|---------|-----------------------------------------------------|
+//! | `0x00000` | `96000` | sprite sheet, rows of 400 pixels like `sget`        |
+//! | `0x18000` | `1500`  | flags of the sprites                                |
+//! | `0x20000` | `96000` | screen, rows of the width of the `mode`             |
+//! | `0x38000` | `32768` | user memory, cleared when a cartridge is loaded     |
+//! | `0x40000` | `48000` | map, 2 bytes per cell (low byte first), rows of 400 |
+
+use gfx::Screen;
+use unicorn;
+
+/// Pixels in a row of the sheet, 50 sprites like `sget`
+pub const SHEET_WIDTH: usize = 400;
+pub const SHEET_HEIGHT: usize = 240;
+
+pub const SPRITES_ADDR: usize = 0x00000;
+pub const SPRITES_LEN: usize = SHEET_WIDTH * SHEET_HEIGHT;
+pub const FLAGS_ADDR: usize = 0x18000;
+pub const FLAGS_LEN: usize = (SHEET_WIDTH / 8) * (SHEET_HEIGHT / 8);
+/// Enough for the largest `mode`
+pub const SCREEN_ADDR: usize = 0x20000;
+pub const SCREEN_LEN: usize = 400 * 240;
+pub const USER_ADDR: usize = 0x38000;
+pub const USER_LEN: usize = 0x8000;
+/// Last region, its length follows the size of the map of the build
+pub const MAP_ADDR: usize = 0x40000;
+pub const MAP_LEN: usize = unicorn::MAP_WIDTH * unicorn::MAP_HEIGHT * 2;
+
+pub const MEMORY_LEN: usize = MAP_ADDR + MAP_LEN;
+
+/// Sprite and pixel of a byte of the sheet
+fn sprite_pixel(offset: usize) -> (usize, usize) {
+    let (x, y) = (offset % SHEET_WIDTH, offset / SHEET_WIDTH);
+    ((x / 8) + (SHEET_WIDTH / 8) * (y / 8), (x % 8) + (y % 8) * 8)
+}
+
+pub fn peek(screen: &Screen, addr: usize) -> u8 {
+    if addr < SPRITES_ADDR + SPRITES_LEN {
+        let (sprite, pixel) = sprite_pixel(addr - SPRITES_ADDR);
+        screen.sprites.get(sprite).map(|sprite| sprite.data[pixel] as u8).unwrap_or(0)
+    } else if addr >= FLAGS_ADDR && addr < FLAGS_ADDR + FLAGS_LEN {
+        screen.sprites.get(addr - FLAGS_ADDR).map(|sprite| sprite.flags).unwrap_or(0)
+    } else if addr >= SCREEN_ADDR && addr < SCREEN_ADDR + SCREEN_LEN {
+        screen.frame_buffer.get(addr - SCREEN_ADDR).map(|pixel| *pixel as u8).unwrap_or(0)
+    } else if addr >= USER_ADDR && addr < USER_ADDR + USER_LEN {
+        screen.memory[addr - USER_ADDR]
+    } else if addr >= MAP_ADDR && addr < MAP_ADDR + MAP_LEN {
+        let offset = addr - MAP_ADDR;
+        let cell = screen.map.get(offset / 2).cloned().unwrap_or(0);
+        (cell >> (8 * (offset % 2))) as u8
+    } else {
+        0
+    }
+}
+
+pub fn poke(screen: &mut Screen, addr: usize, value: u8) {
+    if addr < SPRITES_ADDR + SPRITES_LEN {
+        let (sprite, pixel) = sprite_pixel(addr - SPRITES_ADDR);
+        if let Some(sprite) = screen.sprites.get_mut(sprite) {
+            sprite.set_data(pixel, value as u32);
+        }
+    } else if addr >= FLAGS_ADDR && addr < FLAGS_ADDR + FLAGS_LEN {
+        if let Some(sprite) = screen.sprites.get_mut(addr - FLAGS_ADDR) {
+            sprite.set_flags(value);
+        }
+    } else if addr >= SCREEN_ADDR && addr < SCREEN_ADDR + SCREEN_LEN {
+        if let Some(pixel) = screen.frame_buffer.get_mut(addr - SCREEN_ADDR) {
+            *pixel = value as u32;
+        }
+    } else if addr >= USER_ADDR && addr < USER_ADDR + USER_LEN {
+        screen.memory[addr - USER_ADDR] = value;
+    } else if addr >= MAP_ADDR && addr < MAP_ADDR + MAP_LEN {
+        let offset = addr - MAP_ADDR;
+        let cell = offset / 2;
+        let shift = 8 * (offset % 2);
+        let value = (screen.map.get(cell).cloned().unwrap_or(0) & !(0xff << shift)) |
+                    ((value as u32) << shift);
+        screen.mset((cell % unicorn::MAP_WIDTH) as i32,
+                    (cell / unicorn::MAP_WIDTH) as i32,
+                    value);
+    }
+}
+
+/// Copy of `len` bytes, right even when the source and the destination
+/// overlap
+pub fn memcpy(screen: &mut Screen, dest_addr: usize, source_addr: usize, len: usize) {
+    let len = len.min(MEMORY_LEN);
+    let data: Vec<u8> = (source_addr..source_addr + len).map(|addr| peek(screen, addr)).collect();
+
+    for (idx, value) in data.into_iter().enumerate() {
+        poke(screen, dest_addr + idx, value);
+    }
+}
+
+pub fn memset(screen: &mut Screen, dest_addr: usize, value: u8, len: usize) {
+    let len = len.min(MEMORY_LEN);
+
+    for addr in dest_addr..dest_addr + len {
+        poke(screen, addr, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gfx::{Screen, Sprite};
+    use unicorn;
+
+    use super::{memcpy, memset, peek, poke, FLAGS_ADDR, MAP_ADDR, SCREEN_ADDR, USER_ADDR};
+
+    #[test]
+    fn test_memory() {
+        let mut screen = Screen::new(128, 128);
+        screen.init();
+        screen.set_sprites(vec![Sprite::new([0; 64]), Sprite::new([0; 64])]);
+        screen.set_map(vec![0; unicorn::MAP_WIDTH * unicorn::MAP_HEIGHT]);
+
+        // Second pixel of the second row of the second sprite
+        poke(&mut screen, 400 + 9, 12);
+        assert_eq!(screen.sprites[1].data[9], 12);
+        assert_eq!(peek(&screen, 409), 12);
+        // Outside of the sprites
+        poke(&mut screen, 16, 3);
+        assert_eq!(peek(&screen, 16), 0);
+
+        poke(&mut screen, FLAGS_ADDR + 1, 5);
+        assert_eq!(screen.sprites[1].flags, 5);
+
+        poke(&mut screen, SCREEN_ADDR + 130, 7);
+        assert_eq!(screen.frame_buffer[130], 7);
+        assert_eq!(peek(&screen, SCREEN_ADDR + 128 * 128), 0);
+
+        poke(&mut screen, MAP_ADDR + 6, 0x34);
+        poke(&mut screen, MAP_ADDR + 7, 0x12);
+        assert_eq!(screen.mget(3, 0), 0x1234);
+        assert_eq!(peek(&screen, MAP_ADDR + 7), 0x12);
+
+        // Overlapping copy
+        memset(&mut screen, USER_ADDR, 1, 4);
+        poke(&mut screen, USER_ADDR, 9);
+        memcpy(&mut screen, USER_ADDR + 1, USER_ADDR, 4);
+        let user: Vec<u8> = (0..6).map(|idx| peek(&screen, USER_ADDR + idx)).collect();
+        assert_eq!(user, vec![9, 9, 1, 1, 1, 0]);
+
+        // The sheet to the screen
+        memcpy(&mut screen, SCREEN_ADDR, 400 + 8, 2);
+        assert_eq!(&screen.frame_buffer[..2], &[0, 12]);
+
+        screen.memory_clear();
+        assert_eq!(peek(&screen, USER_ADDR), 0);
+        assert_eq!(peek(&screen, 0x3ffff), 0);
+    }
+}
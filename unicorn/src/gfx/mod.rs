@@ -1,14 +1,32 @@
 mod fonts;
+pub mod bitmap_font;
+pub mod raster;
+pub mod particles;
+pub mod gfx3d;
+pub mod raycast;
+pub mod collision;
+pub mod memory;
 
 use std::fmt;
+use std::collections::HashMap;
 
 use unicorn;
 use std::cmp;
 use std::ptr;
+use std::mem;
 use num_traits::pow;
 use std::f64;
 use std::f64::consts::PI;
 
+use self::bitmap_font::BitmapFont;
+use self::particles::Particles;
+use self::gfx3d::{DepthMode, Gfx3d};
+
+/// Resolution of the console, restored for the editor and the cart browser
+pub const DEFAULT_MODE: (usize, usize) = (400, 240);
+/// Resolutions asked by the cartridges with `mode(w, h)`
+pub const MODES: [(usize, usize); 4] = [DEFAULT_MODE, (128, 128), (256, 256), (240, 136)];
+
 // Fixed pitch font definition
 #[allow(dead_code)]
 pub struct Font {
@@ -175,7 +193,121 @@ impl fmt::Debug for Sprite {
 
 #[cfg(test)]
 mod tests {
-    use super::Sprite;
+    use unicorn;
+
+    use super::{ClipRect, Screen, Sprite, DEFAULT_MODE, MAX_LINE_WIDTH};
+    use super::particles::EmitterDef;
+    use super::gfx3d::DepthMode;
+
+    #[test]
+    fn test_particles_draw() {
+        let mut screen = Screen::new(16, 16);
+        screen.init();
+
+        let mut def = EmitterDef::new();
+        def.x = 3.0;
+        def.y = 4.0;
+        def.count = 1;
+        def.speed = 0.0;
+        def.colors = vec![9];
+        screen.particles.spawn(def.clone());
+
+        // With the camera
+        screen.camera(1, 0);
+        screen.particles_draw();
+        assert_eq!(screen.frame_buffer[2 + 4 * 16], 9);
+
+        def.sprite = Some(1);
+        screen.set_sprites(vec![Sprite::new([0; 64]), Sprite::new([5; 64])]);
+        screen.particles.clear();
+        screen.particles.spawn(def);
+        screen.camera(0, 0);
+        screen.particles_draw();
+        assert_eq!(screen.frame_buffer[0], 5);
+        assert_eq!(screen.frame_buffer[6 + 7 * 16], 5);
+        assert_eq!(screen.particles.count(), 1);
+    }
+
+    #[test]
+    fn test_screen_render3d() {
+        let mut screen = Screen::new(32, 32);
+        screen.init();
+        screen.gfx3d.set_light(0.0, 0.0, 1.0, 1.0);
+
+        for &mode in [DepthMode::Sort, DepthMode::Buffer].iter() {
+            screen.cls(-1);
+            screen.gfx3d.mode = mode;
+            // The near one first, the far one is hidden in the middle
+            screen.gfx3d.triangle([[-1.0, -1.0, 0.0], [1.0, -1.0, 0.0], [0.0, 1.0, 0.0]], 8, 32, 32);
+            screen.gfx3d.triangle([[-3.0, -3.0, -1.0], [3.0, -3.0, -1.0], [0.0, 3.0, -1.0]], 9, 32, 32);
+            assert_eq!(screen.render3d(), 2);
+            assert_eq!(screen.frame_buffer[16 + 16 * 32], 8);
+            assert_eq!(screen.frame_buffer[16 + 24 * 32], 9);
+            assert_eq!(screen.frame_buffer[0], 0);
+        }
+        assert_eq!(screen.render3d(), 0);
+    }
+
+    #[test]
+    fn test_screen_raycast() {
+        let mut screen = Screen::new(32, 32);
+        screen.init();
+        screen.set_sprites(vec![Sprite::new([0; 64]), Sprite::new([7; 64])]);
+        for i in 0..8 {
+            screen.mset(4, i, 1);
+            screen.mset(i, 0, 1);
+        }
+
+        // Toward the right, the wall of the column 4
+        let depths = screen.raycast(1.5, 1.5, 0.0, 0.125, 0, 16.0);
+        assert_eq!(depths.len(), 32);
+        assert!((depths[16] - 2.5).abs() < 0.1);
+        assert_eq!(screen.frame_buffer[16 + 16 * 32], 7);
+        assert_eq!(screen.frame_buffer[16], 0);
+
+        // Toward the top, the faces of the rows are darker
+        let depths = screen.raycast(1.5, 1.5, 0.25, 0.125, 0, 16.0);
+        assert!((depths[16] - 0.5).abs() < 0.1);
+        assert_eq!(screen.frame_buffer[16 + 16 * 32], 6);
+
+        // Nothing on the layer 1
+        screen.cls(-1);
+        let depths = screen.raycast(1.5, 1.5, 0.0, 0.125, 1, 16.0);
+        assert_eq!(depths[0], 16.0);
+        assert_eq!(screen.frame_buffer[16 + 16 * 32], 0);
+    }
+
+    #[test]
+    fn test_screen_collisions() {
+        let mut screen = Screen::new(32, 32);
+        screen.init();
+        let mut wall = Sprite::new([7; 64]);
+        wall.set_flag(0, true);
+        let mut ball = [0; 64];
+        ball[3 + 3 * 8] = 8;
+        screen.set_sprites(vec![Sprite::new([0; 64]), wall, Sprite::new(ball)]);
+        screen.mset(2, 1, 1);
+
+        assert!(screen.collide_map(12.0, 4.0, 6.0, 6.0, 0));
+        assert!(!screen.collide_map(12.0, 4.0, 4.0, 4.0, 0));
+        assert!(!screen.collide_map(12.0, 4.0, 6.0, 6.0, 1));
+        assert!(!screen.collide_map(-8.0, -8.0, 4.0, 4.0, 0));
+        // Only the cells of the map are visited
+        assert!(screen.collide_map(-1000.0, -1000.0, 4000.0, 4000.0, 0));
+        assert!(!screen.collide_map(20000.0, 20000.0, 8.0, 8.0, 0));
+
+        // Only the pixel of the ball is opaque
+        assert!(screen.sprite_overlap(1, 0, 0, 1, 1, 2, 2, 2, 1, 1));
+        assert!(!screen.sprite_overlap(1, 0, 0, 1, 1, 2, 5, 5, 1, 1));
+        assert!(screen.sprite_overlap(2, 5, 5, 1, 1, 2, 5, 5, 1, 1));
+        assert!(!screen.sprite_overlap(2, 5, 5, 1, 1, 2, 6, 5, 1, 1));
+        // The blocks are cut to the sheet
+        assert!(screen.sprite_overlap(1, 0, 0, 1000000, 1000000, 2, 2, 2, 1, 1));
+        assert!(!screen.sprite_overlap(1, 0, 0, -1, 1, 2, 2, 2, 1, 1));
+        // The transparent color of palt is left out
+        screen.palt(7, true);
+        assert!(!screen.sprite_overlap(1, 0, 0, 1, 1, 2, 2, 2, 1, 1));
+    }
 
     #[test]
     fn test_sprite_flags() {
@@ -203,6 +335,457 @@ mod tests {
         assert_eq!(s.is_flags_set(6), false);
         assert_eq!(s.is_flags_set(7), true);
     }
+
+    #[test]
+    fn test_screen_dump_state() {
+        let mut screen = Screen::new(4, 2);
+        screen.init();
+        screen.camera(1, 2);
+        screen.pal(3, 5);
+        screen.palt(0, true);
+        screen.putpixel_direct(1, 1, 12);
+
+        let dump = screen.dump_state();
+        assert!(dump.contains("Size: 4x2\n"));
+        assert!(dump.contains("Camera: 1 2\n"));
+        assert!(dump.contains("Pal: 3->5\n"));
+        assert!(dump.contains("Transparent: 0\n"));
+        assert!(dump.ends_with("00 00 00 00\n00 0c 00 00\n"));
+    }
+
+    #[test]
+    fn test_screen_map() {
+        let mut screen = Screen::new(4, 2);
+        assert_eq!(screen.mget(3, 2), 0);
+
+        screen.mset(3, 2, 42);
+        assert_eq!(screen.mget(3, 2), 42);
+        assert_eq!(screen.mget(2, 3), 0);
+        assert_eq!(screen.map[3 + 2 * unicorn::MAP_WIDTH], 42);
+
+        screen.mset(-1, 0, 1);
+        screen.mset(unicorn::MAP_WIDTH as i32, 0, 1);
+        assert_eq!(screen.mget(unicorn::MAP_WIDTH as i32, 0), 0);
+    }
+
+    #[test]
+    fn test_pal_cycle() {
+        let mut screen = Screen::new(8, 8);
+        screen.init();
+        assert_eq!(screen.display_color(9), 9);
+
+        screen.pal_cycle(8, 10, 2.0);
+        screen.pal_cycle_update(0.5);
+        assert_eq!(screen.display_color(8), 9);
+        assert_eq!(screen.display_color(10), 8);
+        assert_eq!(screen.display_color(7), 7);
+
+        screen.pal_cycle(10, 8, -2.0);
+        assert_eq!(screen.pal_cycles.len(), 1);
+        assert_eq!(screen.display_color(8), 10);
+
+        screen.pal_cycle(8, 10, 0.0);
+        assert_eq!(screen.display_color(8), 8);
+    }
+
+    #[test]
+    fn test_pal_screen() {
+        let mut screen = Screen::new(8, 8);
+        screen.init();
+
+        // The display palette is applied after the cycles
+        screen.pal_screen(9, 1);
+        assert_eq!(screen.display_color(9), 1);
+        screen.pal_cycle(8, 10, 2.0);
+        screen.pal_cycle_update(0.5);
+        assert_eq!(screen.display_color(8), 1);
+        assert_eq!(screen.display_color(9), 10);
+
+        // The draw palette is not changed
+        screen.pal(3, 5);
+        screen.pal_screen(-1, -1);
+        assert_eq!(screen.display_color(8), 9);
+        assert_eq!(screen.color_map[3], 5);
+
+        screen.pal_screen(2, 7);
+        screen.pal(-1, -1);
+        assert_eq!(screen.color_map[3], 3);
+        assert_eq!(screen.display_color(2), 2);
+    }
+
+    #[test]
+    fn test_spr_batch() {
+        let mut screen = Screen::new(16, 16);
+        screen.init();
+        screen.set_sprites(vec![Sprite::new([0; 64]), Sprite::new([7; 64])]);
+
+        screen.spr_batch(1, &[(0, 0), (12, 12), (-100, 4)]);
+        assert_eq!(screen.frame_buffer[0], 7);
+        assert_eq!(screen.frame_buffer[8], 0);
+        assert_eq!(screen.frame_buffer[15 + 15 * 16], 7);
+
+        // Unknown sprite
+        screen.spr_batch(2, &[(8, 0)]);
+        assert_eq!(screen.frame_buffer[8], 0);
+    }
+
+    #[test]
+    fn test_spr_tile_9slice() {
+        let mut screen = Screen::new(32, 32);
+        screen.init();
+
+        let mut sprites = Vec::new();
+        for i in 0..103 {
+            sprites.push(Sprite::new([(i % 16) as u32; 64]));
+        }
+        screen.set_sprites(sprites);
+
+        screen.spr_tile(3, 0, 0, 12, 4);
+        assert_eq!(screen.frame_buffer[11 + 3 * 32], 3);
+        assert_eq!(screen.frame_buffer[12], 0);
+        assert_eq!(screen.frame_buffer[4 * 32], 0);
+
+        screen.cls(0);
+        screen.spr_9slice(0, 0, 0, 24, 32);
+        // Corners
+        assert_eq!(screen.frame_buffer[16], 2);
+        assert_eq!(screen.frame_buffer[31 * 32], 100 % 16);
+        assert_eq!(screen.frame_buffer[23 + 31 * 32], 102 % 16);
+        // Borders and center
+        assert_eq!(screen.frame_buffer[8], 1);
+        assert_eq!(screen.frame_buffer[20 * 32], 50 % 16);
+        assert_eq!(screen.frame_buffer[12 + 20 * 32], 51 % 16);
+        assert_eq!(screen.frame_buffer[23 + 20 * 32], 52 % 16);
+        assert_eq!(screen.frame_buffer[24], 0);
+    }
+
+    #[test]
+    fn test_visible_cells() {
+        assert_eq!(super::visible_cells(0, 100, 0, 128), (0, 16));
+        assert_eq!(super::visible_cells(-12, 100, 0, 128), (1, 18));
+        assert_eq!(super::visible_cells(-800, 50, 0, 128), (50, 50));
+        assert_eq!(super::visible_cells(130, 50, 0, 128), (0, 0));
+    }
+
+    #[test]
+    fn test_screen_mapdraw() {
+        let mut screen = Screen::new(16, 8);
+        screen.init();
+        screen.palt(0, true);
+
+        let mut data = [0; 64];
+        data[0] = 7;
+        data[63] = 8;
+        screen.set_sprites(vec![Sprite::new([9; 64]), Sprite::new(data)]);
+
+        screen.mset(0, 0, 1);
+        screen.mset(1, 0, 1);
+        screen.mapdraw(0, 0, -4, 0, 4, 4, 0);
+
+        // The first cell is clipped, the second one is inside the screen
+        assert_eq!(screen.frame_buffer[3], 0);
+        assert_eq!(screen.frame_buffer[4], 7);
+        assert_eq!(screen.frame_buffer[3 + 7 * 16], 8);
+        assert_eq!(screen.frame_buffer[11 + 7 * 16], 8);
+        // The sprite 0 is not drawn
+        assert_eq!(screen.frame_buffer[15], 0);
+    }
+
+    #[test]
+    fn test_screen_map_affine() {
+        let mut screen = Screen::new(16, 8);
+        screen.init();
+        screen.palt(0, true);
+
+        let mut data = [0; 64];
+        data[0] = 7;
+        data[63] = 8;
+        screen.set_sprites(vec![Sprite::new([9; 64]), Sprite::new(data)]);
+        screen.mset(0, 0, 1);
+
+        // Identity, moved by 4 pixels on x
+        screen.map_affine(1.0, 0.0, 0.0, 1.0, 4.0, 0.0, 0);
+        assert_eq!(screen.frame_buffer[3 + 7 * 16], 8);
+        assert_eq!(screen.frame_buffer[0], 0);
+
+        // The map repeats itself
+        screen.cls(-1);
+        screen.map_affine(1.0, 0.0, 0.0, 1.0, 1020.0, 0.0, 0);
+        assert_eq!(screen.frame_buffer[4], 7);
+
+        // Twice bigger, the camera moves the screen
+        screen.cls(-1);
+        screen.camera(-2, 0);
+        screen.map_affine(0.5, 0.0, 0.0, 0.5, 0.0, 0.0, 0);
+        assert_eq!(&screen.frame_buffer[0..5], &[0, 0, 7, 7, 0]);
+
+        // One row
+        screen.cls(-1);
+        screen.camera(0, 0);
+        screen.map_scanline(3, 0.0, 7.0, 1.0, 0.0, 0);
+        assert_eq!(screen.frame_buffer[7 + 3 * 16], 8);
+        assert_eq!(screen.frame_buffer.iter().filter(|c| **c != 0).count(), 1);
+    }
+
+    #[test]
+    fn test_screen_mode() {
+        let mut screen = Screen::new(DEFAULT_MODE.0, DEFAULT_MODE.1);
+        screen.init();
+
+        assert!(screen.mode(240, 136));
+        assert_eq!((screen.width, screen.height), (240, 136));
+        assert_eq!(screen.frame_buffer.len(), 240 * 136);
+        assert_eq!(screen.cliprect.right, 240);
+        screen.pset(239, 135, 7);
+        assert_eq!(screen.pget(239, 135), 7);
+
+        // Unknown resolutions are refused, the screen is unchanged
+        assert!(!screen.mode(100, 3000));
+        assert_eq!((screen.width, screen.height), (240, 136));
+
+        assert!(screen.mode(DEFAULT_MODE.0, DEFAULT_MODE.1));
+        assert_eq!(screen.frame_buffer.len(), 400 * 240);
+    }
+
+    #[test]
+    fn test_sspr_rot() {
+        let mut screen = Screen::new(16, 16);
+        screen.init();
+
+        // Left half 1, right half 2
+        let mut data = [1; 64];
+        for y in 0..8 {
+            for x in 4..8 {
+                data[x + y * 8] = 2;
+            }
+        }
+        screen.set_sprites(vec![Sprite::new(data)]);
+
+        // Without rotation, like sspr
+        screen.sspr_rot(0, 0, 8, 8, 0, 0, 16, 16, 0.0, false, false);
+        assert_eq!(screen.frame_buffer[0], 1);
+        assert_eq!(screen.frame_buffer[15 + 15 * 16], 2);
+
+        // A quarter turn counter-clockwise: the right half goes up
+        screen.cls(0);
+        screen.sspr_rot(0, 0, 8, 8, 4, 4, 8, 8, 0.25, false, false);
+        assert_eq!(screen.frame_buffer[8 + 4 * 16], 2);
+        assert_eq!(screen.frame_buffer[8 + 11 * 16], 1);
+        assert_eq!(screen.frame_buffer[0], 0);
+
+        // Half a turn with flip_x is a vertical flip
+        screen.cls(0);
+        screen.sspr_rot(0, 0, 8, 8, 4, 4, 8, 8, 0.5, true, false);
+        assert_eq!(screen.frame_buffer[4 + 4 * 16], 1);
+        assert_eq!(screen.frame_buffer[11 + 4 * 16], 2);
+
+        // Only the box in the clipping rectangle, and the source in the sheet
+        screen.cls(0);
+        screen.clip(4, 4, 4, 4);
+        screen.sspr_rot(0, 0, 8, 8, -1000000, -1000000, 4000000, 4000000, 0.1, false, false);
+        assert_eq!(screen.frame_buffer[0], 0);
+        assert!(screen.frame_buffer[4 + 4 * 16] != 0);
+        screen.clip(-1, -1, -1, -1);
+        screen.cls(0);
+        screen.sspr_rot(4, 0, 100, 100, 0, 0, 96, 8, 0.0, false, false);
+        assert_eq!(screen.frame_buffer[0], 2);
+        assert_eq!(screen.frame_buffer[5], 0);
+        screen.sspr_rot(400, 0, 8, 8, 0, 0, 8, 8, 0.0, false, false);
+        screen.sspr_rot(0, 8, 8, 8, 0, 0, 8, 8, 0.0, false, false);
+        assert_eq!(screen.frame_buffer[0], 2);
+    }
+
+    #[test]
+    fn test_camera_clip_stack() {
+        let mut screen = Screen::new(16, 16);
+        screen.init();
+
+        // The world camera, then a layer moved from it
+        screen.camera(2, 3);
+        assert!(screen.camera_push(1, 1, false));
+        assert_eq!((screen.camera.x, screen.camera.y), (3, 4));
+        screen.camera(1, 0);
+        assert_eq!((screen.camera.x, screen.camera.y), (4, 4));
+
+        // The UI layer ignores the world camera
+        assert!(screen.camera_push(0, 0, true));
+        screen.pset(0, 0, 7);
+        assert_eq!(screen.frame_buffer[0], 7);
+
+        assert!(screen.camera_pop());
+        assert_eq!((screen.camera.x, screen.camera.y), (4, 4));
+        assert!(screen.camera_pop());
+        assert_eq!((screen.camera.x, screen.camera.y), (2, 3));
+        assert!(!screen.camera_pop());
+
+        // clip stays inside the pushed clip, for all the primitives
+        screen.camera(0, 0);
+        screen.cls(0);
+        assert!(screen.clip_push(4, 4, 8, 8));
+        screen.clip(0, 0, 6, 6);
+        screen.rectfill(0, 0, 15, 15, 3);
+        screen.line(0, 0, 15, 15, 3);
+        assert_eq!(screen.frame_buffer[3 + 3 * 16], 0);
+        assert_eq!(screen.frame_buffer[4 + 4 * 16], 3);
+        assert_eq!(screen.frame_buffer[6 + 6 * 16], 0);
+
+        screen.clip(-1, -1, -1, -1);
+        screen.trifill(0, 0, 15, 0, 0, 15, 5);
+        assert_eq!(screen.frame_buffer[3 + 4 * 16], 0);
+        assert_eq!(screen.frame_buffer[10 + 4 * 16], 5);
+        assert_eq!(screen.frame_buffer[12 + 4 * 16], 0);
+
+        assert!(screen.clip_pop());
+        assert!(!screen.clip_pop());
+        assert_eq!(screen.cliprect, ClipRect { left: 0, top: 0, right: 16, bottom: 16 });
+    }
+
+    #[test]
+    fn test_fillp() {
+        let mut screen = Screen::new(16, 16);
+        screen.init();
+
+        // Checkerboard with a transparent half
+        screen.fillp(0xa5a5, -1);
+        screen.rectfill(0, 0, 15, 15, 3);
+        assert_eq!(screen.frame_buffer[0], 0);
+        assert_eq!(screen.frame_buffer[1], 3);
+        assert_eq!(screen.frame_buffer[16], 3);
+        assert_eq!(screen.frame_buffer.iter().filter(|c| **c == 3).count(), 128);
+
+        // The pattern stays on the screen when the camera moves
+        screen.fillp(0xa5a5, 5);
+        screen.camera(1, 0);
+        screen.line(1, 2, 4, 2, 4);
+        assert_eq!(&screen.frame_buffer[32..36], &[5, 4, 5, 4]);
+
+        // All the pixels again
+        screen.camera(0, 0);
+        screen.fillp(0, -1);
+        screen.circfill(8, 8, 2, 6);
+        assert_eq!(screen.frame_buffer[8 + 8 * 16], 6);
+        assert_eq!(screen.frame_buffer[9 + 8 * 16], 6);
+    }
+
+    #[test]
+    fn test_line_width_bezier() {
+        let mut screen = Screen::new(16, 16);
+        screen.init();
+
+        assert_eq!(screen.line_width(3), 1);
+        screen.line(2, 8, 10, 8, 3);
+        assert_eq!(screen.frame_buffer[5 + 7 * 16], 3);
+        assert_eq!(screen.frame_buffer[5 + 9 * 16], 3);
+        assert_eq!(screen.frame_buffer[5 + 6 * 16], 0);
+        assert_eq!(screen.frame_buffer[5 + 10 * 16], 0);
+        assert_eq!(screen.frame_buffer[11 + 8 * 16], 3);
+        assert_eq!(screen.frame_buffer[12 + 8 * 16], 0);
+        assert_eq!(screen.line_width(100), 3);
+        assert_eq!(screen.line_width(0), MAX_LINE_WIDTH);
+
+        // A control point on the line gives the line
+        screen.cls(0);
+        screen.bezier(0, 0, 4, 4, 8, 8, 2);
+        assert_eq!(screen.frame_buffer.iter().filter(|c| **c == 2).count(), 9);
+        assert_eq!(screen.frame_buffer[4 + 4 * 16], 2);
+
+        screen.cls(0);
+        screen.bezier(0, 15, 8, -15, 15, 15, 2);
+        assert_eq!(screen.frame_buffer[15 * 16], 2);
+        assert_eq!(screen.frame_buffer[15 + 15 * 16], 2);
+        assert_eq!(screen.frame_buffer[8], 2);
+        assert_eq!(screen.frame_buffer[8 + 15 * 16], 0);
+
+        // The squares of the distances don't fit in an i32
+        screen.cls(0);
+        screen.bezier(0, 0, 40000, 40000, 0, 15, 2);
+        assert_eq!(screen.frame_buffer[0], 2);
+        assert_eq!(screen.frame_buffer[15 * 16], 2);
+    }
+
+    #[test]
+    fn test_custom_font() {
+        let mut screen = Screen::new(16, 16);
+        screen.init();
+
+        // 4x4 cells: "A" is 3 pixels wide, "B" 1 pixel and "C" is empty
+        let mut data = [0; 64];
+        data[0] = 1;
+        data[1] = 1;
+        data[2] = 1;
+        data[4 + 8] = 1;
+        screen.set_sprites(vec![Sprite::new(data)]);
+
+        assert!(screen.font_load("tiny", 0, 0, 4, 4, 'A', 3, &[]));
+        assert!(!screen.font_load("wide", 0, 0, 40, 4, 'A', 3, &[]));
+        assert!(!screen.font_load("low", 0, 8, 4, 4, 'A', 3, &[]));
+        // Only the cells of the sheet, 100 columns on 2 rows
+        assert!(screen.font_load("all", 0, 0, 4, 4, 'A', 4000000000, &[]));
+        assert_eq!(screen.custom_fonts["all"].glyph_count(), 200);
+        assert!(!screen.font_load("last", 0, 0, 4, 4, '\u{10ffff}', 2, &[]));
+        assert_eq!(screen.get_font(), "pico-8");
+
+        screen.font("tiny");
+        assert_eq!(screen.get_font(), "tiny");
+        assert_eq!(screen.text_width("AB"), 5);
+        assert_eq!(screen.text_width("C"), 2);
+        assert_eq!(screen.text_width("AB\nA"), 5);
+
+        // Lower case with the upper case glyphs
+        screen.print("ab".to_string(), 1, 1, 5);
+        assert_eq!(screen.frame_buffer.iter().filter(|c| **c == 5).count(), 4);
+        assert_eq!(screen.frame_buffer[3 + 16], 5);
+        assert_eq!(screen.frame_buffer[5 + 2 * 16], 5);
+        assert_eq!(screen.frame_buffer[4 + 16], 0);
+
+        screen.font("bbc");
+        assert_eq!(screen.get_font(), "bbc");
+
+        assert!(screen.font_data("data", 2, &['x' as u32, 2, 1, 2]));
+        assert!(!screen.font_data("data", 2, &['x' as u32, 2, 1]));
+        screen.cls(0);
+        screen.print_font("xx".to_string(), 0, 0, 6, "data");
+        assert_eq!(screen.get_font(), "bbc");
+        assert_eq!(screen.frame_buffer.iter().filter(|c| **c == 6).count(), 4);
+        assert_eq!(screen.frame_buffer[1 + 16], 6);
+        assert_eq!(screen.frame_buffer[3], 6);
+
+        screen.font_clear();
+        screen.font("tiny");
+        assert_eq!(screen.get_font(), "pico-8");
+    }
+
+    #[test]
+    fn test_unicode_print() {
+        let mut screen = Screen::new(32, 16);
+        screen.init();
+
+        assert_eq!(screen.text_width("Été"), 12);
+        assert_eq!(screen.text_width("カナ"), 12);
+
+        // The acute in the row above the E
+        screen.print("É".to_string(), 0, 0, 7);
+        assert_eq!(screen.frame_buffer[3], 7);
+        assert_eq!(screen.frame_buffer[1 + 32], 7);
+        assert_eq!(screen.frame_buffer[1 + 3 * 32], 7);
+
+        // Without a glyph, a question mark
+        screen.cls(0);
+        screen.print("€".to_string(), 0, 0, 7);
+        let unknown = screen.frame_buffer.clone();
+        screen.cls(0);
+        screen.print("?".to_string(), 0, 0, 7);
+        assert_eq!(screen.frame_buffer, unknown);
+
+        // The Cyrillic A is the A of the font
+        screen.font("bbc");
+        screen.cls(0);
+        screen.print("А".to_string(), 0, 0, 7);
+        let cyrillic = screen.frame_buffer.clone();
+        screen.cls(0);
+        screen.print("A".to_string(), 0, 0, 7);
+        assert_eq!(screen.frame_buffer, cyrillic);
+    }
 }
 
 // Screen scaling
@@ -215,6 +798,7 @@ pub enum Scale {
     Scale4x,
     Scale5x,
     Scale6x,
+    Scale7x,
     Scale8x,
     Scale10x,
 }
@@ -228,12 +812,29 @@ impl Scale {
             Scale::Scale4x => 4,
             Scale::Scale5x => 5,
             Scale::Scale6x => 6,
+            Scale::Scale7x => 7,
             Scale::Scale8x => 8,
             Scale::Scale10x => 10,
         }
     }
+
+    pub fn from_factor(factor: usize) -> Option<Scale> {
+        match factor {
+            1 => Some(Scale::Scale1x),
+            2 => Some(Scale::Scale2x),
+            3 => Some(Scale::Scale3x),
+            4 => Some(Scale::Scale4x),
+            5 => Some(Scale::Scale5x),
+            6 => Some(Scale::Scale6x),
+            7 => Some(Scale::Scale7x),
+            8 => Some(Scale::Scale8x),
+            10 => Some(Scale::Scale10x),
+            _ => None,
+        }
+    }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Camera {
     pub x: i32,
     pub y: i32,
@@ -245,7 +846,40 @@ impl Camera {
     }
 }
 
+/// Colors `first..last` (inclusive) rotated when the screen is displayed,
+/// `speed` shifts per second (negative to rotate the other way)
+#[derive(Clone, Debug, PartialEq)]
+pub struct PaletteCycle {
+    pub first: u32,
+    pub last: u32,
+    pub speed: f64,
+}
+
+impl PaletteCycle {
+    pub fn new(first: u32, last: u32, speed: f64) -> PaletteCycle {
+        PaletteCycle {
+            first: cmp::min(first, last),
+            last: cmp::max(first, last),
+            speed: speed,
+        }
+    }
+
+    pub fn contains(&self, value: u32) -> bool {
+        value >= self.first && value <= self.last
+    }
+
+    /// Color displayed instead of `value` after `time` seconds
+    pub fn color(&self, value: u32, time: f64) -> u32 {
+        let len = (self.last - self.first + 1) as i64;
+        let shift = (time * self.speed).floor() as i64;
+        let idx = (value - self.first) as i64 + shift;
+
+        self.first + (((idx % len) + len) % len) as u32
+    }
+}
+
 // ClipRect rectangle is exclusive of right and bottom edges
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct ClipRect {
     left: i32,
     top: i32,
@@ -275,6 +909,19 @@ impl ClipRect {
     }
 }
 
+/// Range of the cells of 8 pixels starting at `origin` visible in [min, max)
+fn visible_cells(origin: i32, count: i32, min: i32, max: i32) -> (i32, i32) {
+    let first = if min - origin > 0 { (min - origin) / 8 } else { 0 };
+    let last = if max - origin > 0 { (max - origin + 7) / 8 } else { 0 };
+
+    (cmp::min(first, count), cmp::min(last, count))
+}
+
+/// Layers pushed by `camera_push`/`clip_push` without their pop
+pub const MAX_DRAW_LAYERS: usize = 64;
+/// Widest pen of `line_width`
+pub const MAX_LINE_WIDTH: i32 = 16;
+
 pub struct Screen {
     pub width: usize,
     pub height: usize,
@@ -286,6 +933,8 @@ pub struct Screen {
     pub dyn_sprites: Vec<DynamicSprite>,
 
     pub map: Vec<u32>,
+    /// User memory of `peek`/`poke`, from `memory::USER_ADDR`
+    pub memory: Vec<u8>,
 
     pub transparency_map: [bool; 256],
 
@@ -294,8 +943,39 @@ pub struct Screen {
 
     pub camera: Camera,
     pub cliprect: ClipRect,
-    
+    /// Origin of the current camera layer, `camera(x, y)` is relative to it
+    pub camera_origin: Camera,
+    /// Camera and origin saved by each `camera_push`
+    pub camera_stack: Vec<(Camera, Camera)>,
+    /// Clip of the current clip layer, `clip(x, y, w, h)` stays inside it
+    pub clip_base: ClipRect,
+    /// Clip and base saved by each `clip_push`
+    pub clip_stack: Vec<(ClipRect, ClipRect)>,
+
+    /// 4x4 pattern of the shapes, `fillp(pattern, [col2])`, 0 for none
+    pub fill_pattern: u16,
+    /// Color of the set bits of the pattern, None for transparent
+    pub fill_secondary: Option<u32>,
+    /// Diameter of the pen of `line` and `bezier`, `line_width(w)`
+    pub line_width: i32,
+
     pub font: &'static Font,
+    /// Fonts of the cartridge, `font_load` and `font_data`
+    pub custom_fonts: HashMap<String, BitmapFont>,
+    /// Name of the custom font drawing the text instead of `font`
+    pub custom_font: Option<String>,
+
+    pub pal_cycles: Vec<PaletteCycle>,
+    pub pal_cycle_time: f64,
+    /// Color displayed for each color of the frame buffer, with the cycles
+    pub display_map: [u32; 256],
+    /// Display palette, `pal(c0, c1, 1)`, applied after the cycles
+    pub screen_map: [u32; 256],
+
+    /// Emitters of `particles_spawn`, moved with the time of the game
+    pub particles: Particles,
+    /// Matrices, meshes and queued triangles of the 3D
+    pub gfx3d: Gfx3d,
 }
 
 unsafe impl Send for Screen {}
@@ -313,20 +993,60 @@ impl Screen {
             sprites: Vec::new(),
             dyn_sprites: Vec::new(),
             map: Vec::new(),
+            memory: vec![0; memory::USER_LEN],
             transparency_map: [false; 256],
             color_map: [0; 0xFFF],
             color: 0,
             camera: Camera::new(),
             cliprect: ClipRect::new(),
+            camera_origin: Camera::new(),
+            camera_stack: Vec::new(),
+            clip_base: ClipRect::new(),
+            clip_stack: Vec::new(),
+            fill_pattern: 0,
+            fill_secondary: None,
+            line_width: 1,
             font: &fonts::pico8::FONT,
+            custom_fonts: HashMap::new(),
+            custom_font: None,
+            pal_cycles: Vec::new(),
+            pal_cycle_time: 0.0,
+            display_map: [0; 256],
+            screen_map: [0; 256],
+            particles: Particles::new(),
+            gfx3d: Gfx3d::new(),
         }
     }
 
     pub fn init(&mut self) {
         self._reset_colors();
         self._reset_transparency();
+        self._reset_camera();
         self._reset_cliprect();
+        self.fillp(0, -1);
+        self.line_width = 1;
         self.color = 0;
+        self._reset_screen_colors();
+        self.pal_cycle_reset();
+    }
+
+    /// Resize the screen to one of the `MODES`, the frame buffer is cleared
+    pub fn mode(&mut self, width: usize, height: usize) -> bool {
+        if !MODES.contains(&(width, height)) {
+            return false;
+        }
+
+        if (width, height) != (self.width, self.height) {
+            info!("[GFX] Mode {:?}x{:?}", width, height);
+
+            self.width = width;
+            self.height = height;
+            self.aspect_ratio = width as f32 / height as f32;
+            self.frame_buffer = vec![0; width * height];
+            self.saved_frame_buffer = vec![0; width * height];
+            self._reset_cliprect();
+        }
+        true
     }
 
     pub fn mode_width(&mut self) -> usize {
@@ -349,6 +1069,18 @@ impl Screen {
         }
     }
 
+    pub fn _reset_screen_colors(&mut self) {
+        for i in 0..256 {
+            self.screen_map[i] = i as u32;
+        }
+    }
+
+    pub fn _reset_camera(&mut self) {
+        self.camera = Camera::new();
+        self.camera_origin = Camera::new();
+        self.camera_stack.clear();
+    }
+
     pub fn _reset_cliprect(&mut self) {
         self.cliprect = ClipRect {
             left: 0,
@@ -356,6 +1088,8 @@ impl Screen {
             right: self.width as i32,
             bottom: self.height as i32,
         };
+        self.clip_base = self.cliprect;
+        self.clip_stack.clear();
     }
 
     pub fn save(&mut self) {
@@ -374,8 +1108,41 @@ impl Screen {
     }
 
     pub fn camera(&mut self, x: i32, y: i32) {
-        self.camera.x = x;
-        self.camera.y = y;
+        self.camera.x = self.camera_origin.x + x;
+        self.camera.y = self.camera_origin.y + y;
+    }
+
+    /// Start a camera layer moved by `x`/`y` from the current camera, or at
+    /// the origin of the screen when `absolute` (for the UI over a world)
+    pub fn camera_push(&mut self, x: i32, y: i32, absolute: bool) -> bool {
+        if self.camera_stack.len() >= MAX_DRAW_LAYERS {
+            warn!("[GFX] Too many camera layers, camera_pop is missing");
+            return false;
+        }
+
+        self.camera_stack.push((self.camera, self.camera_origin));
+        self.camera_origin = if absolute {
+            Camera { x: x, y: y }
+        } else {
+            Camera {
+                x: self.camera.x + x,
+                y: self.camera.y + y,
+            }
+        };
+        self.camera = self.camera_origin;
+        true
+    }
+
+    /// Back to the camera before the last `camera_push`
+    pub fn camera_pop(&mut self) -> bool {
+        match self.camera_stack.pop() {
+            Some((camera, origin)) => {
+                self.camera = camera;
+                self.camera_origin = origin;
+                true
+            }
+            None => false,
+        }
     }
 
     pub fn set_sprites(&mut self, sprites: Vec<Sprite>) {
@@ -440,7 +1207,15 @@ impl Screen {
         }
     }
 
+    /// Select a font by name, a custom font of the cartridge or a font of
+    /// the console
     pub fn font(&mut self, name: &str) {
+        if self.custom_fonts.contains_key(name) {
+            self.custom_font = Some(name.to_string());
+            return;
+        }
+
+        self.custom_font = None;
         self.font = match name {
             "pico-8" => &fonts::pico8::FONT,
             "bbc" => &fonts::bbc::FONT,
@@ -452,12 +1227,127 @@ impl Screen {
     }
 
     pub fn get_font(&mut self) -> String {
-        self.font.name.to_string().clone()
+        match self.custom_font {
+            Some(ref name) => name.clone(),
+            None => self.font.name.to_string().clone(),
+        }
+    }
+
+    /// Register the font `name` from the sprite sheet: `count` glyphs from
+    /// the character `first`, in cells of `w`x`h` pixels from (`sx`, `sy`),
+    /// going to the next row of cells at the right of the sheet, until its
+    /// bottom. The color 0 is the background. The widths are measured without
+    /// `widths`.
+    pub fn font_load(&mut self,
+                     name: &str,
+                     sx: i32,
+                     sy: i32,
+                     w: i32,
+                     h: i32,
+                     first: char,
+                     count: u32,
+                     widths: &[i32])
+                     -> bool {
+        // 50 sprites per row of the sheet
+        let sheet_width = 400;
+        let sheet_height = ((self.sprites.len() as i32 + 49) / 50) * 8;
+        let columns = if sx < 0 || sx >= sheet_width || w <= 0 {
+            0
+        } else {
+            (sheet_width - sx) / w
+        };
+        let rows = if sy < 0 || sy >= sheet_height || h <= 0 {
+            0
+        } else {
+            (sheet_height - sy) / h
+        };
+
+        let font = {
+            let sprites = &self.sprites;
+            let pixel = |x: i32, y: i32| {
+                let (x, y) = (sx + x, sy + y);
+                if x < 0 || y < 0 || x >= sheet_width {
+                    return false;
+                }
+
+                let idx_sprite = ((x / 8) + 50 * (y / 8)) as usize;
+                match sprites.get(idx_sprite) {
+                    Some(sprite) => sprite.data[((x % 8) + (y % 8) * 8) as usize] != 0,
+                    None => false,
+                }
+            };
+
+            BitmapFont::from_sheet(pixel, w, h, columns, rows, first, count, widths)
+        };
+
+        self.font_register(name, font)
     }
 
+    /// Register the font `name` from its data, see `BitmapFont::from_data`
+    pub fn font_data(&mut self, name: &str, height: i32, data: &[u32]) -> bool {
+        let font = BitmapFont::from_data(height, data);
+        self.font_register(name, font)
+    }
+
+    fn font_register(&mut self, name: &str, font: Result<BitmapFont, String>) -> bool {
+        match font {
+            Ok(font) => {
+                info!("[GFX] Font {:?} with {:?} glyphs", name, font.glyph_count());
+                self.custom_fonts.insert(name.to_string(), font);
+                true
+            }
+            Err(e) => {
+                error!("[GFX] Failed to load the font {:?}: {}", name, e);
+                false
+            }
+        }
+    }
+
+    /// Remove the fonts of the cartridge
+    pub fn font_clear(&mut self) {
+        self.custom_fonts.clear();
+        self.custom_font = None;
+    }
+
+    /// Pixel of a shape, with the fill pattern
     #[inline]
     pub fn putpixel(&mut self, x: i32, y: i32, col: u32) {
-        self.putpixel_(x, y, col);
+        if self.fill_pattern == 0 {
+            self.putpixel_(x, y, col);
+            return;
+        }
+
+        let fill = raster::Fill {
+            color: col,
+            pattern: self.fill_pattern,
+            secondary: self.fill_secondary,
+        };
+        if let Some(col) = fill.color_at(x - self.camera.x, y - self.camera.y) {
+            self.putpixel_(x, y, col);
+        }
+    }
+
+    /// Pattern of the next shapes (`rectfill`, `circfill`, `line` ...), bit 15
+    /// is the top-left pixel of each 4x4 tile of the screen. Its set bits are
+    /// drawn with `secondary`, or not drawn with -1; a pattern of 0 fills
+    /// the shapes again.
+    pub fn fillp(&mut self, pattern: u16, secondary: i32) {
+        self.fill_pattern = pattern;
+        self.fill_secondary = if secondary >= 0 && secondary <= 255 {
+            Some(secondary as u32)
+        } else {
+            None
+        };
+    }
+
+    /// Colors of a shape of the color `col` in the frame buffer
+    fn raster_fill(&mut self, col: i32) -> raster::Fill {
+        let col = self._find_color(col);
+        raster::Fill {
+            color: self.color_map[col as usize],
+            pattern: self.fill_pattern,
+            secondary: self.fill_secondary.map(|secondary| self.color_map[secondary as usize]),
+        }
     }
 
     #[inline]
@@ -539,6 +1429,79 @@ impl Screen {
         self._print(string, x, y, col, true);
     }
 
+    /// Width in pixels of the string with the current font
+    pub fn text_width(&self, string: &str) -> i32 {
+        if let Some(font) = self.get_custom_font() {
+            return font.text_width(string);
+        }
+
+        string.chars().map(|c| self.char_advance(c)).sum()
+    }
+
+    /// Horizontal distance to the next character with the built-in font, the
+    /// wide glyphs after ASCII take more
+    fn char_advance(&self, c: char) -> i32 {
+        match fonts::unicode::glyph(fonts::unicode::alias(c)) {
+            Some(glyph) => self.font.advance_width.max(glyph.width + 1),
+            None => self.font.advance_width,
+        }
+    }
+
+    fn get_custom_font(&self) -> Option<&BitmapFont> {
+        match self.custom_font {
+            Some(ref name) => self.custom_fonts.get(name),
+            None => None,
+        }
+    }
+
+    /// Text dump of the draw state (camera, clip, pal/palt) and of the indexed
+    /// frame buffer, one line per row with the color index of each pixel
+    pub fn dump_state(&self) -> String {
+        let mut data = String::new();
+
+        data.push_str(&format!("Size: {}x{}\n", self.width, self.height));
+        data.push_str(&format!("Camera: {} {}\n", self.camera.x, self.camera.y));
+        data.push_str(&format!("Clip: {} {} {} {}\n",
+                               self.cliprect.left,
+                               self.cliprect.top,
+                               self.cliprect.right,
+                               self.cliprect.bottom));
+        data.push_str(&format!("Color: {}\n", self.color));
+
+        data.push_str("Pal:");
+        for i in 0..256 {
+            if self.color_map[i] != i as u32 {
+                data.push_str(&format!(" {}->{}", i, self.color_map[i]));
+            }
+        }
+        data.push_str("\n");
+
+        data.push_str("Screen pal:");
+        for i in 0..256 {
+            if self.screen_map[i] != i as u32 {
+                data.push_str(&format!(" {}->{}", i, self.screen_map[i]));
+            }
+        }
+        data.push_str("\n");
+
+        data.push_str("Transparent:");
+        for i in 0..256 {
+            if self.transparency_map[i] {
+                data.push_str(&format!(" {}", i));
+            }
+        }
+        data.push_str("\n");
+
+        data.push_str("\n## Frame buffer\n\n");
+        for row in self.frame_buffer.chunks(self.width) {
+            let values: Vec<String> = row.iter().map(|value| format!("{:02x}", value)).collect();
+            data.push_str(&values.join(" "));
+            data.push_str("\n");
+        }
+
+        data
+    }
+
 
     pub fn print_char(&mut self, data: char, x: i32, y: i32, col: i32) {
         self._print(data.to_string(), x, y, col, false);
@@ -548,13 +1511,54 @@ impl Screen {
         self._print(string, x, y, col, false);
     }
 
+    /// Print with the font `name`, the current font stays selected
+    pub fn print_font(&mut self, string: String, x: i32, y: i32, col: i32, name: &str) {
+        let previous = self.get_font();
+        self.font(name);
+        self._print(string, x, y, col, false);
+        self.font(&previous);
+    }
+
     #[inline]
     pub fn _print(&mut self, string: String, x: i32, y: i32, col: i32, force: bool) {
+        let pixels = self.get_custom_font().map(|font| font.pixels(&string));
+        if let Some(pixels) = pixels {
+            for (dx, dy) in pixels {
+                self.print_pixel(x + dx, y + dy, col, force);
+            }
+            return;
+        }
+
         let mut x = x;
         let y = y + self.font.top_bearing;
 
-        for c in string.as_bytes() {
-            let glyph_index = if (*c < 32) || (*c > 126) { 0 } else { *c - 32 } as u32;
+        for c in string.chars() {
+            let c = fonts::unicode::alias(c);
+
+            // After ASCII, the accents are in the row above the glyphs
+            if let Some(glyph) = fonts::unicode::glyph(c) {
+                for (i, glyph_row) in glyph.rows.iter().enumerate() {
+                    for dx in 0..glyph.width {
+                        if glyph_row & (1 << (glyph.width - 1 - dx)) != 0 {
+                            self.print_pixel(x + self.font.left_bearing + dx,
+                                             y + (i as i32) - 1,
+                                             col,
+                                             force);
+                        }
+                    }
+                }
+
+                x += self.char_advance(c);
+                continue;
+            }
+
+            let glyph_index = if c < ' ' {
+                0
+            } else if c > '~' {
+                '?' as u32 - 32
+            } else {
+                c as u32 - 32
+            };
 
             let glyph_start = (glyph_index * (self.font.glyph_height as u32)) as usize;
             let glyph_end = glyph_start + (self.font.glyph_height as usize);
@@ -566,11 +1570,7 @@ impl Screen {
                 let mut row = *glyph_row;
                 while row != 0 {
                     if row & 0x80 != 0 {
-                        if force {
-                            self.putpixel_direct(x + dx, y + (i as i32), col as u32);
-                        } else {
-                            self.pset(x + dx, y + (i as i32), col);
-                        }
+                        self.print_pixel(x + dx, y + (i as i32), col, force);
                     }
                     row <<= 1;
                     dx += 1;
@@ -581,6 +1581,15 @@ impl Screen {
         }
     }
 
+    #[inline]
+    fn print_pixel(&mut self, x: i32, y: i32, col: i32, force: bool) {
+        if force {
+            self.putpixel_direct(x, y, col as u32);
+        } else {
+            self.pset(x, y, col);
+        }
+    }
+
     pub fn line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, col: i32) {
         // debug!("LINE {:?} {:?} {:?} {:?} {:?}", x0, y0, x1, y1, col);
 
@@ -597,7 +1606,7 @@ impl Screen {
         let mut err: i32 = dx + dy; /* error value e_xy */
 
         loop {
-            self.putpixel(x0, y0, color);
+            self.pen(x0, y0, color);
             if x0 == x1 && y0 == y1 {
                 break;
             }
@@ -614,6 +1623,60 @@ impl Screen {
         }
     }
 
+    /// Pen of `line_width` at the point of a line, a disc around it
+    fn pen(&mut self, x: i32, y: i32, col: u32) {
+        let w = self.line_width;
+        if w <= 1 {
+            self.putpixel(x, y, col);
+            return;
+        }
+
+        // Distances in half pixels, from the center of the pen
+        for j in 0..w {
+            for i in 0..w {
+                let (dx, dy) = (2 * i - (w - 1), 2 * j - (w - 1));
+                if dx * dx + dy * dy <= w * w {
+                    self.putpixel(x + i - (w - 1) / 2, y + j - (w - 1) / 2, col);
+                }
+            }
+        }
+    }
+
+    /// Pen of the next lines and curves, 1 to `MAX_LINE_WIDTH` pixels. The
+    /// previous width is returned.
+    pub fn line_width(&mut self, w: i32) -> i32 {
+        let previous = self.line_width;
+        self.line_width = cmp::max(1, cmp::min(w, MAX_LINE_WIDTH));
+        previous
+    }
+
+    /// Quadratic curve from `x0`/`y0` to `x1`/`y1`, bent toward the control
+    /// point `cx`/`cy`
+    pub fn bezier(&mut self, x0: i32, y0: i32, cx: i32, cy: i32, x1: i32, y1: i32, col: i32) {
+        // The control polygon is longer than the curve, a segment every 2
+        // pixels at most. In f64, the squares of far points overflow an i32
+        let length = (cx as f64 - x0 as f64).hypot(cy as f64 - y0 as f64) +
+                     (x1 as f64 - cx as f64).hypot(y1 as f64 - cy as f64);
+        let segments = cmp::max(1, cmp::min((length / 2.0).ceil() as i32, 512));
+
+        let (mut px, mut py) = (x0, y0);
+        for idx in 1..segments + 1 {
+            let t = idx as f64 / segments as f64;
+            let u = 1.0 - t;
+            let x = u * u * x0 as f64 + 2.0 * u * t * cx as f64 + t * t * x1 as f64;
+            let y = u * u * y0 as f64 + 2.0 * u * t * cy as f64 + t * t * y1 as f64;
+            let (x, y) = (x.round() as i32, y.round() as i32);
+
+            if (x, y) != (px, py) {
+                self.line(px, py, x, y, col);
+                px = x;
+                py = y;
+            } else if segments == 1 {
+                self.line(px, py, x, y, col);
+            }
+        }
+    }
+
     pub fn hline(&mut self, x1: i32, x2: i32, y: i32, col: i32) {
         let x_min = cmp::min(x1, x2);
         let x_max = cmp::max(x1, x2);
@@ -639,17 +1702,55 @@ impl Screen {
         }
     }
 
-    pub fn rectfill(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, col: i32) {
-        let x_min = cmp::min(x0, x1);
-        let x_max = cmp::max(x0, x1);
-        let y_min = cmp::min(y0, y1);
-        let y_max = cmp::max(y0, y1);
+    /// Clip rectangle, for the scanline fills
+    fn raster_bounds(&self) -> raster::Bounds {
+        raster::Bounds {
+            left: self.cliprect.left,
+            top: self.cliprect.top,
+            right: self.cliprect.right,
+            bottom: self.cliprect.bottom,
+        }
+    }
 
-        for y in y_min..(y_max + 1) {
-            for x in x_min..(x_max + 1) {
-                self.putpixel(x, y, col as u32);
-            }
+    pub fn rectfill(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, col: i32) {
+        let x_min = cmp::min(x0, x1) - self.camera.x;
+        let x_max = cmp::max(x0, x1) - self.camera.x;
+        let y_min = cmp::min(y0, y1) - self.camera.y;
+        let y_max = cmp::max(y0, y1) - self.camera.y;
+
+        let bounds = self.raster_bounds();
+        let fill = self.raster_fill(col);
+        raster::fill_rect(&mut self.frame_buffer,
+                          self.width,
+                          bounds,
+                          x_min,
+                          y_min,
+                          x_max,
+                          y_max,
+                          fill);
+    }
+
+    pub fn trifill(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, x3: i32, y3: i32, col: i32) {
+        let (cx, cy) = (self.camera.x, self.camera.y);
+        let points = [(x1 - cx, y1 - cy), (x2 - cx, y2 - cy), (x3 - cx, y3 - cy)];
+
+        let bounds = self.raster_bounds();
+        let fill = self.raster_fill(col);
+        raster::fill_triangle(&mut self.frame_buffer, self.width, bounds, points, fill);
+    }
+
+    /// Filled polygon of the points, concave or crossing itself (even-odd)
+    pub fn polyfill(&mut self, points: &[(i32, i32)], col: i32) {
+        if points.len() < 3 {
+            return;
         }
+
+        let (cx, cy) = (self.camera.x, self.camera.y);
+        let points: Vec<(i32, i32)> = points.iter().map(|&(x, y)| (x - cx, y - cy)).collect();
+
+        let bounds = self.raster_bounds();
+        let fill = self.raster_fill(col);
+        raster::fill_polygon(&mut self.frame_buffer, self.width, bounds, &points, fill);
     }
 
     pub fn square(&mut self, x0: i32, y0: i32, h: i32, col: i32) {
@@ -669,7 +1770,7 @@ impl Screen {
     }
 
     pub fn clip(&mut self, x: i32, y: i32, w: i32, h: i32) {
-        self._reset_cliprect();
+        self.cliprect = self.clip_base;
 
         if x == -1 && y == -1 && w == -1 && h == -1 {
             return;
@@ -684,6 +1785,38 @@ impl Screen {
                        });
     }
 
+    /// Start a clip layer inside the current clip, `clip` and the next
+    /// pushes can't draw outside of it
+    pub fn clip_push(&mut self, x: i32, y: i32, w: i32, h: i32) -> bool {
+        if self.clip_stack.len() >= MAX_DRAW_LAYERS {
+            warn!("[GFX] Too many clip layers, clip_pop is missing");
+            return false;
+        }
+
+        self.clip_stack.push((self.cliprect, self.clip_base));
+        self.cliprect
+            .intersect(&ClipRect {
+                           left: x,
+                           top: y,
+                           right: x + w,
+                           bottom: y + h,
+                       });
+        self.clip_base = self.cliprect;
+        true
+    }
+
+    /// Back to the clip before the last `clip_push`
+    pub fn clip_pop(&mut self) -> bool {
+        match self.clip_stack.pop() {
+            Some((cliprect, base)) => {
+                self.cliprect = cliprect;
+                self.clip_base = base;
+                true
+            }
+            None => false,
+        }
+    }
+
     // Original algorithm from SDL2 gfx project
     pub fn ellipse(&mut self, x: i32, y: i32, rx: i32, ry: i32, col: i32) {
         if rx <= 0 || ry <= 0 {
@@ -1145,6 +2278,119 @@ impl Screen {
         }
     }
 
+    /// Repeat the sprite in the `w`x`h` rectangle at the screen position
+    /// (camera already applied), the last column/row is cut
+    fn fill_cells(&mut self, idx_sprite: usize, x: i32, y: i32, w: i32, h: i32) {
+        // Skip the cells outside the clip rectangle
+        let (first_x, last_x) = visible_cells(x, (w + 7) / 8, self.cliprect.left, self.cliprect.right);
+        let (first_y, last_y) = visible_cells(y, (h + 7) / 8, self.cliprect.top, self.cliprect.bottom);
+
+        for idx_y in first_y..last_y {
+            let cell_h = cmp::min(8, h - idx_y * 8);
+
+            for idx_x in first_x..last_x {
+                let cell_w = cmp::min(8, w - idx_x * 8);
+
+                self.blit_cell_part(idx_sprite, x + idx_x * 8, y + idx_y * 8, cell_w, cell_h);
+            }
+        }
+    }
+
+    /// Fill the `w`x`h` pixels rectangle with the sprite `n`, for the
+    /// repeating backgrounds
+    pub fn spr_tile(&mut self, n: u32, x: i32, y: i32, w: i32, h: i32) {
+        if n as usize >= self.sprites.len() || w <= 0 || h <= 0 {
+            return;
+        }
+
+        let x = x - self.camera.x;
+        let y = y - self.camera.y;
+        self.fill_cells(n as usize, x, y, w, h);
+    }
+
+    /// Draw a `w`x`h` pixels panel with the 3x3 sprites starting at `n`:
+    /// the corners are drawn once, the borders and the center are repeated
+    pub fn spr_9slice(&mut self, n: u32, x: i32, y: i32, w: i32, h: i32) {
+        let n = n as usize;
+        if n + 102 >= self.sprites.len() {
+            return;
+        }
+
+        let w = cmp::max(w, 16);
+        let h = cmp::max(h, 16);
+        let x = x - self.camera.x;
+        let y = y - self.camera.y;
+
+        let right = x + w - 8;
+        let bottom = y + h - 8;
+
+        // Borders and center
+        self.fill_cells(n + 1, x + 8, y, w - 16, 8);
+        self.fill_cells(n + 50, x, y + 8, 8, h - 16);
+        self.fill_cells(n + 51, x + 8, y + 8, w - 16, h - 16);
+        self.fill_cells(n + 52, right, y + 8, 8, h - 16);
+        self.fill_cells(n + 101, x + 8, bottom, w - 16, 8);
+
+        // Corners
+        self.blit_cell(n, x, y);
+        self.blit_cell(n + 2, right, y);
+        self.blit_cell(n + 100, x, bottom);
+        self.blit_cell(n + 102, right, bottom);
+    }
+
+    /// Draw the 8x8 sprite `n` at all the positions, in one call for the
+    /// carts drawing hundreds of bullets/particles each frame
+    pub fn spr_batch(&mut self, n: u32, positions: &[(i32, i32)]) {
+        let idx_sprite = n as usize;
+        if idx_sprite >= self.sprites.len() {
+            return;
+        }
+
+        for &(x, y) in positions.iter() {
+            let x = x - self.camera.x;
+            let y = y - self.camera.y;
+
+            if x + 8 <= self.cliprect.left || y + 8 <= self.cliprect.top ||
+               x >= self.cliprect.right || y >= self.cliprect.bottom {
+                continue;
+            }
+
+            self.blit_cell(idx_sprite, x, y);
+        }
+    }
+
+    /// Draw the 8x8 sprite at the screen position (camera already applied),
+    /// the cells fully inside the clip rectangle are copied row by row
+    fn blit_cell(&mut self, idx_sprite: usize, x: i32, y: i32) {
+        self.blit_cell_part(idx_sprite, x, y, 8, 8);
+    }
+
+    /// Draw only the top left `w`x`h` pixels of the 8x8 sprite
+    fn blit_cell_part(&mut self, idx_sprite: usize, x: i32, y: i32, w: i32, h: i32) {
+        let inside = x >= self.cliprect.left && y >= self.cliprect.top &&
+                     x + w <= self.cliprect.right && y + h <= self.cliprect.bottom;
+
+        for row in 0..h {
+            let mut offset = if inside { self.pixel_offset(x, y + row) } else { 0 };
+
+            for col in 0..w {
+                let c = self.sprites[idx_sprite].data[(col + row * 8) as usize];
+
+                if !self.is_transparent(c) {
+                    let draw_col = self.color_map[c as usize];
+                    if inside {
+                        self.frame_buffer[offset] = draw_col;
+                    } else if self.cliprect.contains(x + col, y + row) {
+                        let offset = self.pixel_offset(x + col, y + row);
+                        self.frame_buffer[offset] = draw_col;
+                    }
+                }
+
+                offset += 1;
+            }
+        }
+    }
+
     pub fn mapdraw(&mut self,
                    cel_x: u32,
                    cel_y: u32,
@@ -1153,9 +2399,6 @@ impl Screen {
                    cel_w: u32,
                    cel_h: u32,
                    layer: u8) {
-        let mut idx_x;
-        let mut idx_y: i32 = 0;
-
         let mut cel_w = cel_w;
         if cel_w > unicorn::MAP_WIDTH as u32 {
             cel_w = unicorn::MAP_WIDTH as u32;
@@ -1175,53 +2418,202 @@ impl Screen {
                cel_h,
                layer);*/
 
-        while idx_y < cel_h as i32 {
-            idx_x = 0;
-            while idx_x < cel_w as i32 {
-                let orig_x = sx + 8 * idx_x;
-
-                let mut new_x = orig_x;
-                let mut new_y = sy + 8 * idx_y;
+        // Only the cells in the clip rectangle are drawn, so a scrolling game
+        // could draw the whole map each frame
+        let orig_x = sx - self.camera.x;
+        let orig_y = sy - self.camera.y;
+        let (first_x, last_x) =
+            visible_cells(orig_x, cel_w as i32, self.cliprect.left, self.cliprect.right);
+        let (first_y, last_y) =
+            visible_cells(orig_y, cel_h as i32, self.cliprect.top, self.cliprect.bottom);
+
+        let bounds = self.raster_bounds();
+        let width = self.width;
+        let Screen { ref mut frame_buffer, ref sprites, ref map, ref color_map, ref transparency_map, .. } =
+            *self;
+
+        let mut grid = raster::CellGrid {
+            origin: (orig_x + 8 * first_x, orig_y + 8 * first_y),
+            rows: Vec::new(),
+        };
 
-                let map_x = cel_x as i32 + idx_x;
-                let map_y = cel_y as i32 + idx_y;
+        for idx_y in first_y..last_y {
+            let map_y = (cel_y as usize + idx_y as usize) % unicorn::MAP_HEIGHT;
+            let mut row = Vec::new();
 
-                //debug!("MAP X {:?} MAP Y {:?}", map_x, map_y);
+            for idx_x in first_x..last_x {
+                let map_x = (cel_x as usize + idx_x as usize) % unicorn::MAP_WIDTH;
 
-                let idx_sprite: u32 = *self.map.get(((map_x as usize) % unicorn::MAP_WIDTH) * unicorn::MAP_WIDTH + (map_y as usize) % unicorn::MAP_HEIGHT).unwrap_or(&0);
-                //self.map[(map_x as usize) % unicorn::MAP_WIDTH][(map_y as usize) % unicorn::MAP_HEIGHT];
+                let idx_sprite = *map.get(map_x + map_y * unicorn::MAP_WIDTH).unwrap_or(&0) as usize;
 
                 // Skip the sprite 0
-                if idx_sprite != 0 {
-                    let mut sprite = self.sprites[idx_sprite as usize].clone();
-                    //debug!("GET SPRITE {:?}, {:?} {:?}", idx_sprite, map_x, map_y);
-
-                    // not the correct layer
-                    if layer == 0 || sprite.is_bit_flags_set(layer) {
-                        let mut index = 0;
-
-                        for (_, c) in sprite.data.iter_mut().enumerate() {
-                            if !self.is_transparent(*c as u32) {
-                                self.putpixel_(new_x, new_y, *c as u32);
-                            }
-
-                            index += 1;
-
-                            if index > 0 && index % 8 == 0 {
-                                new_y += 1;
-                                new_x = orig_x;
-                            } else {
-                                new_x += 1;
-                            }
-                        }
-                    }
+                if idx_sprite == 0 || idx_sprite >= sprites.len() {
+                    continue;
                 }
 
-                idx_x += 1;
+                // not the correct layer
+                if layer != 0 && !sprites[idx_sprite].is_bit_flags_set(layer) {
+                    continue;
+                }
+
+                row.push((idx_x - first_x, &sprites[idx_sprite].data[..]));
+            }
+
+            grid.rows.push(row);
+        }
+
+        raster::fill_cells(frame_buffer, width, bounds, &grid, |c| {
+            if c <= 255 && transparency_map[c as usize] {
+                None
+            } else {
+                Some(color_map[c as usize])
+            }
+        });
+    }
+
+    /// Map through an affine transform, like the background of the mode 7:
+    /// the pixel x/y of the screen shows the pixel (a*x + b*y + tx,
+    /// c*x + d*y + ty) of the map, which repeats itself
+    pub fn map_affine(&mut self, a: f64, b: f64, c: f64, d: f64, tx: f64, ty: f64, layer: u8) {
+        let (camera_x, camera_y) = (self.camera.x as f64, self.camera.y as f64);
+        let bounds = self.raster_bounds();
+        self.map_rows(bounds, layer, |y| {
+            let x0 = camera_x;
+            let y = y as f64 + camera_y;
+            Some((a * x0 + b * y + tx, c * x0 + d * y + ty, a, c))
+        });
+    }
+
+    /// One row of the map at the y of the screen: its pixel x shows the
+    /// pixel (u + du*x, v + dv*x) of the map. A transform per row gives the
+    /// ground planes in perspective
+    pub fn map_scanline(&mut self, y: i32, u: f64, v: f64, du: f64, dv: f64, layer: u8) {
+        let y = y - self.camera.y;
+        let mut bounds = self.raster_bounds();
+        bounds.top = cmp::max(bounds.top, y);
+        bounds.bottom = cmp::min(bounds.bottom, y + 1);
+
+        let camera_x = self.camera.x as f64;
+        self.map_rows(bounds,
+                      layer,
+                      |_| Some((u + du * camera_x, v + dv * camera_x, du, dv)));
+    }
+
+    /// Rows of the map sampled at the coordinates of `line`, in pixels
+    fn map_rows<L>(&mut self, bounds: raster::Bounds, layer: u8, line: L)
+        where L: Fn(i32) -> Option<(f64, f64, f64, f64)> + Sync
+    {
+        let width = self.width;
+        let Screen { ref mut frame_buffer, ref sprites, ref map, ref color_map, ref transparency_map, .. } =
+            *self;
+
+        let map_width = (unicorn::MAP_WIDTH * 8) as i32;
+        let map_height = (unicorn::MAP_HEIGHT * 8) as i32;
+
+        raster::fill_affine(frame_buffer, width, bounds, line, |u, v| {
+            let u = ((u % map_width) + map_width) % map_width;
+            let v = ((v % map_height) + map_height) % map_height;
+
+            let cell = (u / 8) as usize + (v / 8) as usize * unicorn::MAP_WIDTH;
+            let idx_sprite = *map.get(cell).unwrap_or(&0) as usize;
+
+            // Skip the sprite 0, and the other layers
+            if idx_sprite == 0 || idx_sprite >= sprites.len() {
+                return None;
+            }
+            if layer != 0 && !sprites[idx_sprite].is_bit_flags_set(layer) {
+                return None;
+            }
+
+            let c = sprites[idx_sprite].data[(u % 8) as usize + (v % 8) as usize * 8];
+            if c <= 255 && transparency_map[c as usize] {
+                None
+            } else {
+                Some(color_map[c as usize])
             }
+        });
+    }
+
+    /// Walls of the map seen from `x`/`y` (in cells) toward `angle`, in the
+    /// clip, the horizon in its middle. The cells of the sprites with the
+    /// flag `layer` are walls (all but the sprite 0 for the layer 0).
+    /// Returns the distance of the wall of each column of the clip, or
+    /// `max_distance`
+    pub fn raycast(&mut self,
+                   x: f64,
+                   y: f64,
+                   angle: f64,
+                   fov: f64,
+                   layer: u8,
+                   max_distance: f64)
+                   -> Vec<f64> {
+        let bounds = self.raster_bounds();
+        let columns = (bounds.right - bounds.left).max(0);
+        let mut depths = Vec::with_capacity(columns as usize);
+
+        let fov = fov.max(0.01).min(0.45);
+        let (dir, plane) = raycast::view_vectors(angle, fov);
+        // Height of a wall at the distance 1, the pixels are square
+        let focal = columns as f64 / 2.0 / (fov * PI).tan();
+        let horizon = (bounds.top + bounds.bottom) as f64 / 2.0;
+
+        let width = self.width;
+        let Screen { ref mut frame_buffer, ref sprites, ref map, ref color_map, ref transparency_map, .. } =
+            *self;
+
+        let sprite_at = |cx: i32, cy: i32| {
+            if cx < 0 || cy < 0 || cx as usize >= unicorn::MAP_WIDTH ||
+               cy as usize >= unicorn::MAP_HEIGHT {
+                return None;
+            }
+            let idx = *map.get(cx as usize + cy as usize * unicorn::MAP_WIDTH).unwrap_or(&0) as usize;
+            if idx == 0 || idx >= sprites.len() ||
+               (layer != 0 && !sprites[idx].is_bit_flags_set(layer)) {
+                return None;
+            }
+            Some(&sprites[idx])
+        };
+
+        for column in 0..columns {
+            let camera = 2.0 * (column as f64 + 0.5) / columns as f64 - 1.0;
+            let (dx, dy) = (dir.0 + plane.0 * camera, dir.1 + plane.1 * camera);
+
+            let is_wall = |cx, cy| sprite_at(cx, cy).is_some();
+            let hit = match raycast::cast(x, y, dx, dy, max_distance, is_wall) {
+                Some(hit) => hit,
+                None => {
+                    depths.push(max_distance);
+                    continue;
+                }
+            };
+            depths.push(hit.distance);
+
+            let sprite = sprite_at(hit.cell.0, hit.cell.1).unwrap();
+            let height = focal / hit.distance.max(0.0001);
+            let top = horizon - height / 2.0;
+            let tx = ((hit.u * 8.0) as usize).min(7);
+
+            let from = cmp::max(bounds.top, top.floor() as i32);
+            let to = cmp::min(bounds.bottom, (top + height).ceil() as i32);
+            for py in from..to {
+                let v = (py as f64 + 0.5 - top) / height;
+                if v < 0.0 || v >= 1.0 {
+                    continue;
+                }
 
-            idx_y += 1;
+                let mut c = sprite.data[tx + ((v * 8.0) as usize).min(7) * 8];
+                if c <= 255 && transparency_map[c as usize] {
+                    continue;
+                }
+                if !hit.x_side && c < 16 {
+                    c = gfx3d::DARKER[c as usize];
+                }
+                let offset = (bounds.left + column) as usize + py as usize * width;
+                frame_buffer[offset] = color_map[c as usize];
+            }
         }
+
+        depths
     }
 
     pub fn mget(&mut self, x: i32, y: i32) -> u32 {
@@ -1231,12 +2623,80 @@ impl Screen {
             return 0;
         }
 
-        if x as usize > unicorn::MAP_WIDTH || y as usize >= unicorn::MAP_HEIGHT {
+        if x as usize >= unicorn::MAP_WIDTH || y as usize >= unicorn::MAP_HEIGHT {
             return 0;
         }
 
-        0
-        //self.map[x as usize][y as usize]
+        *self.map.get(x as usize + y as usize * unicorn::MAP_WIDTH).unwrap_or(&0)
+    }
+
+    /// Flags of the sprite of the cell, for the collisions with the map
+    pub fn tile_flags(&mut self, x: i32, y: i32) -> u8 {
+        let idx = self.mget(x, y);
+        self.fget_all(idx)
+    }
+
+    /// A cell under the rectangle of pixels of the map has a sprite with the
+    /// flag, only the cells of the map are checked
+    pub fn collide_map(&mut self, x: f64, y: f64, w: f64, h: f64, flag: u8) -> bool {
+        let ((left, top), (right, bottom)) = match collision::rect_cells(x, y, w, h) {
+            Some(cells) => cells,
+            None => return false,
+        };
+        let (left, top) = (cmp::max(left, 0), cmp::max(top, 0));
+        let right = cmp::min(right, unicorn::MAP_WIDTH as i32 - 1);
+        let bottom = cmp::min(bottom, unicorn::MAP_HEIGHT as i32 - 1);
+
+        for cy in top..bottom + 1 {
+            for cx in left..right + 1 {
+                let idx = self.mget(cx, cy);
+                if self.fget(idx, flag) {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Opaque pixels of the blocks of `w`x`h` sprites drawn at `x1`/`y1` and
+    /// `x2`/`y2`, like `spr`, at the same place. The blocks are cut to the
+    /// size of the sheet.
+    pub fn sprite_overlap(&mut self,
+                          n1: u32,
+                          x1: i32,
+                          y1: i32,
+                          w1: i32,
+                          h1: i32,
+                          n2: u32,
+                          x2: i32,
+                          y2: i32,
+                          w2: i32,
+                          h2: i32)
+                          -> bool {
+        // 50 sprites per row of the sheet
+        let rows = ((self.sprites.len() + 49) / 50) as i32;
+        let block = |w: i32, h: i32| (cmp::max(0, cmp::min(w, 50)), cmp::max(0, cmp::min(h, rows)));
+        let (w1, h1) = block(w1, h1);
+        let (w2, h2) = block(w2, h2);
+
+        let sprites = &self.sprites;
+        let transparency_map = &self.transparency_map;
+        let opaque = |n: u32, x: i32, y: i32| {
+            let idx = n as i32 + x / 8 + (y / 8) * 50;
+            match sprites.get(idx as usize) {
+                Some(sprite) => {
+                    let col = sprite.data[((x % 8) + (y % 8) * 8) as usize];
+                    !transparency_map[(col & 0xff) as usize]
+                }
+                None => false,
+            }
+        };
+
+        collision::pixels_overlap((x1, y1, w1 * 8, h1 * 8),
+                                  (x2, y2, w2 * 8, h2 * 8),
+                                  |x, y| opaque(n1, x, y),
+                                  |x, y| opaque(n2, x, y))
     }
 
     pub fn mset(&mut self, x: i32, y: i32, v: u32) {
@@ -1246,11 +2706,16 @@ impl Screen {
             return;
         }
 
-        if x as usize > unicorn::MAP_WIDTH || y as usize >= unicorn::MAP_HEIGHT {
+        if x as usize >= unicorn::MAP_WIDTH || y as usize >= unicorn::MAP_HEIGHT {
             return;
         }
 
-  //      self.map[x as usize][y as usize] = v;
+        // The map of a cartridge without __map__ section is empty
+        if self.map.len() < unicorn::MAP_WIDTH * unicorn::MAP_HEIGHT {
+            self.map.resize(unicorn::MAP_WIDTH * unicorn::MAP_HEIGHT, 0);
+        }
+
+        self.map[x as usize + y as usize * unicorn::MAP_WIDTH] = v;
     }
 
     pub fn sspr(&mut self,
@@ -1491,6 +2956,118 @@ impl Screen {
         return self._sprite_rotazoom(v, sw, sh, destx, desty, angle, zoom, flip_x, flip_y)
     }
 
+    /// Draw the `sw`x`sh` pixels of the sprite sheet at `sx`/`sy`, scaled to
+    /// `dw`x`dh` and rotated by `angle` (in turns, counter-clockwise like
+    /// `cos`/`sin`) around the center of the destination rectangle. Each pixel
+    /// of the rotated box is mapped back to the sheet with 16.16 fixed-point
+    /// steps, two additions per pixel. The source is clamped to the sheet,
+    /// and only the pixels of the box in the clipping rectangle are visited.
+    pub fn sspr_rot(&mut self,
+                    sx: u32,
+                    sy: u32,
+                    sw: u32,
+                    sh: u32,
+                    dx: i32,
+                    dy: i32,
+                    dw: u32,
+                    dh: u32,
+                    angle: f64,
+                    flip_x: bool,
+                    flip_y: bool) {
+        // 50 sprites per row of the sheet
+        let sheet_width = 400;
+        let sheet_height = ((self.sprites.len() as u32 + 49) / 50) * 8;
+        if sx >= sheet_width || sy >= sheet_height {
+            return;
+        }
+        let sw = cmp::min(sw, sheet_width - sx);
+        let sh = cmp::min(sh, sheet_height - sy);
+        if sw == 0 || sh == 0 || dw == 0 || dh == 0 {
+            return;
+        }
+
+        let mut pixels = Vec::with_capacity((sw * sh) as usize);
+        for y in sy..sy + sh {
+            for x in sx..sx + sw {
+                let idx_sprite = ((x / 8) + 50 * (y / 8)) as usize;
+                let value = match self.sprites.get(idx_sprite) {
+                    Some(sprite) => sprite.data[((x % 8) + (y % 8) * 8) as usize],
+                    None => 0,
+                };
+                pixels.push(value);
+            }
+        }
+
+        // Counter-clockwise on the screen, where the y axis goes down
+        let (sin, cos) = (angle * 2.0 * PI).sin_cos();
+
+        let center_x = dx as f64 + dw as f64 / 2.0;
+        let center_y = dy as f64 + dh as f64 / 2.0;
+        let half_w = (dw as f64 / 2.0 * cos).abs() + (dh as f64 / 2.0 * sin).abs();
+        let half_h = (dw as f64 / 2.0 * sin).abs() + (dh as f64 / 2.0 * cos).abs();
+
+        // The box in the clipping rectangle, moved by the camera
+        let clip_x0 = (self.cliprect.left + self.camera.x) as f64;
+        let clip_x1 = (self.cliprect.right + self.camera.x) as f64;
+        let clip_y0 = (self.cliprect.top + self.camera.y) as f64;
+        let clip_y1 = (self.cliprect.bottom + self.camera.y) as f64;
+        let x0 = (center_x - half_w).floor().max(clip_x0) as i32;
+        let x1 = (center_x + half_w).ceil().min(clip_x1) as i32;
+        let y0 = (center_y - half_h).floor().max(clip_y0) as i32;
+        let y1 = (center_y + half_h).ceil().min(clip_y1) as i32;
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+
+        // Steps in the sheet for one pixel of the screen, in 16.16
+        let scale_x = sw as f64 / dw as f64;
+        let scale_y = sh as f64 / dh as f64;
+        let fixed = |value: f64| (value * 65536.0) as i64;
+        let du_dx = fixed(cos * scale_x);
+        let dv_dx = fixed(sin * scale_y);
+        let du_dy = fixed(-sin * scale_x);
+        let dv_dy = fixed(cos * scale_y);
+
+        // Sheet coordinates of the center of the first pixel
+        let rx = x0 as f64 + 0.5 - center_x;
+        let ry = y0 as f64 + 0.5 - center_y;
+        let mut row_u = fixed((rx * cos - ry * sin) * scale_x + sw as f64 / 2.0);
+        let mut row_v = fixed((rx * sin + ry * cos) * scale_y + sh as f64 / 2.0);
+
+        let sw = sw as i64;
+        let sh = sh as i64;
+
+        for y in y0..y1 {
+            let mut u = row_u;
+            let mut v = row_v;
+
+            for x in x0..x1 {
+                let mut src_x = u >> 16;
+                let mut src_y = v >> 16;
+
+                if src_x >= 0 && src_y >= 0 && src_x < sw && src_y < sh {
+                    if flip_x {
+                        src_x = sw - 1 - src_x;
+                    }
+                    if flip_y {
+                        src_y = sh - 1 - src_y;
+                    }
+
+                    let d = pixels[(src_x + src_y * sw) as usize];
+                    if !self.is_transparent(d) {
+                        self.putpixel_(x, y, d);
+                    }
+                }
+
+                u += du_dx;
+                v += dv_dx;
+            }
+
+            row_u += du_dy;
+            row_v += dv_dy;
+        }
+    }
+
     #[inline]
     pub fn is_transparent(&self, value: u32) -> bool {
         if value <= 255 {
@@ -1503,11 +3080,160 @@ impl Screen {
     pub fn pal(&mut self, c0: i32, c1: i32) {
         if c0 < 0 || c1 < 0 {
             self._reset_colors();
+            self._reset_screen_colors();
+            self._update_display_map();
         } else {
             self.color_map[c0 as usize] = c1 as u32;
         }
     }
 
+    /// Display the color `c0` of the frame buffer as `c1`, the pixels already
+    /// drawn change too
+    pub fn pal_screen(&mut self, c0: i32, c1: i32) {
+        if c0 < 0 || c1 < 0 {
+            self._reset_screen_colors();
+        } else if c0 < 256 {
+            self.screen_map[c0 as usize] = c1 as u32;
+        }
+        self._update_display_map();
+    }
+
+    /// Rotate the colors `first..last` at `speed` shifts per second, without
+    /// changing the frame buffer; a speed of 0 stops the cycle of the range
+    pub fn pal_cycle(&mut self, first: u32, last: u32, speed: f64) {
+        let cycle = PaletteCycle::new(cmp::min(first, 255), cmp::min(last, 255), speed);
+
+        self.pal_cycles.retain(|c| c.first != cycle.first || c.last != cycle.last);
+        if speed != 0.0 {
+            self.pal_cycles.push(cycle);
+        }
+        self._update_display_map();
+    }
+
+    pub fn pal_cycle_reset(&mut self) {
+        self.pal_cycles.clear();
+        self.pal_cycle_time = 0.0;
+        self._update_display_map();
+    }
+
+    pub fn pal_cycle_update(&mut self, dt: f64) {
+        if !self.pal_cycles.is_empty() {
+            self.pal_cycle_time += dt;
+            self._update_display_map();
+        }
+    }
+
+    /// All the particles, with the camera and the clip of the cartridge
+    pub fn particles_draw(&mut self) {
+        let particles = mem::replace(&mut self.particles, Particles::new());
+
+        for emitter in particles.emitters.iter() {
+            if let Some(sprite) = emitter.def.sprite {
+                let positions: Vec<(i32, i32)> = emitter
+                    .particles
+                    .iter()
+                    .map(|particle| (particle.x.round() as i32 - 4, particle.y.round() as i32 - 4))
+                    .collect();
+                self.spr_batch(sprite, &positions);
+                continue;
+            }
+
+            for particle in emitter.particles.iter() {
+                let (color, radius) = particle.look(&emitter.def);
+                let (x, y) = (particle.x.round() as i32, particle.y.round() as i32);
+                if radius < 0.5 {
+                    self.pset(x, y, color as i32);
+                } else {
+                    self.circfill(x, y, radius.round() as i32, color as i32);
+                }
+            }
+        }
+
+        self.particles = particles;
+    }
+
+    /// Queue the faces of the 3D mesh `id` with the current matrix
+    pub fn draw3d(&mut self, id: u32) {
+        let (width, height) = (self.width, self.height);
+        self.gfx3d.draw(id, width, height);
+    }
+
+    /// Queue a 3D triangle with the current matrix
+    pub fn tri3d(&mut self, points: [[f64; 3]; 3], col: i32) {
+        let (width, height) = (self.width, self.height);
+        let col = self._find_color(col);
+        self.gfx3d.triangle(points, col, width, height);
+    }
+
+    /// Queued 3D triangles, with the camera and the clip of the cartridge.
+    /// Returns the number of triangles drawn
+    pub fn render3d(&mut self) -> usize {
+        let queue = self.gfx3d.take_queue();
+        let (cx, cy) = (self.camera.x as f64, self.camera.y as f64);
+        let bounds = self.raster_bounds();
+
+        if self.gfx3d.mode == DepthMode::Buffer {
+            let len = self.frame_buffer.len();
+            let depth = self.gfx3d.depth_buffer(len);
+            for triangle in queue.iter() {
+                let mut points = triangle.points;
+                for point in points.iter_mut() {
+                    point[0] -= cx;
+                    point[1] -= cy;
+                }
+                gfx3d::fill_triangle_depth(&mut self.frame_buffer,
+                                           depth,
+                                           self.width,
+                                           bounds,
+                                           points,
+                                           triangle.fill(&self.color_map));
+            }
+        } else {
+            for triangle in queue.iter() {
+                let point = |idx: usize| {
+                    ((triangle.points[idx][0] - cx).round() as i32,
+                     (triangle.points[idx][1] - cy).round() as i32)
+                };
+                raster::fill_triangle(&mut self.frame_buffer,
+                                      self.width,
+                                      bounds,
+                                      [point(0), point(1), point(2)],
+                                      triangle.fill(&self.color_map));
+            }
+        }
+
+        queue.len()
+    }
+
+    fn _update_display_map(&mut self) {
+        for i in 0..256 {
+            self.display_map[i] = i as u32;
+        }
+
+        for cycle in self.pal_cycles.iter() {
+            for value in cycle.first..cycle.last + 1 {
+                self.display_map[value as usize] = cycle.color(value, self.pal_cycle_time);
+            }
+        }
+
+        for i in 0..256 {
+            let value = self.display_map[i];
+            if value < 256 {
+                self.display_map[i] = self.screen_map[value as usize];
+            }
+        }
+    }
+
+    /// Color to display for a value of the frame buffer
+    #[inline]
+    pub fn display_color(&self, value: u32) -> u32 {
+        if value < 256 {
+            self.display_map[value as usize]
+        } else {
+            value
+        }
+    }
+
     pub fn palt(&mut self, c: i32, t: bool) {
         if c == -1 {
             self._reset_transparency();
@@ -1517,32 +3243,30 @@ impl Screen {
     }
 
     pub fn peek(&mut self, addr: u32) -> u8 {
-        self.frame_buffer[addr as usize] as u8
+        memory::peek(self, addr as usize)
     }
 
-    pub fn poke(&mut self, _addr: u32, _val: u16) {}
+    pub fn poke(&mut self, addr: u32, val: u8) {
+        memory::poke(self, addr as usize, val);
+    }
 
     pub fn memcpy(&mut self, dest_addr: u32, source_addr: u32, len: u32) {
-        let mut idx = 0;
-
-        let dest_addr = dest_addr * 2;
-        let source_addr = source_addr * 2;
-
         debug!("MEMPCY dest_addr {:?}, source_addr {:?}, len {:?}",
                dest_addr,
                source_addr,
                len);
 
-        let a = &self.frame_buffer[source_addr as usize..(source_addr + len * 2) as usize].to_vec();
-
-        while idx < len * 2 {
-            let value = a[idx as usize] as u32;
+        memory::memcpy(self, dest_addr as usize, source_addr as usize, len as usize);
+    }
 
-            self.frame_buffer[(dest_addr + idx) as usize] = value as u32;
+    pub fn memset(&mut self, dest_addr: u32, val: u8, len: u32) {
+        memory::memset(self, dest_addr as usize, val, len as usize);
+    }
 
-            idx += 1;
+    /// Zeroes of the user memory, for the next cartridge
+    pub fn memory_clear(&mut self) {
+        for value in self.memory.iter_mut() {
+            *value = 0;
         }
     }
-
-    pub fn memset(&mut self, _dest_addr: u32, _val: u32, _len: u32) {}
 }
@@ -0,0 +1,287 @@
+//! Particles of the cartridges, `particles_spawn(def)`: the emitters and
+//! their particles are moved by the console with the time of the game, and
+//! `particles_draw()` draws all of them in one call, so the effects with
+//! thousands of particles don't cost a frame of Lua.
+
+use std::f64::consts::PI;
+
+use unicorn::random::Random;
+
+/// Particles alive at once, the next ones aren't spawned
+pub const MAX_PARTICLES: usize = 10000;
+
+/// Same particles in each run of a cartridge
+const PARTICLES_SEED: u64 = 0x7061727469636c65;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EmitterDef {
+    pub x: f64,
+    pub y: f64,
+    /// Particles at once when spawned
+    pub count: u32,
+    /// Particles per second, until the end of `duration`
+    pub rate: f64,
+    /// Seconds of the emission, until `particles_stop` when negative
+    pub duration: f64,
+    /// Seconds of a particle, more or less `life_var`
+    pub life: f64,
+    pub life_var: f64,
+    /// Pixels per second, more or less `speed_var`
+    pub speed: f64,
+    pub speed_var: f64,
+    /// Direction in turns like `cos`/`sin` (0.25 is up), and the part of
+    /// the turn around it (1 for all the directions)
+    pub angle: f64,
+    pub spread: f64,
+    /// Pixels per second per second, downward when positive
+    pub gravity: f64,
+    /// Part of the speed lost each second
+    pub drag: f64,
+    /// Colors from the birth to the death of a particle
+    pub colors: Vec<u32>,
+    /// 8x8 sprite drawn instead of the colors
+    pub sprite: Option<u32>,
+    /// Radius at the birth and at the death, a pixel below 0.5
+    pub size: f64,
+    pub size_end: f64,
+}
+
+impl EmitterDef {
+    pub fn new() -> EmitterDef {
+        EmitterDef {
+            x: 0.0,
+            y: 0.0,
+            count: 0,
+            rate: 0.0,
+            duration: -1.0,
+            life: 1.0,
+            life_var: 0.0,
+            speed: 20.0,
+            speed_var: 0.0,
+            angle: 0.25,
+            spread: 1.0,
+            gravity: 0.0,
+            drag: 0.0,
+            colors: vec![7],
+            sprite: None,
+            size: 0.0,
+            size_end: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Particle {
+    pub x: f64,
+    pub y: f64,
+    pub vx: f64,
+    pub vy: f64,
+    pub age: f64,
+    pub life: f64,
+}
+
+impl Particle {
+    /// Color and radius at its age
+    pub fn look(&self, def: &EmitterDef) -> (u32, f64) {
+        let t = if self.life > 0.0 { (self.age / self.life).min(1.0) } else { 1.0 };
+
+        let color = if def.colors.is_empty() {
+            7
+        } else {
+            let idx = (t * def.colors.len() as f64) as usize;
+            def.colors[idx.min(def.colors.len() - 1)]
+        };
+
+        (color, def.size + (def.size_end - def.size) * t)
+    }
+}
+
+/// The particles stay with their emitter, which lives until the last one
+pub struct Emitter {
+    pub id: u32,
+    pub def: EmitterDef,
+    pub age: f64,
+    pub stopped: bool,
+    /// Part of a particle carried to the next update
+    pending: f64,
+    pub particles: Vec<Particle>,
+}
+
+impl Emitter {
+    fn is_emitting(&self) -> bool {
+        !self.stopped && self.def.rate > 0.0 &&
+        (self.def.duration < 0.0 || self.age < self.def.duration)
+    }
+}
+
+pub struct Particles {
+    next_id: u32,
+    pub emitters: Vec<Emitter>,
+    random: Random,
+}
+
+/// Value more or less `var`
+fn vary(random: &mut Random, value: f64, var: f64) -> f64 {
+    value + (random.rnd(2.0) - 1.0) * var
+}
+
+fn spawn_particles(emitter: &mut Emitter, random: &mut Random, count: usize) {
+    let def = &emitter.def;
+    for _ in 0..count {
+        let angle = def.angle + (random.rnd(1.0) - 0.5) * def.spread;
+        let speed = vary(random, def.speed, def.speed_var);
+        emitter.particles.push(Particle {
+                                   x: def.x,
+                                   y: def.y,
+                                   // The y axis goes down, like `sin`
+                                   vx: (angle * 2.0 * PI).cos() * speed,
+                                   vy: -(angle * 2.0 * PI).sin() * speed,
+                                   age: 0.0,
+                                   life: vary(random, def.life, def.life_var).max(0.0),
+                               });
+    }
+}
+
+impl Particles {
+    pub fn new() -> Particles {
+        Particles {
+            next_id: 1,
+            emitters: Vec::new(),
+            random: Random::from_seed(PARTICLES_SEED),
+        }
+    }
+
+    /// No particle, at the start of a cartridge
+    pub fn clear(&mut self) {
+        *self = Particles::new();
+    }
+
+    pub fn count(&self) -> usize {
+        self.emitters.iter().map(|emitter| emitter.particles.len()).sum()
+    }
+
+    pub fn spawn(&mut self, def: EmitterDef) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+
+        let count = (def.count as usize).min(MAX_PARTICLES.saturating_sub(self.count()));
+        let mut emitter = Emitter {
+            id: id,
+            def: def,
+            age: 0.0,
+            stopped: false,
+            pending: 0.0,
+            particles: Vec::new(),
+        };
+        spawn_particles(&mut emitter, &mut self.random, count);
+
+        self.emitters.push(emitter);
+        id
+    }
+
+    /// The next particles start from x/y
+    pub fn move_to(&mut self, id: u32, x: f64, y: f64) {
+        if let Some(emitter) = self.emitters.iter_mut().find(|emitter| emitter.id == id) {
+            emitter.def.x = x;
+            emitter.def.y = y;
+        }
+    }
+
+    /// No more particle, the ones alive keep moving until their death
+    pub fn stop(&mut self, id: u32) {
+        if let Some(emitter) = self.emitters.iter_mut().find(|emitter| emitter.id == id) {
+            emitter.stopped = true;
+        }
+    }
+
+    pub fn update(&mut self, dt: f64) {
+        let mut alive = self.count();
+
+        for emitter in self.emitters.iter_mut() {
+            let (gravity, drag) = (emitter.def.gravity, emitter.def.drag);
+            let slowdown = (1.0 - drag * dt).max(0.0);
+
+            let before = emitter.particles.len();
+            emitter.particles.retain(|particle| particle.age + dt < particle.life);
+            for particle in emitter.particles.iter_mut() {
+                particle.age += dt;
+                particle.vy += gravity * dt;
+                particle.vx *= slowdown;
+                particle.vy *= slowdown;
+                particle.x += particle.vx * dt;
+                particle.y += particle.vy * dt;
+            }
+            alive -= before - emitter.particles.len();
+
+            if emitter.is_emitting() {
+                emitter.pending += emitter.def.rate * dt;
+                let count = emitter.pending.floor();
+                emitter.pending -= count;
+
+                let count = (count as usize).min(MAX_PARTICLES.saturating_sub(alive));
+                spawn_particles(emitter, &mut self.random, count);
+                alive += count;
+            }
+            emitter.age += dt;
+        }
+
+        self.emitters
+            .retain(|emitter| emitter.is_emitting() || !emitter.particles.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EmitterDef, Particles, MAX_PARTICLES};
+
+    #[test]
+    fn test_particles() {
+        let mut particles = Particles::new();
+
+        let mut def = EmitterDef::new();
+        def.count = 10;
+        def.life = 0.5;
+        def.speed = 10.0;
+        def.angle = 0.0;
+        def.spread = 0.0;
+        def.colors = vec![8, 9];
+        particles.spawn(def.clone());
+        assert_eq!(particles.count(), 10);
+
+        particles.update(0.1);
+        let particle = particles.emitters[0].particles[0].clone();
+        assert!((particle.x - 1.0).abs() < 1e-9);
+        assert!(particle.y.abs() < 1e-9);
+        assert_eq!(particle.look(&def).0, 8);
+
+        // Dead, the emitter of the burst too
+        particles.update(0.5);
+        assert_eq!(particles.count(), 0);
+        assert!(particles.emitters.is_empty());
+
+        // 30 particles per second, the gravity pulls them down
+        def.count = 0;
+        def.life = 1.0;
+        def.rate = 30.0;
+        def.gravity = 100.0;
+        let id = particles.spawn(def.clone());
+        for _ in 0..10 {
+            particles.update(0.05);
+        }
+        assert_eq!(particles.count(), 15);
+        assert!(particles.emitters[0].particles[0].vy > 0.0);
+
+        particles.stop(id);
+        particles.update(1.0);
+        assert_eq!(particles.count(), 0);
+        assert!(particles.emitters.is_empty());
+
+        def.count = MAX_PARTICLES as u32 + 100;
+        def.rate = 0.0;
+        particles.spawn(def);
+        assert_eq!(particles.count(), MAX_PARTICLES);
+
+        particles.clear();
+        assert_eq!(particles.count(), 0);
+    }
+}
@@ -0,0 +1,499 @@
+//! Scanline fills of the frame buffer for the big shapes (`rectfill`,
+//! `trifill`, `polyfill`, `mapdraw`, `map_affine`). The rows are independent, with the `rayon` feature
+//! they are split over the threads of the pool once the shape covers
+//! `PARALLEL_PIXELS`; the small ones stay on the current thread, where the
+//! cost of the pool is bigger than the fill. The web build has no threads.
+
+use std::cmp;
+use std::ops::Range;
+
+#[cfg(all(feature = "rayon", not(target_os = "emscripten")))]
+use rayon::prelude::*;
+
+/// Pixels of a shape from which its rows are filled in parallel
+pub const PARALLEL_PIXELS: usize = 128 * 128;
+
+/// Visible area, in the coordinates of the frame buffer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub left: i32,
+    pub top: i32,
+    /// Excluded
+    pub right: i32,
+    /// Excluded
+    pub bottom: i32,
+}
+
+/// Color of the pixels of a shape, with the 4x4 pattern of `fillp`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fill {
+    pub color: u32,
+    /// Bit 15 is the top-left pixel of the tile, the set bits use `secondary`
+    pub pattern: u16,
+    /// None: the pixels of the pattern are not drawn
+    pub secondary: Option<u32>,
+}
+
+impl Fill {
+    pub fn solid(color: u32) -> Fill {
+        Fill {
+            color: color,
+            pattern: 0,
+            secondary: None,
+        }
+    }
+
+    /// Color of the pixel `x`/`y` of the frame buffer, the tiles of the
+    /// pattern are aligned on the screen
+    #[inline]
+    pub fn color_at(&self, x: i32, y: i32) -> Option<u32> {
+        let bit = 15 - ((y & 3) * 4 + (x & 3));
+        if self.pattern & (1 << bit) != 0 {
+            self.secondary
+        } else {
+            Some(self.color)
+        }
+    }
+
+    /// Pixels `left..right` of the row `y`
+    #[inline]
+    fn span(&self, row: &mut [u32], y: i32, left: i32, right: i32) {
+        if self.pattern == 0 {
+            for pixel in row[left as usize..right as usize].iter_mut() {
+                *pixel = self.color;
+            }
+            return;
+        }
+
+        for x in left..right {
+            if let Some(color) = self.color_at(x, y) {
+                row[x as usize] = color;
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "rayon", not(target_os = "emscripten")))]
+fn for_each_row<F>(frame_buffer: &mut [u32], width: usize, rows: Range<usize>, parallel: bool, f: F)
+    where F: Fn(usize, &mut [u32]) + Sync
+{
+    let start = rows.start;
+    let buffer = &mut frame_buffer[rows.start * width..rows.end * width];
+    if parallel {
+        buffer
+            .par_chunks_mut(width)
+            .enumerate()
+            .for_each(|(idx, row)| f(start + idx, row));
+    } else {
+        for (idx, row) in buffer.chunks_mut(width).enumerate() {
+            f(start + idx, row);
+        }
+    }
+}
+
+#[cfg(not(all(feature = "rayon", not(target_os = "emscripten"))))]
+fn for_each_row<F>(frame_buffer: &mut [u32], width: usize, rows: Range<usize>, _parallel: bool, f: F)
+    where F: Fn(usize, &mut [u32]) + Sync
+{
+    let start = rows.start;
+    let buffer = &mut frame_buffer[rows.start * width..rows.end * width];
+    for (idx, row) in buffer.chunks_mut(width).enumerate() {
+        f(start + idx, row);
+    }
+}
+
+/// Rectangle `x0..=x1`, `y0..=y1` clipped by the bounds
+pub fn fill_rect(frame_buffer: &mut [u32],
+                 width: usize,
+                 bounds: Bounds,
+                 x0: i32,
+                 y0: i32,
+                 x1: i32,
+                 y1: i32,
+                 fill: Fill) {
+    let left = cmp::max(x0, bounds.left);
+    let right = cmp::min(x1 + 1, bounds.right);
+    let top = cmp::max(y0, bounds.top);
+    let bottom = cmp::min(y1 + 1, bounds.bottom);
+    if left >= right || top >= bottom {
+        return;
+    }
+
+    let pixels = (right - left) as usize * (bottom - top) as usize;
+    for_each_row(frame_buffer,
+                 width,
+                 top as usize..bottom as usize,
+                 pixels >= PARALLEL_PIXELS,
+                 |y, row| fill.span(row, y as i32, left, right));
+}
+
+/// X of the edge on the row `y`, in f64: the points far out of the screen
+/// don't overflow
+fn edge_x(a: (i32, i32), b: (i32, i32), y: i32) -> f64 {
+    a.0 as f64 + (y as f64 - a.1 as f64) * (b.0 as f64 - a.0 as f64) / (b.1 as f64 - a.1 as f64)
+}
+
+/// First and last x of the triangle on the row `y`
+fn triangle_span(points: &[(i32, i32); 3], y: i32) -> Option<(i32, i32)> {
+    let mut x_min = i32::max_value();
+    let mut x_max = i32::min_value();
+
+    for idx in 0..3 {
+        let (ax, ay) = points[idx];
+        let (bx, by) = points[(idx + 1) % 3];
+
+        if y < cmp::min(ay, by) || y > cmp::max(ay, by) {
+            continue;
+        }
+
+        if ay == by {
+            x_min = cmp::min(x_min, cmp::min(ax, bx));
+            x_max = cmp::max(x_max, cmp::max(ax, bx));
+        } else {
+            let x = edge_x((ax, ay), (bx, by), y);
+            let x = x.round() as i32;
+            x_min = cmp::min(x_min, x);
+            x_max = cmp::max(x_max, x);
+        }
+    }
+
+    if x_min <= x_max { Some((x_min, x_max)) } else { None }
+}
+
+/// Filled triangle, the edges included
+pub fn fill_triangle(frame_buffer: &mut [u32],
+                     width: usize,
+                     bounds: Bounds,
+                     points: [(i32, i32); 3],
+                     fill: Fill) {
+    let y_min = points.iter().map(|p| p.1).min().unwrap();
+    let y_max = points.iter().map(|p| p.1).max().unwrap();
+    let x_min = points.iter().map(|p| p.0).min().unwrap();
+    let x_max = points.iter().map(|p| p.0).max().unwrap();
+
+    let top = cmp::max(y_min, bounds.top);
+    let bottom = cmp::min(y_max.saturating_add(1), bounds.bottom);
+    let left = cmp::max(x_min, bounds.left);
+    let right = cmp::min(x_max.saturating_add(1), bounds.right);
+    if top >= bottom || left >= right {
+        return;
+    }
+
+    // The half of the visible bounding box, roughly the area of the triangle
+    let pixels = (right - left) as usize * (bottom - top) as usize / 2;
+    for_each_row(frame_buffer,
+                 width,
+                 top as usize..bottom as usize,
+                 pixels >= PARALLEL_PIXELS,
+                 |y, row| if let Some((x0, x1)) = triangle_span(&points, y as i32) {
+                     let left = cmp::max(x0, bounds.left);
+                     let right = cmp::min(x1.saturating_add(1), bounds.right);
+                     if left < right {
+                         fill.span(row, y as i32, left, right);
+                     }
+                 });
+}
+
+/// Spans of the polygon on the row `y`, sorted and merged: the inside by
+/// the even-odd rule, and the pixels of the edges
+fn polygon_spans(points: &[(i32, i32)], y: i32) -> Vec<(i32, i32)> {
+    let mut crossings = Vec::new();
+    let mut spans = Vec::new();
+
+    for idx in 0..points.len() {
+        let (ax, ay) = points[idx];
+        let (bx, by) = points[(idx + 1) % points.len()];
+
+        if y < cmp::min(ay, by) || y > cmp::max(ay, by) {
+            continue;
+        }
+
+        if ay == by {
+            spans.push((cmp::min(ax, bx), cmp::max(ax, bx)));
+            continue;
+        }
+
+        let x = edge_x((ax, ay), (bx, by), y);
+        spans.push((x.round() as i32, x.round() as i32));
+
+        // The lowest end of the edge is not counted, a vertex between two
+        // edges is crossed once
+        if y < cmp::max(ay, by) {
+            crossings.push(x);
+        }
+    }
+
+    crossings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    for pair in crossings.chunks(2) {
+        if pair.len() == 2 {
+            spans.push((pair[0].round() as i32, pair[1].round() as i32));
+        }
+    }
+
+    spans.sort();
+    let mut merged: Vec<(i32, i32)> = Vec::with_capacity(spans.len());
+    for (x0, x1) in spans {
+        if let Some(last) = merged.last_mut() {
+            if x0 <= last.1.saturating_add(1) {
+                last.1 = cmp::max(last.1, x1);
+                continue;
+            }
+        }
+        merged.push((x0, x1));
+    }
+    merged
+}
+
+/// Filled polygon, the edges included, the crossing ones too
+pub fn fill_polygon(frame_buffer: &mut [u32],
+                    width: usize,
+                    bounds: Bounds,
+                    points: &[(i32, i32)],
+                    fill: Fill) {
+    if points.is_empty() {
+        return;
+    }
+
+    let y_min = points.iter().map(|p| p.1).min().unwrap();
+    let y_max = points.iter().map(|p| p.1).max().unwrap();
+    let x_min = points.iter().map(|p| p.0).min().unwrap();
+    let x_max = points.iter().map(|p| p.0).max().unwrap();
+
+    let top = cmp::max(y_min, bounds.top);
+    let bottom = cmp::min(y_max.saturating_add(1), bounds.bottom);
+    let left = cmp::max(x_min, bounds.left);
+    let right = cmp::min(x_max.saturating_add(1), bounds.right);
+    if top >= bottom || left >= right {
+        return;
+    }
+
+    // The visible bounding box, the one of the points could overflow
+    let pixels = (right - left) as usize * (bottom - top) as usize / 2;
+    for_each_row(frame_buffer,
+                 width,
+                 top as usize..bottom as usize,
+                 pixels >= PARALLEL_PIXELS,
+                 |y, row| for (x0, x1) in polygon_spans(points, y as i32) {
+                     let left = cmp::max(x0, bounds.left);
+                     let right = cmp::min(x1.saturating_add(1), bounds.right);
+                     if left < right {
+                         fill.span(row, y as i32, left, right);
+                     }
+                 });
+}
+
+/// Cells of 8x8 pixels of a map drawn at `origin`, `cells[row]` has the
+/// columns and the pixels of the cells of this row of the map
+pub struct CellGrid<'a> {
+    pub origin: (i32, i32),
+    pub rows: Vec<Vec<(i32, &'a [u32])>>,
+}
+
+/// Draw the cells, the pixels are given by `color` (None when transparent)
+pub fn fill_cells<F>(frame_buffer: &mut [u32], width: usize, bounds: Bounds, grid: &CellGrid, color: F)
+    where F: Fn(u32) -> Option<u32> + Sync
+{
+    let (origin_x, origin_y) = grid.origin;
+    let top = cmp::max(origin_y, bounds.top);
+    let bottom = cmp::min(origin_y + 8 * grid.rows.len() as i32, bounds.bottom);
+    if top >= bottom {
+        return;
+    }
+
+    let cells: usize = grid.rows.iter().map(|row| row.len()).sum();
+    for_each_row(frame_buffer,
+                 width,
+                 top as usize..bottom as usize,
+                 cells * 64 >= PARALLEL_PIXELS,
+                 |y, row| {
+        let dy = y as i32 - origin_y;
+        let line = (dy % 8) as usize;
+
+        for &(column, data) in grid.rows[(dy / 8) as usize].iter() {
+            let x = origin_x + 8 * column;
+            for dx in 0..8 {
+                let px = x + dx;
+                if px < bounds.left || px >= bounds.right {
+                    continue;
+                }
+                if let Some(c) = color(data[dx as usize + line * 8]) {
+                    row[px as usize] = c;
+                }
+            }
+        }
+    });
+}
+
+/// Row by row sampling of a texture: `line(y)` gives the texture coordinates
+/// (u, v) of the pixel x 0 of the row and their step to the next pixel,
+/// `texel(u, v)` the color (None when transparent)
+pub fn fill_affine<L, T>(frame_buffer: &mut [u32], width: usize, bounds: Bounds, line: L, texel: T)
+    where L: Fn(i32) -> Option<(f64, f64, f64, f64)> + Sync,
+          T: Fn(i32, i32) -> Option<u32> + Sync
+{
+    if bounds.left >= bounds.right || bounds.top >= bounds.bottom {
+        return;
+    }
+
+    let pixels = (bounds.right - bounds.left) as usize * (bounds.bottom - bounds.top) as usize;
+    for_each_row(frame_buffer,
+                 width,
+                 bounds.top as usize..bounds.bottom as usize,
+                 pixels >= PARALLEL_PIXELS,
+                 |y, row| if let Some((u, v, du, dv)) = line(y as i32) {
+                     for px in bounds.left..bounds.right {
+                         let x = px as f64;
+                         let tu = (u + du * x).floor() as i32;
+                         let tv = (v + dv * x).floor() as i32;
+                         if let Some(c) = texel(tu, tv) {
+                             row[px as usize] = c;
+                         }
+                     }
+                 });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fill_affine, fill_cells, fill_polygon, fill_rect, fill_triangle, Bounds, CellGrid,
+                Fill};
+
+    fn bounds() -> Bounds {
+        Bounds {
+            left: 0,
+            top: 0,
+            right: 8,
+            bottom: 8,
+        }
+    }
+
+    #[test]
+    fn test_fill_rect() {
+        let mut buffer = vec![0; 64];
+        fill_rect(&mut buffer, 8, bounds(), 6, -2, 10, 1, Fill::solid(3));
+        assert_eq!(&buffer[0..8], &[0, 0, 0, 0, 0, 0, 3, 3]);
+        assert_eq!(&buffer[8..16], &[0, 0, 0, 0, 0, 0, 3, 3]);
+        assert_eq!(buffer.iter().filter(|c| **c == 3).count(), 4);
+
+        fill_rect(&mut buffer, 8, bounds(), 9, 0, 12, 2, Fill::solid(5));
+        assert!(!buffer.contains(&5));
+    }
+
+    #[test]
+    fn test_fill_pattern() {
+        // Checkerboard, the top-left pixel is the secondary color
+        let fill = Fill {
+            color: 1,
+            pattern: 0xa5a5,
+            secondary: Some(2),
+        };
+        assert_eq!(fill.color_at(0, 0), Some(2));
+        assert_eq!(fill.color_at(1, 0), Some(1));
+        assert_eq!(fill.color_at(4, 4), Some(2));
+        assert_eq!(fill.color_at(-1, 0), Some(1));
+
+        let mut buffer = vec![0; 64];
+        fill_rect(&mut buffer, 8, bounds(), 0, 0, 7, 7, Fill { secondary: None, ..fill });
+        assert_eq!(&buffer[0..8], &[0, 1, 0, 1, 0, 1, 0, 1]);
+        assert_eq!(&buffer[8..16], &[1, 0, 1, 0, 1, 0, 1, 0]);
+        assert_eq!(buffer.iter().filter(|c| **c == 1).count(), 32);
+
+        fill_triangle(&mut buffer, 8, bounds(), [(0, 0), (7, 0), (0, 7)], fill);
+        assert_eq!(&buffer[0..4], &[2, 1, 2, 1]);
+    }
+
+    #[test]
+    fn test_fill_triangle() {
+        let mut buffer = vec![0; 64];
+        fill_triangle(&mut buffer, 8, bounds(), [(0, 0), (7, 0), (0, 7)], Fill::solid(1));
+        // The edges are included
+        assert_eq!(&buffer[0..8], &[1; 8]);
+        assert_eq!(&buffer[56..64], &[1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(buffer.iter().filter(|c| **c == 1).count(), 36);
+    }
+
+    #[test]
+    fn test_fill_polygon() {
+        // Same pixels as the triangle
+        let mut triangle = vec![0; 64];
+        fill_triangle(&mut triangle, 8, bounds(), [(0, 0), (7, 0), (0, 7)], Fill::solid(1));
+        let mut buffer = vec![0; 64];
+        fill_polygon(&mut buffer, 8, bounds(), &[(0, 0), (7, 0), (0, 7)], Fill::solid(1));
+        assert_eq!(buffer, triangle);
+
+        // A U, the inside of the notch stays empty
+        let mut buffer = vec![0; 64];
+        fill_polygon(&mut buffer,
+                     8,
+                     bounds(),
+                     &[(0, 0), (2, 0), (2, 5), (5, 5), (5, 0), (7, 0), (7, 7), (0, 7)],
+                     Fill::solid(1));
+        assert_eq!(&buffer[24..32], &[1, 1, 1, 0, 0, 1, 1, 1]);
+        assert_eq!(&buffer[40..48], &[1; 8]);
+        assert_eq!(buffer.iter().filter(|c| **c == 1).count(), 54);
+
+        // Clipped, and nothing for no points
+        let mut buffer = vec![0; 64];
+        fill_polygon(&mut buffer, 8, bounds(), &[(-8, -8), (20, -8), (20, 2), (-8, 2)], Fill::solid(1));
+        assert_eq!(&buffer[0..8], &[1; 8]);
+        fill_polygon(&mut buffer, 8, bounds(), &[], Fill::solid(2));
+        assert!(!buffer.contains(&2));
+
+        // Points at the ends of the i32, no overflow
+        let (min, max) = (i32::min_value(), i32::max_value());
+        let mut buffer = vec![0; 64];
+        fill_polygon(&mut buffer,
+                     8,
+                     bounds(),
+                     &[(min, min), (max, min), (max, max), (min, max)],
+                     Fill::solid(3));
+        assert_eq!(buffer, vec![3; 64]);
+        fill_triangle(&mut buffer, 8, bounds(), [(min, 0), (max, 0), (0, max)], Fill::solid(4));
+        assert_eq!(&buffer[..8], &[4; 8]);
+        let mut buffer = vec![0; 64];
+        fill_polygon(&mut buffer, 8, bounds(), &[(max, 0), (max, 7)], Fill::solid(5));
+        assert!(!buffer.contains(&5));
+    }
+
+    #[test]
+    fn test_fill_cells() {
+        let mut sprite = [0; 64];
+        sprite[0] = 7;
+        sprite[63] = 8;
+
+        let grid = CellGrid {
+            origin: (-4, 0),
+            rows: vec![vec![(0, &sprite[..]), (1, &sprite[..])]],
+        };
+
+        let mut buffer = vec![0; 64];
+        fill_cells(&mut buffer, 8, bounds(), &grid, |c| if c == 0 { None } else { Some(c) });
+        // The first cell is cut by the left side
+        assert_eq!(buffer[3], 0);
+        assert_eq!(buffer[4], 7);
+        assert_eq!(buffer[3 + 7 * 8], 8);
+        assert_eq!(buffer.iter().filter(|c| **c != 0).count(), 2);
+    }
+
+    #[test]
+    fn test_fill_affine() {
+        // Texture of the u + 8 * v colors, 0 is transparent
+        let texel = |u: i32, v: i32| if u + 8 * v == 0 { None } else { Some((u + 8 * v) as u32) };
+
+        // Identity
+        let mut buffer = vec![99; 64];
+        fill_affine(&mut buffer, 8, bounds(), |y| Some((0.0, y as f64, 1.0, 0.0)), texel);
+        assert_eq!(buffer[0], 99);
+        assert_eq!(&buffer[1..8], &[1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(buffer[63], 63);
+
+        // Twice smaller on x, the rows without a line are skipped
+        let mut buffer = vec![0; 64];
+        fill_affine(&mut buffer,
+                    8,
+                    bounds(),
+                    |y| if y == 1 { Some((0.0, 1.0, 0.5, 0.0)) } else { None },
+                    texel);
+        assert_eq!(&buffer[8..16], &[8, 8, 9, 9, 10, 10, 11, 11]);
+        assert_eq!(buffer.iter().filter(|c| **c != 0).count(), 8);
+    }
+}
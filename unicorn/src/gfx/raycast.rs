@@ -0,0 +1,136 @@
+//! Raycaster of `raycast`: one ray per column of the clip through the map,
+//! from a position in cells and an angle in turns like `cos`/`sin` (0.25 is
+//! toward the top of the map). The wall cells are drawn with their sprite,
+//! the faces of the rows a bit darker, and the distance of each column is
+//! returned to draw the sprites of the game behind or in front of the walls.
+
+use std::f64::consts::PI;
+
+use unicorn;
+
+/// Longest ray, across the whole map
+pub const MAX_DISTANCE: f64 = (unicorn::MAP_WIDTH + unicorn::MAP_HEIGHT) as f64;
+
+/// Ray stopping on a wall
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hit {
+    /// Distance to the wall along the direction of the view, without the
+    /// fisheye of the real distance
+    pub distance: f64,
+    pub cell: (i32, i32),
+    /// Face on the left or right of the cell, else on its top or bottom
+    pub x_side: bool,
+    /// Horizontal texture coordinate on the face, from 0 to 1
+    pub u: f64,
+}
+
+/// Direction of the view and of the right edge of the screen plane
+pub fn view_vectors(angle: f64, fov: f64) -> ((f64, f64), (f64, f64)) {
+    let (sin, cos) = (angle * 2.0 * PI).sin_cos();
+    let dir = (cos, -sin);
+    let half = (fov * PI).tan();
+    (dir, (-dir.1 * half, dir.0 * half))
+}
+
+/// Walk the cells from `x`/`y` along `dx`/`dy` until a wall, or stop after
+/// `max_distance` steps of the direction or outside of the map
+pub fn cast<W>(x: f64, y: f64, dx: f64, dy: f64, max_distance: f64, is_wall: W) -> Option<Hit>
+    where W: Fn(i32, i32) -> bool
+{
+    if !x.is_finite() || !y.is_finite() || !dx.is_finite() || !dy.is_finite() ||
+       max_distance.is_nan() {
+        return None;
+    }
+    let max_distance = max_distance.min(MAX_DISTANCE);
+
+    let mut cell = (x.floor() as i32, y.floor() as i32);
+    let delta_x = if dx == 0.0 { ::std::f64::INFINITY } else { (1.0 / dx).abs() };
+    let delta_y = if dy == 0.0 { ::std::f64::INFINITY } else { (1.0 / dy).abs() };
+
+    let (step_x, mut side_x) = if dx < 0.0 {
+        (-1, (x - cell.0 as f64) * delta_x)
+    } else {
+        (1, (cell.0 as f64 + 1.0 - x) * delta_x)
+    };
+    let (step_y, mut side_y) = if dy < 0.0 {
+        (-1, (y - cell.1 as f64) * delta_y)
+    } else {
+        (1, (cell.1 as f64 + 1.0 - y) * delta_y)
+    };
+
+    loop {
+        let x_side = side_x < side_y;
+        let distance = if x_side {
+            cell.0 += step_x;
+            side_x += delta_x;
+            side_x - delta_x
+        } else {
+            cell.1 += step_y;
+            side_y += delta_y;
+            side_y - delta_y
+        };
+
+        if distance > max_distance {
+            return None;
+        }
+        // Walking away from the map
+        if (cell.0 < 0 && step_x < 0) || (cell.0 >= unicorn::MAP_WIDTH as i32 && step_x > 0) ||
+           (cell.1 < 0 && step_y < 0) || (cell.1 >= unicorn::MAP_HEIGHT as i32 && step_y > 0) {
+            return None;
+        }
+        if !is_wall(cell.0, cell.1) {
+            continue;
+        }
+
+        let along = if x_side { y + distance * dy } else { x + distance * dx };
+        let mut u = along - along.floor();
+        // From left to right seen from the ray
+        if (x_side && dx < 0.0) || (!x_side && dy > 0.0) {
+            u = 1.0 - u;
+        }
+
+        return Some(Hit {
+                        distance: distance,
+                        cell: cell,
+                        x_side: x_side,
+                        u: u,
+                    });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cast, view_vectors};
+
+    #[test]
+    fn test_cast() {
+        // Walls on the column 5 and the row 0
+        let is_wall = |x: i32, y: i32| x == 5 || y == 0;
+
+        let hit = cast(2.5, 2.25, 1.0, 0.0, 16.0, &is_wall).unwrap();
+        assert_eq!(hit.cell, (5, 2));
+        assert!(hit.x_side);
+        assert!((hit.distance - 2.5).abs() < 1e-9);
+        assert!((hit.u - 0.25).abs() < 1e-9);
+
+        let hit = cast(2.5, 2.25, 0.0, -1.0, 16.0, &is_wall).unwrap();
+        assert_eq!(hit.cell, (2, 0));
+        assert!(!hit.x_side);
+        assert!((hit.distance - 1.25).abs() < 1e-9);
+
+        assert_eq!(cast(2.5, 2.25, -1.0, 0.0, 16.0, &is_wall), None);
+
+        // Ended without a wall
+        let never = |_: i32, _: i32| false;
+        assert_eq!(cast(2.5, 2.25, 1.0, 0.3, ::std::f64::INFINITY, &never), None);
+        assert_eq!(cast(2.5, 2.25, 1.0, 0.3, 1e300, &never), None);
+        assert_eq!(cast(2.5, 2.25, 1.0, 0.3, ::std::f64::NAN, &is_wall), None);
+        assert_eq!(cast(::std::f64::NAN, 2.25, 1.0, 0.0, 16.0, &is_wall), None);
+        assert_eq!(cast(-1e9, 2.25, -1.0, 0.0, ::std::f64::INFINITY, &never), None);
+
+        // 0.25 turn is toward the top, the plane on its right
+        let ((dx, dy), (px, py)) = view_vectors(0.25, 0.125);
+        assert!(dx.abs() < 1e-9 && (dy + 1.0).abs() < 1e-9);
+        assert!((px - 0.41421356).abs() < 1e-6 && py.abs() < 1e-9);
+    }
+}
@@ -11,12 +11,12 @@ extern crate duktape;
 #[cfg(feature = "syntect")]
 extern crate syntect;
 
-#[cfg(feature = "noise")]
-extern crate noise;
-
 #[cfg(feature = "image")]
 extern crate image;
 
+#[cfg(all(feature = "rayon", not(target_os = "emscripten")))]
+extern crate rayon;
+
 extern crate chiptune;
 
 extern crate gif;
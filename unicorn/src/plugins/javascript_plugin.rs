@@ -57,6 +57,8 @@ pub mod plugin {
         mouse_y                 #               #               #
         mouse_state             #               #               #
         mouse_statep            #               #               #
+        touchpad                #               #               #
+        led                     #               #               #
         # Map                   #               #               #
         mapdraw                 #               #               #
         mget                    #               #               #
@@ -64,6 +66,7 @@ pub mod plugin {
         # Noise                 #               #               #
         noise                   #               #               #
         noise_set_seed          #               #               #
+        noise_seed              #               #               #
         # Palette               #               #               #
         palette                 #               #               #
         palette_hexa            #               #               #
@@ -76,10 +79,20 @@ pub mod plugin {
         flr                     #               #               #
         rnd                     #               #               #
         srand                   #               #               #
+        fx                      #               #               #
+        fx_num                  #               #               #
+        fx_mul                  #               #               #
+        fx_div                  #               #               #
+        fx_sqrt                 #               #               #
+        fx_sin                  #               #               #
+        fx_cos                  #               #               #
         mid                     #               #               #
         bxor                    #               #               #
         # Memory                #               #               #
+        peek                    #               #               #
+        poke                    #               #               #
         memcpy                  #               #               #
+        memset                  #               #               #
         # System                #               #               #
         time                    #      X        # unicorn_time  #
         time_sec                #               #               #
@@ -1,53 +1,97 @@
 #[cfg(feature = "unicorn_plugin_lua")]
 pub mod plugin {
+    use std::char;
+    use std::mem;
+    use std::ptr;
     use std::sync::{Arc, Mutex};
 
-    use rand;
-    use rand::Rng;
 
     use unicorn_plugin_lua as lua;
     use unicorn_plugin_lua::ffi::lua_State;
-    use unicorn_plugin_lua::{State, Function, ThreadStatus};
-    use libc::c_int;
+    use unicorn_plugin_lua::{State, Function, ThreadStatus, GcOption, Type};
+    use libc::{c_int, c_void, size_t};
 
     use config::Players;
 
     use unicorn::info::Info;
     use unicorn::noise::Noise;
+    use unicorn::random::Random;
+    use unicorn::deprecation;
+    use unicorn::PALETTE;
+    use unicorn::crash;
+    use unicorn::format::{self, FormatArg};
+    use unicorn::timers::Easing;
+    use unicorn::physics::Body;
+    use unicorn::fixed::Fixed;
+    use unicorn::pathfind;
+    use unicorn::{MAP_HEIGHT, MAP_WIDTH};
+    use unicorn::script_cache::ScriptCache;
+    use unicorn::permissions::Permission;
     use sound::sound::Sound;
 
     use gfx::Screen;
+    use gfx::memory::MEMORY_LEN;
+    use gfx::particles::EmitterDef;
+    use gfx::gfx3d::{self, DepthMode, Mesh};
+    use gfx::collision;
 
     /*
         # GFX                   #    Lua        #    New name   #
         camera                  #     X         #               #
+        camera_push             #     X         #               #
+        camera_pop              #     X         #               #
         circ                    #     X         #               #
         circfill                #     X         #               #
         clip                    #     X         #               #
+        clip_push               #     X         #               #
+        clip_pop                #     X         #               #
         cls                     #     X         #               #
         color                   #     X         #               #
         ellipse                 #     X         #               #
         ellipsefill             #     X         #               #
+        fade                    #     X         #               #
         fget                    #     X         #               #
+        fillp                   #     X         #               #
+        flash                   #     X         #               #
         font                    #     X         #               #
+        font_load               #     X         #               #
+        font_data               #     X         #               #
         line                    #     X         #               #
+        line_width              #     X         #               #
+        bezier                  #     X         #               #
+        mode                    #     X         #               #
         pal                     #     X         #               #
         palt                    #     X         #               #
+        pal_cycle               #     X         #               #
+        set_palette             #     X         #               #
+        shake                   #     X         #               #
+        particles_spawn         #     X         #               #
+        particles_move          #     X         #               #
+        particles_stop          #     X         #               #
+        particles_clear         #     X         #               #
+        particles_count         #     X         #               #
+        particles_draw          #     X         #               #
         pget                    #     X         #               #
         polygon                 #               #               #
+        polyfill                #     X         #               #
         print                   #     X         #               #
         pset                    #     X         #               #
         rect                    #     X         #               #
         rectfill                #     X         #               #
         sget                    #     X         #               #
         spr                     #     X         #               #
+        spr_batch               #     X         #               #
+        spr_tile                #     X         #               #
+        spr_9slice              #     X         #               #
         sset                    #     X         #               #
         sspr                    #     X         #               #
+        sspr_rot                #     X         #               #
         sspr_rotazoom           #               #               #
         trigon                  #     X         #               #
         # Audio                 #               #               #
         music                   #     X         #               #
         sfx                     #     X         #               #
+        sfx_new                 #     X         #               #
         music_stop              #               #               #
         music_volume            #               #               #
         music_pause             #               #               #
@@ -57,17 +101,31 @@ pub mod plugin {
         # Input                 #               #               #
         btn                     #     X         #               #
         btnp                    #               #               #
-        mouse_x                 #               #               #
-        mouse_y                 #               #               #
+        mouse_x                 #     X         #               #
+        mouse_y                 #     X         #               #
+        mouse_btn               #     X         #               #
+        mouse_wheel             #     X         #               #
+        touchpad                #     X         #               #
+        led                     #     X         #               #
+        keyboard_text           #     X         #               #
+        key_pressed             #     X         #               #
         mouse_state             #               #               #
         mouse_statep            #               #               #
         # Map                   #               #               #
         mapdraw                 #     X         #               #
+        map_affine              #     X         #               #
+        map_scanline            #     X         #               #
+        raycast                 #     X         #               #
+        collide_map             #     X         #               #
+        collide_rect            #     X         #               #
+        sprite_overlap          #     X         #               #
+        pathfind                #     X         #               #
         mget                    #     X         #               #
         mset                    #     X         #               #
         # Noise                 #               #               #
         noise                   #     X         #               #
         noise_set_seed          #     X         #               #
+        noise_seed              #     X         #               #
         # Palette               #               #               #
         palette                 #               #               #
         palette_hexa            #               #               #
@@ -79,15 +137,65 @@ pub mod plugin {
         sin                     #               #               #
         flr                     #               #               #
         rnd                     #     X         #               #
-        srand                   #               #               #
+        srand                   #     X         #               #
+        fx                      #     X         #               #
+        fx_num                  #     X         #               #
+        fx_mul                  #     X         #               #
+        fx_div                  #     X         #               #
+        fx_sqrt                 #     X         #               #
+        fx_sin                  #     X         #               #
+        fx_cos                  #     X         #               #
         mid                     #               #               #
         bxor                    #               #               #
         # Memory                #               #               #
-        memcpy                  #               #               #
+        peek                    #     X         #               #
+        poke                    #     X         #               #
+        memcpy                  #     X         #               #
+        memset                  #     X         #               #
+        # Cart data             #               #               #
+        cartdata                #     X         #               #
+        dget                    #     X         #               #
+        dset                    #     X         #               #
         # System                #               #               #
         time                    #     X         #               #
+        trace_begin             #     X         #               #
+        trace_end               #     X         #               #
         time_sec                #               #               #
+        after                   #     X         #               #
+        every                   #     X         #               #
+        tween                   #     X         #               #
+        timer_cancel            #     X         #               #
+        timer_scale             #     X         #               #
+        menuitem                #     X         #               #
+        co_start                #     X         #               #
+        co_stop                 #     X         #               #
+        yield_frame             #     X         #               #
+        # Entities              #               #               #
+        ent_new                 #     X         #               #
+        ent_del                 #     X         #               #
+        ent_tag                 #     X         #               #
+        ent_has                 #     X         #               #
+        ent_layer               #     X         #               #
+        ent_count               #     X         #               #
+        ent_each                #     X         #               #
+        ent_update              #     X         #               #
+        ent_draw                #     X         #               #
+        # Physics               #               #               #
+        body_new                #     X         #               #
+        body_move               #     X         #               #
+        body_get                #     X         #               #
+        body_set                #     X         #               #
+        body_gravity            #     X         #               #
+        body_del                #     X         #               #
         show_mouse              #               #               #
+        score_sign              #     X         #               #
+        score_verify            #     X         #               #
+        strf                    #     X         #               #
+        tostr                   #     X         #               #
+        stat                    #     X         #               #
+        extcmd                  #     X         #               #
+        set_filter              #     X         #               #
+        permission              #     X         #               #
     */
 
     pub struct ExtraData {
@@ -102,6 +210,124 @@ pub mod plugin {
     pub struct LuaPlugin {
         lua_state: Arc<Mutex<lua::State>>,
         loaded_code: bool,
+        /// `SandboxLimits` given to the allocator of the state, 0 without
+        /// the sandbox
+        sandbox_limits: usize,
+    }
+
+    /// Calls counted by `watch_nondeterminism`
+    const NONDETERMINISTIC_CALLS: [&'static str; 4] = ["os.time", "os.clock", "os.date", "math.random"];
+
+    /// Number of instructions between two checks of the sandbox limits
+    const SANDBOX_HOOK_COUNT: c_int = 1000;
+
+    /// Limits reached, read by the hook of the sandbox
+    const SANDBOX_CPU_LIMIT: c_int = 1;
+    const SANDBOX_MEMORY_LIMIT: c_int = 2;
+
+    /// Limits of a sandboxed state, the user data of its allocator
+    struct SandboxLimits {
+        /// Allocator of the state, called by the one of the sandbox
+        alloc: lua::Allocator,
+        ud: usize,
+        memory: usize,
+        max_memory: usize,
+        instructions: isize,
+        max_instructions: isize,
+        /// Limit reached during the current call, kept until the next one
+        reached: c_int,
+    }
+
+    /// The limits of the state, when it is sandboxed
+    unsafe fn sandbox_limits<'a>(state: &mut State) -> Option<&'a mut SandboxLimits> {
+        match state.get_alloc_fn() {
+            (Some(alloc), ud) if alloc as usize == lua_sandbox_alloc as usize => {
+                Some(&mut *(ud as *mut SandboxLimits))
+            }
+            _ => None,
+        }
+    }
+
+    /// Allocator of the sandbox: the memory is refused past the limit, even
+    /// inside a C function allocating without running any instruction
+    unsafe extern "C" fn lua_sandbox_alloc(ud: *mut c_void,
+                                           ptr: *mut c_void,
+                                           old_size: size_t,
+                                           new_size: size_t)
+                                           -> *mut c_void {
+        let limits = &mut *(ud as *mut SandboxLimits);
+
+        // Without a block, the old size is the type of the object
+        let old_size = if ptr.is_null() { 0 } else { old_size as usize };
+        let memory = limits.memory.saturating_sub(old_size);
+        if new_size as usize > old_size && memory + new_size as usize > limits.max_memory {
+            limits.reached = SANDBOX_MEMORY_LIMIT;
+            return ptr::null_mut();
+        }
+
+        let block = match limits.alloc {
+            Some(alloc) => alloc(limits.ud as *mut c_void, ptr, old_size as size_t, new_size),
+            None => ptr::null_mut(),
+        };
+        if !block.is_null() || new_size == 0 {
+            limits.memory = memory + new_size as usize;
+        }
+        block
+    }
+
+    /// Called by the count hook of the sandbox, the limit reached (or 0) is
+    /// returned and the error raised by the Lua hook, so nothing longjmps
+    /// across the Rust code and nothing is allocated
+    unsafe extern "C" fn lua_sandbox_check(lua_context: *mut lua_State) -> c_int {
+        let mut state = State::from_ptr(lua_context);
+
+        let reached = match sandbox_limits(&mut state) {
+            Some(limits) => {
+                limits.instructions += SANDBOX_HOOK_COUNT as isize;
+                if limits.reached == 0 && limits.instructions > limits.max_instructions {
+                    limits.reached = SANDBOX_CPU_LIMIT;
+                }
+                limits.reached
+            }
+            None => 0,
+        };
+
+        state.push_integer(reached as i64);
+        1
+    }
+
+    /// Limit reached during the call, without counting instructions
+    unsafe extern "C" fn lua_sandbox_reached(lua_context: *mut lua_State) -> c_int {
+        let mut state = State::from_ptr(lua_context);
+
+        let reached = sandbox_limits(&mut state).map(|limits| limits.reached).unwrap_or(0);
+        state.push_integer(reached as i64);
+        1
+    }
+
+    /// Message handler of the calls of the cartridge, the traceback of the
+    /// Lua stack is added to the error for the crash report
+    unsafe extern "C" fn lua_traceback(lua_context: *mut lua_State) -> c_int {
+        let mut state = State::from_ptr(lua_context);
+        let mut thread = State::from_ptr(lua_context);
+
+        let message = state.to_str(1).unwrap_or("(error object is not a string)").to_string();
+        state.traceback(&mut thread, &message, 1);
+        1
+    }
+
+    impl Drop for LuaPlugin {
+        /// The state could outlive the plugin, its allocator is given back
+        fn drop(&mut self) {
+            if self.sandbox_limits != 0 {
+                let limits = unsafe { Box::from_raw(self.sandbox_limits as *mut SandboxLimits) };
+                match self.lua_state.lock() {
+                    Ok(mut lua_state) => lua_state.set_alloc_fn(limits.alloc, limits.ud as *mut c_void),
+                    // Still the allocator of the state, it is kept
+                    Err(_) => mem::forget(limits),
+                }
+            }
+        }
     }
 
     impl LuaPlugin {
@@ -109,6 +335,16 @@ pub mod plugin {
             LuaPlugin {
                 lua_state: Arc::new(Mutex::new(lua::State::new())),
                 loaded_code: false,
+                sandbox_limits: 0,
+            }
+        }
+
+        /// New call of the cartridge, the sandbox counts its instructions
+        /// from 0 and forgets the limit reached by the previous one
+        fn reset_limits(lua_state: &mut lua::State) {
+            if let Some(limits) = unsafe { sandbox_limits(lua_state) } {
+                limits.instructions = 0;
+                limits.reached = 0;
             }
         }
 
@@ -154,6 +390,12 @@ pub mod plugin {
             info!("[PLUGIN][LUA][Unicorn][EXPORT DEBUG PRINT FUNCTION] = {:?}", value);
 
             let value = lua_state.do_string(r#"camera = function(x, y)
+              if x == nil then
+                x = 0
+              end
+              if y == nil then
+                y = 0
+              end
 
               x = math.floor(x)
               y = math.floor(y)
@@ -163,6 +405,32 @@ pub mod plugin {
               "#);
             info!("[PLUGIN][LUA][Unicorn][CAMERA] = {:?}", value);
 
+            let value = lua_state.do_string(r#"camera_push = function(x, y)
+              local absolute = 0
+              if x == nil and y == nil then
+                absolute = 1
+              end
+              if x == nil then
+                x = 0
+              end
+              if y == nil then
+                y = 0
+              end
+
+              x = math.floor(x)
+              y = math.floor(y)
+
+              return UnicornObject:camera_push(x, y, absolute) == 1
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][CAMERA_PUSH] = {:?}", value);
+
+            let value = lua_state.do_string(r#"camera_pop = function()
+              return UnicornObject:camera_pop() == 1
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][CAMERA_POP] = {:?}", value);
+
             let value = lua_state.do_string(r#"btn = function(x, p)
 
               x = math.floor(x)
@@ -189,6 +457,62 @@ pub mod plugin {
               "#);
             info!("[PLUGIN][LUA][Unicorn][BTNP] = {:?}", value);
 
+            let value = lua_state.do_string(r#"mouse_x = function()
+                return UnicornObject:mouse(0)
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][MOUSE_X] = {:?}", value);
+
+            let value = lua_state.do_string(r#"mouse_y = function()
+                return UnicornObject:mouse(1)
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][MOUSE_Y] = {:?}", value);
+
+            let value = lua_state.do_string(r#"mouse_wheel = function()
+                return UnicornObject:mouse(2)
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][MOUSE_WHEEL] = {:?}", value);
+
+            let value = lua_state.do_string(r#"mouse_btn = function(b)
+              if b == nil then
+                b = 0
+              end
+
+              return UnicornObject:mouse_btn(math.floor(b)) == 1
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][MOUSE_BTN] = {:?}", value);
+
+            let value = lua_state.do_string(r#"touchpad = function(p)
+              if p == nil then
+                p = 0
+              end
+
+              return UnicornObject:touchpad(math.floor(p))
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][TOUCHPAD] = {:?}", value);
+
+            let value = lua_state.do_string(r#"led = function(p, r, g, b)
+              UnicornObject:led(math.floor(p), math.floor(r), math.floor(g), math.floor(b))
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][LED] = {:?}", value);
+
+            let value = lua_state.do_string(r#"keyboard_text = function()
+                return UnicornObject:keyboard_text()
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][KEYBOARD_TEXT] = {:?}", value);
+
+            let value = lua_state.do_string(r#"key_pressed = function(name)
+                return UnicornObject:key_pressed(name) == 1
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][KEY_PRESSED] = {:?}", value);
+
 
             let value = lua_state.do_string(r#"rect = function(x0, y0, x1, y1, color)
 
@@ -282,6 +606,33 @@ pub mod plugin {
               "#);
             info!("[PLUGIN][LUA][Unicorn][CLIP] = {:?}", value);
 
+            let value = lua_state.do_string(r#"clip_push = function(x, y, w, h)
+              x = math.floor(x)
+              y = math.floor(y)
+              w = math.floor(w)
+              h = math.floor(h)
+
+              return UnicornObject:clip_push(x, y, w, h) == 1
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][CLIP_PUSH] = {:?}", value);
+
+            let value = lua_state.do_string(r#"clip_pop = function()
+              return UnicornObject:clip_pop() == 1
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][CLIP_POP] = {:?}", value);
+
+            let value = lua_state.do_string(r#"mode = function(w, h)
+              if w == nil then
+                return UnicornObject:mode(-1, -1)
+              end
+
+              return UnicornObject:mode(math.floor(w), math.floor(h)) == 1
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][MODE] = {:?}", value);
+
             let value = lua_state.do_string(r#"ellipse = function(x, y, rx, ry, color)
               x = math.floor(x)
               y = math.floor(y)
@@ -318,18 +669,33 @@ pub mod plugin {
 
             let value = lua_state.do_string(r#"fget = function(idx, flag)
               idx = math.floor(idx)
-              flag = math.floor(flag)
 
               if flag == nil then
                 return UnicornObject:fget_all(idx)
               end
 
-              return UnicornObject:fget(idx, flag)
+              return UnicornObject:fget(idx, math.floor(flag))
 
               end
               "#);
             info!("[PLUGIN][LUA][Unicorn][FGET] = {:?}", value);
 
+            let value = lua_state.do_string(r#"fillp = function(pattern, col2)
+              if pattern == nil then
+                pattern = 0
+              end
+              if col2 == nil then
+                col2 = -1
+              end
+
+              pattern = math.floor(pattern)
+              col2 = math.floor(col2)
+
+              UnicornObject:fillp(pattern, col2)
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][FILLP] = {:?}", value);
+
             let value = lua_state.do_string(r#"fset = function(idx, flag, value)
               idx = math.floor(idx)
               flag = math.floor(flag)
@@ -366,6 +732,29 @@ pub mod plugin {
               "#);
             info!("[PLUGIN][LUA][Unicorn][LINE] = {:?}", value);
 
+            let value = lua_state.do_string(r#"line_width = function(w)
+              if w == nil then
+                w = 1
+              end
+
+              return UnicornObject:line_width(math.floor(w))
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][LINE_WIDTH] = {:?}", value);
+
+            let value = lua_state.do_string(r#"bezier = function(x0, y0, cx, cy, x1, y1, color)
+              if color == nil then
+                color = -1
+              end
+
+              UnicornObject:bezier(math.floor(x0), math.floor(y0),
+                                   math.floor(cx), math.floor(cy),
+                                   math.floor(x1), math.floor(y1),
+                                   math.floor(color))
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][BEZIER] = {:?}", value);
+
             let value = lua_state.do_string(r#"trigon = function(x1, y1, x2, y2, x3, y3, color)
               x1 = math.floor(x1)
               y1 = math.floor(y1)
@@ -385,17 +774,35 @@ pub mod plugin {
               "#);
             info!("[PLUGIN][LUA][Unicorn][TRIGON] = {:?}", value);
 
+            let value = lua_state.do_string(r#"trifill = function(x1, y1, x2, y2, x3, y3, color)
+              if color == nil then
+                color = -1
+              end
+
+              UnicornObject:trifill(math.floor(x1), math.floor(y1),
+                                    math.floor(x2), math.floor(y2),
+                                    math.floor(x3), math.floor(y3),
+                                    math.floor(color))
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][TRIFILL] = {:?}", value);
+
             let value = lua_state.do_string(r#"rnd = function(x)
               if x == nil then
                 x = 1
               end
 
-              x = math.floor(x)
               return UnicornObject:rnd(x)
               end
               "#);
             info!("[PLUGIN][LUA][Unicorn][RND] = {:?}", value);
 
+            let value = lua_state.do_string(r#"srand = function(seed)
+              UnicornObject:srand(math.floor(seed or 0))
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][SRAND] = {:?}", value);
+
             let value = lua_state.do_string(r#"add = function(t, v)
               t[#t+1] = v
               end
@@ -412,8 +819,147 @@ pub mod plugin {
               "#);
             info!("[PLUGIN][LUA][Unicorn][CLS] = {:?}", value);
 
+            let value = lua_state.do_string(r#"fade = function(t, col)
+              if col == nil then
+                col = 0
+              end
+
+              UnicornObject:fade(t, math.floor(col))
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][FADE] = {:?}", value);
+
+            let value = lua_state.do_string(r#"flash = function(col, frames)
+              if frames == nil then
+                frames = 2
+              end
+
+              UnicornObject:flash(math.floor(col), math.floor(frames))
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][FLASH] = {:?}", value);
+
+            let value = lua_state.do_string(r#"shake = function(intensity, frames)
+              if frames == nil then
+                frames = 15
+              end
+
+              UnicornObject:shake(intensity, math.floor(frames))
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][SHAKE] = {:?}", value);
+
+            // The missing fields of the definition keep their default value
+            let value = lua_state.do_string(r#"particles_spawn = function(def)
+              if def == nil then
+                def = {}
+              end
+
+              return UnicornObject:particles_spawn(def.x, def.y, def.count, def.rate, def.duration,
+                                                   def.life, def.life_var, def.speed, def.speed_var,
+                                                   def.angle, def.spread, def.gravity, def.drag,
+                                                   def.sprite, def.size, def.size_end,
+                                                   table.unpack(def.colors or {}))
+              end
+
+              particles_move = function(id, x, y)
+                UnicornObject:particles_move(id, x, y)
+              end
+
+              particles_stop = function(id)
+                UnicornObject:particles_stop(id)
+              end
+
+              particles_clear = function()
+                UnicornObject:particles_clear()
+              end
+
+              particles_count = function()
+                return UnicornObject:particles_count()
+              end
+
+              particles_draw = function()
+                UnicornObject:particles_draw()
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][PARTICLES] = {:?}", value);
+
+            // The faces of mesh3d are {a, b, c, color, ...}, the vertices from 1
+            let value = lua_state.do_string(r#"camera3d = function(ex, ey, ez, tx, ty, tz)
+                UnicornObject:camera3d(ex, ey, ez, tx or 0, ty or 0, tz or 0)
+              end
+
+              perspective3d = function(fov, near)
+                UnicornObject:perspective3d(fov, near or 0.1)
+              end
+
+              light3d = function(x, y, z, ambient)
+                UnicornObject:light3d(x, y, z, ambient or 0.3)
+              end
+
+              mode3d = function(mode, cull)
+                if cull == nil then
+                  cull = true
+                end
+
+                UnicornObject:mode3d(mode, cull and 1 or 0)
+              end
+
+              push3d = function()
+                UnicornObject:push3d()
+              end
+
+              pop3d = function()
+                UnicornObject:pop3d()
+              end
+
+              identity3d = function()
+                UnicornObject:identity3d()
+              end
+
+              translate3d = function(x, y, z)
+                UnicornObject:translate3d(x or 0, y or 0, z or 0)
+              end
+
+              rotate3d = function(x, y, z)
+                UnicornObject:rotate3d(x or 0, y or 0, z or 0)
+              end
+
+              scale3d = function(x, y, z)
+                UnicornObject:scale3d(x, y or x, z or x)
+              end
+
+              mesh3d = function(vertices, faces)
+                return UnicornObject:mesh3d(vertices, faces)
+              end
+
+              mesh3d_del = function(id)
+                UnicornObject:mesh3d_del(math.floor(id))
+              end
+
+              draw3d = function(id)
+                UnicornObject:draw3d(math.floor(id))
+              end
+
+              tri3d = function(x0, y0, z0, x1, y1, z1, x2, y2, z2, color)
+                if color == nil then
+                  color = -1
+                end
+
+                UnicornObject:tri3d(x0, y0, z0, x1, y1, z1, x2, y2, z2, math.floor(color))
+              end
+
+              render3d = function()
+                return UnicornObject:render3d()
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][3D] = {:?}", value);
+
 
             let value = lua_state.do_string(r#"palt = function(c, t)
+              if c == nil then
+                c = -1
+              end
               c = math.floor(c)
 
               if t == true then
@@ -427,6 +973,27 @@ pub mod plugin {
               "#);
             info!("[PLUGIN][LUA][Unicorn][PALT] = {:?}", value);
 
+            let value = lua_state.do_string(r#"pal_cycle = function(range, speed)
+              if range == nil then
+                UnicornObject:pal_cycle(-1, -1, 0)
+              else
+                UnicornObject:pal_cycle(math.floor(range[1]), math.floor(range[2]), speed or 1)
+              end
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][PAL_CYCLE] = {:?}", value);
+
+            let value = lua_state.do_string(r#"set_palette = function(colors)
+              for c, value in pairs(colors) do
+                if type(value) == "table" then
+                  value = value[1] * 65536 + value[2] * 256 + value[3]
+                end
+                UnicornObject:palette_color(math.floor(c), math.floor(value))
+              end
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][SET_PALETTE] = {:?}", value);
+
             let value = lua_state.do_string(r#"pal = function(c0, c1, p)
               if c0 == nil then
                 c0 = -1
@@ -457,6 +1024,33 @@ pub mod plugin {
               "#);
             info!("[PLUGIN][LUA][Unicorn][FONT] = {:?}", value);
 
+            let value = lua_state.do_string(r#"font_load = function(name, sx, sy, w, h, first, count, widths)
+              if first == nil then
+                first = " "
+              end
+              if type(first) == "string" then
+                first = string.byte(first)
+              end
+              if count == nil then
+                count = 96
+              end
+              if widths == nil then
+                widths = {}
+              end
+
+              return UnicornObject:font_load(name, math.floor(sx), math.floor(sy),
+                                             math.floor(w), math.floor(h),
+                                             math.floor(first), math.floor(count), widths)
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][FONT_LOAD] = {:?}", value);
+
+            let value = lua_state.do_string(r#"font_data = function(name, height, data)
+              return UnicornObject:font_data(name, math.floor(height), data)
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][FONT_DATA] = {:?}", value);
+
             let value = lua_state.do_string(r#"pset = function(x, y, color)
               x = math.floor(x)
               y = math.floor(y)
@@ -507,14 +1101,15 @@ pub mod plugin {
             info!("[PLUGIN][LUA][Unicorn][SSET] = {:?}", value);
 
             let value = lua_state.do_string(r#"noise = function(x, y, z)
-              return UnicornObject:noise(x, y, z)
+              return UnicornObject:noise(x, y or 0, z or 0)
               end
               "#);
             info!("[PLUGIN][LUA][Unicorn][NOISE] = {:?}", value);
 
             let value = lua_state.do_string(r#"noise_set_seed = function(seed)
-              return UnicornObject:noise_set_seed(seed)
+              return UnicornObject:noise_set_seed(math.floor(seed or 0))
               end
+              noise_seed = noise_set_seed
               "#);
             info!("[PLUGIN][LUA][Unicorn][NOISE_SET_SEED] = {:?}", value);
 
@@ -536,41 +1131,165 @@ pub mod plugin {
               "#);
             info!("[PLUGIN][LUA][Unicorn][MAP] = {:?}", value);
 
-            let value = lua_state.do_string(r#"mget = function(x, y)
-              x = math.floor(x)
-              y = math.floor(y)
+            let value = lua_state.do_string(r#"map = mapdraw
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][MAP ALIAS] = {:?}", value);
 
-              return UnicornObject:mget(x, y)
+            let value = lua_state.do_string(r#"map_affine = function(a, b, c, d, tx, ty, layer)
+              if layer == nil then
+                layer = 0
+              end
+
+              UnicornObject:map_affine(a, b, c, d, tx, ty, layer)
               end
               "#);
-            info!("[PLUGIN][LUA][Unicorn][MGET] = {:?}", value);
+            info!("[PLUGIN][LUA][Unicorn][MAP_AFFINE] = {:?}", value);
 
-            let value = lua_state.do_string(r#"mset = function(x, y, v)
-              x = math.floor(x)
+            let value = lua_state.do_string(r#"map_scanline = function(y, u, v, du, dv, layer)
               y = math.floor(y)
-              v = math.floor(v)
 
-              UnicornObject:mset(x, y, v)
+              if layer == nil then
+                layer = 0
+              end
+
+              UnicornObject:map_scanline(y, u, v, du, dv, layer)
               end
               "#);
-            info!("[PLUGIN][LUA][Unicorn][MSET] = {:?}", value);
+            info!("[PLUGIN][LUA][Unicorn][MAP_SCANLINE] = {:?}", value);
 
-            let value = lua_state.do_string(r#"spr = function(n, x, y, w, h, flip_x, flip_y, dynamic)
+            let value = lua_state.do_string(r#"raycast = function(x, y, angle, fov, layer, max_distance)
+              if fov == nil then
+                fov = 1 / 6
+              end
 
-              n = math.floor(n)
-              x = math.floor(x)
-              y = math.floor(y)
-              if w == nil then
-                w = 1
+              if layer == nil then
+                layer = 0
               end
-              if h == nil then
-                h = 1
+
+              if max_distance == nil then
+                max_distance = 32
               end
-              if flip_x == nil then
-                flip_x = false
+
+              return UnicornObject:raycast(x, y, angle, fov, math.floor(layer), max_distance)
               end
-              if flip_y == nil then
-                flip_y = false
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][RAYCAST] = {:?}", value);
+
+            let value = lua_state.do_string(r#"collide_map = function(x, y, w, h, flag)
+              if flag == nil then
+                flag = 0
+              end
+
+              return UnicornObject:collide_map(x, y, w, h, math.floor(flag))
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][COLLIDE_MAP] = {:?}", value);
+
+            let value = lua_state.do_string(r#"collide_rect = function(x1, y1, w1, h1, x2, y2, w2, h2)
+              return UnicornObject:collide_rect(x1, y1, w1, h1, x2, y2, w2, h2)
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][COLLIDE_RECT] = {:?}", value);
+
+            let value = lua_state.do_string(r#"sprite_overlap = function(n1, x1, y1, n2, x2, y2, w1, h1, w2, h2)
+              if w1 == nil then
+                w1 = 1
+              end
+
+              if h1 == nil then
+                h1 = 1
+              end
+
+              if w2 == nil then
+                w2 = w1
+              end
+
+              if h2 == nil then
+                h2 = h1
+              end
+
+              return UnicornObject:sprite_overlap(math.floor(n1), math.floor(x1), math.floor(y1),
+                                                  math.floor(w1), math.floor(h1),
+                                                  math.floor(n2), math.floor(x2), math.floor(y2),
+                                                  math.floor(w2), math.floor(h2))
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][SPRITE_OVERLAP] = {:?}", value);
+
+            let value = lua_state.do_string(r#"pathfind = function(x0, y0, x1, y1, flag_mask)
+              if flag_mask == nil then
+                flag_mask = 1
+              end
+
+              return UnicornObject:pathfind(math.floor(x0), math.floor(y0), math.floor(x1), math.floor(y1),
+                                            math.floor(flag_mask))
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][PATHFIND] = {:?}", value);
+
+            let value = lua_state.do_string(r#"mget = function(x, y)
+              x = math.floor(x)
+              y = math.floor(y)
+
+              return UnicornObject:mget(x, y)
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][MGET] = {:?}", value);
+
+            let value = lua_state.do_string(r#"mset = function(x, y, v)
+              x = math.floor(x)
+              y = math.floor(y)
+              v = math.floor(v)
+
+              UnicornObject:mset(x, y, v)
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][MSET] = {:?}", value);
+
+            let value = lua_state.do_string(r#"spr_batch = function(n, positions)
+                UnicornObject:spr_batch(math.floor(n), positions)
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][SPR_BATCH] = {:?}", value);
+
+            let value = lua_state.do_string(r#"polyfill = function(points, color)
+              if color == nil then
+                color = -1
+              end
+
+              UnicornObject:polyfill(points, math.floor(color))
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][POLYFILL] = {:?}", value);
+
+            let value = lua_state.do_string(r#"spr_tile = function(n, x, y, w, h)
+                UnicornObject:spr_tile(math.floor(n), math.floor(x), math.floor(y), math.floor(w), math.floor(h))
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][SPR_TILE] = {:?}", value);
+
+            let value = lua_state.do_string(r#"spr_9slice = function(n, x, y, w, h)
+                UnicornObject:spr_9slice(math.floor(n), math.floor(x), math.floor(y), math.floor(w), math.floor(h))
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][SPR_9SLICE] = {:?}", value);
+
+            let value = lua_state.do_string(r#"spr = function(n, x, y, w, h, flip_x, flip_y, dynamic)
+
+              n = math.floor(n)
+              x = math.floor(x)
+              y = math.floor(y)
+              if w == nil then
+                w = 1
+              end
+              if h == nil then
+                h = 1
+              end
+              if flip_x == nil then
+                flip_x = false
+              end
+              if flip_y == nil then
+                flip_y = false
               end
 
               if flip_x == true then
@@ -640,7 +1359,25 @@ pub mod plugin {
               "#);
             info!("[PLUGIN][LUA][Unicorn][SSPR] = {:?}", value);
 
-            let value = lua_state.do_string(r#"print = function(str, x, y, col)
+            let value = lua_state.do_string(r#"sspr_rot = function(sx, sy, sw, sh, dx, dy, dw, dh, angle, flip_x, flip_y)
+              if dw == nil then
+                dw = sw
+              end
+              if dh == nil then
+                dh = sh
+              end
+
+              flip_x = flip_x == true and 1 or 0
+              flip_y = flip_y == true and 1 or 0
+
+              UnicornObject:sspr_rot(math.floor(sx), math.floor(sy), math.floor(sw), math.floor(sh),
+                                     math.floor(dx), math.floor(dy), math.floor(dw), math.floor(dh),
+                                     angle or 0, flip_x, flip_y)
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][SSPR_ROT] = {:?}", value);
+
+            let value = lua_state.do_string(r#"print = function(str, x, y, col, font)
               if x == nil then
                 x = -1
               end
@@ -657,7 +1394,11 @@ pub mod plugin {
               y = math.floor(y)
               col = math.floor(col)
 
-              UnicornObject:print(str, x, y, col)
+              if font == nil then
+                UnicornObject:print(str, x, y, col)
+              else
+                UnicornObject:print_font(str, x, y, col, font)
+              end
 
               end
               "#);
@@ -669,6 +1410,363 @@ pub mod plugin {
               "#);
             info!("[PLUGIN][LUA][Unicorn][TIME] = {:?}", value);
 
+            let value = lua_state.do_string(r#"trace_begin = function(name)
+                UnicornObject:trace_begin(name)
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][TRACE_BEGIN] = {:?}", value);
+
+            let value = lua_state.do_string(r#"trace_end = function()
+                UnicornObject:trace_end()
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][TRACE_END] = {:?}", value);
+
+            // The callbacks of the timers and the fields of the tweens stay
+            // here, _unicorn_timers_update is called before _update
+            let value = lua_state.do_string(r#"_unicorn_timers = {}
+              _unicorn_tweens = {}
+
+              after = function(seconds, fn)
+                local id = UnicornObject:timer_new(seconds, 0)
+                _unicorn_timers[id] = fn
+                return id
+              end
+
+              every = function(seconds, fn)
+                local id = UnicornObject:timer_new(seconds, 1)
+                _unicorn_timers[id] = fn
+                return id
+              end
+
+              tween = function(obj, field, target, duration, easing)
+                if easing == nil then
+                  easing = "linear"
+                end
+
+                local id = UnicornObject:tween_new(obj[field], target, duration, easing)
+                _unicorn_tweens[id] = {obj, field}
+                return id
+              end
+
+              timer_cancel = function(id)
+                UnicornObject:timer_cancel(id)
+                _unicorn_timers[id] = nil
+                _unicorn_tweens[id] = nil
+              end
+
+              timer_scale = function(scale)
+                UnicornObject:timer_scale(scale)
+              end
+
+              _unicorn_timers_update = function()
+                local id, value, done = UnicornObject:tween_next()
+                while id ~= nil do
+                  local target = _unicorn_tweens[id]
+                  if target ~= nil then
+                    target[1][target[2]] = value
+                    if done then
+                      _unicorn_tweens[id] = nil
+                    end
+                  end
+                  id, value, done = UnicornObject:tween_next()
+                end
+
+                id, done = UnicornObject:timer_next()
+                while id ~= nil do
+                  local fn = _unicorn_timers[id]
+                  if done then
+                    _unicorn_timers[id] = nil
+                  end
+                  if fn ~= nil then
+                    fn()
+                  end
+                  id, done = UnicornObject:timer_next()
+                end
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][TIMERS] = {:?}", value);
+
+            // The callbacks of the entries of the pause menu stay here,
+            // _unicorn_menuitems_update is called before _update
+            let value = lua_state.do_string(r#"_unicorn_menuitems = {}
+
+              menuitem = function(index, label, fn)
+                index = math.floor(index)
+                if UnicornObject:menuitem(index, label) then
+                  if label == nil then
+                    _unicorn_menuitems[index] = nil
+                  else
+                    _unicorn_menuitems[index] = fn
+                  end
+                end
+              end
+
+              _unicorn_menuitems_update = function()
+                local index = UnicornObject:menuitem_next()
+                if index ~= nil then
+                  local fn = _unicorn_menuitems[index]
+                  if fn ~= nil then
+                    fn()
+                  end
+                end
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][MENUITEM] = {:?}", value);
+
+            // The coroutines of co_start stay here, _unicorn_coroutines_update
+            // resumes them after _draw so they could draw over the frame
+            let value = lua_state.do_string(r#"_unicorn_coroutines = {}
+              _unicorn_coroutine_entries = {}
+
+              co_start = function(fn, ...)
+                local co = coroutine.create(fn)
+                local entry = {co = co, args = table.pack(...)}
+                _unicorn_coroutines[#_unicorn_coroutines + 1] = entry
+                _unicorn_coroutine_entries[co] = entry
+                return co
+              end
+
+              co_stop = function(co)
+                local entry = _unicorn_coroutine_entries[co]
+                if entry == nil then
+                  return false
+                end
+                entry.stopped = true
+                _unicorn_coroutine_entries[co] = nil
+                return true
+              end
+
+              yield_frame = function(n)
+                if not coroutine.isyieldable() then
+                  error("yield_frame outside of a coroutine, start it with co_start", 2)
+                end
+                n = math.max(math.floor(n or 1), 1)
+                for _ = 1, n do
+                  coroutine.yield()
+                end
+              end
+
+              cocreate = coroutine.create
+              coresume = coroutine.resume
+              costatus = coroutine.status
+              yield = coroutine.yield
+
+              _unicorn_coroutines_update = function()
+                local entries = _unicorn_coroutines
+                -- The coroutines started during the update wait for the next frame
+                _unicorn_coroutines = {}
+
+                local kept = {}
+                local err = nil
+                for _, entry in ipairs(entries) do
+                  if not entry.stopped and err == nil then
+                    local ok, msg = coroutine.resume(entry.co, table.unpack(entry.args, 1, entry.args.n))
+                    entry.args = {n = 0}
+                    if not ok then
+                      err = msg
+                    end
+                  end
+
+                  if entry.stopped or coroutine.status(entry.co) == "dead" then
+                    _unicorn_coroutine_entries[entry.co] = nil
+                  else
+                    kept[#kept + 1] = entry
+                  end
+                end
+
+                for _, entry in ipairs(_unicorn_coroutines) do
+                  kept[#kept + 1] = entry
+                end
+                _unicorn_coroutines = kept
+
+                -- Reported like the errors of _draw, the other coroutines
+                -- are resumed at the next frame
+                if err ~= nil then
+                  error(err, 0)
+                end
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][COROUTINES] = {:?}", value);
+
+            // The values of the watch expressions, the tables on one line
+            let value = lua_state.do_string(r#"_unicorn_watch_format = function(v)
+                if type(v) ~= "table" then
+                  return tostring(v)
+                end
+
+                local items = {}
+                for k, x in pairs(v) do
+                  if #items == 8 then
+                    items[#items + 1] = "..."
+                    break
+                  end
+                  if type(k) == "number" then
+                    items[#items + 1] = tostring(x)
+                  else
+                    items[#items + 1] = tostring(k) .. "=" .. tostring(x)
+                  end
+                end
+                return "{" .. table.concat(items, ", ") .. "}"
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][WATCH] = {:?}", value);
+
+            // The tables of the entities stay here, by id
+            let value = lua_state.do_string(r#"_unicorn_entities = {}
+
+              ent_new = function(tags, layer, e)
+                if tags == nil then
+                  tags = ""
+                end
+
+                if layer == nil then
+                  layer = 0
+                end
+
+                if e == nil then
+                  e = {}
+                end
+
+                e.id = UnicornObject:ent_new(tags, math.floor(layer))
+                _unicorn_entities[e.id] = e
+                return e
+              end
+
+              ent_del = function(e)
+                UnicornObject:ent_del(e.id)
+                _unicorn_entities[e.id] = nil
+              end
+
+              ent_tag = function(e, tag, on)
+                if on == false then
+                  UnicornObject:ent_tag(e.id, tag, 0)
+                else
+                  UnicornObject:ent_tag(e.id, tag, 1)
+                end
+              end
+
+              ent_has = function(e, tag)
+                return UnicornObject:ent_has(e.id, tag) == 1
+              end
+
+              ent_layer = function(e, layer)
+                UnicornObject:ent_layer(e.id, math.floor(layer))
+              end
+
+              ent_count = function(tag)
+                return UnicornObject:ent_count(tag or "")
+              end
+
+              ent_each = function(tag, fn)
+                local ids = UnicornObject:ent_ids(tag or "")
+                for i = 1, #ids do
+                  -- Removed by a previous call
+                  local e = _unicorn_entities[ids[i]]
+                  if e ~= nil then
+                    fn(e)
+                  end
+                end
+              end
+
+              ent_update = function(tag)
+                ent_each(tag, function(e)
+                  if e.update ~= nil then
+                    e:update()
+                  end
+                end)
+              end
+
+              ent_draw = function(tag)
+                ent_each(tag, function(e)
+                  if e.draw ~= nil then
+                    e:draw()
+                  end
+                end)
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][ENTITIES] = {:?}", value);
+
+            let value = lua_state.do_string(r#"body_new = function(x, y, w, h)
+                return UnicornObject:body_new(x, y, w, h)
+              end
+
+              body_move = function(id)
+                UnicornObject:body_move(id)
+              end
+
+              body_get = function(id)
+                return UnicornObject:body_get(id)
+              end
+
+              body_set = function(id, x, y, vx, vy)
+                UnicornObject:body_set(id, x, y, vx, vy)
+              end
+
+              body_gravity = function(id, gravity, max_fall)
+                UnicornObject:body_gravity(id, gravity, max_fall)
+              end
+
+              body_del = function(id)
+                UnicornObject:body_del(id)
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][PHYSICS] = {:?}", value);
+
+            let value = lua_state.do_string(r#"fx = function(x)
+                return UnicornObject:fx(x or 0)
+              end
+
+              fx_num = function(a)
+                return UnicornObject:fx_num(a)
+              end
+
+              fx_mul = function(a, b)
+                return UnicornObject:fx_mul(a, b)
+              end
+
+              fx_div = function(a, b)
+                return UnicornObject:fx_div(a, b)
+              end
+
+              fx_sqrt = function(a)
+                return UnicornObject:fx_sqrt(a)
+              end
+
+              fx_sin = function(a)
+                return UnicornObject:fx_sin(a)
+              end
+
+              fx_cos = function(a)
+                return UnicornObject:fx_cos(a)
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][FIXED] = {:?}", value);
+
+            let value = lua_state.do_string(r#"score_sign = function(value)
+                return UnicornObject:score_sign(math.floor(value))
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][SCORE_SIGN] = {:?}", value);
+
+            let value = lua_state.do_string(r#"score_verify = function(blob)
+                return UnicornObject:score_verify(blob)
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][SCORE_VERIFY] = {:?}", value);
+
+            let value = lua_state.do_string(r#"strf = function(fmt, ...)
+                return UnicornObject:strf(fmt, ...)
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][STRF] = {:?}", value);
+
+            let value = lua_state.do_string(r#"tostr = function(value)
+                return UnicornObject:strf("%s", value)
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][TOSTR] = {:?}", value);
+
             let value = lua_state.do_string(r#"sfx = function(id, filename, note, panning, rate, loops, channel)
               if filename == nil then
                 filename = ""
@@ -705,8 +1803,21 @@ pub mod plugin {
             info!("[PLUGIN][LUA][Unicorn][SFX] = {:?}", value);
 
             let value = lua_state.do_string(r#"music = function(id, filename, channel, loops, start_position)
-              if filename == nil then
-                filename = ""
+              -- music(n, [fade_ms, [channel_mask]]) plays the patterns of the cartridge
+              if type(filename) ~= "string" then
+                local fade_ms = filename
+                local channel_mask = channel
+
+                if fade_ms == nil then
+                  fade_ms = 0
+                end
+
+                if channel_mask == nil then
+                  channel_mask = 15
+                end
+
+                UnicornObject:music_pattern(id, fade_ms, channel_mask)
+                return
               end
 
               if channel == nil then
@@ -757,6 +1868,32 @@ pub mod plugin {
               "#);
             info!("[PLUGIN][LUA][Unicorn][SFX] = {:?}", value);
 
+            let value = lua_state.do_string(r#"sfx_new = function(name, waveform, attack, decay, sustain, release)
+              if waveform == nil then
+                waveform = "square"
+              end
+
+              if attack == nil then
+                attack = 0
+              end
+
+              if decay == nil then
+                decay = 8
+              end
+
+              if sustain == nil then
+                sustain = 16
+              end
+
+              if release == nil then
+                release = 4
+              end
+
+              UnicornObject:sfx_new(name, waveform, attack, decay, sustain, release)
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][SFX_NEW] = {:?}", value);
+
 
             let value = lua_state.do_string(r#"flip = function()
               end
@@ -771,16 +1908,29 @@ pub mod plugin {
             info!("[PLUGIN][LUA][Unicorn][COLOR] = {:?}", value);
 
             let value = lua_state.do_string(r#"peek = function(addr)
-                return 0
+                return UnicornObject:peek(math.floor(addr))
               end
               "#);
             info!("[PLUGIN][LUA][Unicorn][PEEK] = {:?}", value);
 
             let value = lua_state.do_string(r#"poke = function(addr, val)
+                UnicornObject:poke(math.floor(addr), math.floor(val))
               end
               "#);
             info!("[PLUGIN][LUA][Unicorn][POKE] = {:?}", value);
 
+            let value = lua_state.do_string(r#"memcpy = function(dest_addr, source_addr, len)
+                UnicornObject:memcpy(math.floor(dest_addr), math.floor(source_addr), math.floor(len))
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][MEMCPY] = {:?}", value);
+
+            let value = lua_state.do_string(r#"memset = function(dest_addr, val, len)
+                UnicornObject:memset(math.floor(dest_addr), math.floor(val), math.floor(len))
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][MEMSET] = {:?}", value);
+
             let value = lua_state.do_string(r#"stat = function(x)
                 v = UnicornObject:stat(x)
                 return v
@@ -788,16 +1938,33 @@ pub mod plugin {
               "#);
             info!("[PLUGIN][LUA][Unicorn][STAT] = {:?}", value);
 
-            /* CARTDATA */
-            let value = lua_state.do_string(r#"cartdata = function(x)
-              x = math.floor(x)
-              UnicornObject:cartdata(x)
+            let value = lua_state.do_string(r#"extcmd = function(cmd, arg)
+                UnicornObject:extcmd(cmd, arg)
               end
               "#);
-            info!("[PLUGIN][LUA][Unicorn][CARTDATA] = {:?}", value);
+            info!("[PLUGIN][LUA][Unicorn][EXTCMD] = {:?}", value);
 
-            let value = lua_state.do_string(r#"dget = function(x)
-              x = math.floor(x)
+            let value = lua_state.do_string(r#"set_filter = function(name)
+                return UnicornObject:set_filter(name) == 1
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][SET_FILTER] = {:?}", value);
+
+            let value = lua_state.do_string(r#"permission = function(name)
+                return UnicornObject:permission(name) == 1
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][PERMISSION] = {:?}", value);
+
+            /* CARTDATA */
+            let value = lua_state.do_string(r#"cartdata = function(id)
+              return UnicornObject:cartdata(id) == 1
+              end
+              "#);
+            info!("[PLUGIN][LUA][Unicorn][CARTDATA] = {:?}", value);
+
+            let value = lua_state.do_string(r#"dget = function(x)
+              x = math.floor(x)
               return UnicornObject:dget(x)
               end
               "#);
@@ -805,7 +1972,6 @@ pub mod plugin {
 
             let value = lua_state.do_string(r#"dset = function(x, y)
               x = math.floor(x)
-              y = math.floor(y)
 
               UnicornObject:dset(x, y)
               end
@@ -951,6 +2117,411 @@ pub mod plugin {
             end
             "#);
             info!("[PLUGIN][LUA] LOADED MATH FUNCTIONS = {:?}", value);
+
+            // The deprecated functions warn about their first call
+            for name in deprecation::names("lua") {
+                let value = lua_state.do_string(&format!(r#"do
+                  local replaced = {name}
+                  {name} = function(...)
+                    UnicornObject:deprecated("{name}")
+                    return replaced(...)
+                  end
+                end"#,
+                                                         name = name));
+                info!("[PLUGIN][LUA] DEPRECATED {:?} = {:?}", name, value);
+            }
+
+            // Children of a variable for the inspector, from the globals
+            // without the ones of the console, defined until here
+            let value = lua_state.do_string(r#"_unicorn_builtins = {}
+            local globals = _G
+            local function quote(v)
+              return (string.format("%q", v):gsub("\\\n", "\\n"))
+            end
+
+            _unicorn_inspect = function(...)
+              local t = globals
+              for _, key in ipairs({...}) do
+                t = t[key]
+                if type(t) ~= "table" then
+                  return ""
+                end
+              end
+
+              local lines = {}
+              for k, v in next, t do
+                if t ~= globals or not _unicorn_builtins[k] then
+                  local kind = type(v)
+                  local key = ""
+                  if type(k) == "string" then
+                    key = quote(k)
+                  elseif type(k) == "number" or type(k) == "boolean" then
+                    key = tostring(k)
+                  end
+
+                  local value = kind
+                  if kind == "table" then
+                    local n = 0
+                    for _ in next, v do
+                      n = n + 1
+                    end
+                    value = "{" .. n .. "}"
+                  elseif kind == "string" then
+                    value = quote(v:sub(1, 64))
+                  elseif kind ~= "function" then
+                    value = tostring(v)
+                  end
+
+                  local name = tostring(k):gsub("%c", "?")
+                  lines[#lines + 1] = kind .. "\t" .. key .. "\t" .. name .. "\t" .. value:gsub("%c", "?")
+                end
+              end
+              return table.concat(lines, "\n")
+            end
+
+            for k in pairs(_G) do
+              _unicorn_builtins[k] = true
+            end
+            "#);
+            info!("[PLUGIN][LUA] LOADED INSPECTOR = {:?}", value);
+        }
+
+        /// Keep the error message on the top of the stack for the crash report
+        fn report_error(lua_state: &mut lua::State) {
+            let message = match lua_state.to_str(-1) {
+                Some(message) => message.to_string(),
+                None => return,
+            };
+            lua_state.pop(1);
+
+            error!("[PLUGIN][LUA] {}", message);
+            crash::set_script_error(&format!("[LUA] {}", message));
+        }
+
+        /// Run the code like `do_string`, the error has the traceback
+        fn call(lua_state: &mut lua::State, code: &str) -> ThreadStatus {
+            lua_state.push_fn(Some(lua_traceback));
+            let handler = lua_state.get_top();
+
+            let mut value = lua_state.load_string(code);
+            if value == ThreadStatus::Ok {
+                value = lua_state.pcall(0, lua::MULTRET, handler);
+            }
+            lua_state.remove(handler);
+
+            value
+        }
+
+        /// Register the code of a module in package.preload, for `require(name)`
+        pub fn preload(&mut self, name: &str, code: &str) -> bool {
+            info!("[PLUGIN][LUA] Preload {:?}", name);
+
+            let mut lua_state = self.lua_state.lock().unwrap();
+
+            lua_state.get_global("package");
+            lua_state.get_field(-1, "preload");
+
+            let value = lua_state.load_buffer(code.as_bytes(), &format!("={}", name));
+            if value != ThreadStatus::Ok {
+                LuaPlugin::report_error(&mut lua_state);
+                error!("[PLUGIN][LUA] PRELOAD {:?} = {:?}", name, value);
+                lua_state.pop(2);
+                return false;
+            }
+
+            lua_state.set_field(-2, name);
+            lua_state.pop(2);
+
+            true
+        }
+
+        /// Remove the access to the system and limit the CPU/memory used
+        /// by the cartridge
+        pub fn sandbox(&mut self, max_instructions: i32, max_memory_kb: i32) {
+            info!("[PLUGIN][LUA] Sandbox {:?} instructions, {:?} KB",
+                  max_instructions,
+                  max_memory_kb);
+
+            let mut lua_state = self.lua_state.lock().unwrap();
+
+            // The memory is counted by the allocator of the state, from the
+            // blocks already allocated
+            let max_memory = max_memory_kb.max(0) as usize * 1024;
+            match unsafe { sandbox_limits(&mut lua_state) } {
+                Some(limits) => {
+                    limits.max_memory = max_memory;
+                    limits.max_instructions = max_instructions as isize;
+                }
+                None => {
+                    let (alloc, ud) = lua_state.get_alloc_fn();
+                    let memory = lua_state.gc(GcOption::Count, 0) as usize * 1024 +
+                                 lua_state.gc(GcOption::CountBytes, 0) as usize;
+                    let limits = Box::new(SandboxLimits {
+                                              alloc: alloc,
+                                              ud: ud as usize,
+                                              memory: memory,
+                                              max_memory: max_memory,
+                                              instructions: 0,
+                                              max_instructions: max_instructions as isize,
+                                              reached: 0,
+                                          });
+                    let limits = Box::into_raw(limits);
+                    self.sandbox_limits = limits as usize;
+                    lua_state.set_alloc_fn(Some(lua_sandbox_alloc), limits as *mut c_void);
+                }
+            }
+
+            // The limits are checked by a hook of the chunk, with the check
+            // functions only reachable from it
+            lua_state.register("_unicorn_sandbox_check", Some(lua_sandbox_check));
+            lua_state.register("_unicorn_sandbox_reached", Some(lua_sandbox_reached));
+            lua_state.push_integer(SANDBOX_HOOK_COUNT as i64);
+            lua_state.set_global("SANDBOX_HOOK_COUNT");
+
+            // Only the preloaded shared libraries could be required
+            let value = lua_state.do_string(r#"local preload = package.preload
+                                               local loaded = {}
+                                               require = function(name)
+                                                 if loaded[name] == nil then
+                                                   local loader = preload[name]
+                                                   if loader == nil then
+                                                     error("module '" .. name .. "' not found")
+                                                   end
+                                                   loaded[name] = loader(name) or true
+                                                 end
+                                                 return loaded[name]
+                                               end
+                                               os = { time = os.time, clock = os.clock, date = os.date }
+                                               io = nil
+                                               package = nil
+                                               dofile = nil
+                                               loadfile = nil
+                                               load = nil
+
+                                               local sethook, gethook = debug.sethook, debug.gethook
+                                               local check, reached = _unicorn_sandbox_check, _unicorn_sandbox_reached
+                                               local count = SANDBOX_HOOK_COUNT
+                                               _unicorn_sandbox_check = nil
+                                               _unicorn_sandbox_reached = nil
+                                               SANDBOX_HOOK_COUNT = nil
+                                               local messages = { "[SANDBOX] CPU limit reached",
+                                                                  "[SANDBOX] Memory limit reached" }
+
+                                               -- Once reached, the limit fails each instruction
+                                               -- until the end of the call
+                                               local hook
+                                               hook = function()
+                                                 local limit = check()
+                                                 if limit ~= 0 then
+                                                   sethook(hook, "", 1)
+                                                   error(messages[limit], 2)
+                                                 end
+                                                 if select(3, gethook()) ~= count then
+                                                   sethook(hook, "", count)
+                                                 end
+                                               end
+                                               sethook(hook, "", count)
+
+                                               -- The errors of the limits can't be caught
+                                               local function guard(...)
+                                                 local limit = reached()
+                                                 if limit ~= 0 then
+                                                   error(messages[limit], 0)
+                                                 end
+                                                 return ...
+                                               end
+                                               local raw_pcall, raw_xpcall = pcall, xpcall
+                                               pcall = function(...) return guard(raw_pcall(...)) end
+                                               xpcall = function(...) return guard(raw_xpcall(...)) end
+
+                                               -- The hooks are per thread, each coroutine sets it
+                                               local create, wrap, resume = coroutine.create, coroutine.wrap, coroutine.resume
+                                               local function hooked(f)
+                                                 if type(f) ~= "function" then
+                                                   return f
+                                                 end
+                                                 return function(...)
+                                                   sethook(hook, "", count)
+                                                   return f(...)
+                                                 end
+                                               end
+                                               coroutine.create = function(f) return create(hooked(f)) end
+                                               coroutine.wrap = function(f) return wrap(hooked(f)) end
+                                               coroutine.resume = function(...) return guard(resume(...)) end
+                                               cocreate = coroutine.create
+                                               coresume = coroutine.resume
+                                               debug = nil"#);
+            info!("[PLUGIN][LUA][SANDBOX] = {:?}", value);
+        }
+
+        /// Count the calls of the global functions, read with `call_count`
+        pub fn trace_calls(&mut self, names: &[String]) -> bool {
+            info!("[PLUGIN][LUA] Trace the calls of {:?}", names);
+
+            let names: Vec<String> = names.iter()
+                .filter(|name| name.chars().all(|c| c.is_alphanumeric() || c == '_'))
+                .map(|name| format!("{:?}", name))
+                .collect();
+
+            let mut lua_state = self.lua_state.lock().unwrap();
+
+            let value = lua_state.do_string(&format!(r#"__unicorn_calls = {{}}
+                                                        for _, name in ipairs({{ {} }}) do
+                                                          local f = _G[name]
+                                                          if type(f) == "function" then
+                                                            _G[name] = function(...)
+                                                              __unicorn_calls[name] = (__unicorn_calls[name] or 0) + 1
+                                                              return f(...)
+                                                            end
+                                                          end
+                                                        end"#,
+                                                     names.join(", ")));
+            info!("[PLUGIN][LUA][TRACE] = {:?}", value);
+
+            value == ThreadStatus::Ok
+        }
+
+        pub fn call_count(&mut self, name: &str) -> u32 {
+            let mut lua_state = self.lua_state.lock().unwrap();
+
+            lua_state.get_global("__unicorn_calls");
+            if lua_state.is_nil(-1) {
+                lua_state.pop(1);
+                return 0;
+            }
+
+            lua_state.get_field(-1, name);
+            let count = lua_state.to_integerx(-1).unwrap_or(0);
+            lua_state.pop(2);
+
+            count as u32
+        }
+
+        /// Count the calls of the clock and of `math.random`, which differ
+        /// between two runs, read with `nondeterminism_calls`
+        pub fn watch_nondeterminism(&mut self) -> bool {
+            let mut lua_state = self.lua_state.lock().unwrap();
+
+            let value = lua_state.do_string(r#"__unicorn_nondeterminism = {}
+                                               local function watch(lib, lib_name, name)
+                                                 local f = lib and lib[name]
+                                                 if type(f) == "function" then
+                                                   local key = lib_name .. "." .. name
+                                                   lib[name] = function(...)
+                                                     __unicorn_nondeterminism[key] = (__unicorn_nondeterminism[key] or 0) + 1
+                                                     return f(...)
+                                                   end
+                                                 end
+                                               end
+                                               watch(os, "os", "time")
+                                               watch(os, "os", "clock")
+                                               watch(os, "os", "date")
+                                               watch(math, "math", "random")"#);
+            info!("[PLUGIN][LUA][NONDETERMINISM] = {:?}", value);
+
+            value == ThreadStatus::Ok
+        }
+
+        pub fn nondeterminism_calls(&mut self) -> Vec<(String, u32)> {
+            let mut lua_state = self.lua_state.lock().unwrap();
+
+            lua_state.get_global("__unicorn_nondeterminism");
+            if lua_state.is_nil(-1) {
+                lua_state.pop(1);
+                return Vec::new();
+            }
+
+            let mut calls = Vec::new();
+            for name in NONDETERMINISTIC_CALLS.iter() {
+                lua_state.get_field(-1, name);
+                let count = lua_state.to_integerx(-1).unwrap_or(0);
+                lua_state.pop(1);
+                calls.push((name.to_string(), count as u32));
+            }
+            lua_state.pop(1);
+
+            calls
+        }
+
+        /// Value of a watch expression, its errors aren't the ones of the
+        /// cartridge
+        pub fn eval(&mut self, expression: &str) -> Result<String, String> {
+            self.eval_code(&format!("return _unicorn_watch_format(({}))", expression))
+        }
+
+        /// Children of the global at `path` for the inspector, the keys are
+        /// the ones listed for its parent
+        pub fn inspect(&mut self, path: &[String]) -> Result<String, String> {
+            self.eval_code(&format!("return _unicorn_inspect({})", path.join(", ")))
+        }
+
+        /// Line of the developer console: the values of an expression,
+        /// formatted like the watches, or a statement. It's compiled here, the
+        /// cartridges of the sandbox can't load code.
+        pub fn execute(&mut self, code: &str) -> Result<String, String> {
+            if !self.loaded_code {
+                return Err("No code".to_string());
+            }
+
+            let mut lua_state = self.lua_state.lock().unwrap();
+            LuaPlugin::reset_limits(&mut lua_state);
+            let top = lua_state.get_top();
+
+            let expression = format!("return {}", code);
+            let mut value = lua_state.load_bufferx(expression.as_bytes(), "=console", "t");
+            if value != ThreadStatus::Ok {
+                lua_state.pop(1);
+                value = lua_state.load_bufferx(code.as_bytes(), "=console", "t");
+            }
+            if value == ThreadStatus::Ok {
+                value = lua_state.pcall(0, lua::MULTRET, 0);
+            }
+            if value != ThreadStatus::Ok {
+                let text = lua_state.to_str(-1).unwrap_or("").to_string();
+                lua_state.set_top(top);
+                return Err(text);
+            }
+
+            let mut values = Vec::new();
+            for idx in top + 1..lua_state.get_top() + 1 {
+                lua_state.get_global("_unicorn_watch_format");
+                lua_state.push_value(idx);
+                lua_state.pcall(1, 1, 0);
+                values.push(lua_state.to_str(-1).unwrap_or("").to_string());
+                lua_state.pop(1);
+            }
+            lua_state.set_top(top);
+
+            Ok(values.join(", "))
+        }
+
+        /// First value returned by the code, as a string
+        fn eval_code(&mut self, code: &str) -> Result<String, String> {
+            if !self.loaded_code {
+                return Err("No code".to_string());
+            }
+
+            let mut lua_state = self.lua_state.lock().unwrap();
+            let top = lua_state.get_top();
+
+            let mut value = lua_state.load_bufferx(code.as_bytes(), "=watch", "t");
+            if value == ThreadStatus::Ok {
+                value = lua_state.pcall(0, 1, 0);
+            }
+
+            let text = lua_state.to_str(-1).unwrap_or("").to_string();
+            lua_state.set_top(top);
+
+            if value == ThreadStatus::Ok { Ok(text) } else { Err(text) }
+        }
+
+        pub fn reset_calls(&mut self) {
+            let mut lua_state = self.lua_state.lock().unwrap();
+
+            let value = lua_state.do_string("if __unicorn_calls ~= nil then __unicorn_calls = {} end");
+            if value != ThreadStatus::Ok {
+                LuaPlugin::report_error(&mut lua_state);
+            }
         }
 
         pub fn init(&mut self) {
@@ -959,9 +2530,11 @@ pub mod plugin {
             }
 
             let mut lua_state = self.lua_state.lock().unwrap();
+            LuaPlugin::reset_limits(&mut lua_state);
 
-            let value = lua_state.do_string("_init()");
+            let value = LuaPlugin::call(&mut lua_state, "_init()");
             if value != ThreadStatus::Ok {
+                LuaPlugin::report_error(&mut lua_state);
                 error!("[PLUGIN][LUA] INIT = {:?}", value);
             } else {
                 info!("[PLUGIN][LUA] INIT SUCCESS");
@@ -974,12 +2547,20 @@ pub mod plugin {
             }
 
             let mut lua_state = self.lua_state.lock().unwrap();
+            LuaPlugin::reset_limits(&mut lua_state);
 
-            let value = lua_state.do_string("_draw()");
+            let value = LuaPlugin::call(&mut lua_state, "_draw()");
             if value != ThreadStatus::Ok {
+                LuaPlugin::report_error(&mut lua_state);
                 error!("[PLUGIN][LUA] DRAW = {:?}", value);
             }
 
+            let value = LuaPlugin::call(&mut lua_state, "_unicorn_coroutines_update()");
+            if value != ThreadStatus::Ok {
+                LuaPlugin::report_error(&mut lua_state);
+                error!("[PLUGIN][LUA] COROUTINES = {:?}", value);
+            }
+
             return true;
         }
 
@@ -989,11 +2570,25 @@ pub mod plugin {
             }
 
             let mut lua_state = self.lua_state.lock().unwrap();
+            LuaPlugin::reset_limits(&mut lua_state);
+
+            let value = LuaPlugin::call(&mut lua_state, "_unicorn_menuitems_update()");
+            if value != ThreadStatus::Ok {
+                LuaPlugin::report_error(&mut lua_state);
+                error!("[PLUGIN][LUA] MENUITEMS = {:?}", value);
+            }
 
-            let value = lua_state.do_string("_update()");
+            let value = LuaPlugin::call(&mut lua_state, "_unicorn_timers_update()");
             if value != ThreadStatus::Ok {
-                let value = lua_state.do_string("_update60()");
+                LuaPlugin::report_error(&mut lua_state);
+                error!("[PLUGIN][LUA] TIMERS = {:?}", value);
+            }
+
+            let value = LuaPlugin::call(&mut lua_state, "_update()");
+            if value != ThreadStatus::Ok {
+                let value = LuaPlugin::call(&mut lua_state, "_update60()");
                 if value != ThreadStatus::Ok {
+                    LuaPlugin::report_error(&mut lua_state);
                     error!("[PLUGIN][LUA] UPDATE = {:?}", value);
                 }
             }
@@ -1003,13 +2598,53 @@ pub mod plugin {
         }
 
         pub fn load_code(&mut self, data: String) -> bool {
+            self.load_code_cached(data, &ScriptCache::disabled())
+        }
+
+        /// The bytecode of the code is read from the cache, or compiled and
+        /// written in it. The name of the chunk is the code, like `do_string`,
+        /// and the debug information is kept for the error messages
+        pub fn load_code_cached(&mut self, data: String, cache: &ScriptCache) -> bool {
             info!("[PLUGIN][LUA] LOAD CODE");
             let mut lua_state = self.lua_state.lock().unwrap();
+            LuaPlugin::reset_limits(&mut lua_state);
 
             self.loaded_code = true;
 
-            let value = lua_state.do_string(&data);
+            let mut value = ThreadStatus::SyntaxError;
+            if let Some(bytecode) = cache.load(&data, "luac") {
+                value = lua_state.load_bufferx(&bytecode, &data, "b");
+                if value == ThreadStatus::Ok {
+                    info!("[PLUGIN][LUA] Bytecode from the cache");
+                } else {
+                    warn!("[PLUGIN][LUA] Invalid bytecode in the cache = {:?}", value);
+                    lua_state.pop(1);
+                }
+            }
+
+            if value != ThreadStatus::Ok {
+                value = lua_state.load_bufferx(data.as_bytes(), &data, "t");
+                if value == ThreadStatus::Ok && cache.dir.is_some() {
+                    let mut bytecode = Vec::new();
+                    lua_state.dump(|_, chunk| {
+                                       bytecode.extend_from_slice(chunk);
+                                       0
+                                   },
+                                   false);
+                    cache.store(&data, "luac", &bytecode);
+                }
+            }
+
+            if value == ThreadStatus::Ok {
+                lua_state.push_fn(Some(lua_traceback));
+                lua_state.insert(-2);
+                let handler = lua_state.get_top() - 1;
+                value = lua_state.pcall(0, lua::MULTRET, handler);
+                lua_state.remove(handler);
+            }
+
             if value != ThreadStatus::Ok {
+                LuaPlugin::report_error(&mut lua_state);
                 error!("[PLUGIN][LUA] LOAD CODE = {:?}", value);
                 self.loaded_code = false;
             }
@@ -1020,6 +2655,25 @@ pub mod plugin {
 
     struct UnicornLua {}
 
+    /// Address of `peek`/`poke`/`memcpy`/`memset`, none outside of the
+    /// memory instead of wrapping around onto another one
+    fn memory_addr(addr: i64) -> Option<u32> {
+        if addr >= 0 && addr < MEMORY_LEN as i64 {
+            Some(addr as u32)
+        } else {
+            None
+        }
+    }
+
+    /// Length of `memcpy`/`memset`, at most the whole memory
+    fn memory_len(len: i64) -> Option<u32> {
+        if len > 0 {
+            Some(len.min(MEMORY_LEN as i64) as u32)
+        } else {
+            None
+        }
+    }
+
     impl UnicornLua {
         fn new() -> UnicornLua {
             return UnicornLua {};
@@ -1066,6 +2720,32 @@ pub mod plugin {
             1
         }
 
+        unsafe extern "C" fn lua_music_pattern(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA MUSIC PATTERN");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let n = state.check_integer(2);
+            let fade_ms = state.check_integer(3);
+            let channel_mask = state.check_integer(4);
+
+            let sound = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.sound.clone()
+                                          });
+
+            sound
+                .lock()
+                .unwrap()
+                .music_pattern(n as i32, fade_ms as i32, channel_mask as i32);
+
+            1
+        }
+
         unsafe extern "C" fn lua_sfx(lua_context: *mut lua_State) -> c_int {
             debug!("LUA SFX");
 
@@ -1097,6 +2777,36 @@ pub mod plugin {
             1
         }
 
+        unsafe extern "C" fn lua_sfx_new(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA SFX NEW");
+
+            let mut state = State::from_ptr(lua_context);
+            let mut state2 = State::from_ptr(lua_context);
+
+            let name = state.check_string(2);
+            let waveform = state2.check_string(3);
+            let attack = state.check_integer(4);
+            let decay = state.check_integer(5);
+            let sustain = state.check_integer(6);
+            let release = state.check_integer(7);
+
+            let sound = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.sound.clone()
+                                          });
+
+            sound
+                .lock()
+                .unwrap()
+                .new_sfx(name.to_string(), waveform.to_string(), attack, decay, sustain, release);
+
+            1
+        }
+
         unsafe extern "C" fn lua_camera(lua_context: *mut lua_State) -> c_int {
             debug!("LUA CAMERA");
 
@@ -1119,6 +2829,55 @@ pub mod plugin {
             1
         }
 
+        // camera_push(x, y): camera layer moved from the current camera,
+        // camera_push() draws in the coordinates of the screen
+        unsafe extern "C" fn lua_camera_push(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA CAMERA_PUSH");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let x = state.check_integer(2);
+            let y = state.check_integer(3);
+            let absolute = state.check_integer(4);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            let value = screen
+                .lock()
+                .unwrap()
+                .camera_push(x as i32, y as i32, absolute == 1);
+            state.push_integer(value as i64);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_camera_pop(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA CAMERA_POP");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            let value = screen.lock().unwrap().camera_pop();
+            state.push_integer(value as i64);
+
+            1
+        }
+
         unsafe extern "C" fn lua_color(lua_context: *mut lua_State) -> c_int {
             debug!("LUA COLOR");
 
@@ -1196,36 +2955,171 @@ pub mod plugin {
             1
         }
 
-        unsafe extern "C" fn lua_cls(lua_context: *mut lua_State) -> c_int {
+        // mouse 0 (x), 1 (y) or 2 (wheel)
+        unsafe extern "C" fn lua_mouse(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA MOUSE");
+
             let mut state = State::from_ptr(lua_context);
 
-            let value = state.check_integer(2);
+            let index = state.check_integer(2);
 
-            let screen = state.with_extra(|extra| {
-                                              let data = extra
-                                                  .as_ref()
-                                                  .unwrap()
-                                                  .downcast_ref::<ExtraData>()
-                                                  .unwrap();
-                                              data.screen.clone()
-                                          });
+            let players = state.with_extra(|extra| {
+                                               let data = extra
+                                                   .as_ref()
+                                                   .unwrap()
+                                                   .downcast_ref::<ExtraData>()
+                                                   .unwrap();
+                                               data.players.clone()
+                                           });
 
-            screen.lock().unwrap().cls(value as i8);
+            let mut players_data = players.lock().unwrap();
+
+            let value = match index {
+                2 => players_data.mouse_wheel(),
+                _ => players_data.mouse_coordinate(index as u8),
+            };
+
+            state.push_integer(value as i64);
 
             1
         }
 
-        #[allow(non_snake_case)]
-        unsafe extern "C" fn lua_rect(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA RECT");
+        unsafe extern "C" fn lua_mouse_btn(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA MOUSE BTN");
 
             let mut state = State::from_ptr(lua_context);
 
-            let x0 = state.check_integer(2);
-            let y0 = state.check_integer(3);
-            let x1 = state.check_integer(4);
-            let y1 = state.check_integer(5);
-            let col = state.check_integer(6);
+            let button = state.check_integer(2);
+
+            let players = state.with_extra(|extra| {
+                                               let data = extra
+                                                   .as_ref()
+                                                   .unwrap()
+                                                   .downcast_ref::<ExtraData>()
+                                                   .unwrap();
+                                               data.players.clone()
+                                           });
+
+            let value = players.lock().unwrap().mouse_btn(button as u8);
+
+            state.push_integer(value as i64);
+
+            1
+        }
+
+        // x, y of the finger on the touchpad of the player in the screen, or
+        // nil
+        unsafe extern "C" fn lua_touchpad(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA TOUCHPAD");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let player = state.check_integer(2);
+
+            let (players, screen) = state.with_extra(|extra| {
+                                                         let data = extra
+                                                             .as_ref()
+                                                             .unwrap()
+                                                             .downcast_ref::<ExtraData>()
+                                                             .unwrap();
+                                                         (data.players.clone(), data.screen.clone())
+                                                     });
+
+            let (width, height) = {
+                let screen = screen.lock().unwrap();
+                (screen.width, screen.height)
+            };
+
+            match players.lock().unwrap().touchpad(player as u8, width, height) {
+                Some((x, y)) => {
+                    state.push_integer(x as i64);
+                    state.push_integer(y as i64);
+                    2
+                }
+                None => {
+                    state.push_nil();
+                    1
+                }
+            }
+        }
+
+        unsafe extern "C" fn lua_led(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA LED");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let player = state.check_integer(2);
+            let r = state.check_integer(3);
+            let g = state.check_integer(4);
+            let b = state.check_integer(5);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            let channel = |value: i64| value.max(0).min(255) as u8;
+            info.lock().unwrap().led(player as u8, channel(r), channel(g), channel(b));
+
+            0
+        }
+
+        // Text typed during the frame, empty without the "keyboard" permission
+        unsafe extern "C" fn lua_keyboard_text(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA KEYBOARD TEXT");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let (players, info) = state.with_extra(|extra| {
+                                                       let data = extra
+                                                           .as_ref()
+                                                           .unwrap()
+                                                           .downcast_ref::<ExtraData>()
+                                                           .unwrap();
+                                                       (data.players.clone(), data.info.clone())
+                                                   });
+
+            let text = if info.lock().unwrap().permission(Permission::Keyboard) {
+                players.lock().unwrap().get_text()
+            } else {
+                "".to_string()
+            };
+            state.push_string(&text);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_key_pressed(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA KEY PRESSED");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let name = state.check_string(2).to_string();
+
+            let (players, info) = state.with_extra(|extra| {
+                                                       let data = extra
+                                                           .as_ref()
+                                                           .unwrap()
+                                                           .downcast_ref::<ExtraData>()
+                                                           .unwrap();
+                                                       (data.players.clone(), data.info.clone())
+                                                   });
+
+            let value = info.lock().unwrap().permission(Permission::Keyboard) &&
+                        players.lock().unwrap().key_pressed(&name);
+            state.push_integer(value as i64);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_cls(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let value = state.check_integer(2);
 
             let screen = state.with_extra(|extra| {
                                               let data = extra
@@ -1236,32 +3130,106 @@ pub mod plugin {
                                               data.screen.clone()
                                           });
 
-            screen
-                .lock()
-                .unwrap()
-                .rect(x0 as i32, y0 as i32, x1 as i32, y1 as i32, col as i32);
+            screen.lock().unwrap().cls(value as i8);
 
             1
         }
 
-        #[allow(non_snake_case)]
-        unsafe extern "C" fn lua_rectfill(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA RECTFILL");
+        // fade t col
+        unsafe extern "C" fn lua_fade(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let t = state.check_number(2);
+            let col = state.check_integer(3);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            info.lock().unwrap().effects.fade(t, col as u32);
 
+            0
+        }
+
+        // flash col frames
+        unsafe extern "C" fn lua_flash(lua_context: *mut lua_State) -> c_int {
             let mut state = State::from_ptr(lua_context);
 
-            let x0 = state.check_integer(2);
-            let y0 = state.check_integer(3);
-            let x1 = state.check_integer(4);
-            let y1 = state.check_integer(5);
-            let col = state.check_integer(6);
+            let col = state.check_integer(2);
+            let frames = state.check_integer(3);
 
-            debug!("LUA RECTFILL x0:{:?} y0:{:?} x1:{:?} y1:{:?} col:{:?}",
-                   x0,
-                   y0,
-                   x1,
-                   y1,
-                   col);
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            info.lock().unwrap().effects.flash(col as u32, frames.max(0) as u32);
+
+            0
+        }
+
+        // shake intensity frames
+        unsafe extern "C" fn lua_shake(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let intensity = state.check_number(2);
+            let frames = state.check_integer(3);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            info.lock().unwrap().effects.shake(intensity, frames.max(0) as u32);
+
+            0
+        }
+
+        // particles_spawn x y count rate duration life life_var speed speed_var angle
+        // spread gravity drag sprite size size_end colors..., nil for the defaults
+        unsafe extern "C" fn lua_particles_spawn(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let mut def = EmitterDef::new();
+            def.x = state.to_numberx(2).unwrap_or(def.x);
+            def.y = state.to_numberx(3).unwrap_or(def.y);
+            def.count = state.to_numberx(4).map(|count| count.max(0.0) as u32).unwrap_or(def.count);
+            def.rate = state.to_numberx(5).unwrap_or(def.rate);
+            def.duration = state.to_numberx(6).unwrap_or(def.duration);
+            def.life = state.to_numberx(7).unwrap_or(def.life);
+            def.life_var = state.to_numberx(8).unwrap_or(def.life_var);
+            def.speed = state.to_numberx(9).unwrap_or(def.speed);
+            def.speed_var = state.to_numberx(10).unwrap_or(def.speed_var);
+            def.angle = state.to_numberx(11).unwrap_or(def.angle);
+            def.spread = state.to_numberx(12).unwrap_or(def.spread);
+            def.gravity = state.to_numberx(13).unwrap_or(def.gravity);
+            def.drag = state.to_numberx(14).unwrap_or(def.drag);
+            def.sprite = state.to_numberx(15).map(|sprite| sprite.max(0.0) as u32);
+            def.size = state.to_numberx(16).unwrap_or(def.size);
+            def.size_end = state.to_numberx(17).unwrap_or(def.size);
+
+            let mut colors = Vec::new();
+            for idx in 18..state.get_top() + 1 {
+                if let Some(color) = state.to_numberx(idx) {
+                    colors.push(color.max(0.0) as u32);
+                }
+            }
+            if !colors.is_empty() {
+                def.colors = colors;
+            }
 
             let screen = state.with_extra(|extra| {
                                               let data = extra
@@ -1271,24 +3239,20 @@ pub mod plugin {
                                                   .unwrap();
                                               data.screen.clone()
                                           });
-            screen
-                .lock()
-                .unwrap()
-                .rectfill(x0 as i32, y0 as i32, x1 as i32, y1 as i32, col as i32);
+
+            let id = screen.lock().unwrap().particles.spawn(def);
+            state.push_integer(id as i64);
 
             1
         }
 
-        #[allow(non_snake_case)]
-        unsafe extern "C" fn lua_circ(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA CIRC");
-
+        // particles_move id x y
+        unsafe extern "C" fn lua_particles_move(lua_context: *mut lua_State) -> c_int {
             let mut state = State::from_ptr(lua_context);
 
-            let x = state.check_integer(2);
-            let y = state.check_integer(3);
-            let r = state.check_integer(4);
-            let col = state.check_integer(5);
+            let id = state.check_integer(2);
+            let x = state.check_number(3);
+            let y = state.check_number(4);
 
             let screen = state.with_extra(|extra| {
                                               let data = extra
@@ -1299,26 +3263,16 @@ pub mod plugin {
                                               data.screen.clone()
                                           });
 
-            screen
-                .lock()
-                .unwrap()
-                .circ(x as i32, y as i32, r as i32, col as i32);
+            screen.lock().unwrap().particles.move_to(id as u32, x, y);
 
-            1
+            0
         }
 
-        #[allow(non_snake_case)]
-        unsafe extern "C" fn lua_circfill(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA CIRCFILL");
-
+        // particles_stop id
+        unsafe extern "C" fn lua_particles_stop(lua_context: *mut lua_State) -> c_int {
             let mut state = State::from_ptr(lua_context);
 
-            let x = state.check_integer(2);
-            let y = state.check_integer(3);
-            let r = state.check_integer(4);
-            let col = state.check_integer(5);
-
-            debug!("LUA CIRCFILL {:?} {:?} {:?} {:?}", x, y, r, col);
+            let id = state.check_integer(2);
 
             let screen = state.with_extra(|extra| {
                                               let data = extra
@@ -1329,27 +3283,14 @@ pub mod plugin {
                                               data.screen.clone()
                                           });
 
-            screen
-                .lock()
-                .unwrap()
-                .circfill(x as i32, y as i32, r as i32, col as i32);
+            screen.lock().unwrap().particles.stop(id as u32);
 
-            1
+            0
         }
 
-        #[allow(non_snake_case)]
-        unsafe extern "C" fn lua_clip(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA CLIP");
-
+        unsafe extern "C" fn lua_particles_clear(lua_context: *mut lua_State) -> c_int {
             let mut state = State::from_ptr(lua_context);
 
-            let x = state.check_integer(2);
-            let y = state.check_integer(3);
-            let w = state.check_integer(4);
-            let h = state.check_integer(5);
-
-            debug!("LUA CLIP {:?} {:?} {:?} {:?}", x, y, w, h);
-
             let screen = state.with_extra(|extra| {
                                               let data = extra
                                                   .as_ref()
@@ -1359,33 +3300,14 @@ pub mod plugin {
                                               data.screen.clone()
                                           });
 
-            screen
-                .lock()
-                .unwrap()
-                .clip(x as i32, y as i32, w as i32, h as i32);
+            screen.lock().unwrap().particles.clear();
 
-            1
+            0
         }
 
-        #[allow(non_snake_case)]
-        unsafe extern "C" fn lua_ellipse(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA ELLIPSE");
-
+        unsafe extern "C" fn lua_particles_count(lua_context: *mut lua_State) -> c_int {
             let mut state = State::from_ptr(lua_context);
 
-            let x = state.check_integer(2);
-            let y = state.check_integer(3);
-            let rx = state.check_integer(4);
-            let ry = state.check_integer(5);
-            let col = state.check_integer(6);
-
-            debug!("LUA ELLIPSE x:{:?} y:{:?} rx:{:?} ry:{:?} col:{:?}",
-                   x,
-                   y,
-                   rx,
-                   ry,
-                   col);
-
             let screen = state.with_extra(|extra| {
                                               let data = extra
                                                   .as_ref()
@@ -1394,33 +3316,16 @@ pub mod plugin {
                                                   .unwrap();
                                               data.screen.clone()
                                           });
-            screen
-                .lock()
-                .unwrap()
-                .ellipse(x as i32, y as i32, rx as i32, ry as i32, col as i32);
+
+            let count = screen.lock().unwrap().particles.count();
+            state.push_integer(count as i64);
 
             1
         }
 
-        #[allow(non_snake_case)]
-        unsafe extern "C" fn lua_ellipsefill(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA ELLIPSEFILL");
-
+        unsafe extern "C" fn lua_particles_draw(lua_context: *mut lua_State) -> c_int {
             let mut state = State::from_ptr(lua_context);
 
-            let x = state.check_integer(2);
-            let y = state.check_integer(3);
-            let rx = state.check_integer(4);
-            let ry = state.check_integer(5);
-            let col = state.check_integer(6);
-
-            debug!("LUA ELLIPSEFILL x:{:?} y:{:?} rx:{:?} ry:{:?} col:{:?}",
-                   x,
-                   y,
-                   rx,
-                   ry,
-                   col);
-
             let screen = state.with_extra(|extra| {
                                               let data = extra
                                                   .as_ref()
@@ -1429,37 +3334,22 @@ pub mod plugin {
                                                   .unwrap();
                                               data.screen.clone()
                                           });
-            screen
-                .lock()
-                .unwrap()
-                .ellipsefill(x as i32, y as i32, rx as i32, ry as i32, col as i32);
-
-            1
-        }
 
+            screen.lock().unwrap().particles_draw();
 
-        #[allow(non_snake_case)]
-        unsafe extern "C" fn lua_trigon(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA TRIGON");
+            0
+        }
 
+        // camera3d eye_x eye_y eye_z target_x target_y target_z
+        unsafe extern "C" fn lua_camera3d(lua_context: *mut lua_State) -> c_int {
             let mut state = State::from_ptr(lua_context);
 
-            let x1 = state.check_integer(2);
-            let y1 = state.check_integer(3);
-            let x2 = state.check_integer(4);
-            let y2 = state.check_integer(5);
-            let x3 = state.check_integer(6);
-            let y3 = state.check_integer(7);
-            let col = state.check_integer(8);
-
-            debug!("LUA TRIGON x1:{:?} y1:{:?} x2:{:?} y2:{:?} x3:{:?} y3:{:?} col:{:?}",
-                   x1,
-                   y1,
-                   x2,
-                   y2,
-                   x3,
-                   y3,
-                   col);
+            let ex = state.check_number(2);
+            let ey = state.check_number(3);
+            let ez = state.check_number(4);
+            let tx = state.check_number(5);
+            let ty = state.check_number(6);
+            let tz = state.check_number(7);
 
             let screen = state.with_extra(|extra| {
                                               let data = extra
@@ -1469,30 +3359,18 @@ pub mod plugin {
                                                   .unwrap();
                                               data.screen.clone()
                                           });
-            screen
-                .lock()
-                .unwrap()
-                .trigon(x1 as i32,
-                        y1 as i32,
-                        x2 as i32,
-                        y2 as i32,
-                        x3 as i32,
-                        y3 as i32,
-                        col as i32);
 
-            1
-        }
+            screen.lock().unwrap().gfx3d.camera([ex, ey, ez], [tx, ty, tz]);
 
-        #[allow(non_snake_case)]
-        unsafe extern "C" fn lua_palt(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA PALT");
+            0
+        }
 
+        // perspective3d fov near
+        unsafe extern "C" fn lua_perspective3d(lua_context: *mut lua_State) -> c_int {
             let mut state = State::from_ptr(lua_context);
 
-            let c = state.check_integer(2);
-            let t = state.check_integer(3);
-
-            debug!("LUA PALT {:?} {:?}", c, t);
+            let fov = state.check_number(2);
+            let near = state.check_number(3);
 
             let screen = state.with_extra(|extra| {
                                               let data = extra
@@ -1503,20 +3381,19 @@ pub mod plugin {
                                               data.screen.clone()
                                           });
 
-            screen.lock().unwrap().palt(c as i32, t == 1);
+            screen.lock().unwrap().gfx3d.perspective(fov, near);
 
-            1
+            0
         }
 
-
-        unsafe extern "C" fn lua_pal(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA PAL");
-
+        // light3d x y z ambient
+        unsafe extern "C" fn lua_light3d(lua_context: *mut lua_State) -> c_int {
             let mut state = State::from_ptr(lua_context);
 
-            let c0 = state.check_integer(2);
-            let c1 = state.check_integer(3);
-            //            let p = state.check_integer(4);
+            let x = state.check_number(2);
+            let y = state.check_number(3);
+            let z = state.check_number(4);
+            let ambient = state.check_number(5);
 
             let screen = state.with_extra(|extra| {
                                               let data = extra
@@ -1527,23 +3404,17 @@ pub mod plugin {
                                               data.screen.clone()
                                           });
 
-            screen.lock().unwrap().pal(c0 as i32, c1 as i32);
+            screen.lock().unwrap().gfx3d.set_light(x, y, z, ambient);
 
-            1
+            0
         }
 
-        unsafe extern "C" fn lua_pset(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA PSET");
-
+        // mode3d "sort"|"zbuffer" cull
+        unsafe extern "C" fn lua_mode3d(lua_context: *mut lua_State) -> c_int {
             let mut state = State::from_ptr(lua_context);
 
-            let x = state.check_integer(2);
-            let y = state.check_integer(3);
-            let col = state.check_integer(4);
-
-            if x < 0 || y < 0 {
-                return 1;
-            }
+            let mode = state.check_string(2).to_string();
+            let cull = state.check_integer(3);
 
             let screen = state.with_extra(|extra| {
                                               let data = extra
@@ -1554,23 +3425,19 @@ pub mod plugin {
                                               data.screen.clone()
                                           });
 
-            screen.lock().unwrap().pset(x as i32, y as i32, col as i32);
+            let mut screen = screen.lock().unwrap();
+            match DepthMode::from_name(&mode) {
+                Some(mode) => screen.gfx3d.mode = mode,
+                None => warn!("[PLUGIN][LUA] Unknown 3D mode {:?}", mode),
+            }
+            screen.gfx3d.cull = cull != 0;
 
-            1
+            0
         }
 
-        unsafe extern "C" fn lua_pget(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA PGET");
-
+        unsafe extern "C" fn lua_push3d(lua_context: *mut lua_State) -> c_int {
             let mut state = State::from_ptr(lua_context);
 
-            let x = state.check_integer(2);
-            let y = state.check_integer(3);
-
-            if x < 0 || y < 0 {
-                return 1;
-            }
-
             let screen = state.with_extra(|extra| {
                                               let data = extra
                                                   .as_ref()
@@ -1580,31 +3447,30 @@ pub mod plugin {
                                               data.screen.clone()
                                           });
 
-            let value = screen.lock().unwrap().pget(x as u32, y as u32);
-
-            state.push_integer(value as i64);
+            screen.lock().unwrap().gfx3d.push();
 
-            1
+            0
         }
 
-
-        unsafe extern "C" fn lua_sget(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA SGET");
-
+        unsafe extern "C" fn lua_pop3d(lua_context: *mut lua_State) -> c_int {
             let mut state = State::from_ptr(lua_context);
 
-            let x = state.check_integer(2);
-            let y = state.check_integer(3);
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
 
-            debug!("LUA SGET {:?} {:?}", x, y);
+            screen.lock().unwrap().gfx3d.pop();
 
-            if x < 0 || y < 0 {
-                return 1;
-            }
+            0
+        }
 
-            if x as usize >= 128 || y as usize >= 128 {
-                return 1;
-            }
+        unsafe extern "C" fn lua_identity3d(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
 
             let screen = state.with_extra(|extra| {
                                               let data = extra
@@ -1615,31 +3481,18 @@ pub mod plugin {
                                               data.screen.clone()
                                           });
 
-            let value = screen.lock().unwrap().sget(x as u32, y as u32);
-
-            state.push_integer(value as i64);
+            screen.lock().unwrap().gfx3d.set_identity();
 
-            1
+            0
         }
 
-        unsafe extern "C" fn lua_sset(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA SSET");
-
+        // translate3d x y z
+        unsafe extern "C" fn lua_translate3d(lua_context: *mut lua_State) -> c_int {
             let mut state = State::from_ptr(lua_context);
 
-            let x = state.check_integer(2);
-            let y = state.check_integer(3);
-            let col = state.check_integer(4);
-
-            debug!("LUA SSET {:?} {:?} {:?}", x, y, col);
-
-            if x < 0 || y < 0 {
-                return 1;
-            }
-
-            if x as usize >= 128 || y as usize >= 128 {
-                return 1;
-            }
+            let x = state.check_number(2);
+            let y = state.check_number(3);
+            let z = state.check_number(4);
 
             let screen = state.with_extra(|extra| {
                                               let data = extra
@@ -1650,70 +3503,92 @@ pub mod plugin {
                                               data.screen.clone()
                                           });
 
-            screen.lock().unwrap().sset(x as u32, y as u32, col as i32);
+            screen.lock().unwrap().gfx3d.apply(&gfx3d::translation(x, y, z));
 
-            1
+            0
         }
 
-        unsafe extern "C" fn lua_noise(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA NOISE");
-
+        // rotate3d x y z
+        unsafe extern "C" fn lua_rotate3d(lua_context: *mut lua_State) -> c_int {
             let mut state = State::from_ptr(lua_context);
 
             let x = state.check_number(2);
             let y = state.check_number(3);
             let z = state.check_number(4);
 
-            debug!("LUA NOISE {:?} {:?} {:?}", x, y, z);
-
-            let noise = state.with_extra(|extra| {
-                                             let data = extra
-                                                 .as_ref()
-                                                 .unwrap()
-                                                 .downcast_ref::<ExtraData>()
-                                                 .unwrap();
-                                             data.noise.clone()
-                                         });
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
 
-            let value = noise.lock().unwrap().get(x, y, z);
-            state.push_number(value);
+            screen.lock().unwrap().gfx3d.apply(&gfx3d::rotation(x, y, z));
 
-            1
+            0
         }
 
-        unsafe extern "C" fn lua_noise_set_seed(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA NOISE SET SEED");
-
+        // scale3d x y z
+        unsafe extern "C" fn lua_scale3d(lua_context: *mut lua_State) -> c_int {
             let mut state = State::from_ptr(lua_context);
 
-            let seed = state.check_integer(2);
-
-            debug!("LUA NOISE SET SEED {:?}", seed);
+            let x = state.check_number(2);
+            let y = state.check_number(3);
+            let z = state.check_number(4);
 
-            let noise = state.with_extra(|extra| {
-                                             let data = extra
-                                                 .as_ref()
-                                                 .unwrap()
-                                                 .downcast_ref::<ExtraData>()
-                                                 .unwrap();
-                                             data.noise.clone()
-                                         });
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
 
-            noise.lock().unwrap().set_seed(seed as u32);
+            screen.lock().unwrap().gfx3d.apply(&gfx3d::scaling(x, y, z));
 
-            1
+            0
         }
 
-        unsafe extern "C" fn lua_line(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA LINE");
-
+        // mesh3d {x1, y1, z1, ...} {a1, b1, c1, color1, ...}
+        unsafe extern "C" fn lua_mesh3d(lua_context: *mut lua_State) -> c_int {
             let mut state = State::from_ptr(lua_context);
 
-            let x0 = state.check_integer(2);
-            let y0 = state.check_integer(3);
-            let x1 = state.check_integer(4);
-            let y1 = state.check_integer(5);
-            let col = state.check_integer(6);
+            state.check_type(2, Type::Table);
+            state.check_type(3, Type::Table);
+
+            let len = state.raw_len(2) as i64;
+            let mut vertices = Vec::with_capacity((len / 3) as usize);
+            let mut idx = 1;
+            while idx + 2 <= len {
+                state.raw_geti(2, idx);
+                state.raw_geti(2, idx + 1);
+                state.raw_geti(2, idx + 2);
+                vertices.push([state.to_number(-3), state.to_number(-2), state.to_number(-1)]);
+                state.pop(3);
+                idx += 3;
+            }
+
+            // The vertices are counted from 1 in Lua
+            let len = state.raw_len(3) as i64;
+            let mut faces = Vec::with_capacity((len / 4) as usize);
+            let mut idx = 1;
+            while idx + 3 <= len {
+                let mut face = [0; 3];
+                for (offset, vertex) in face.iter_mut().enumerate() {
+                    state.raw_geti(3, idx + offset as i64);
+                    *vertex = (state.to_number(-1) as i64 - 1) as usize;
+                    state.pop(1);
+                }
+                state.raw_geti(3, idx + 3);
+                let color = state.to_number(-1).max(0.0) as u32;
+                state.pop(1);
+
+                faces.push((face, color));
+                idx += 4;
+            }
 
             let screen = state.with_extra(|extra| {
                                               let data = extra
@@ -1724,21 +3599,27 @@ pub mod plugin {
                                               data.screen.clone()
                                           });
 
-            screen
+            let id = screen
                 .lock()
                 .unwrap()
-                .line(x0 as i32, y0 as i32, x1 as i32, y1 as i32, col as i32);
+                .gfx3d
+                .mesh_new(Mesh {
+                              vertices: vertices,
+                              faces: faces,
+                          });
+            match id {
+                Some(id) => state.push_integer(id as i64),
+                None => state.push_nil(),
+            }
 
             1
         }
 
-        unsafe extern "C" fn lua_fget(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA FGET");
-
+        // mesh3d_del id
+        unsafe extern "C" fn lua_mesh3d_del(lua_context: *mut lua_State) -> c_int {
             let mut state = State::from_ptr(lua_context);
 
-            let idx = state.check_integer(2);
-            let flag = state.check_integer(3);
+            let id = state.check_integer(2);
 
             let screen = state.with_extra(|extra| {
                                               let data = extra
@@ -1749,17 +3630,16 @@ pub mod plugin {
                                               data.screen.clone()
                                           });
 
-            state.push_bool(screen.lock().unwrap().fget(idx as u32, flag as u8));
+            screen.lock().unwrap().gfx3d.mesh_del(id as u32);
 
-            1
+            0
         }
 
-        unsafe extern "C" fn lua_fget_all(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA FGET ALL");
-
+        // draw3d id
+        unsafe extern "C" fn lua_draw3d(lua_context: *mut lua_State) -> c_int {
             let mut state = State::from_ptr(lua_context);
 
-            let idx = state.check_integer(2);
+            let id = state.check_integer(2);
 
             let screen = state.with_extra(|extra| {
                                               let data = extra
@@ -1770,19 +3650,25 @@ pub mod plugin {
                                               data.screen.clone()
                                           });
 
-            state.push_integer(screen.lock().unwrap().fget_all(idx as u32) as i64);
+            screen.lock().unwrap().draw3d(id as u32);
 
-            1
+            0
         }
 
-        unsafe extern "C" fn lua_fset(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA FSET");
-
+        // tri3d x0 y0 z0 x1 y1 z1 x2 y2 z2 color
+        unsafe extern "C" fn lua_tri3d(lua_context: *mut lua_State) -> c_int {
             let mut state = State::from_ptr(lua_context);
 
-            let idx = state.check_integer(2);
-            let flag = state.check_integer(3);
-            let value = state.check_integer(4);
+            let x0 = state.check_number(2);
+            let y0 = state.check_number(3);
+            let z0 = state.check_number(4);
+            let x1 = state.check_number(5);
+            let y1 = state.check_number(6);
+            let z1 = state.check_number(7);
+            let x2 = state.check_number(8);
+            let y2 = state.check_number(9);
+            let z2 = state.check_number(10);
+            let color = state.check_integer(11);
 
             let screen = state.with_extra(|extra| {
                                               let data = extra
@@ -1796,19 +3682,14 @@ pub mod plugin {
             screen
                 .lock()
                 .unwrap()
-                .fset(idx as u32, flag as u8, value == 1);
+                .tri3d([[x0, y0, z0], [x1, y1, z1], [x2, y2, z2]], color as i32);
 
-            1
+            0
         }
 
-        unsafe extern "C" fn lua_fset_all(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA FSET ALL");
-
+        unsafe extern "C" fn lua_render3d(lua_context: *mut lua_State) -> c_int {
             let mut state = State::from_ptr(lua_context);
 
-            let idx = state.check_integer(2);
-            let flags = state.check_integer(3);
-
             let screen = state.with_extra(|extra| {
                                               let data = extra
                                                   .as_ref()
@@ -1818,45 +3699,23 @@ pub mod plugin {
                                               data.screen.clone()
                                           });
 
-            screen.lock().unwrap().fset_all(idx as u32, flags as u8);
+            let count = screen.lock().unwrap().render3d();
+            state.push_integer(count as i64);
 
             1
         }
 
-        unsafe extern "C" fn lua_rnd(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA RND");
-
-            let mut state = State::from_ptr(lua_context);
-
-            let x = state.check_integer(2);
-
-            let value: f64;
-
-            if x == 0 {
-                value = rand::thread_rng().gen_range(0.0, 1.0);
-            } else {
-                value = rand::thread_rng().gen_range(0.0, x as f64);
-            }
-
-            state.push_number(value);
-
-            1
-        }
+        #[allow(non_snake_case)]
+        unsafe extern "C" fn lua_rect(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA RECT");
 
-        // spr n x y [w h] [flip_x] [flip_y]
-        unsafe extern "C" fn lua_spr(lua_context: *mut lua_State) -> c_int {
             let mut state = State::from_ptr(lua_context);
 
-            let n = state.check_integer(2);
-            let x = state.check_integer(3);
-            let y = state.check_integer(4);
-            let w = state.check_integer(5);
-            let h = state.check_integer(6);
-            let flip_x = state.check_integer(7);
-            let flip_y = state.check_integer(8);
-            let angle = state.check_number(9);
-            let zoom = state.check_number(10);
-            let dynamic = state.check_integer(11);
+            let x0 = state.check_integer(2);
+            let y0 = state.check_integer(3);
+            let x1 = state.check_integer(4);
+            let y1 = state.check_integer(5);
+            let col = state.check_integer(6);
 
             let screen = state.with_extra(|extra| {
                                               let data = extra
@@ -1870,34 +3729,56 @@ pub mod plugin {
             screen
                 .lock()
                 .unwrap()
-                .spr(n as u32,
-                     x as i32,
-                     y as i32,
-                     w as i32,
-                     h as i32,
-                     flip_x == 1,
-                     flip_y == 1,
-                     angle,
-                     zoom,
-                     dynamic == 1);
+                .rect(x0 as i32, y0 as i32, x1 as i32, y1 as i32, col as i32);
 
             1
         }
 
-        // sspr sx sy sw sh dx dy [dw dh] [flip_x] [flip_y]
-        unsafe extern "C" fn lua_sspr(lua_context: *mut lua_State) -> c_int {
+        #[allow(non_snake_case)]
+        unsafe extern "C" fn lua_rectfill(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA RECTFILL");
+
             let mut state = State::from_ptr(lua_context);
 
-            let sx = state.check_integer(2);
-            let sy = state.check_integer(3);
-            let sw = state.check_integer(4);
-            let sh = state.check_integer(5);
-            let dx = state.check_integer(6);
-            let dy = state.check_integer(7);
-            let dw = state.check_integer(8);
-            let dh = state.check_integer(9);
-            let flip_x = state.check_integer(10);
-            let flip_y = state.check_integer(11);
+            let x0 = state.check_integer(2);
+            let y0 = state.check_integer(3);
+            let x1 = state.check_integer(4);
+            let y1 = state.check_integer(5);
+            let col = state.check_integer(6);
+
+            debug!("LUA RECTFILL x0:{:?} y0:{:?} x1:{:?} y1:{:?} col:{:?}",
+                   x0,
+                   y0,
+                   x1,
+                   y1,
+                   col);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+            screen
+                .lock()
+                .unwrap()
+                .rectfill(x0 as i32, y0 as i32, x1 as i32, y1 as i32, col as i32);
+
+            1
+        }
+
+        #[allow(non_snake_case)]
+        unsafe extern "C" fn lua_circ(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA CIRC");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let x = state.check_integer(2);
+            let y = state.check_integer(3);
+            let r = state.check_integer(4);
+            let col = state.check_integer(5);
 
             let screen = state.with_extra(|extra| {
                                               let data = extra
@@ -1911,33 +3792,23 @@ pub mod plugin {
             screen
                 .lock()
                 .unwrap()
-                .sspr(sx as u32,
-                      sy as u32,
-                      sw as u32,
-                      sh as u32,
-                      dx as i32,
-                      dy as i32,
-                      dw as u32,
-                      dh as u32,
-                      flip_x == 1,
-                      flip_y == 1);
+                .circ(x as i32, y as i32, r as i32, col as i32);
 
             1
         }
 
-        // map cel_x cel_y sx sy cel_w cel_h [layer]
-        unsafe extern "C" fn lua_mapdraw(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA MAP");
+        #[allow(non_snake_case)]
+        unsafe extern "C" fn lua_circfill(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA CIRCFILL");
 
             let mut state = State::from_ptr(lua_context);
 
-            let cel_x = state.check_integer(2);
-            let cel_y = state.check_integer(3);
-            let sx = state.check_integer(4);
-            let sy = state.check_integer(5);
-            let cel_w = state.check_integer(6);
-            let cel_h = state.check_integer(7);
-            let layer = state.check_integer(8);
+            let x = state.check_integer(2);
+            let y = state.check_integer(3);
+            let r = state.check_integer(4);
+            let col = state.check_integer(5);
+
+            debug!("LUA CIRCFILL {:?} {:?} {:?} {:?}", x, y, r, col);
 
             let screen = state.with_extra(|extra| {
                                               let data = extra
@@ -1951,98 +3822,2352 @@ pub mod plugin {
             screen
                 .lock()
                 .unwrap()
-                .mapdraw(cel_x as u32,
-                         cel_y as u32,
-                         sx as i32,
-                         sy as i32,
-                         cel_w as u32,
-                         cel_h as u32,
-                         layer as u8);
+                .circfill(x as i32, y as i32, r as i32, col as i32);
+
+            1
+        }
+
+        #[allow(non_snake_case)]
+        unsafe extern "C" fn lua_clip(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA CLIP");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let x = state.check_integer(2);
+            let y = state.check_integer(3);
+            let w = state.check_integer(4);
+            let h = state.check_integer(5);
+
+            debug!("LUA CLIP {:?} {:?} {:?} {:?}", x, y, w, h);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen
+                .lock()
+                .unwrap()
+                .clip(x as i32, y as i32, w as i32, h as i32);
+
+            1
+        }
+
+        // clip_push(x, y, w, h): clip layer inside the current clip
+        unsafe extern "C" fn lua_clip_push(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA CLIP_PUSH");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let x = state.check_integer(2);
+            let y = state.check_integer(3);
+            let w = state.check_integer(4);
+            let h = state.check_integer(5);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            let value = screen
+                .lock()
+                .unwrap()
+                .clip_push(x as i32, y as i32, w as i32, h as i32);
+            state.push_integer(value as i64);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_clip_pop(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA CLIP_POP");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            let value = screen.lock().unwrap().clip_pop();
+            state.push_integer(value as i64);
+
+            1
+        }
+
+        // mode(w, h): resolution of the screen, one of 400x240, 128x128,
+        // 256x256 or 240x136. mode() returns the current one.
+        unsafe extern "C" fn lua_mode(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA MODE");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let width = state.check_integer(2);
+            let height = state.check_integer(3);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            let mut screen = screen.lock().unwrap();
+            if width < 0 || height < 0 {
+                state.push_integer(screen.width as i64);
+                state.push_integer(screen.height as i64);
+                return 2;
+            }
+
+            let value = screen.mode(width as usize, height as usize);
+            if !value {
+                warn!("[PLUGIN][LUA] Unknown mode {:?}x{:?}", width, height);
+            }
+            state.push_integer(value as i64);
+
+            1
+        }
+
+        #[allow(non_snake_case)]
+        unsafe extern "C" fn lua_ellipse(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA ELLIPSE");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let x = state.check_integer(2);
+            let y = state.check_integer(3);
+            let rx = state.check_integer(4);
+            let ry = state.check_integer(5);
+            let col = state.check_integer(6);
+
+            debug!("LUA ELLIPSE x:{:?} y:{:?} rx:{:?} ry:{:?} col:{:?}",
+                   x,
+                   y,
+                   rx,
+                   ry,
+                   col);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+            screen
+                .lock()
+                .unwrap()
+                .ellipse(x as i32, y as i32, rx as i32, ry as i32, col as i32);
+
+            1
+        }
+
+        #[allow(non_snake_case)]
+        unsafe extern "C" fn lua_ellipsefill(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA ELLIPSEFILL");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let x = state.check_integer(2);
+            let y = state.check_integer(3);
+            let rx = state.check_integer(4);
+            let ry = state.check_integer(5);
+            let col = state.check_integer(6);
+
+            debug!("LUA ELLIPSEFILL x:{:?} y:{:?} rx:{:?} ry:{:?} col:{:?}",
+                   x,
+                   y,
+                   rx,
+                   ry,
+                   col);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+            screen
+                .lock()
+                .unwrap()
+                .ellipsefill(x as i32, y as i32, rx as i32, ry as i32, col as i32);
+
+            1
+        }
+
+
+        #[allow(non_snake_case)]
+        unsafe extern "C" fn lua_trigon(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA TRIGON");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let x1 = state.check_integer(2);
+            let y1 = state.check_integer(3);
+            let x2 = state.check_integer(4);
+            let y2 = state.check_integer(5);
+            let x3 = state.check_integer(6);
+            let y3 = state.check_integer(7);
+            let col = state.check_integer(8);
+
+            debug!("LUA TRIGON x1:{:?} y1:{:?} x2:{:?} y2:{:?} x3:{:?} y3:{:?} col:{:?}",
+                   x1,
+                   y1,
+                   x2,
+                   y2,
+                   x3,
+                   y3,
+                   col);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+            screen
+                .lock()
+                .unwrap()
+                .trigon(x1 as i32,
+                        y1 as i32,
+                        x2 as i32,
+                        y2 as i32,
+                        x3 as i32,
+                        y3 as i32,
+                        col as i32);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_trifill(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA TRIFILL");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let x1 = state.check_integer(2);
+            let y1 = state.check_integer(3);
+            let x2 = state.check_integer(4);
+            let y2 = state.check_integer(5);
+            let x3 = state.check_integer(6);
+            let y3 = state.check_integer(7);
+            let col = state.check_integer(8);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+            screen
+                .lock()
+                .unwrap()
+                .trifill(x1 as i32,
+                         y1 as i32,
+                         x2 as i32,
+                         y2 as i32,
+                         x3 as i32,
+                         y3 as i32,
+                         col as i32);
+
+            1
+        }
+
+        #[allow(non_snake_case)]
+        unsafe extern "C" fn lua_palt(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA PALT");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let c = state.check_integer(2);
+            let t = state.check_integer(3);
+
+            debug!("LUA PALT {:?} {:?}", c, t);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen.lock().unwrap().palt(c as i32, t == 1);
+
+            1
+        }
+
+
+        // pal_cycle(first, last, speed), without range to stop all the cycles
+        unsafe extern "C" fn lua_pal_cycle(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA PAL CYCLE");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let first = state.check_integer(2);
+            let last = state.check_integer(3);
+            let speed = state.check_number(4);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            if first < 0 || last < 0 {
+                screen.lock().unwrap().pal_cycle_reset();
+            } else {
+                screen.lock().unwrap().pal_cycle(first as u32, last as u32, speed);
+            }
+
+            0
+        }
+
+        unsafe extern "C" fn lua_pal(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA PAL");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let c0 = state.check_integer(2);
+            let c1 = state.check_integer(3);
+            let p = state.check_integer(4);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            // 1: display palette, the pixels already drawn change too
+            if p == 1 {
+                screen.lock().unwrap().pal_screen(c0 as i32, c1 as i32);
+            } else {
+                screen.lock().unwrap().pal(c0 as i32, c1 as i32);
+            }
+
+            1
+        }
+
+        // palette_color(c, 0xRRGGBB), called by set_palette
+        unsafe extern "C" fn lua_palette_color(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA PALETTE COLOR");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let c = state.check_integer(2);
+            let value = state.check_integer(3);
+
+            if c >= 0 && c <= 255 {
+                PALETTE
+                    .lock()
+                    .unwrap()
+                    ._set_color(c as u32,
+                                ((value >> 16) & 0xff) as u8,
+                                ((value >> 8) & 0xff) as u8,
+                                (value & 0xff) as u8);
+            }
+
+            0
+        }
+
+        unsafe extern "C" fn lua_pset(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA PSET");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let x = state.check_integer(2);
+            let y = state.check_integer(3);
+            let col = state.check_integer(4);
+
+            if x < 0 || y < 0 {
+                return 1;
+            }
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen.lock().unwrap().pset(x as i32, y as i32, col as i32);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_pget(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA PGET");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let x = state.check_integer(2);
+            let y = state.check_integer(3);
+
+            if x < 0 || y < 0 {
+                return 1;
+            }
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            let value = screen.lock().unwrap().pget(x as u32, y as u32);
+
+            state.push_integer(value as i64);
+
+            1
+        }
+
+
+        unsafe extern "C" fn lua_sget(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA SGET");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let x = state.check_integer(2);
+            let y = state.check_integer(3);
+
+            debug!("LUA SGET {:?} {:?}", x, y);
+
+            if x < 0 || y < 0 {
+                return 1;
+            }
+
+            if x as usize >= 128 || y as usize >= 128 {
+                return 1;
+            }
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            let value = screen.lock().unwrap().sget(x as u32, y as u32);
+
+            state.push_integer(value as i64);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_sset(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA SSET");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let x = state.check_integer(2);
+            let y = state.check_integer(3);
+            let col = state.check_integer(4);
+
+            debug!("LUA SSET {:?} {:?} {:?}", x, y, col);
+
+            if x < 0 || y < 0 {
+                return 1;
+            }
+
+            if x as usize >= 128 || y as usize >= 128 {
+                return 1;
+            }
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen.lock().unwrap().sset(x as u32, y as u32, col as i32);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_noise(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA NOISE");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let x = state.check_number(2);
+            let y = state.check_number(3);
+            let z = state.check_number(4);
+
+            debug!("LUA NOISE {:?} {:?} {:?}", x, y, z);
+
+            let noise = state.with_extra(|extra| {
+                                             let data = extra
+                                                 .as_ref()
+                                                 .unwrap()
+                                                 .downcast_ref::<ExtraData>()
+                                                 .unwrap();
+                                             data.noise.clone()
+                                         });
+
+            let value = noise.lock().unwrap().get(x, y, z);
+            state.push_number(value);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_noise_set_seed(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA NOISE SET SEED");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let seed = state.check_integer(2);
+
+            debug!("LUA NOISE SET SEED {:?}", seed);
+
+            let noise = state.with_extra(|extra| {
+                                             let data = extra
+                                                 .as_ref()
+                                                 .unwrap()
+                                                 .downcast_ref::<ExtraData>()
+                                                 .unwrap();
+                                             data.noise.clone()
+                                         });
+
+            noise.lock().unwrap().set_seed(seed as u64);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_line(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA LINE");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let x0 = state.check_integer(2);
+            let y0 = state.check_integer(3);
+            let x1 = state.check_integer(4);
+            let y1 = state.check_integer(5);
+            let col = state.check_integer(6);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen
+                .lock()
+                .unwrap()
+                .line(x0 as i32, y0 as i32, x1 as i32, y1 as i32, col as i32);
+
+            1
+        }
+
+        // line_width(w): pen of the lines, returns the previous width
+        unsafe extern "C" fn lua_line_width(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let w = state.check_integer(2);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            let previous = screen.lock().unwrap().line_width(w as i32);
+            state.push_integer(previous as i64);
+
+            1
+        }
+
+        // bezier x0 y0 cx cy x1 y1 col
+        unsafe extern "C" fn lua_bezier(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let x0 = state.check_integer(2);
+            let y0 = state.check_integer(3);
+            let cx = state.check_integer(4);
+            let cy = state.check_integer(5);
+            let x1 = state.check_integer(6);
+            let y1 = state.check_integer(7);
+            let col = state.check_integer(8);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen
+                .lock()
+                .unwrap()
+                .bezier(x0 as i32,
+                        y0 as i32,
+                        cx as i32,
+                        cy as i32,
+                        x1 as i32,
+                        y1 as i32,
+                        col as i32);
+
+            0
+        }
+
+        // fillp(pattern, col2): 4x4 pattern of the shapes, its set bits are
+        // drawn with col2 (-1: not drawn)
+        unsafe extern "C" fn lua_fillp(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA FILLP");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let pattern = state.check_integer(2);
+            let col2 = state.check_integer(3);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen
+                .lock()
+                .unwrap()
+                .fillp((pattern & 0xffff) as u16, col2 as i32);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_fget(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA FGET");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let idx = state.check_integer(2);
+            let flag = state.check_integer(3);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            state.push_bool(screen.lock().unwrap().fget(idx as u32, flag as u8));
+
+            1
+        }
+
+        unsafe extern "C" fn lua_fget_all(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA FGET ALL");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let idx = state.check_integer(2);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            state.push_integer(screen.lock().unwrap().fget_all(idx as u32) as i64);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_fset(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA FSET");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let idx = state.check_integer(2);
+            let flag = state.check_integer(3);
+            let value = state.check_integer(4);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen
+                .lock()
+                .unwrap()
+                .fset(idx as u32, flag as u8, value == 1);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_fset_all(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA FSET ALL");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let idx = state.check_integer(2);
+            let flags = state.check_integer(3);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen.lock().unwrap().fset_all(idx as u32, flags as u8);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_rnd(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA RND");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let x = state.check_number(2);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            let value = info.lock().unwrap().random.rnd(x);
+            state.push_number(value);
+
+            1
+        }
+
+        // deprecated name, from the wrapper of the function
+        unsafe extern "C" fn lua_deprecated(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let name = state.check_string(2).to_string();
+
+            // The caller of the wrapper
+            state.location(2);
+            let line = state.to_str(-1).and_then(|location| deprecation::location_line(location));
+            state.pop(1);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            if let Some(message) = info.lock().unwrap().deprecations.warn(&name, line) {
+                warn!("[PLUGIN][LUA] {}", message);
+            }
+
+            0
+        }
+
+        unsafe extern "C" fn lua_srand(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA SRAND");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let seed = state.check_integer(2);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            info.lock().unwrap().random = Random::from_seed(seed as u64);
+
+            0
+        }
+
+        // The fixed-point numbers are the raw 16.16 integers
+        unsafe extern "C" fn lua_fx(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let x = state.check_number(2);
+            state.push_integer(Fixed::from_f64(x).0 as i64);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_fx_num(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let a = Fixed(state.check_integer(2) as i32);
+            state.push_number(a.to_f64());
+
+            1
+        }
+
+        unsafe extern "C" fn lua_fx_mul(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let a = Fixed(state.check_integer(2) as i32);
+            let b = Fixed(state.check_integer(3) as i32);
+            state.push_integer((a * b).0 as i64);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_fx_div(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let a = Fixed(state.check_integer(2) as i32);
+            let b = Fixed(state.check_integer(3) as i32);
+            state.push_integer((a / b).0 as i64);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_fx_sqrt(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let a = Fixed(state.check_integer(2) as i32);
+            state.push_integer(a.sqrt().0 as i64);
+
+            1
+        }
+
+        // Inverted like sin
+        unsafe extern "C" fn lua_fx_sin(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let a = Fixed(state.check_integer(2) as i32);
+            state.push_integer((-a.sin()).0 as i64);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_fx_cos(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let a = Fixed(state.check_integer(2) as i32);
+            state.push_integer(a.cos().0 as i64);
+
+            1
+        }
+
+        // spr n x y [w h] [flip_x] [flip_y]
+        unsafe extern "C" fn lua_spr(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let n = state.check_integer(2);
+            let x = state.check_integer(3);
+            let y = state.check_integer(4);
+            let w = state.check_integer(5);
+            let h = state.check_integer(6);
+            let flip_x = state.check_integer(7);
+            let flip_y = state.check_integer(8);
+            let angle = state.check_number(9);
+            let zoom = state.check_number(10);
+            let dynamic = state.check_integer(11);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen
+                .lock()
+                .unwrap()
+                .spr(n as u32,
+                     x as i32,
+                     y as i32,
+                     w as i32,
+                     h as i32,
+                     flip_x == 1,
+                     flip_y == 1,
+                     angle,
+                     zoom,
+                     dynamic == 1);
+
+            1
+        }
+
+        // spr_batch n {x1, y1, x2, y2, ...}
+        unsafe extern "C" fn lua_spr_batch(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let n = state.check_integer(2);
+            state.check_type(3, Type::Table);
+
+            let len = state.raw_len(3) as i64;
+            let mut positions = Vec::with_capacity((len / 2) as usize);
+            let mut idx = 1;
+            while idx < len {
+                state.raw_geti(3, idx);
+                state.raw_geti(3, idx + 1);
+                let x = state.to_number(-2).floor() as i32;
+                let y = state.to_number(-1).floor() as i32;
+                state.pop(2);
+
+                positions.push((x, y));
+                idx += 2;
+            }
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen.lock().unwrap().spr_batch(n as u32, &positions);
+
+            0
+        }
+
+        // polyfill {x1, y1, x2, y2, ...} color
+        unsafe extern "C" fn lua_polyfill(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            state.check_type(2, Type::Table);
+            let color = state.check_integer(3);
+
+            let len = state.raw_len(2) as i64;
+            let mut points = Vec::with_capacity((len / 2) as usize);
+            let mut idx = 1;
+            while idx < len {
+                state.raw_geti(2, idx);
+                state.raw_geti(2, idx + 1);
+                let x = state.to_number(-2).floor() as i32;
+                let y = state.to_number(-1).floor() as i32;
+                state.pop(2);
+
+                points.push((x, y));
+                idx += 2;
+            }
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen.lock().unwrap().polyfill(&points, color as i32);
+
+            0
+        }
+
+        // spr_tile n x y w h
+        unsafe extern "C" fn lua_spr_tile(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let n = state.check_integer(2);
+            let x = state.check_integer(3);
+            let y = state.check_integer(4);
+            let w = state.check_integer(5);
+            let h = state.check_integer(6);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen
+                .lock()
+                .unwrap()
+                .spr_tile(n as u32, x as i32, y as i32, w as i32, h as i32);
+
+            0
+        }
+
+        // spr_9slice n x y w h
+        unsafe extern "C" fn lua_spr_9slice(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let n = state.check_integer(2);
+            let x = state.check_integer(3);
+            let y = state.check_integer(4);
+            let w = state.check_integer(5);
+            let h = state.check_integer(6);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen
+                .lock()
+                .unwrap()
+                .spr_9slice(n as u32, x as i32, y as i32, w as i32, h as i32);
+
+            0
+        }
+
+        // sspr sx sy sw sh dx dy [dw dh] [flip_x] [flip_y]
+        unsafe extern "C" fn lua_sspr(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let sx = state.check_integer(2);
+            let sy = state.check_integer(3);
+            let sw = state.check_integer(4);
+            let sh = state.check_integer(5);
+            let dx = state.check_integer(6);
+            let dy = state.check_integer(7);
+            let dw = state.check_integer(8);
+            let dh = state.check_integer(9);
+            let flip_x = state.check_integer(10);
+            let flip_y = state.check_integer(11);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen
+                .lock()
+                .unwrap()
+                .sspr(sx as u32,
+                      sy as u32,
+                      sw as u32,
+                      sh as u32,
+                      dx as i32,
+                      dy as i32,
+                      dw as u32,
+                      dh as u32,
+                      flip_x == 1,
+                      flip_y == 1);
+
+            1
+        }
+
+        // sspr_rot sx sy sw sh dx dy dw dh angle flip_x flip_y, angle in turns
+        unsafe extern "C" fn lua_sspr_rot(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let sx = state.check_integer(2);
+            let sy = state.check_integer(3);
+            let sw = state.check_integer(4);
+            let sh = state.check_integer(5);
+            let dx = state.check_integer(6);
+            let dy = state.check_integer(7);
+            let dw = state.check_integer(8);
+            let dh = state.check_integer(9);
+            let angle = state.check_number(10);
+            let flip_x = state.check_integer(11);
+            let flip_y = state.check_integer(12);
+
+            if sx < 0 || sy < 0 || sw <= 0 || sh <= 0 || dw <= 0 || dh <= 0 {
+                return 0;
+            }
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen
+                .lock()
+                .unwrap()
+                .sspr_rot(sx as u32,
+                          sy as u32,
+                          sw as u32,
+                          sh as u32,
+                          dx as i32,
+                          dy as i32,
+                          dw as u32,
+                          dh as u32,
+                          angle,
+                          flip_x == 1,
+                          flip_y == 1);
+
+            0
+        }
+
+        // map cel_x cel_y sx sy cel_w cel_h [layer]
+        unsafe extern "C" fn lua_mapdraw(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA MAP");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let cel_x = state.check_integer(2);
+            let cel_y = state.check_integer(3);
+            let sx = state.check_integer(4);
+            let sy = state.check_integer(5);
+            let cel_w = state.check_integer(6);
+            let cel_h = state.check_integer(7);
+            let layer = state.check_integer(8);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen
+                .lock()
+                .unwrap()
+                .mapdraw(cel_x as u32,
+                         cel_y as u32,
+                         sx as i32,
+                         sy as i32,
+                         cel_w as u32,
+                         cel_h as u32,
+                         layer as u8);
+
+            1
+        }
+
+        // map_affine a b c d tx ty layer
+        unsafe extern "C" fn lua_map_affine(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA MAP_AFFINE");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let a = state.check_number(2);
+            let b = state.check_number(3);
+            let c = state.check_number(4);
+            let d = state.check_number(5);
+            let tx = state.check_number(6);
+            let ty = state.check_number(7);
+            let layer = state.check_integer(8);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen.lock().unwrap().map_affine(a, b, c, d, tx, ty, layer as u8);
+
+            1
+        }
+
+        // map_scanline y u v du dv layer
+        unsafe extern "C" fn lua_map_scanline(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA MAP_SCANLINE");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let y = state.check_integer(2);
+            let u = state.check_number(3);
+            let v = state.check_number(4);
+            let du = state.check_number(5);
+            let dv = state.check_number(6);
+            let layer = state.check_integer(7);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen.lock().unwrap().map_scanline(y as i32, u, v, du, dv, layer as u8);
+
+            1
+        }
+
+        // raycast x y angle fov layer max_distance
+        unsafe extern "C" fn lua_raycast(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA RAYCAST");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let x = state.check_number(2);
+            let y = state.check_number(3);
+            let angle = state.check_number(4);
+            let fov = state.check_number(5);
+            let layer = state.check_integer(6);
+            let max_distance = state.check_number(7);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            let depths = screen
+                .lock()
+                .unwrap()
+                .raycast(x, y, angle, fov, layer as u8, max_distance);
+
+            state.new_table();
+            for (idx, depth) in depths.iter().enumerate() {
+                state.push_number(*depth);
+                state.raw_seti(-2, idx as i64 + 1);
+            }
+
+            1
+        }
+
+        // collide_map x y w h flag
+        unsafe extern "C" fn lua_collide_map(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA COLLIDE_MAP");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let x = state.check_number(2);
+            let y = state.check_number(3);
+            let w = state.check_number(4);
+            let h = state.check_number(5);
+            let flag = state.check_integer(6);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            let value = screen
+                .lock()
+                .unwrap()
+                .collide_map(x, y, w, h, flag as u8);
+            state.push_bool(value);
+
+            1
+        }
+
+        // collide_rect x1 y1 w1 h1 x2 y2 w2 h2
+        unsafe extern "C" fn lua_collide_rect(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA COLLIDE_RECT");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let a = (state.check_number(2),
+                     state.check_number(3),
+                     state.check_number(4),
+                     state.check_number(5));
+            let b = (state.check_number(6),
+                     state.check_number(7),
+                     state.check_number(8),
+                     state.check_number(9));
+
+            state.push_bool(collision::rects_overlap(a, b));
+
+            1
+        }
+
+        // sprite_overlap n1 x1 y1 w1 h1 n2 x2 y2 w2 h2
+        unsafe extern "C" fn lua_sprite_overlap(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA SPRITE_OVERLAP");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let n1 = state.check_integer(2);
+            let x1 = state.check_integer(3);
+            let y1 = state.check_integer(4);
+            let w1 = state.check_integer(5);
+            let h1 = state.check_integer(6);
+            let n2 = state.check_integer(7);
+            let x2 = state.check_integer(8);
+            let y2 = state.check_integer(9);
+            let w2 = state.check_integer(10);
+            let h2 = state.check_integer(11);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            let value = screen
+                .lock()
+                .unwrap()
+                .sprite_overlap(n1 as u32,
+                                x1 as i32,
+                                y1 as i32,
+                                w1 as i32,
+                                h1 as i32,
+                                n2 as u32,
+                                x2 as i32,
+                                y2 as i32,
+                                w2 as i32,
+                                h2 as i32);
+            state.push_bool(value);
+
+            1
+        }
+
+        // pathfind x0 y0 x1 y1 flag_mask, table of {x=, y=} from the start to the goal
+        unsafe extern "C" fn lua_pathfind(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA PATHFIND");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let x0 = state.check_integer(2);
+            let y0 = state.check_integer(3);
+            let x1 = state.check_integer(4);
+            let y1 = state.check_integer(5);
+            let flag_mask = state.check_integer(6);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            let path = {
+                let mut screen = screen.lock().unwrap();
+                pathfind::find((x0 as i32, y0 as i32),
+                               (x1 as i32, y1 as i32),
+                               MAP_WIDTH,
+                               MAP_HEIGHT,
+                               &mut |cx, cy| screen.tile_flags(cx, cy) & flag_mask as u8 != 0)
+            };
+
+            state.new_table();
+            for (idx, &(x, y)) in path.unwrap_or(Vec::new()).iter().enumerate() {
+                state.new_table();
+                state.push_integer(x as i64);
+                state.set_field(-2, "x");
+                state.push_integer(y as i64);
+                state.set_field(-2, "y");
+                state.raw_seti(-2, idx as i64 + 1);
+            }
+
+            1
+        }
+
+        unsafe extern "C" fn lua_mget(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA MGET");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let x = state.check_integer(2);
+            let y = state.check_integer(3);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            let value = screen.lock().unwrap().mget(x as i32, y as i32);
+
+            state.push_integer(value as i64);
+
+            1
+        }
+
+
+        unsafe extern "C" fn lua_mset(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA MSET");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let x = state.check_integer(2);
+            let y = state.check_integer(3);
+            let v = state.check_integer(4);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen.lock().unwrap().mset(x as i32, y as i32, v as u32);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_peek(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA PEEK");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let addr = state.check_integer(2);
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            let value = match memory_addr(addr) {
+                Some(addr) => screen.lock().unwrap().peek(addr),
+                None => 0,
+            };
+
+            state.push_integer(value as i64);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_poke(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA POKE");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let addr = state.check_integer(2);
+            let val = state.check_integer(3);
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            if let Some(addr) = memory_addr(addr) {
+                screen.lock().unwrap().poke(addr, (val & 0xff) as u8);
+            }
+
+            0
+        }
+
+        unsafe extern "C" fn lua_memcpy(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA MEMCPY");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let dest_addr = state.check_integer(2);
+            let source_addr = state.check_integer(3);
+            let len = state.check_integer(4);
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            if let (Some(dest_addr), Some(source_addr), Some(len)) =
+                (memory_addr(dest_addr), memory_addr(source_addr), memory_len(len)) {
+                screen.lock().unwrap().memcpy(dest_addr, source_addr, len);
+            }
+
+            0
+        }
+
+        unsafe extern "C" fn lua_memset(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA MEMSET");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let dest_addr = state.check_integer(2);
+            let val = state.check_integer(3);
+            let len = state.check_integer(4);
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            if let (Some(dest_addr), Some(len)) = (memory_addr(dest_addr), memory_len(len)) {
+                screen.lock().unwrap().memset(dest_addr, (val & 0xff) as u8, len);
+            }
+
+            0
+        }
+
+        unsafe extern "C" fn lua_print(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA PRINT");
+
+            let mut state = State::from_ptr(lua_context);
+            let mut state2 = State::from_ptr(lua_context);
+
+            // Any bytes, the invalid UTF-8 is drawn as unknown characters
+            let str_data = match state2.to_bytes_in_place(2) {
+                Some(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+                None => state.check_string(2).to_string(),
+            };
+            let x = state.check_integer(3);
+            let y = state.check_integer(4);
+            let col = state.check_integer(5);
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen
+                .lock()
+                .unwrap()
+                .print(str_data, x as i32, y as i32, col as i32);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_print_font(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+            let mut state2 = State::from_ptr(lua_context);
+
+            // Any bytes, the invalid UTF-8 is drawn as unknown characters
+            let str_data = match state2.to_bytes_in_place(2) {
+                Some(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+                None => state.check_string(2).to_string(),
+            };
+            let x = state.check_integer(3);
+            let y = state.check_integer(4);
+            let col = state.check_integer(5);
+            let name = state.check_string(6).to_string();
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen
+                .lock()
+                .unwrap()
+                .print_font(str_data, x as i32, y as i32, col as i32, &name);
+
+            0
+        }
+
+        unsafe extern "C" fn lua_font(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let name = state.check_string(2).to_string();
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            screen.lock().unwrap().font(&name);
+
+            0
+        }
+
+        // font_load name sx sy w h first count {w1, w2, ...}
+        unsafe extern "C" fn lua_font_load(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let name = state.check_string(2).to_string();
+            let sx = state.check_integer(3);
+            let sy = state.check_integer(4);
+            let w = state.check_integer(5);
+            let h = state.check_integer(6);
+            let first = state.check_integer(7);
+            let count = state.check_integer(8);
+            state.check_type(9, Type::Table);
+
+            let len = state.raw_len(9) as i64;
+            let mut widths = Vec::with_capacity(len as usize);
+            for idx in 1..len + 1 {
+                state.raw_geti(9, idx);
+                widths.push(state.to_number(-1).floor() as i32);
+                state.pop(1);
+            }
+
+            let first = match char::from_u32(first as u32) {
+                Some(first) => first,
+                None => ' ',
+            };
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            let done = screen
+                .lock()
+                .unwrap()
+                .font_load(&name,
+                           sx as i32,
+                           sy as i32,
+                           w as i32,
+                           h as i32,
+                           first,
+                           count.max(0).min(::std::u32::MAX as i64) as u32,
+                           &widths);
+            state.push_bool(done);
+
+            1
+        }
+
+        // font_data name height {char, width, row1, row2, ..., char, ...}
+        unsafe extern "C" fn lua_font_data(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let name = state.check_string(2).to_string();
+            let height = state.check_integer(3);
+            state.check_type(4, Type::Table);
+
+            let len = state.raw_len(4) as i64;
+            let mut data = Vec::with_capacity(len as usize);
+            for idx in 1..len + 1 {
+                state.raw_geti(4, idx);
+                data.push(state.to_number(-1).floor() as u32);
+                state.pop(1);
+            }
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            let done = screen
+                .lock()
+                .unwrap()
+                .font_data(&name, height as i32, &data);
+            state.push_bool(done);
+
+            1
+        }
+
+        // timer_new seconds repeat
+        unsafe extern "C" fn lua_timer_new(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA TIMER NEW");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let delay = state.check_number(2);
+            let repeat = state.check_integer(3);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            let id = info.lock().unwrap().timers.timer(delay, repeat != 0);
+            state.push_integer(id as i64);
+
+            1
+        }
+
+        // tween_new from to duration easing
+        unsafe extern "C" fn lua_tween_new(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA TWEEN NEW");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let from = state.check_number(2);
+            let to = state.check_number(3);
+            let duration = state.check_number(4);
+            let name = state.check_string(5).to_string();
+
+            let easing = match Easing::from_name(&name) {
+                Some(easing) => easing,
+                None => {
+                    warn!("[PLUGIN][LUA] Unknown easing {:?}, linear is used", name);
+                    Easing::Linear
+                }
+            };
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            let id = info.lock().unwrap().timers.tween(from, to, duration, easing);
+            state.push_integer(id as i64);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_timer_cancel(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA TIMER CANCEL");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let id = state.check_integer(2);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            info.lock().unwrap().timers.cancel(id as u32);
+
+            0
+        }
+
+        unsafe extern "C" fn lua_timer_scale(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA TIMER SCALE");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let scale = state.check_number(2);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            info.lock().unwrap().timers.scale = scale.max(0.0);
+
+            0
+        }
+
+        // The next timer fired and true when it is finished, nil otherwise
+        unsafe extern "C" fn lua_timer_next(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            let fired = info.lock().unwrap().timers.next_fired();
+            match fired {
+                Some((id, done)) => {
+                    state.push_integer(id as i64);
+                    state.push_bool(done);
+                    2
+                }
+                None => {
+                    state.push_nil();
+                    1
+                }
+            }
+        }
+
+        // The next tween, its value and true when it is finished, nil otherwise
+        unsafe extern "C" fn lua_tween_next(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            let value = info.lock().unwrap().timers.next_value();
+            match value {
+                Some((id, value, done)) => {
+                    state.push_integer(id as i64);
+                    state.push_number(value);
+                    state.push_bool(done);
+                    3
+                }
+                None => {
+                    state.push_nil();
+                    1
+                }
+            }
+        }
+
+        // menuitem index label, a nil label removes the entry
+        unsafe extern "C" fn lua_menuitem(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA MENUITEM");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let index = state.check_integer(2);
+            let label = if state.is_none_or_nil(3) {
+                None
+            } else {
+                Some(state.check_string(3).to_string())
+            };
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            let value = info.lock().unwrap().menuitem(index as u32, label.as_ref().map(|label| label.as_str()));
+            state.push_bool(value);
+
+            1
+        }
+
+        // Entry of the pause menu chosen since the last call, or nil
+        unsafe extern "C" fn lua_menuitem_next(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            let index = info.lock().unwrap().menuitem_selected.take();
+            match index {
+                Some(index) => state.push_integer(index as i64),
+                None => state.push_nil(),
+            }
+
+            1
+        }
+
+        // ent_new tags layer
+        unsafe extern "C" fn lua_ent_new(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA ENT NEW");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let tags = state.check_string(2).to_string();
+            let layer = state.check_integer(3);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            let id = info.lock().unwrap().entities.add(&tags, layer as i32);
+            state.push_integer(id as i64);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_ent_del(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA ENT DEL");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let id = state.check_integer(2);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            info.lock().unwrap().entities.remove(id as u32);
+
+            0
+        }
+
+        // ent_tag id tag on
+        unsafe extern "C" fn lua_ent_tag(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA ENT TAG");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let id = state.check_integer(2);
+            let tag = state.check_string(3).to_string();
+            let value = state.check_integer(4);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            info.lock().unwrap().entities.tag(id as u32, &tag, value != 0);
+
+            0
+        }
+
+        unsafe extern "C" fn lua_ent_has(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let id = state.check_integer(2);
+            let tag = state.check_string(3).to_string();
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            let value = info.lock().unwrap().entities.has_tag(id as u32, &tag);
+            state.push_integer(value as i64);
+
+            1
+        }
+
+        unsafe extern "C" fn lua_ent_layer(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA ENT LAYER");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let id = state.check_integer(2);
+            let layer = state.check_integer(3);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            info.lock().unwrap().entities.set_layer(id as u32, layer as i32);
+
+            0
+        }
+
+        unsafe extern "C" fn lua_ent_count(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let tag = state.check_string(2).to_string();
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            let count = info.lock().unwrap().entities.count(&tag);
+            state.push_integer(count as i64);
+
+            1
+        }
+
+        // Table of the ids of the tag, by layer
+        unsafe extern "C" fn lua_ent_ids(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let tag = state.check_string(2).to_string();
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            let ids = info.lock().unwrap().entities.ids(&tag);
+
+            state.new_table();
+            for (idx, id) in ids.iter().enumerate() {
+                state.push_integer(*id as i64);
+                state.raw_seti(-2, idx as i64 + 1);
+            }
+
+            1
+        }
+
+        // body_new x y w h
+        unsafe extern "C" fn lua_body_new(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA BODY NEW");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let x = state.check_number(2);
+            let y = state.check_number(3);
+            let w = state.check_number(4);
+            let h = state.check_number(5);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            match Body::from_f64(x, y, w, h) {
+                Some(body) => {
+                    let id = info.lock().unwrap().physics.add(body);
+                    state.push_integer(id as i64);
+                }
+                None => state.push_nil(),
+            }
+
+            1
+        }
+
+        // One frame of the body against the flags of the map
+        unsafe extern "C" fn lua_body_move(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA BODY MOVE");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let id = state.check_integer(2);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            let screen = state.with_extra(|extra| {
+                                              let data = extra
+                                                  .as_ref()
+                                                  .unwrap()
+                                                  .downcast_ref::<ExtraData>()
+                                                  .unwrap();
+                                              data.screen.clone()
+                                          });
+
+            let mut info = info.lock().unwrap();
+            let mut screen = screen.lock().unwrap();
+            if let Some(body) = info.physics.get_mut(id as u32) {
+                body.step(&mut |cx, cy| screen.tile_flags(cx, cy));
+            }
+
+            0
+        }
+
+        // x, y, vx, vy, on_ground
+        unsafe extern "C" fn lua_body_get(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
+
+            let id = state.check_integer(2);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            let body = info.lock().unwrap().physics.get(id as u32).cloned();
+            match body {
+                Some(body) => {
+                    state.push_number(body.x.to_f64());
+                    state.push_number(body.y.to_f64());
+                    state.push_number(body.vx.to_f64());
+                    state.push_number(body.vy.to_f64());
+                    state.push_bool(body.on_ground);
+                    5
+                }
+                None => {
+                    state.push_nil();
+                    1
+                }
+            }
+        }
+
+        // body_set id x y vx vy, nil keeps the value
+        unsafe extern "C" fn lua_body_set(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA BODY SET");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let id = state.check_integer(2);
+            let mut values = [None; 4];
+            for (idx, value) in values.iter_mut().enumerate() {
+                let arg = idx as c_int + 3;
+                if !state.is_none_or_nil(arg) {
+                    *value = Some(state.check_number(arg));
+                }
+            }
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            let mut info = info.lock().unwrap();
+            if let Some(body) = info.physics.get_mut(id as u32) {
+                body.set(values[0], values[1], values[2], values[3]);
+            }
+
+            0
+        }
+
+        // body_gravity id gravity max_fall
+        unsafe extern "C" fn lua_body_gravity(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA BODY GRAVITY");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let id = state.check_integer(2);
+            let gravity = state.check_number(3);
+            let max_fall = if state.is_none_or_nil(4) {
+                None
+            } else {
+                Some(state.check_number(4))
+            };
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            let mut info = info.lock().unwrap();
+            if let Some(body) = info.physics.get_mut(id as u32) {
+                body.set_gravity(gravity, max_fall);
+            }
+
+            0
+        }
+
+        unsafe extern "C" fn lua_body_del(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA BODY DEL");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let id = state.check_integer(2);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
 
-            1
+            info.lock().unwrap().physics.remove(id as u32);
+
+            0
         }
 
-        unsafe extern "C" fn lua_mget(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA MGET");
+        unsafe extern "C" fn lua_time(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA TIME");
 
             let mut state = State::from_ptr(lua_context);
 
-            let x = state.check_integer(2);
-            let y = state.check_integer(3);
-
-            let screen = state.with_extra(|extra| {
-                                              let data = extra
-                                                  .as_ref()
-                                                  .unwrap()
-                                                  .downcast_ref::<ExtraData>()
-                                                  .unwrap();
-                                              data.screen.clone()
-                                          });
-
-            let value = screen.lock().unwrap().mget(x as i32, y as i32);
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
 
-            state.push_integer(value as i64);
+            state.push_integer(info.lock().unwrap().time());
 
             1
         }
 
+        // Section of the trace of `--trace`, until `trace_end`
+        unsafe extern "C" fn lua_trace_begin(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
 
-        unsafe extern "C" fn lua_mset(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA MSET");
+            let name = state.check_string(2).to_string();
 
-            let mut state = State::from_ptr(lua_context);
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
 
-            let x = state.check_integer(2);
-            let y = state.check_integer(3);
-            let v = state.check_integer(4);
+            info.lock().unwrap().trace.begin(&name);
 
-            let screen = state.with_extra(|extra| {
-                                              let data = extra
-                                                  .as_ref()
-                                                  .unwrap()
-                                                  .downcast_ref::<ExtraData>()
-                                                  .unwrap();
-                                              data.screen.clone()
-                                          });
+            0
+        }
 
-            screen.lock().unwrap().mset(x as i32, y as i32, v as u32);
+        unsafe extern "C" fn lua_trace_end(lua_context: *mut lua_State) -> c_int {
+            let mut state = State::from_ptr(lua_context);
 
-            1
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            info.lock().unwrap().trace.end();
+
+            0
         }
 
-        unsafe extern "C" fn lua_print(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA PRINT");
+        unsafe extern "C" fn lua_score_sign(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA SCORE SIGN");
 
             let mut state = State::from_ptr(lua_context);
-            let mut state2 = State::from_ptr(lua_context);
 
-            let str_data = state2.check_string(2);
-            let x = state.check_integer(3);
-            let y = state.check_integer(4);
-            let col = state.check_integer(5);
+            let value = state.check_integer(2);
 
-            let screen = state.with_extra(|extra| {
-                                              let data = extra
-                                                  .as_ref()
-                                                  .unwrap()
-                                                  .downcast_ref::<ExtraData>()
-                                                  .unwrap();
-                                              data.screen.clone()
-                                          });
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
 
-            screen
-                .lock()
-                .unwrap()
-                .print(str_data.to_string(), x as i32, y as i32, col as i32);
+            let blob = info.lock().unwrap().score_sign(value as i64);
+            state.push_string(&blob);
 
             1
         }
 
-        unsafe extern "C" fn lua_time(lua_context: *mut lua_State) -> c_int {
-            debug!("LUA TIME");
+        // The value of the blob, nil when the signature is wrong
+        unsafe extern "C" fn lua_score_verify(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA SCORE VERIFY");
 
             let mut state = State::from_ptr(lua_context);
 
+            let blob = state.check_string(2).to_string();
+
             let info = state.with_extra(|extra| {
                                             let data = extra
                                                 .as_ref()
@@ -2052,7 +6177,40 @@ pub mod plugin {
                                             data.info.clone()
                                         });
 
-            state.push_integer(info.lock().unwrap().time());
+            let value = info.lock().unwrap().score_verify(&blob);
+            match value {
+                Some(value) => state.push_integer(value),
+                None => state.push_nil(),
+            }
+
+            1
+        }
+
+        // strf fmt ...
+        unsafe extern "C" fn lua_strf(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA STRF");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let fmt = state.check_string(2).to_string();
+
+            let mut args = Vec::new();
+            for idx in 3..state.get_top() + 1 {
+                let arg = match state.type_of(idx) {
+                    Some(Type::Number) => FormatArg::Number(state.to_number(idx)),
+                    Some(Type::String) => {
+                        // luaL_tolstring pushes the string on the stack
+                        let value = state.to_str(idx).unwrap_or("").to_string();
+                        state.pop(1);
+                        FormatArg::Str(value)
+                    }
+                    Some(Type::Boolean) => FormatArg::Bool(state.to_bool(idx)),
+                    _ => FormatArg::Nil,
+                };
+                args.push(arg);
+            }
+
+            state.push_string(&format::strf(&fmt, &args));
 
             1
         }
@@ -2062,6 +6220,39 @@ pub mod plugin {
 
             let mut state = State::from_ptr(lua_context);
 
+            // stat("last_capture"): file of the last screenshot/record ("" before),
+            // stat("captures"): number of screenshots/records taken,
+            // stat("real_time"): seconds since the start, even in the pause menu,
+            // stat("over_budget"): milliseconds the last frame was over its
+            // budget (0 when on time), stat("frame_time"): milliseconds of
+            // _update and _draw in the last frame, stat("reduce_motion"): the
+            // player asked for fewer flashes and shakes
+            if state.type_of(2) == Some(Type::String) {
+                let name = state.check_string(2).to_string();
+
+                let info = state.with_extra(|extra| {
+                                                let data = extra
+                                                    .as_ref()
+                                                    .unwrap()
+                                                    .downcast_ref::<ExtraData>()
+                                                    .unwrap();
+                                                data.info.clone()
+                                            });
+
+                let info = info.lock().unwrap();
+                match name.as_str() {
+                    "last_capture" => state.push_string(&info.last_capture),
+                    "captures" => state.push_integer(info.captures as i64),
+                    "real_time" => state.push_number(info.elapsed_time),
+                    "over_budget" => state.push_number(info.over_budget()),
+                    "frame_time" => state.push_number(info.frame_time * 1000.0),
+                    "reduce_motion" => state.push_bool(info.display.reduce_motion),
+                    _ => state.push_nil(),
+                }
+
+                return 1;
+            }
+
             let value = state.check_integer(2);
 
             let players = state.with_extra(|extra| {
@@ -2089,62 +6280,231 @@ pub mod plugin {
         }
 
 
+        // extcmd("screenshot", name): screenshot of the next frame in `name`
+        // extcmd("rec_len"|"rec_fps"|"rec_scale", value): GIF recording
+        // extcmd("scale", n), extcmd("fullscreen", [on]): size of the window
+        // extcmd("keyboard"): on-screen keyboard of a handheld
+        unsafe extern "C" fn lua_extcmd(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA EXTCMD");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let cmd = state.check_string(2).to_string();
+            let arg = if state.is_none_or_nil(3) {
+                None
+            } else {
+                Some(state.check_string(3).to_string())
+            };
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            let value = info.lock().unwrap().extcmd(&cmd, arg.as_ref().map(|arg| arg.as_str()));
+            if let Err(e) = value {
+                warn!("[PLUGIN][LUA] {}", e);
+            }
+
+            0
+        }
+
+        // set_filter(name): post-processing of the screen ("none", "scanlines",
+        // "crt", "lcd", "smooth"), false for an unknown filter
+        unsafe extern "C" fn lua_set_filter(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA SET_FILTER");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let name = state.check_string(2).to_string();
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            let value = info.lock().unwrap().request_filter(&name);
+            if !value {
+                warn!("[PLUGIN][LUA] Unknown filter {:?}", name);
+            }
+            state.push_integer(value as i64);
+
+            1
+        }
+
+        // permission(name): capability allowed by the player ("keyboard",
+        // "network", "filesystem"), asked with a prompt the first time
+        unsafe extern "C" fn lua_permission(lua_context: *mut lua_State) -> c_int {
+            debug!("LUA PERMISSION");
+
+            let mut state = State::from_ptr(lua_context);
+
+            let name = state.check_string(2).to_string();
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            let value = match Permission::from_name(&name) {
+                Some(permission) => info.lock().unwrap().permission(permission),
+                None => {
+                    warn!("[PLUGIN][LUA] Unknown permission {:?}", name);
+                    false
+                }
+            };
+            state.push_integer(value as i64);
+
+            1
+        }
+
         /***** CARTDATA *****/
-        unsafe extern "C" fn lua_cartdata(_lua_context: *mut lua_State) -> c_int {
+        // cartdata id: true when the data was already saved
+        unsafe extern "C" fn lua_cartdata(lua_context: *mut lua_State) -> c_int {
             debug!("LUA CARTDATA");
 
-            /*
             let mut state = State::from_ptr(lua_context);
-            let x = state.check_integer(2);
-            */
+
+            let id = state.check_string(2).to_string();
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            let result = info.lock().unwrap().cartdata.open(&id);
+            let value = match result {
+                Ok(value) => value,
+                Err(e) => {
+                    warn!("[PLUGIN][LUA] {}", e);
+                    false
+                }
+            };
+            state.push_integer(value as i64);
 
             1
         }
 
-        unsafe extern "C" fn lua_dget(_lua_context: *mut lua_State) -> c_int {
+        unsafe extern "C" fn lua_dget(lua_context: *mut lua_State) -> c_int {
             debug!("LUA DGET");
 
-            /*
             let mut state = State::from_ptr(lua_context);
-            let x = state.check_integer(2);
-            */
+
+            let index = state.check_integer(2);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            let value = info.lock().unwrap().cartdata.get(index as usize);
+            state.push_number(value);
 
             1
         }
 
 
-        unsafe extern "C" fn lua_dset(_lua_context: *mut lua_State) -> c_int {
+        unsafe extern "C" fn lua_dset(lua_context: *mut lua_State) -> c_int {
             debug!("LUA DSET");
 
-            /*
             let mut state = State::from_ptr(lua_context);
-            let x = state.check_integer(2);
-            */
 
-            1
+            let index = state.check_integer(2);
+            let value = state.check_number(3);
+
+            let info = state.with_extra(|extra| {
+                                            let data = extra
+                                                .as_ref()
+                                                .unwrap()
+                                                .downcast_ref::<ExtraData>()
+                                                .unwrap();
+                                            data.info.clone()
+                                        });
+
+            info.lock().unwrap().cartdata.set(index as usize, value);
+
+            0
         }
     }
 
-    pub const UNICORN_LUA_LIB: [(&'static str, Function); 41] =
+    pub const UNICORN_LUA_LIB: [(&'static str, Function); 142] =
         [("new", Some(UnicornLua::lua_new)),
 
          ("music", Some(UnicornLua::lua_music)),
+         ("music_pattern", Some(UnicornLua::lua_music_pattern)),
          ("sfx", Some(UnicornLua::lua_sfx)),
+         ("sfx_new", Some(UnicornLua::lua_sfx_new)),
 
          ("camera", Some(UnicornLua::lua_camera)),
+         ("camera_push", Some(UnicornLua::lua_camera_push)),
+         ("camera_pop", Some(UnicornLua::lua_camera_pop)),
          ("color", Some(UnicornLua::lua_color)),
 
          ("btn", Some(UnicornLua::lua_btn)),
          ("btnp", Some(UnicornLua::lua_btnp)),
+         ("mouse", Some(UnicornLua::lua_mouse)),
+         ("mouse_btn", Some(UnicornLua::lua_mouse_btn)),
+         ("touchpad", Some(UnicornLua::lua_touchpad)),
+         ("led", Some(UnicornLua::lua_led)),
+         ("keyboard_text", Some(UnicornLua::lua_keyboard_text)),
+         ("key_pressed", Some(UnicornLua::lua_key_pressed)),
 
          ("cls", Some(UnicornLua::lua_cls)),
+         ("fade", Some(UnicornLua::lua_fade)),
+         ("flash", Some(UnicornLua::lua_flash)),
+         ("shake", Some(UnicornLua::lua_shake)),
+         ("particles_spawn", Some(UnicornLua::lua_particles_spawn)),
+         ("particles_move", Some(UnicornLua::lua_particles_move)),
+         ("particles_stop", Some(UnicornLua::lua_particles_stop)),
+         ("particles_clear", Some(UnicornLua::lua_particles_clear)),
+         ("particles_count", Some(UnicornLua::lua_particles_count)),
+         ("particles_draw", Some(UnicornLua::lua_particles_draw)),
+
+         ("camera3d", Some(UnicornLua::lua_camera3d)),
+         ("perspective3d", Some(UnicornLua::lua_perspective3d)),
+         ("light3d", Some(UnicornLua::lua_light3d)),
+         ("mode3d", Some(UnicornLua::lua_mode3d)),
+         ("push3d", Some(UnicornLua::lua_push3d)),
+         ("pop3d", Some(UnicornLua::lua_pop3d)),
+         ("identity3d", Some(UnicornLua::lua_identity3d)),
+         ("translate3d", Some(UnicornLua::lua_translate3d)),
+         ("rotate3d", Some(UnicornLua::lua_rotate3d)),
+         ("scale3d", Some(UnicornLua::lua_scale3d)),
+         ("mesh3d", Some(UnicornLua::lua_mesh3d)),
+         ("mesh3d_del", Some(UnicornLua::lua_mesh3d_del)),
+         ("draw3d", Some(UnicornLua::lua_draw3d)),
+         ("tri3d", Some(UnicornLua::lua_tri3d)),
+         ("render3d", Some(UnicornLua::lua_render3d)),
 
          ("fget", Some(UnicornLua::lua_fget)),
+         ("fillp", Some(UnicornLua::lua_fillp)),
          ("fget_all", Some(UnicornLua::lua_fget_all)),
          ("fset", Some(UnicornLua::lua_fset)),
          ("fset_all", Some(UnicornLua::lua_fset_all)),
 
          ("line", Some(UnicornLua::lua_line)),
+         ("line_width", Some(UnicornLua::lua_line_width)),
+         ("bezier", Some(UnicornLua::lua_bezier)),
 
          ("rect", Some(UnicornLua::lua_rect)),
          ("rectfill", Some(UnicornLua::lua_rectfill)),
@@ -2153,18 +6513,40 @@ pub mod plugin {
          ("ellipse", Some(UnicornLua::lua_ellipse)),
          ("ellipsefill", Some(UnicornLua::lua_ellipsefill)),
          ("trigon", Some(UnicornLua::lua_trigon)),
+         ("trifill", Some(UnicornLua::lua_trifill)),
 
          ("clip", Some(UnicornLua::lua_clip)),
+         ("clip_push", Some(UnicornLua::lua_clip_push)),
+         ("clip_pop", Some(UnicornLua::lua_clip_pop)),
+         ("mode", Some(UnicornLua::lua_mode)),
 
          ("spr", Some(UnicornLua::lua_spr)),
+         ("spr_batch", Some(UnicornLua::lua_spr_batch)),
+         ("polyfill", Some(UnicornLua::lua_polyfill)),
+         ("spr_tile", Some(UnicornLua::lua_spr_tile)),
+         ("spr_9slice", Some(UnicornLua::lua_spr_9slice)),
          ("sspr", Some(UnicornLua::lua_sspr)),
+         ("sspr_rot", Some(UnicornLua::lua_sspr_rot)),
 
          ("mapdraw", Some(UnicornLua::lua_mapdraw)),
+         ("map_affine", Some(UnicornLua::lua_map_affine)),
+         ("map_scanline", Some(UnicornLua::lua_map_scanline)),
+         ("raycast", Some(UnicornLua::lua_raycast)),
+         ("collide_map", Some(UnicornLua::lua_collide_map)),
+         ("collide_rect", Some(UnicornLua::lua_collide_rect)),
+         ("sprite_overlap", Some(UnicornLua::lua_sprite_overlap)),
+         ("pathfind", Some(UnicornLua::lua_pathfind)),
          ("mget", Some(UnicornLua::lua_mget)),
          ("mset", Some(UnicornLua::lua_mset)),
+         ("peek", Some(UnicornLua::lua_peek)),
+         ("poke", Some(UnicornLua::lua_poke)),
+         ("memcpy", Some(UnicornLua::lua_memcpy)),
+         ("memset", Some(UnicornLua::lua_memset)),
 
          ("palt", Some(UnicornLua::lua_palt)),
+         ("pal_cycle", Some(UnicornLua::lua_pal_cycle)),
          ("pal", Some(UnicornLua::lua_pal)),
+         ("palette_color", Some(UnicornLua::lua_palette_color)),
 
          ("pget", Some(UnicornLua::lua_pget)),
          ("pset", Some(UnicornLua::lua_pset)),
@@ -2176,16 +6558,152 @@ pub mod plugin {
          ("noise_set_seed", Some(UnicornLua::lua_noise_set_seed)),
 
          ("rnd", Some(UnicornLua::lua_rnd)),
+         ("srand", Some(UnicornLua::lua_srand)),
+         ("deprecated", Some(UnicornLua::lua_deprecated)),
+         ("fx", Some(UnicornLua::lua_fx)),
+         ("fx_num", Some(UnicornLua::lua_fx_num)),
+         ("fx_mul", Some(UnicornLua::lua_fx_mul)),
+         ("fx_div", Some(UnicornLua::lua_fx_div)),
+         ("fx_sqrt", Some(UnicornLua::lua_fx_sqrt)),
+         ("fx_sin", Some(UnicornLua::lua_fx_sin)),
+         ("fx_cos", Some(UnicornLua::lua_fx_cos)),
 
          ("print", Some(UnicornLua::lua_print)),
+         ("print_font", Some(UnicornLua::lua_print_font)),
+         ("font", Some(UnicornLua::lua_font)),
+         ("font_load", Some(UnicornLua::lua_font_load)),
+         ("font_data", Some(UnicornLua::lua_font_data)),
 
          ("time", Some(UnicornLua::lua_time)),
+         ("trace_begin", Some(UnicornLua::lua_trace_begin)),
+         ("trace_end", Some(UnicornLua::lua_trace_end)),
+
+         ("timer_new", Some(UnicornLua::lua_timer_new)),
+         ("tween_new", Some(UnicornLua::lua_tween_new)),
+         ("timer_cancel", Some(UnicornLua::lua_timer_cancel)),
+         ("timer_scale", Some(UnicornLua::lua_timer_scale)),
+         ("timer_next", Some(UnicornLua::lua_timer_next)),
+         ("tween_next", Some(UnicornLua::lua_tween_next)),
+
+         ("menuitem", Some(UnicornLua::lua_menuitem)),
+         ("menuitem_next", Some(UnicornLua::lua_menuitem_next)),
+
+         ("ent_new", Some(UnicornLua::lua_ent_new)),
+         ("ent_del", Some(UnicornLua::lua_ent_del)),
+         ("ent_tag", Some(UnicornLua::lua_ent_tag)),
+         ("ent_has", Some(UnicornLua::lua_ent_has)),
+         ("ent_layer", Some(UnicornLua::lua_ent_layer)),
+         ("ent_count", Some(UnicornLua::lua_ent_count)),
+         ("ent_ids", Some(UnicornLua::lua_ent_ids)),
+
+         ("body_new", Some(UnicornLua::lua_body_new)),
+         ("body_move", Some(UnicornLua::lua_body_move)),
+         ("body_get", Some(UnicornLua::lua_body_get)),
+         ("body_set", Some(UnicornLua::lua_body_set)),
+         ("body_gravity", Some(UnicornLua::lua_body_gravity)),
+         ("body_del", Some(UnicornLua::lua_body_del)),
+
+         ("score_sign", Some(UnicornLua::lua_score_sign)),
+         ("score_verify", Some(UnicornLua::lua_score_verify)),
+
+         ("strf", Some(UnicornLua::lua_strf)),
 
          ("stat", Some(UnicornLua::lua_stat)),
+         ("extcmd", Some(UnicornLua::lua_extcmd)),
+         ("set_filter", Some(UnicornLua::lua_set_filter)),
+         ("permission", Some(UnicornLua::lua_permission)),
 
          ("cartdata", Some(UnicornLua::lua_cartdata)),
          ("dget", Some(UnicornLua::lua_dget)),
          ("dset", Some(UnicornLua::lua_dset))];
+
+    #[cfg(test)]
+    mod tests {
+        use std::sync::{mpsc, Arc, Mutex};
+
+        use config::Players;
+        use gfx::Screen;
+        use sound::sound::Sound;
+        use unicorn::info::Info;
+        use unicorn::noise::Noise;
+
+        use super::LuaPlugin;
+
+        fn loaded() -> LuaPlugin {
+            let (csend, _) = mpsc::channel();
+
+            let mut plugin = LuaPlugin::new();
+            plugin.load(Arc::new(Mutex::new(Players::new())),
+                        Arc::new(Mutex::new(Info::new())),
+                        Arc::new(Mutex::new(Screen::new(128, 128))),
+                        Arc::new(Mutex::new(Noise::new())),
+                        Arc::new(Mutex::new(Sound::new(csend))));
+            plugin
+        }
+
+        fn sandboxed(max_instructions: i32, max_memory_kb: i32) -> LuaPlugin {
+            let mut plugin = loaded();
+            plugin.sandbox(max_instructions, max_memory_kb);
+            assert!(plugin.load_code("x = 1".to_string()));
+            plugin
+        }
+
+        fn assert_limit(result: Result<String, String>, message: &str) {
+            match result {
+                Err(e) => assert!(e.contains(message), "{:?}", e),
+                Ok(value) => panic!("No limit reached, {:?}", value),
+            }
+        }
+
+        #[test]
+        fn test_sandbox_limits() {
+            let mut plugin = sandboxed(1_000_000, 16 * 1024);
+            assert_eq!(plugin.execute("x + 1"), Ok("2".to_string()));
+
+            // The hooks are per thread, the coroutines have theirs
+            assert_limit(plugin.execute("coroutine.wrap(function() while true do end end)()"),
+                         "[SANDBOX] CPU limit reached");
+            assert_limit(plugin.execute("coroutine.resume(coroutine.create(function() while true do end end))"),
+                         "[SANDBOX] CPU limit reached");
+            assert!(plugin.execute("co_start(function() while true do end end)").is_ok());
+            assert_limit(plugin.execute("_unicorn_coroutines_update()"), "[SANDBOX] CPU limit reached");
+
+            // The limit can't be caught
+            assert_limit(plugin.execute("while true do pcall(function() while true do end end) end"),
+                         "[SANDBOX] CPU limit reached");
+            assert_limit(plugin.execute("while true do xpcall(function() while true do end end, print) end"),
+                         "[SANDBOX] CPU limit reached");
+
+            // Allocated by a C function, without instructions
+            assert_limit(plugin.execute("pcall(string.rep, 'x', 2^30)"), "[SANDBOX] Memory limit reached");
+
+            // The next call runs again, the errors of the cartridge are caught
+            assert_eq!(plugin.execute("pcall(error, 'x')"), Ok("false, x".to_string()));
+            assert_eq!(plugin.execute("#string.rep('x', 1024)"), Ok("1024".to_string()));
+        }
+
+        #[test]
+        fn test_memory_range() {
+            let mut plugin = loaded();
+            assert!(plugin.load_code("x = 1".to_string()));
+            assert!(plugin.execute("poke(0x20000, 7)").is_ok());
+
+            // Outside of the memory, not wrapped around onto the screen
+            for addr in &["-1", "2^32 + 0x20000"] {
+                assert_eq!(plugin.execute(&format!("peek({})", addr)), Ok("0".to_string()));
+                assert!(plugin.execute(&format!("poke({}, 9)", addr)).is_ok());
+                assert!(plugin.execute(&format!("memset({}, 9, 16)", addr)).is_ok());
+                assert!(plugin.execute(&format!("memcpy({}, 0x38000, 16)", addr)).is_ok());
+                assert!(plugin.execute(&format!("memcpy(0x38000, {}, 16)", addr)).is_ok());
+            }
+            assert!(plugin.execute("memset(0x20000, 9, -1)").is_ok());
+            assert_eq!(plugin.execute("peek(0x20000)"), Ok("7".to_string()));
+            assert_eq!(plugin.execute("peek(0x38000)"), Ok("0".to_string()));
+
+            assert!(plugin.execute("memcpy(0x38000, 0x20000, 2^32 + 1)").is_ok());
+            assert_eq!(plugin.execute("peek(0x38000)"), Ok("7".to_string()));
+        }
+    }
 }
 
 #[cfg(not(feature = "unicorn_plugin_lua"))]
@@ -2196,6 +6714,7 @@ pub mod plugin {
 
     use unicorn::noise::Noise;
     use unicorn::info::Info;
+    use unicorn::script_cache::ScriptCache;
     use sound::sound::Sound;
 
     use gfx::Screen;
@@ -2217,9 +6736,38 @@ pub mod plugin {
                     _sound: Arc<Mutex<Sound>>) {
             error!("LUA plugin disabled");
         }
+        pub fn preload(&mut self, _name: &str, _code: &str) -> bool {
+            false
+        }
+        pub fn sandbox(&mut self, _max_instructions: i32, _max_memory_kb: i32) {}
+        pub fn trace_calls(&mut self, _names: &[String]) -> bool {
+            false
+        }
+        pub fn call_count(&mut self, _name: &str) -> u32 {
+            0
+        }
+        pub fn reset_calls(&mut self) {}
+        pub fn eval(&mut self, _expression: &str) -> Result<String, String> {
+            Err("Lua plugin disabled".to_string())
+        }
+        pub fn inspect(&mut self, _path: &[String]) -> Result<String, String> {
+            Err("Lua plugin disabled".to_string())
+        }
+        pub fn execute(&mut self, _code: &str) -> Result<String, String> {
+            Err("Lua plugin disabled".to_string())
+        }
+        pub fn watch_nondeterminism(&mut self) -> bool {
+            false
+        }
+        pub fn nondeterminism_calls(&mut self) -> Vec<(String, u32)> {
+            Vec::new()
+        }
         pub fn load_code(&mut self, _data: String) -> bool {
             false
         }
+        pub fn load_code_cached(&mut self, _data: String, _cache: &ScriptCache) -> bool {
+            false
+        }
         pub fn init(&mut self) {}
         pub fn draw(&mut self) -> bool {
             false
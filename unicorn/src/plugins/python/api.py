@@ -1,12 +1,21 @@
 import math
-import random
 from math import frexp, copysign
 from sys import float_info
+import sys
 
 # Audio
 
 
-def music(id=-1, filename="", loops=0, start_position=0, channel=-1):
+def music(id=-1, filename="", loops=None, start_position=0, channel=-1):
+    # music(n, [fade_ms, [channel_mask]]) plays the patterns of the cartridge
+    if not isinstance(filename, str) or filename == "":
+        fade_ms = filename if filename else 0
+        channel_mask = 15 if loops is None else loops
+        unicorn_audio.chiptune_music_pattern(id, fade_ms, channel_mask)
+        return
+
+    if loops is None:
+        loops = 0
     unicorn_audio.chiptune_music(id, filename, channel, loops, start_position)
 
 
@@ -16,6 +25,12 @@ def sfx(id=-1, filename="", note=13312, panning=64,
                                rate, loops)
 
 
+def sfx_new(name, waveform="square", attack=0, decay=8, sustain=16,
+            release=4):
+    unicorn_audio.chiptune_new_sfx(name, waveform, attack, decay, sustain,
+                                   release)
+
+
 def music_stop():
     unicorn_audio.chiptune_stop()
 
@@ -38,6 +53,7 @@ def music_position():
 
 globals()["music"] = music
 globals()["sfx"] = sfx
+globals()["sfx_new"] = sfx_new
 globals()["music_stop"] = music_stop
 globals()["music_pause"] = music_pause
 globals()["music_resume"] = music_resume
@@ -413,10 +429,20 @@ globals()["world_draw_debug"] = world_draw_debug
 # Graphics
 
 
-def camera(x=-1, y=-1):
+def camera(x=0, y=0):
     unicorn_graphic.camera(flr(x), flr(y))
 
 
+def camera_push(x=None, y=None):
+    # Without coordinates, the layer is drawn in the coordinates of the screen
+    absolute = x is None and y is None
+    return unicorn_graphic.camera_push(flr(x or 0), flr(y or 0), absolute)
+
+
+def camera_pop():
+    return unicorn_graphic.camera_pop()
+
+
 def circ(x, y, r, color=-1):
     unicorn_graphic.circ(math.floor(x), math.floor(y),
                          math.floor(r), math.floor(color))
@@ -432,10 +458,31 @@ def clip(x=-1, y=-1, w=-1, h=-1):
                          math.floor(w), math.floor(h))
 
 
+def clip_push(x, y, w, h):
+    return unicorn_graphic.clip_push(math.floor(x), math.floor(y),
+                                     math.floor(w), math.floor(h))
+
+
+def clip_pop():
+    return unicorn_graphic.clip_pop()
+
+
 def cls(value=-1):
     unicorn_graphic.cls(value)
 
 
+def fade(t, col=0):
+    unicorn_sys.fade(t, math.floor(col))
+
+
+def flash(col, frames=2):
+    unicorn_sys.flash(math.floor(col), math.floor(frames))
+
+
+def shake(intensity, frames=15):
+    unicorn_sys.shake(intensity, math.floor(frames))
+
+
 def color(col):
     unicorn_graphic.color(col)
 
@@ -454,17 +501,35 @@ def ellipsefill(x, y, rx, ry, color=-1):
 
 def fget(idx_sprite, flag=-1):
     if flag == -1:
-        unicorn_graphic.fget_all(idx_sprite)
+        return unicorn_graphic.fget_all(idx_sprite)
     return unicorn_graphic.fget(idx_sprite, flag)
 
 
+def fillp(pattern=0, col2=-1):
+    unicorn_graphic.fillp(math.floor(pattern) & 0xffff, math.floor(col2))
+
+
 def font(name="pico8"):
     unicorn_graphic.font(name)
 
 
+def font_load(name, sx, sy, w, h, first=" ", count=96, widths=[]):
+    if isinstance(first, str):
+        first = ord(first)
+    return unicorn_graphic.font_load(name, math.floor(sx), math.floor(sy),
+                                     math.floor(w), math.floor(h),
+                                     first, count,
+                                     [math.floor(v) for v in widths])
+
+
+def font_data(name, height, data):
+    return unicorn_graphic.font_data(name, math.floor(height),
+                                     [math.floor(v) for v in data])
+
+
 def fset(idx_sprite, flag, value=-1):
     if value == -1:
-        unicorn_graphic.fset_all(flag)
+        unicorn_graphic.fset_all(idx_sprite, flag)
     else:
         unicorn_graphic.fset(idx_sprite, flag, value)
 
@@ -475,14 +540,65 @@ def line(x1, y1, x2, y2, color=-1):
                          math.floor(color))
 
 
+def line_width(w=1):
+    return unicorn_graphic.line_width(math.floor(w))
+
+
+def bezier(x0, y0, cx, cy, x1, y1, color=-1):
+    unicorn_graphic.bezier(math.floor(x0), math.floor(y0),
+                           math.floor(cx), math.floor(cy),
+                           math.floor(x1), math.floor(y1),
+                           math.floor(color))
+
+
+def mode(width=-1, height=-1):
+    if width == -1:
+        return (unicorn_graphic.mode_get_width(), unicorn_graphic.mode_get_height())
+    return unicorn_graphic.mode(math.floor(width), math.floor(height))
+
+
 def pal(c0=-1, c1=-1, p=0):
-    unicorn_graphic.pal(math.floor(c0), math.floor(c1))
+    unicorn_graphic.pal(math.floor(c0), math.floor(c1), math.floor(p))
 
 
 def palt(c=-1, t=False):
     unicorn_graphic.palt(math.floor(c), t)
 
 
+def pal_cycle(colors=None, speed=1):
+    if colors is None:
+        unicorn_graphic.pal_cycle(-1, -1, 0.0)
+    else:
+        unicorn_graphic.pal_cycle(math.floor(colors[0]), math.floor(colors[1]), float(speed))
+
+
+# particles_spawn({"x": 64, "y": 64, "count": 50, "colors": [7, 10, 9, 8]}) or
+# with keywords, the missing fields keep their default value
+def particles_spawn(d=None, **kwargs):
+    d = dict(d or {}, **kwargs)
+    return unicorn_graphic.particles_spawn(d, list(d.get("colors", [])))
+
+
+def particles_move(id, x, y):
+    unicorn_graphic.particles_move(id, x, y)
+
+
+def particles_stop(id):
+    unicorn_graphic.particles_stop(id)
+
+
+def particles_clear():
+    unicorn_graphic.particles_clear()
+
+
+def particles_count():
+    return unicorn_graphic.particles_count()
+
+
+def particles_draw():
+    unicorn_graphic.particles_draw()
+
+
 def pget(x, y):
     return unicorn_graphic.pget(math.floor(x), math.floor(y))
 
@@ -491,7 +607,9 @@ def pset(x, y, color):
     unicorn_graphic.pset(math.floor(x), math.floor(y), math.floor(color))
 
 
-def unicorn_print(str, x=-1, y=-1, col=-1):
+def unicorn_print(str, x=-1, y=-1, col=-1, font=None):
+    if font is not None:
+        return unicorn_graphic.print_font(str, x, y, col, font)
     return unicorn_graphic.print(str, x, y, col)
 
 
@@ -521,6 +639,16 @@ def spr(n, x, y, w=-1, h=-1, flip_x=False, flip_y=False, angle=0, zoom=1, dynami
                         angle, zoom, dynamic)
 
 
+def spr_tile(n, x, y, w, h):
+    unicorn_graphic.spr_tile(math.floor(n), math.floor(x), math.floor(y),
+                             math.floor(w), math.floor(h))
+
+
+def spr_9slice(n, x, y, w, h):
+    unicorn_graphic.spr_9slice(math.floor(n), math.floor(x), math.floor(y),
+                               math.floor(w), math.floor(h))
+
+
 def sset(x, y, c=-1):
     unicorn_graphic.sset(x, y, c)
 
@@ -535,6 +663,21 @@ def sspr(sx, sy, sw, sh, dx, dy, dw=-1, dh=-1, flip_x=False, flip_y=False):
     unicorn_graphic.sspr(sx, sy, sw, sh, dx, dy, dw, dh, flip_x, flip_y)
 
 
+def sspr_rot(sx, sy, sw, sh, dx, dy, dw=-1, dh=-1, angle=0.0,
+             flip_x=False, flip_y=False):
+    if dw == -1:
+        dw = sw
+
+    if dh == -1:
+        dh = sh
+
+    unicorn_graphic.sspr_rot(math.floor(sx), math.floor(sy),
+                             math.floor(sw), math.floor(sh),
+                             math.floor(dx), math.floor(dy),
+                             math.floor(dw), math.floor(dh),
+                             float(angle), flip_x, flip_y)
+
+
 def sspr_rotazoom(idx_sprite, sx, sy, sw, sh, dx, dy,
                   angle=0.0, zoom=1.0,
                   flip_x=False, flip_y=False):
@@ -553,36 +696,148 @@ def trigon(x1, y1, x2, y2, x3, y3, color):
                            color)
 
 
+def trifill(x1, y1, x2, y2, x3, y3, color=-1):
+    unicorn_graphic.trifill(math.floor(x1), math.floor(y1),
+                            math.floor(x2), math.floor(y2),
+                            math.floor(x3), math.floor(y3),
+                            color)
+
+
 def polygon(x, y, color):
     unicorn_graphic.polygon(x, y, color)
 
 
+def polyfill(points, color=-1):
+    unicorn_graphic.polyfill([math.floor(v) for v in points], color)
+
+
+def camera3d(ex, ey, ez, tx=0, ty=0, tz=0):
+    unicorn_graphic.camera3d(ex, ey, ez, tx, ty, tz)
+
+
+def perspective3d(fov, near=0.1):
+    unicorn_graphic.perspective3d(fov, near)
+
+
+def light3d(x, y, z, ambient=0.3):
+    unicorn_graphic.light3d(x, y, z, ambient)
+
+
+def mode3d(mode, cull=True):
+    unicorn_graphic.mode3d(mode, cull)
+
+
+def push3d():
+    unicorn_graphic.push3d()
+
+
+def pop3d():
+    unicorn_graphic.pop3d()
+
+
+def identity3d():
+    unicorn_graphic.identity3d()
+
+
+def translate3d(x=0, y=0, z=0):
+    unicorn_graphic.translate3d(x, y, z)
+
+
+def rotate3d(x=0, y=0, z=0):
+    unicorn_graphic.rotate3d(x, y, z)
+
+
+def scale3d(x, y=None, z=None):
+    unicorn_graphic.scale3d(x, x if y is None else y, x if z is None else z)
+
+
+# The faces are [a, b, c, color, ...], the vertices from 0
+def mesh3d(vertices, faces):
+    return unicorn_graphic.mesh3d(list(vertices), [math.floor(v) for v in faces])
+
+
+def mesh3d_del(id):
+    unicorn_graphic.mesh3d_del(id)
+
+
+def draw3d(id):
+    unicorn_graphic.draw3d(id)
+
+
+def tri3d(x0, y0, z0, x1, y1, z1, x2, y2, z2, color=-1):
+    unicorn_graphic.tri3d(x0, y0, z0, x1, y1, z1, x2, y2, z2, math.floor(color))
+
+
+def render3d():
+    return unicorn_graphic.render3d()
+
+
 globals()["camera"] = camera
+globals()["camera_push"] = camera_push
+globals()["camera_pop"] = camera_pop
 globals()["circ"] = circ
 globals()["circfill"] = circfill
 globals()["clip"] = clip
+globals()["clip_push"] = clip_push
+globals()["clip_pop"] = clip_pop
 globals()["cls"] = cls
+globals()["fade"] = fade
+globals()["flash"] = flash
+globals()["shake"] = shake
 globals()["color"] = color
 globals()["ellipse"] = ellipse
 globals()["ellipsefill"] = ellipsefill
 globals()["fget"] = fget
+globals()["fillp"] = fillp
 globals()["fset"] = fset
 globals()["line"] = line
+globals()["line_width"] = line_width
+globals()["bezier"] = bezier
+globals()["mode"] = mode
 globals()["pal"] = pal
 globals()["palt"] = palt
+globals()["pal_cycle"] = pal_cycle
+globals()["particles_spawn"] = particles_spawn
+globals()["particles_move"] = particles_move
+globals()["particles_stop"] = particles_stop
+globals()["particles_clear"] = particles_clear
+globals()["particles_count"] = particles_count
+globals()["particles_draw"] = particles_draw
 globals()["pset"] = pset
 globals()["pget"] = pget
 globals()["unicorn_print"] = unicorn_print
+globals()["font_load"] = font_load
+globals()["font_data"] = font_data
 globals()["rect"] = rect
 globals()["rectfill"] = rectfill
 globals()["sget"] = sget
 globals()["spr"] = spr
+globals()["spr_tile"] = spr_tile
+globals()["spr_9slice"] = spr_9slice
 globals()["spr_reg"] = spr_reg
 globals()["sset"] = sset
 globals()["sspr"] = sspr
+globals()["sspr_rot"] = sspr_rot
 globals()["sspr_rotazoom"] = sspr_rotazoom
 globals()["trigon"] = trigon
+globals()["trifill"] = trifill
 globals()["polygon"] = polygon
+globals()["polyfill"] = polyfill
+globals()["camera3d"] = camera3d
+globals()["perspective3d"] = perspective3d
+globals()["light3d"] = light3d
+globals()["mode3d"] = mode3d
+globals()["push3d"] = push3d
+globals()["pop3d"] = pop3d
+globals()["identity3d"] = identity3d
+globals()["translate3d"] = translate3d
+globals()["rotate3d"] = rotate3d
+globals()["scale3d"] = scale3d
+globals()["mesh3d"] = mesh3d
+globals()["mesh3d_del"] = mesh3d_del
+globals()["draw3d"] = draw3d
+globals()["tri3d"] = tri3d
+globals()["render3d"] = render3d
 
 # Input
 
@@ -615,6 +870,33 @@ def mouse_statep():
     return unicorn_input.btn_mouse_statep()
 
 
+def mouse_btn(b=0):
+    return unicorn_input.btn_mouse_btn(math.floor(b))
+
+
+def mouse_wheel():
+    return unicorn_input.btn_mouse_wheel()
+
+
+# None when the touchpad isn't touched
+def touchpad(p=0):
+    width, height = mode()
+    return unicorn_input.touchpad(math.floor(p), width, height)
+
+
+def led(p, r, g, b):
+    r, g, b = (max(0, min(255, math.floor(c))) for c in (r, g, b))
+    unicorn_input.led(math.floor(p), r, g, b)
+
+
+def keyboard_text():
+    return unicorn_input.keyboard_text()
+
+
+def key_pressed(name):
+    return unicorn_input.key_pressed(name)
+
+
 globals()["btn"] = btn
 globals()["btnp"] = btnp
 
@@ -622,6 +904,12 @@ globals()["mouse_x"] = mouse_x
 globals()["mouse_y"] = mouse_y
 globals()["mouse_state"] = mouse_state
 globals()["mouse_statep"] = mouse_statep
+globals()["mouse_btn"] = mouse_btn
+globals()["mouse_wheel"] = mouse_wheel
+globals()["touchpad"] = touchpad
+globals()["led"] = led
+globals()["keyboard_text"] = keyboard_text
+globals()["key_pressed"] = key_pressed
 
 # Map
 
@@ -630,6 +918,42 @@ def mapdraw(cel_x, cel_y, sx, sy, cel_w, cel_h, layer=0):
     unicorn_map.mapdraw(cel_x, cel_y, sx, sy, cel_w, cel_h, layer)
 
 
+def map_affine(a, b, c, d, tx, ty, layer=0):
+    unicorn_map.map_affine(a, b, c, d, tx, ty, layer)
+
+
+def map_scanline(y, u, v, du, dv, layer=0):
+    unicorn_map.map_scanline(math.floor(y), u, v, du, dv, layer)
+
+
+def raycast(x, y, angle, fov=1.0 / 6, layer=0, max_distance=32):
+    return unicorn_map.raycast(x, y, angle, fov, math.floor(layer), max_distance)
+
+
+def collide_map(x, y, w, h, flag=0):
+    return unicorn_map.collide_map(x, y, w, h, math.floor(flag))
+
+
+def collide_rect(x1, y1, w1, h1, x2, y2, w2, h2):
+    return unicorn_map.collide_rect(x1, y1, w1, h1, x2, y2, w2, h2)
+
+
+def sprite_overlap(n1, x1, y1, n2, x2, y2, w1=1, h1=1, w2=-1, h2=-1):
+    if w2 == -1:
+        w2 = w1
+    if h2 == -1:
+        h2 = h1
+    return unicorn_graphic.sprite_overlap(math.floor(n1), math.floor(x1), math.floor(y1),
+                                          math.floor(w1), math.floor(h1),
+                                          math.floor(n2), math.floor(x2), math.floor(y2),
+                                          math.floor(w2), math.floor(h2))
+
+
+def pathfind(x0, y0, x1, y1, flag_mask=1):
+    return unicorn_map.pathfind(math.floor(x0), math.floor(y0), math.floor(x1), math.floor(y1),
+                                math.floor(flag_mask))
+
+
 def mget(x, y):
     return unicorn_map.mget(math.floor(x), math.floor(y))
 
@@ -638,9 +962,50 @@ def mset(x, y, v):
     unicorn_map.mset(math.floor(x), math.floor(y), math.floor(v))
 
 
+
+# Platformer bodies, moved against the flags of the sprites of the map
+
+
+def body_new(x, y, w, h):
+    return unicorn_map.body_new(x, y, w, h)
+
+
+def body_move(id):
+    unicorn_map.body_move(id)
+
+
+def body_get(id):
+    return unicorn_map.body_get(id)
+
+
+def body_set(id, x=None, y=None, vx=None, vy=None):
+    unicorn_map.body_set(id, x, y, vx, vy)
+
+
+def body_gravity(id, gravity, max_fall=None):
+    unicorn_map.body_gravity(id, gravity, max_fall)
+
+
+def body_del(id):
+    unicorn_map.body_del(id)
+
+
 globals()["mapdraw"] = mapdraw
+globals()["map_affine"] = map_affine
+globals()["map_scanline"] = map_scanline
+globals()["raycast"] = raycast
+globals()["collide_map"] = collide_map
+globals()["collide_rect"] = collide_rect
+globals()["sprite_overlap"] = sprite_overlap
+globals()["pathfind"] = pathfind
 globals()["mget"] = mget
 globals()["mset"] = mset
+globals()["body_new"] = body_new
+globals()["body_move"] = body_move
+globals()["body_get"] = body_get
+globals()["body_set"] = body_set
+globals()["body_gravity"] = body_gravity
+globals()["body_del"] = body_del
 
 # Math
 
@@ -662,12 +1027,45 @@ def flr(x):
     return math.floor(x)
 
 
-def rnd(x):
-    return random.random() * x
+def rnd(x=1):
+    return unicorn_sys.rnd(x)
+
+
+def srand(x=0):
+    return unicorn_sys.srand(math.floor(x))
+
+
+def _fx_raw(a):
+    # The additions of the scripts don't wrap around like the ones of Rust
+    return ((int(a) + 0x80000000) & 0xffffffff) - 0x80000000
+
+
+def fx(x=0):
+    return unicorn_sys.fx(x)
+
+
+def fx_num(a):
+    return unicorn_sys.fx_num(_fx_raw(a))
+
+
+def fx_mul(a, b):
+    return unicorn_sys.fx_mul(_fx_raw(a), _fx_raw(b))
+
+
+def fx_div(a, b):
+    return unicorn_sys.fx_div(_fx_raw(a), _fx_raw(b))
+
+
+def fx_sqrt(a):
+    return unicorn_sys.fx_sqrt(_fx_raw(a))
+
+
+def fx_sin(a):
+    return unicorn_sys.fx_sin(_fx_raw(a))
 
 
-def srand(x):
-    return random.seed(x)
+def fx_cos(a):
+    return unicorn_sys.fx_cos(_fx_raw(a))
 
 
 def mid(x,y,z):
@@ -687,6 +1085,14 @@ globals()["cos"] = cos
 globals()["sin"] = sin
 globals()["flr"] = flr
 globals()["rnd"] = rnd
+globals()["srand"] = srand
+globals()["fx"] = fx
+globals()["fx_num"] = fx_num
+globals()["fx_mul"] = fx_mul
+globals()["fx_div"] = fx_div
+globals()["fx_sqrt"] = fx_sqrt
+globals()["fx_sin"] = fx_sin
+globals()["fx_cos"] = fx_cos
 globals()["sqrt"] = math.sqrt
 globals()["mid"] = mid
 globals()["bxor"] = bxor
@@ -694,11 +1100,37 @@ globals()["bxor"] = bxor
 # Memory
 
 
+# The negative addresses are outside of the memory
+def peek(addr):
+    addr = math.floor(addr)
+    if addr < 0:
+        return 0
+    return unicorn_mem.peek(addr)
+
+
+def poke(addr, val):
+    addr = math.floor(addr)
+    if addr >= 0:
+        unicorn_mem.poke(addr, math.floor(val) & 0xff)
+
+
 def memcpy(dest_addr, source_addr, len_buff):
-    unicorn_mem.memcpy(dest_addr, source_addr, len_buff)
+    dest_addr, source_addr, len_buff = (math.floor(dest_addr), math.floor(source_addr),
+                                        math.floor(len_buff))
+    if dest_addr >= 0 and source_addr >= 0 and len_buff > 0:
+        unicorn_mem.memcpy(dest_addr, source_addr, len_buff)
 
 
+def memset(dest_addr, val, len_buff):
+    dest_addr, len_buff = math.floor(dest_addr), math.floor(len_buff)
+    if dest_addr >= 0 and len_buff > 0:
+        unicorn_mem.memset(dest_addr, math.floor(val) & 0xff, len_buff)
+
+
+globals()["peek"] = peek
+globals()["poke"] = poke
 globals()["memcpy"] = memcpy
+globals()["memset"] = memset
 
 
 # Palette
@@ -722,23 +1154,41 @@ def palette_switch(name):
     unicorn_palette.switch(name)
 
 
+def set_palette(colors):
+    if isinstance(colors, dict):
+        items = colors.items()
+    else:
+        items = enumerate(colors)
+
+    for col, value in items:
+        if isinstance(value, int):
+            r = (value & 0xFF0000) >> 16
+            g = (value & 0x00FF00) >> 8
+            b = (value & 0x0000FF)
+        else:
+            r, g, b = value
+        unicorn_palette.set_color(math.floor(col), r, g, b)
+
+
 globals()["palette"] = palette
 globals()["palette_hexa"] = palette_hexa
 globals()["palette_reset"] = palette_reset
 globals()["palette_switch"] = palette_switch
+globals()["set_palette"] = set_palette
 
 
 # Noise
-def noise(x, y, z):
+def noise(x, y=0, z=0):
     return unicorn_noise.noise(x, y, z)
 
 
-def noise_set_seed(seed):
-    return unicorn_noise.noise_set_seed(seed)
+def noise_set_seed(seed=0):
+    return unicorn_noise.noise_set_seed(math.floor(seed))
 
 
 globals()["noise"] = noise
 globals()["noise_set_seed"] = noise_set_seed
+globals()["noise_seed"] = noise_set_seed
 
 # System
 
@@ -751,10 +1201,388 @@ def unicorn_time_sec():
     return unicorn_sys.time_sec()
 
 
+def unicorn_real_time_sec():
+    return unicorn_sys.real_time_sec()
+
+
+def unicorn_over_budget():
+    return unicorn_sys.over_budget()
+
+
+def trace_begin(name):
+    unicorn_sys.trace_begin(name)
+
+
+def trace_end():
+    unicorn_sys.trace_end()
+
+
+def unicorn_reduce_motion():
+    return unicorn_sys.reduce_motion()
+
+
+def stat(name):
+    if name == "last_capture":
+        return unicorn_sys.last_capture()
+    if name == "captures":
+        return unicorn_sys.captures()
+    if name == "real_time":
+        return unicorn_sys.real_time_sec()
+    if name == "over_budget":
+        return unicorn_sys.over_budget()
+    if name == "frame_time":
+        return unicorn_sys.frame_time()
+    if name == "reduce_motion":
+        return unicorn_sys.reduce_motion()
+    if isinstance(name, str):
+        return None
+    if name == 32:
+        return mouse_x()
+    if name == 33:
+        return mouse_y()
+    if name == 34:
+        return mouse_state()
+    return 0
+
+
+def extcmd(cmd, arg=None):
+    if isinstance(arg, bool):
+        arg = int(arg)
+    unicorn_sys.extcmd(cmd, None if arg is None else str(arg))
+
+
 def show_mouse(value=True):
     unicorn_sys.show_mouse(value)
 
 
+# The deprecated functions warn about their first call, with the line of
+# the caller
+def _unicorn_deprecated(name, function):
+    def deprecated(*args, **kwargs):
+        unicorn_sys.deprecated(name, sys._getframe(1).f_lineno)
+        return function(*args, **kwargs)
+    return deprecated
+
+
+for _name in unicorn_sys.deprecations():
+    if _name in globals():
+        globals()[_name] = _unicorn_deprecated(_name, globals()[_name])
+
+
+# The callbacks of the timers and the fields of the tweens stay here,
+# _unicorn_timers_update is called before _update
+_unicorn_timers = {}
+_unicorn_tweens = {}
+
+
+def after(seconds, fn):
+    id = unicorn_sys.timer_new(seconds, False)
+    _unicorn_timers[id] = fn
+    return id
+
+
+def every(seconds, fn):
+    id = unicorn_sys.timer_new(seconds, True)
+    _unicorn_timers[id] = fn
+    return id
+
+
+def _tween_get(obj, field):
+    if isinstance(obj, (dict, list)):
+        return obj[field]
+    return getattr(obj, field)
+
+
+def _tween_set(obj, field, value):
+    if isinstance(obj, (dict, list)):
+        obj[field] = value
+    else:
+        setattr(obj, field, value)
+
+
+def tween(obj, field, target, duration, easing="linear"):
+    id = unicorn_sys.tween_new(_tween_get(obj, field), target, duration,
+                               easing)
+    _unicorn_tweens[id] = (obj, field)
+    return id
+
+
+def timer_cancel(id):
+    unicorn_sys.timer_cancel(id)
+    _unicorn_timers.pop(id, None)
+    _unicorn_tweens.pop(id, None)
+
+
+def timer_scale(scale):
+    unicorn_sys.timer_scale(scale)
+
+
+def _unicorn_timers_update():
+    value = unicorn_sys.tween_next()
+    while value is not None:
+        id, current, done = value
+        target = _unicorn_tweens.get(id)
+        if target is not None:
+            _tween_set(target[0], target[1], current)
+            if done:
+                del _unicorn_tweens[id]
+        value = unicorn_sys.tween_next()
+
+    value = unicorn_sys.timer_next()
+    while value is not None:
+        id, done = value
+        fn = _unicorn_timers.get(id)
+        if done:
+            _unicorn_timers.pop(id, None)
+        if fn is not None:
+            fn()
+        value = unicorn_sys.timer_next()
+
+
+# The callbacks of the entries of the pause menu stay here,
+# _unicorn_menuitems_update is called before _update
+_unicorn_menuitems = {}
+
+
+def menuitem(index, label=None, fn=None):
+    index = math.floor(index)
+    if unicorn_sys.menuitem(index, label):
+        if label is None:
+            _unicorn_menuitems.pop(index, None)
+        else:
+            _unicorn_menuitems[index] = fn
+
+
+def _unicorn_menuitems_update():
+    index = unicorn_sys.menuitem_next()
+    if index is not None:
+        fn = _unicorn_menuitems.get(index)
+        if fn is not None:
+            fn()
+
+
+# Entities, the objects stay here by id
+
+
+class Entity(object):
+    pass
+
+
+_unicorn_entities = {}
+
+
+def ent_new(tags="", layer=0, e=None):
+    if e is None:
+        e = Entity()
+    e.id = unicorn_sys.ent_new(tags, math.floor(layer))
+    _unicorn_entities[e.id] = e
+    return e
+
+
+def ent_del(e):
+    unicorn_sys.ent_del(e.id)
+    _unicorn_entities.pop(e.id, None)
+
+
+def ent_tag(e, tag, on=True):
+    unicorn_sys.ent_tag(e.id, tag, on)
+
+
+def ent_has(e, tag):
+    return unicorn_sys.ent_has(e.id, tag)
+
+
+def ent_layer(e, layer):
+    unicorn_sys.ent_layer(e.id, math.floor(layer))
+
+
+def ent_count(tag=None):
+    return unicorn_sys.ent_count(tag or "")
+
+
+def ent_each(tag, fn):
+    for id in unicorn_sys.ent_ids(tag or ""):
+        # Removed by a previous call
+        e = _unicorn_entities.get(id)
+        if e is not None:
+            fn(e)
+
+
+def _ent_call(e, name):
+    method = getattr(e, name, None)
+    if method is None:
+        return
+    # Like e:update() in Lua, the functions set on the entity get it
+    if getattr(method, "__self__", None) is e:
+        method()
+    else:
+        method(e)
+
+
+def ent_update(tag=None):
+    ent_each(tag, lambda e: _ent_call(e, "update"))
+
+
+def ent_draw(tag=None):
+    ent_each(tag, lambda e: _ent_call(e, "draw"))
+
+
+# Display
+
+
+def set_filter(name):
+    return unicorn_sys.set_filter(name)
+
+
+def permission(name):
+    return unicorn_sys.permission(name)
+
+
+# Cart data
+
+
+def cartdata(id):
+    return unicorn_sys.cartdata(id)
+
+
+def dget(index):
+    return unicorn_sys.dget(math.floor(index))
+
+
+def dset(index, value):
+    unicorn_sys.dset(math.floor(index), value)
+
+
+def score_sign(value):
+    return unicorn_sys.score_sign(math.floor(value))
+
+
+def score_verify(blob):
+    return unicorn_sys.score_verify(blob)
+
+
+def _strf_arg(value):
+    # Same strings as in Lua for the booleans and nil
+    if isinstance(value, bool):
+        return "true" if value else "false"
+    if value is None:
+        return "nil"
+    if isinstance(value, (int, float)):
+        return value
+    return str(value)
+
+
+def strf(fmt, *args):
+    return unicorn_sys.strf(fmt, [_strf_arg(arg) for arg in args])
+
+
+def tostr(value):
+    return strf("%s", value)
+
+
 globals()["unicorn_time"] = unicorn_time
 globals()["unicorn_time_sec"] = unicorn_time_sec
+globals()["unicorn_real_time_sec"] = unicorn_real_time_sec
+globals()["unicorn_over_budget"] = unicorn_over_budget
+globals()["trace_begin"] = trace_begin
+globals()["trace_end"] = trace_end
+globals()["unicorn_reduce_motion"] = unicorn_reduce_motion
+globals()["stat"] = stat
+globals()["extcmd"] = extcmd
 globals()["show_mouse"] = show_mouse
+globals()["after"] = after
+globals()["every"] = every
+globals()["tween"] = tween
+globals()["timer_cancel"] = timer_cancel
+globals()["timer_scale"] = timer_scale
+globals()["_unicorn_timers_update"] = _unicorn_timers_update
+globals()["menuitem"] = menuitem
+globals()["_unicorn_menuitems_update"] = _unicorn_menuitems_update
+globals()["Entity"] = Entity
+globals()["ent_new"] = ent_new
+globals()["ent_del"] = ent_del
+globals()["ent_tag"] = ent_tag
+globals()["ent_has"] = ent_has
+globals()["ent_layer"] = ent_layer
+globals()["ent_count"] = ent_count
+globals()["ent_each"] = ent_each
+globals()["ent_update"] = ent_update
+globals()["ent_draw"] = ent_draw
+globals()["set_filter"] = set_filter
+globals()["permission"] = permission
+globals()["cartdata"] = cartdata
+globals()["dget"] = dget
+globals()["dset"] = dset
+globals()["score_sign"] = score_sign
+globals()["score_verify"] = score_verify
+globals()["strf"] = strf
+globals()["tostr"] = tostr
+
+
+# Inspector
+
+
+def _unicorn_inspect_kind(v):
+    if v is None:
+        return "nil"
+    if isinstance(v, bool):
+        return "boolean"
+    if isinstance(v, (int, float)):
+        return "number"
+    if isinstance(v, str):
+        return "string"
+    if isinstance(v, (dict, list, tuple)):
+        return "table"
+    if callable(v):
+        return "function"
+    if type(v).__name__ == "module":
+        return "module"
+    if hasattr(v, "__dict__"):
+        return "object"
+    return type(v).__name__
+
+
+def _unicorn_inspect_items(v):
+    # (key, name, value) of the children, the keys of the objects are
+    # their attributes
+    if isinstance(v, dict):
+        return [(repr(k) if isinstance(k, (str, int, float, bool)) else "", str(k), x)
+                for k, x in v.items()]
+    if isinstance(v, (list, tuple)):
+        return [(repr(k), str(k), x) for k, x in enumerate(v)]
+    if hasattr(v, "__dict__"):
+        return [("." + k, k, x) for k, x in vars(v).items()]
+    return []
+
+
+def _unicorn_inspect(*path):
+    import ast
+
+    v = globals()
+    for key in path:
+        if key.startswith("."):
+            v = getattr(v, key[1:])
+        else:
+            v = v[ast.literal_eval(key)]
+
+    lines = []
+    for key, name, x in _unicorn_inspect_items(v):
+        if not path and (name in _unicorn_builtins or name.startswith("_unicorn") or
+                         name.startswith("__")):
+            continue
+
+        kind = _unicorn_inspect_kind(x)
+        if kind == "table" or kind == "object":
+            value = "{%d}" % len(_unicorn_inspect_items(x))
+        elif kind == "function" or kind == "module":
+            value = kind
+        else:
+            value = repr(x)[:64]
+
+        fields = [kind, key, name, value]
+        lines.append("\t".join("".join(c if c.isprintable() else "?" for c in f)
+                               for f in fields))
+    return "\n".join(lines)
+
+
+_unicorn_builtins = set(globals())
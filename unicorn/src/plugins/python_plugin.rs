@@ -3,46 +3,103 @@
 pub mod plugin {
     use cpython::*;
 
+    use std::char;
     use std::sync::{Arc, Mutex};
 
     use config::Players;
     use unicorn::info::Info;
     use unicorn::Palettes;
     use unicorn::noise::Noise;
+    use unicorn::random::Random;
+    use unicorn::deprecation;
     use unicorn::UnicornConfig;
+    use unicorn::crash;
+    use unicorn::format::{self, FormatArg};
+    use unicorn::timers::Easing;
+    use unicorn::physics::Body;
+    use unicorn::fixed::Fixed;
+    use unicorn::pathfind;
+    use unicorn::{MAP_HEIGHT, MAP_WIDTH};
+    use unicorn::script_cache::ScriptCache;
+    use unicorn::permissions::Permission;
     use gfx::Screen;
+    use gfx::particles::EmitterDef;
+    use gfx::gfx3d::{self, DepthMode, Mesh};
+    use gfx::collision;
     use sound::sound::Sound;
 
     /*
         # GFX                   #    Python     #    New name       #
         camera                  #       X       #                   #
+        camera_push             #       X       #                   #
+        camera_pop              #       X       #                   #
         circ                    #       X       #                   #
         circfill                #       X       #                   #
         clip                    #       X       #                   #
+        clip_push               #       X       #                   #
+        clip_pop                #       X       #                   #
         cls                     #       X       #                   #
         color                   #       X       #                   #
         ellipse                 #       X       #                   #
         ellipsefill             #       X       #                   #
+        fade                    #       X       #                   #
         fget                    #       X       #                   #
+        fillp                   #       X       #                   #
+        flash                   #       X       #                   #
         font                    #       X       #                   #
+        font_load               #       X       #                   #
+        font_data               #       X       #                   #
         line                    #       X       #                   #
+        line_width              #       X       #                   #
+        bezier                  #       X       #                   #
+        mode                    #       X       #                   #
         pal                     #       X       #                   #
         palt                    #       X       #                   #
+        pal_cycle               #       X       #                   #
         pget                    #       X       #                   #
         polygon                 #       X       #                   #
+        polyfill                #       X       #                   #
         print                   #       X       # unicorn_print     #
         pset                    #       X       #                   #
+        shake                   #       X       #                   #
+        particles_spawn         #       X       #                   #
+        particles_move          #       X       #                   #
+        particles_stop          #       X       #                   #
+        particles_clear         #       X       #                   #
+        particles_count         #       X       #                   #
+        particles_draw          #       X       #                   #
         rect                    #       X       #                   #
         rectfill                #       X       #                   #
         sget                    #       X       #                   #
         spr                     #       X       #                   #
+        spr_tile                #       X       #                   #
+        spr_9slice              #       X       #                   #
         sset                    #       X       #                   #
         sspr                    #       X       #                   #
+        sspr_rot                #       X       #                   #
         sspr_rotazoom           #       X       #                   #
         trigon                  #       X       #                   #
+        trifill                 #       X       #                   #
+        # 3D                    #               #                   #
+        camera3d                #       X       #                   #
+        perspective3d           #       X       #                   #
+        light3d                 #       X       #                   #
+        mode3d                  #       X       #                   #
+        push3d                  #       X       #                   #
+        pop3d                   #       X       #                   #
+        identity3d              #       X       #                   #
+        translate3d             #       X       #                   #
+        rotate3d                #       X       #                   #
+        scale3d                 #       X       #                   #
+        mesh3d                  #       X       #                   #
+        mesh3d_del              #       X       #                   #
+        draw3d                  #       X       #                   #
+        tri3d                   #       X       #                   #
+        render3d                #       X       #                   #
         # Audio                 #               #                   #
         music                   #       X       #                   #
         sfx                     #       X       #                   #
+        sfx_new                 #       X       #                   #
         music_stop              #       X       #                   #
         music_volume            #       X       #                   #
         music_pause             #       X       #                   #
@@ -56,18 +113,33 @@ pub mod plugin {
         mouse_y                 #       X       #                   #
         mouse_state             #       X       #                   #
         mouse_statep            #       X       #                   #
+        mouse_btn               #       X       #                   #
+        mouse_wheel             #       X       #                   #
+        touchpad                #       X       #                   #
+        led                     #       X       #                   #
+        keyboard_text           #       X       #                   #
+        key_pressed             #       X       #                   #
         # Map                   #               #                   #
         mapdraw                 #       X       #                   #
+        map_affine              #       X       #                   #
+        map_scanline            #       X       #                   #
+        raycast                 #       X       #                   #
+        collide_map             #       X       #                   #
+        collide_rect            #       X       #                   #
+        sprite_overlap          #       X       #                   #
+        pathfind                #       X       #                   #
         mget                    #       X       #                   #
         mset                    #       X       #                   #
         # Noise                 #               #                   #
         noise                   #       X       #                   #
         noise_set_seed          #       X       #                   #
+        noise_seed              #       X       #                   #
         # Palette               #               #                   #
         palette                 #       X       #                   #
         palette_hexa            #       X       #                   #
         palette_reset           #       X       #                   #
         palette_switch          #       X       #                   #
+        set_palette             #       X       #                   #
         # Math                  #               #                   #
         atan2                   #       X       #                   #
         cos                     #       X       #                   #
@@ -75,14 +147,65 @@ pub mod plugin {
         flr                     #       X       #                   #
         rnd                     #       X       #                   #
         srand                   #       X       #                   #
+        fx                      #       X       #                   #
+        fx_num                  #       X       #                   #
+        fx_mul                  #       X       #                   #
+        fx_div                  #       X       #                   #
+        fx_sqrt                 #       X       #                   #
+        fx_sin                  #       X       #                   #
+        fx_cos                  #       X       #                   #
         mid                     #       X       #                   #
         bxor                    #       X       #                   #
         # Memory                #               #                   #
+        peek                    #       X       #                   #
+        poke                    #       X       #                   #
         memcpy                  #       X       #                   #
+        memset                  #       X       #                   #
+        # Display               #               #                   #
+        set_filter              #       X       #                   #
+        permission              #       X       #                   #
+        # Cart data             #               #                   #
+        cartdata                #       X       #                   #
+        dget                    #       X       #                   #
+        dset                    #       X       #                   #
         # System                #               #                   #
         time                    #       X       # unicorn_time      #
         time_sec                #       X       # unicorn_time_sec  #
+        real_time_sec           #       X       # unicorn_real_time_sec #
+        over_budget             #       X       # unicorn_over_budget #
+        trace_begin             #       X       #                   #
+        trace_end               #       X       #                   #
+        reduce_motion           #       X       # unicorn_reduce_motion #
+        stat                    #       X       #                   #
+        extcmd                  #       X       #                   #
+        after                   #       X       #                   #
+        every                   #       X       #                   #
+        tween                   #       X       #                   #
+        timer_cancel            #       X       #                   #
+        timer_scale             #       X       #                   #
+        menuitem                #       X       #                   #
+        # Entities              #               #                   #
+        ent_new                 #       X       #                   #
+        ent_del                 #       X       #                   #
+        ent_tag                 #       X       #                   #
+        ent_has                 #       X       #                   #
+        ent_layer               #       X       #                   #
+        ent_count               #       X       #                   #
+        ent_each                #       X       #                   #
+        ent_update              #       X       #                   #
+        ent_draw                #       X       #                   #
+        # Physics               #               #                   #
+        body_new                #       X       #                   #
+        body_move               #       X       #                   #
+        body_get                #       X       #                   #
+        body_set                #       X       #                   #
+        body_gravity            #       X       #                   #
+        body_del                #       X       #                   #
         show_mouse              #       X       #                   #
+        score_sign              #       X       #                   #
+        score_verify            #       X       #                   #
+        strf                    #       X       #                   #
+        tostr                   #       X       #                   #
     */
 
     // Audio
@@ -95,11 +218,21 @@ pub mod plugin {
         Ok(0)
     }
 
+    def chiptune_music_pattern(&self, n: i32, fade_ms: i32, channel_mask: i32) -> PyResult<i32> {
+        self.sound(py).lock().unwrap().music_pattern(n, fade_ms, channel_mask);
+        Ok(0)
+    }
+
     def chiptune_sfx(&self, id: i32, filename: String, channel: i32, note: u16, panning: i32, rate: i32, loops: i32) -> PyResult<i32> {
         self.sound(py).lock().unwrap().sfx(id, filename, channel, note, panning, rate, loops);
         Ok(0)
     }
 
+    def chiptune_new_sfx(&self, name: String, waveform: String, attack: i64, decay: i64, sustain: i64, release: i64) -> PyResult<i32> {
+        self.sound(py).lock().unwrap().new_sfx(name, waveform, attack, decay, sustain, release);
+        Ok(0)
+    }
+
     def chiptune_stop(&self) -> PyResult<i32> {
         self.sound(py).lock().unwrap().music_stop();
         Ok(0)
@@ -158,6 +291,14 @@ pub mod plugin {
         Ok(0)
     }
 
+    def camera_push(&self, x: i32, y: i32, absolute: bool) -> PyResult<bool> {
+        Ok(self.screen(py).lock().unwrap().camera_push(x, y, absolute))
+    }
+
+    def camera_pop(&self) -> PyResult<bool> {
+        Ok(self.screen(py).lock().unwrap().camera_pop())
+    }
+
     def circ(&self, x: i32, y: i32, r: i32, color: i32) -> PyResult<i32> {
         self.screen(py).lock().unwrap().circ(x, y, r, color);
         Ok(0)
@@ -173,6 +314,14 @@ pub mod plugin {
         Ok(0)
     }
 
+    def clip_push(&self, x: i32, y: i32, w: i32, h: i32) -> PyResult<bool> {
+        Ok(self.screen(py).lock().unwrap().clip_push(x, y, w, h))
+    }
+
+    def clip_pop(&self) -> PyResult<bool> {
+        Ok(self.screen(py).lock().unwrap().clip_pop())
+    }
+
     def cls(&self, value: i8) -> PyResult<i32> {
         self.screen(py).lock().unwrap().cls(value);
         Ok(0)
@@ -201,11 +350,45 @@ pub mod plugin {
         Ok(self.screen(py).lock().unwrap().fget_all(idx))
     }
 
+    def sprite_overlap(&self, n1: u32, x1: i32, y1: i32, w1: i32, h1: i32, n2: u32, x2: i32, y2: i32, w2: i32, h2: i32) -> PyResult<bool> {
+        Ok(self.screen(py).lock().unwrap().sprite_overlap(n1, x1, y1, w1, h1, n2, x2, y2, w2, h2))
+    }
+
+    def fillp(&self, pattern: u32, col2: i32) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().fillp((pattern & 0xffff) as u16, col2);
+        Ok(0)
+    }
+
     def font(&self, name: String) -> PyResult<i32> {
         self.screen(py).lock().unwrap().font(&name);
         Ok(0)
     }
 
+    def font_load(&self, name: String, sx: i32, sy: i32, w: i32, h: i32, first: u32, count: u32, widths: PyList) -> PyResult<bool> {
+        let mut values: Vec<i32> = Vec::new();
+        for value in widths.iter(py) {
+            values.push(try!(value.extract::<i32>(py)));
+        }
+
+        let first = match char::from_u32(first) {
+            Some(first) => first,
+            None => ' ',
+        };
+
+        let done = self.screen(py).lock().unwrap().font_load(&name, sx, sy, w, h, first, count, &values);
+        Ok(done)
+    }
+
+    def font_data(&self, name: String, height: i32, data: PyList) -> PyResult<bool> {
+        let mut values: Vec<u32> = Vec::new();
+        for value in data.iter(py) {
+            values.push(try!(value.extract::<u32>(py)));
+        }
+
+        let done = self.screen(py).lock().unwrap().font_data(&name, height, &values);
+        Ok(done)
+    }
+
     def fset(&self, idx: u32, flag: u8, value: bool) -> PyResult<i32> {
         self.screen(py).lock().unwrap().fset(idx, flag, value);
         Ok(0)
@@ -225,6 +408,23 @@ pub mod plugin {
         Ok(0)
     }
 
+    def line_width(&self, w: i32) -> PyResult<i32> {
+        Ok(self.screen(py).lock().unwrap().line_width(w))
+    }
+
+    def bezier(&self, x0: i32, y0: i32, cx: i32, cy: i32, x1: i32, y1: i32, color: i32) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().bezier(x0, y0, cx, cy, x1, y1, color);
+        Ok(0)
+    }
+
+    def mode(&self, width: usize, height: usize) -> PyResult<bool> {
+        let value = self.screen(py).lock().unwrap().mode(width, height);
+        if !value {
+            warn!("[PLUGIN][PYTHON] Unknown mode {:?}x{:?}", width, height);
+        }
+        Ok(value)
+    }
+
     def mode_get_width(&self) -> PyResult<usize> {
         Ok(self.screen(py).lock().unwrap().mode_width())
     }
@@ -238,8 +438,78 @@ pub mod plugin {
         Ok(0)
     }
 
-    def pal(&self, c0: i32, c1: i32) -> PyResult<i32> {
-        self.screen(py).lock().unwrap().pal(c0, c1);
+    def pal(&self, c0: i32, c1: i32, p: i32) -> PyResult<i32> {
+        if p == 1 {
+            self.screen(py).lock().unwrap().pal_screen(c0, c1);
+        } else {
+            self.screen(py).lock().unwrap().pal(c0, c1);
+        }
+        Ok(0)
+    }
+
+    def pal_cycle(&self, first: i32, last: i32, speed: f64) -> PyResult<i32> {
+        if first < 0 || last < 0 {
+            self.screen(py).lock().unwrap().pal_cycle_reset();
+        } else {
+            self.screen(py).lock().unwrap().pal_cycle(first as u32, last as u32, speed);
+        }
+        Ok(0)
+    }
+
+    def particles_spawn(&self, fields: PyDict, colors: PyList) -> PyResult<u32> {
+        // The missing fields keep their default value
+        let number = |name: &str| fields.get_item(py, name).and_then(|value| value.extract::<f64>(py).ok());
+
+        let mut def = EmitterDef::new();
+        def.x = number("x").unwrap_or(def.x);
+        def.y = number("y").unwrap_or(def.y);
+        def.count = number("count").map(|count| count.max(0.0) as u32).unwrap_or(def.count);
+        def.rate = number("rate").unwrap_or(def.rate);
+        def.duration = number("duration").unwrap_or(def.duration);
+        def.life = number("life").unwrap_or(def.life);
+        def.life_var = number("life_var").unwrap_or(def.life_var);
+        def.speed = number("speed").unwrap_or(def.speed);
+        def.speed_var = number("speed_var").unwrap_or(def.speed_var);
+        def.angle = number("angle").unwrap_or(def.angle);
+        def.spread = number("spread").unwrap_or(def.spread);
+        def.gravity = number("gravity").unwrap_or(def.gravity);
+        def.drag = number("drag").unwrap_or(def.drag);
+        def.sprite = number("sprite").map(|sprite| sprite.max(0.0) as u32);
+        def.size = number("size").unwrap_or(def.size);
+        def.size_end = number("size_end").unwrap_or(def.size);
+
+        let mut values: Vec<u32> = Vec::new();
+        for value in colors.iter(py) {
+            values.push(value.extract::<f64>(py).unwrap().max(0.0) as u32);
+        }
+        if !values.is_empty() {
+            def.colors = values;
+        }
+
+        Ok(self.screen(py).lock().unwrap().particles.spawn(def))
+    }
+
+    def particles_move(&self, id: u32, x: f64, y: f64) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().particles.move_to(id, x, y);
+        Ok(0)
+    }
+
+    def particles_stop(&self, id: u32) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().particles.stop(id);
+        Ok(0)
+    }
+
+    def particles_clear(&self) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().particles.clear();
+        Ok(0)
+    }
+
+    def particles_count(&self) -> PyResult<usize> {
+        Ok(self.screen(py).lock().unwrap().particles.count())
+    }
+
+    def particles_draw(&self) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().particles_draw();
         Ok(0)
     }
 
@@ -253,6 +523,11 @@ pub mod plugin {
         Ok(0)
     }
 
+    def print_font(&self, str: String, x: i32, y: i32, color: i32, font: String) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().print_font(str, x, y, color, &font);
+        Ok(0)
+    }
+
     def pget(&self, x: i32, y: i32) -> PyResult<u32> {
         let value = self.screen(py).lock().unwrap().pget(x as u32, y as u32);
         Ok(value)
@@ -286,6 +561,16 @@ pub mod plugin {
                                                    height))
     }
 
+    def spr_tile(&self, n: i32, x: i32, y: i32, w: i32, h: i32) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().spr_tile(n as u32, x, y, w, h);
+        Ok(0)
+    }
+
+    def spr_9slice(&self, n: i32, x: i32, y: i32, w: i32, h: i32) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().spr_9slice(n as u32, x, y, w, h);
+        Ok(0)
+    }
+
     def spr(&self, n: i32, x: i32, y: i32, w: i32, h: i32, flip_x: bool, flip_y: bool, angle: f64, zoom: f64, dynamic: bool) -> PyResult<i32> {
         self.screen(py).lock().unwrap().spr(n as u32,
                                             x,
@@ -320,6 +605,11 @@ pub mod plugin {
         Ok(0)
     }
 
+    def sspr_rot(&self, sx: u32, sy: u32, sw: u32, sh: u32, dx: i32, dy: i32, dw: u32, dh: u32, angle: f64, flip_x: bool, flip_y: bool) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().sspr_rot(sx, sy, sw, sh, dx, dy, dw, dh, angle, flip_x, flip_y);
+        Ok(0)
+    }
+
     def sspr_rotazoom(&self, idx_sprite: i32, sx: i32, sy: i32, sw: i32, sh: i32, dx: i32, dy: i32, angle: f64, zoom: f64, flip_x: bool, flip_y: bool) -> PyResult<PyList> {
         let (dw, dh) = self.screen(py).lock().unwrap().sspr_rotazoom(idx_sprite,
                                                                      sx as u32,
@@ -343,6 +633,11 @@ pub mod plugin {
         Ok(0)
     }
 
+    def trifill(&self, x1: i32, y1: i32, x2: i32, y2: i32, x3: i32, y3: i32, color: i32) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().trifill(x1, y1, x2, y2, x3, y3, color);
+        Ok(0)
+    }
+
 
     def polygon(&self, x: PyList, y: PyList, color: i32) -> PyResult<i32> {
         if x.len(py) != y.len(py) {
@@ -368,11 +663,126 @@ pub mod plugin {
         Ok(0)
     }
 
+    def polyfill(&self, points: PyList, color: i32) -> PyResult<i32> {
+        let mut values: Vec<i32> = Vec::new();
+        for value in points.iter(py) {
+            values.push(value.extract::<i32>(py).unwrap());
+        }
+
+        let points: Vec<(i32, i32)> = values.chunks(2)
+            .filter(|point| point.len() == 2)
+            .map(|point| (point[0], point[1]))
+            .collect();
+
+        self.screen(py).lock().unwrap().polyfill(&points, color);
+        Ok(0)
+    }
+
+    def camera3d(&self, ex: f64, ey: f64, ez: f64, tx: f64, ty: f64, tz: f64) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().gfx3d.camera([ex, ey, ez], [tx, ty, tz]);
+        Ok(0)
+    }
+
+    def perspective3d(&self, fov: f64, near: f64) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().gfx3d.perspective(fov, near);
+        Ok(0)
+    }
+
+    def light3d(&self, x: f64, y: f64, z: f64, ambient: f64) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().gfx3d.set_light(x, y, z, ambient);
+        Ok(0)
+    }
+
+    def mode3d(&self, mode: String, cull: bool) -> PyResult<i32> {
+        let mut screen = self.screen(py).lock().unwrap();
+        match DepthMode::from_name(&mode) {
+            Some(mode) => screen.gfx3d.mode = mode,
+            None => warn!("[PLUGIN][PYTHON] Unknown 3D mode {:?}", mode),
+        }
+        screen.gfx3d.cull = cull;
+        Ok(0)
+    }
+
+    def push3d(&self) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().gfx3d.push();
+        Ok(0)
+    }
+
+    def pop3d(&self) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().gfx3d.pop();
+        Ok(0)
+    }
+
+    def identity3d(&self) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().gfx3d.set_identity();
+        Ok(0)
+    }
+
+    def translate3d(&self, x: f64, y: f64, z: f64) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().gfx3d.apply(&gfx3d::translation(x, y, z));
+        Ok(0)
+    }
+
+    def rotate3d(&self, x: f64, y: f64, z: f64) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().gfx3d.apply(&gfx3d::rotation(x, y, z));
+        Ok(0)
+    }
+
+    def scale3d(&self, x: f64, y: f64, z: f64) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().gfx3d.apply(&gfx3d::scaling(x, y, z));
+        Ok(0)
+    }
+
+    def mesh3d(&self, vertices: PyList, faces: PyList) -> PyResult<Option<u32>> {
+        let mut coords: Vec<f64> = Vec::new();
+        for value in vertices.iter(py) {
+            coords.push(value.extract::<f64>(py).unwrap());
+        }
+
+        let mut indexes: Vec<i64> = Vec::new();
+        for value in faces.iter(py) {
+            indexes.push(value.extract::<i64>(py).unwrap());
+        }
+
+        let mesh = Mesh {
+            vertices: coords.chunks(3)
+                .filter(|vertex| vertex.len() == 3)
+                .map(|vertex| [vertex[0], vertex[1], vertex[2]])
+                .collect(),
+            faces: indexes.chunks(4)
+                .filter(|face| face.len() == 4)
+                .map(|face| ([face[0] as usize, face[1] as usize, face[2] as usize], face[3].max(0) as u32))
+                .collect(),
+        };
+
+        Ok(self.screen(py).lock().unwrap().gfx3d.mesh_new(mesh))
+    }
+
+    def mesh3d_del(&self, id: u32) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().gfx3d.mesh_del(id);
+        Ok(0)
+    }
+
+    def draw3d(&self, id: u32) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().draw3d(id);
+        Ok(0)
+    }
+
+    def tri3d(&self, x0: f64, y0: f64, z0: f64, x1: f64, y1: f64, z1: f64, x2: f64, y2: f64, z2: f64, color: i32) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().tri3d([[x0, y0, z0], [x1, y1, z1], [x2, y2, z2]], color);
+        Ok(0)
+    }
+
+    def render3d(&self) -> PyResult<usize> {
+        Ok(self.screen(py).lock().unwrap().render3d())
+    }
+
     });
 
     // Input
     py_class!(class UnicornInput |py| {
     data players: Arc < Mutex < Players> >;
+    data info: Arc < Mutex < Info > >;
 
     def btn(&self, x: i32, p: i32) -> PyResult<bool> {
         let value = self.players(py).lock().unwrap().btn(p as u8, x as u8);
@@ -409,11 +819,47 @@ pub mod plugin {
         Ok(value)
     }
 
+    def btn_mouse_btn(&self, button: u8) -> PyResult<bool> {
+        let value = self.players(py).lock().unwrap().mouse_btn(button);
+        Ok(value)
+    }
+
+    def btn_mouse_wheel(&self) -> PyResult<i32> {
+        let value = self.players(py).lock().unwrap().mouse_wheel();
+        Ok(value)
+    }
+
+    def keyboard_text(&self) -> PyResult<String> {
+        if !self.info(py).lock().unwrap().permission(Permission::Keyboard) {
+            return Ok("".to_string());
+        }
+        let value = self.players(py).lock().unwrap().get_text();
+        Ok(value)
+    }
+
+    def touchpad(&self, player: u8, width: usize, height: usize) -> PyResult<Option<(i32, i32)>> {
+        Ok(self.players(py).lock().unwrap().touchpad(player, width, height))
+    }
+
+    def led(&self, player: u8, r: u8, g: u8, b: u8) -> PyResult<u32> {
+        self.info(py).lock().unwrap().led(player, r, g, b);
+        Ok(0)
+    }
+
+    def key_pressed(&self, name: String) -> PyResult<bool> {
+        if !self.info(py).lock().unwrap().permission(Permission::Keyboard) {
+            return Ok(false);
+        }
+        let value = self.players(py).lock().unwrap().key_pressed(&name);
+        Ok(value)
+    }
+
     });
 
     // Map
     py_class!(class UnicornMap |py| {
     data screen: Arc < Mutex < Screen > >;
+    data info: Arc < Mutex < Info > >;
 
     def mapdraw(&self, cel_x: i32, cel_y: i32, sx: i32, sy: i32, cel_w: i32, cel_h: i32, layer: u8) -> PyResult<i32> {
         self.screen(py).lock().unwrap().mapdraw(cel_x as u32, cel_y as u32,
@@ -424,6 +870,38 @@ pub mod plugin {
         Ok(0)
     }
 
+    def map_affine(&self, a: f64, b: f64, c: f64, d: f64, tx: f64, ty: f64, layer: u8) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().map_affine(a, b, c, d, tx, ty, layer);
+        Ok(0)
+    }
+
+    def map_scanline(&self, y: i32, u: f64, v: f64, du: f64, dv: f64, layer: u8) -> PyResult<i32> {
+        self.screen(py).lock().unwrap().map_scanline(y, u, v, du, dv, layer);
+        Ok(0)
+    }
+
+    def raycast(&self, x: f64, y: f64, angle: f64, fov: f64, layer: u8, max_distance: f64) -> PyResult<Vec<f64>> {
+        Ok(self.screen(py).lock().unwrap().raycast(x, y, angle, fov, layer, max_distance))
+    }
+
+    def collide_map(&self, x: f64, y: f64, w: f64, h: f64, flag: u8) -> PyResult<bool> {
+        Ok(self.screen(py).lock().unwrap().collide_map(x, y, w, h, flag))
+    }
+
+    def collide_rect(&self, x1: f64, y1: f64, w1: f64, h1: f64, x2: f64, y2: f64, w2: f64, h2: f64) -> PyResult<bool> {
+        Ok(collision::rects_overlap((x1, y1, w1, h1), (x2, y2, w2, h2)))
+    }
+
+    def pathfind(&self, x0: i32, y0: i32, x1: i32, y1: i32, flag_mask: u8) -> PyResult<Vec<(i32, i32)>> {
+        let mut screen = self.screen(py).lock().unwrap();
+        let path = pathfind::find((x0, y0),
+                                  (x1, y1),
+                                  MAP_WIDTH,
+                                  MAP_HEIGHT,
+                                  &mut |cx, cy| screen.tile_flags(cx, cy) & flag_mask != 0);
+        Ok(path.unwrap_or(Vec::new()))
+    }
+
 
     def mget(&self, x: i32, y: i32) -> PyResult<u32> {
         let value = self.screen(py).lock().unwrap().mget(x, y);
@@ -435,6 +913,47 @@ pub mod plugin {
         Ok(0)
     }
 
+    def body_new(&self, x: f64, y: f64, w: f64, h: f64) -> PyResult<Option<u32>> {
+        Ok(Body::from_f64(x, y, w, h).map(|body| self.info(py).lock().unwrap().physics.add(body)))
+    }
+
+    def body_move(&self, id: u32) -> PyResult<i32> {
+        let mut info = self.info(py).lock().unwrap();
+        let mut screen = self.screen(py).lock().unwrap();
+        if let Some(body) = info.physics.get_mut(id) {
+            body.step(&mut |cx, cy| screen.tile_flags(cx, cy));
+        }
+        Ok(0)
+    }
+
+    def body_get(&self, id: u32) -> PyResult<Option<(f64, f64, f64, f64, bool)>> {
+        let info = self.info(py).lock().unwrap();
+        Ok(info.physics.get(id).map(|body| {
+            (body.x.to_f64(), body.y.to_f64(), body.vx.to_f64(), body.vy.to_f64(), body.on_ground)
+        }))
+    }
+
+    def body_set(&self, id: u32, x: Option<f64>, y: Option<f64>, vx: Option<f64>, vy: Option<f64>) -> PyResult<i32> {
+        let mut info = self.info(py).lock().unwrap();
+        if let Some(body) = info.physics.get_mut(id) {
+            body.set(x, y, vx, vy);
+        }
+        Ok(0)
+    }
+
+    def body_gravity(&self, id: u32, gravity: f64, max_fall: Option<f64>) -> PyResult<i32> {
+        let mut info = self.info(py).lock().unwrap();
+        if let Some(body) = info.physics.get_mut(id) {
+            body.set_gravity(gravity, max_fall);
+        }
+        Ok(0)
+    }
+
+    def body_del(&self, id: u32) -> PyResult<i32> {
+        self.info(py).lock().unwrap().physics.remove(id);
+        Ok(0)
+    }
+
     });
 
     // Math
@@ -443,11 +962,25 @@ pub mod plugin {
     py_class!(class UnicornMemory |py| {
     data screen: Arc < Mutex < Screen > >;
 
+    def peek(&self, addr: u32) -> PyResult<u8> {
+        Ok(self.screen(py).lock().unwrap().peek(addr))
+    }
+
+    def poke(&self, addr: u32, val: u8) -> PyResult<u32> {
+        self.screen(py).lock().unwrap().poke(addr, val);
+        Ok(0)
+    }
+
     def memcpy(&self, dest_addr: u32, source_addr: u32, len: u32) -> PyResult<u32> {
         self.screen(py).lock().unwrap().memcpy(dest_addr, source_addr, len);
         Ok(0)
     }
 
+    def memset(&self, dest_addr: u32, val: u8, len: u32) -> PyResult<u32> {
+        self.screen(py).lock().unwrap().memset(dest_addr, val, len);
+        Ok(0)
+    }
+
     });
 
     // Noise
@@ -457,8 +990,8 @@ pub mod plugin {
             Ok(self._noise(py).lock().unwrap().get(x, y, z))
         }
 
-        def noise_set_seed(&self, seed: u32) -> PyResult<u32> {
-            self._noise(py).lock().unwrap().set_seed(seed);
+        def noise_set_seed(&self, seed: i64) -> PyResult<u32> {
+            self._noise(py).lock().unwrap().set_seed(seed as u64);
             Ok(0)
         }
     });
@@ -478,9 +1011,247 @@ pub mod plugin {
             Ok(self.info(py).lock().unwrap().time())
         }
 
+        def rnd(&self, x: f64) -> PyResult<f64> {
+            Ok(self.info(py).lock().unwrap().random.rnd(x))
+        }
+
+        def srand(&self, seed: i64) -> PyResult<u32> {
+            self.info(py).lock().unwrap().random = Random::from_seed(seed as u64);
+            Ok(0)
+        }
+
+        def deprecations(&self) -> PyResult<Vec<String>> {
+            Ok(deprecation::names("python").iter().map(|name| name.to_string()).collect())
+        }
+
+        def deprecated(&self, name: String, line: u32) -> PyResult<u32> {
+            if let Some(message) = self.info(py).lock().unwrap().deprecations.warn(&name, Some(line)) {
+                warn!("[PLUGIN][PYTHON] {}", message);
+            }
+            Ok(0)
+        }
+
+        def fx(&self, x: f64) -> PyResult<i32> {
+            Ok(Fixed::from_f64(x).0)
+        }
+
+        def fx_num(&self, a: i32) -> PyResult<f64> {
+            Ok(Fixed(a).to_f64())
+        }
+
+        def fx_mul(&self, a: i32, b: i32) -> PyResult<i32> {
+            Ok((Fixed(a) * Fixed(b)).0)
+        }
+
+        def fx_div(&self, a: i32, b: i32) -> PyResult<i32> {
+            Ok((Fixed(a) / Fixed(b)).0)
+        }
+
+        def fx_sqrt(&self, a: i32) -> PyResult<i32> {
+            Ok(Fixed(a).sqrt().0)
+        }
+
+        def fx_sin(&self, a: i32) -> PyResult<i32> {
+            Ok((-Fixed(a).sin()).0)
+        }
+
+        def fx_cos(&self, a: i32) -> PyResult<i32> {
+            Ok(Fixed(a).cos().0)
+        }
+
         def time_sec(&self) -> PyResult<f64> {
             Ok(self.info(py).lock().unwrap().time_sec())
         }
+
+        def real_time_sec(&self) -> PyResult<f64> {
+            Ok(self.info(py).lock().unwrap().real_time_sec())
+        }
+
+        def over_budget(&self) -> PyResult<f64> {
+            Ok(self.info(py).lock().unwrap().over_budget())
+        }
+
+        def trace_begin(&self, name: String) -> PyResult<i32> {
+            self.info(py).lock().unwrap().trace.begin(&name);
+            Ok(0)
+        }
+
+        def trace_end(&self) -> PyResult<i32> {
+            self.info(py).lock().unwrap().trace.end();
+            Ok(0)
+        }
+
+        def fade(&self, t: f64, col: u32) -> PyResult<i32> {
+            self.info(py).lock().unwrap().effects.fade(t, col);
+            Ok(0)
+        }
+
+        def flash(&self, col: u32, frames: u32) -> PyResult<i32> {
+            self.info(py).lock().unwrap().effects.flash(col, frames);
+            Ok(0)
+        }
+
+        def shake(&self, intensity: f64, frames: u32) -> PyResult<i32> {
+            self.info(py).lock().unwrap().effects.shake(intensity, frames);
+            Ok(0)
+        }
+
+        def reduce_motion(&self) -> PyResult<bool> {
+            Ok(self.info(py).lock().unwrap().display.reduce_motion)
+        }
+
+        def timer_new(&self, delay: f64, repeat: bool) -> PyResult<u32> {
+            Ok(self.info(py).lock().unwrap().timers.timer(delay, repeat))
+        }
+
+        def tween_new(&self, from: f64, to: f64, duration: f64, easing: String) -> PyResult<u32> {
+            let easing = match Easing::from_name(&easing) {
+                Some(easing) => easing,
+                None => {
+                    warn!("[PLUGIN][PYTHON] Unknown easing {:?}, linear is used", easing);
+                    Easing::Linear
+                }
+            };
+            Ok(self.info(py).lock().unwrap().timers.tween(from, to, duration, easing))
+        }
+
+        def timer_cancel(&self, id: u32) -> PyResult<u32> {
+            self.info(py).lock().unwrap().timers.cancel(id);
+            Ok(0)
+        }
+
+        def timer_scale(&self, scale: f64) -> PyResult<u32> {
+            self.info(py).lock().unwrap().timers.scale = scale.max(0.0);
+            Ok(0)
+        }
+
+        def timer_next(&self) -> PyResult<Option<(u32, bool)>> {
+            Ok(self.info(py).lock().unwrap().timers.next_fired())
+        }
+
+        def tween_next(&self) -> PyResult<Option<(u32, f64, bool)>> {
+            Ok(self.info(py).lock().unwrap().timers.next_value())
+        }
+
+        def menuitem(&self, index: u32, label: Option<String>) -> PyResult<bool> {
+            Ok(self.info(py).lock().unwrap().menuitem(index, label.as_ref().map(|label| label.as_str())))
+        }
+
+        def menuitem_next(&self) -> PyResult<Option<u32>> {
+            Ok(self.info(py).lock().unwrap().menuitem_selected.take())
+        }
+
+        def ent_new(&self, tags: String, layer: i32) -> PyResult<u32> {
+            Ok(self.info(py).lock().unwrap().entities.add(&tags, layer))
+        }
+
+        def ent_del(&self, id: u32) -> PyResult<u32> {
+            self.info(py).lock().unwrap().entities.remove(id);
+            Ok(0)
+        }
+
+        def ent_tag(&self, id: u32, tag: String, value: bool) -> PyResult<u32> {
+            self.info(py).lock().unwrap().entities.tag(id, &tag, value);
+            Ok(0)
+        }
+
+        def ent_has(&self, id: u32, tag: String) -> PyResult<bool> {
+            Ok(self.info(py).lock().unwrap().entities.has_tag(id, &tag))
+        }
+
+        def ent_layer(&self, id: u32, layer: i32) -> PyResult<u32> {
+            self.info(py).lock().unwrap().entities.set_layer(id, layer);
+            Ok(0)
+        }
+
+        def ent_count(&self, tag: String) -> PyResult<usize> {
+            Ok(self.info(py).lock().unwrap().entities.count(&tag))
+        }
+
+        def ent_ids(&self, tag: String) -> PyResult<Vec<u32>> {
+            Ok(self.info(py).lock().unwrap().entities.ids(&tag))
+        }
+
+        def permission(&self, name: String) -> PyResult<bool> {
+            match Permission::from_name(&name) {
+                Some(permission) => Ok(self.info(py).lock().unwrap().permission(permission)),
+                None => {
+                    warn!("[PLUGIN][PYTHON] Unknown permission {:?}", name);
+                    Ok(false)
+                }
+            }
+        }
+
+        def set_filter(&self, name: String) -> PyResult<bool> {
+            let value = self.info(py).lock().unwrap().request_filter(&name);
+            if !value {
+                warn!("[PLUGIN][PYTHON] Unknown filter {:?}", name);
+            }
+            Ok(value)
+        }
+
+        def last_capture(&self) -> PyResult<String> {
+            Ok(self.info(py).lock().unwrap().last_capture.clone())
+        }
+
+        def captures(&self) -> PyResult<u32> {
+            Ok(self.info(py).lock().unwrap().captures)
+        }
+
+        def frame_time(&self) -> PyResult<f64> {
+            Ok(self.info(py).lock().unwrap().frame_time * 1000.0)
+        }
+
+        def extcmd(&self, cmd: String, arg: Option<String>) -> PyResult<i32> {
+            let value = self.info(py).lock().unwrap().extcmd(&cmd, arg.as_ref().map(|arg| arg.as_str()));
+            if let Err(e) = value {
+                warn!("[PLUGIN][PYTHON] {}", e);
+            }
+            Ok(0)
+        }
+
+        def cartdata(&self, id: String) -> PyResult<bool> {
+            let result = self.info(py).lock().unwrap().cartdata.open(&id);
+            match result {
+                Ok(value) => Ok(value),
+                Err(e) => {
+                    warn!("[PLUGIN][PYTHON] {}", e);
+                    Ok(false)
+                }
+            }
+        }
+
+        def dget(&self, index: usize) -> PyResult<f64> {
+            Ok(self.info(py).lock().unwrap().cartdata.get(index))
+        }
+
+        def dset(&self, index: usize, value: f64) -> PyResult<u32> {
+            self.info(py).lock().unwrap().cartdata.set(index, value);
+            Ok(0)
+        }
+
+        def score_sign(&self, value: i64) -> PyResult<String> {
+            Ok(self.info(py).lock().unwrap().score_sign(value))
+        }
+
+        def score_verify(&self, blob: String) -> PyResult<Option<i64>> {
+            Ok(self.info(py).lock().unwrap().score_verify(&blob))
+        }
+
+        def strf(&self, fmt: String, args: PyList) -> PyResult<String> {
+            let mut values = Vec::new();
+
+            // The booleans and None are converted in api.py
+            for arg in args.iter(py) {
+                let value = match arg.extract::<String>(py) {
+                    Ok(value) => FormatArg::Str(value),
+                    Err(_) => FormatArg::Number(arg.extract::<f64>(py).unwrap_or(0.0)),
+                };
+                values.push(value);
+            }
+
+            Ok(format::strf(&fmt, &values))
+        }
     });
 
     pub struct PythonPlugin {
@@ -530,12 +1301,12 @@ pub mod plugin {
                 .set_item(py, "unicorn_audio", unicorn_audio_obj)
                 .unwrap();
 
-            let unicorn_input_obj = UnicornInput::create_instance(py, players.clone()).unwrap();
+            let unicorn_input_obj = UnicornInput::create_instance(py, players.clone(), info.clone()).unwrap();
             self.mydict
                 .set_item(py, "unicorn_input", unicorn_input_obj)
                 .unwrap();
 
-            let unicorn_map_obj = UnicornMap::create_instance(py, screen.clone()).unwrap();
+            let unicorn_map_obj = UnicornMap::create_instance(py, screen.clone(), info.clone()).unwrap();
             self.mydict.set_item(py, "unicorn_map", unicorn_map_obj).unwrap();
 
             let unicorn_sys_obj = UnicornSys::create_instance(py, info.clone(), config.clone()).unwrap();
@@ -627,6 +1398,7 @@ pub mod plugin {
                 Err(v) => {
                     return_draw_value = false;
                     warn!("[PLUGIN][PYTHON] DRAW = {:?}", v);
+                    crash::set_script_error(&format!("[PYTHON] DRAW = {:?}", v));
                 }
                 Ok(v) => {
                     match v.extract(py) {
@@ -652,12 +1424,25 @@ pub mod plugin {
             let gil = Python::acquire_gil();
             let py = gil.python();
 
+            let result = py.eval(r###"_unicorn_menuitems_update()"###, None, Some(&self.mydict));
+            if let Err(v) = result {
+                warn!("[PLUGIN][PYTHON] MENUITEMS = {:?}", v);
+                crash::set_script_error(&format!("[PYTHON] MENUITEMS = {:?}", v));
+            }
+
+            let result = py.eval(r###"_unicorn_timers_update()"###, None, Some(&self.mydict));
+            if let Err(v) = result {
+                warn!("[PLUGIN][PYTHON] TIMERS = {:?}", v);
+                crash::set_script_error(&format!("[PYTHON] TIMERS = {:?}", v));
+            }
+
             let result = py.eval(r###"_update()"###, None, Some(&self.mydict));
 
             match result {
                 Err(v) => {
                     return_update_value = false;
                     warn!("[PLUGIN][PYTHON] UPDATE = {:?}", v);
+                    crash::set_script_error(&format!("[PYTHON] UPDATE = {:?}", v));
                 }
                 Ok(v) => {
                     match v.extract(py) {
@@ -673,13 +1458,152 @@ pub mod plugin {
         }
 
 
+        /// Value of a watch expression, its errors aren't the ones of the
+        /// cartridge
+        pub fn eval(&mut self, expression: &str) -> Result<String, String> {
+            if !self.loaded_code {
+                return Err("No code".to_string());
+            }
+
+            let gil = Python::acquire_gil();
+            let py = gil.python();
+
+            let locals = PyDict::new(py);
+            locals.set_item(py, "_unicorn_watch", expression).unwrap();
+
+            match py.eval("repr(eval(_unicorn_watch))", None, Some(&locals)) {
+                Ok(value) => {
+                    match value.extract::<String>(py) {
+                        Ok(text) => Ok(text),
+                        Err(_) => Err("Invalid value".to_string()),
+                    }
+                }
+                Err(mut err) => {
+                    let name = err.get_type(py).name(py).into_owned();
+                    Err(format!("{}: {}", name, err.instance(py)))
+                }
+            }
+        }
+
+        /// Line of the developer console: the repr of an expression, or a
+        /// statement run in the globals of the cartridge
+        pub fn execute(&mut self, code: &str) -> Result<String, String> {
+            if !self.loaded_code {
+                return Err("No code".to_string());
+            }
+
+            let gil = Python::acquire_gil();
+            let py = gil.python();
+
+            let locals = PyDict::new(py);
+            locals.set_item(py, "_unicorn_console", code).unwrap();
+
+            let result = py.run(r###"
+try:
+    _unicorn_code = compile(_unicorn_console, "<console>", "eval")
+except SyntaxError:
+    _unicorn_code = None
+if _unicorn_code is None:
+    exec(compile(_unicorn_console, "<console>", "exec"), globals())
+    _unicorn_result = ""
+else:
+    _unicorn_result = repr(eval(_unicorn_code, globals()))
+"###,
+                                None,
+                                Some(&locals));
+
+            match result {
+                Ok(_) => {
+                    match locals.get_item(py, "_unicorn_result") {
+                        Some(value) => {
+                            match value.extract::<String>(py) {
+                                Ok(text) => Ok(text),
+                                Err(_) => Err("Invalid value".to_string()),
+                            }
+                        }
+                        None => Err("Invalid value".to_string()),
+                    }
+                }
+                Err(mut err) => {
+                    let name = err.get_type(py).name(py).into_owned();
+                    Err(format!("{}: {}", name, err.instance(py)))
+                }
+            }
+        }
+
+        /// Children of the global at `path` for the inspector, the keys are
+        /// the ones listed for its parent
+        pub fn inspect(&mut self, path: &[String]) -> Result<String, String> {
+            if !self.loaded_code {
+                return Err("No code".to_string());
+            }
+
+            let gil = Python::acquire_gil();
+            let py = gil.python();
+
+            let locals = PyDict::new(py);
+            locals.set_item(py, "_unicorn_path", path).unwrap();
+
+            match py.eval("_unicorn_inspect(*_unicorn_path)", None, Some(&locals)) {
+                Ok(value) => {
+                    match value.extract::<String>(py) {
+                        Ok(text) => Ok(text),
+                        Err(_) => Err("Invalid value".to_string()),
+                    }
+                }
+                Err(mut err) => {
+                    let name = err.get_type(py).name(py).into_owned();
+                    Err(format!("{}: {}", name, err.instance(py)))
+                }
+            }
+        }
+
         pub fn load_code(&mut self, data: String) -> bool {
+            self.load_code_cached(data, &ScriptCache::disabled())
+        }
+
+        /// The code object is read from the cache with `marshal`, or compiled
+        /// and written in it. The magic number of the interpreter is checked,
+        /// the objects of another version of Python are compiled again
+        pub fn load_code_cached(&mut self, data: String, cache: &ScriptCache) -> bool {
             info!("[PLUGIN][PYTHON] Load the code");
             let gil = Python::acquire_gil();
             let py = gil.python();
 
-
-            let result = py.run(&data, None, None);
+            let cache_path = match cache.path(&data, "pyc") {
+                Some(path) => path.to_string_lossy().into_owned(),
+                None => "".to_string(),
+            };
+
+            let locals = PyDict::new(py);
+            locals.set_item(py, "_unicorn_source", &data).unwrap();
+            locals.set_item(py, "_unicorn_cache", &cache_path).unwrap();
+
+            let result = py.run(r###"
+import importlib.util, marshal, os
+_code = None
+if _unicorn_cache:
+    try:
+        with open(_unicorn_cache, "rb") as f:
+            _data = f.read()
+        if _data.startswith(importlib.util.MAGIC_NUMBER):
+            _code = marshal.loads(_data[len(importlib.util.MAGIC_NUMBER):])
+    except (OSError, ValueError, EOFError, TypeError):
+        _code = None
+if _code is None:
+    _code = compile(_unicorn_source, "<string>", "exec")
+    if _unicorn_cache:
+        try:
+            os.makedirs(os.path.dirname(_unicorn_cache), exist_ok=True)
+            with open(_unicorn_cache, "wb") as f:
+                f.write(importlib.util.MAGIC_NUMBER + marshal.dumps(_code))
+        except OSError:
+            pass
+exec(_code, globals())
+"###,
+                                None,
+                                Some(&locals));
+            cache.prune();
 
             match result {
                 Ok(_) => {
@@ -688,6 +1612,7 @@ pub mod plugin {
                 }
                 Err(err) => {
                     error!("[PLUGIN][PYTHON] Load code error => {:?}", err);
+                    crash::set_script_error(&format!("[PYTHON] LOAD CODE = {:?}", err));
                     self.loaded_code = false
                 }
             }
@@ -710,6 +1635,7 @@ pub mod plugin {
     use sound::sound::Sound;
     use unicorn::noise::Noise;
     use unicorn::UnicornConfig;
+    use unicorn::script_cache::ScriptCache;
 
     pub struct PythonPlugin {}
 
@@ -736,8 +1662,20 @@ pub mod plugin {
         pub fn update(&mut self) -> bool {
             false
         }
+        pub fn eval(&mut self, _expression: &str) -> Result<String, String> {
+            Err("Python plugin disabled".to_string())
+        }
+        pub fn inspect(&mut self, _path: &[String]) -> Result<String, String> {
+            Err("Python plugin disabled".to_string())
+        }
+        pub fn execute(&mut self, _code: &str) -> Result<String, String> {
+            Err("Python plugin disabled".to_string())
+        }
         pub fn load_code(&mut self, _data: String) -> bool {
             false
         }
+        pub fn load_code_cached(&mut self, _data: String, _cache: &ScriptCache) -> bool {
+            false
+        }
     }
 }
@@ -1,9 +1,13 @@
+pub mod music;
+
 pub mod sound {
     use std::sync::mpsc;
     use unicorn::packet;
     use unicorn::UnicornCartridge;
 
     use chiptune::chiptune;
+    use chiptune::chiptune::ChiptuneWaveform;
+    use sound::music::{AudioStatus, MusicEvent, MusicSequencer, MUSIC_MAX_VOLUME};
 
     use std::sync::{Arc, Mutex};
 
@@ -11,6 +15,9 @@ pub mod sound {
         pub player: chiptune::Chiptune,
         pub csend: mpsc::Sender<Vec<u8>>,
         pub crecv: mpsc::Receiver<Vec<u8>>,
+        pub sequencer: MusicSequencer,
+        /// Volume set by music_volume, the fades of the patterns are relative to it
+        pub volume: i32,
     }
 
     impl SoundInternal {
@@ -21,6 +28,8 @@ pub mod sound {
                 player: chiptune::Chiptune::new(),
                 csend: csend,
                 crecv: crecv,
+                sequencer: MusicSequencer::new(),
+                volume: MUSIC_MAX_VOLUME,
             }
         }
 
@@ -39,6 +48,7 @@ pub mod sound {
         pub fn stop(&mut self) {
             info!("[SOUND] Stop");
             self.player.stop();
+            self.sequencer = MusicSequencer::new();
         }
 
         pub fn stop_chan(&mut self, chan: i32) {
@@ -141,6 +151,33 @@ pub mod sound {
             res
         }
 
+        fn update_music(&mut self, cartridge: &mut UnicornCartridge) {
+            let events = self.sequencer.tick(&cartridge.cartridge.music.patterns);
+
+            for event in events {
+                match event {
+                    MusicEvent::Play(channel, id) => {
+                        if id < 0 || id >= cartridge.sound_tracks_name.len() as i32 {
+                            warn!("[SOUND] Unknown sfx {:?} in the music pattern", id);
+                            continue;
+                        }
+
+                        match cartridge.sound_tracks
+                            .get_mut(&cartridge.sound_tracks_name[id as usize]) {
+                            Some(mut sound) => {
+                                self.player.play_sound(&mut sound, channel, 13312, 64, 50);
+                            }
+                            None => {}
+                        }
+                    }
+                    MusicEvent::Stop(channel) => self.player.stop_chan(channel),
+                    MusicEvent::Volume(volume) => {
+                        self.player.set_volume(volume * self.volume / MUSIC_MAX_VOLUME);
+                    }
+                }
+            }
+        }
+
         pub fn update(&mut self, cartridge: &mut UnicornCartridge, sound: Arc<Mutex<Sound>>) {
             for sound_packet in self.crecv.try_iter() {
                 debug!("[SOUND] PACKET {:?}", sound_packet);
@@ -257,18 +294,71 @@ pub mod sound {
                     }
 
                     packet::Packet::ChiptuneVolume(res) => {
+                        self.volume = res.volume;
                         self.player.set_volume(res.volume);
                     }
+
+                    packet::Packet::ChiptunePattern(res) => {
+                        info!("MUSIC PATTERN {:?}", res);
+
+                        // 1 frame = 1/60s
+                        let fade_frames = if res.fade_ms > 0 { res.fade_ms as u32 * 60 / 1000 } else { 0 };
+                        self.sequencer.start(res.n, fade_frames, res.channel_mask as u8);
+                    }
+
+                    packet::Packet::ChiptuneNewSFX(res) => {
+                        info!("NEW SFX {:?}", res);
+
+                        let waveform = match ChiptuneWaveform::from_name(&res.waveform) {
+                            Some(waveform) => waveform,
+                            None => {
+                                error!("[SOUND] Unknown waveform {:?}", res.waveform);
+                                continue;
+                            }
+                        };
+
+                        match self.player.new_sound(res.name.clone()) {
+                            Ok(chip_sound) => {
+                                self.player.set_waveform(chip_sound, waveform);
+                                self.player.set_attack(chip_sound, res.attack);
+                                self.player.set_decay(chip_sound, res.decay);
+                                self.player.set_sustain(chip_sound, res.sustain);
+                                self.player.set_release(chip_sound, res.release);
+
+                                if !cartridge.sound_tracks.contains_key(&res.name) {
+                                    cartridge.sound_tracks_name.push(res.name.clone());
+                                }
+                                cartridge.sound_tracks.insert(res.name.clone(), chip_sound);
+                            }
+                            Err(e) => error!("ERROR to create the sound {:?}", e),
+                        }
+                    }
                 }
             }
 
-            sound.lock().unwrap().chiptune_position = self.player.get_music_position();
+            self.update_music(cartridge);
+
+            let position = self.player.get_music_position();
+            let mut sound = sound.lock().unwrap();
+            sound.chiptune_position = position;
+
+            let status = &mut sound.status;
+            self.player.get_vu_meters(&mut status.levels);
+            status.music_position = position;
+            status.pattern = self.sequencer.pattern;
+            status.pattern_frame = self.sequencer.frame();
+            status.pattern_duration = cartridge.cartridge
+                .music
+                .patterns
+                .get(self.sequencer.pattern as usize)
+                .map_or(0, |pattern| pattern.duration);
         }
     }
 
     pub struct Sound {
         csend: mpsc::Sender<Vec<u8>>,
         chiptune_position: i32,
+        status: AudioStatus,
     }
 
     impl Sound {
@@ -276,6 +366,7 @@ pub mod sound {
             Sound {
                 csend: csend,
                 chiptune_position: 0,
+                status: AudioStatus::new(),
             }
         }
 
@@ -318,6 +409,40 @@ pub mod sound {
             self.csend.send(packet::write_packet(p).unwrap()).unwrap();
         }
 
+        /// Play the patterns of the cartridge from `n` (-1 to stop), on the
+        /// channels of `channel_mask` (bit 0 for the channel 0, ...)
+        pub fn music_pattern(&mut self, n: i32, fade_ms: i32, channel_mask: i32) {
+            debug!("[SOUND] Chiptune Music pattern {:?}", n);
+            let p = packet::ChiptunePattern {
+                n: n,
+                fade_ms: fade_ms,
+                channel_mask: channel_mask,
+            };
+            self.csend.send(packet::write_packet(p).unwrap()).unwrap();
+        }
+
+        /// Create a sound played later by `sfx(-1, name)`, the ADSR values
+        /// are in the klystron envelope units, clamped to 0..255
+        pub fn new_sfx(&mut self,
+                       name: String,
+                       waveform: String,
+                       attack: i64,
+                       decay: i64,
+                       sustain: i64,
+                       release: i64) {
+            debug!("[SOUND] Chiptune SFX New {:?} {:?}", name, waveform);
+            let envelope = |value: i64| value.max(0).min(255) as u8;
+            let p = packet::ChiptuneNewSFX {
+                name: name,
+                waveform: waveform,
+                attack: envelope(attack),
+                decay: envelope(decay),
+                sustain: envelope(sustain),
+                release: envelope(release),
+            };
+            self.csend.send(packet::write_packet(p).unwrap()).unwrap();
+        }
+
         pub fn load_sfx(&mut self, filename: String, data: Vec<u8>) {
             debug!("[SOUND] Chiptune SFX Load {:?}", filename);
             let p = packet::ChiptuneLoadSFX {
@@ -380,5 +505,10 @@ pub mod sound {
         pub fn chiptune_get_position(&mut self) -> i32 {
             self.chiptune_position
         }
+
+        /// Levels of the channels and position of the music, of the last update
+        pub fn audio_status(&self) -> AudioStatus {
+            self.status.clone()
+        }
     }
 }
\ No newline at end of file
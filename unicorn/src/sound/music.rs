@@ -0,0 +1,318 @@
+use cartridge::{MusicPattern, SfxPattern, MUSIC_CHANNELS, SFX_NOTES};
+
+/// Volume of the klystron player
+pub const MUSIC_MAX_VOLUME: i32 = 128;
+/// Channels of the klystron player, the ones of `sfx` start at 16
+pub const AUDIO_CHANNELS: usize = 32;
+pub const SFX_CHANNELS_START: usize = 16;
+
+/// State of the player after each update, for the audio visualizer
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioStatus {
+    /// Envelope of each channel, from 0 to MUSIC_MAX_VOLUME
+    pub levels: [i32; AUDIO_CHANNELS],
+    /// Position in the song of `music`
+    pub music_position: i32,
+    /// Pattern of `music_pattern` (-1 when stopped), its frame and its duration
+    pub pattern: i32,
+    pub pattern_frame: u32,
+    pub pattern_duration: u32,
+}
+
+impl AudioStatus {
+    pub fn new() -> AudioStatus {
+        AudioStatus {
+            levels: [0; AUDIO_CHANNELS],
+            music_position: 0,
+            pattern: -1,
+            pattern_frame: 0,
+            pattern_duration: 0,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MusicEvent {
+    /// Play the sfx on the channel
+    Play(i32, i32),
+    Stop(i32),
+    Volume(i32),
+}
+
+/// Play the patterns of the cartridge one after the other, the sequencer
+/// is updated once per frame
+pub struct MusicSequencer {
+    pub pattern: i32,
+    pub channel_mask: u8,
+    frame: u32,
+    fade_frames: u32,
+    fade_frame: u32,
+    fade_out: bool,
+}
+
+impl MusicSequencer {
+    pub fn new() -> MusicSequencer {
+        MusicSequencer {
+            pattern: -1,
+            channel_mask: 0,
+            frame: 0,
+            fade_frames: 0,
+            fade_frame: 0,
+            fade_out: false,
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.pattern >= 0
+    }
+
+    /// Frame of the current pattern
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// Start the pattern `n` with a fade in, or fade out the current music when `n` is -1
+    pub fn start(&mut self, n: i32, fade_frames: u32, channel_mask: u8) {
+        self.fade_frames = fade_frames;
+        self.fade_frame = 0;
+
+        if n < 0 {
+            self.fade_out = true;
+            return;
+        }
+
+        self.fade_out = false;
+        self.pattern = n;
+        self.channel_mask = channel_mask;
+        self.frame = 0;
+    }
+
+    fn stop_events(&mut self, events: &mut Vec<MusicEvent>) {
+        for channel in 0..MUSIC_CHANNELS {
+            if self.channel_mask & (1 << channel) != 0 {
+                events.push(MusicEvent::Stop(channel as i32));
+            }
+        }
+        events.push(MusicEvent::Volume(MUSIC_MAX_VOLUME));
+
+        self.pattern = -1;
+        self.fade_frames = 0;
+        self.fade_out = false;
+    }
+
+    fn next_pattern(&self, patterns: &[MusicPattern]) -> i32 {
+        let current = &patterns[self.pattern as usize];
+
+        if current.is_stop() {
+            return -1;
+        }
+
+        if current.is_loop_end() {
+            let mut idx = self.pattern;
+            while idx > 0 && !patterns[idx as usize].is_loop_start() {
+                idx -= 1;
+            }
+            return idx;
+        }
+
+        if self.pattern + 1 < patterns.len() as i32 {
+            self.pattern + 1
+        } else {
+            -1
+        }
+    }
+
+    pub fn tick(&mut self, patterns: &[MusicPattern]) -> Vec<MusicEvent> {
+        let mut events = Vec::new();
+
+        if !self.is_playing() {
+            return events;
+        }
+
+        if self.pattern as usize >= patterns.len() {
+            warn!("[SOUND] Unknown music pattern {:?}", self.pattern);
+            self.stop_events(&mut events);
+            return events;
+        }
+
+        if self.fade_frames > 0 {
+            self.fade_frame += 1;
+
+            let ratio = if self.fade_frame >= self.fade_frames {
+                1.0
+            } else {
+                self.fade_frame as f64 / self.fade_frames as f64
+            };
+
+            if self.fade_out {
+                if ratio >= 1.0 {
+                    self.stop_events(&mut events);
+                    return events;
+                }
+                events.push(MusicEvent::Volume(((1.0 - ratio) * MUSIC_MAX_VOLUME as f64) as i32));
+            } else {
+                events.push(MusicEvent::Volume((ratio * MUSIC_MAX_VOLUME as f64) as i32));
+                if ratio >= 1.0 {
+                    self.fade_frames = 0;
+                }
+            }
+        } else if self.fade_out {
+            self.stop_events(&mut events);
+            return events;
+        }
+
+        if self.frame == 0 {
+            let pattern = &patterns[self.pattern as usize];
+            for channel in 0..MUSIC_CHANNELS {
+                if self.channel_mask & (1 << channel) != 0 && pattern.sfx[channel] >= 0 {
+                    events.push(MusicEvent::Play(channel as i32, pattern.sfx[channel]));
+                }
+            }
+        }
+
+        self.frame += 1;
+        if self.frame >= patterns[self.pattern as usize].duration {
+            self.frame = 0;
+
+            let next = self.next_pattern(patterns);
+            if next < 0 {
+                self.stop_events(&mut events);
+            } else {
+                self.pattern = next;
+            }
+        }
+
+        events
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SfxEvent {
+    /// Play the semitone
+    Note(i32),
+    Rest,
+    End,
+}
+
+/// Play the notes of a sfx one after the other, the player is updated once
+/// per frame and a note lasts `speed` frames
+pub struct SfxPlayer {
+    pub step: i32,
+    frame: u32,
+}
+
+impl SfxPlayer {
+    pub fn new() -> SfxPlayer {
+        SfxPlayer {
+            step: -1,
+            frame: 0,
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.step >= 0
+    }
+
+    pub fn start(&mut self) {
+        self.step = 0;
+        self.frame = 0;
+    }
+
+    pub fn stop(&mut self) {
+        self.step = -1;
+    }
+
+    /// The event of the frame, only at the beginning of a step
+    pub fn tick(&mut self, pattern: &SfxPattern) -> Option<SfxEvent> {
+        if !self.is_playing() {
+            return None;
+        }
+
+        let mut event = None;
+        if self.frame == 0 {
+            if self.step as usize >= SFX_NOTES {
+                self.stop();
+                return Some(SfxEvent::End);
+            }
+
+            let note = pattern.notes[self.step as usize];
+            event = Some(if note < 0 { SfxEvent::Rest } else { SfxEvent::Note(note) });
+        }
+
+        self.frame += 1;
+        if self.frame >= pattern.speed as u32 {
+            self.frame = 0;
+            self.step += 1;
+        }
+
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cartridge::{MusicPattern, SfxPattern, MUSIC_LOOP_END, MUSIC_LOOP_START, SFX_NOTES};
+
+    use super::{MusicEvent, MusicSequencer, SfxEvent, SfxPlayer};
+
+    fn pattern(flags: u8, sfx: i32) -> MusicPattern {
+        let mut pattern = MusicPattern::new();
+        pattern.flags = flags;
+        pattern.duration = 2;
+        pattern.sfx[0] = sfx;
+        pattern
+    }
+
+    #[test]
+    fn test_sequencer_loop() {
+        let patterns = vec![pattern(0, 0), pattern(MUSIC_LOOP_START, 1), pattern(MUSIC_LOOP_END, 2)];
+
+        let mut sequencer = MusicSequencer::new();
+        sequencer.start(0, 0, 0xf);
+
+        let mut played = Vec::new();
+        for _ in 0..10 {
+            for event in sequencer.tick(&patterns) {
+                if let MusicEvent::Play(_, sfx) = event {
+                    played.push(sfx);
+                }
+            }
+        }
+
+        assert_eq!(played, vec![0, 1, 2, 1, 2]);
+        assert!(sequencer.is_playing());
+    }
+
+    #[test]
+    fn test_sequencer_end_and_mask() {
+        let patterns = vec![pattern(0, 3)];
+
+        let mut sequencer = MusicSequencer::new();
+        sequencer.start(0, 0, 0xe);
+
+        assert_eq!(sequencer.tick(&patterns), vec![]);
+        assert!(sequencer.tick(&patterns).contains(&MusicEvent::Stop(1)));
+        assert!(!sequencer.is_playing());
+    }
+
+    #[test]
+    fn test_sfx_player() {
+        let mut pattern = SfxPattern::new();
+        pattern.speed = 2;
+        pattern.notes[0] = 48;
+
+        let mut player = SfxPlayer::new();
+        assert_eq!(player.tick(&pattern), None);
+
+        player.start();
+        assert_eq!(player.tick(&pattern), Some(SfxEvent::Note(48)));
+        assert_eq!(player.tick(&pattern), None);
+        assert_eq!(player.tick(&pattern), Some(SfxEvent::Rest));
+
+        for _ in 3..SFX_NOTES * 2 {
+            player.tick(&pattern);
+        }
+        assert_eq!(player.tick(&pattern), Some(SfxEvent::End));
+        assert!(!player.is_playing());
+    }
+}
@@ -0,0 +1,252 @@
+//! Audio visualizer (Shift+F7): a level meter for each channel of the
+//! player, the levels of the last seconds of the busiest channels and the
+//! position of the music, over the game or the sound editors. The channels
+//! from 16 are the ones of `sfx`.
+
+use std::collections::VecDeque;
+
+use gfx::Screen;
+use sound::music::{AudioStatus, AUDIO_CHANNELS, MUSIC_MAX_VOLUME, SFX_CHANNELS_START};
+
+/// Frames of levels kept for each channel
+pub const HISTORY_LEN: usize = 256;
+/// Channels with a graph of their last levels
+pub const LEVEL_GRAPHS: usize = 4;
+
+const LINE_HEIGHT: i32 = 8;
+const METER_HEIGHT: i32 = 16;
+const GRAPH_HEIGHT: i32 = 14;
+/// Fall of the peak marks per frame
+const PEAK_FALL: i32 = 2;
+
+// Dithered background, like the help overlay
+const DITHER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+pub struct AudioViewer {
+    pub enabled: bool,
+    pub status: AudioStatus,
+    /// Levels of the last frames of each channel, the oldest first
+    history: Vec<VecDeque<i32>>,
+    pub peaks: [i32; AUDIO_CHANNELS],
+    pub background: u32,
+    pub color: u32,
+    pub music_color: u32,
+    pub sfx_color: u32,
+    pub peak_color: u32,
+}
+
+impl AudioViewer {
+    pub fn new() -> AudioViewer {
+        AudioViewer {
+            enabled: false,
+            status: AudioStatus::new(),
+            history: vec![VecDeque::new(); AUDIO_CHANNELS],
+            peaks: [0; AUDIO_CHANNELS],
+            background: 0,
+            color: 7,
+            music_color: 11,
+            sfx_color: 12,
+            peak_color: 8,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        if self.enabled {
+            self.clear();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for history in self.history.iter_mut() {
+            history.clear();
+        }
+        self.peaks = [0; AUDIO_CHANNELS];
+    }
+
+    /// Status of the player of the frame
+    pub fn update(&mut self, status: AudioStatus) {
+        for (channel, level) in status.levels.iter().enumerate() {
+            let level = (*level).max(0).min(MUSIC_MAX_VOLUME);
+
+            let history = &mut self.history[channel];
+            if history.len() >= HISTORY_LEN {
+                history.pop_front();
+            }
+            history.push_back(level);
+
+            self.peaks[channel] = (self.peaks[channel] - PEAK_FALL).max(level);
+        }
+
+        self.status = status;
+    }
+
+    pub fn history(&self, channel: usize) -> &VecDeque<i32> {
+        &self.history[channel]
+    }
+
+    /// The channels heard the most in the history, in their order
+    pub fn level_graph_channels(&self) -> Vec<usize> {
+        let mut channels: Vec<(usize, i32)> = self.history
+            .iter()
+            .enumerate()
+            .map(|(channel, history)| (channel, history.iter().sum()))
+            .filter(|&(_, sum)| sum > 0)
+            .collect();
+        channels.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        let mut channels: Vec<usize> =
+            channels.iter().take(LEVEL_GRAPHS).map(|&(channel, _)| channel).collect();
+        channels.sort();
+        channels
+    }
+
+    /// Position of `music` and of `music_pattern`
+    pub fn position_line(&self) -> String {
+        let pattern = if self.status.pattern < 0 {
+            "-".to_string()
+        } else {
+            format!("{} {}/{}",
+                    self.status.pattern,
+                    self.status.pattern_frame,
+                    self.status.pattern_duration)
+        };
+        format!("MUSIC {}  PATTERN {}", self.status.music_position, pattern)
+    }
+
+    fn channel_color(&self, channel: usize) -> u32 {
+        if channel >= SFX_CHANNELS_START { self.sfx_color } else { self.music_color }
+    }
+
+    pub fn draw(&self, screen: &mut Screen) {
+        if !self.enabled {
+            return;
+        }
+
+        let screen_width = screen.width as i32;
+        let graphs = self.level_graph_channels();
+        let meters_top = 3 + LINE_HEIGHT;
+        let graphs_top = meters_top + METER_HEIGHT + 2 + LINE_HEIGHT;
+        let bottom = graphs_top + GRAPH_HEIGHT * graphs.len().max(1) as i32;
+
+        for j in 0..bottom.min(screen.height as i32) {
+            for i in 0..screen_width {
+                if DITHER[(j % 4) as usize][(i % 4) as usize] < 12 {
+                    screen.putpixel_direct(i, j, self.background);
+                }
+            }
+        }
+
+        screen.force_print(self.position_line(), 2, 2, self.color as i32);
+
+        // One bar per channel, with the peak over it
+        let bar_width = ((screen_width - 4) / AUDIO_CHANNELS as i32).max(1);
+        let meters_bottom = meters_top + METER_HEIGHT - 1;
+        for channel in 0..AUDIO_CHANNELS {
+            let x = 2 + channel as i32 * bar_width;
+            let level = self.history[channel].back().cloned().unwrap_or(0);
+            let height = level * METER_HEIGHT / MUSIC_MAX_VOLUME;
+            let peak = self.peaks[channel] * (METER_HEIGHT - 1) / MUSIC_MAX_VOLUME;
+
+            for i in x..x + (bar_width - 1).max(1) {
+                for j in 0..height {
+                    screen.putpixel_direct(i, meters_bottom - j, self.channel_color(channel));
+                }
+                if self.peaks[channel] > 0 {
+                    screen.putpixel_direct(i, meters_bottom - peak, self.peak_color);
+                }
+            }
+        }
+        screen.force_print("MUSIC".to_string(),
+                           2,
+                           meters_top + METER_HEIGHT + 1,
+                           self.music_color as i32);
+        screen.force_print("SFX".to_string(),
+                           2 + SFX_CHANNELS_START as i32 * bar_width,
+                           meters_top + METER_HEIGHT + 1,
+                           self.sfx_color as i32);
+
+        if graphs.is_empty() {
+            screen.force_print("NO SOUND".to_string(), 2, graphs_top + 2, self.color as i32);
+            return;
+        }
+
+        // The levels of the last frames, the newest on the right
+        let left = 2 + 3 * screen.text_width("0");
+        let width = (screen_width - 2 - left).max(1) as usize;
+        for (row, channel) in graphs.iter().enumerate() {
+            let top = graphs_top + row as i32 * GRAPH_HEIGHT;
+            let color = self.channel_color(*channel);
+            screen.force_print(format!("{}", channel), 2, top + 3, color as i32);
+
+            let history = &self.history[*channel];
+            let skip = history.len().saturating_sub(width);
+            let mut previous = None;
+            for (idx, level) in history.iter().skip(skip).enumerate() {
+                let x = left + idx as i32;
+                let y = top + GRAPH_HEIGHT - 2 - level * (GRAPH_HEIGHT - 3) / MUSIC_MAX_VOLUME;
+                let (from, to) = match previous {
+                    Some(previous) if previous < y => (previous + 1, y),
+                    Some(previous) if previous > y => (y, previous - 1),
+                    _ => (y, y),
+                };
+                for j in from..to + 1 {
+                    screen.putpixel_direct(x, j, color);
+                }
+                previous = Some(y);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gfx::Screen;
+    use sound::music::AudioStatus;
+
+    use super::{AudioViewer, HISTORY_LEN, LEVEL_GRAPHS};
+
+    #[test]
+    fn test_audio_viewer() {
+        let mut viewer = AudioViewer::new();
+        let mut status = AudioStatus::new();
+        status.levels[2] = 64;
+        status.levels[17] = 200;
+        viewer.update(status.clone());
+
+        assert_eq!(viewer.history(17).back(), Some(&128));
+        assert_eq!(viewer.level_graph_channels(), vec![2, 17]);
+
+        // The peak falls slowly
+        status.levels[17] = 0;
+        viewer.update(status.clone());
+        assert_eq!(viewer.peaks[17], 126);
+
+        for channel in 0..8 {
+            status.levels[channel] = 10 + channel as i32;
+        }
+        for _ in 0..HISTORY_LEN {
+            viewer.update(status.clone());
+        }
+        assert_eq!(viewer.history(0).len(), HISTORY_LEN);
+        assert_eq!(viewer.level_graph_channels().len(), LEVEL_GRAPHS);
+        assert_eq!(viewer.level_graph_channels(), vec![4, 5, 6, 7]);
+
+        assert_eq!(viewer.position_line(), "MUSIC 0  PATTERN -");
+        status.pattern = 3;
+        status.pattern_frame = 17;
+        status.pattern_duration = 60;
+        viewer.update(status);
+        assert_eq!(viewer.position_line(), "MUSIC 0  PATTERN 3 17/60");
+
+        let mut screen = Screen::new(128, 128);
+        screen.init();
+        viewer.draw(&mut screen);
+        assert_eq!(screen.frame_buffer[8 + 26 * 128], 0);
+
+        viewer.enabled = true;
+        viewer.draw(&mut screen);
+        // Bottom of the meter of the channel 2, in the color of the music
+        assert_eq!(screen.frame_buffer[8 + 26 * 128], 11);
+    }
+}
@@ -1,3 +1,195 @@
-/*pub struct CartData {
+//! Persistent data of a cartridge (`cartdata`/`dget`/`dset`): 64 numbers
+//! saved in <dir>/<id>.txt, one per line. The file is written to a
+//! temporary file then renamed, at the end of the frame where a value
+//! changed and when the cartridge is closed.
 
-}*/
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+pub const CARTDATA_SIZE: usize = 64;
+
+pub struct CartData {
+    /// Directory of the files, in the profile (or the sandbox)
+    pub dir: PathBuf,
+    pub id: Option<String>,
+    values: [f64; CARTDATA_SIZE],
+    dirty: bool,
+}
+
+impl CartData {
+    pub fn new() -> CartData {
+        CartData {
+            dir: PathBuf::from("cartdata"),
+            id: None,
+            values: [0.0; CARTDATA_SIZE],
+            dirty: false,
+        }
+    }
+
+    /// Letters, digits, '-' and '_', the id is the name of the file
+    pub fn is_valid_id(id: &str) -> bool {
+        !id.is_empty() && id.len() <= 64 &&
+        id.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    }
+
+    /// Save the previous data and forget it, for the next cartridge
+    pub fn reset(&mut self, dir: PathBuf) {
+        self.flush();
+
+        self.dir = dir;
+        self.id = None;
+        self.values = [0.0; CARTDATA_SIZE];
+    }
+
+    fn filename(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.txt", id))
+    }
+
+    pub fn parse(data: &str) -> [f64; CARTDATA_SIZE] {
+        let mut values = [0.0; CARTDATA_SIZE];
+        for (idx, line) in data.lines().take(CARTDATA_SIZE).enumerate() {
+            values[idx] = line.trim().parse::<f64>().unwrap_or(0.0);
+        }
+        values
+    }
+
+    pub fn serialize(values: &[f64]) -> String {
+        values.iter().map(|value| format!("{}\n", value)).collect()
+    }
+
+    /// Open the data of `id`, true when it was already saved. The id can't
+    /// be changed by the cartridge once it is set.
+    pub fn open(&mut self, id: &str) -> Result<bool, String> {
+        if let Some(ref current) = self.id {
+            if current != id {
+                return Err(format!("cartdata already opened with {:?}", current));
+            }
+        }
+
+        if !CartData::is_valid_id(id) {
+            return Err(format!("Invalid cartdata id {:?}", id));
+        }
+
+        self.id = Some(id.to_string());
+
+        let filename = self.filename(id);
+        let mut data = String::new();
+        match File::open(&filename) {
+            Ok(mut f) => {
+                if let Err(e) = f.read_to_string(&mut data) {
+                    return Err(format!("Failed to read {:?}: {}", filename, e));
+                }
+            }
+            Err(_) => return Ok(false),
+        }
+
+        info!("[Unicorn][CARTDATA] Loading {:?}", filename);
+        self.values = CartData::parse(&data);
+        Ok(true)
+    }
+
+    pub fn get(&self, index: usize) -> f64 {
+        if self.id.is_none() {
+            return 0.0;
+        }
+        *self.values.get(index).unwrap_or(&0.0)
+    }
+
+    pub fn set(&mut self, index: usize, value: f64) {
+        if self.id.is_none() || index >= CARTDATA_SIZE {
+            return;
+        }
+
+        if self.values[index] != value {
+            self.values[index] = value;
+            self.dirty = true;
+        }
+    }
+
+    /// Write the file if a value changed
+    pub fn flush(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        let id = match self.id {
+            Some(ref id) => id.clone(),
+            None => return,
+        };
+
+        if let Err(e) = fs::create_dir_all(&self.dir) {
+            error!("[Unicorn][CARTDATA] Failed to create {:?}: {}", self.dir, e);
+            return;
+        }
+
+        let filename = self.filename(&id);
+        let tmp_filename = self.dir.join(format!("{}.txt.tmp", id));
+
+        let result = File::create(&tmp_filename)
+            .and_then(|mut f| {
+                          try!(f.write_all(CartData::serialize(&self.values).as_bytes()));
+                          f.sync_all()
+                      })
+            .and_then(|_| fs::rename(&tmp_filename, &filename));
+
+        match result {
+            Ok(_) => debug!("[Unicorn][CARTDATA] Saved {:?}", filename),
+            Err(e) => error!("[Unicorn][CARTDATA] Failed to save {:?}: {}", filename, e),
+        }
+    }
+}
+
+impl Drop for CartData {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use super::{CartData, CARTDATA_SIZE};
+
+    #[test]
+    fn test_cartdata_format() {
+        let mut values = [0.0; CARTDATA_SIZE];
+        values[0] = 4200.0;
+        values[3] = -1.5;
+
+        let data = CartData::serialize(&values);
+        assert_eq!(data.lines().count(), CARTDATA_SIZE);
+        assert_eq!(&CartData::parse(&data)[..], &values[..]);
+        assert_eq!(CartData::parse("12\nbad\n")[..2], [12.0, 0.0]);
+
+        assert!(CartData::is_valid_id("my_game-1"));
+        assert!(!CartData::is_valid_id("../game"));
+    }
+
+    #[test]
+    fn test_cartdata_save() {
+        let dir = env::temp_dir().join("unicorn-test-cartdata");
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut cartdata = CartData::new();
+        cartdata.reset(dir.clone());
+        assert_eq!(cartdata.open("game"), Ok(false));
+        assert!(cartdata.open("other").is_err());
+
+        cartdata.set(2, 99.0);
+        cartdata.set(CARTDATA_SIZE, 1.0);
+        cartdata.flush();
+        assert!(dir.join("game.txt").exists());
+        assert!(!dir.join("game.txt.tmp").exists());
+
+        cartdata.reset(dir.clone());
+        assert_eq!(cartdata.get(2), 0.0);
+        assert_eq!(cartdata.open("game"), Ok(true));
+        assert_eq!(cartdata.get(2), 99.0);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
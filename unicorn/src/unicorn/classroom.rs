@@ -0,0 +1,118 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde_json;
+
+use unicorn::profile::Profile;
+
+/// Manifest of a teacher, restricting the console to some cartridges:
+/// ```text
+/// {
+///     "carts": ["lesson1.uni", "lesson2.uni"],
+///     "starter": "lesson1.uni",
+///     "saves": "students"
+/// }
+/// ```
+/// The paths are relative to the manifest.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Classroom {
+    #[serde(default)]
+    pub carts: Vec<String>,
+    #[serde(default)]
+    pub starter: Option<String>,
+    #[serde(default)]
+    pub saves: Option<String>,
+    #[serde(skip)]
+    pub dir: PathBuf,
+}
+
+impl Classroom {
+    pub fn from_str(data: &str, dir: &Path) -> Result<Classroom, String> {
+        let mut classroom = match serde_json::from_str::<Classroom>(data) {
+            Ok(classroom) => classroom,
+            Err(e) => return Err(format!("Invalid classroom manifest: {}", e)),
+        };
+        classroom.dir = dir.to_path_buf();
+
+        if let Some(ref starter) = classroom.starter {
+            if !classroom.carts.contains(starter) {
+                classroom.carts.push(starter.clone());
+            }
+        }
+
+        Ok(classroom)
+    }
+
+    pub fn from_manifest(filename: &str) -> Result<Classroom, String> {
+        let mut data = String::new();
+        match File::open(filename) {
+            Ok(mut f) => {
+                if let Err(e) = f.read_to_string(&mut data) {
+                    return Err(format!("Failed to read {:?}: {}", filename, e));
+                }
+            }
+            Err(e) => return Err(format!("Failed to open {:?}: {}", filename, e)),
+        }
+
+        let dir = Path::new(filename).parent().unwrap_or(Path::new(""));
+        Classroom::from_str(&data, dir)
+    }
+
+    pub fn cart_path(&self, name: &str) -> String {
+        self.dir.join(name).to_string_lossy().into_owned()
+    }
+
+    pub fn starter_path(&self) -> Option<String> {
+        self.starter.as_ref().map(|starter| self.cart_path(starter))
+    }
+
+    /// Folder collecting the saves of the students, one profile per student
+    pub fn saves_dir(&self) -> String {
+        match self.saves {
+            Some(ref saves) => self.cart_path(saves),
+            None => self.cart_path("saves"),
+        }
+    }
+
+    /// Copy of a cartridge edited by the student, saved in the profile so
+    /// the cartridges of the teacher are never overwritten
+    pub fn student_filename(&self, profile: &Profile, filename: &str) -> String {
+        let name = match Path::new(filename).file_stem() {
+            Some(stem) => stem.to_string_lossy().into_owned(),
+            None => "cartridge".to_string(),
+        };
+
+        profile.path(&format!("carts/{}.uni", name))
+    }
+
+    /// Only the cartridges of the manifest could be loaded
+    pub fn is_allowed(&self, filename: &str) -> bool {
+        let path = Path::new(filename);
+        self.carts.iter().any(|cart| path == self.dir.join(cart) || path == Path::new(cart))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::Classroom;
+
+    #[test]
+    fn test_classroom() {
+        let data = r#"{"carts": ["lesson1.uni"], "starter": "starter.uni"}"#;
+        let classroom = Classroom::from_str(data, Path::new("class")).unwrap();
+
+        assert!(classroom.is_allowed("class/lesson1.uni"));
+        assert!(classroom.is_allowed("class/starter.uni"));
+        assert!(!classroom.is_allowed("class/other.uni"));
+        assert!(!classroom.is_allowed("lesson2.uni"));
+        assert_eq!(classroom.starter_path(),
+                   Some(Path::new("class").join("starter.uni").to_string_lossy().into_owned()));
+        assert_eq!(classroom.saves_dir(),
+                   Path::new("class").join("saves").to_string_lossy().into_owned());
+
+        assert!(Classroom::from_str("{", Path::new("")).is_err());
+    }
+}
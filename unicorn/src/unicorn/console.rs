@@ -0,0 +1,402 @@
+//! Developer console (`): Lua or Python lines run in the globals of the
+//! cartridge, while the game is paused, with the frame kept under it. An
+//! expression shows its values, a statement is run, so the variables could be
+//! changed and the functions of the console called (`pset(10, 10, 8)` draws
+//! on the frame). Up/Down go through the previous lines, PageUp/PageDown
+//! scroll, and the lines starting with ':' are the commands of the console
+//! (`:watch x` adds the expression to the watches shown while playing).
+
+use std::cmp;
+
+use config::Players;
+use config::scancode::Scancode;
+use gfx::Screen;
+use unicorn::inspector::parse_variables;
+
+const LINE_HEIGHT: i32 = 8;
+/// Lines kept, the oldest ones are removed
+pub const MAX_LINES: usize = 256;
+/// Previous lines given to Up/Down
+pub const MAX_HISTORY: usize = 64;
+pub const MAX_INPUT_LEN: usize = 256;
+
+// Dithered background, like the help overlay
+const DITHER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+const HELP: [&'static str; 8] = ["LUA/PYTHON LINES OF THE CARTRIDGE",
+                                 ":GLOBALS  VARIABLES",
+                                 ":WATCH X  WATCH X WHILE PLAYING",
+                                 ":UNWATCH  STOP WATCHING X, OR ALL",
+                                 ":CLEAR    CLEAR THE LINES",
+                                 ":HELP     THIS HELP",
+                                 "UP/DOWN   PREVIOUS LINES",
+                                 "PGUP/PGDN SCROLL"];
+
+/// Asked to the plugin of the cartridge
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request<'a> {
+    /// Line of code, its values are returned
+    Execute(&'a str),
+    /// Globals, listed like for the inspector
+    Globals,
+    /// Expression added to the watches
+    Watch(&'a str),
+    /// Watch removed, all of them without an expression
+    Unwatch(Option<&'a str>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LineKind {
+    Input,
+    Output,
+    Error,
+}
+
+pub struct Console {
+    pub enabled: bool,
+    pub input: String,
+    pub lines: Vec<(LineKind, String)>,
+    pub history: Vec<String>,
+    /// Line of the history in the input, from the last one
+    history_idx: Option<usize>,
+    /// Lines scrolled from the bottom
+    pub scroll: usize,
+    /// The text of the key opening the console isn't typed
+    skip_text: bool,
+    /// Frame of the game under the console, with the drawings of the lines
+    frame: Vec<u32>,
+    pub background: u32,
+    pub input_color: u32,
+    pub color: u32,
+    pub error_color: u32,
+}
+
+impl Console {
+    pub fn new() -> Console {
+        Console {
+            enabled: false,
+            input: String::new(),
+            lines: vec![(LineKind::Output, ":HELP FOR THE COMMANDS".to_string())],
+            history: Vec::new(),
+            history_idx: None,
+            scroll: 0,
+            skip_text: false,
+            frame: Vec::new(),
+            background: 0,
+            input_color: 6,
+            color: 7,
+            error_color: 8,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        self.skip_text = true;
+    }
+
+    pub fn save_frame(&mut self, screen: &Screen) {
+        self.frame = screen.frame_buffer.clone();
+    }
+
+    /// The lines aren't drawn again over the previous ones, nor recorded
+    pub fn restore_frame(&self, screen: &mut Screen) {
+        if self.frame.len() == screen.frame_buffer.len() {
+            screen.frame_buffer.copy_from_slice(&self.frame);
+        }
+    }
+
+    pub fn type_text(&mut self, text: &str) {
+        for c in text.chars().filter(|c| !c.is_control()) {
+            if self.input.chars().count() < MAX_INPUT_LEN {
+                self.input.push(c);
+            }
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        self.input.pop();
+    }
+
+    pub fn history_previous(&mut self) {
+        if self.history.is_empty() {
+            return;
+        }
+
+        let idx = match self.history_idx {
+            Some(idx) => cmp::min(idx + 1, self.history.len() - 1),
+            None => 0,
+        };
+        self.history_idx = Some(idx);
+        self.input = self.history[self.history.len() - 1 - idx].clone();
+    }
+
+    /// After the last line of the history, the input is empty
+    pub fn history_next(&mut self) {
+        match self.history_idx {
+            Some(0) | None => {
+                self.history_idx = None;
+                self.input.clear();
+            }
+            Some(idx) => {
+                self.history_idx = Some(idx - 1);
+                self.input = self.history[self.history.len() - idx].clone();
+            }
+        }
+    }
+
+    fn push(&mut self, kind: LineKind, text: &str) {
+        for line in text.lines() {
+            self.lines.push((kind, line.to_string()));
+        }
+        if self.lines.len() > MAX_LINES {
+            let extra = self.lines.len() - MAX_LINES;
+            self.lines.drain(..extra);
+        }
+    }
+
+    /// Keys of the console, the line submitted with Return
+    pub fn update(&mut self, screen: &Screen, players: &mut Players) -> Option<String> {
+        if self.skip_text {
+            self.skip_text = false;
+        } else {
+            let text = players.get_text();
+            self.type_text(&text);
+        }
+
+        if players.btnp3(Scancode::Backspace) {
+            self.backspace();
+        }
+        if players.btnp3(Scancode::Up) {
+            self.history_previous();
+        }
+        if players.btnp3(Scancode::Down) {
+            self.history_next();
+        }
+
+        let rows = self.rows(screen);
+        if players.btnp3(Scancode::PageUp) {
+            self.scroll = cmp::min(self.scroll + rows, self.lines.len().saturating_sub(1));
+        }
+        if players.btnp3(Scancode::PageDown) {
+            self.scroll = self.scroll.saturating_sub(rows);
+        }
+
+        if players.btnp3(Scancode::Return) || players.btnp3(Scancode::KpEnter) {
+            let line = self.input.trim().to_string();
+            self.input.clear();
+            self.history_idx = None;
+            if !line.is_empty() {
+                return Some(line);
+            }
+        }
+        None
+    }
+
+    /// Run a line with `run` of the plugin, and show its result
+    pub fn submit<F>(&mut self, line: &str, run: &mut F)
+        where F: FnMut(Request) -> Result<String, String>
+    {
+        self.push(LineKind::Input, &format!("> {}", line));
+        if self.history.last().map(|last| last != line).unwrap_or(true) {
+            self.history.push(line.to_string());
+            if self.history.len() > MAX_HISTORY {
+                self.history.remove(0);
+            }
+        }
+        self.scroll = 0;
+
+        let mut words = line.splitn(2, char::is_whitespace);
+        let command = words.next().unwrap_or("").to_lowercase();
+        let argument = words.next().unwrap_or("").trim();
+
+        match command.as_str() {
+            ":help" => {
+                for help in HELP.iter() {
+                    self.push(LineKind::Output, help);
+                }
+            }
+            ":clear" => self.lines.clear(),
+            ":globals" => {
+                match run(Request::Globals) {
+                    Ok(text) => {
+                        for variable in parse_variables(&text) {
+                            let line = if variable.kind == "function" {
+                                format!("{} ()", variable.name)
+                            } else {
+                                format!("{} = {}", variable.name, variable.value)
+                            };
+                            self.push(LineKind::Output, &line);
+                        }
+                    }
+                    Err(e) => self.push(LineKind::Error, &e),
+                }
+            }
+            ":watch" => {
+                match run(Request::Watch(argument)) {
+                    Ok(text) => self.push(LineKind::Output, &text),
+                    Err(e) => self.push(LineKind::Error, &e),
+                }
+            }
+            ":unwatch" => {
+                let expression = if argument.is_empty() { None } else { Some(argument) };
+                match run(Request::Unwatch(expression)) {
+                    Ok(text) => self.push(LineKind::Output, &text),
+                    Err(e) => self.push(LineKind::Error, &e),
+                }
+            }
+            _ if line.starts_with(':') => {
+                self.push(LineKind::Error, &format!("Unknown command {}", line));
+            }
+            _ => {
+                match run(Request::Execute(line)) {
+                    Ok(text) => self.push(LineKind::Output, &text),
+                    Err(e) => self.push(LineKind::Error, &e),
+                }
+            }
+        }
+    }
+
+    pub fn rows(&self, screen: &Screen) -> usize {
+        cmp::max(1, (screen.height as i32 - 4) / LINE_HEIGHT - 1) as usize
+    }
+
+    /// Lines cut to `columns` characters, the last `rows` ones above the
+    /// scroll, then the input
+    pub fn get_lines(&self, columns: usize, rows: usize) -> Vec<(LineKind, String)> {
+        let columns = cmp::max(1, columns);
+        let mut lines = Vec::new();
+        for &(kind, ref text) in self.lines.iter() {
+            let chars: Vec<char> = text.chars().collect();
+            if chars.is_empty() {
+                lines.push((kind, String::new()));
+            }
+            for chunk in chars.chunks(columns) {
+                lines.push((kind, chunk.iter().cloned().collect()));
+            }
+        }
+
+        let end = lines.len().saturating_sub(self.scroll);
+        let start = end.saturating_sub(rows);
+        let mut lines: Vec<(LineKind, String)> = lines[start..end].to_vec();
+
+        // The end of a long input stays visible
+        let input: Vec<char> = format!("> {}_", self.input).chars().collect();
+        let skip = input.len().saturating_sub(columns);
+        lines.push((LineKind::Input, input[skip..].iter().cloned().collect()));
+        lines
+    }
+
+    pub fn draw(&self, screen: &mut Screen) {
+        if !self.enabled {
+            return;
+        }
+
+        let char_width = screen.text_width("0").max(1);
+        let columns = ((screen.width as i32 - 4) / char_width).max(1) as usize;
+        let lines = self.get_lines(columns, self.rows(screen));
+
+        let screen_width = screen.width as i32;
+        let screen_height = screen.height as i32;
+        for j in 0..screen_height {
+            for i in 0..screen_width {
+                if DITHER[(j % 4) as usize][(i % 4) as usize] < 12 {
+                    screen.putpixel_direct(i, j, self.background);
+                }
+            }
+        }
+
+        let top = screen_height - 2 - lines.len() as i32 * LINE_HEIGHT;
+        for (idx, &(kind, ref line)) in lines.iter().enumerate() {
+            let color = match kind {
+                LineKind::Input => self.input_color,
+                LineKind::Output => self.color,
+                LineKind::Error => self.error_color,
+            };
+            screen.force_print(line.clone(), 2, top + 1 + idx as i32 * LINE_HEIGHT, color as i32);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Console, LineKind, Request};
+
+    fn run(request: Request) -> Result<String, String> {
+        match request {
+            Request::Execute("x + 1") => Ok("4".to_string()),
+            Request::Execute("x = 3") => Ok("".to_string()),
+            Request::Execute(_) => Err("console:1: syntax error".to_string()),
+            Request::Globals => {
+                Ok("number\t\"x\"\tx\t3\nfunction\t\"_draw\"\t_draw\tfunction".to_string())
+            }
+            Request::Watch("player.x") => Ok("Watching player.x".to_string()),
+            Request::Watch(_) => Err("Empty expression".to_string()),
+            Request::Unwatch(Some(expression)) => Err(format!("No watch {:?}", expression)),
+            Request::Unwatch(None) => Ok("No more watches".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_console() {
+        let mut console = Console::new();
+        console.submit(":clear", &mut run);
+        assert!(console.lines.is_empty());
+
+        console.submit("x = 3", &mut run);
+        console.submit("x + 1", &mut run);
+        console.submit("x +", &mut run);
+        console.submit(":globals", &mut run);
+        assert_eq!(console.lines,
+                   vec![(LineKind::Input, "> x = 3".to_string()),
+                        (LineKind::Input, "> x + 1".to_string()),
+                        (LineKind::Output, "4".to_string()),
+                        (LineKind::Input, "> x +".to_string()),
+                        (LineKind::Error, "console:1: syntax error".to_string()),
+                        (LineKind::Input, "> :globals".to_string()),
+                        (LineKind::Output, "_draw ()".to_string()),
+                        (LineKind::Output, "x = 3".to_string())]);
+
+        // Previous lines, then the empty input
+        console.history_previous();
+        assert_eq!(console.input, ":globals");
+        console.history_previous();
+        console.history_previous();
+        assert_eq!(console.input, "x + 1");
+        console.history_next();
+        assert_eq!(console.input, "x +");
+        console.history_next();
+        console.history_next();
+        assert_eq!(console.input, "");
+
+        console.type_text("print(\"\u{7f}a long line\")");
+        console.backspace();
+        let lines = console.get_lines(8, 2);
+        assert_eq!(lines,
+                   vec![(LineKind::Output, "_draw ()".to_string()),
+                        (LineKind::Output, "x = 3".to_string()),
+                        (LineKind::Input, "g line\"_".to_string())]);
+
+        console.scroll = 3;
+        assert_eq!(console.get_lines(32, 2)[..2],
+                   [(LineKind::Input, "> x +".to_string()),
+                    (LineKind::Error, "console:1: syntax error".to_string())]);
+
+        console.submit(":clear", &mut run);
+        console.submit(":WATCH  player.x ", &mut run);
+        console.submit(":watch", &mut run);
+        console.submit(":unwatch y", &mut run);
+        console.submit(":unwatch", &mut run);
+        console.submit(":watches", &mut run);
+        assert_eq!(console.lines,
+                   vec![(LineKind::Input, "> :WATCH  player.x ".to_string()),
+                        (LineKind::Output, "Watching player.x".to_string()),
+                        (LineKind::Input, "> :watch".to_string()),
+                        (LineKind::Error, "Empty expression".to_string()),
+                        (LineKind::Input, "> :unwatch y".to_string()),
+                        (LineKind::Error, "No watch \"y\"".to_string()),
+                        (LineKind::Input, "> :unwatch".to_string()),
+                        (LineKind::Output, "No more watches".to_string()),
+                        (LineKind::Input, "> :watches".to_string()),
+                        (LineKind::Error, "Unknown command :watches".to_string())]);
+    }
+}
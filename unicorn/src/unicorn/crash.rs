@@ -0,0 +1,189 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Write;
+use std::panic;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use unicorn::{VERSION, MAJOR_VERSION, MINOR_VERSION};
+
+/// Number of log lines kept for the crash report
+pub const LOG_HISTORY: usize = 100;
+
+struct CrashContext {
+    logs: VecDeque<String>,
+    cartridge: String,
+    cartridge_uuid: String,
+    cartridge_hash: u64,
+    script_error: String,
+    /// Number of script errors, also the same one raised again
+    script_errors: u64,
+}
+
+lazy_static! {
+    static ref CRASH_CONTEXT: Mutex<CrashContext> = Mutex::new(CrashContext {
+        logs: VecDeque::with_capacity(LOG_HISTORY),
+        cartridge: "".to_string(),
+        cartridge_uuid: "".to_string(),
+        cartridge_hash: 0,
+        script_error: "".to_string(),
+        script_errors: 0,
+    });
+}
+
+/// Keep a log line for the crash report, called by the logger of the frontend
+pub fn log_line(line: &str) {
+    if let Ok(mut context) = CRASH_CONTEXT.lock() {
+        if context.logs.len() >= LOG_HISTORY {
+            context.logs.pop_front();
+        }
+        context.logs.push_back(line.to_string());
+    }
+}
+
+/// Last `count` log lines, the oldest first
+pub fn last_logs(count: usize) -> Vec<String> {
+    match CRASH_CONTEXT.lock() {
+        Ok(context) => {
+            let skip = if context.logs.len() > count { context.logs.len() - count } else { 0 };
+            context.logs.iter().skip(skip).cloned().collect()
+        }
+        Err(_) => Vec::new(),
+    }
+}
+
+pub fn set_cartridge(filename: &str, uuid: &str, hash: u64) {
+    if let Ok(mut context) = CRASH_CONTEXT.lock() {
+        context.cartridge = filename.to_string();
+        context.cartridge_uuid = uuid.to_string();
+        context.cartridge_hash = hash;
+        context.script_error.clear();
+    }
+}
+
+/// Last error (with the stack when available) raised by the Lua/Python code
+pub fn set_script_error(error: &str) {
+    if let Ok(mut context) = CRASH_CONTEXT.lock() {
+        context.script_error = error.to_string();
+        context.script_errors += 1;
+    }
+}
+
+/// Number of script errors so far and the last one
+pub fn script_error() -> (u64, String) {
+    match CRASH_CONTEXT.lock() {
+        Ok(context) => (context.script_errors, context.script_error.clone()),
+        Err(_) => (0, "".to_string()),
+    }
+}
+
+pub fn report(reason: &str) -> String {
+    let mut data = String::new();
+
+    data.push_str("# Unicorn Console crash report\n\n");
+    data.push_str(&format!("Version: {}.{}.{}\n", VERSION, MAJOR_VERSION, MINOR_VERSION));
+    data.push_str(&format!("OS: {} {}\n", ::std::env::consts::OS, ::std::env::consts::ARCH));
+    data.push_str(&format!("Reason: {}\n", reason));
+
+    // The panic could come from a thread holding the lock
+    match CRASH_CONTEXT.try_lock() {
+        Ok(context) => {
+            data.push_str(&format!("Cartridge: {}\n", context.cartridge));
+            data.push_str(&format!("Cartridge UUID: {}\n", context.cartridge_uuid));
+            data.push_str(&format!("Cartridge hash: {:016x}\n", context.cartridge_hash));
+
+            data.push_str("\n## Script error\n\n");
+            if context.script_error.is_empty() {
+                data.push_str("None\n");
+            } else {
+                data.push_str(&context.script_error);
+                data.push_str("\n");
+            }
+
+            data.push_str(&format!("\n## Last {} log lines\n\n", context.logs.len()));
+            for line in context.logs.iter() {
+                data.push_str(line);
+                data.push_str("\n");
+            }
+        }
+        Err(_) => {
+            data.push_str("Cartridge context not available\n");
+        }
+    }
+
+    data
+}
+
+/// Install a panic hook writing a report in the directory `dir`
+pub fn install(dir: &str) {
+    let dir = PathBuf::from(dir);
+
+    panic::set_hook(Box::new(move |info| {
+        let reason = match info.payload().downcast_ref::<&str>() {
+            Some(s) => s.to_string(),
+            None => {
+                match info.payload().downcast_ref::<String>() {
+                    Some(s) => s.clone(),
+                    None => "Unknown".to_string(),
+                }
+            }
+        };
+
+        let reason = match info.location() {
+            Some(location) => format!("{} ({}:{})", reason, location.file(), location.line()),
+            None => reason,
+        };
+
+        let timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs(),
+            Err(_) => 0,
+        };
+
+        let filename = dir.join(format!("crash-{}.txt", timestamp));
+        let written = match File::create(&filename) {
+            Ok(mut f) => f.write_all(report(&reason).as_bytes()).is_ok(),
+            Err(_) => false,
+        };
+
+        eprintln!("\nOops, Unicorn Console crashed: {}", reason);
+        if written {
+            eprintln!("A crash report was written in {:?}, please attach it to your bug report.",
+                      filename);
+        } else {
+            eprintln!("{}", report(&reason));
+        }
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{last_logs, log_line, report, script_error, set_cartridge, set_script_error,
+                LOG_HISTORY};
+
+    #[test]
+    fn test_report() {
+        for i in 0..LOG_HISTORY + 2 {
+            log_line(&format!("line {}", i));
+        }
+        assert_eq!(last_logs(2),
+                   vec![format!("line {}", LOG_HISTORY), format!("line {}", LOG_HISTORY + 1)]);
+        assert_eq!(last_logs(1000).len(), LOG_HISTORY);
+
+        set_cartridge("games/snake.uni", "1234-abcd", 0xdeadbeef);
+        let text = report("index out of bounds (src/gfx/mod.rs:42)");
+        assert!(text.starts_with("# Unicorn Console crash report\n\nVersion: "));
+        assert!(text.contains("\nReason: index out of bounds (src/gfx/mod.rs:42)\n"));
+        assert!(text.contains("\nCartridge: games/snake.uni\nCartridge UUID: 1234-abcd\n\
+                               Cartridge hash: 00000000deadbeef\n"));
+        assert!(text.contains("\n## Script error\n\nNone\n"));
+        assert!(text.contains(&format!("\n## Last {} log lines\n\nline 2\n", LOG_HISTORY)));
+        assert!(text.ends_with(&format!("line {}\n", LOG_HISTORY + 1)));
+
+        // The traceback of the Lua plugin is kept with the error
+        let error = "[LUA] main:3: boom\nstack traceback:\n\t[C]: in function 'error'\n\tmain:3: in function '_update'";
+        set_script_error(error);
+        assert_eq!(script_error().1, error);
+        assert!(report("Lua").contains(&format!("\n## Script error\n\n{}\n\n## Last", error)));
+    }
+}
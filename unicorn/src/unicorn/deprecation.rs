@@ -0,0 +1,110 @@
+//! Deprecated functions of the API: they keep working, and the first call of
+//! each one by a cartridge logs a warning with the line of the call and the
+//! function replacing it, shown in the log of the debug window (F8). A
+//! function is renamed by adding the new name and its old one here.
+
+use std::collections::HashSet;
+
+pub struct Deprecation {
+    pub name: &'static str,
+    pub replacement: &'static str,
+    /// Plugins where the old name exists, "lua" or "python"
+    pub plugins: &'static [&'static str],
+}
+
+pub static DEPRECATIONS: [Deprecation; 2] = [
+    Deprecation {
+        name: "noise_set_seed",
+        replacement: "noise_seed",
+        plugins: &["lua", "python"],
+    },
+    // map is a builtin of Python
+    Deprecation {
+        name: "mapdraw",
+        replacement: "map",
+        plugins: &["lua"],
+    },
+];
+
+/// Deprecated functions of a plugin
+pub fn names(plugin: &str) -> Vec<&'static str> {
+    DEPRECATIONS
+        .iter()
+        .filter(|deprecation| deprecation.plugins.iter().any(|name| *name == plugin))
+        .map(|deprecation| deprecation.name)
+        .collect()
+}
+
+/// Line of a `luaL_where` location, "chunk:line:"
+pub fn location_line(location: &str) -> Option<u32> {
+    let location = if location.ends_with(':') {
+        &location[..location.len() - 1]
+    } else {
+        location
+    };
+    location.rsplit(':').next().and_then(|line| line.parse::<u32>().ok())
+}
+
+/// Deprecated functions already called by the running cartridge
+pub struct Deprecations {
+    warned: HashSet<&'static str>,
+}
+
+impl Deprecations {
+    pub fn new() -> Deprecations {
+        Deprecations { warned: HashSet::new() }
+    }
+
+    pub fn clear(&mut self) {
+        self.warned.clear();
+    }
+
+    /// Message of the first call of a deprecated function, nothing for the
+    /// next ones and the other functions
+    pub fn warn(&mut self, name: &str, line: Option<u32>) -> Option<String> {
+        let deprecation = match DEPRECATIONS.iter().find(|deprecation| deprecation.name == name) {
+            Some(deprecation) => deprecation,
+            None => return None,
+        };
+
+        if !self.warned.insert(deprecation.name) {
+            return None;
+        }
+
+        let location = match line {
+            Some(line) => format!(" (line {})", line),
+            None => String::new(),
+        };
+        Some(format!("{}{} is deprecated, use {} instead",
+                     deprecation.name,
+                     location,
+                     deprecation.replacement))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{location_line, names, Deprecations};
+
+    #[test]
+    fn test_deprecations() {
+        let mut deprecations = Deprecations::new();
+
+        assert_eq!(deprecations.warn("noise_set_seed", Some(12)),
+                   Some("noise_set_seed (line 12) is deprecated, use noise_seed instead".to_string()));
+        // Once per cartridge
+        assert_eq!(deprecations.warn("noise_set_seed", Some(30)), None);
+        assert_eq!(deprecations.warn("mapdraw", None),
+                   Some("mapdraw is deprecated, use map instead".to_string()));
+        assert_eq!(deprecations.warn("spr", Some(1)), None);
+
+        deprecations.clear();
+        assert!(deprecations.warn("noise_set_seed", None).is_some());
+
+        assert_eq!(names("python"), vec!["noise_set_seed"]);
+        assert_eq!(names("lua"), vec!["noise_set_seed", "mapdraw"]);
+
+        assert_eq!(location_line("[string \"game.lua\"]:42:"), Some(42));
+        assert_eq!(location_line(""), None);
+    }
+}
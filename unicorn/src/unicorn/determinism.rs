@@ -0,0 +1,200 @@
+//! Determinism check of `--check-determinism`: the cartridge runs twice side
+//! by side without a window, with the same seed of `rnd` and the same input
+//! script, and the frame buffers are compared every `interval` frames. The
+//! report gives the first frame which differs, and the calls of the clock or
+//! of the random numbers of Lua seen in the runs, the usual causes.
+
+use std::time::Duration;
+
+use unicorn::{Code, Unicorn};
+use unicorn::headless::InputScript;
+
+/// Seed of `rnd` in both runs
+pub const CHECK_SEED: u64 = 0x756e69636f726e;
+
+/// Pixels which differ in a frame
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameDiff {
+    pub frame: u32,
+    pub pixels: usize,
+    /// Smallest rectangle with all of them, (x0, y0, x1, y1)
+    pub rect: (usize, usize, usize, usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeterminismReport {
+    pub frames: u32,
+    pub checks: u32,
+    pub diff: Option<FrameDiff>,
+    /// Calls which differ between two runs, with their count
+    pub suspects: Vec<(String, u32)>,
+}
+
+impl DeterminismReport {
+    pub fn is_deterministic(&self) -> bool {
+        self.diff.is_none()
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = match self.diff {
+            None => {
+                format!("Deterministic: {} frames, {} checks\n", self.frames, self.checks)
+            }
+            Some(ref diff) => {
+                format!("Not deterministic: {} pixels differ at the frame {}, in ({}, {}) - \
+                         ({}, {})\n",
+                        diff.pixels,
+                        diff.frame,
+                        diff.rect.0,
+                        diff.rect.1,
+                        diff.rect.2,
+                        diff.rect.3)
+            }
+        };
+
+        for &(ref name, count) in &self.suspects {
+            text.push_str(&format!("  {} called {} times\n", name, count));
+        }
+
+        text
+    }
+}
+
+/// The pixels of `b` which aren't the ones of `a`, frames of `width` pixels
+pub fn diff_frames(frame: u32, a: &[u32], b: &[u32], width: usize) -> Option<FrameDiff> {
+    if a.len() != b.len() || width == 0 {
+        return Some(FrameDiff {
+                        frame: frame,
+                        pixels: a.len().max(b.len()),
+                        rect: (0, 0, 0, 0),
+                    });
+    }
+
+    let mut pixels = 0;
+    let mut rect = (usize::max_value(), usize::max_value(), 0, 0);
+    for (i, (pixel_a, pixel_b)) in a.iter().zip(b.iter()).enumerate() {
+        if pixel_a != pixel_b {
+            let (x, y) = (i % width, i / width);
+            pixels += 1;
+            rect = (rect.0.min(x), rect.1.min(y), rect.2.max(x), rect.3.max(y));
+        }
+    }
+
+    if pixels == 0 {
+        None
+    } else {
+        Some(FrameDiff {
+                 frame: frame,
+                 pixels: pixels,
+                 rect: rect,
+             })
+    }
+}
+
+/// Run the cartridges loaded in `a` and `b` for `frames` frames, with the
+/// buttons of `script`. The Python cartridges share one interpreter and
+/// can't run twice side by side.
+pub fn check(a: &mut Unicorn,
+             b: &mut Unicorn,
+             frames: u32,
+             interval: u32,
+             script: &InputScript)
+             -> Result<DeterminismReport, String> {
+    let code = match a.cartridges.get_mut(a.current_cartridge) {
+        Some(cartridge) => cartridge.get_code_type(),
+        None => return Err("No cartridge".to_string()),
+    };
+    if let Code::PYTHON = code {
+        return Err("The Python cartridges can't be checked".to_string());
+    }
+
+    let fps = a.info.lock().unwrap().fps;
+    let dt = Duration::from_secs(1) / fps;
+    let interval = interval.max(1);
+
+    let mut report = DeterminismReport {
+        frames: 0,
+        checks: 0,
+        diff: None,
+        suspects: Vec::new(),
+    };
+
+    for frame in 0..frames {
+        let running_a = a.headless_frame(frame, script, dt);
+        let running_b = b.headless_frame(frame, script, dt);
+        report.frames = frame + 1;
+
+        let last = !running_a || !running_b || frame + 1 == frames;
+        if (frame + 1) % interval == 0 || last {
+            report.checks += 1;
+            let (width, frame_a) = {
+                let screen = a.screen.lock().unwrap();
+                (screen.width, screen.frame_buffer.clone())
+            };
+            let frame_b = b.screen.lock().unwrap().frame_buffer.clone();
+
+            report.diff = diff_frames(frame, &frame_a, &frame_b, width);
+            if report.diff.is_none() && running_a != running_b {
+                // One of them stopped
+                report.diff = Some(FrameDiff {
+                                       frame: frame,
+                                       pixels: 0,
+                                       rect: (0, 0, 0, 0),
+                                   });
+            }
+            if report.diff.is_some() {
+                break;
+            }
+        }
+
+        if last {
+            break;
+        }
+    }
+
+    // The counts of both runs
+    for (name, count) in a.nondeterminism_calls().into_iter().chain(b.nondeterminism_calls()) {
+        match report.suspects.iter().position(|suspect| suspect.0 == name) {
+            Some(idx) => report.suspects[idx].1 += count,
+            None => report.suspects.push((name, count)),
+        }
+    }
+    report.suspects.retain(|suspect| suspect.1 > 0);
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_frames, DeterminismReport, FrameDiff};
+
+    #[test]
+    fn test_diff_frames() {
+        let a = vec![0; 16];
+        assert_eq!(diff_frames(3, &a, &a, 4), None);
+
+        let mut b = a.clone();
+        b[5] = 1;
+        b[14] = 2;
+        let diff = diff_frames(3, &a, &b, 4).unwrap();
+        assert_eq!(diff,
+                   FrameDiff {
+                       frame: 3,
+                       pixels: 2,
+                       rect: (1, 1, 2, 3),
+                   });
+
+        // Another resolution
+        assert_eq!(diff_frames(3, &a, &[0; 4], 4).unwrap().pixels, 16);
+
+        let report = DeterminismReport {
+            frames: 60,
+            checks: 2,
+            diff: Some(diff),
+            suspects: vec![("os.time".to_string(), 4)],
+        };
+        assert!(!report.is_deterministic());
+        assert!(report.to_text().contains("frame 3"));
+        assert!(report.to_text().contains("os.time called 4 times"));
+    }
+}
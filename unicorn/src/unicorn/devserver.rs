@@ -0,0 +1,419 @@
+//! Protocol of the external editors (VS Code ...): `--dev-port <port>` listens
+//! on 127.0.0.1 only, one JSON message per line in both ways. The editor
+//! sends commands with an `id`, run by the console between two frames:
+//! ```text
+//! {"id": 1, "command": "run", "file": "games/jump.uni"}       load and run a cartridge
+//! {"id": 2, "command": "code", "code": "function _draw() ..."} replace the code of the cartridge
+//! {"id": 3, "command": "reload"}                              reload the code of the cartridge files
+//! {"id": 4, "command": "restart"}                             call _init again
+//! {"id": 5, "command": "status"}                              state, cartridge and last error
+//! {"id": 6, "command": "watch", "expression": "player.x"}     show its value each frame
+//! {"id": 7, "command": "unwatch", "expression": "player.x"}   remove it, all without expression
+//! ```
+//! `code` and `reload` keep the state of the game (hot reload), or call
+//! `_init` again with `"restart": true`. The answers have the same `id`,
+//! `{"id": 1, "ok": true, ...}` or `{"id": 1, "ok": false, "error": ...}`.
+//! The errors of the scripts are sent to all the editors when they happen:
+//! ```text
+//! {"event": "error", "message": "...", "file": "games/jump.lua", "line": 12}
+//! ```
+//! The first message gives the token of the session, printed at startup,
+//! `{"id": 0, "token": "5f0c..."}`, and could also have a command. The
+//! connection is closed after a missing or invalid token, or at the first
+//! line which isn't a JSON object, against the pages of a browser posting to
+//! the port.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{self, Map, Value};
+
+use unicorn::crash;
+use unicorn::remote::{new_token, same_token, RemoteReply};
+
+/// Longest message, a pushed code is in one line
+pub const MAX_MESSAGE_SIZE: u64 = 4 * 1024 * 1024;
+/// Seconds to write a message, an editor not reading its connection is
+/// disconnected instead of blocking the frames
+const WRITE_TIMEOUT: u64 = 2;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DevCommand {
+    Status,
+    Run(String),
+    Code { code: String, restart: bool },
+    Reload { restart: bool },
+    Restart,
+    Watch(String),
+    Unwatch(Option<String>),
+}
+
+pub struct DevRequest {
+    pub id: Value,
+    pub command: DevCommand,
+    client: TcpStream,
+}
+
+impl DevRequest {
+    pub fn answer(&self, reply: RemoteReply) {
+        let mut fields = match reply {
+            Ok(mut fields) => {
+                fields.insert("ok".to_string(), Value::Bool(true));
+                fields
+            }
+            Err(e) => {
+                let mut fields = Map::new();
+                fields.insert("ok".to_string(), Value::Bool(false));
+                fields.insert("error".to_string(), Value::String(e));
+                fields
+            }
+        };
+        fields.insert("id".to_string(), self.id.clone());
+
+        // The editor could be gone
+        write_message(&self.client, fields);
+    }
+}
+
+pub struct DevServer {
+    pub port: u16,
+    /// Secret of the session, in the first message of the editors
+    pub token: String,
+    requests: mpsc::Receiver<DevRequest>,
+    /// Connections of the editors, for the events
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+    /// Number of script errors seen
+    errors: u64,
+    /// Last error sent, an error raised each frame is sent once
+    reported: String,
+}
+
+impl DevServer {
+    /// Listen on 127.0.0.1:`port`, each editor is read by a thread
+    pub fn start(port: u16) -> Result<DevServer, String> {
+        let listener = match TcpListener::bind(("127.0.0.1", port)) {
+            Ok(listener) => listener,
+            Err(e) => return Err(format!("Failed to listen on the port {:?}: {}", port, e)),
+        };
+
+        info!("[Unicorn][DEV] Listening on 127.0.0.1:{:?}", port);
+
+        let token = new_token();
+
+        let (sender, requests) = mpsc::channel();
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let clients_listener = clients.clone();
+        let server_token = token.clone();
+        thread::spawn(move || for stream in listener.incoming() {
+                          match stream {
+                              Ok(stream) => {
+                                  connect(stream, &server_token, &sender, &clients_listener)
+                              }
+                              Err(e) => error!("[Unicorn][DEV] Failed connection: {}", e),
+                          }
+                      });
+
+        Ok(DevServer {
+               port: port,
+               token: token,
+               requests: requests,
+               clients: clients,
+               errors: crash::script_error().0,
+               reported: "".to_string(),
+           })
+    }
+
+    /// Requests received since the last frame
+    pub fn poll(&mut self) -> Vec<DevRequest> {
+        let mut requests = Vec::new();
+        while let Ok(request) = self.requests.try_recv() {
+            requests.push(request);
+        }
+        requests
+    }
+
+    /// Send the event to all the editors, the closed connections are removed
+    pub fn send_event(&self, name: &str, mut fields: Map<String, Value>) {
+        fields.insert("event".to_string(), Value::from(name));
+
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.retain(|client| write_message(client, fields.clone()));
+        }
+    }
+
+    /// Script error raised since the last call, if it isn't the last one sent
+    pub fn new_error(&mut self) -> Option<String> {
+        let (errors, message) = crash::script_error();
+        if errors == self.errors {
+            return None;
+        }
+        self.errors = errors;
+
+        if message == self.reported {
+            return None;
+        }
+        self.reported = message.clone();
+        Some(message)
+    }
+
+    /// The code changed, its errors are sent again
+    pub fn forget_error(&mut self) {
+        self.reported.clear();
+    }
+}
+
+/// Line of the code in the error of a script: `[string "..."]:12: ...` for
+/// Lua, `line 12` for Python
+pub fn error_location(message: &str) -> Option<u32> {
+    let mut rest = message;
+    while let Some(idx) = rest.find("\"]:") {
+        let chunk_start = match rest[..idx].rfind("[string \"") {
+            Some(start) => start + 9,
+            None => 0,
+        };
+        let chunk = &rest[chunk_start..idx];
+        let after = &rest[idx + 3..];
+
+        // The calls of the console, like "_draw()", aren't in the code
+        if !(chunk.starts_with('_') && chunk.ends_with("()")) {
+            let digits: String = after.chars().take_while(|c| c.is_digit(10)).collect();
+            if let Ok(line) = digits.parse::<u32>() {
+                return Some(line);
+            }
+        }
+        rest = after;
+    }
+
+    if let Some(idx) = message.find("line ") {
+        let digits: String = message[idx + 5..]
+            .chars()
+            .take_while(|c| c.is_digit(10))
+            .collect();
+        return digits.parse::<u32>().ok();
+    }
+
+    None
+}
+
+/// Command of a message of an editor
+pub fn parse_message(message: &Value) -> Result<DevCommand, String> {
+    let field = |name: &str| -> Result<String, String> {
+        match message.get(name).and_then(|value| value.as_str()) {
+            Some(value) => Ok(value.to_string()),
+            None => Err(format!("Missing field {:?}", name)),
+        }
+    };
+    let restart = message
+        .get("restart")
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false);
+
+    let command = try!(field("command"));
+    match command.as_str() {
+        "status" => Ok(DevCommand::Status),
+        "run" => Ok(DevCommand::Run(try!(field("file")))),
+        "code" => {
+            Ok(DevCommand::Code {
+                   code: try!(field("code")),
+                   restart: restart,
+               })
+        }
+        "reload" => Ok(DevCommand::Reload { restart: restart }),
+        "restart" => Ok(DevCommand::Restart),
+        "watch" => Ok(DevCommand::Watch(try!(field("expression")))),
+        "unwatch" => {
+            Ok(DevCommand::Unwatch(message
+                                       .get("expression")
+                                       .and_then(|value| value.as_str())
+                                       .map(|value| value.to_string())))
+        }
+        _ => Err(format!("Unknown command {:?}", command)),
+    }
+}
+
+/// Token of the first message of an editor
+pub fn check_token(message: &Value, token: &str) -> Result<(), String> {
+    match message.get("token").and_then(|value| value.as_str()) {
+        Some(value) if same_token(value, token) => Ok(()),
+        _ => Err("Missing or invalid token".to_string()),
+    }
+}
+
+/// One line of JSON, false if the connection is closed. The connection is
+/// closed after a message written in part, its next lines would be invalid.
+fn write_message(stream: &TcpStream, fields: Map<String, Value>) -> bool {
+    let mut data = serde_json::to_string(&Value::Object(fields)).unwrap_or("{}".to_string());
+    data.push('\n');
+
+    let mut writer = stream;
+    if let Err(e) = writer.write_all(data.as_bytes()) {
+        error!("[Unicorn][DEV] Failed to write to the editor, closing the connection: {}", e);
+        let _ = stream.shutdown(Shutdown::Both);
+        return false;
+    }
+    true
+}
+
+fn connect(stream: TcpStream,
+           token: &str,
+           sender: &mpsc::Sender<DevRequest>,
+           clients: &Arc<Mutex<Vec<TcpStream>>>) {
+    info!("[Unicorn][DEV] Connection of {:?}", stream.peer_addr());
+
+    // Shared by the clones of the connection
+    if let Err(e) = stream.set_write_timeout(Some(Duration::from_secs(WRITE_TIMEOUT))) {
+        error!("[Unicorn][DEV] Failed connection: {}", e);
+        return;
+    }
+
+    let token = token.to_string();
+    let sender = sender.clone();
+    let clients = clients.clone();
+    thread::spawn(move || serve(stream, &token, sender, clients));
+}
+
+fn serve(stream: TcpStream,
+         token: &str,
+         sender: mpsc::Sender<DevRequest>,
+         clients: Arc<Mutex<Vec<TcpStream>>>) {
+    let mut reader = BufReader::new(&stream);
+    let mut authenticated = false;
+
+    loop {
+        let mut line = String::new();
+        match (&mut reader).take(MAX_MESSAGE_SIZE).read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => (),
+        }
+        if !line.ends_with('\n') {
+            error!("[Unicorn][DEV] Message of more than {:?} bytes", MAX_MESSAGE_SIZE);
+            break;
+        }
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let message: Value = match serde_json::from_str(line) {
+            Ok(message) => message,
+            Err(_) => Value::Null,
+        };
+        if !message.is_object() {
+            error!("[Unicorn][DEV] Invalid message, closing the connection");
+            break;
+        }
+
+        let client = match stream.try_clone() {
+            Ok(client) => client,
+            Err(_) => break,
+        };
+        let request = DevRequest {
+            id: message.get("id").cloned().unwrap_or(Value::Null),
+            command: DevCommand::Status,
+            client: client,
+        };
+
+        if !authenticated {
+            if let Err(e) = check_token(&message, token) {
+                error!("[Unicorn][DEV] {}, closing the connection", e);
+                request.answer(Err(e));
+                break;
+            }
+            authenticated = true;
+
+            // The events are only sent to the editors with the token
+            match stream.try_clone() {
+                Ok(client) => clients.lock().unwrap().push(client),
+                Err(_) => break,
+            }
+            if message.get("command").is_none() {
+                request.answer(Ok(Map::new()));
+                continue;
+            }
+        }
+
+        match parse_message(&message) {
+            Ok(command) => {
+                let request = DevRequest { command: command, ..request };
+                if sender.send(request).is_err() {
+                    break;
+                }
+            }
+            Err(e) => request.answer(Err(e)),
+        }
+    }
+
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{TcpListener, TcpStream};
+    use std::time::Duration;
+
+    use serde_json::{self, Map, Value};
+
+    use super::{check_token, error_location, parse_message, write_message, DevCommand};
+
+    #[test]
+    fn test_error_location() {
+        assert_eq!(error_location("[LUA] [string \"function _init()...\"]:12: attempt to \
+                                   index a nil value"),
+                   Some(12));
+        // The error of the call, then the location in the code
+        assert_eq!(error_location("[LUA] [string \"_update()\"]:1: [string \"x = 1...\"]:7: \
+                                   boom"),
+                   Some(7));
+        assert_eq!(error_location("[LUA] [string \"_draw()\"]:1: attempt to call a nil \
+                                   value (global '_draw')"),
+                   None);
+        assert_eq!(error_location("[PYTHON] File \"<string>\", line 3, in _draw"),
+                   Some(3));
+        assert_eq!(error_location("[LUA] not enough memory"), None);
+    }
+
+    #[test]
+    fn test_parse_message() {
+        let message = |text: &str| parse_message(&serde_json::from_str(text).unwrap());
+
+        assert_eq!(message(r#"{"id": 1, "command": "watch", "expression": "player.x"}"#),
+                   Ok(DevCommand::Watch("player.x".to_string())));
+        assert!(message(r#"{"id": 1, "command": "watch"}"#).is_err());
+        assert_eq!(message(r#"{"command": "unwatch", "expression": "player.x"}"#),
+                   Ok(DevCommand::Unwatch(Some("player.x".to_string()))));
+        assert_eq!(message(r#"{"command": "unwatch"}"#), Ok(DevCommand::Unwatch(None)));
+        assert_eq!(message(r#"{"command": "reload", "restart": true}"#),
+                   Ok(DevCommand::Reload { restart: true }));
+        assert!(message(r#"{"command": "eval"}"#).is_err());
+    }
+
+    #[test]
+    fn test_check_token() {
+        let token = |text: &str| check_token(&serde_json::from_str(text).unwrap(), "secret");
+
+        assert_eq!(token(r#"{"id": 0, "token": "secret"}"#), Ok(()));
+        assert_eq!(token(r#"{"command": "status", "token": "secret"}"#), Ok(()));
+        assert!(token(r#"{"id": 0, "token": "guess"}"#).is_err());
+        assert!(token(r#"{"id": 0, "token": 1}"#).is_err());
+        assert!(token(r#"{"command": "run", "file": "cart.uni"}"#).is_err());
+    }
+
+    #[test]
+    fn test_write_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_write_timeout(Some(Duration::from_millis(100))).unwrap();
+
+        // The editor doesn't read its connection, it is closed
+        let mut fields = Map::new();
+        fields.insert("code".to_string(), Value::String("x".repeat(64 * 1024 * 1024)));
+        assert!(!write_message(&stream, fields));
+        assert!(!write_message(&stream, Map::new()));
+        drop(client);
+    }
+}
@@ -0,0 +1,253 @@
+//! Size of the window and filter of the screen, changed at run-time in the
+//! "Config" entry of the pause menu or by the cartridges with
+//! `extcmd("scale", n)`/`extcmd("fullscreen")`/`set_filter(name)`, and the
+//! reduced flashing of the accessibility. The choice is saved in
+//! <profile>/display.json, with the frame limiter read when the window is
+//! created:
+//! ```text
+//! {"scale": 3, "fullscreen": false, "filter": "scanlines", "reduce_motion": false,
+//!  "frame_limiter": "vsync"}
+//! ```
+
+use std::fs::File;
+use std::io::{Read, Write};
+
+use serde_json;
+
+use gfx::Scale;
+
+pub const MIN_SCALE: usize = 1;
+pub const MAX_SCALE: usize = 8;
+
+/// Post-processing of the screen by the renderer: darker lines between the
+/// rows, the scanlines and darker corners of a CRT, the grid of a LCD, or a
+/// smooth scaling instead of the nearest pixels
+pub const FILTERS: [&'static str; 5] = ["none", "scanlines", "crt", "lcd", "smooth"];
+
+/// Pacing of the frames: the vsync of the display, or the sleep of the
+/// frontend (`--frame-limiter` replaces it)
+pub const FRAME_LIMITERS: [&'static str; 2] = ["vsync", "sleep"];
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct DisplayConfig {
+    /// Pixels of the window for each pixel of the screen
+    pub scale: usize,
+    pub fullscreen: bool,
+    /// One of `FILTERS`
+    pub filter: String,
+    /// Fewer full-screen flashes, `stat("reduce_motion")` for the cartridges
+    pub reduce_motion: bool,
+    /// One of `FRAME_LIMITERS`
+    pub frame_limiter: String,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> DisplayConfig {
+        DisplayConfig {
+            scale: 1,
+            fullscreen: false,
+            filter: FILTERS[0].to_string(),
+            reduce_motion: false,
+            frame_limiter: FRAME_LIMITERS[0].to_string(),
+        }
+    }
+}
+
+impl DisplayConfig {
+    pub fn new(scale: Scale, fullscreen: bool) -> DisplayConfig {
+        DisplayConfig {
+            scale: scale.factor(),
+            fullscreen: fullscreen,
+            filter: FILTERS[0].to_string(),
+            reduce_motion: false,
+            frame_limiter: FRAME_LIMITERS[0].to_string(),
+        }
+    }
+
+    pub fn from_str(data: &str) -> Result<DisplayConfig, String> {
+        match serde_json::from_str::<DisplayConfig>(data) {
+            Ok(config) => Ok(config.clamp()),
+            Err(e) => Err(format!("Invalid display config: {}", e)),
+        }
+    }
+
+    pub fn from_file(filename: &str) -> Result<DisplayConfig, String> {
+        let mut data = String::new();
+        match File::open(filename) {
+            Ok(mut f) => {
+                if let Err(e) = f.read_to_string(&mut data) {
+                    return Err(format!("Failed to read {:?}: {}", filename, e));
+                }
+            }
+            Err(e) => return Err(format!("Failed to open {:?}: {}", filename, e)),
+        }
+
+        DisplayConfig::from_str(&data)
+    }
+
+    pub fn save(&self, filename: &str) -> Result<(), String> {
+        let data = match serde_json::to_string(self) {
+            Ok(data) => data,
+            Err(e) => return Err(format!("Failed to serialize the display config: {}", e)),
+        };
+
+        match File::create(filename) {
+            Ok(mut f) => {
+                match f.write_all(data.as_bytes()) {
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(format!("Failed to write {:?}: {}", filename, e)),
+                }
+            }
+            Err(e) => Err(format!("Failed to create {:?}: {}", filename, e)),
+        }
+    }
+
+    pub fn clamp(mut self) -> DisplayConfig {
+        self.scale = self.scale.max(MIN_SCALE).min(MAX_SCALE);
+        if !FILTERS.contains(&self.filter.as_str()) {
+            self.filter = FILTERS[0].to_string();
+        }
+        if !FRAME_LIMITERS.contains(&self.frame_limiter.as_str()) {
+            self.frame_limiter = FRAME_LIMITERS[0].to_string();
+        }
+        self
+    }
+
+    /// Same size of the window, only the filter is different
+    pub fn same_window(&self, other: &DisplayConfig) -> bool {
+        self.scale == other.scale && self.fullscreen == other.fullscreen
+    }
+
+    /// Next filter of `FILTERS`, `offset` is 1 or -1
+    pub fn cycle_filter(&self, offset: isize) -> DisplayConfig {
+        let idx = FILTERS
+            .iter()
+            .position(|filter| *filter == self.filter)
+            .unwrap_or(0) as isize;
+        let len = FILTERS.len() as isize;
+
+        let mut config = self.clone();
+        config.filter = FILTERS[(((idx + offset) % len + len) % len) as usize].to_string();
+        config
+    }
+
+    pub fn get_scale(&self) -> Scale {
+        Scale::from_factor(self.scale).unwrap_or(Scale::Scale1x)
+    }
+
+    /// Bigger window, then the fullscreen after the biggest one
+    pub fn next(&self) -> DisplayConfig {
+        if self.fullscreen {
+            return self.clone();
+        }
+
+        if self.scale >= MAX_SCALE {
+            DisplayConfig {
+                fullscreen: true,
+                ..self.clone()
+            }
+        } else {
+            DisplayConfig {
+                scale: self.scale + 1,
+                fullscreen: false,
+                ..self.clone()
+            }
+        }
+    }
+
+    /// Smaller window, the fullscreen goes back to the biggest one
+    pub fn previous(&self) -> DisplayConfig {
+        if self.fullscreen {
+            return DisplayConfig {
+                fullscreen: false,
+                ..self.clone()
+            };
+        }
+
+        DisplayConfig {
+            scale: (self.scale.min(MAX_SCALE + 1) - 1).max(MIN_SCALE),
+            fullscreen: false,
+            ..self.clone()
+        }
+    }
+
+    pub fn toggle_reduce_motion(&self) -> DisplayConfig {
+        DisplayConfig {
+            reduce_motion: !self.reduce_motion,
+            ..self.clone()
+        }
+    }
+
+    /// Label of the "Config" entry of the pause menu
+    pub fn label(&self) -> String {
+        if self.fullscreen {
+            "Fullscreen".to_string()
+        } else {
+            format!("Window {}x", self.scale)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DisplayConfig, FILTERS, MAX_SCALE};
+
+    #[test]
+    fn test_display_config() {
+        let config = DisplayConfig::from_str(r#"{"scale": 12}"#).unwrap();
+        assert_eq!(config.scale, MAX_SCALE);
+        assert!(!config.fullscreen);
+        assert!(DisplayConfig::from_str("[").is_err());
+        assert_eq!(DisplayConfig::from_str(r#"{"scale": 0}"#).unwrap(),
+                   DisplayConfig::default());
+        assert_eq!(config.frame_limiter, "vsync");
+
+        let config = DisplayConfig::from_str(r#"{"frame_limiter": "sleep"}"#).unwrap();
+        assert_eq!(config.frame_limiter, "sleep");
+        let config = DisplayConfig::from_str(r#"{"frame_limiter": "busy"}"#).unwrap();
+        assert_eq!(config.frame_limiter, "vsync");
+    }
+
+    #[test]
+    fn test_display_next_previous() {
+        let mut config = DisplayConfig::default();
+        assert_eq!(config.previous(), config);
+
+        for _ in 0..MAX_SCALE + 2 {
+            config = config.next();
+        }
+        assert!(config.fullscreen);
+        assert_eq!(config.label(), "Fullscreen");
+
+        config = config.previous();
+        assert_eq!(config.label(), "Window 8x");
+        config = config.previous();
+        assert_eq!(config.scale, 7);
+    }
+
+    #[test]
+    fn test_display_filter() {
+        let config = DisplayConfig::from_str(r#"{"scale": 2, "filter": "crt"}"#).unwrap();
+        assert_eq!(config.filter, "crt");
+        assert_eq!(DisplayConfig::from_str(r#"{"filter": "blur"}"#).unwrap().filter, "none");
+
+        let next = config.cycle_filter(1);
+        assert_eq!(next.filter, "lcd");
+        assert!(next.same_window(&config));
+        assert_eq!(config.cycle_filter(-3).filter, FILTERS[4]);
+        // The size keeps the filter
+        assert_eq!(config.next().filter, "crt");
+    }
+
+    #[test]
+    fn test_display_reduce_motion() {
+        assert!(!DisplayConfig::default().reduce_motion);
+
+        let config = DisplayConfig::from_str(r#"{"scale": 2, "reduce_motion": true}"#).unwrap();
+        assert!(config.reduce_motion);
+        assert!(config.next().reduce_motion);
+        assert!(!config.toggle_reduce_motion().reduce_motion);
+        assert!(config.toggle_reduce_motion().same_window(&config));
+    }
+}
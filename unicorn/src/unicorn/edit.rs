@@ -11,6 +11,7 @@ pub mod edit {
 
     use gfx::Screen;
     use config::Players;
+    use config::keybindings::EditorKeyBindings;
     use sound::sound::{SoundInternal, Sound};
     use unicorn::{UnicornCartridge, UnicornConfig, Palettes};
 
@@ -20,6 +21,7 @@ pub mod edit {
         pub mouse_y: i32,
         pub mouse_state: u32,
         pub mouse_statep: u32,
+        pub shift: bool,
 
         pub idx_sprites_batch_x: i32,
         pub idx_sprites_batch_y: i32,
@@ -52,6 +54,7 @@ pub mod edit {
                 mouse_y: 0,
                 mouse_state: 0,
                 mouse_statep: 0,
+                shift: false,
 
                 idx_sprites_batch_x: 0,
                 idx_sprites_batch_y: 170,
@@ -82,6 +85,7 @@ pub mod edit {
         pub fn update(&mut self, players: Arc<Mutex<Players>>) {
             self.mouse_state = players.lock().unwrap().mouse_state();
             self.mouse_statep = players.lock().unwrap().mouse_state_quick();
+            self.shift = players.lock().unwrap().is_shift_down();
             self.mouse_x = players.lock().unwrap().mouse_coordinate(0);
             self.mouse_y = players.lock().unwrap().mouse_coordinate(1);
         }
@@ -103,11 +107,13 @@ pub mod edit {
         music: music_editor::MusicEditor,
         filename: String,
         widgets: Vec<Arc<Mutex<Widget>>>,
+        keybindings: Arc<Mutex<EditorKeyBindings>>,
     }
 
     impl Editor {
         pub fn new(screen: Arc<Mutex<Screen>>) -> Editor {
             let state = Arc::new(Mutex::new(State::new()));
+            let keybindings = Arc::new(Mutex::new(EditorKeyBindings::new()));
             let mut highlight = HashMap::new();
             highlight.insert(6, 10);
 
@@ -186,11 +192,20 @@ pub mod edit {
             Editor {
                 state: state.clone(),
                 state_editor: STATE::GfxEditor,
-                gfx: gfx_editor::GFXEditor::new(state.clone()),
-                txt: text_editor::TextEditor::new(state.clone()),
+                gfx: gfx_editor::GFXEditor::new(state.clone(), keybindings.clone()),
+                txt: text_editor::TextEditor::new(state.clone(), keybindings.clone()),
                 music: music_editor::MusicEditor::new(state.clone()),
                 filename: "".to_string(),
                 widgets: widgets,
+                keybindings: keybindings,
+            }
+        }
+
+        pub fn load_keybindings(&mut self, filename: &str) {
+            info!("[EDITOR] Load keybindings {:?}", filename);
+
+            if let Err(e) = self.keybindings.lock().unwrap().load(filename) {
+                error!("[EDITOR] Failed to load the keybindings: {}", e);
             }
         }
 
@@ -311,5 +326,7 @@ pub mod edit {
         pub fn new(screen: Arc<Mutex<Screen>>) -> Editor {
             Editor {}
         }
+
+        pub fn load_keybindings(&mut self, filename: &str) {}
     }
 }
\ No newline at end of file
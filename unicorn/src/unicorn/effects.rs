@@ -0,0 +1,195 @@
+//! Screen effects of the cartridges, applied to the frame buffer after
+//! `_draw`: `fade(t, col)` dithers the screen to a color, `flash(col, frames)`
+//! fills it for a few frames and `shake(intensity, frames)` moves it around.
+//! The frame of the cartridge is drawn back before the next `_draw`, for the
+//! cartridges which don't clear the screen. With the `reduce_motion` setting
+//! the screen doesn't shake and the flashes are half dithered.
+
+/// Thresholds of the ordered dithering of the fade, in 16th
+const BAYER: [[u32; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Longest flash or shake, 10 seconds at 60 FPS
+pub const MAX_EFFECT_FRAMES: u32 = 600;
+/// Pixels of the strongest shake
+pub const MAX_SHAKE: f64 = 32.0;
+
+pub struct Effects {
+    /// Part of the pixels (0 to 1) in the fade color, until `fade(0)`
+    fade: f64,
+    fade_color: u32,
+    flash_color: u32,
+    flash_frames: u32,
+    shake_intensity: f64,
+    shake_frames: u32,
+    shake_total: u32,
+    /// Random offsets of the shake, the same ones for each run
+    seed: u32,
+    /// Frame of the cartridge before the effects
+    frame: Vec<u32>,
+}
+
+impl Effects {
+    pub fn new() -> Effects {
+        Effects {
+            fade: 0.0,
+            fade_color: 0,
+            flash_color: 0,
+            flash_frames: 0,
+            shake_intensity: 0.0,
+            shake_frames: 0,
+            shake_total: 0,
+            seed: 0x2545f491,
+            frame: Vec::new(),
+        }
+    }
+
+    /// No effect, at the start of a cartridge
+    pub fn reset(&mut self) {
+        *self = Effects::new();
+    }
+
+    pub fn fade(&mut self, t: f64, col: u32) {
+        self.fade = t.max(0.0).min(1.0);
+        self.fade_color = col;
+    }
+
+    pub fn flash(&mut self, col: u32, frames: u32) {
+        self.flash_color = col;
+        self.flash_frames = frames.min(MAX_EFFECT_FRAMES);
+    }
+
+    /// Offsets up to `intensity` pixels, decreasing until the end
+    pub fn shake(&mut self, intensity: f64, frames: u32) {
+        self.shake_intensity = intensity.max(0.0).min(MAX_SHAKE);
+        self.shake_frames = frames.min(MAX_EFFECT_FRAMES);
+        self.shake_total = self.shake_frames;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.fade > 0.0 || self.flash_frames > 0 || self.shake_frames > 0
+    }
+
+    fn random(&mut self) -> u32 {
+        // xorshift32
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed
+    }
+
+    /// Offset of -`max` to `max` pixels
+    fn random_offset(&mut self, max: i32) -> i32 {
+        (self.random() % (2 * max as u32 + 1)) as i32 - max
+    }
+
+    /// Apply the effects of this frame, the frame of the cartridge is kept
+    pub fn apply(&mut self, frame: &mut [u32], width: usize, reduce_motion: bool) {
+        if !self.is_active() || width == 0 {
+            return;
+        }
+
+        self.frame.clear();
+        self.frame.extend_from_slice(frame);
+
+        if self.shake_frames > 0 {
+            let max = (self.shake_intensity * self.shake_frames as f64 /
+                       self.shake_total as f64)
+                    .round() as i32;
+            let dx = self.random_offset(max);
+            let dy = self.random_offset(max);
+            self.shake_frames -= 1;
+
+            if !reduce_motion && (dx != 0 || dy != 0) {
+                let height = frame.len() / width;
+                for y in 0..height {
+                    for x in 0..width {
+                        let (src_x, src_y) = (x as i32 - dx, y as i32 - dy);
+                        let inside = src_x >= 0 && src_x < width as i32 && src_y >= 0 &&
+                                     src_y < height as i32;
+                        frame[x + y * width] = if inside {
+                            self.frame[src_x as usize + src_y as usize * width]
+                        } else {
+                            0
+                        };
+                    }
+                }
+            }
+        }
+
+        if self.fade > 0.0 {
+            dither(frame, width, self.fade, self.fade_color);
+        }
+
+        if self.flash_frames > 0 {
+            let amount = if reduce_motion { 0.5 } else { 1.0 };
+            dither(frame, width, amount, self.flash_color);
+            self.flash_frames -= 1;
+        }
+    }
+
+    /// Draw back the frame of the cartridge, before its next `_draw`
+    pub fn restore(&mut self, frame: &mut [u32]) {
+        if self.frame.len() == frame.len() {
+            frame.copy_from_slice(&self.frame);
+        }
+        self.frame.clear();
+    }
+}
+
+/// `amount` (0 to 1) of the pixels in the color `col`
+fn dither(frame: &mut [u32], width: usize, amount: f64, col: u32) {
+    let level = (amount * 16.0).round() as u32;
+    for (i, pixel) in frame.iter_mut().enumerate() {
+        if BAYER[(i / width) % 4][(i % width) % 4] < level {
+            *pixel = col;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Effects;
+
+    #[test]
+    fn test_effects() {
+        let mut effects = Effects::new();
+        let mut frame = vec![1; 16];
+        effects.apply(&mut frame, 4, false);
+        assert_eq!(frame, vec![1; 16]);
+
+        effects.fade(0.5, 0);
+        effects.apply(&mut frame, 4, false);
+        assert_eq!(frame.iter().filter(|c| **c == 0).count(), 8);
+        effects.restore(&mut frame);
+        assert_eq!(frame, vec![1; 16]);
+
+        effects.fade(0.0, 0);
+        effects.flash(7, 2);
+        effects.apply(&mut frame, 4, false);
+        assert_eq!(frame, vec![7; 16]);
+        effects.restore(&mut frame);
+        effects.apply(&mut frame, 4, true);
+        assert_eq!(frame.iter().filter(|c| **c == 7).count(), 8);
+        effects.restore(&mut frame);
+        assert!(!effects.is_active());
+
+        // The pixels moved in are black, none with reduce_motion
+        frame[5] = 2;
+        effects.shake(2.0, 3);
+        let mut moved = false;
+        for _ in 0..3 {
+            effects.apply(&mut frame, 4, false);
+            moved |= frame[5] != 2;
+            effects.restore(&mut frame);
+        }
+        assert!(moved);
+        assert!(!effects.is_active());
+
+        effects.shake(2.0, 3);
+        effects.apply(&mut frame, 4, true);
+        assert_eq!(frame[5], 2);
+
+        effects.reset();
+        assert!(!effects.is_active());
+    }
+}
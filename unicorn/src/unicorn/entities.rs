@@ -0,0 +1,172 @@
+//! Registry of the entities of a cartridge (`ent_new`, `ent_each` ...): the
+//! tags and the layers are indexed here, the script keeps the data of the
+//! entities and only iterates over the ids returned, by layer then by age.
+
+use std::collections::HashMap;
+
+struct Entity {
+    tags: Vec<String>,
+    layer: i32,
+}
+
+pub struct Entities {
+    next_id: u32,
+    entities: HashMap<u32, Entity>,
+    /// Entities of each tag, and of "" for all of them
+    tags: HashMap<String, Vec<u32>>,
+    /// The lists of the tags must be sorted again
+    dirty: bool,
+}
+
+/// Tags separated by spaces or commas: "enemy, solid"
+pub fn parse_tags(tags: &str) -> Vec<String> {
+    let mut values: Vec<String> = Vec::new();
+    for tag in tags.split(|c: char| c.is_whitespace() || c == ',') {
+        if !tag.is_empty() && !values.iter().any(|value| value == tag) {
+            values.push(tag.to_string());
+        }
+    }
+    values
+}
+
+impl Entities {
+    pub fn new() -> Entities {
+        Entities {
+            next_id: 1,
+            entities: HashMap::new(),
+            tags: HashMap::new(),
+            dirty: false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entities.clear();
+        self.tags.clear();
+        self.dirty = false;
+    }
+
+    pub fn add(&mut self, tags: &str, layer: i32) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+
+        self.entities.insert(id,
+                             Entity {
+                                 tags: Vec::new(),
+                                 layer: layer,
+                             });
+        self.tags.entry(String::new()).or_insert_with(Vec::new).push(id);
+        for tag in parse_tags(tags) {
+            self.tag(id, &tag, true);
+        }
+
+        self.dirty = true;
+        id
+    }
+
+    pub fn remove(&mut self, id: u32) {
+        if let Some(entity) = self.entities.remove(&id) {
+            for tag in entity.tags.iter() {
+                if let Some(ids) = self.tags.get_mut(tag) {
+                    ids.retain(|value| *value != id);
+                }
+            }
+            if let Some(ids) = self.tags.get_mut("") {
+                ids.retain(|value| *value != id);
+            }
+        }
+    }
+
+    pub fn tag(&mut self, id: u32, tag: &str, value: bool) {
+        if tag.is_empty() {
+            return;
+        }
+
+        let entity = match self.entities.get_mut(&id) {
+            Some(entity) => entity,
+            None => return,
+        };
+
+        let has_tag = entity.tags.iter().any(|t| t == tag);
+        if value && !has_tag {
+            entity.tags.push(tag.to_string());
+            self.tags.entry(tag.to_string()).or_insert_with(Vec::new).push(id);
+            self.dirty = true;
+        } else if !value && has_tag {
+            entity.tags.retain(|t| t != tag);
+            if let Some(ids) = self.tags.get_mut(tag) {
+                ids.retain(|value| *value != id);
+            }
+        }
+    }
+
+    pub fn has_tag(&self, id: u32, tag: &str) -> bool {
+        match self.entities.get(&id) {
+            Some(entity) => tag.is_empty() || entity.tags.iter().any(|t| t == tag),
+            None => false,
+        }
+    }
+
+    pub fn set_layer(&mut self, id: u32, layer: i32) {
+        if let Some(entity) = self.entities.get_mut(&id) {
+            if entity.layer != layer {
+                entity.layer = layer;
+                self.dirty = true;
+            }
+        }
+    }
+
+    pub fn count(&self, tag: &str) -> usize {
+        self.tags.get(tag).map(|ids| ids.len()).unwrap_or(0)
+    }
+
+    /// Entities of the tag (all of them with ""), by layer then by age
+    pub fn ids(&mut self, tag: &str) -> Vec<u32> {
+        if self.dirty {
+            let entities = &self.entities;
+            for ids in self.tags.values_mut() {
+                ids.sort_by_key(|id| (entities.get(id).map(|e| e.layer).unwrap_or(0), *id));
+            }
+            self.dirty = false;
+        }
+
+        self.tags.get(tag).cloned().unwrap_or(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_tags, Entities};
+
+    #[test]
+    fn test_parse_tags() {
+        assert_eq!(parse_tags("enemy, solid  enemy"), vec!["enemy", "solid"]);
+        assert!(parse_tags("").is_empty());
+    }
+
+    #[test]
+    fn test_entities() {
+        let mut entities = Entities::new();
+        let player = entities.add("player solid", 1);
+        let enemy = entities.add("enemy solid", 0);
+        let bullet = entities.add("bullet", 2);
+
+        assert_eq!(entities.ids(""), vec![enemy, player, bullet]);
+        assert_eq!(entities.ids("solid"), vec![enemy, player]);
+        assert_eq!(entities.count("solid"), 2);
+        assert!(entities.ids("boss").is_empty());
+
+        entities.set_layer(enemy, 3);
+        assert_eq!(entities.ids(""), vec![player, bullet, enemy]);
+
+        entities.tag(bullet, "solid", true);
+        entities.tag(player, "solid", false);
+        assert_eq!(entities.ids("solid"), vec![bullet, enemy]);
+        assert!(entities.has_tag(bullet, "solid"));
+        assert!(!entities.has_tag(player, "solid"));
+
+        entities.remove(enemy);
+        assert_eq!(entities.ids(""), vec![player, bullet]);
+        assert_eq!(entities.count("enemy"), 0);
+        assert!(!entities.has_tag(enemy, ""));
+    }
+}
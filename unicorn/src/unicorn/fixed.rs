@@ -0,0 +1,239 @@
+//! Fixed-point numbers of the `fx_*` functions and of the physics: 16 bits
+//! of integer and 16 bits of fraction in an i32, like the numbers of PICO-8.
+//! The operations only use integers, so a lockstep network game or a replay
+//! gets the same numbers on every machine, whatever the floats of the
+//! scripts. The operations wrap around on overflow and a division by 0 gives
+//! the largest number with the sign of the dividend.
+
+use std::cmp::Ordering;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Fixed(pub i32);
+
+pub const FRAC_BITS: u32 = 16;
+pub const ZERO: Fixed = Fixed(0);
+pub const ONE: Fixed = Fixed(1 << FRAC_BITS);
+
+/// 2 pi with 30 bits of fraction, for the sines
+const TWO_PI: i64 = 6746518852;
+const SIN_BITS: u32 = 30;
+
+impl Fixed {
+    pub fn from_int(n: i32) -> Fixed {
+        Fixed(n.wrapping_shl(FRAC_BITS))
+    }
+
+    /// The closest fixed-point number, saturated to the range
+    pub fn from_f64(value: f64) -> Fixed {
+        let raw = (value * (1 << FRAC_BITS) as f64).round();
+        if raw.is_nan() {
+            ZERO
+        } else if raw >= ::std::i32::MAX as f64 {
+            Fixed(::std::i32::MAX)
+        } else if raw <= ::std::i32::MIN as f64 {
+            Fixed(::std::i32::MIN)
+        } else {
+            Fixed(raw as i32)
+        }
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / (1 << FRAC_BITS) as f64
+    }
+
+    /// Integer part, toward the negative numbers
+    pub fn to_int(self) -> i32 {
+        self.0 >> FRAC_BITS
+    }
+
+    pub fn floor(self) -> Fixed {
+        Fixed(self.0 & !((1 << FRAC_BITS) - 1))
+    }
+
+    pub fn ceil(self) -> Fixed {
+        Fixed(self.0.wrapping_add((1 << FRAC_BITS) - 1)).floor()
+    }
+
+    pub fn abs(self) -> Fixed {
+        Fixed(self.0.wrapping_abs())
+    }
+
+    pub fn min(self, other: Fixed) -> Fixed {
+        if other < self { other } else { self }
+    }
+
+    pub fn max(self, other: Fixed) -> Fixed {
+        if other > self { other } else { self }
+    }
+
+    /// Square root, rounded down, 0 for the negative numbers
+    pub fn sqrt(self) -> Fixed {
+        if self.0 <= 0 {
+            return ZERO;
+        }
+
+        // Integer root of the value with 32 bits of fraction, bit by bit
+        let mut value = (self.0 as u64) << FRAC_BITS;
+        let mut root = 0u64;
+        let mut bit = 1u64 << 62;
+        while bit > value {
+            bit >>= 2;
+        }
+        while bit != 0 {
+            if value >= root + bit {
+                value -= root + bit;
+                root = (root >> 1) + bit;
+            } else {
+                root >>= 1;
+            }
+            bit >>= 2;
+        }
+
+        Fixed(root as i32)
+    }
+
+    /// Sine of an angle in turns (1 is a full turn), the Taylor series to
+    /// the 11th power on a quarter of turn
+    pub fn sin(self) -> Fixed {
+        // Around 0 from -0.25 to 0.25 turn, the sine being symmetric around
+        // the quarters
+        let turn = (self.0 & 0xffff) as i64;
+        let x = if turn < 0x4000 {
+            turn
+        } else if turn < 0xc000 {
+            0x8000 - turn
+        } else {
+            turn - 0x10000
+        };
+
+        let one = 1i64 << SIN_BITS;
+        let a = (x * TWO_PI) >> FRAC_BITS;
+        let a2 = (a * a) >> SIN_BITS;
+        let mut sum = one;
+        for &n in [110, 72, 42, 20, 6].iter() {
+            sum = one - ((a2 * sum) >> SIN_BITS) / n;
+        }
+        let value = (a * sum) >> SIN_BITS;
+
+        Fixed(((value + (1 << (SIN_BITS - FRAC_BITS - 1))) >> (SIN_BITS - FRAC_BITS)) as i32)
+    }
+
+    pub fn cos(self) -> Fixed {
+        (self + Fixed(0x4000)).sin()
+    }
+}
+
+impl PartialOrd for Fixed {
+    fn partial_cmp(&self, other: &Fixed) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Fixed {
+    fn cmp(&self, other: &Fixed) -> Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+
+    fn add(self, other: Fixed) -> Fixed {
+        Fixed(self.0.wrapping_add(other.0))
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Fixed;
+
+    fn sub(self, other: Fixed) -> Fixed {
+        Fixed(self.0.wrapping_sub(other.0))
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Fixed;
+
+    fn neg(self) -> Fixed {
+        Fixed(self.0.wrapping_neg())
+    }
+}
+
+/// Rounded toward the negative numbers
+impl Mul for Fixed {
+    type Output = Fixed;
+
+    fn mul(self, other: Fixed) -> Fixed {
+        Fixed(((self.0 as i64 * other.0 as i64) >> FRAC_BITS) as i32)
+    }
+}
+
+/// Rounded toward 0
+impl Div for Fixed {
+    type Output = Fixed;
+
+    fn div(self, other: Fixed) -> Fixed {
+        if other.0 == 0 {
+            return Fixed(if self.0 < 0 { -::std::i32::MAX } else { ::std::i32::MAX });
+        }
+
+        Fixed((((self.0 as i64) << FRAC_BITS) / other.0 as i64) as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fixed, ONE, ZERO};
+
+    fn fx(value: f64) -> Fixed {
+        Fixed::from_f64(value)
+    }
+
+    #[test]
+    fn test_fixed() {
+        assert_eq!(fx(1.5), Fixed(0x18000));
+        assert_eq!(fx(-0.25).to_f64(), -0.25);
+        assert_eq!(Fixed::from_int(-3), fx(-3.0));
+        assert_eq!(fx(1e10), Fixed(::std::i32::MAX));
+
+        assert_eq!(fx(2.5) + fx(1.25), fx(3.75));
+        assert_eq!(fx(2.5) - fx(3.0), fx(-0.5));
+        assert_eq!(fx(2.5) * fx(-1.5), fx(-3.75));
+        assert_eq!(fx(7.0) / fx(2.0), fx(3.5));
+        assert_eq!(ONE / fx(3.0), Fixed(0x5555));
+        assert_eq!(ONE / ZERO, Fixed(0x7fffffff));
+        assert_eq!(-ONE / ZERO, Fixed(-0x7fffffff));
+        // Wraps around like the numbers of PICO-8
+        assert_eq!(fx(32767.0) + ONE, fx(-32768.0));
+
+        assert_eq!(fx(-1.5).floor(), fx(-2.0));
+        assert_eq!(fx(-1.5).ceil(), fx(-1.0));
+        assert_eq!(fx(1.25).floor(), ONE);
+        assert_eq!(fx(1.25).ceil(), fx(2.0));
+        assert_eq!(fx(-1.5).to_int(), -2);
+        assert!(fx(-1.0) < fx(0.5));
+        assert_eq!(fx(-1.0).max(fx(0.5)), fx(0.5));
+    }
+
+    #[test]
+    fn test_fixed_functions() {
+        assert_eq!(fx(16.0).sqrt(), fx(4.0));
+        assert_eq!(fx(2.0).sqrt(), Fixed(92681));
+        assert_eq!(fx(-4.0).sqrt(), ZERO);
+
+        assert_eq!(ZERO.sin(), ZERO);
+        assert_eq!(fx(0.25).sin(), ONE);
+        assert_eq!(fx(0.5).sin(), ZERO);
+        assert_eq!(fx(0.75).sin(), -ONE);
+        assert_eq!(fx(-0.25).sin(), -ONE);
+        assert_eq!(ZERO.cos(), ONE);
+        assert_eq!(fx(0.5).cos(), -ONE);
+
+        for i in 0..256 {
+            let turn = i as f64 / 200.0 - 0.3;
+            let expected = (turn * 2.0 * ::std::f64::consts::PI).sin();
+            assert!((fx(turn).sin().to_f64() - expected).abs() < 0.0001);
+        }
+    }
+}
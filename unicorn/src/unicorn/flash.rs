@@ -0,0 +1,131 @@
+//! Reduced flashing, the `reduce_motion` accessibility setting: a frame of
+//! the cartridge that changes the brightness of a quarter of the screen is a
+//! flash, and the flashes closer than `MIN_FLASH_INTERVAL` to the previous
+//! one are replaced by the last frame shown in the window (at most 3 flashes
+//! per second), the screen of the cartridge is kept.
+//! The cartridges read the setting with `stat("reduce_motion")` to tone down
+//! their own effects.
+
+/// Seconds between two flashes, a flash and its end make 2 of them
+pub const MIN_FLASH_INTERVAL: f64 = 1.0 / 6.0;
+/// Change of the luminance (0 to 1) of a pixel counted in a flash
+pub const FLASH_LUMINANCE: f32 = 0.1;
+
+pub struct FlashLimiter {
+    /// RGB colors of the last frame shown, shown again instead of a flash
+    frame: Vec<u8>,
+    /// Luminance of each pixel of the last frame shown
+    luminance: Vec<f32>,
+    last_flash: Option<f64>,
+}
+
+impl FlashLimiter {
+    pub fn new() -> FlashLimiter {
+        FlashLimiter {
+            frame: Vec::new(),
+            luminance: Vec::new(),
+            last_flash: None,
+        }
+    }
+
+    /// Forget the last frame, at the start of a cartridge
+    pub fn reset(&mut self) {
+        self.frame.clear();
+        self.luminance.clear();
+        self.last_flash = None;
+    }
+
+    /// Check the RGB colors of the new frame shown at `time` seconds. True
+    /// when the frame was a flash too close to the previous one, and its
+    /// colors replaced by the last frame shown. The screen of the cartridge
+    /// isn't changed, only what is displayed.
+    pub fn limit(&mut self, rgb: &mut [u8], time: f64) -> bool {
+        let values: Vec<f32> = rgb.chunks(3)
+            .map(|pixel| if pixel.len() == 3 { luminance(pixel[0], pixel[1], pixel[2]) } else { 0.0 })
+            .collect();
+
+        // First frame, or another resolution
+        if values.len() != self.luminance.len() || rgb.len() != self.frame.len() {
+            self.frame = rgb.to_vec();
+            self.luminance = values;
+            return false;
+        }
+
+        let changed = values
+            .iter()
+            .zip(self.luminance.iter())
+            .filter(|&(new, old)| (new - old).abs() >= FLASH_LUMINANCE)
+            .count();
+
+        if changed * 4 >= values.len() && !values.is_empty() {
+            if let Some(last_flash) = self.last_flash {
+                if time - last_flash < MIN_FLASH_INTERVAL {
+                    rgb.copy_from_slice(&self.frame);
+                    return true;
+                }
+            }
+            self.last_flash = Some(time);
+        }
+
+        self.frame.copy_from_slice(rgb);
+        self.luminance = values;
+        false
+    }
+}
+
+/// Relative luminance of a color, 0 for black and 1 for white
+pub fn luminance(r: u8, g: u8, b: u8) -> f32 {
+    (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32) / 255.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{luminance, FlashLimiter};
+
+    fn frame(pixels: &[u8]) -> Vec<u8> {
+        pixels.iter().flat_map(|value| vec![*value, *value, *value]).collect()
+    }
+
+    #[test]
+    fn test_flash_limiter() {
+        let mut limiter = FlashLimiter::new();
+
+        let mut black = frame(&[0; 16]);
+        assert!(!limiter.limit(&mut black, 0.0));
+
+        // The first flash is shown, the end of it comes too soon
+        let mut white = frame(&[255; 16]);
+        assert!(!limiter.limit(&mut white, 0.1));
+        let mut rgb = frame(&[0; 16]);
+        assert!(limiter.limit(&mut rgb, 0.15));
+        assert_eq!(rgb, frame(&[255; 16]));
+        let mut rgb = frame(&[0; 16]);
+        assert!(!limiter.limit(&mut rgb, 0.3));
+
+        // A few pixels are not a flash
+        let mut pixels = [0; 16];
+        pixels[0] = 255;
+        pixels[1] = 255;
+        let mut rgb = frame(&pixels);
+        assert!(!limiter.limit(&mut rgb, 0.31));
+        assert_eq!(rgb[0], 255);
+
+        // The same values with other colors, like a swap of the palette
+        let mut red: Vec<u8> = (0..16).flat_map(|_| vec![255, 0, 0]).collect();
+        assert!(!limiter.limit(&mut red, 0.6));
+        let mut cyan: Vec<u8> = (0..16).flat_map(|_| vec![0, 255, 255]).collect();
+        assert!(limiter.limit(&mut cyan, 0.65));
+        assert_eq!(&cyan[..3], &[255, 0, 0]);
+
+        // Another resolution
+        let mut rgb = frame(&[255; 4]);
+        assert!(!limiter.limit(&mut rgb, 0.7));
+
+        limiter.reset();
+        let mut rgb = frame(&[0; 4]);
+        assert!(!limiter.limit(&mut rgb, 0.75));
+
+        assert_eq!(luminance(0, 0, 0), 0.0);
+        assert!((luminance(255, 255, 255) - 1.0).abs() < 0.001);
+    }
+}
@@ -0,0 +1,235 @@
+//! `printf` like formatting of `strf`, done here so the Lua and Python
+//! cartridges display the numbers exactly the same way:
+//! `%[flags][width][.precision]conversion` with the flags `-` (left align),
+//! `0` (zero padding), `+` and ` ` (sign of the positive numbers), and the
+//! conversions `d`/`i` (integer), `f` (fixed decimals, 6 by default),
+//! `x`/`X` (hexadecimal), `s` (string) and `%%`.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FormatArg {
+    Number(f64),
+    Str(String),
+    Bool(bool),
+    Nil,
+}
+
+impl FormatArg {
+    fn number(&self) -> f64 {
+        match *self {
+            FormatArg::Number(value) => value,
+            FormatArg::Str(ref value) => value.trim().parse::<f64>().unwrap_or(0.0),
+            FormatArg::Bool(value) => if value { 1.0 } else { 0.0 },
+            FormatArg::Nil => 0.0,
+        }
+    }
+
+    fn string(&self) -> String {
+        match *self {
+            FormatArg::Number(value) => tostr(value),
+            FormatArg::Str(ref value) => value.clone(),
+            FormatArg::Bool(value) => value.to_string(),
+            FormatArg::Nil => "nil".to_string(),
+        }
+    }
+}
+
+/// Number without the decimals when it is an integer ("3" and not "3.0")
+pub fn tostr(value: f64) -> String {
+    if value.is_nan() {
+        return "nan".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        return format!("{}", value as i64);
+    }
+    format!("{}", value)
+}
+
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    zero: bool,
+    plus: bool,
+    space: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Spec {
+    fn sign(&self, negative: bool) -> &'static str {
+        if negative {
+            "-"
+        } else if self.plus {
+            "+"
+        } else if self.space {
+            " "
+        } else {
+            ""
+        }
+    }
+
+    /// Pad the digits to the width, the zeros go after the sign
+    fn pad_number(&self, sign: &str, digits: &str) -> String {
+        let len = sign.len() + digits.chars().count();
+        if len >= self.width {
+            return format!("{}{}", sign, digits);
+        }
+
+        let padding = self.width - len;
+        if self.left {
+            format!("{}{}{}", sign, digits, " ".repeat(padding))
+        } else if self.zero {
+            format!("{}{}{}", sign, "0".repeat(padding), digits)
+        } else {
+            format!("{}{}{}", " ".repeat(padding), sign, digits)
+        }
+    }
+
+    fn pad(&self, text: &str) -> String {
+        let len = text.chars().count();
+        if len >= self.width {
+            return text.to_string();
+        }
+
+        let padding = " ".repeat(self.width - len);
+        if self.left {
+            format!("{}{}", text, padding)
+        } else {
+            format!("{}{}", padding, text)
+        }
+    }
+}
+
+fn read_number(chars: &[char], idx: &mut usize) -> usize {
+    let mut value = 0;
+    while *idx < chars.len() && chars[*idx].is_digit(10) {
+        value = value * 10 + chars[*idx].to_digit(10).unwrap() as usize;
+        *idx += 1;
+    }
+    value
+}
+
+fn format_one(conversion: char, spec: &Spec, arg: &FormatArg) -> String {
+    match conversion {
+        'd' | 'i' => {
+            let value = arg.number().trunc();
+            spec.pad_number(spec.sign(value < 0.0), &tostr(value.abs()))
+        }
+        'f' => {
+            let value = arg.number();
+            let precision = spec.precision.unwrap_or(6);
+            let digits = format!("{:.*}", precision, value.abs());
+            // -0.00 is displayed as 0.00
+            let negative = value < 0.0 && digits.chars().any(|c| c != '0' && c != '.');
+            spec.pad_number(spec.sign(negative), &digits)
+        }
+        'x' | 'X' => {
+            let value = arg.number().trunc() as i64;
+            let digits = if conversion == 'x' {
+                format!("{:x}", value.abs())
+            } else {
+                format!("{:X}", value.abs())
+            };
+            spec.pad_number(spec.sign(value < 0), &digits)
+        }
+        _ => {
+            let text = arg.string();
+            match spec.precision {
+                Some(precision) => spec.pad(&text.chars().take(precision).collect::<String>()),
+                None => spec.pad(&text),
+            }
+        }
+    }
+}
+
+/// Format the arguments, the missing ones are nil
+pub fn strf(fmt: &str, args: &[FormatArg]) -> String {
+    let chars: Vec<char> = fmt.chars().collect();
+    let mut output = String::new();
+    let mut args = args.iter();
+
+    let mut idx = 0;
+    while idx < chars.len() {
+        if chars[idx] != '%' {
+            output.push(chars[idx]);
+            idx += 1;
+            continue;
+        }
+
+        let start = idx;
+        idx += 1;
+
+        let mut spec = Spec::default();
+        while idx < chars.len() {
+            match chars[idx] {
+                '-' => spec.left = true,
+                '0' => spec.zero = true,
+                '+' => spec.plus = true,
+                ' ' => spec.space = true,
+                _ => break,
+            }
+            idx += 1;
+        }
+
+        spec.width = read_number(&chars, &mut idx);
+        if idx < chars.len() && chars[idx] == '.' {
+            idx += 1;
+            spec.precision = Some(read_number(&chars, &mut idx));
+        }
+
+        if idx >= chars.len() {
+            output.extend(chars[start..].iter());
+            break;
+        }
+
+        let conversion = chars[idx];
+        idx += 1;
+
+        match conversion {
+            '%' => output.push('%'),
+            'd' | 'i' | 'f' | 'x' | 'X' | 's' => {
+                let arg = args.next().cloned().unwrap_or(FormatArg::Nil);
+                output.push_str(&format_one(conversion, &spec, &arg));
+            }
+            // Unknown conversion, kept as is
+            _ => output.extend(chars[start..idx].iter()),
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{strf, tostr, FormatArg};
+
+    #[test]
+    fn test_tostr() {
+        assert_eq!(tostr(3.0), "3");
+        assert_eq!(tostr(-12.0), "-12");
+        assert_eq!(tostr(0.5), "0.5");
+    }
+
+    #[test]
+    fn test_strf() {
+        let n = |value| FormatArg::Number(value);
+
+        assert_eq!(strf("SCORE %06d", &[n(420.0)]), "SCORE 000420");
+        assert_eq!(strf("%.2f", &[n(3.14159)]), "3.14");
+        assert_eq!(strf("%8.3f|", &[n(-1.5)]), "  -1.500|");
+        assert_eq!(strf("%08.3f", &[n(-1.5)]), "-001.500");
+        assert_eq!(strf("%.1f", &[n(-0.01)]), "0.0");
+        assert_eq!(strf("%x %X %04x", &[n(255.0), n(255.0), n(10.0)]), "ff FF 000a");
+        assert_eq!(strf("%d", &[n(-7.9)]), "-7");
+        assert_eq!(strf("%+d % d", &[n(5.0), n(5.0)]), "+5  5");
+        assert_eq!(strf("[%-5s][%5s]", &[FormatArg::Str("ab".to_string()), n(2.0)]),
+                   "[ab   ][    2]");
+        assert_eq!(strf("%.3s", &[FormatArg::Str("unicorn".to_string())]), "uni");
+        assert_eq!(strf("%s %s %s", &[FormatArg::Bool(true), FormatArg::Nil]),
+                   "true nil nil");
+        assert_eq!(strf("100%% %q %", &[]), "100% %q %");
+        assert_eq!(strf("%d", &[FormatArg::Str("12".to_string())]), "12");
+    }
+}
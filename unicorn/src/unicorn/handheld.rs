@@ -0,0 +1,110 @@
+//! Presets of the handheld PCs, chosen with `--handheld steam-deck` or
+//! detected from the environment of the device. The cartridges run in
+//! fullscreen, at the biggest integer scale of the built-in screen, the
+//! buttons of the controller open the pause menu and the on-screen keyboard,
+//! and a suspend of the device pauses the cartridge instead of running its
+//! timers for the time it was asleep.
+
+use std::cmp;
+use std::env;
+use std::time::Duration;
+
+use unicorn::display::{DisplayConfig, MAX_SCALE, MIN_SCALE};
+
+/// A frame longer than this was a suspend of the device
+pub const SUSPEND_SECONDS: u64 = 5;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Handheld {
+    pub name: &'static str,
+    /// Resolution of the built-in screen
+    pub width: u32,
+    pub height: u32,
+    /// Variable set in the environment of the programs on the device, and
+    /// its value
+    pub env: (&'static str, &'static str),
+    /// Opened to show the on-screen keyboard of the system
+    pub keyboard_url: &'static str,
+}
+
+pub static HANDHELDS: [Handheld; 1] = [
+    // Steam sets SteamDeck=1 in the desktop and in the game modes
+    Handheld {
+        name: "steam-deck",
+        width: 1280,
+        height: 800,
+        env: ("SteamDeck", "1"),
+        keyboard_url: "steam://open/keyboard",
+    },
+];
+
+impl Handheld {
+    pub fn from_name(name: &str) -> Option<Handheld> {
+        HANDHELDS.iter().find(|handheld| handheld.name == name).cloned()
+    }
+
+    /// The device running the console, none on the other machines
+    pub fn detect() -> Option<Handheld> {
+        HANDHELDS
+            .iter()
+            .find(|handheld| match env::var(handheld.env.0) {
+                Ok(value) => value == handheld.env.1,
+                Err(_) => false,
+            })
+            .cloned()
+    }
+
+    /// Biggest integer scale of a screen of the cartridge on the built-in one
+    pub fn scale(&self, screen_width: usize, screen_height: usize) -> usize {
+        let scale = cmp::min(self.width as usize / screen_width.max(1),
+                             self.height as usize / screen_height.max(1));
+        scale.max(MIN_SCALE).min(MAX_SCALE)
+    }
+
+    /// Fullscreen, and the size of the window leaving it fits the built-in
+    /// screen. The filter and the reduced flashing are kept.
+    pub fn display(&self, config: &DisplayConfig, screen_width: usize, screen_height: usize) -> DisplayConfig {
+        DisplayConfig {
+            scale: self.scale(screen_width, screen_height),
+            fullscreen: true,
+            ..config.clone()
+        }
+    }
+}
+
+/// The process was frozen during the frame, by a suspend of the device
+pub fn is_suspend(dt: Duration) -> bool {
+    dt >= Duration::from_secs(SUSPEND_SECONDS)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use unicorn::display::DisplayConfig;
+
+    use super::{is_suspend, Handheld};
+
+    #[test]
+    fn test_handheld() {
+        assert!(Handheld::from_name("gameboy").is_none());
+        let deck = Handheld::from_name("steam-deck").unwrap();
+
+        // 768x768 and 1200x720 in 1280x800
+        assert_eq!(deck.scale(128, 128), 6);
+        assert_eq!(deck.scale(400, 240), 3);
+        assert_eq!(deck.scale(2000, 2000), 1);
+        // At most the biggest scale of the window
+        assert_eq!(deck.scale(64, 64), 8);
+
+        let mut config = DisplayConfig::default();
+        config.filter = "lcd".to_string();
+        let display = deck.display(&config, 128, 128);
+        assert!(display.fullscreen);
+        assert_eq!(display.scale, 6);
+        assert_eq!(display.filter, "lcd");
+
+        assert!(!is_suspend(Duration::from_millis(100)));
+        assert!(is_suspend(Duration::from_secs(30)));
+    }
+}
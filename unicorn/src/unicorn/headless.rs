@@ -0,0 +1,132 @@
+//! Headless runs of a cartridge, without a window or sound, to record the
+//! GIF of its page (`uc-devkit cart.uc --preview-gif preview.gif`). The
+//! players press nothing, unless an input script gives the buttons held from
+//! a frame, for a number of frames (1 by default):
+//! ```text
+//! # frame player button [frames]
+//! 30 0 a 10
+//! 60 0 right 120
+//! ```
+
+use std::fs::File;
+use std::io::Read;
+
+use config::keys::PX8Key;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InputEvent {
+    pub frame: u32,
+    pub player: u8,
+    pub key: PX8Key,
+    pub frames: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputScript {
+    pub events: Vec<InputEvent>,
+}
+
+impl InputScript {
+    /// Nothing is pressed
+    pub fn new() -> InputScript {
+        InputScript { events: Vec::new() }
+    }
+
+    pub fn from_str(data: &str) -> Result<InputScript, String> {
+        let mut events = Vec::new();
+
+        for (idx, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let values: Vec<&str> = line.split_whitespace().collect();
+            if values.len() < 3 || values.len() > 4 {
+                return Err(format!("Line {}: expected \"frame player button [frames]\"", idx + 1));
+            }
+
+            let frame = try!(values[0]
+                                 .parse::<u32>()
+                                 .map_err(|_| format!("Line {}: invalid frame {:?}", idx + 1, values[0])));
+            let player = try!(values[1]
+                                  .parse::<u8>()
+                                  .map_err(|_| format!("Line {}: invalid player {:?}", idx + 1, values[1])));
+            let key = try!(PX8Key::from_name(values[2])
+                               .ok_or(format!("Line {}: unknown button {:?}", idx + 1, values[2])));
+            let frames = match values.get(3) {
+                Some(value) => {
+                    try!(value
+                             .parse::<u32>()
+                             .map_err(|_| format!("Line {}: invalid frames {:?}", idx + 1, value)))
+                }
+                None => 1,
+            };
+
+            events.push(InputEvent {
+                            frame: frame,
+                            player: player,
+                            key: key,
+                            frames: frames.max(1),
+                        });
+        }
+
+        Ok(InputScript { events: events })
+    }
+
+    pub fn from_file(filename: &str) -> Result<InputScript, String> {
+        let mut data = String::new();
+        match File::open(filename) {
+            Ok(mut f) => {
+                if let Err(e) = f.read_to_string(&mut data) {
+                    return Err(format!("Failed to read {:?}: {}", filename, e));
+                }
+            }
+            Err(e) => return Err(format!("Failed to open {:?}: {}", filename, e)),
+        }
+
+        InputScript::from_str(&data)
+    }
+
+    /// Buttons pressed at `frame`
+    pub fn pressed(&self, frame: u32) -> Vec<(u8, PX8Key)> {
+        self.events
+            .iter()
+            .filter(|event| event.frame == frame)
+            .map(|event| (event.player, event.key))
+            .collect()
+    }
+
+    /// Buttons released at `frame`, after their last frame
+    pub fn released(&self, frame: u32) -> Vec<(u8, PX8Key)> {
+        self.events
+            .iter()
+            .filter(|event| event.frame + event.frames == frame)
+            .map(|event| (event.player, event.key))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::keys::PX8Key;
+
+    use super::InputScript;
+
+    #[test]
+    fn test_input_script() {
+        let script = InputScript::from_str("# frame player button [frames]\n\n30 0 a 10\n31 1 Right\n")
+            .unwrap();
+        assert_eq!(script.events.len(), 2);
+        assert_eq!(script.pressed(30), vec![(0, PX8Key::A)]);
+        assert_eq!(script.released(40), vec![(0, PX8Key::A)]);
+        assert_eq!(script.pressed(31), vec![(1, PX8Key::Right)]);
+        assert_eq!(script.released(32), vec![(1, PX8Key::Right)]);
+        assert!(script.pressed(0).is_empty());
+
+        assert!(InputScript::from_str("30 0 jump").is_err());
+        assert!(InputScript::from_str("30 0").is_err());
+        assert!(InputScript::from_str("-1 0 a").is_err());
+        assert_eq!(InputScript::new().events.len(), 0);
+    }
+}
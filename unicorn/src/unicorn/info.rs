@@ -1,9 +1,86 @@
+use std::collections::HashMap;
+use std::path::Path;
 use std::time::Duration;
 
+use cartridge::UPDATE_RATES;
+use unicorn::score;
+use unicorn::timers::Timers;
+use unicorn::entities::Entities;
+use unicorn::cartdata::CartData;
+use unicorn::physics::Physics;
+use unicorn::permissions::{Permission, Permissions};
+use unicorn::record::RecordConfig;
+use unicorn::display::{DisplayConfig, FILTERS};
+use unicorn::record::CONSOLE_FPS;
+use unicorn::trace::Trace;
+use unicorn::effects::Effects;
+use unicorn::random::Random;
+use unicorn::deprecation::Deprecations;
+
+/// Custom entries of the pause menu, `menuitem(1..5)`
+pub const MENUITEMS: usize = 5;
+/// Characters of the label of a custom entry
+const MENUITEM_LEN: usize = 16;
+
 pub struct Info {
     pub current: Duration,
-    pub milliseconds: u64,
+    /// Real time since the start, for the inputs and the host overlays
     pub elapsed_time: f64,
+    /// Time of the running cartridge, frozen in the pause menu (`time()`)
+    pub game_time: f64,
+    /// UUID of the running cartridge, the key of its signed scores
+    pub cartridge_uuid: String,
+    /// Hash of the content of the running cartridge, the key of its
+    /// permissions
+    pub cartridge_hash: String,
+    /// File of the last screenshot/record, `stat("last_capture")`
+    pub last_capture: String,
+    pub captures: u32,
+    /// Screenshot asked by the cartridge with `extcmd("screenshot", name)`
+    pub capture_request: Option<String>,
+    /// On-screen keyboard asked by the cartridge with `extcmd("keyboard")`,
+    /// shown by the frontend of a handheld
+    pub keyboard_request: bool,
+    /// Colors of the LEDs of the controllers asked by `led`, by player, until
+    /// the frontend sets them
+    pub leds: HashMap<u8, (u8, u8, u8)>,
+    /// Timers and tweens of the cartridge, updated with the time of the game
+    pub timers: Timers,
+    /// Tags and layers of the entities of the cartridge
+    pub entities: Entities,
+    /// Numbers saved by the cartridge with `dset`
+    pub cartdata: CartData,
+    /// Platformer bodies moved against the map with `body_move`
+    pub physics: Physics,
+    /// Labels of the custom entries of the pause menu
+    pub menuitems: Vec<Option<String>>,
+    /// Custom entry chosen in the pause menu, its callback is called
+    /// before the next `_update`
+    pub menuitem_selected: Option<u32>,
+    /// Length, FPS and scale of the GIF recordings
+    pub record_config: RecordConfig,
+    /// Current size of the window, updated by the frontend
+    pub display: DisplayConfig,
+    /// Size asked in the pause menu or with `extcmd("scale", n)`, applied
+    /// by the frontend after the frame
+    pub display_request: Option<DisplayConfig>,
+    /// Frames per second of the running cartridge, 60 by default or the
+    /// `fps` of its metadata
+    pub fps: u32,
+    /// Seconds of `_update` and `_draw` in the last frame
+    pub frame_time: f64,
+    /// Capabilities allowed by the player for each cartridge
+    pub permissions: Permissions,
+    /// Spans of `--trace`, with the sections of the cartridge
+    pub trace: Trace,
+    /// Fade, flash and shake asked by the cartridge
+    pub effects: Effects,
+    /// Numbers of `rnd`
+    pub random: Random,
+    /// Seed of `rnd` at the start of each cartridge, from the system if none
+    pub random_seed: Option<u64>,
+    /// Deprecated functions already called by the cartridge
+    pub deprecations: Deprecations,
 }
 
 
@@ -11,8 +88,32 @@ impl Info {
     pub fn new() -> Info {
         Info {
             current: Duration::from_millis(0),
-            milliseconds: 0,
             elapsed_time: 0.0,
+            game_time: 0.0,
+            cartridge_uuid: String::new(),
+            cartridge_hash: String::new(),
+            last_capture: String::new(),
+            captures: 0,
+            capture_request: None,
+            keyboard_request: false,
+            leds: HashMap::new(),
+            timers: Timers::new(),
+            entities: Entities::new(),
+            cartdata: CartData::new(),
+            physics: Physics::new(),
+            menuitems: vec![None; MENUITEMS],
+            menuitem_selected: None,
+            record_config: RecordConfig::default(),
+            display: DisplayConfig::default(),
+            display_request: None,
+            fps: CONSOLE_FPS,
+            frame_time: 0.0,
+            permissions: Permissions::new(),
+            trace: Trace::new(),
+            effects: Effects::new(),
+            random: Random::new(),
+            random_seed: None,
+            deprecations: Deprecations::new(),
         }
     }
 
@@ -20,17 +121,212 @@ impl Info {
         if self.current > dt {
             let nanoseconds = dt.subsec_nanos();
             self.elapsed_time += dt.as_secs() as f64 + nanoseconds as f64 / 1000000000.0;
-            self.milliseconds = nanoseconds as u64 * 1000000000;
         }
         
         self.current = self.current + dt;
     }
 
+    /// Only called while the cartridge runs
+    pub fn update_game(&mut self, seconds: f64) {
+        self.game_time += seconds;
+    }
+
+    /// Milliseconds of the game
     pub fn time(&mut self) -> i64 {
-        self.milliseconds as i64
+        (self.game_time * 1000.0) as i64
     }
 
     pub fn time_sec(&mut self) -> f64 {
+        self.game_time
+    }
+
+    pub fn real_time_sec(&mut self) -> f64 {
         self.elapsed_time
     }
+
+    /// Only a rate of `cartridge::UPDATE_RATES`, the default otherwise
+    pub fn set_fps(&mut self, fps: u32) {
+        self.fps = if UPDATE_RATES.contains(&fps) { fps } else { CONSOLE_FPS };
+    }
+
+    /// Seconds of a frame
+    pub fn frame_budget(&self) -> f64 {
+        1.0 / self.fps as f64
+    }
+
+    /// Measured by the frontend after each frame
+    pub fn set_frame_time(&mut self, seconds: f64) {
+        self.frame_time = seconds;
+    }
+
+    /// Milliseconds over the budget of the last frame, 0 when it was on time
+    pub fn over_budget(&self) -> f64 {
+        ((self.frame_time - self.frame_budget()) * 1000.0).max(0.0)
+    }
+
+    /// Capability allowed for the running cartridge, the player is asked
+    /// the first time
+    pub fn permission(&mut self, permission: Permission) -> bool {
+        let cartridge = self.cartridge_hash.clone();
+        self.permissions.check(&cartridge, permission)
+    }
+
+    pub fn score_sign(&self, value: i64) -> String {
+        score::score_sign(&score::cartridge_key(&self.cartridge_uuid), value)
+    }
+
+    pub fn score_verify(&self, blob: &str) -> Option<i64> {
+        score::score_verify(&score::cartridge_key(&self.cartridge_uuid), blob)
+    }
+
+    /// Set the label of the entry `index` (1 to 5), or remove it with None
+    pub fn menuitem(&mut self, index: u32, label: Option<&str>) -> bool {
+        if index < 1 || index as usize > MENUITEMS {
+            return false;
+        }
+
+        self.menuitems[index as usize - 1] = label.map(|label| label.chars().take(MENUITEM_LEN).collect());
+        true
+    }
+
+    /// Forget the time, the timers, the entities, the bodies and the entries
+    /// of the pause menu of the previous cartridge
+    pub fn reset_cartridge(&mut self) {
+        self.game_time = 0.0;
+        self.timers.clear();
+        self.entities.clear();
+        self.physics.clear();
+
+        for item in self.menuitems.iter_mut() {
+            *item = None;
+        }
+        self.menuitem_selected = None;
+        self.permissions.request = None;
+        self.random = match self.random_seed {
+            Some(seed) => Random::from_seed(seed),
+            None => Random::new(),
+        };
+        self.deprecations.clear();
+    }
+
+    pub fn set_capture(&mut self, filename: &str) {
+        self.last_capture = filename.to_string();
+        self.captures += 1;
+    }
+
+    /// Filter of the screen asked by the cartridge, false when unknown
+    pub fn request_filter(&mut self, name: &str) -> bool {
+        if !FILTERS.contains(&name) {
+            return false;
+        }
+
+        let mut display = self.display_request.clone().unwrap_or(self.display.clone());
+        display.filter = name.to_string();
+        self.display_request = Some(display);
+        true
+    }
+
+    /// Only the name of the file is kept, the screenshot is written with the
+    /// others (or in the save area of the sandbox)
+    pub fn request_capture(&mut self, name: &str) {
+        let mut filename = match Path::new(name).file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => format!("screenshot-{}", self.captures),
+        };
+        if !filename.to_lowercase().ends_with(".png") {
+            filename.push_str(".png");
+        }
+
+        self.capture_request = Some(filename);
+    }
+
+    /// `extcmd(cmd, arg)` of the cartridges, the error is a warning for
+    /// the log
+    pub fn extcmd(&mut self, cmd: &str, arg: Option<&str>) -> Result<(), String> {
+        match cmd {
+            "screenshot" => {
+                let name = arg.map(|arg| arg.to_string())
+                    .unwrap_or(format!("screenshot-{}", self.captures));
+                self.request_capture(&name);
+            }
+            "rec_len" | "rec_fps" | "rec_scale" => {
+                let value = match arg.and_then(|arg| arg.trim().parse::<f64>().ok()) {
+                    Some(value) => value,
+                    None => return Err(format!("extcmd {:?} needs a number, not {:?}", cmd, arg)),
+                };
+
+                let mut config = self.record_config.clone();
+                match cmd {
+                    "rec_len" => config.max_seconds = value,
+                    "rec_fps" => config.fps = value as u32,
+                    _ => config.scale = value,
+                }
+                self.record_config = config.clamp();
+            }
+            "scale" => {
+                let value = match arg.and_then(|arg| arg.trim().parse::<usize>().ok()) {
+                    Some(value) => value,
+                    None => return Err(format!("extcmd {:?} needs a number, not {:?}", cmd, arg)),
+                };
+
+                let mut display = self.display.clone();
+                display.scale = value;
+                display.fullscreen = false;
+                self.display_request = Some(display.clamp());
+            }
+            "fullscreen" => {
+                let mut display = self.display.clone();
+                // Toggled without a value
+                display.fullscreen = match arg.map(|arg| arg.trim()) {
+                    Some("0") | Some("false") => false,
+                    Some(_) => true,
+                    None => !display.fullscreen,
+                };
+                self.display_request = Some(display);
+            }
+            "keyboard" => self.keyboard_request = true,
+            _ => return Err(format!("Unknown extcmd {:?}", cmd)),
+        }
+
+        Ok(())
+    }
+
+    pub fn led(&mut self, player: u8, r: u8, g: u8, b: u8) {
+        self.leds.insert(player, (r, g, b));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Info;
+
+    #[test]
+    fn test_extcmd() {
+        let mut info = Info::new();
+
+        assert!(info.extcmd("screenshot", None).is_ok());
+        assert_eq!(info.capture_request, Some("screenshot-0.png".to_string()));
+        assert!(info.extcmd("screenshot", Some("../title")).is_ok());
+        assert_eq!(info.capture_request, Some("title.png".to_string()));
+
+        assert!(info.extcmd("rec_len", Some("30")).is_ok());
+        assert!(info.extcmd("rec_fps", Some(" 120 ")).is_ok());
+        assert!(info.extcmd("rec_scale", Some("two")).is_err());
+        assert_eq!(info.record_config.max_seconds, 30.0);
+        assert_eq!(info.record_config.fps, 60);
+
+        assert!(info.extcmd("scale", Some("20")).is_ok());
+        assert_eq!(info.display_request.take().map(|display| display.scale), Some(8));
+        assert!(info.extcmd("scale", None).is_err());
+        assert!(info.display_request.is_none());
+
+        assert!(info.extcmd("fullscreen", None).is_ok());
+        assert_eq!(info.display_request.take().map(|display| display.fullscreen), Some(true));
+        assert!(info.extcmd("fullscreen", Some("0")).is_ok());
+        assert_eq!(info.display_request.take().map(|display| display.fullscreen), Some(false));
+
+        assert!(info.extcmd("keyboard", None).is_ok());
+        assert!(info.keyboard_request);
+        assert!(info.extcmd("reboot", None).is_err());
+    }
 }
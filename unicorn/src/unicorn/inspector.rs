@@ -0,0 +1,417 @@
+//! Variable inspector (Shift+F2): the globals of the cartridge as a tree,
+//! the game is paused while it's open. Up/Down select a variable, Return or
+//! Right expands a table, Left collapses it, R reads the values again and a
+//! click expands or collapses. The built-in functions of the console are
+//! hidden.
+//!
+//! The plugins list the children of a variable, one per line:
+//! `kind \t key \t name \t value`, where the key is a literal of the language
+//! giving the child to its parent, empty when it can't be expanded.
+
+use std::cmp;
+
+use config::Players;
+use config::scancode::Scancode;
+use gfx::Screen;
+
+const LINE_HEIGHT: i32 = 8;
+/// Variables in the tree, the next ones aren't read
+pub const MAX_NODES: usize = 4096;
+/// Longest value shown
+const MAX_VALUE_LEN: usize = 64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variable {
+    pub kind: String,
+    pub key: String,
+    pub name: String,
+    pub value: String,
+}
+
+impl Variable {
+    pub fn is_expandable(&self) -> bool {
+        !self.key.is_empty() && (self.kind == "table" || self.kind == "object")
+    }
+}
+
+/// Children of a variable from the text of a plugin, the numbers first
+pub fn parse_variables(text: &str) -> Vec<Variable> {
+    let mut variables: Vec<Variable> = text.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.splitn(4, '\t').collect();
+            if fields.len() != 4 {
+                return None;
+            }
+            Some(Variable {
+                     kind: fields[0].to_string(),
+                     key: fields[1].to_string(),
+                     name: fields[2].to_string(),
+                     value: fields[3].chars().take(MAX_VALUE_LEN).collect(),
+                 })
+        })
+        .collect();
+
+    variables.sort_by(|a, b| match (a.name.parse::<f64>(), b.name.parse::<f64>()) {
+                          (Ok(x), Ok(y)) => x.partial_cmp(&y).unwrap_or(cmp::Ordering::Equal),
+                          (Ok(_), Err(_)) => cmp::Ordering::Less,
+                          (Err(_), Ok(_)) => cmp::Ordering::Greater,
+                          (Err(_), Err(_)) => a.name.cmp(&b.name),
+                      });
+    variables
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub variable: Variable,
+    /// Keys from the globals
+    pub path: Vec<String>,
+    pub depth: usize,
+    pub expanded: bool,
+}
+
+pub struct Inspector {
+    pub enabled: bool,
+    /// Visible nodes, the children after their parent
+    pub nodes: Vec<Node>,
+    pub selected: usize,
+    /// First node displayed
+    pub scroll: usize,
+    /// Error of the plugin for the globals
+    pub error: Option<String>,
+    /// Mouse button held in the last frame
+    clicked: bool,
+    pub background: u32,
+    pub color: u32,
+    pub table_color: u32,
+    pub selected_color: u32,
+}
+
+impl Inspector {
+    pub fn new() -> Inspector {
+        Inspector {
+            enabled: false,
+            nodes: Vec::new(),
+            selected: 0,
+            scroll: 0,
+            error: None,
+            clicked: false,
+            background: 1,
+            color: 6,
+            table_color: 12,
+            selected_color: 10,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    /// Children of the variable at `path`, from `fetch` of the plugin
+    fn children<F>(path: &[String], depth: usize, fetch: &mut F) -> Result<Vec<Node>, String>
+        where F: FnMut(&[String]) -> Result<String, String>
+    {
+        let text = try!(fetch(path));
+        Ok(parse_variables(&text)
+               .into_iter()
+               .map(|variable| {
+                        let mut child_path = path.to_vec();
+                        child_path.push(variable.key.clone());
+                        Node {
+                            variable: variable,
+                            path: child_path,
+                            depth: depth,
+                            expanded: false,
+                        }
+                    })
+               .collect())
+    }
+
+    /// Read the globals again, the expanded tables stay expanded
+    pub fn refresh<F>(&mut self, fetch: &mut F)
+        where F: FnMut(&[String]) -> Result<String, String>
+    {
+        let expanded: Vec<Vec<String>> = self.nodes
+            .iter()
+            .filter(|node| node.expanded)
+            .map(|node| node.path.clone())
+            .collect();
+        let selected = self.nodes.get(self.selected).map(|node| node.path.clone());
+
+        self.nodes = match Inspector::children(&[], 0, fetch) {
+            Ok(nodes) => nodes,
+            Err(e) => {
+                self.error = Some(e);
+                self.nodes.clear();
+                self.selected = 0;
+                return;
+            }
+        };
+        self.error = None;
+
+        let mut idx = 0;
+        while idx < self.nodes.len() {
+            if self.nodes[idx].variable.is_expandable() &&
+               expanded.contains(&self.nodes[idx].path) {
+                self.expand(idx, fetch);
+            }
+            idx += 1;
+        }
+
+        self.selected = selected.and_then(|path| self.nodes.iter().position(|node| node.path == path))
+            .unwrap_or(0);
+    }
+
+    pub fn expand<F>(&mut self, idx: usize, fetch: &mut F)
+        where F: FnMut(&[String]) -> Result<String, String>
+    {
+        let (path, depth) = match self.nodes.get(idx) {
+            Some(node) if node.variable.is_expandable() && !node.expanded => {
+                (node.path.clone(), node.depth)
+            }
+            _ => return,
+        };
+
+        let mut children = Inspector::children(&path, depth + 1, fetch).unwrap_or(Vec::new());
+        children.truncate(MAX_NODES.saturating_sub(self.nodes.len()));
+
+        self.nodes[idx].expanded = true;
+        let tail = self.nodes.split_off(idx + 1);
+        self.nodes.extend(children);
+        self.nodes.extend(tail);
+    }
+
+    /// The descendants are removed
+    pub fn collapse(&mut self, idx: usize) {
+        let depth = match self.nodes.get(idx) {
+            Some(node) => node.depth,
+            None => return,
+        };
+        self.nodes[idx].expanded = false;
+
+        let end = self.nodes[idx + 1..]
+            .iter()
+            .position(|node| node.depth <= depth)
+            .map(|count| idx + 1 + count)
+            .unwrap_or(self.nodes.len());
+        self.nodes.drain(idx + 1..end);
+
+        if self.selected > idx && self.selected < end {
+            self.selected = idx;
+        } else if self.selected >= end {
+            self.selected -= end - idx - 1;
+        }
+    }
+
+    fn parent(&self, idx: usize) -> Option<usize> {
+        let depth = match self.nodes.get(idx) {
+            Some(node) if node.depth > 0 => node.depth,
+            _ => return None,
+        };
+        self.nodes[..idx].iter().rposition(|node| node.depth < depth)
+    }
+
+    pub fn rows(&self, screen: &Screen) -> usize {
+        cmp::max(1, (screen.height as i32 - 4) / LINE_HEIGHT - 1) as usize
+    }
+
+    pub fn select(&mut self, idx: usize, rows: usize) {
+        if self.nodes.is_empty() {
+            self.selected = 0;
+            return;
+        }
+
+        self.selected = cmp::min(idx, self.nodes.len() - 1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+    }
+
+    pub fn update<F>(&mut self, screen: &Screen, players: &mut Players, fetch: &mut F)
+        where F: FnMut(&[String]) -> Result<String, String>
+    {
+        let rows = self.rows(screen);
+        let selected = self.selected;
+
+        if players.btnp3(Scancode::Up) && selected > 0 {
+            self.select(selected - 1, rows);
+        }
+        if players.btnp3(Scancode::Down) {
+            self.select(selected + 1, rows);
+        }
+        if players.btnp3(Scancode::PageUp) {
+            self.select(selected.saturating_sub(rows), rows);
+        }
+        if players.btnp3(Scancode::PageDown) {
+            self.select(selected + rows, rows);
+        }
+
+        let wheel = -players.mouse_wheel();
+        if wheel != 0 {
+            let last = self.nodes.len().saturating_sub(rows);
+            self.scroll = cmp::min(cmp::max(self.scroll as i32 + wheel, 0) as usize, last);
+        }
+
+        let selected = self.selected;
+        if players.btnp3(Scancode::Return) || players.btnp3(Scancode::Right) {
+            self.expand(selected, fetch);
+        }
+        if players.btnp3(Scancode::Left) {
+            let expanded = self.nodes.get(selected).map(|node| node.expanded).unwrap_or(false);
+            if expanded {
+                self.collapse(selected);
+            } else if let Some(parent) = self.parent(selected) {
+                self.collapse(parent);
+                self.select(parent, rows);
+            }
+        }
+
+        let click = players.mouse_btn(0) && !self.clicked;
+        self.clicked = players.mouse_btn(0);
+        if click {
+            let y = players.mouse_coordinate(1);
+            let row = (y - 2) / LINE_HEIGHT - 1;
+            if row >= 0 && (row as usize) < rows {
+                let idx = self.scroll + row as usize;
+                if idx < self.nodes.len() {
+                    self.select(idx, rows);
+                    if self.nodes[idx].expanded {
+                        self.collapse(idx);
+                    } else {
+                        self.expand(idx, fetch);
+                    }
+                }
+            }
+        }
+
+        if players.get_text().chars().any(|c| c == 'r' || c == 'R') {
+            self.refresh(fetch);
+            let selected = self.selected;
+            self.select(selected, rows);
+        }
+    }
+
+    /// Title and the visible nodes, indented by their depth
+    pub fn get_lines(&self, rows: usize) -> Vec<String> {
+        let mut lines = vec![format!("VARIABLES {} (R: REFRESH)", self.nodes.len())];
+        if let Some(ref e) = self.error {
+            lines.push(e.clone());
+            return lines;
+        }
+
+        for node in self.nodes.iter().skip(self.scroll).take(rows) {
+            let marker = if !node.variable.is_expandable() {
+                " "
+            } else if node.expanded {
+                "-"
+            } else {
+                "+"
+            };
+
+            let indent: String = ::std::iter::repeat(' ').take(node.depth * 2).collect();
+            let line = if node.variable.kind == "function" {
+                format!("{}{}{} ()", indent, marker, node.variable.name)
+            } else {
+                format!("{}{}{} = {}", indent, marker, node.variable.name, node.variable.value)
+            };
+            lines.push(line);
+        }
+        lines
+    }
+
+    pub fn draw(&self, screen: &mut Screen) {
+        if !self.enabled {
+            return;
+        }
+
+        let rows = self.rows(screen);
+        let lines = self.get_lines(rows);
+
+        let screen_width = screen.width as i32;
+        let screen_height = screen.height as i32;
+        for j in 0..screen_height {
+            for i in 0..screen_width {
+                screen.putpixel_direct(i, j, self.background);
+            }
+        }
+
+        for (idx, line) in lines.iter().enumerate() {
+            let y = 2 + idx as i32 * LINE_HEIGHT;
+            let color = if idx == 0 {
+                self.table_color
+            } else if self.error.is_none() && self.scroll + idx - 1 == self.selected {
+                self.selected_color
+            } else if self.error.is_none() &&
+                      self.nodes[self.scroll + idx - 1].variable.is_expandable() {
+                self.table_color
+            } else {
+                self.color
+            };
+            screen.force_print(line.clone(), 2, y, color as i32);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_variables, Inspector};
+
+    fn fetch(path: &[String]) -> Result<String, String> {
+        match path.len() {
+            0 => {
+                Ok("number\t\"score\"\tscore\t12\ntable\t\"player\"\tplayer\t{2}\n\
+                    function\t\"_draw\"\t_draw\tfunction"
+                           .to_string())
+            }
+            1 => Ok("number\t\"x\"\tx\t3\ntable\t\"pos\"\tpos\t{1}".to_string()),
+            _ => Ok("number\t1\t1\t5".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_parse_variables() {
+        let variables = parse_variables("string\t\"b\"\tb\t\"hello\"\nnumber\t2\t2\t1\n\
+                                         number\t10\t10\t1\ninvalid\nnumber\t\"a\"\ta\t0");
+        let names: Vec<&str> = variables.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, vec!["2", "10", "a", "b"]);
+        assert!(!variables[0].is_expandable());
+    }
+
+    #[test]
+    fn test_inspector() {
+        let mut inspector = Inspector::new();
+        inspector.refresh(&mut fetch);
+        assert_eq!(inspector.nodes.len(), 3);
+        assert_eq!(inspector.nodes[0].variable.name, "_draw");
+        assert_eq!(inspector.nodes[1].variable.name, "player");
+
+        inspector.expand(1, &mut fetch);
+        assert_eq!(inspector.nodes.len(), 5);
+        assert_eq!(inspector.nodes[2].path, vec!["\"player\"".to_string(), "\"pos\"".to_string()]);
+        inspector.expand(2, &mut fetch);
+        assert_eq!(inspector.nodes.len(), 6);
+        assert_eq!(inspector.nodes[3].depth, 2);
+
+        // Still expanded after a refresh
+        inspector.selected = 4;
+        inspector.refresh(&mut fetch);
+        assert_eq!(inspector.nodes.len(), 6);
+        assert_eq!(inspector.selected, 4);
+
+        let lines = inspector.get_lines(10);
+        assert_eq!(lines[1], " _draw ()");
+        assert_eq!(lines[2], "-player = {2}");
+        assert_eq!(lines[4], "     1 = 5");
+
+        inspector.collapse(1);
+        assert_eq!(inspector.nodes.len(), 3);
+        assert_eq!(inspector.selected, 1);
+        assert_eq!(inspector.get_lines(10)[2], "+player = {2}");
+
+        let mut failing = |_: &[String]| -> Result<String, String> { Err("No code".to_string()) };
+        inspector.refresh(&mut failing);
+        assert!(inspector.nodes.is_empty());
+        assert_eq!(inspector.get_lines(10)[1], "No code");
+    }
+}
@@ -0,0 +1,293 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::PathBuf;
+
+use regex::Regex;
+
+use cartridge::CartridgeLibs;
+
+/// Prefix of the shared libraries in `require`
+pub const LIBRARY_PREFIX: &'static str = "lib:";
+
+/// Names of the libraries, they are folders of the library folder
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() &&
+    name.chars().all(|c| (c.is_alphanumeric() && c.is_ascii()) || c == '_' || c == '-')
+}
+
+/// Dotted numbers like "1.10", they are files of the folder of a library
+pub fn is_valid_version(version: &str) -> bool {
+    version.split('.').all(|part| !part.is_empty() && part.chars().all(|c| c.is_digit(10)))
+}
+
+/// A shared library required by a cartridge, like "lib:vector@1.2"
+#[derive(Clone, Debug, PartialEq)]
+pub struct LibraryRef {
+    pub name: String,
+    pub version: Option<String>,
+}
+
+impl LibraryRef {
+    pub fn parse(value: &str) -> Option<LibraryRef> {
+        if !value.starts_with(LIBRARY_PREFIX) {
+            return None;
+        }
+
+        let mut parts = value[LIBRARY_PREFIX.len()..].splitn(2, '@');
+        let name = parts.next().unwrap_or("").trim();
+        let version = parts.next().map(|version| version.trim());
+        if !is_valid_name(name) || !version.map(is_valid_version).unwrap_or(true) {
+            return None;
+        }
+
+        Some(LibraryRef {
+                 name: name.to_string(),
+                 version: version.map(|version| version.to_string()),
+             })
+    }
+}
+
+/// All the libraries in `require "lib:..."`/`require("lib:...")` of the code
+pub fn find_requires(code: &str) -> Vec<String> {
+    let re = Regex::new(r#"require\s*\(?\s*["'](lib:[\w\.\-@]+)["']"#).unwrap();
+
+    let mut requires = Vec::new();
+    for capture in re.captures_iter(code) {
+        let value = capture[1].to_string();
+        if !requires.contains(&value) {
+            requires.push(value);
+        }
+    }
+    requires
+}
+
+/// Compare "1.10" > "1.2", the non numeric parts are compared as strings
+fn version_key(version: &str) -> Vec<(u32, String)> {
+    version.split('.')
+        .map(|part| (part.parse::<u32>().unwrap_or(0), part.to_string()))
+        .collect()
+}
+
+/// Local folder of the shared libraries, stored as <dir>/<name>/<version>.lua
+pub struct Libraries {
+    pub dir: PathBuf,
+}
+
+impl Libraries {
+    pub fn new() -> Libraries {
+        Libraries { dir: PathBuf::from("libs") }
+    }
+
+    pub fn set_dir(&mut self, dir: &str) {
+        info!("[Unicorn][LIBS] Library folder {:?}", dir);
+        self.dir = PathBuf::from(dir);
+    }
+
+    pub fn versions(&self, name: &str) -> Vec<String> {
+        let mut versions = Vec::new();
+        if !is_valid_name(name) {
+            return versions;
+        }
+
+        if let Ok(entries) = fs::read_dir(self.dir.join(name)) {
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let path = entry.path();
+                if path.extension().map(|ext| ext == "lua").unwrap_or(false) {
+                    if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
+                        if is_valid_version(stem) {
+                            versions.push(stem.to_string());
+                        }
+                    }
+                }
+            }
+        }
+
+        versions.sort_by(|a, b| version_key(a).cmp(&version_key(b)));
+        versions
+    }
+
+    /// Version to use: the one of the `require`, else the one pinned in the
+    /// cartridge, else the latest one (pinned in the cartridge from now on)
+    pub fn resolve_version(&self,
+                           library: &LibraryRef,
+                           pins: &mut CartridgeLibs)
+                           -> Result<String, String> {
+        if let Some(ref version) = library.version {
+            return Ok(version.clone());
+        }
+
+        if let Some(version) = pins.get(&library.name) {
+            if !is_valid_version(&version) {
+                return Err(format!("Invalid version {:?} of {:?}", version, library.name));
+            }
+            return Ok(version);
+        }
+
+        match self.versions(&library.name).pop() {
+            Some(version) => {
+                info!("[Unicorn][LIBS] Pinning {:?} to {:?}", library.name, version);
+                pins.set(&library.name, &version);
+                Ok(version)
+            }
+            None => Err(format!("No version of {:?} in {:?}", library.name, self.dir)),
+        }
+    }
+
+    pub fn load(&self, library: &LibraryRef, pins: &mut CartridgeLibs) -> Result<String, String> {
+        let version = try!(self.resolve_version(library, pins));
+        if !is_valid_name(&library.name) || !is_valid_version(&version) {
+            return Err(format!("Invalid library {:?}@{:?}", library.name, version));
+        }
+
+        let filename = self.dir.join(&library.name).join(format!("{}.lua", version));
+
+        // A link of the library folder can't lead out of it
+        let inside = match (self.dir.canonicalize(), filename.canonicalize()) {
+            (Ok(dir), Ok(path)) => path.starts_with(&dir),
+            _ => true,
+        };
+        if !inside {
+            return Err(format!("{:?} is not in the library folder {:?}", filename, self.dir));
+        }
+
+        let mut data = String::new();
+        match File::open(&filename) {
+            Ok(mut f) => {
+                if let Err(e) = f.read_to_string(&mut data) {
+                    return Err(format!("Failed to read {:?}: {}", filename, e));
+                }
+            }
+            Err(e) => return Err(format!("Failed to open {:?}: {}", filename, e)),
+        }
+
+        Ok(data)
+    }
+
+    /// Embed the code of the libraries required by `code` in the cartridge,
+    /// for the exports run without the library folder. Return the number of
+    /// libraries embedded.
+    pub fn embed(&self, code: &str, libs: &mut CartridgeLibs) -> Result<usize, String> {
+        let requires = find_requires(code);
+
+        for value in requires.iter() {
+            let library = match LibraryRef::parse(value) {
+                Some(library) => library,
+                None => return Err(format!("Invalid library {:?}", value)),
+            };
+
+            let data = try!(self.load(&library, libs));
+            libs.embed(value, &data);
+        }
+
+        Ok(requires.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+
+    use cartridge::CartridgeLibs;
+
+    use super::{find_requires, version_key, Libraries, LibraryRef};
+
+    #[test]
+    fn test_library_ref() {
+        assert_eq!(LibraryRef::parse("lib:vector@1.2"),
+                   Some(LibraryRef {
+                            name: "vector".to_string(),
+                            version: Some("1.2".to_string()),
+                        }));
+        assert_eq!(LibraryRef::parse("lib:vector").unwrap().version, None);
+        assert_eq!(LibraryRef::parse("vector"), None);
+        assert_eq!(LibraryRef::parse("lib:"), None);
+
+        // Only names and numbers, not paths
+        assert_eq!(LibraryRef::parse("lib:../secret"), None);
+        assert_eq!(LibraryRef::parse("lib:vector@../../secret"), None);
+        assert_eq!(LibraryRef::parse("lib:vector@1..2"), None);
+        assert_eq!(LibraryRef::parse("lib:vec/tor"), None);
+        assert_eq!(LibraryRef::parse("lib:my_vector-2@10.0.1").unwrap().name, "my_vector-2");
+    }
+
+    #[test]
+    fn test_load_traversal() {
+        let dir = env::temp_dir().join("unicorn-test-libs-traversal");
+        fs::create_dir_all(dir.join("libs").join("vector")).unwrap();
+        let mut f = File::create(dir.join("secret.lua")).unwrap();
+        f.write_all(b"return \"secret\"\n").unwrap();
+
+        let mut libraries = Libraries::new();
+        libraries.set_dir(&dir.join("libs").to_string_lossy());
+
+        // The pins of the cartridge are checked too
+        let mut libs = CartridgeLibs::new(&["vector ../../secret".to_string(), ".. 1.0".to_string()]);
+        assert!(libs.pins.is_empty());
+        libs.pins.push(("vector".to_string(), "../../secret".to_string()));
+        let vector = LibraryRef::parse("lib:vector").unwrap();
+        assert!(libraries.load(&vector, &mut libs).is_err());
+
+        let parent = LibraryRef {
+            name: "..".to_string(),
+            version: Some("1".to_string()),
+        };
+        assert!(libraries.load(&parent, &mut CartridgeLibs::empty()).is_err());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::symlink;
+
+            let link = dir.join("libs").join("vector").join("1.0.lua");
+            let _ = fs::remove_file(&link);
+            symlink(dir.join("secret.lua"), &link).unwrap();
+            let vector = LibraryRef::parse("lib:vector@1.0").unwrap();
+            assert!(libraries.load(&vector, &mut CartridgeLibs::empty()).is_err());
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_find_requires() {
+        let code = r#"local v = require "lib:vector@1.2"
+                      local t = require("lib:tween")
+                      local s = require "string"
+                      local w = require 'lib:vector@1.2'"#;
+        assert_eq!(find_requires(code),
+                   vec!["lib:vector@1.2".to_string(), "lib:tween".to_string()]);
+    }
+
+    #[test]
+    fn test_embed() {
+        let dir = env::temp_dir().join("unicorn-test-libs");
+        fs::create_dir_all(dir.join("vector")).unwrap();
+        for version in ["1.2", "1.10"].iter() {
+            let mut f = File::create(dir.join("vector").join(format!("{}.lua", version))).unwrap();
+            f.write_all(format!("return \"{}\"\n", version).as_bytes()).unwrap();
+        }
+
+        let mut libraries = Libraries::new();
+        libraries.set_dir(&dir.to_string_lossy());
+
+        // The latest version is pinned, then embedded with its require
+        let mut libs = CartridgeLibs::empty();
+        let code = "local v = require \"lib:vector\"\nlocal w = require \"lib:vector@1.2\"";
+        assert_eq!(libraries.embed(code, &mut libs), Ok(2));
+        assert_eq!(libs.get("vector"), Some("1.10".to_string()));
+        assert_eq!(libs.embedded_code("lib:vector"), Some("return \"1.10\"\n".to_string()));
+        assert_eq!(libs.embedded_code("lib:vector@1.2"), Some("return \"1.2\"\n".to_string()));
+
+        assert!(libraries.embed("require \"lib:tween\"", &mut libs).is_err());
+        assert_eq!(libraries.embed("print(1)", &mut libs), Ok(0));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_version_order() {
+        assert!(version_key("1.10") > version_key("1.2"));
+        assert!(version_key("2.0") > version_key("1.10"));
+    }
+}
@@ -0,0 +1,410 @@
+//! Memory viewer (Shift+F10): hex view of the memory of `peek`/`poke`, one
+//! region at a time. The game is paused while it's open, with its frame kept
+//! under it, so its keys and clicks aren't seen by the game. The mouse wheel
+//! and PageUp/Down scroll, Tab switches the region, a click selects a byte
+//! and the hex digits typed poke it.
+
+use std::cmp;
+
+use config::Players;
+use config::scancode::Scancode;
+use gfx::Screen;
+use gfx::memory;
+
+const LINE_HEIGHT: i32 = 8;
+/// Hex digits of an address, and of a byte
+const ADDRESS_DIGITS: usize = 5;
+const DIGITS: usize = 2;
+
+// Dithered background, like the help overlay
+const DITHER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Regions of the memory, see `gfx::memory`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Region {
+    Sprites,
+    Flags,
+    Screen,
+    User,
+    Map,
+}
+
+pub const REGIONS: [Region; 5] = [Region::Sprites, Region::Flags, Region::Screen, Region::User, Region::Map];
+
+impl Region {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Region::Sprites => "SPRITES",
+            Region::Flags => "FLAGS",
+            Region::Screen => "SCREEN",
+            Region::User => "USER",
+            Region::Map => "MAP",
+        }
+    }
+
+    pub fn addr(&self) -> usize {
+        match *self {
+            Region::Sprites => memory::SPRITES_ADDR,
+            Region::Flags => memory::FLAGS_ADDR,
+            Region::Screen => memory::SCREEN_ADDR,
+            Region::User => memory::USER_ADDR,
+            Region::Map => memory::MAP_ADDR,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match *self {
+            Region::Sprites => memory::SPRITES_LEN,
+            Region::Flags => memory::FLAGS_LEN,
+            Region::Screen => memory::SCREEN_LEN,
+            Region::User => memory::USER_LEN,
+            Region::Map => memory::MAP_LEN,
+        }
+    }
+
+    /// Byte `offset` of the region, with `peek`
+    pub fn read(&self, screen: &Screen, offset: usize) -> u8 {
+        memory::peek(screen, self.addr() + offset)
+    }
+
+    pub fn write(&self, screen: &mut Screen, offset: usize, value: u8) {
+        memory::poke(screen, self.addr() + offset, value)
+    }
+}
+
+pub struct MemoryViewer {
+    pub enabled: bool,
+    pub region: Region,
+    /// First row displayed
+    pub scroll: usize,
+    /// Byte of the region poked by the digits typed
+    pub selected: Option<usize>,
+    /// Digits typed in the selected cell
+    typed: usize,
+    /// Frame of the game under the viewer, with the pokes of the screen
+    frame: Vec<u32>,
+    pub background: u32,
+    pub color: u32,
+    pub address_color: u32,
+    pub selected_color: u32,
+}
+
+impl MemoryViewer {
+    pub fn new() -> MemoryViewer {
+        MemoryViewer {
+            enabled: false,
+            region: Region::Sprites,
+            scroll: 0,
+            selected: None,
+            typed: 0,
+            frame: Vec::new(),
+            background: 0,
+            color: 6,
+            address_color: 13,
+            selected_color: 10,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    pub fn save_frame(&mut self, screen: &Screen) {
+        self.frame = screen.frame_buffer.clone();
+    }
+
+    /// The viewer isn't drawn again over the previous one, nor read in the
+    /// screen region
+    pub fn restore_frame(&self, screen: &mut Screen) {
+        if self.frame.len() == screen.frame_buffer.len() {
+            screen.frame_buffer.copy_from_slice(&self.frame);
+        }
+    }
+
+    pub fn next_region(&mut self) {
+        let idx = REGIONS.iter().position(|region| *region == self.region).unwrap_or(0);
+        self.region = REGIONS[(idx + 1) % REGIONS.len()];
+        self.scroll = 0;
+        self.selected = None;
+        self.typed = 0;
+    }
+
+    /// Bytes in a row, a power of 2 fitting in the screen
+    pub fn columns(&self, screen: &Screen) -> usize {
+        let char_width = cmp::max(screen.text_width("0"), 1) as usize;
+        let chars = screen.width.saturating_sub(4) / char_width;
+
+        let mut columns = 1;
+        while ADDRESS_DIGITS + columns * 2 * (DIGITS + 1) <= chars {
+            columns *= 2;
+        }
+        columns
+    }
+
+    /// Rows of cells between the title and the status line
+    pub fn rows(&self, screen: &Screen) -> usize {
+        cmp::max(screen.height as i32 - 4 - 2 * LINE_HEIGHT, LINE_HEIGHT) as usize /
+        LINE_HEIGHT as usize
+    }
+
+    pub fn scroll_by(&mut self, screen: &Screen, rows: i32) {
+        let columns = self.columns(screen);
+        let total = (self.region.len() + columns - 1) / columns;
+        let last = total.saturating_sub(self.rows(screen)) as i32;
+
+        self.scroll = cmp::min(cmp::max(self.scroll as i32 + rows, 0), last) as usize;
+    }
+
+    /// Byte under the pixel x/y of the screen
+    pub fn cell_at(&self, screen: &Screen, x: i32, y: i32) -> Option<usize> {
+        let char_width = screen.text_width("0");
+        let cell_width = (DIGITS as i32 + 1) * char_width;
+        let left = 2 + (ADDRESS_DIGITS as i32 + 1) * char_width;
+        let top = 2 + LINE_HEIGHT;
+
+        if x < left || y < top || char_width == 0 {
+            return None;
+        }
+
+        let column = ((x - left) / cell_width) as usize;
+        let row = ((y - top) / LINE_HEIGHT) as usize;
+        let columns = self.columns(screen);
+        let offset = (self.scroll + row) * columns + column;
+        if column < columns && row < self.rows(screen) && offset < self.region.len() {
+            Some(offset)
+        } else {
+            None
+        }
+    }
+
+    pub fn select(&mut self, offset: Option<usize>) {
+        if offset != self.selected {
+            self.selected = offset;
+            self.typed = 0;
+        }
+    }
+
+    /// The digit enters the selected byte from the right, the next byte is
+    /// selected once its 2 digits are typed
+    pub fn type_digit(&mut self, screen: &mut Screen, digit: u32) {
+        let offset = match self.selected {
+            Some(offset) => offset,
+            None => return,
+        };
+
+        let value = self.region.read(screen, offset);
+        self.region.write(screen, offset, (value << 4) | (digit & 0xF) as u8);
+
+        self.typed += 1;
+        if self.typed == DIGITS {
+            self.typed = 0;
+            if offset + 1 < self.region.len() {
+                self.selected = Some(offset + 1);
+            }
+        }
+    }
+
+    /// Keys and clicks of the viewer, the pokes of the screen go in the frame
+    /// of the game
+    pub fn update(&mut self, screen: &mut Screen, players: &mut Players) {
+        self.restore_frame(screen);
+
+        if players.btnp3(Scancode::Tab) {
+            self.next_region();
+        }
+
+        let page = self.rows(screen) as i32;
+        let mut rows = -players.mouse_wheel();
+        if players.btnp3(Scancode::PageUp) {
+            rows -= page;
+        }
+        if players.btnp3(Scancode::PageDown) {
+            rows += page;
+        }
+        if rows != 0 {
+            self.scroll_by(screen, rows);
+        }
+
+        if players.mouse_btn(0) {
+            let (x, y) = (players.mouse_coordinate(0), players.mouse_coordinate(1));
+            let offset = self.cell_at(screen, x, y);
+            self.select(offset);
+        }
+
+        for c in players.get_text().chars() {
+            if let Some(digit) = c.to_digit(16) {
+                self.type_digit(screen, digit);
+            }
+        }
+
+        self.save_frame(screen);
+    }
+
+    /// Title, rows of bytes with their address and value of the selected
+    /// byte, read before drawing over the screen
+    pub fn get_lines(&self, screen: &Screen) -> Vec<String> {
+        let columns = self.columns(screen);
+        let len = self.region.len();
+        let addr = self.region.addr();
+
+        let mut lines = vec![format!("{} {:X} BYTES (TAB)", self.region.name(), len)];
+        for row in self.scroll..self.scroll + self.rows(screen) {
+            let start = row * columns;
+            if start >= len {
+                break;
+            }
+
+            let mut line = format!("{:01$X}", addr + start, ADDRESS_DIGITS);
+            for offset in start..cmp::min(start + columns, len) {
+                line.push_str(&format!(" {:01$X}", self.region.read(screen, offset), DIGITS));
+            }
+            lines.push(line);
+        }
+
+        lines.push(match self.selected {
+                       Some(offset) => {
+                           let value = self.region.read(screen, offset);
+                           format!("{:01$X}: {2} (0-F: POKE)", addr + offset, ADDRESS_DIGITS, value)
+                       }
+                       None => "CLICK: SELECT".to_string(),
+                   });
+        lines
+    }
+
+    pub fn draw(&mut self, screen: &mut Screen) {
+        if !self.enabled {
+            return;
+        }
+
+        self.restore_frame(screen);
+        let lines = self.get_lines(screen);
+
+        let screen_width = screen.width as i32;
+        let screen_height = screen.height as i32;
+        for j in 0..screen_height {
+            for i in 0..screen_width {
+                if DITHER[(j % 4) as usize][(i % 4) as usize] < 12 {
+                    screen.putpixel_direct(i, j, self.background);
+                }
+            }
+        }
+
+        let char_width = screen.text_width("0");
+        let cell_width = (DIGITS as i32 + 1) * char_width;
+        let columns = self.columns(screen);
+        let last = lines.len() - 1;
+
+        for (idx, line) in lines.iter().enumerate() {
+            let y = 2 + idx as i32 * LINE_HEIGHT;
+            if idx == 0 || idx == last {
+                let y = if idx == last { screen_height - 2 - LINE_HEIGHT } else { y };
+                screen.force_print(line.clone(), 2, y, self.address_color as i32);
+                continue;
+            }
+
+            screen.force_print(line[..ADDRESS_DIGITS].to_string(), 2, y, self.address_color as i32);
+            screen.force_print(line[ADDRESS_DIGITS..].to_string(),
+                               2 + ADDRESS_DIGITS as i32 * char_width,
+                               y,
+                               self.color as i32);
+        }
+
+        // Printed again over the row in another color
+        if let Some(offset) = self.selected {
+            let row = (offset / columns) as i32 - self.scroll as i32;
+            if row >= 0 && (row as usize) < self.rows(screen) {
+                let x = 2 + (ADDRESS_DIGITS as i32 + 1) * char_width + (offset % columns) as i32 * cell_width;
+                let y = 2 + (row + 1) * LINE_HEIGHT;
+                let value = self.region.read(screen, offset);
+                screen.force_print(format!("{:01$X}", value, DIGITS),
+                                   x,
+                                   y,
+                                   self.selected_color as i32);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gfx::{Screen, Sprite};
+    use gfx::memory;
+
+    use super::{MemoryViewer, Region};
+
+    #[test]
+    fn test_memory_regions() {
+        let mut screen = Screen::new(128, 128);
+        screen.init();
+        screen.set_sprites(vec![Sprite::new([0; 64]), Sprite::new([0; 64])]);
+
+        // The bytes of peek/poke
+        assert_eq!(Region::Sprites.len(), memory::SPRITES_LEN);
+        Region::Sprites.write(&mut screen, 9, 12);
+        assert_eq!(screen.sprites[1].data[1], 12);
+        assert_eq!(Region::Sprites.read(&screen, 9), 12);
+
+        Region::Flags.write(&mut screen, 1, 5);
+        assert_eq!(screen.sprites[1].flags, 5);
+
+        Region::Map.write(&mut screen, 6, 0x34);
+        Region::Map.write(&mut screen, 7, 0x12);
+        assert_eq!(screen.mget(3, 0), 0x1234);
+        assert_eq!(memory::peek(&screen, memory::MAP_ADDR + 7), 0x12);
+
+        Region::User.write(&mut screen, 2, 9);
+        assert_eq!(memory::peek(&screen, memory::USER_ADDR + 2), 9);
+
+        Region::Screen.write(&mut screen, 130, 7);
+        assert_eq!(screen.frame_buffer[130], 7);
+    }
+
+    #[test]
+    fn test_memory_viewer() {
+        let mut screen = Screen::new(128, 128);
+        screen.init();
+        screen.set_sprites(vec![Sprite::new([1; 64])]);
+
+        let mut viewer = MemoryViewer::new();
+        assert_eq!(viewer.columns(&screen), 8);
+        let lines = viewer.get_lines(&screen);
+        assert_eq!(lines[0], "SPRITES 17700 BYTES (TAB)");
+        assert_eq!(lines[1], "00000 01 01 01 01 01 01 01 01");
+        assert_eq!(lines[lines.len() - 1], "CLICK: SELECT");
+
+        // First byte of the second row
+        let offset = viewer.cell_at(&screen, 2 + 6 * 4, 2 + 2 * 8);
+        assert_eq!(offset, Some(8));
+
+        // Second row of the first sprite
+        viewer.select(Some(400));
+        viewer.type_digit(&mut screen, 0xA);
+        viewer.type_digit(&mut screen, 0xB);
+        assert_eq!(screen.sprites[0].data[8], 0xAB);
+        assert_eq!(viewer.selected, Some(401));
+        assert_eq!(viewer.get_lines(&screen).last().unwrap(), "00191: 1 (0-F: POKE)");
+
+        viewer.scroll_by(&screen, -4);
+        assert_eq!(viewer.scroll, 0);
+        viewer.scroll_by(&screen, 1000000);
+        assert_eq!(viewer.scroll, memory::SPRITES_LEN / 8 - viewer.rows(&screen));
+
+        viewer.next_region();
+        assert_eq!(viewer.region, Region::Flags);
+        assert_eq!(viewer.selected, None);
+        assert_eq!(viewer.get_lines(&screen)[1], "18000 00 00 00 00 00 00 00 00");
+
+        // The frame of the game is kept under the viewer, with the pokes
+        screen.frame_buffer[0] = 3;
+        viewer.save_frame(&screen);
+        viewer.region = Region::Screen;
+        viewer.select(Some(1));
+        viewer.type_digit(&mut screen, 0);
+        viewer.type_digit(&mut screen, 9);
+        viewer.save_frame(&screen);
+        screen.frame_buffer[0] = 12;
+        screen.frame_buffer[1] = 12;
+        viewer.restore_frame(&mut screen);
+        assert_eq!(&screen.frame_buffer[..2], &[3, 9]);
+    }
+}
@@ -4,10 +4,46 @@ pub mod cartdata;
 pub mod emscripten;
 pub mod noise;
 pub mod math;
+pub mod fixed;
 pub mod packet;
 pub mod wfc;
 pub mod bump;
 pub mod utils;
+pub mod sandbox;
+pub mod crash;
+pub mod overlay;
+pub mod memview;
+pub mod audioview;
+pub mod watch;
+pub mod inspector;
+pub mod console;
+pub mod libraries;
+pub mod profile;
+pub mod classroom;
+pub mod tutorial;
+pub mod score;
+pub mod format;
+pub mod timers;
+pub mod entities;
+pub mod physics;
+pub mod pathfind;
+pub mod record;
+pub mod display;
+pub mod handheld;
+pub mod video;
+pub mod output;
+pub mod script_cache;
+pub mod preview;
+pub mod headless;
+pub mod permissions;
+pub mod flash;
+pub mod effects;
+pub mod random;
+pub mod deprecation;
+pub mod determinism;
+pub mod remote;
+pub mod devserver;
+pub mod trace;
 
 use std::collections::HashMap;
 use std::io::Cursor;
@@ -22,24 +58,58 @@ use gif;
 use gif::SetParameter;
 
 use std::io::prelude::*;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use std::path::Path;
-use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::fs::{self, File};
 
 use plugins::lua_plugin::plugin::LuaPlugin;
 use plugins::python_plugin::plugin::PythonPlugin;
 use plugins::javascript_plugin::plugin::JavascriptPlugin;
 
 use config::Players;
+use config::keys::PX8Key;
+use config::macros::MacroState;
+use config::switch::SCAN_KEYS;
 use self::noise::Noise;
+use self::sandbox::Sandbox;
+use self::libraries::{Libraries, LibraryRef};
+use self::profile::Profile;
+use self::script_cache::ScriptCache;
+use self::classroom::Classroom;
+use self::tutorial::Tutorial;
+use self::overlay::{HelpOverlay, Magnifier, PermissionPrompt, StatsOverlay};
+use self::memview::MemoryViewer;
+use self::audioview::AudioViewer;
+use self::watch::Watches;
+use self::inspector::Inspector;
+use self::console::{Console, Request};
+use self::record::{capture_filename, Record, RecordConfig, CONSOLE_FPS};
+use self::display::DisplayConfig;
+use self::video::VideoRecord;
+use self::output::{Frame, Output, Outputs};
+use self::preview::Preview;
+use self::headless::InputScript;
+use self::flash::FlashLimiter;
+use self::remote::{Remote, RemoteCommand, RemoteReply};
+use self::devserver::{error_location, DevCommand, DevServer};
+use serde_json::{Map, Value};
 use gfx;
-use cartridge::{Cartridge, CartridgeFormat};
+use cartridge::{png, Cartridge, CartridgeFormat};
 use sound::sound::{Sound, SoundInternal};
 use chiptune::chiptune;
 
 include!(concat!(env!("OUT_DIR"), "/parameters.rs"));
 
+/// First color of the extended palette, shown with `pal(c, EXTENDED_COLOR + i, 1)`
+pub const EXTENDED_COLOR: u32 = 128;
+
+/// Hidden colors of the extended palette (the secret colors of PICO-8): the
+/// sprites and the map keep 16 colors, the display palette picks up to 32
+pub const EXTENDED_PALETTE: [u32; 16] = [0x291814, 0x111d35, 0x422136, 0x125359, 0x742f29, 0x49333b,
+                                         0xa28879, 0xf3ef7d, 0xbe1250, 0xff6c24, 0xa8e72e, 0x00b543,
+                                         0x065ab5, 0x754665, 0xff6e59, 0xff9d81];
+
 #[inline]
 pub fn clamp<T: PartialOrd>(val: T, min: T, max: T) -> T {
     if val > min {
@@ -102,9 +172,7 @@ impl Palette {
     }
 
     pub fn set_color(&mut self, color: u32, r: u8, g: u8, b: u8) {
-        if color >= 16 {
-            self._set_color(color, r, g, b);
-        }
+        self._set_color(color, r, g, b);
     }
 
     pub fn set_colors(&mut self, colors: HashMap<u32, RGB>) {
@@ -113,6 +181,19 @@ impl Palette {
         }
     }
 
+    /// Colors of the palette sorted by index, as "index: #rrggbb" lines
+    pub fn dump(&self) -> String {
+        let mut indexes: Vec<&u32> = self.colors.keys().collect();
+        indexes.sort();
+
+        let mut data = String::new();
+        for idx in indexes {
+            let rgb = &self.colors[idx];
+            data.push_str(&format!("{}: #{:02x}{:02x}{:02x}\n", idx, rgb.r, rgb.g, rgb.b));
+        }
+        data
+    }
+
     pub fn get_color(&mut self, color: u32) -> u32 {
         match self.colors.get(&color) {
             Some(rgb_value) => {
@@ -129,7 +210,7 @@ lazy_static! {
     };
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct RGB {
     pub r: u8,
     pub g: u8,
@@ -164,6 +245,7 @@ pub enum UnicornState {
     INTERACTIVE,
 }
 
+#[derive(Debug)]
 pub enum Code {
     UNKNOWN = 0,
     LUA = 1,
@@ -256,6 +338,11 @@ pub struct PauseMenu {
     idx: u32,
     selected_idx: i32,
     items: Vec<String>,
+    /// Index (1 to 5) of the `menuitem` entries shown after "Continue"
+    custom: Vec<u32>,
+    /// Size of the window shown in the "Config" page
+    pub display: DisplayConfig,
+    config_idx: u32,
 }
 
 impl PauseMenu {
@@ -271,14 +358,35 @@ impl PauseMenu {
             idx: 0,
             selected_idx: -1,
             items: items.clone(),
+            custom: Vec::new(),
+            display: DisplayConfig::default(),
+            config_idx: 0,
         }
     }
 
+    /// Entries of the cartridge, between "Continue" and "Config"
+    pub fn set_custom_items(&mut self, items: &[Option<String>]) {
+        self.items.truncate(1);
+        self.custom.clear();
+
+        for (idx, item) in items.iter().enumerate() {
+            if let Some(ref label) = *item {
+                self.items.push(label.clone());
+                self.custom.push(idx as u32 + 1);
+            }
+        }
+
+        self.items.push("Config".to_string());
+        self.items.push("Menu".to_string());
+        self.items.push("Exit".to_string());
+    }
+
     pub fn reset(&mut self) {
         info!("[Unicorn][PauseMenu] Reset");
 
         self.selected_idx = -1;
         self.idx = 0;
+        self.config_idx = 0;
     }
 
     pub fn stop(&mut self) -> bool {
@@ -290,6 +398,59 @@ impl PauseMenu {
         self.selected_idx == self.items.len() as i32 - 2
     }
 
+    pub fn config(&self) -> bool {
+        self.selected_idx == 1 + self.custom.len() as i32
+    }
+
+    /// "Config" page: left/right change the size of the window or the filter, true when
+    /// it changed
+    pub fn update_config(&mut self, players: Arc<Mutex<Players>>) -> bool {
+        let mut players = players.lock().unwrap();
+        let previous = self.display.clone();
+
+        if players.btnp(0, 2) && self.config_idx > 0 {
+            self.config_idx -= 1;
+        }
+        if players.btnp(0, 3) && self.config_idx < 3 {
+            self.config_idx += 1;
+        }
+
+        if self.config_idx == 0 {
+            if players.btnp(0, 0) {
+                self.display = self.display.previous();
+            }
+            if players.btnp(0, 1) || players.btnp(0, 4) {
+                self.display = self.display.next();
+            }
+        } else if self.config_idx == 1 {
+            if players.btnp(0, 0) {
+                self.display = self.display.cycle_filter(-1);
+            }
+            if players.btnp(0, 1) || players.btnp(0, 4) {
+                self.display = self.display.cycle_filter(1);
+            }
+        } else if self.config_idx == 2 {
+            if players.btnp(0, 0) || players.btnp(0, 1) || players.btnp(0, 4) {
+                self.display = self.display.toggle_reduce_motion();
+            }
+        } else if players.btnp(0, 4) {
+            // Back to the entries of the pause menu
+            self.selected_idx = -1;
+            self.config_idx = 0;
+        }
+
+        self.display != previous
+    }
+
+    /// Index given to `menuitem` of the entry clicked
+    pub fn custom_selected(&self) -> Option<u32> {
+        if self.selected_idx >= 1 && self.selected_idx as usize <= self.custom.len() {
+            Some(self.custom[self.selected_idx as usize - 1])
+        } else {
+            None
+        }
+    }
+
     pub fn update(&mut self, players: Arc<Mutex<Players>>) -> bool {
         if players.lock().unwrap().btnp(0, 4) {
             self.selected_idx = self.idx as i32;
@@ -298,7 +459,7 @@ impl PauseMenu {
             }
         } else {
             if players.lock().unwrap().btnp(0, 2) {
-                self.idx = clamp(self.idx - 1, 0, (self.items.len() as u32) - 1);
+                self.idx = clamp(self.idx.saturating_sub(1), 0, (self.items.len() as u32) - 1);
             }
 
             if players.lock().unwrap().btnp(0, 3) {
@@ -311,19 +472,23 @@ impl PauseMenu {
 
     pub fn draw(&mut self, screen: &mut gfx::Screen) {
         if self.selected_idx == -1 {
-            let idx_x = (screen.width / 2 - 20) as i32;
+            // 4 pixels by character, for the labels of the cartridge
+            let longest = self.items.iter().map(|item| item.chars().count()).max().unwrap_or(0);
+            let width = max(40, 4 * longest as i32 + 10);
+
+            let idx_x = screen.width as i32 / 2 - width / 2;
             let idx_y = (screen.height / 2 - 10) as i32;
 
             screen.rectfill(idx_x,
                             idx_y - 5,
-                            idx_x + 40,
+                            idx_x + width,
                             idx_y + 10 * self.items.len() as i32,
                             11);
 
 
             screen.rect(idx_x - 1,
                         idx_y - 6,
-                        idx_x + 41,
+                        idx_x + width + 1,
                         idx_y + 1 + 10 * self.items.len() as i32,
                         0);
 
@@ -337,28 +502,25 @@ impl PauseMenu {
 
         }
 
-        if self.selected_idx == 1 {
+        if self.config() {
             screen.cls(-1);
-        }
-    }
-}
 
-pub struct Record {
-    pub recording: bool,
-    pub images: Vec<u8>,
-    pub filename: String,
-    pub nb: i32,
-}
-
-impl Record {
-    pub fn new() -> Record {
-        let images = Vec::new();
+            let width = 70;
+            let idx_x = screen.width as i32 / 2 - width / 2;
+            let idx_y = (screen.height / 2 - 10) as i32;
 
-        Record {
-            recording: false,
-            images: images,
-            filename: "".to_string(),
-            nb: 0,
+            screen.rectfill(idx_x, idx_y - 5, idx_x + width, idx_y + 40, 11);
+            screen.rect(idx_x - 1, idx_y - 6, idx_x + width + 1, idx_y + 41, 0);
+
+            screen.print(">".to_string(), idx_x, idx_y + (self.config_idx as i32) * 10, 3);
+            screen.print(format!("< {} >", self.display.label()), idx_x + 5, idx_y, 7);
+            screen.print(format!("< {} >", self.display.filter), idx_x + 5, idx_y + 10, 7);
+            screen.print(format!("< Flashes {} >",
+                                 if self.display.reduce_motion { "off" } else { "on" }),
+                         idx_x + 5,
+                         idx_y + 20,
+                         7);
+            screen.print("Back".to_string(), idx_x + 5, idx_y + 30, 7);
         }
     }
 }
@@ -521,11 +683,22 @@ impl Palettes {
         PALETTE.lock().unwrap().get_color(color)
     }
 
+    /// Colors saved in the cartridge: the ones added or replaced by the
+    /// cartridge, not the defaults of the 16 first and extended colors
     pub fn get_colors(&mut self) -> HashMap<u32, RGB> {
         let mut colors = HashMap::new();
+        let default = self.palettes.get("pico-8").cloned().unwrap_or(Vec::new());
 
         for (key, value) in PALETTE.lock().unwrap().colors.clone() {
-            if key >= 16 {
+            let changed = if key < 16 {
+                default.get(key as usize) != Some(&value)
+            } else if key >= EXTENDED_COLOR && key < EXTENDED_COLOR + 16 {
+                RGB::new_hexa(EXTENDED_PALETTE[(key - EXTENDED_COLOR) as usize]) != value
+            } else {
+                true
+            };
+
+            if changed {
                 colors.insert(key, value);
             }
         }
@@ -533,8 +706,15 @@ impl Palettes {
         colors
     }
 
+    /// Only the extended colors are left, the palette must be switched
     pub fn reset(&mut self) {
-        PALETTE.lock().unwrap().reset();
+        let mut palette = PALETTE.lock().unwrap();
+        palette.reset();
+
+        for (idx, value) in EXTENDED_PALETTE.iter().enumerate() {
+            let rgb = RGB::new_hexa(*value);
+            palette._set_color(EXTENDED_COLOR + idx as u32, rgb.r, rgb.g, rgb.b);
+        }
     }
 
     pub fn get_name(&mut self) -> String {
@@ -624,6 +804,24 @@ impl UnicornCartridge {
         }
     }
 
+    /// Children of a global for the inspector, listed by the plugin
+    pub fn inspect(&mut self, path: &[String]) -> Result<String, String> {
+        match self.get_code_type() {
+            Code::LUA => self.lua_plugin.inspect(path),
+            Code::PYTHON => self.python_plugin.inspect(path),
+            _ => Err("No variables in this language".to_string()),
+        }
+    }
+
+    /// Line of the developer console, run in the globals of the code
+    pub fn execute(&mut self, code: &str) -> Result<String, String> {
+        match self.get_code_type() {
+            Code::LUA => self.lua_plugin.execute(code),
+            Code::PYTHON => self.python_plugin.execute(code),
+            _ => Err("No console in this language".to_string()),
+        }
+    }
+
     pub fn get_code_type(&mut self) -> Code {
         match self.cartridge.code.get_name().as_ref() {
             "lua" => Code::LUA,
@@ -666,12 +864,41 @@ pub struct Unicorn {
     pub pause_menu: PauseMenu,
     pub fps: f64,
     pub record: Record,
+    pub video: VideoRecord,
+    pub outputs: Outputs,
+    pub preview: Preview,
     pub draw_return: bool,
     pub update_return: bool,
     pub mouse_spr: Vec<u8>,
     pub version: u32,
     pub major_version: u32,
     pub minor_version: u32,
+    pub sandbox: Sandbox,
+    pub libraries: Libraries,
+    pub profile: Profile,
+    pub classroom: Option<Classroom>,
+    pub tutorial: Option<Tutorial>,
+    pub stats_overlay: StatsOverlay,
+    pub magnifier: Magnifier,
+    pub memory_viewer: MemoryViewer,
+    pub audio_viewer: AudioViewer,
+    /// Expressions of `--watch` and of the editors, shown in a panel
+    pub watches: Watches,
+    /// Tree of the globals of the cartridge, the game is paused while it's open
+    pub inspector: Inspector,
+    pub console: Console,
+    pub help_overlay: HelpOverlay,
+    pub permission_prompt: PermissionPrompt,
+    /// Reduced flashing, when `reduce_motion` is set in the display config
+    pub flash_limiter: FlashLimiter,
+    /// Local HTTP API of `--remote-port`
+    pub remote: Option<Remote>,
+    /// Protocol of the external editors of `--dev-port`
+    pub dev_server: Option<DevServer>,
+    /// Resolution of the last frame, the records stop when it changes
+    pub mode: (usize, usize),
+    /// Count the calls of the cartridges which differ between two runs
+    pub watch_nondeterminism: bool,
 }
 
 impl Unicorn {
@@ -680,7 +907,8 @@ impl Unicorn {
 
         let sound_internal = Arc::new(Mutex::new(SoundInternal::new()));
         let csend = sound_internal.lock().unwrap().csend.clone();
-        let screen = Arc::new(Mutex::new(gfx::Screen::new(400, 240)));
+        let (width, height) = gfx::DEFAULT_MODE;
+        let screen = Arc::new(Mutex::new(gfx::Screen::new(width, height)));
 
         Unicorn {
             screen: screen.clone(),
@@ -702,15 +930,41 @@ impl Unicorn {
             menu: Menu::new(),
             fps: 0.0,
             record: Record::new(),
+            video: VideoRecord::new(),
+            outputs: Outputs::new(),
+            preview: Preview::new(width, height),
             draw_return: true,
             update_return: true,
             mouse_spr: Unicorn::mouse_sprite(),
             version: VERSION,
             major_version: MAJOR_VERSION,
             minor_version: MINOR_VERSION,
+            sandbox: Sandbox::new(),
+            libraries: Libraries::new(),
+            profile: Profile::new(),
+            classroom: None,
+            tutorial: None,
+            stats_overlay: StatsOverlay::new(),
+            magnifier: Magnifier::new(),
+            memory_viewer: MemoryViewer::new(),
+            audio_viewer: AudioViewer::new(),
+            watches: Watches::new(),
+            inspector: Inspector::new(),
+            console: Console::new(),
+            help_overlay: HelpOverlay::new(),
+            permission_prompt: PermissionPrompt::new(),
+            flash_limiter: FlashLimiter::new(),
+            remote: None,
+            dev_server: None,
+            mode: gfx::DEFAULT_MODE,
+            watch_nondeterminism: false,
         }
     }
 
+    pub fn set_safe_mode(&mut self, value: bool) {
+        self.sandbox.set_enabled(value);
+    }
+
     pub fn setup(&mut self) {
         info!("[Unicorn] Setup");
 
@@ -729,6 +983,84 @@ impl Unicorn {
         self.sound_internal.lock().unwrap().stop();
     }
 
+    pub fn set_library_dir(&mut self, dir: &str) {
+        self.libraries.set_dir(dir);
+    }
+
+    pub fn set_profile(&mut self, root: &str, name: &str) -> Result<(), String> {
+        self.profile.set(root, name)
+    }
+
+    /// Restrict the console to the cartridges of the classroom manifest
+    pub fn set_classroom(&mut self, classroom: Classroom) {
+        info!("[Unicorn][CLASSROOM] Allowed cartridges {:?}", classroom.carts);
+        self.classroom = Some(classroom);
+    }
+
+    /// Validate the steps of the tutorial with the calls of the Lua code
+    pub fn set_tutorial(&mut self, tutorial: Tutorial) {
+        info!("[Unicorn][TUTORIAL] {:?}, {:?} steps", tutorial.title, tutorial.steps.len());
+        self.tutorial = Some(tutorial);
+    }
+
+    fn tutorial_update(&mut self) {
+        let cartridge = match self.cartridges.get_mut(self.current_cartridge) {
+            Some(cartridge) => cartridge,
+            None => return,
+        };
+
+        if let Some(ref mut tutorial) = self.tutorial {
+            let lua_plugin = &mut cartridge.lua_plugin;
+            if tutorial.update(|name| lua_plugin.call_count(name)) {
+                lua_plugin.reset_calls();
+            }
+        }
+    }
+
+    pub fn tutorial_draw(&mut self) {
+        if let Some(ref tutorial) = self.tutorial {
+            tutorial.draw(&mut self.screen.lock().unwrap());
+        }
+    }
+
+    /// Resolve the `require "lib:name@version"` of the code from the code
+    /// embedded in the cartridge, else from the library folder
+    fn preload_libraries(&mut self, cartridge: &mut UnicornCartridge, data: &str) {
+        for value in libraries::find_requires(data) {
+            if let Some(code) = cartridge.cartridge.libs.embedded_code(&value) {
+                cartridge.lua_plugin.preload(&value, &code);
+                continue;
+            }
+
+            let library = match LibraryRef::parse(&value) {
+                Some(library) => library,
+                None => continue,
+            };
+
+            match self.libraries.load(&library, &mut cartridge.cartridge.libs) {
+                Ok(code) => {
+                    cartridge.lua_plugin.preload(&value, &code);
+                }
+                Err(e) => error!("[Unicorn] Failed to load the library {:?}: {}", value, e),
+            }
+        }
+    }
+
+    pub fn load_keybindings(&mut self, filename: &str) {
+        info!("[Unicorn] Load the keys of the players {:?}", filename);
+        if let Err(e) = self.players.lock().unwrap().keybindings.load(filename) {
+            error!("[Unicorn] Failed to load the keys of the players: {}", e);
+        }
+        if let Err(e) = self.players.lock().unwrap().joystick.load(filename) {
+            error!("[Unicorn] Failed to load the joystick mapping: {}", e);
+        }
+        if let Err(e) = self.players.lock().unwrap().switch.load(filename) {
+            error!("[Unicorn] Failed to load the switch: {}", e);
+        }
+
+        self.editor.load_keybindings(filename);
+    }
+
     pub fn toggle_debug(&mut self) {
         self.configuration.lock().unwrap().toggle_info_overlay();
     }
@@ -787,9 +1119,360 @@ impl Unicorn {
         }
     }
 
+    pub fn toggle_stats_overlay(&mut self) {
+        self.stats_overlay.toggle();
+    }
+
+    pub fn stats_draw(&mut self) {
+        let title = match self.cartridges.get(self.current_cartridge) {
+            Some(cartridge) => cartridge.filename.clone(),
+            None => "".to_string(),
+        };
+
+        self.stats_overlay
+            .draw(&mut self.screen.lock().unwrap(), &title, self.fps);
+    }
+
+    /// Answer of the player to the question of the cartridge, true while the
+    /// question is displayed and the game frozen
+    pub fn permission_update(&mut self) -> bool {
+        if self.info.lock().unwrap().permissions.request.is_none() {
+            return false;
+        }
+
+        let answer = {
+            let mut players = self.players.lock().unwrap();
+            self.permission_prompt
+                .update(players.btnp(0, 0),
+                        players.btnp(0, 1),
+                        players.btnp(0, 4),
+                        players.btnp(0, 5))
+        };
+        if let Some(allow) = answer {
+            self.info.lock().unwrap().permissions.answer(allow);
+        }
+        true
+    }
+
+    /// The question is drawn over the last frame of the cartridge
+    pub fn permission_draw(&mut self) -> bool {
+        let request = self.info.lock().unwrap().permissions.request.clone();
+        let request = match request {
+            Some(request) => request,
+            None => return false,
+        };
+
+        let title = match self.cartridges.get(self.current_cartridge) {
+            Some(cartridge) => cartridge.filename.clone(),
+            None => "".to_string(),
+        };
+        self.permission_prompt
+            .draw(&mut self.screen.lock().unwrap(), &title, request.permission);
+        true
+    }
+
+    /// Fade, flash and shake of the cartridge over its frame
+    pub fn effects_draw(&mut self) {
+        let mut info = self.info.lock().unwrap();
+        let reduce_motion = info.display.reduce_motion;
+
+        let mut screen = self.screen.lock().unwrap();
+        let width = screen.width;
+        info.effects.apply(&mut screen.frame_buffer, width, reduce_motion);
+    }
+
+    /// The frame of the cartridge without the effects of the last frame
+    pub fn effects_restore(&mut self) {
+        self.info
+            .lock()
+            .unwrap()
+            .effects
+            .restore(&mut self.screen.lock().unwrap().frame_buffer);
+    }
+
+    pub fn toggle_magnifier(&mut self) {
+        self.magnifier.toggle();
+    }
+
+    pub fn magnifier_draw(&mut self) {
+        let (mouse_x, mouse_y) = {
+            let mut players = self.players.lock().unwrap();
+            (players.mouse_coordinate(0), players.mouse_coordinate(1))
+        };
+
+        self.magnifier
+            .draw(&mut self.screen.lock().unwrap(), mouse_x, mouse_y);
+    }
+
+    pub fn toggle_memory_viewer(&mut self) {
+        self.memory_viewer.toggle();
+        if self.memory_viewer.enabled {
+            self.memory_viewer.save_frame(&self.screen.lock().unwrap());
+        }
+    }
+
+    /// Keys of the memory viewer, true while it's open and the game paused
+    pub fn memory_viewer_update(&mut self) -> bool {
+        if !self.memory_viewer.enabled {
+            return false;
+        }
+
+        self.memory_viewer
+            .update(&mut self.screen.lock().unwrap(), &mut self.players.lock().unwrap());
+        true
+    }
+
+    /// Hex view of the data of the running cartridge
+    pub fn memory_viewer_draw(&mut self) {
+        if self.state != UnicornState::RUN {
+            return;
+        }
+
+        self.memory_viewer.draw(&mut self.screen.lock().unwrap());
+    }
+
+    pub fn toggle_audio_viewer(&mut self) {
+        self.audio_viewer.toggle();
+    }
+
+    /// Levels of the channels, over the game and the editors
+    pub fn audio_viewer_draw(&mut self) {
+        if !self.audio_viewer.enabled ||
+           (self.state != UnicornState::RUN && self.state != UnicornState::EDITOR) {
+            return;
+        }
+
+        let status = self.sound.lock().unwrap().audio_status();
+        self.audio_viewer.update(status);
+        self.audio_viewer.draw(&mut self.screen.lock().unwrap());
+    }
+
+    /// Values of the watch expressions, once per frame after `_update`
+    pub fn watches_update(&mut self) {
+        if self.watches.is_empty() {
+            return;
+        }
+
+        let cartridge = match self.cartridges.get_mut(self.current_cartridge) {
+            Some(cartridge) => cartridge,
+            None => return,
+        };
+        match self.current_code_type {
+            Code::LUA => {
+                let plugin = &mut cartridge.lua_plugin;
+                self.watches.evaluate(|expression| plugin.eval(expression));
+            }
+            Code::PYTHON => {
+                let plugin = &mut cartridge.python_plugin;
+                self.watches.evaluate(|expression| plugin.eval(expression));
+            }
+            _ => {
+                self.watches
+                    .evaluate(|_| Err("No watches in this language".to_string()))
+            }
+        }
+    }
+
+    pub fn toggle_inspector(&mut self) {
+        self.inspector.toggle();
+        if self.inspector.enabled {
+            self.inspector_refresh();
+        }
+    }
+
+    /// Read the globals of the cartridge again
+    pub fn inspector_refresh(&mut self) {
+        if let Some(cartridge) = self.cartridges.get_mut(self.current_cartridge) {
+            self.inspector.refresh(&mut |path: &[String]| cartridge.inspect(path));
+        }
+    }
+
+    /// Keys of the inspector, true while it's open and the game paused
+    pub fn inspector_update(&mut self) -> bool {
+        if !self.inspector.enabled {
+            return false;
+        }
+
+        if let Some(cartridge) = self.cartridges.get_mut(self.current_cartridge) {
+            self.inspector.update(&self.screen.lock().unwrap(),
+                                  &mut self.players.lock().unwrap(),
+                                  &mut |path: &[String]| cartridge.inspect(path));
+        }
+        true
+    }
+
+    pub fn inspector_draw(&mut self) {
+        if self.state != UnicornState::RUN {
+            return;
+        }
+
+        self.inspector.draw(&mut self.screen.lock().unwrap());
+    }
+
+    /// Opened only over a running game, the backquote is typed in the editors
+    pub fn toggle_console(&mut self) {
+        if !self.console.enabled && self.state != UnicornState::RUN {
+            return;
+        }
+        self.console.toggle();
+
+        let mut screen = self.screen.lock().unwrap();
+        if self.console.enabled {
+            self.console.save_frame(&screen);
+        } else {
+            self.console.restore_frame(&mut screen);
+        }
+    }
+
+    /// Keys of the console and its line run in the cartridge, true while
+    /// it's open and the game paused
+    pub fn console_update(&mut self) -> bool {
+        if !self.console.enabled {
+            return false;
+        }
+
+        // Unlocked before the line is run, the functions it calls lock the
+        // screen and the players
+        let line = self.console.update(&self.screen.lock().unwrap(), &mut self.players.lock().unwrap());
+        if let Some(line) = line {
+            let watches = &mut self.watches;
+            if let Some(cartridge) = self.cartridges.get_mut(self.current_cartridge) {
+                self.console.submit(&line, &mut |request: Request| match request {
+                                        Request::Execute(code) => cartridge.execute(code),
+                                        Request::Globals => cartridge.inspect(&[]),
+                                        Request::Watch(expression) => {
+                                            watches
+                                                .add(expression)
+                                                .map(|_| format!("Watching {}", expression.trim()))
+                                        }
+                                        Request::Unwatch(Some(expression)) => {
+                                            if watches.remove(expression) {
+                                                Ok(format!("Unwatched {}", expression))
+                                            } else {
+                                                Err(format!("No watch {:?}", expression))
+                                            }
+                                        }
+                                        Request::Unwatch(None) => {
+                                            watches.clear();
+                                            Ok("No more watches".to_string())
+                                        }
+                                    });
+            }
+            self.console.save_frame(&self.screen.lock().unwrap());
+        }
+        true
+    }
+
+    pub fn console_draw(&mut self) {
+        if self.state != UnicornState::RUN {
+            return;
+        }
+
+        self.console.draw(&mut self.screen.lock().unwrap());
+    }
+
+    pub fn watches_draw(&mut self) {
+        if self.state != UnicornState::RUN {
+            return;
+        }
+
+        self.watches.draw(&mut self.screen.lock().unwrap());
+    }
+
+    /// Record the keys of the players, then bind them to the next key pressed
+    pub fn toggle_macro_record(&mut self) {
+        self.players.lock().unwrap().macros.toggle_record();
+    }
+
+    /// State of the macro being recorded, in the bottom-left corner
+    pub fn macro_draw(&mut self) {
+        let text = match self.players.lock().unwrap().macros.state {
+            MacroState::Recording(ref input_macro) => {
+                format!("REC MACRO {:.1}S", input_macro.frames as f64 / CONSOLE_FPS as f64)
+            }
+            MacroState::Binding(_) => "MACRO KEY? (ESC: CANCEL)".to_string(),
+            MacroState::Idle => return,
+        };
+
+        let mut screen = self.screen.lock().unwrap();
+        let y = screen.height as i32 - 8;
+        let width = screen.text_width(&text) + 2;
+        for j in y..y + 8 {
+            for i in 0..width {
+                screen.putpixel_direct(i, j, 0);
+            }
+        }
+        screen.force_print(text, 1, y + 1, 8);
+    }
+
+    /// Buttons scanned by the switch, in the bottom-right corner
+    pub fn switch_draw(&mut self) {
+        let (current, pressed) = {
+            let players = self.players.lock().unwrap();
+            if !players.switch.is_enabled() {
+                return;
+            }
+            (players.switch.current(), players.switch.pressed().is_some())
+        };
+
+        let mut screen = self.screen.lock().unwrap();
+        let y = screen.height as i32 - 8;
+        let labels: Vec<(PX8Key, String)> = SCAN_KEYS
+            .iter()
+            .map(|key| (*key, format!("{:?}", key)))
+            .collect();
+        let width = labels
+            .iter()
+            .fold(0, |width, &(_, ref label)| width + screen.text_width(label) + 2);
+
+        let mut x = screen.width as i32 - width;
+        for &(key, ref label) in labels.iter() {
+            let label_width = screen.text_width(label) + 2;
+            let (background, color) = if key != current {
+                (0, 6)
+            } else if pressed {
+                (8, 7)
+            } else {
+                (7, 0)
+            };
+
+            for j in y..y + 8 {
+                for i in x..x + label_width {
+                    screen.putpixel_direct(i, j, background);
+                }
+            }
+            screen.force_print(label.clone(), x + 1, y + 1, color);
+            x += label_width;
+        }
+    }
+
+    pub fn toggle_help(&mut self) {
+        self.help_overlay.toggle();
+    }
+
+    pub fn help_draw(&mut self) {
+        let players = self.players.lock().unwrap();
+        self.help_overlay
+            .draw(&mut self.screen.lock().unwrap(), &players.keybindings);
+    }
+
     pub fn update_time(&mut self, dt: Duration) {
         self.info.lock().unwrap().update(dt);
 
+        if self.state == UnicornState::RUN {
+            let seconds = dt.as_secs() as f64 + dt.subsec_nanos() as f64 / 1_000_000_000.0;
+            self.stats_overlay.update(seconds);
+            {
+                let mut screen = self.screen.lock().unwrap();
+                screen.pal_cycle_update(seconds);
+                screen.particles.update(seconds);
+            }
+
+            let mut info = self.info.lock().unwrap();
+            info.update_game(seconds);
+            info.timers.update(seconds);
+        }
+
         self.players
             .lock()
             .unwrap()
@@ -805,7 +1488,311 @@ impl Unicorn {
         }
     }
 
+    pub fn start_remote(&mut self, port: u16) {
+        match Remote::start(port) {
+            Ok(remote) => self.remote = Some(remote),
+            Err(e) => error!("[Unicorn][REMOTE] {}", e),
+        }
+    }
+
+    /// Requests of the remote tools, run before the frame
+    pub fn remote_update(&mut self) {
+        let (requests, released) = match self.remote {
+            Some(ref mut remote) => (remote.poll(), remote.update()),
+            None => return,
+        };
+
+        {
+            let mut players = self.players.lock().unwrap();
+            for (player, key) in released {
+                players.key_up_direct(player, key);
+            }
+        }
+
+        for request in requests {
+            let reply = self.remote_command(&request.command);
+            request.answer(reply);
+        }
+    }
+
+    fn remote_command(&mut self, command: &RemoteCommand) -> RemoteReply {
+        let mut fields = Map::new();
+
+        match *command {
+            RemoteCommand::Status => {
+                let cartridge = match self.cartridges.get(self.current_cartridge) {
+                    Some(cartridge) => cartridge.filename.clone(),
+                    None => "".to_string(),
+                };
+                fields.insert("state".to_string(),
+                              Value::from(format!("{:?}", self.state).to_lowercase()));
+                fields.insert("cartridge".to_string(), Value::from(cartridge));
+                fields.insert("fps".to_string(), Value::from(self.fps));
+            }
+            RemoteCommand::Load(ref filename) => {
+                info!("[Unicorn][REMOTE] Load {:?}", filename);
+                try!(self.load_checked(filename));
+            }
+            RemoteCommand::Screenshot(ref filename) => {
+                // Only a name, in the captures directory
+                let name = match Path::new(filename).file_name() {
+                    Some(name) => name.to_string_lossy().into_owned(),
+                    None => return Err(format!("Invalid file {:?}", filename)),
+                };
+                let filename = self.captures_file(&name);
+                self.screenshot(&filename);
+                fields.insert("file".to_string(), Value::from(filename));
+            }
+            RemoteCommand::Input { player, key, frames } => {
+                let elapsed = self.info.lock().unwrap().elapsed_time;
+                self.players
+                    .lock()
+                    .unwrap()
+                    .key_down_direct(player, key, false, elapsed);
+                if let Some(ref mut remote) = self.remote {
+                    remote.hold(player, key, frames);
+                }
+            }
+            RemoteCommand::Stat(ref name) => {
+                // The named values of stat(name)
+                let info = self.info.lock().unwrap();
+                let value = match name.as_str() {
+                    "last_capture" => Value::from(info.last_capture.clone()),
+                    "captures" => Value::from(info.captures),
+                    "real_time" => Value::from(info.elapsed_time),
+                    "over_budget" => Value::from(info.over_budget()),
+                    "frame_time" => Value::from(info.frame_time * 1000.0),
+                    "reduce_motion" => Value::from(info.display.reduce_motion),
+                    _ => return Err(format!("Unknown stat {:?}", name)),
+                };
+                fields.insert("value".to_string(), value);
+            }
+        }
+
+        Ok(fields)
+    }
+
+    /// Load and run a cartridge of the remote tools
+    fn load_checked(&mut self, filename: &str) -> Result<(), String> {
+        // The loaders panic on a missing file
+        if !Path::new(filename).is_file() {
+            return Err(format!("No cartridge {:?}", filename));
+        }
+        if ![".png", ".uni", ".uc", ".duc"].iter().any(|ext| filename.ends_with(ext)) {
+            return Err(format!("Unknown format of {:?}", filename));
+        }
+
+        self.preview.stop();
+        if !self.load_cartridge(filename, filename, false) {
+            return Err(format!("Failed to load {:?}", filename));
+        }
+        Ok(())
+    }
+
+    /// Record the execution trace of `--trace`, written by `stop_trace`
+    pub fn start_trace(&mut self, filename: &str) {
+        self.info.lock().unwrap().trace.start(filename);
+    }
+
+    pub fn trace_span(&mut self, name: &str, start: Instant, end: Instant) {
+        self.info.lock().unwrap().trace.span(name, start, end);
+    }
+
+    pub fn stop_trace(&mut self) {
+        match self.info.lock().unwrap().trace.stop() {
+            Ok(filename) => info!("[Unicorn][TRACE] Trace written in {:?}", filename),
+            Err(e) => error!("[Unicorn][TRACE] {}", e),
+        }
+    }
+
+    pub fn start_dev_server(&mut self, port: u16) {
+        match DevServer::start(port) {
+            Ok(dev_server) => self.dev_server = Some(dev_server),
+            Err(e) => error!("[Unicorn][DEV] {}", e),
+        }
+    }
+
+    /// Commands of the external editors, and the new errors of the scripts
+    pub fn dev_update(&mut self) {
+        let requests = match self.dev_server {
+            Some(ref mut dev_server) => dev_server.poll(),
+            None => return,
+        };
+
+        for request in requests {
+            let reply = self.dev_command(&request.command);
+            request.answer(reply);
+        }
+
+        let error = match self.dev_server {
+            Some(ref mut dev_server) => dev_server.new_error(),
+            None => None,
+        };
+        if let Some(message) = error {
+            let fields = self.dev_error(&message);
+            if let Some(ref dev_server) = self.dev_server {
+                dev_server.send_event("error", fields);
+            }
+        }
+    }
+
+    /// Message, file and line of an error of the current cartridge
+    fn dev_error(&mut self, message: &str) -> Map<String, Value> {
+        let mut fields = Map::new();
+        fields.insert("message".to_string(), Value::from(message));
+
+        if let Some(cartridge) = self.cartridges.get(self.current_cartridge) {
+            let file = match cartridge.cartridge.format {
+                CartridgeFormat::UnicornSplittedFormat => cartridge.cartridge.code.filename.clone(),
+                _ => cartridge.filename.clone(),
+            };
+            fields.insert("file".to_string(), Value::from(file));
+        }
+        if let Some(line) = error_location(message) {
+            fields.insert("line".to_string(), Value::from(line));
+        }
+
+        fields
+    }
+
+    fn dev_command(&mut self, command: &DevCommand) -> RemoteReply {
+        let mut fields = Map::new();
+
+        if let DevCommand::Run(ref filename) = *command {
+            info!("[Unicorn][DEV] Run {:?}", filename);
+            try!(self.load_checked(filename));
+            return Ok(fields);
+        }
+
+        // The watches stay from a cartridge to the next one
+        match *command {
+            DevCommand::Watch(ref expression) => {
+                info!("[Unicorn][DEV] Watch {:?}", expression);
+                try!(self.watches.add(expression));
+                fields.insert("watches".to_string(), self.watches_value());
+                return Ok(fields);
+            }
+            DevCommand::Unwatch(ref expression) => {
+                match *expression {
+                    Some(ref expression) => {
+                        if !self.watches.remove(expression) {
+                            return Err(format!("No watch {:?}", expression));
+                        }
+                    }
+                    None => self.watches.clear(),
+                }
+                fields.insert("watches".to_string(), self.watches_value());
+                return Ok(fields);
+            }
+            _ => (),
+        }
+
+        if self.cartridges.get(self.current_cartridge).is_none() {
+            return Err("No cartridge".to_string());
+        }
+        let idx = self.current_cartridge;
+
+        match *command {
+            DevCommand::Status => {
+                let (_, error) = crash::script_error();
+                fields.insert("state".to_string(),
+                              Value::from(format!("{:?}", self.state).to_lowercase()));
+                fields.insert("cartridge".to_string(),
+                              Value::from(self.cartridges[idx].filename.clone()));
+                fields.insert("fps".to_string(), Value::from(self.fps));
+                fields.insert("error".to_string(), Value::from(error));
+                fields.insert("watches".to_string(), self.watches_value());
+            }
+            DevCommand::Code { ref code, restart } => {
+                info!("[Unicorn][DEV] Code of {:?} bytes", code.len());
+                self.cartridges[idx].set_code(code.lines().map(|line| line.to_string()).collect());
+                try!(self.dev_load_code(restart));
+            }
+            DevCommand::Reload { restart } => {
+                let reload_code = match self.cartridges[idx].cartridge.format {
+                    CartridgeFormat::UnicornSplittedFormat => true,
+                    _ => false,
+                };
+
+                if reload_code {
+                    info!("[Unicorn][DEV] Reload the code");
+                    self.cartridges[idx].cartridge.code.reload();
+                    try!(self.dev_load_code(restart));
+                } else {
+                    // The code is in the cartridge file
+                    let filename = self.cartridges[idx].filename.clone();
+                    let full_filename = self.cartridges[idx].full_filename.clone();
+                    info!("[Unicorn][DEV] Reload {:?}", full_filename);
+
+                    if !Path::new(&full_filename).is_file() {
+                        return Err(format!("No cartridge {:?}", full_filename));
+                    }
+                    if !self.load_cartridge(&filename, &full_filename, false) {
+                        return Err(format!("Failed to load {:?}", full_filename));
+                    }
+                }
+            }
+            DevCommand::Restart => {
+                if let Some(ref mut dev_server) = self.dev_server {
+                    dev_server.forget_error();
+                }
+                self.init();
+            }
+            DevCommand::Run(_) | DevCommand::Watch(_) | DevCommand::Unwatch(_) => (),
+        }
+
+        Ok(fields)
+    }
+
+    /// Last values of the watch expressions, `{"expression": ..., "value": ...}`
+    /// or `{"expression": ..., "error": ...}`
+    fn watches_value(&self) -> Value {
+        let watches = self.watches
+            .watches
+            .iter()
+            .map(|watch| {
+                let mut fields = Map::new();
+                fields.insert("expression".to_string(), Value::from(watch.expression.clone()));
+                match watch.value {
+                    Ok(ref value) => fields.insert("value".to_string(), Value::from(value.clone())),
+                    Err(ref e) => fields.insert("error".to_string(), Value::from(e.clone())),
+                };
+                Value::Object(fields)
+            })
+            .collect();
+        Value::Array(watches)
+    }
+
+    /// Run the code of the cartridge again, its functions are replaced and
+    /// its state is kept without `restart`
+    fn dev_load_code(&mut self, restart: bool) -> Result<(), String> {
+        if let Some(ref mut dev_server) = self.dev_server {
+            dev_server.forget_error();
+        }
+
+        let idx = self.current_cartridge;
+        let data = self.cartridges[idx].get_code();
+        let loaded = match self.cartridges[idx].get_code_type() {
+            Code::LUA => self.cartridges[idx].lua_plugin.load_code(data),
+            Code::JAVASCRIPT => self.cartridges[idx].javascript_plugin.load_code(data),
+            Code::PYTHON => self.cartridges[idx].python_plugin.load_code(data),
+            _ => return Err("The code of the cartridge can't be reloaded".to_string()),
+        };
+        if !loaded {
+            let (_, error) = crash::script_error();
+            return Err(error);
+        }
+
+        if restart {
+            self.init();
+        }
+        Ok(())
+    }
+
     pub fn update(&mut self) -> bool {
+        self.remote_update();
+        self.dev_update();
+
         match self.state {
             UnicornState::PAUSE => {
                 if self.pause_menu.stop() {
@@ -814,9 +1801,24 @@ impl Unicorn {
 
                 if self.pause_menu.quit() {
                     self.state = UnicornState::INTERACTIVE;
+                    self.reset_mode();
                     self.sound_internal.lock().unwrap().stop();
                 }
 
+                if self.pause_menu.config() {
+                    if self.pause_menu.update_config(self.players.clone()) {
+                        self.info.lock().unwrap().display_request = Some(self.pause_menu.display.clone());
+                    }
+                    return true;
+                }
+
+                // The callback is called by the script before the next _update
+                if let Some(index) = self.pause_menu.custom_selected() {
+                    self.info.lock().unwrap().menuitem_selected = Some(index);
+                    self.switch_pause();
+                    return true;
+                }
+
                 return self.pause_menu.update(self.players.clone());
             }
             UnicornState::RUN => {
@@ -824,11 +1826,22 @@ impl Unicorn {
                     return false;
                 }
 
+                if self.permission_update() || self.inspector_update() || self.memory_viewer_update() ||
+                   self.console_update() {
+                    return true;
+                }
+
                 self.call_update();
+                self.watches_update();
+                self.tutorial_update();
+                self.info.lock().unwrap().cartdata.flush();
             }
             UnicornState::INTERACTIVE => {
                 let return_value = self.menu.update(&mut self.cartridges, self.players.clone());
+                self.preview.select(self.menu.get_current_idx() as usize);
+                self.preview.update(&mut self.cartridges);
                 if self.players.lock().unwrap().btnp(0, 4) {
+                    self.preview.stop();
                     let filename = self.cartridges[self.menu.get_current_idx() as usize].filename.clone();
                     let full_filename = self.cartridges[self.menu.get_current_idx() as usize].full_filename.clone();
                     self.load_cartridge(filename.as_str(), full_filename.as_str(), false);
@@ -853,10 +1866,23 @@ impl Unicorn {
                 self.pause_menu.draw(&mut self.screen.lock().unwrap());
             }
             UnicornState::RUN => {
-                self.call_draw();
+                // The frame stays under the inspector, the memory viewer and
+                // the console while the game is paused
+                if !self.permission_draw() {
+                    if self.console.enabled {
+                        self.console.restore_frame(&mut self.screen.lock().unwrap());
+                    } else if !self.inspector.enabled && !self.memory_viewer.enabled {
+                        self.effects_restore();
+                        self.call_draw();
+                        self.effects_draw();
+                        self.capture_draw();
+                    }
+                }
             }
             UnicornState::INTERACTIVE => {
-                self.menu.draw(&mut self.cartridges, &mut self.screen.lock().unwrap());
+                let mut screen = self.screen.lock().unwrap();
+                self.menu.draw(&mut self.cartridges, &mut screen);
+                self.preview.draw(&mut screen);
             }
             UnicornState::EDITOR => {
                 #[cfg(feature = "editor")]
@@ -884,11 +1910,67 @@ impl Unicorn {
             }
         }
 
-        if self.is_recording() {
-            self.record();
-        }
+        self.check_mode();
+
+        self.present_outputs();
 
         self.debug_draw();
+        self.stats_draw();
+        self.tutorial_draw();
+        self.watches_draw();
+        self.inspector_draw();
+        self.console_draw();
+        self.memory_viewer_draw();
+        self.audio_viewer_draw();
+        self.magnifier_draw();
+        self.macro_draw();
+        self.switch_draw();
+        self.help_draw();
+    }
+
+    /// Screenshot asked by the cartridge, taken right after its frame
+    pub fn capture_draw(&mut self) {
+        let request = self.info.lock().unwrap().capture_request.take();
+        if let Some(filename) = request {
+            let filename = self.captures_file(&filename);
+            self.screenshot(&filename);
+        }
+    }
+
+    /// Resolution asked by the cartridge, one of `gfx::MODES`. The frontend
+    /// resizes its window and texture at the next frame.
+    pub fn set_mode(&mut self, width: usize, height: usize) -> bool {
+        self.screen.lock().unwrap().mode(width, height)
+    }
+
+    /// The editor and the cart browser are drawn at the resolution of the
+    /// console
+    pub fn reset_mode(&mut self) {
+        let (width, height) = gfx::DEFAULT_MODE;
+        self.set_mode(width, height);
+    }
+
+    /// The frames of the GIF and of the video keep the size of the first one
+    fn check_mode(&mut self) {
+        let mode = {
+            let screen = self.screen.lock().unwrap();
+            (screen.width, screen.height)
+        };
+        if mode == self.mode {
+            return;
+        }
+
+        info!("[Unicorn] Resolution {:?} -> {:?}", self.mode, mode);
+        self.mode = mode;
+
+        if self.is_recording() {
+            warn!("[Unicorn] The resolution changed, stop the GIF");
+            self.stop_record();
+        }
+        if self.is_recording_video() {
+            warn!("[Unicorn] The resolution changed, stop the video");
+            self.stop_video();
+        }
     }
 
     pub fn is_end(&self) -> bool {
@@ -902,132 +1984,322 @@ impl Unicorn {
     pub fn start_record(&mut self, filename: &str) {
         info!("[Unicorn] Start to record the frame");
 
-        self.record.recording = true;
-        self.record.images.clear();
-        self.record.filename = filename.to_string();
+        let filename = self.sandbox.output_path(filename);
+        let (width, height) = {
+            let screen = self.screen.lock().unwrap();
+            (screen.width, screen.height)
+        };
+        self.record.console_fps = self.info.lock().unwrap().fps;
+        self.record.start(&filename, width, height);
     }
 
-    pub fn record(&mut self) {
-        info!("[Unicorn] Recording the frame {:?}", self.record.images.len());
+    /// Settings of the GIF recording, for the next frames
+    pub fn set_record_config(&mut self, config: RecordConfig) {
+        info!("[Unicorn] Record config {:?}", config);
+        self.info.lock().unwrap().record_config = config.clamp();
+    }
 
-        if self.record.nb % 4 == 0 {
-            let mut buffer: Vec<u8> = Vec::new();
-            let screen = &mut self.screen.lock().unwrap();
+    pub fn load_record_config(&mut self, filename: &str) {
+        info!("[Unicorn] Load the record config {:?}", filename);
+        match RecordConfig::from_file(filename) {
+            Ok(config) => self.set_record_config(config),
+            Err(e) => error!("[Unicorn] Failed to load the record config: {}", e),
+        }
+    }
+
+    /// Sink of each frame of the game, until `remove_output` or an error
+    pub fn add_output(&mut self, output: Box<Output>) {
+        self.outputs.add(output);
+    }
 
-            for x in 0..screen.width {
-                for y in 0..screen.height {
-                    let value = screen.pget(x as u32, y as u32);
-                    let rgb_value = PALETTE.lock().unwrap().get_rgb(value);
+    pub fn remove_output(&mut self, name: &str) -> bool {
+        self.outputs.remove(name)
+    }
 
-                    buffer.push(rgb_value.r);
-                    buffer.push(rgb_value.g);
-                    buffer.push(rgb_value.b);
-                }
-            }
-            self.record.images.append(&mut buffer);
+    /// The frame is converted once for the GIF, the video and the outputs
+    /// Frame of the window, with the overlays of the console. With the
+    /// reduced flashing, the too frequent flashes of the cartridge are
+    /// replaced by the last frame shown.
+    pub fn display_frame(&mut self) -> Frame {
+        let mut frame = Frame::new(&self.screen.lock().unwrap());
+
+        let (reduce_motion, time) = {
+            let info = self.info.lock().unwrap();
+            (info.display.reduce_motion, info.elapsed_time)
+        };
+        if reduce_motion && self.state == UnicornState::RUN &&
+           self.flash_limiter.limit(&mut frame.rgb, time) {
+            debug!("[Unicorn] Flash skipped");
+        }
+        frame
+    }
+
+    fn present_outputs(&mut self) {
+        let recording = self.is_recording();
+        let recording_video = self.is_recording_video();
+        if !recording && !recording_video && self.outputs.is_empty() {
+            return;
+        }
+
+        let frame = Frame::new(&self.screen.lock().unwrap());
+
+        if recording {
+            self.record(&frame);
+        }
+        if recording_video {
+            self.record_video(&frame);
+        }
+        self.outputs.present(&frame);
+    }
+
+    pub fn record(&mut self, frame: &Frame) {
+        let config = self.info.lock().unwrap().record_config.clone();
+        if !self.record.tick(&config) {
+            return;
         }
 
-        self.record.nb += 1;
+        debug!("[Unicorn] Recording the frame {:?}", self.record.len());
+
+        self.record.push(frame.rgb.clone(), &config);
+    }
+
+    pub fn is_recording_video(&self) -> bool {
+        self.video.is_recording()
+    }
+
+    /// Video in MP4 or WebM, following the extension of `filename`
+    pub fn start_video(&mut self, filename: &str) {
+        info!("[Unicorn] Start to record the video {:?}", filename);
+
+        let filename = self.sandbox.output_path(filename);
+        let (width, height) = {
+            let screen = self.screen.lock().unwrap();
+            (screen.width, screen.height)
+        };
+        let (scale, fps) = {
+            let info = self.info.lock().unwrap();
+            (info.record_config.scale, info.fps)
+        };
+
+        if let Err(e) = self.video.start(&filename, width, height, scale, fps) {
+            error!("[Unicorn] Failed to start the video: {}", e);
+        }
+    }
+
+    pub fn record_video(&mut self, frame: &Frame) {
+        if let Err(e) = self.video.push(&frame.rgb) {
+            error!("[Unicorn] {}", e);
+        }
+    }
+
+    pub fn stop_video(&mut self) {
+        info!("[Unicorn] Stop to record the video {:?}", self.video.frames);
+
+        match self.video.stop() {
+            Ok(_) => {
+                info!("[Unicorn] Video created in {:?}", self.video.filename);
+                self.info.lock().unwrap().set_capture(&self.video.filename);
+            }
+            Err(e) => error!("[Unicorn] Failed to finish the video: {}", e),
+        }
     }
 
     #[cfg(not(feature = "image"))]
     pub fn stop_record(&mut self) {
+        self.record.stop();
     }
 
     #[cfg(feature = "image")]
     pub fn stop_record(&mut self) {
-        info!("[Unicorn] Stop to record the frame {:?}",
-              self.record.images.len());
+        info!("[Unicorn] Stop to record the frame {:?}", self.record.len());
 
-        let screen = &self.screen.lock().unwrap();
-
-        self.record.recording = false;
+        let config = self.info.lock().unwrap().record_config.clone();
+        let (width, height) = self.record.scaled_size(config.scale);
+        let frames = self.record.stop();
 
         let mut filedata = File::create(self.record.filename.clone()).unwrap();
 
-        let mut encoder = gif::Encoder::new(&mut filedata,
-                                            screen.width as u16,
-                                            screen.height as u16,
-                                            &[])
+        let mut encoder = gif::Encoder::new(&mut filedata, width as u16, height as u16, &[])
                 .unwrap();
 
         encoder.set(gif::Repeat::Infinite).unwrap();
 
-        let mut idx = 0;
-        for i in 0..self.record.images.len() / (screen.width * screen.height * 3) {
-            info!("[Unicorn] Generate frame {:?} {:?}/{:?}",
-                  i,
-                  self.record.images.len(),
-                  idx);
-
-            let mut buffer: Vec<u8> = Vec::new();
-
-            for _ in 0..screen.width {
-                for _ in 0..screen.height {
-                    buffer.push(self.record.images[idx]);
-                    buffer.push(self.record.images[idx + 1]);
-                    buffer.push(self.record.images[idx + 2]);
-                    idx += 3;
-                }
-            }
+        for (i, buffer) in frames.iter().enumerate() {
+            debug!("[Unicorn] Generate frame {:?}/{:?}", i, frames.len());
 
-            info!("[Unicorn] Creating ImageBuffer {:?}", buffer.len());
+            let pixels = self.record.scale_frame(buffer, config.scale);
+            let mut frame = gif::Frame::from_rgb(width as u16, height as u16, &pixels);
 
-            let image =
-                image::ImageBuffer::from_raw(screen.height as u32, screen.width as u32, buffer)
-                    .unwrap();
+            frame.delay = config.delay();
+            encoder.write_frame(&frame).unwrap();
+        }
 
-            info!("[Unicorn] Rotating image");
-            let image = image::DynamicImage::ImageRgb8(image)
-                .rotate270()
-                .flipv();
+        info!("[Unicorn] GIF created in {:?}", self.record.filename);
+        self.info.lock().unwrap().set_capture(&self.record.filename);
+    }
 
-            info!("[Unicorn] Creating gif Frame");
-            let mut frame = gif::Frame::from_rgb(screen.width as u16,
-                                                 screen.height as u16,
-                                                 &image.raw_pixels());
+    /// Run the loaded cartridge for `seconds` without a window, with the
+    /// buttons of `script`, and record the frames in the GIF `filename`
+    pub fn record_preview(&mut self, filename: &str, seconds: f64, script: &InputScript) {
+        let fps = self.info.lock().unwrap().fps;
+        let frames = (seconds * fps as f64).ceil() as u32;
+        let dt = Duration::from_secs(1) / fps;
+        info!("[Unicorn] Record a preview of {:?} frames in {:?}", frames, filename);
 
-            frame.delay = 1;
-            encoder.write_frame(&frame).unwrap();
+        let mut config = self.info.lock().unwrap().record_config.clone();
+        config.max_seconds = seconds;
+        self.set_record_config(config);
+
+        self.start_record(filename);
+        for frame in 0..frames {
+            if !self.headless_frame(frame, script, dt) {
+                info!("[Unicorn] End of the cartridge at the frame {:?}", frame);
+                break;
+            }
         }
+        self.stop_record();
+    }
 
-        info!("[Unicorn] GIF created in {:?}", self.record.filename);
+    /// One frame without a window, with the buttons of `script`, false at the
+    /// end of the cartridge
+    pub fn headless_frame(&mut self, frame: u32, script: &InputScript, dt: Duration) -> bool {
+        {
+            let elapsed = self.info.lock().unwrap().elapsed_time;
+            let mut players = self.players.lock().unwrap();
+            for (player, key) in script.released(frame) {
+                players.key_up_direct(player, key);
+            }
+            for (player, key) in script.pressed(frame) {
+                players.key_down_direct(player, key, false, elapsed);
+            }
+        }
+
+        if !self.update() {
+            return false;
+        }
+        // Nobody answers the questions of the cartridge, its privileged
+        // capabilities stay refused without remembering it
+        self.info.lock().unwrap().permissions.request = None;
+        self.draw();
+
+        // Nothing is played
+        while self.sound_internal.lock().unwrap().crecv.try_recv().is_ok() {}
+        self.update_time(dt);
+        true
     }
 
-    #[cfg(not(feature = "image"))]
-    pub fn screenshot(&mut self, filename: &str) {
+    /// The next cartridges start with this seed of `rnd`, and their calls of
+    /// the clock and of the random numbers of Lua are counted
+    pub fn set_deterministic(&mut self, seed: u64) {
+        self.info.lock().unwrap().random_seed = Some(seed);
+        self.watch_nondeterminism = true;
     }
 
-    #[cfg(feature = "image")]
-    pub fn screenshot(&mut self, filename: &str) {
-        let screen = &mut self.screen.lock().unwrap();
+    /// Calls of the current cartridge which differ between two runs
+    pub fn nondeterminism_calls(&mut self) -> Vec<(String, u32)> {
+        let cartridge = match self.cartridges.get_mut(self.current_cartridge) {
+            Some(cartridge) => cartridge,
+            None => return Vec::new(),
+        };
+        match cartridge.get_code_type() {
+            Code::LUA => cartridge.lua_plugin.nondeterminism_calls(),
+            _ => Vec::new(),
+        }
+    }
 
-        info!("[Unicorn] Taking screenshot {:?}x{:?} in {:?}", screen.width, screen.height, filename);
+    /// Write the indexed frame buffer, the palette and the draw state in a
+    /// text file, to inspect a wrong pixel offline
+    pub fn frame_dump(&mut self, filename: &str) {
+        let filename = self.sandbox.output_path(filename);
 
-        let mut buffer: Vec<u8> = vec![0; (screen.width*screen.height) * 3];
+        let mut data = String::new();
+        data.push_str("# Unicorn Console frame dump\n\n");
+        if let Some(cartridge) = self.cartridges.get(self.current_cartridge) {
+            data.push_str(&format!("Cartridge: {}\n", cartridge.filename));
+            data.push_str(&format!("Cartridge ID: {}\n", cartridge.cartridge.identity()));
+        }
+        data.push_str(&format!("Palette: {}\n", self.palettes.lock().unwrap().name));
+        data.push_str(&self.screen.lock().unwrap().dump_state());
+        data.push_str("\n## Palette\n\n");
+        data.push_str(&PALETTE.lock().unwrap().dump());
 
-        let mut idx = 0;
-        for x in 0..screen.width {
-            for y in 0..screen.height {
-                let value = screen.pget(x as u32, y as u32);
-                let rgb_value = PALETTE.lock().unwrap().get_rgb(value);
+        info!("[Unicorn] Dumping the frame in {:?}", filename);
 
-                buffer[idx] = rgb_value.r;
-                buffer[idx + 1] = rgb_value.g;
-                buffer[idx + 2] = rgb_value.b;
-                
-                idx += 3;
+        match File::create(&Path::new(&filename)) {
+            Ok(mut f) => {
+                if let Err(e) = f.write_all(data.as_bytes()) {
+                    error!("[Unicorn] Failed to write the frame dump {:?}: {}", filename, e);
+                }
             }
+            Err(e) => error!("[Unicorn] Failed to create the frame dump {:?}: {}", filename, e),
+        }
+    }
+
+    /// File of a capture taken with the keys of the console, named after the
+    /// current cartridge
+    pub fn capture_path(&mut self, kind: &str, stamp: &str, extension: &str) -> String {
+        let cartridge = match self.cartridges.get(self.current_cartridge) {
+            Some(cartridge) => cartridge.filename.clone(),
+            None => "".to_string(),
+        };
+        self.captures_file(&capture_filename(kind, &cartridge, stamp, extension))
+    }
+
+    /// `filename` in the captures directory of the record config, created if
+    /// missing, or in the save area of the safe mode
+    pub fn captures_file(&mut self, filename: &str) -> String {
+        if self.sandbox.enabled {
+            return self.sandbox.output_path(filename);
         }
 
-        let image = image::ImageBuffer::from_raw(screen.height as u32, screen.width as u32, buffer)
-            .unwrap();
-        let image = image::DynamicImage::ImageRgb8(image)
-            .rotate270()
-            .flipv();
+        let dir = self.info.lock().unwrap().record_config.captures_dir();
+        if let Err(e) = fs::create_dir_all(&dir) {
+            error!("[Unicorn] Failed to create the captures directory {:?}: {}", dir, e);
+            return filename.to_string();
+        }
+
+        dir.join(filename).to_string_lossy().into_owned()
+    }
+
+    /// Screenshot at the size of the screen, or of the window with the
+    /// `screenshot_scaled` setting of the record config
+    pub fn screenshot(&mut self, filename: &str) {
+        let scaled = self.info.lock().unwrap().record_config.screenshot_scaled;
+        self.screenshot_with_scale(filename, scaled);
+    }
+
+    #[cfg(not(feature = "image"))]
+    pub fn screenshot_with_scale(&mut self, filename: &str, scaled: bool) {
+    }
+
+    /// The pixels are repeated by the scale of the window, without blur
+    #[cfg(feature = "image")]
+    pub fn screenshot_with_scale(&mut self, filename: &str, scaled: bool) {
+        let filename = &self.sandbox.output_path(filename);
+        let scale = if scaled {
+            max(1, self.info.lock().unwrap().display.scale)
+        } else {
+            1
+        };
+        let frame = Frame::new(&self.screen.lock().unwrap());
+
+        info!("[Unicorn] Taking screenshot {:?}x{:?} (x{:?}) in {:?}",
+              frame.width,
+              frame.height,
+              scale,
+              filename);
+
+        let width = frame.width * scale;
+        let height = frame.height * scale;
+        let buffer = frame.scaled_rgb(scale);
+
+        let image = image::ImageBuffer::from_raw(width as u32, height as u32, buffer).unwrap();
+        let image = image::DynamicImage::ImageRgb8(image);
 
         let mut output = File::create(&Path::new(filename)).unwrap();
         image.save(&mut output, image::ImageFormat::PNG).unwrap();
+
+        self.info.lock().unwrap().set_capture(filename);
     }
 
     pub fn save_current_cartridge(&mut self) {
@@ -1035,11 +2307,19 @@ impl Unicorn {
             return;
         }
 
+        if self.sandbox.enabled {
+            warn!("[Unicorn][SAVE] Safe mode, the cartridge could not be overwritten");
+            return;
+        }
+
         let screen = &self.screen.lock().unwrap();
 
         let cartridge = &mut self.cartridges[self.current_cartridge].cartridge;
 
-        let output_filename = &cartridge.filename.clone();
+        let output_filename = &match self.classroom {
+            Some(ref classroom) => classroom.student_filename(&self.profile, &cartridge.filename),
+            None => cartridge.filename.clone(),
+        };
         info!("[Unicorn][SAVE] Saving the current cartridge in {:?}",
               output_filename);
 
@@ -1052,20 +2332,50 @@ impl Unicorn {
         info!("[Unicorn][SAVE] Set the new palette");
         cartridge.palette.set_colors(self.palettes.lock().unwrap().get_colors());
 
+        if cartridge.label.is_empty() {
+            if let Some(label) = preview::render_label(cartridge) {
+                cartridge.label = label;
+            }
+        }
+
+        let version = format!("{:?}.{:?}.{:?}",
+                              self.version,
+                              self.major_version,
+                              self.minor_version);
+
+        // The copy of a student is always a single file
+        let copy = self.classroom.is_some();
         match cartridge.format {
             CartridgeFormat::UnicornFormat => {
-                cartridge.save_in_unicorn(output_filename,
-                                          format!("{:?}.{:?}.{:?}",
-                                                self.version,
-                                                self.major_version,
-                                                self.minor_version)
-                                                .as_str());
+                cartridge.save_in_unicorn(output_filename, &version);
+            }
+            CartridgeFormat::UnicornSplittedFormat |
+            CartridgeFormat::PngFormat if copy => {
+                cartridge.save_in_unicorn(output_filename, &version);
             }
             CartridgeFormat::UnicornSplittedFormat => {
                 cartridge.save_in_unicorn_splitted();
             }
-            _ => (),
+            CartridgeFormat::PngFormat => {
+                cartridge.save_in_png(output_filename, &version);
+            }
+        }
+    }
+
+    /// Directory of the `cartdata` files, in the profile
+    pub fn cartdata_dir(&self) -> PathBuf {
+        if self.sandbox.enabled {
+            return PathBuf::from(self.sandbox.output_path("cartdata"));
+        }
+        self.profile.dir().join("cartdata")
+    }
+
+    /// Compiled code of the cartridges, in the profile (never in safe mode)
+    pub fn script_cache(&self) -> ScriptCache {
+        if self.sandbox.enabled {
+            return ScriptCache::disabled();
         }
+        ScriptCache::new(self.profile.dir().join("cache"))
     }
 
     pub fn save_state() {
@@ -1079,6 +2389,8 @@ impl Unicorn {
     pub fn switch_pause(&mut self) {
         info!("[Unicorn] Switch pause");
 
+        self.pause_menu.display = self.info.lock().unwrap().display.clone();
+
         let screen = &mut self.screen.lock().unwrap();
 
         match self.state {
@@ -1101,16 +2413,20 @@ impl Unicorn {
 
                 screen.font("pico-8");
 
+                let items = self.info.lock().unwrap().menuitems.clone();
+                self.pause_menu.set_custom_items(&items);
                 self.pause_menu.reset();
                 self.state = UnicornState::PAUSE;
             }
             UnicornState::INTERACTIVE => {
+                self.pause_menu.set_custom_items(&[]);
                 self.pause_menu.reset();
                 self.state = UnicornState::PAUSE;
                 screen.save();
                 self.sound_internal.lock().unwrap().pause();
             }
             UnicornState::EDITOR => {
+                self.pause_menu.set_custom_items(&[]);
                 self.pause_menu.reset();
                 self.state = UnicornState::PAUSE;
                 screen.save();
@@ -1149,7 +2465,11 @@ impl Unicorn {
             .unwrap()
             .set_map(cartridge.cartridge.map.map.clone());
 
-        self.palettes.lock().unwrap().set_colors(cartridge.cartridge.palette.colors.clone());
+        // The colors changed by the previous cartridge are restored
+        let mut palettes = self.palettes.lock().unwrap();
+        palettes.reset();
+        palettes.switch_to_palette("pico-8");
+        palettes.set_colors(cartridge.cartridge.palette.colors.clone());
     }
 
     pub fn _load_cartridge(&mut self,
@@ -1158,8 +2478,31 @@ impl Unicorn {
                            -> bool {
         info!("[Unicorn] Loading cartridge {:?}", cartridge);
 
+        // Before the code, which could ask for another resolution
+        self.reset_mode();
+        self.flash_limiter.reset();
+        self.info.lock().unwrap().effects.reset();
+        self.screen.lock().unwrap().particles.clear();
+        self.screen.lock().unwrap().gfx3d.clear();
+        // The fonts of the previous cartridge, before its code registers its own
+        self.screen.lock().unwrap().font_clear();
+        self.screen.lock().unwrap().memory_clear();
         let data = cartridge.get_code();
 
+        crash::set_cartridge(&cartridge.filename,
+                             &cartridge.cartridge.meta.uuid,
+                             cartridge.cartridge.hash);
+        self.info.lock().unwrap().cartridge_uuid = cartridge.cartridge.meta.uuid.clone();
+        self.info.lock().unwrap().cartridge_hash = format!("{:016x}", cartridge.cartridge.hash);
+        self.info.lock().unwrap().reset_cartridge();
+        self.info.lock().unwrap().set_fps(cartridge.cartridge.meta.fps);
+        let cartdata_dir = self.cartdata_dir();
+        self.info.lock().unwrap().cartdata.reset(cartdata_dir);
+        let permissions_path = PathBuf::from(self.profile.path("permissions.json"));
+        self.info.lock().unwrap().permissions.load(permissions_path);
+        let script_cache = self.script_cache();
+        self.stats_overlay.reset();
+
         let mut ret: bool = false;
 
         match cartridge.get_code_type() {
@@ -1174,7 +2517,27 @@ impl Unicorn {
                           self.noise.clone(),
                           self.sound.clone());
 
-                ret = cartridge.lua_plugin.load_code(data.clone());
+                self.preload_libraries(cartridge, &data);
+
+                if let Some(ref tutorial) = self.tutorial {
+                    cartridge.lua_plugin.trace_calls(&tutorial.calls());
+                }
+
+                if self.sandbox.enabled {
+                    cartridge
+                        .lua_plugin
+                        .sandbox(self.sandbox.max_instructions,
+                                 self.sandbox.max_memory_kb);
+                }
+
+                if self.watch_nondeterminism {
+                    cartridge.lua_plugin.watch_nondeterminism();
+                }
+
+                ret = cartridge.lua_plugin.load_code_cached(data.clone(), &script_cache);
+            }
+            Code::JAVASCRIPT if self.sandbox.enabled => {
+                error!("[Unicorn] JavaScript cartridges can't be sandboxed, refusing to run it in safe mode");
             }
             Code::JAVASCRIPT => {
                 info!("[Unicorn] Loading JAVASCRIPT Plugin");
@@ -1189,6 +2552,9 @@ impl Unicorn {
 
                 ret = cartridge.javascript_plugin.load_code(data.clone());
             }
+            Code::PYTHON if self.sandbox.enabled => {
+                error!("[Unicorn] Python cartridges can't be sandboxed, refusing to run it in safe mode");
+            }
             Code::PYTHON => {
                 info!("[Unicorn] Loading PYTHON Plugin");
 
@@ -1202,13 +2568,18 @@ impl Unicorn {
                           self.noise.clone(),
                           self.configuration.clone());
 
-                ret = cartridge.python_plugin.load_code(data.clone());
+                ret = cartridge.python_plugin.load_code_cached(data.clone(), &script_cache);
             }
             _ => (),
         }
 
         info!("[Unicorn] LOAD CARTRIDGE {:?}", ret);
 
+        if self.sandbox.enabled && editor {
+            warn!("[Unicorn] Safe mode, the editor is disabled");
+        }
+
+        let editor = editor && !self.sandbox.enabled;
         self.editing = editor;
 
         #[cfg(feature = "editor")]
@@ -1238,18 +2609,40 @@ impl Unicorn {
             return true;
         }
 
+        let mut full_filename = full_filename.to_string();
+        let mut format_filename = filename.to_string();
+        if let Some(ref classroom) = self.classroom {
+            if !classroom.is_allowed(filename) {
+                error!("[Unicorn][CLASSROOM] {:?} is not a cartridge of the classroom", filename);
+                return false;
+            }
+
+            let copy = classroom.student_filename(&self.profile, filename);
+            if Path::new(&copy).exists() {
+                info!("[Unicorn][CLASSROOM] Loading the copy of the student {:?}", copy);
+                full_filename = copy.clone();
+                format_filename = copy;
+            }
+        }
+        let full_filename = &full_filename;
+
         let cartridge;
-        if filename.contains(".uni") {
+        if Cartridge::is_png_file(full_filename) {
+            match Cartridge::from_png_file(full_filename) {
+                Ok(c) => cartridge = c,
+                Err(e) => panic!("[Unicorn] Impossible to load the PNG cartridge {:?}", e),
+            }
+        } else if format_filename.contains(".uni") {
             match Cartridge::from_unicorn_file(full_filename) {
                 Ok(c) => cartridge = c,
                 Err(e) => panic!("[Unicorn] Impossible to load the unicorn cartridge {:?}", e),
             }
-        }  else if filename.contains(".uc") {
+        }  else if format_filename.contains(".uc") {
             match Cartridge::from_unicorn_splitted_file(full_filename) {
                 Ok(c) => cartridge = c,
                 Err(e) => panic!("[Unicorn] Impossible to load the unicorn splitted cartridge {:?}", e),
             }
-        } else if filename.contains(".duc") {
+        } else if format_filename.contains(".duc") {
             match Cartridge::from_dunicorn_file(full_filename) {
                 Ok(c) => cartridge = c,
                 Err(e) => panic!("[Unicorn] Impossible to load the dUnicorn cartridge {:?}", e),
@@ -1307,7 +2700,12 @@ impl Unicorn {
                               -> bool {
         let cartridge;
 
-        if filename.contains(".uni") {
+        if filename.ends_with(".png") || png::has_png_signature(&data) {
+            match Cartridge::from_png_raw(filename, data) {
+                Ok(c) => cartridge = c,
+                Err(e) => panic!("Impossible to load the PNG cartridge {:?}", e),
+            }
+        } else if filename.contains(".uni") {
             match Cartridge::from_uni_raw(filename, data) {
                 Ok(c) => cartridge = c,
                 Err(e) => panic!("Impossible to load the p8 cartridge {:?}", e),
@@ -1373,6 +2771,9 @@ impl Unicorn {
                 self.editing = false;
                 self.state = UnicornState::RUN;
                 self.reset();
+            } else if self.sandbox.enabled {
+                warn!("[Unicorn] Safe mode, the editor is disabled");
+                return;
             } else {
                 info!("[Unicorn] Switch run to editor");
                 info!("[Unicorn] Back to {:?}/{:?}", self.current_cartridge, self.cartridges.len());
@@ -1384,6 +2785,7 @@ impl Unicorn {
                 }
                 let code = self.cartridges[self.current_cartridge].get_code();
 
+                self.reset_mode();
                 self.editor
                     .init(self.configuration.clone(),
                           self.palettes.clone(),
@@ -1465,3 +2867,48 @@ impl Unicorn {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use gfx::Screen;
+    use unicorn::output::Frame;
+
+    use super::{Palettes, PALETTE, RGB, EXTENDED_COLOR, EXTENDED_PALETTE};
+
+    #[test]
+    fn test_extended_colors() {
+        let mut palettes = Palettes::new();
+        palettes.init();
+        palettes.reset();
+        palettes.switch_to_palette("pico-8");
+
+        for (idx, value) in EXTENDED_PALETTE.iter().enumerate() {
+            assert_eq!(PALETTE.lock().unwrap().get_rgb(EXTENDED_COLOR + idx as u32),
+                       RGB::new_hexa(*value));
+        }
+
+        // The defaults aren't saved in the cartridge
+        assert!(palettes.get_colors().is_empty());
+        PALETTE.lock().unwrap()._set_color(EXTENDED_COLOR + 2, 1, 2, 3);
+        PALETTE.lock().unwrap()._set_color(200, 4, 5, 6);
+        let colors = palettes.get_colors();
+        assert_eq!(colors.len(), 2);
+        assert_eq!(colors[&(EXTENDED_COLOR + 2)], RGB::new(1, 2, 3));
+        assert_eq!(colors[&200], RGB::new(4, 5, 6));
+
+        palettes.reset();
+        assert!(!PALETTE.lock().unwrap().colors.contains_key(&200));
+        palettes.switch_to_palette("pico-8");
+
+        // Shown by the display palette, the pixel keeps its color
+        let mut screen = Screen::new(2, 1);
+        screen.init();
+        screen.putpixel_direct(0, 0, 1);
+        screen.putpixel_direct(1, 0, 1);
+        screen.pal_screen(1, (EXTENDED_COLOR + 1) as i32);
+        let frame = Frame::new(&screen);
+        let rgb = RGB::new_hexa(EXTENDED_PALETTE[1]);
+        assert_eq!(frame.pixels, vec![1, 1]);
+        assert_eq!(frame.rgb, vec![rgb.r, rgb.g, rgb.b, rgb.r, rgb.g, rgb.b]);
+    }
+}
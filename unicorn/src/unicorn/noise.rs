@@ -1,53 +1,128 @@
-use num_traits;
-use num_traits::NumCast;
+//! Noise of `noise`: the improved Perlin noise of Ken Perlin (2002) in 3D,
+//! from -1 to 1 and 0 on the integer coordinates. The permutation of the
+//! lattice is shuffled by `noise_seed` with the numbers of `rnd` (Fisher-Yates
+//! from the top), only floors, additions and multiplications of f64 are used
+//! after, so a seed gives the same values on all the platforms and plugins.
 
-#[cfg(feature = "noise")]
-use noise::{NoiseModule, Perlin, Seedable};
+use unicorn::random::Random;
 
-fn cast<T: NumCast, R: NumCast>(val: T) -> R {
-    num_traits::cast(val).unwrap()
-}
-
-
-#[cfg(feature = "noise")]
 pub struct Noise {
-    perlin: Perlin,
+    /// Permutation of 0..255, repeated to skip the wrapping of the indexes
+    perm: Vec<u8>,
 }
 
-#[cfg(feature = "noise")]
 impl Noise {
+    /// Seeded with 0 until `noise_seed`
     pub fn new() -> Noise {
         info!("[Unicorn][Noise] new");
-        Noise { perlin: Perlin::new() }
+        Noise::from_seed(0)
+    }
+
+    pub fn from_seed(seed: u64) -> Noise {
+        let mut random = Random::from_seed(seed);
+        let mut perm: Vec<u8> = (0..256).map(|i| i as u8).collect();
+
+        for i in (1..256).rev() {
+            let j = (random.next_u64() % (i as u64 + 1)) as usize;
+            perm.swap(i, j);
+        }
+
+        let repeated = perm.clone();
+        perm.extend(repeated);
+        Noise { perm: perm }
     }
 
     pub fn get(&mut self, x: f64, y: f64, z: f64) -> f64 {
-        let r: f64 = cast(self.perlin.get([x, y, z]));
-        r
+        let (xf, yf, zf) = (x.floor(), y.floor(), z.floor());
+        let xi = (xf as i64 & 255) as usize;
+        let yi = (yf as i64 & 255) as usize;
+        let zi = (zf as i64 & 255) as usize;
+        let (x, y, z) = (x - xf, y - yf, z - zf);
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+
+        let p = &self.perm;
+        let a = p[xi] as usize + yi;
+        let aa = p[a] as usize + zi;
+        let ab = p[a + 1] as usize + zi;
+        let b = p[xi + 1] as usize + yi;
+        let ba = p[b] as usize + zi;
+        let bb = p[b + 1] as usize + zi;
+
+        lerp(w,
+             lerp(v,
+                  lerp(u, grad(p[aa], x, y, z), grad(p[ba], x - 1.0, y, z)),
+                  lerp(u, grad(p[ab], x, y - 1.0, z), grad(p[bb], x - 1.0, y - 1.0, z))),
+             lerp(v,
+                  lerp(u,
+                       grad(p[aa + 1], x, y, z - 1.0),
+                       grad(p[ba + 1], x - 1.0, y, z - 1.0)),
+                  lerp(u,
+                       grad(p[ab + 1], x, y - 1.0, z - 1.0),
+                       grad(p[bb + 1], x - 1.0, y - 1.0, z - 1.0))))
     }
 
-    pub fn set_seed(&mut self, seed: u32) {
+    pub fn set_seed(&mut self, seed: u64) {
         debug!("Change seed to {:?}", seed);
-        self.perlin.set_seed(seed as usize);
+        *self = Noise::from_seed(seed);
     }
 }
 
+/// 6t^5 - 15t^4 + 10t^3, flat at 0 and 1
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
 
-#[cfg(not(feature = "noise"))]
-pub struct Noise {
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
 }
 
-#[cfg(not(feature = "noise"))]
-impl Noise {
-    pub fn new() -> Noise {
-        info!("[Unicorn][Noise] new");
-        Noise { }
-    }
+/// Dot product with one of the 12 directions to the middles of the edges of
+/// a cube (16 with 4 of them twice)
+fn grad(hash: u8, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
 
-    pub fn get(&mut self, x: f64, y: f64, z: f64) -> f64 {
-        0.
-    }
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Noise;
+
+    #[test]
+    fn test_noise() {
+        let mut noise = Noise::from_seed(42);
+        let mut same = Noise::from_seed(42);
+        let mut other = Noise::from_seed(43);
+
+        assert_eq!(noise.get(1.0, 2.0, 3.0), 0.0);
+        assert_eq!(noise.get(-7.0, 0.0, 300.0), 0.0);
+        // Pinned, the worlds generated by the cartridges rely on the values
+        assert!((noise.get(0.5, 0.25, 0.75) - 0.43664216995).abs() < 1e-9);
+
+        let mut differ = false;
+        for i in 0..200 {
+            let (x, y, z) = (i as f64 * 0.37, i as f64 * -0.11, i as f64 * 0.05);
+            let value = noise.get(x, y, z);
+            assert_eq!(value, same.get(x, y, z));
+            assert!(value >= -1.0 && value <= 1.0);
+            // Continuous
+            assert!((noise.get(x + 0.001, y, z) - value).abs() < 0.01);
+            differ = differ || value != other.get(x, y, z);
+        }
+        assert!(differ);
+
+        // The lattice wraps every 256 cells
+        assert_eq!(noise.get(0.5, 0.25, 0.75), noise.get(256.5, -255.75, 0.75));
 
-    pub fn set_seed(&mut self, seed: u32) {
+        noise.set_seed(43);
+        assert_eq!(noise.get(0.5, 0.25, 0.75), other.get(0.5, 0.25, 0.75));
     }
 }
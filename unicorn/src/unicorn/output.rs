@@ -0,0 +1,242 @@
+//! Outputs of the frames: the GIF and the video records, and the sinks
+//! added by the frontends with `Unicorn::add_output` (a mirror on the
+//! network, a v4l2 device ...). The frame of the game is converted once,
+//! after `_draw` and before the overlays of the console, and presented to
+//! each output, instead of each one reading the screen again. The window
+//! and the screenshots are given a `Frame` too, the one of the window with
+//! the overlays (`Unicorn::display_frame`).
+
+use std::collections::HashMap;
+
+use gfx::Screen;
+use unicorn::PALETTE;
+
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    /// Values of the frame buffer, row by row
+    pub pixels: Vec<u32>,
+    /// RGB colors of the pixels with the display palette, row by row
+    pub rgb: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(screen: &Screen) -> Frame {
+        let mut palette = PALETTE.lock().unwrap();
+        let rgb = palette_rgb(&screen.frame_buffer, |value| {
+            let color = palette.get_rgb(screen.display_color(value));
+            [color.r, color.g, color.b]
+        });
+
+        Frame {
+            width: screen.width,
+            height: screen.height,
+            pixels: screen.frame_buffer.clone(),
+            rgb: rgb,
+        }
+    }
+
+    /// Nothing shown yet
+    pub fn empty() -> Frame {
+        Frame {
+            width: 0,
+            height: 0,
+            pixels: Vec::new(),
+            rgb: Vec::new(),
+        }
+    }
+
+    /// RGB colors with each pixel repeated `scale` times, without blur
+    pub fn scaled_rgb(&self, scale: usize) -> Vec<u8> {
+        let scale = scale.max(1);
+        let mut scaled = Vec::with_capacity(self.rgb.len() * scale * scale);
+        for row in self.rgb.chunks(self.width.max(1) * 3) {
+            let mut line = Vec::with_capacity(row.len() * scale);
+            for pixel in row.chunks(3) {
+                for _ in 0..scale {
+                    line.extend_from_slice(pixel);
+                }
+            }
+            for _ in 0..scale {
+                scaled.extend_from_slice(&line);
+            }
+        }
+        scaled
+    }
+}
+
+/// RGB colors of the values with a palette table: `color` is called once
+/// per frame for each value, the values below 256 are in an array and the
+/// others (the extended colors) in a map
+pub fn palette_rgb<F>(pixels: &[u32], mut color: F) -> Vec<u8>
+    where F: FnMut(u32) -> [u8; 3]
+{
+    let mut table: Vec<Option<[u8; 3]>> = vec![None; 256];
+    let mut extended: HashMap<u32, [u8; 3]> = HashMap::new();
+    let mut rgb = Vec::with_capacity(pixels.len() * 3);
+
+    for value in pixels.iter() {
+        let entry = if *value < 256 {
+            let entry = &mut table[*value as usize];
+            if entry.is_none() {
+                *entry = Some(color(*value));
+            }
+            entry.unwrap()
+        } else {
+            *extended.entry(*value).or_insert_with(|| color(*value))
+        };
+        rgb.extend_from_slice(&entry);
+    }
+    rgb
+}
+
+pub trait Output {
+    /// Name for the logs and `Outputs::remove`
+    fn name(&self) -> String;
+    /// Called once per frame, an error removes the output
+    fn present(&mut self, frame: &Frame) -> Result<(), String>;
+}
+
+pub struct Outputs {
+    outputs: Vec<Box<Output>>,
+}
+
+impl Outputs {
+    pub fn new() -> Outputs {
+        Outputs { outputs: Vec::new() }
+    }
+
+    pub fn add(&mut self, output: Box<Output>) {
+        info!("[Unicorn][Output] Add {:?}", output.name());
+        self.outputs.push(output);
+    }
+
+    /// False when there is no output of this name
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.outputs.len();
+        self.outputs.retain(|output| output.name() != name);
+        self.outputs.len() != len
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.outputs.iter().map(|output| output.name()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+
+    pub fn present(&mut self, frame: &Frame) {
+        let mut failed = Vec::new();
+        for (idx, output) in self.outputs.iter_mut().enumerate() {
+            if let Err(e) = output.present(frame) {
+                error!("[Unicorn][Output] {:?} removed: {}", output.name(), e);
+                failed.push(idx);
+            }
+        }
+
+        for idx in failed.into_iter().rev() {
+            self.outputs.remove(idx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{palette_rgb, Frame, Output, Outputs};
+
+    struct Sink {
+        name: String,
+        frames: Arc<Mutex<Vec<Vec<u8>>>>,
+        /// Frames before an error
+        remaining: u32,
+    }
+
+    impl Output for Sink {
+        fn name(&self) -> String {
+            self.name.clone()
+        }
+
+        fn present(&mut self, frame: &Frame) -> Result<(), String> {
+            if self.remaining == 0 {
+                return Err("closed".to_string());
+            }
+            self.remaining -= 1;
+            self.frames.lock().unwrap().push(frame.rgb.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_outputs() {
+        let window = Arc::new(Mutex::new(Vec::new()));
+        let mirror = Arc::new(Mutex::new(Vec::new()));
+
+        let mut outputs = Outputs::new();
+        assert!(outputs.is_empty());
+        outputs.add(Box::new(Sink {
+                                 name: "window".to_string(),
+                                 frames: window.clone(),
+                                 remaining: 10,
+                             }));
+        outputs.add(Box::new(Sink {
+                                 name: "mirror".to_string(),
+                                 frames: mirror.clone(),
+                                 remaining: 1,
+                             }));
+
+        let frame = Frame {
+            width: 1,
+            height: 1,
+            pixels: vec![7],
+            rgb: vec![255, 241, 232],
+        };
+
+        // The same frame for each output
+        outputs.present(&frame);
+        assert_eq!(*window.lock().unwrap(), vec![vec![255, 241, 232]]);
+        assert_eq!(*mirror.lock().unwrap(), vec![vec![255, 241, 232]]);
+
+        // The mirror fails and is removed
+        outputs.present(&frame);
+        assert_eq!(outputs.names(), vec!["window".to_string()]);
+        assert_eq!(window.lock().unwrap().len(), 2);
+        assert_eq!(mirror.lock().unwrap().len(), 1);
+
+        assert!(outputs.remove("window"));
+        assert!(!outputs.remove("window"));
+        assert!(outputs.is_empty());
+    }
+
+    #[test]
+    fn test_scaled_rgb() {
+        let frame = Frame {
+            width: 2,
+            height: 1,
+            pixels: vec![0, 7],
+            rgb: vec![0, 0, 0, 255, 241, 232],
+        };
+        assert_eq!(frame.scaled_rgb(1), frame.rgb);
+        assert_eq!(frame.scaled_rgb(2),
+                   vec![0, 0, 0, 0, 0, 0, 255, 241, 232, 255, 241, 232,
+                        0, 0, 0, 0, 0, 0, 255, 241, 232, 255, 241, 232]);
+        assert!(Frame::empty().scaled_rgb(3).is_empty());
+    }
+
+    #[test]
+    fn test_palette_rgb() {
+        let mut lookups = Vec::new();
+        let rgb = palette_rgb(&[7, 7, 300, 0, 300, 0x10000], |value| {
+            lookups.push(value);
+            [value as u8, (value >> 8) as u8, (value >> 16) as u8]
+        });
+        assert_eq!(rgb,
+                   vec![7, 0, 0, 7, 0, 0, 44, 1, 0, 0, 0, 0, 44, 1, 0, 0, 0, 1]);
+
+        // One lookup for each value, the extended colors too
+        assert_eq!(lookups, vec![7, 300, 0, 0x10000]);
+        assert!(palette_rgb(&[], |_| [0, 0, 0]).is_empty());
+    }
+}
@@ -0,0 +1,578 @@
+use std::cmp;
+use std::path::Path;
+
+use config::MAX_PLAYERS;
+use config::hotkeys::HOTKEYS;
+use config::keybindings::PlayerKeyBindings;
+use config::keys::PX8Key;
+use gfx::Screen;
+use unicorn::permissions::Permission;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OverlayPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl OverlayPosition {
+    pub fn from_name(name: &str) -> Option<OverlayPosition> {
+        match name.to_lowercase().as_str() {
+            "top-left" => Some(OverlayPosition::TopLeft),
+            "top-right" => Some(OverlayPosition::TopRight),
+            "bottom-left" => Some(OverlayPosition::BottomLeft),
+            "bottom-right" => Some(OverlayPosition::BottomRight),
+            _ => None,
+        }
+    }
+}
+
+// 4x4 Bayer matrix used to dither the background of the overlay
+const DITHER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Corner overlay with the cartridge title, the FPS and the play time,
+/// drawn above the game for the streamers
+pub struct StatsOverlay {
+    pub enabled: bool,
+    pub position: OverlayPosition,
+    /// Opacity of the background, from 0 (transparent) to 100
+    pub opacity: u8,
+    pub background: u32,
+    pub color: u32,
+    pub play_time: f64,
+}
+
+impl StatsOverlay {
+    pub fn new() -> StatsOverlay {
+        StatsOverlay {
+            enabled: false,
+            position: OverlayPosition::TopRight,
+            opacity: 50,
+            background: 0,
+            color: 7,
+            play_time: 0.0,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    pub fn set_opacity(&mut self, opacity: u8) {
+        self.opacity = if opacity > 100 { 100 } else { opacity };
+    }
+
+    pub fn reset(&mut self) {
+        self.play_time = 0.0;
+    }
+
+    pub fn update(&mut self, dt: f64) {
+        self.play_time += dt;
+    }
+
+    /// Only the name of the file of the cartridge is shown, not its directory
+    pub fn get_lines(&self, title: &str, fps: f64) -> Vec<String> {
+        let seconds = self.play_time as u64;
+        let title = match Path::new(title).file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => title.to_string(),
+        };
+
+        vec![title,
+             format!("{:.0} FPS", fps),
+             format!("{:02}:{:02}:{:02}",
+                     seconds / 3600,
+                     (seconds / 60) % 60,
+                     seconds % 60)]
+    }
+
+    pub fn draw(&self, screen: &mut Screen, title: &str, fps: f64) {
+        if !self.enabled {
+            return;
+        }
+
+        let lines = self.get_lines(title, fps);
+
+        let line_height = 8;
+        let width = lines.iter().map(|line| screen.text_width(line)).max().unwrap_or(0) + 4;
+        let height = lines.len() as i32 * line_height + 2;
+
+        let screen_width = screen.width as i32;
+        let screen_height = screen.height as i32;
+
+        let (x, y) = match self.position {
+            OverlayPosition::TopLeft => (0, 0),
+            OverlayPosition::TopRight => (screen_width - width, 0),
+            OverlayPosition::BottomLeft => (0, screen_height - height),
+            OverlayPosition::BottomRight => (screen_width - width, screen_height - height),
+        };
+
+        // Not affected by the camera/clip of the cartridge
+        for j in 0..height {
+            for i in 0..width {
+                let threshold = DITHER[(j % 4) as usize][(i % 4) as usize] as u32 * 100 / 16;
+                if threshold < self.opacity as u32 {
+                    screen.putpixel_direct(x + i, y + j, self.background);
+                }
+            }
+        }
+
+        for (idx, line) in lines.iter().enumerate() {
+            screen.force_print(line.clone(),
+                               x + 2,
+                               y + 2 + idx as i32 * line_height,
+                               self.color as i32);
+        }
+    }
+}
+
+/// Buttons of the players in the help, in the order of `btn`
+const BUTTONS: [PX8Key; 8] = [PX8Key::Left, PX8Key::Right, PX8Key::Up, PX8Key::Down, PX8Key::A,
+                              PX8Key::B, PX8Key::Enter, PX8Key::Pause];
+
+/// Keys of the console and of the players (F1), read from the table of the
+/// hotkeys and from the keybindings, in columns when they don't fit
+pub struct HelpOverlay {
+    pub enabled: bool,
+    pub background: u32,
+    pub color: u32,
+    pub title_color: u32,
+}
+
+impl HelpOverlay {
+    pub fn new() -> HelpOverlay {
+        HelpOverlay {
+            enabled: false,
+            background: 0,
+            color: 6,
+            title_color: 7,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    /// The titles start with '#'
+    pub fn get_lines(&self, bindings: &PlayerKeyBindings) -> Vec<String> {
+        let mut lines = vec!["#CONSOLE".to_string()];
+        for &(scancode, hotkey, description) in HOTKEYS.iter() {
+            if hotkey.is_available() {
+                lines.push(format!("{:<4}{}", format!("{:?}", scancode), description));
+            }
+        }
+
+        for player in 0..MAX_PLAYERS {
+            let mut buttons = Vec::new();
+            for key in BUTTONS.iter() {
+                let names: Vec<String> = bindings
+                    .keys(player, *key)
+                    .iter()
+                    .map(|scancode| format!("{:?}", scancode))
+                    .collect();
+                if !names.is_empty() {
+                    buttons.push(format!("{:<6}{}", format!("{:?}", key), names.join(",")));
+                }
+            }
+
+            if !buttons.is_empty() {
+                lines.push(format!("#PLAYER {}", player + 1));
+                lines.extend(buttons);
+            }
+        }
+
+        lines
+    }
+
+    pub fn draw(&self, screen: &mut Screen, bindings: &PlayerKeyBindings) {
+        if !self.enabled {
+            return;
+        }
+
+        let screen_width = screen.width as i32;
+        let screen_height = screen.height as i32;
+
+        for j in 0..screen_height {
+            for i in 0..screen_width {
+                if DITHER[(j % 4) as usize][(i % 4) as usize] < 12 {
+                    screen.putpixel_direct(i, j, self.background);
+                }
+            }
+        }
+
+        let line_height = 8;
+        let rows = cmp::max((screen_height - 4) / line_height, 1) as usize;
+        let lines = self.get_lines(bindings);
+
+        let mut x = 2;
+        for column in lines.chunks(rows) {
+            let mut width = 0;
+            for (idx, line) in column.iter().enumerate() {
+                let (text, color) = if line.starts_with('#') {
+                    (line[1..].to_string(), self.title_color)
+                } else {
+                    (line.clone(), self.color)
+                };
+
+                width = cmp::max(width, screen.text_width(&text));
+                screen.force_print(text, x, 2 + idx as i32 * line_height, color as i32);
+            }
+            x += width + 8;
+        }
+    }
+}
+
+/// Zoom of the area under the mouse cursor, with the coordinates and the
+/// color index of the pointed pixel
+pub struct Magnifier {
+    pub enabled: bool,
+    pub zoom: i32,
+    /// Number of pixels displayed around the cursor
+    pub radius: i32,
+    pub border: u32,
+    pub color: u32,
+}
+
+impl Magnifier {
+    pub fn new() -> Magnifier {
+        Magnifier {
+            enabled: false,
+            zoom: 4,
+            radius: 8,
+            border: 7,
+            color: 7,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    /// Copy of the pixels around x/y, the pixels outside of the screen are None
+    pub fn sample(&self, screen: &Screen, x: i32, y: i32) -> Vec<Option<u32>> {
+        let size = self.radius * 2 + 1;
+        let mut pixels = Vec::with_capacity((size * size) as usize);
+
+        for j in y - self.radius..y + self.radius + 1 {
+            for i in x - self.radius..x + self.radius + 1 {
+                if i < 0 || j < 0 || i >= screen.width as i32 || j >= screen.height as i32 {
+                    pixels.push(None);
+                } else {
+                    pixels.push(Some(screen.frame_buffer[screen.pixel_offset(i, j)]));
+                }
+            }
+        }
+
+        pixels
+    }
+
+    pub fn draw(&self, screen: &mut Screen, mouse_x: i32, mouse_y: i32) {
+        if !self.enabled {
+            return;
+        }
+
+        // Read the pixels before drawing anything above them
+        let pixels = self.sample(screen, mouse_x, mouse_y);
+        let size = self.radius * 2 + 1;
+        let color = pixels[(self.radius * size + self.radius) as usize];
+
+        let zoom_size = size * self.zoom;
+        let width = zoom_size + 2;
+        let height = zoom_size + 2 + 16;
+
+        // In the opposite corner of the cursor to never hide the pointed area
+        let screen_width = screen.width as i32;
+        let screen_height = screen.height as i32;
+        let x = if mouse_x < screen_width / 2 { screen_width - width } else { 0 };
+        let y = if mouse_y < screen_height / 2 { screen_height - height } else { 0 };
+
+        for j in 0..height {
+            for i in 0..width {
+                let border = i == 0 || j == 0 || i == width - 1 || j == height - 1 ||
+                             j == zoom_size + 1;
+                screen.putpixel_direct(x + i, y + j, if border { self.border } else { 0 });
+            }
+        }
+
+        for (idx, pixel) in pixels.iter().enumerate() {
+            if let Some(pixel) = *pixel {
+                let px = x + 1 + (idx as i32 % size) * self.zoom;
+                let py = y + 1 + (idx as i32 / size) * self.zoom;
+
+                for j in 0..self.zoom {
+                    for i in 0..self.zoom {
+                        screen.putpixel_direct(px + i, py + j, pixel);
+                    }
+                }
+            }
+        }
+
+        // Frame around the pointed pixel
+        let cx = x + 1 + self.radius * self.zoom - 1;
+        let cy = y + 1 + self.radius * self.zoom - 1;
+        for i in 0..self.zoom + 2 {
+            screen.putpixel_direct(cx + i, cy, self.border);
+            screen.putpixel_direct(cx + i, cy + self.zoom + 1, self.border);
+            screen.putpixel_direct(cx, cy + i, self.border);
+            screen.putpixel_direct(cx + self.zoom + 1, cy + i, self.border);
+        }
+
+        let color = match color {
+            Some(color) => format!("COL {}", color),
+            None => "COL -".to_string(),
+        };
+
+        screen.force_print(format!("{},{}", mouse_x, mouse_y),
+                           x + 2,
+                           y + zoom_size + 3,
+                           self.color as i32);
+        screen.force_print(color, x + 2, y + zoom_size + 10, self.color as i32);
+    }
+}
+
+/// Question of a cartridge for a capability, over its frozen frame: left and
+/// right choose the answer, A confirms it and B denies
+pub struct PermissionPrompt {
+    /// Answer selected, "Deny" by default
+    pub allow: bool,
+    pub background: u32,
+    pub border: u32,
+    pub color: u32,
+    pub selected_color: u32,
+}
+
+impl PermissionPrompt {
+    pub fn new() -> PermissionPrompt {
+        PermissionPrompt {
+            allow: false,
+            background: 1,
+            border: 7,
+            color: 6,
+            selected_color: 10,
+        }
+    }
+
+    /// The answer of the player, once confirmed
+    pub fn update(&mut self, left: bool, right: bool, confirm: bool, deny: bool) -> Option<bool> {
+        if left || right {
+            self.allow = !self.allow;
+        }
+
+        let answer = if deny {
+            Some(false)
+        } else if confirm {
+            Some(self.allow)
+        } else {
+            None
+        };
+
+        if answer.is_some() {
+            self.allow = false;
+        }
+        answer
+    }
+
+    pub fn get_lines(&self, title: &str, permission: Permission) -> Vec<String> {
+        let (allow, deny) = if self.allow {
+            (">ALLOW<", " DENY ")
+        } else {
+            (" ALLOW ", ">DENY<")
+        };
+
+        vec![title.to_string(),
+             format!("wants to {}", permission.description()),
+             "".to_string(),
+             format!("{}   {}", allow, deny)]
+    }
+
+    pub fn draw(&self, screen: &mut Screen, title: &str, permission: Permission) {
+        let lines = self.get_lines(title, permission);
+
+        let line_height = 8;
+        let width = lines.iter().map(|line| screen.text_width(line)).max().unwrap_or(0) + 12;
+        let height = lines.len() as i32 * line_height + 10;
+        let x = (screen.width as i32 - width) / 2;
+        let y = (screen.height as i32 - height) / 2;
+
+        for j in 0..height {
+            for i in 0..width {
+                let border = i == 0 || j == 0 || i == width - 1 || j == height - 1;
+                screen.putpixel_direct(x + i, y + j, if border { self.border } else { self.background });
+            }
+        }
+
+        let last = lines.len() - 1;
+        for (idx, line) in lines.into_iter().enumerate() {
+            let color = if idx == last { self.selected_color } else { self.color };
+            screen.force_print(line, x + 6, y + 6 + idx as i32 * line_height, color as i32);
+        }
+    }
+}
+
+/// Profiles of the console at boot: up and down select one, A (or Return)
+/// confirms it
+pub struct ProfilePicker {
+    pub names: Vec<String>,
+    pub selected: usize,
+    pub background: u32,
+    pub border: u32,
+    pub color: u32,
+    pub selected_color: u32,
+}
+
+impl ProfilePicker {
+    pub fn new(names: Vec<String>) -> ProfilePicker {
+        ProfilePicker {
+            names: names,
+            selected: 0,
+            background: 1,
+            border: 7,
+            color: 6,
+            selected_color: 10,
+        }
+    }
+
+    /// The name of the profile, once confirmed
+    pub fn update(&mut self, up: bool, down: bool, confirm: bool) -> Option<String> {
+        if self.names.is_empty() {
+            return None;
+        }
+
+        if up {
+            self.selected = (self.selected + self.names.len() - 1) % self.names.len();
+        }
+        if down {
+            self.selected = (self.selected + 1) % self.names.len();
+        }
+
+        if confirm {
+            Some(self.names[self.selected].clone())
+        } else {
+            None
+        }
+    }
+
+    pub fn get_lines(&self) -> Vec<String> {
+        let mut lines = vec!["WHO IS PLAYING?".to_string(), "".to_string()];
+        for (idx, name) in self.names.iter().enumerate() {
+            if idx == self.selected {
+                lines.push(format!(">{}<", name));
+            } else {
+                lines.push(format!(" {} ", name));
+            }
+        }
+        lines
+    }
+
+    pub fn draw(&self, screen: &mut Screen) {
+        let lines = self.get_lines();
+
+        let line_height = 8;
+        let width = lines.iter().map(|line| screen.text_width(line)).max().unwrap_or(0) + 12;
+        let height = lines.len() as i32 * line_height + 10;
+        let x = (screen.width as i32 - width) / 2;
+        let y = (screen.height as i32 - height) / 2;
+
+        for j in 0..height {
+            for i in 0..width {
+                let border = i == 0 || j == 0 || i == width - 1 || j == height - 1;
+                screen.putpixel_direct(x + i, y + j, if border { self.border } else { self.background });
+            }
+        }
+
+        for (idx, line) in lines.into_iter().enumerate() {
+            let color = if idx == self.selected + 2 { self.selected_color } else { self.color };
+            screen.force_print(line, x + 6, y + 6 + idx as i32 * line_height, color as i32);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gfx::Screen;
+
+    use config::keybindings::PlayerKeyBindings;
+
+    use unicorn::permissions::Permission;
+
+    use super::{HelpOverlay, Magnifier, PermissionPrompt, ProfilePicker, StatsOverlay};
+
+    #[test]
+    fn test_stats_lines() {
+        let mut overlay = StatsOverlay::new();
+        overlay.update(3725.5);
+        assert_eq!(overlay.get_lines("../games/snake/snake.uni", 59.6),
+                   vec!["snake.uni", "60 FPS", "01:02:05"]);
+        assert_eq!(overlay.get_lines("", 30.0)[0], "");
+
+        overlay.reset();
+        assert_eq!(overlay.get_lines("snake.png", 0.0)[2], "00:00:00");
+
+        overlay.set_opacity(150);
+        assert_eq!(overlay.opacity, 100);
+    }
+
+    #[test]
+    fn test_magnifier_sample() {
+        let mut screen = Screen::new(32, 32);
+        screen.putpixel_direct(0, 0, 3);
+        screen.putpixel_direct(1, 0, 5);
+
+        let magnifier = Magnifier::new();
+        let pixels = magnifier.sample(&screen, 0, 0);
+        let size = (magnifier.radius * 2 + 1) as usize;
+
+        assert_eq!(pixels.len(), size * size);
+        assert_eq!(pixels[0], None);
+
+        let center = magnifier.radius as usize * size + magnifier.radius as usize;
+        assert_eq!(pixels[center], Some(3));
+        assert_eq!(pixels[center + 1], Some(5));
+    }
+
+    #[test]
+    fn test_help_lines() {
+        let lines = HelpOverlay::new().get_lines(&PlayerKeyBindings::new());
+
+        assert_eq!(lines[0], "#CONSOLE");
+        assert!(lines.contains(&"F1  This help".to_string()));
+        assert!(lines.contains(&"#PLAYER 1".to_string()));
+        assert!(lines.contains(&"A     C,N,Z".to_string()));
+        // Only the players with keys
+        assert!(!lines.contains(&"#PLAYER 8".to_string()));
+    }
+
+    #[test]
+    fn test_permission_prompt() {
+        let mut prompt = PermissionPrompt::new();
+        let lines = prompt.get_lines("Snake", Permission::Keyboard);
+        assert_eq!(lines[1], "wants to read the whole keyboard");
+        assert_eq!(lines[3], " ALLOW    >DENY<");
+
+        assert_eq!(prompt.update(false, false, false, false), None);
+        assert_eq!(prompt.update(false, true, false, false), None);
+        assert!(prompt.allow);
+        assert_eq!(prompt.update(false, false, true, false), Some(true));
+        // Deny is selected again for the next question
+        assert!(!prompt.allow);
+        assert_eq!(prompt.update(true, false, false, true), Some(false));
+        assert_eq!(prompt.update(false, false, true, false), Some(false));
+    }
+
+    #[test]
+    fn test_profile_picker() {
+        let mut picker = ProfilePicker::new(vec!["alice".to_string(), "bob".to_string(), "default".to_string()]);
+        assert_eq!(picker.get_lines(),
+                   vec!["WHO IS PLAYING?", "", ">alice<", " bob ", " default "]);
+
+        assert_eq!(picker.update(false, true, false), None);
+        assert_eq!(picker.get_lines()[3], ">bob<");
+        // Up from the first one selects the last one
+        picker.update(true, false, false);
+        assert_eq!(picker.update(true, false, true), Some("default".to_string()));
+        assert_eq!(picker.update(false, true, true), Some("alice".to_string()));
+
+        assert_eq!(ProfilePicker::new(Vec::new()).update(false, true, true), None);
+    }
+}
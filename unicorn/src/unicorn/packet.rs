@@ -235,6 +235,19 @@ create_packets!(
     packet ChiptuneVolume {
         field volume: i32 =,
     }
+    packet ChiptunePattern {
+        field n: i32 =,
+        field fade_ms: i32 =,
+        field channel_mask: i32 =,
+    }
+    packet ChiptuneNewSFX {
+        field name: String =,
+        field waveform: String =,
+        field attack: u8 =,
+        field decay: u8 =,
+        field sustain: u8 =,
+        field release: u8 =,
+    }
 );
 
 
@@ -0,0 +1,144 @@
+//! A* of `pathfind` on the cells of the map: the walls are the cells with a
+//! sprite having one of the flags of a mask, the moves are horizontal and
+//! vertical, one cell at a time.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// Cell to explore, the smallest estimate first
+#[derive(Eq, PartialEq)]
+struct Node {
+    estimate: u32,
+    cost: u32,
+    idx: usize,
+}
+
+impl Ord for Node {
+    fn cmp(&self, other: &Node) -> Ordering {
+        // The nearest to the goal first for the same estimate
+        other
+            .estimate
+            .cmp(&self.estimate)
+            .then(self.cost.cmp(&other.cost))
+            .then(other.idx.cmp(&self.idx))
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Node) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn distance(a: (i32, i32), b: (i32, i32)) -> u32 {
+    ((a.0 - b.0).abs() + (a.1 - b.1).abs()) as u32
+}
+
+/// Shortest path between two cells of a `width`x`height` map, both of them
+/// included, or nothing when the goal can't be reached. The start could be a
+/// wall (a body stuck in it), not the goal.
+pub fn find<F>(from: (i32, i32),
+               to: (i32, i32),
+               width: usize,
+               height: usize,
+               is_wall: &mut F)
+               -> Option<Vec<(i32, i32)>>
+    where F: FnMut(i32, i32) -> bool
+{
+    let inside = |cell: (i32, i32)| {
+        cell.0 >= 0 && cell.1 >= 0 && (cell.0 as usize) < width && (cell.1 as usize) < height
+    };
+    if !inside(from) || !inside(to) || is_wall(to.0, to.1) {
+        return None;
+    }
+
+    let index = |cell: (i32, i32)| cell.0 as usize + cell.1 as usize * width;
+    let mut costs = vec![::std::u32::MAX; width * height];
+    let mut parents = vec![::std::usize::MAX; width * height];
+    let mut open = BinaryHeap::new();
+
+    costs[index(from)] = 0;
+    open.push(Node {
+                  estimate: distance(from, to),
+                  cost: 0,
+                  idx: index(from),
+              });
+
+    while let Some(node) = open.pop() {
+        let cell = ((node.idx % width) as i32, (node.idx / width) as i32);
+        if cell == to {
+            let mut path = vec![cell];
+            let mut idx = node.idx;
+            while parents[idx] != ::std::usize::MAX {
+                idx = parents[idx];
+                path.push(((idx % width) as i32, (idx / width) as i32));
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        // Already reached by a shorter path
+        if node.cost > costs[node.idx] {
+            continue;
+        }
+
+        for &(dx, dy) in [(1, 0), (0, 1), (-1, 0), (0, -1)].iter() {
+            let next = (cell.0 + dx, cell.1 + dy);
+            if !inside(next) || is_wall(next.0, next.1) {
+                continue;
+            }
+
+            let cost = node.cost + 1;
+            let idx = index(next);
+            if cost < costs[idx] {
+                costs[idx] = cost;
+                parents[idx] = node.idx;
+                open.push(Node {
+                              estimate: cost + distance(next, to),
+                              cost: cost,
+                              idx: idx,
+                          });
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::find;
+
+    fn maze(rows: &[&str]) -> Vec<Vec<u8>> {
+        rows.iter().map(|row| row.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_find() {
+        let rows = maze(&["....#...", ".##.#.#.", ".#..#.#.", ".#.##.#.", "......#."]);
+        let mut is_wall = |x: i32, y: i32| rows[y as usize][x as usize] == b'#';
+
+        let path = find((0, 0), (7, 0), 8, 5, &mut is_wall).unwrap();
+        assert_eq!(path.first(), Some(&(0, 0)));
+        assert_eq!(path.last(), Some(&(7, 0)));
+        // Down the left column, along the bottom and up the column 5
+        assert_eq!(path.len(), 16);
+        assert_eq!(path[4], (0, 4));
+        assert_eq!(path[9], (5, 4));
+        for step in path.windows(2) {
+            assert_eq!((step[0].0 - step[1].0).abs() + (step[0].1 - step[1].1).abs(), 1);
+            assert!(!is_wall(step[1].0, step[1].1));
+        }
+
+        assert_eq!(find((2, 2), (2, 2), 8, 5, &mut is_wall), Some(vec![(2, 2)]));
+        // A wall or outside of the map
+        assert_eq!(find((0, 0), (4, 0), 8, 5, &mut is_wall), None);
+        assert_eq!(find((0, 0), (8, 0), 8, 5, &mut is_wall), None);
+
+        // Walled in
+        let rows = maze(&[".#.", "##.", "..."]);
+        let mut is_wall = |x: i32, y: i32| rows[y as usize][x as usize] == b'#';
+        assert_eq!(find((0, 0), (2, 2), 3, 3, &mut is_wall), None);
+        assert_eq!(find((2, 0), (0, 2), 3, 3, &mut is_wall).map(|path| path.len()), Some(5));
+    }
+}
@@ -0,0 +1,211 @@
+//! Privileged capabilities of the cartridges: the whole keyboard
+//! (`keyboard_text`, `key_pressed`), the network and the files. The first
+//! use by a cartridge asks the player with a prompt over the game, and the
+//! answer is remembered for this cartridge in <profile>/permissions.json:
+//! ```text
+//! {"8f3a5d8e7c6b4a21": {"keyboard": true, "network": false}}
+//! ```
+//! The answers are keyed by the hash of the content of the cartridge, not by
+//! the UUID of its `__meta__` that another cartridge could copy, so a changed
+//! cartridge is asked again. The capability is refused until the player
+//! answers.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use serde_json;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Permission {
+    Keyboard,
+    Network,
+    Filesystem,
+}
+
+pub const PERMISSIONS: [Permission; 3] =
+    [Permission::Keyboard, Permission::Network, Permission::Filesystem];
+
+impl Permission {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Permission::Keyboard => "keyboard",
+            Permission::Network => "network",
+            Permission::Filesystem => "filesystem",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Permission> {
+        PERMISSIONS
+            .iter()
+            .find(|permission| permission.name() == name)
+            .cloned()
+    }
+
+    /// End of the question of the prompt, "The cartridge wants to ..."
+    pub fn description(&self) -> &'static str {
+        match *self {
+            Permission::Keyboard => "read the whole keyboard",
+            Permission::Network => "use the network",
+            Permission::Filesystem => "read and write files",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PermissionRequest {
+    /// Key of the cartridge, the hash of its content
+    pub cartridge: String,
+    pub permission: Permission,
+}
+
+pub struct Permissions {
+    /// permissions.json of the profile, nothing is saved without it
+    pub path: Option<PathBuf>,
+    /// Answers of the player, by cartridge then by name of permission
+    pub choices: HashMap<String, HashMap<String, bool>>,
+    /// Question waiting for the player
+    pub request: Option<PermissionRequest>,
+}
+
+impl Permissions {
+    pub fn new() -> Permissions {
+        Permissions {
+            path: None,
+            choices: HashMap::new(),
+            request: None,
+        }
+    }
+
+    /// Read the answers saved in `path`, none when the file is missing
+    pub fn load(&mut self, path: PathBuf) {
+        self.choices.clear();
+        self.request = None;
+
+        let mut data = String::new();
+        if let Ok(mut f) = File::open(&path) {
+            if let Err(e) = f.read_to_string(&mut data) {
+                error!("[Unicorn][PERMISSIONS] Failed to read {:?}: {}", path, e);
+            }
+        }
+
+        if !data.is_empty() {
+            match serde_json::from_str::<HashMap<String, HashMap<String, bool>>>(&data) {
+                Ok(choices) => self.choices = choices,
+                Err(e) => error!("[Unicorn][PERMISSIONS] Invalid {:?}: {}", path, e),
+            }
+        }
+
+        self.path = Some(path);
+    }
+
+    fn save(&self) {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return,
+        };
+
+        let data = match serde_json::to_string(&self.choices) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("[Unicorn][PERMISSIONS] Failed to serialize the permissions: {}", e);
+                return;
+            }
+        };
+
+        match File::create(path) {
+            Ok(mut f) => {
+                if let Err(e) = f.write_all(data.as_bytes()) {
+                    error!("[Unicorn][PERMISSIONS] Failed to write {:?}: {}", path, e);
+                }
+            }
+            Err(e) => error!("[Unicorn][PERMISSIONS] Failed to create {:?}: {}", path, e),
+        }
+    }
+
+    /// Answer of the player, None when it was never asked
+    pub fn get(&self, cartridge: &str, permission: Permission) -> Option<bool> {
+        self.choices
+            .get(cartridge)
+            .and_then(|choices| choices.get(permission.name()))
+            .cloned()
+    }
+
+    /// The answer for `cartridge`, or false and a question for the player
+    pub fn check(&mut self, cartridge: &str, permission: Permission) -> bool {
+        if let Some(value) = self.get(cartridge, permission) {
+            return value;
+        }
+
+        if self.request.is_none() {
+            info!("[Unicorn][PERMISSIONS] {:?} asks for {:?}", cartridge, permission);
+            self.request = Some(PermissionRequest {
+                                    cartridge: cartridge.to_string(),
+                                    permission: permission,
+                                });
+        }
+        false
+    }
+
+    /// Remember the answer to the question, for the next runs too
+    pub fn answer(&mut self, allow: bool) {
+        let request = match self.request.take() {
+            Some(request) => request,
+            None => return,
+        };
+
+        info!("[Unicorn][PERMISSIONS] {:?} {:?} = {:?}",
+              request.cartridge,
+              request.permission,
+              allow);
+
+        self.choices
+            .entry(request.cartridge)
+            .or_insert(HashMap::new())
+            .insert(request.permission.name().to_string(), allow);
+        self.save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use super::{Permission, Permissions};
+
+    #[test]
+    fn test_permissions() {
+        let path = env::temp_dir().join("unicorn-test-permissions.json");
+        let _ = fs::remove_file(&path);
+
+        let mut permissions = Permissions::new();
+        permissions.load(path.clone());
+        assert_eq!(permissions.get("cart", Permission::Keyboard), None);
+
+        // Refused until the answer, with one question at a time
+        assert!(!permissions.check("cart", Permission::Keyboard));
+        assert!(!permissions.check("cart", Permission::Network));
+        assert_eq!(permissions.request.as_ref().map(|request| request.permission),
+                   Some(Permission::Keyboard));
+
+        permissions.answer(true);
+        assert!(permissions.request.is_none());
+        assert!(permissions.check("cart", Permission::Keyboard));
+        assert!(!permissions.check("other", Permission::Keyboard));
+        permissions.answer(false);
+
+        // Saved for the next runs
+        let mut permissions = Permissions::new();
+        permissions.load(path.clone());
+        assert_eq!(permissions.get("cart", Permission::Keyboard), Some(true));
+        assert_eq!(permissions.get("other", Permission::Keyboard), Some(false));
+        assert_eq!(permissions.get("cart", Permission::Network), None);
+
+        assert_eq!(Permission::from_name("network"), Some(Permission::Network));
+        assert_eq!(Permission::from_name("camera"), None);
+
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,463 @@
+//! Platformer bodies moved against the tiles of the map, with the flags of
+//! the sprites: `FLAG_SOLID` blocks from all the sides, `FLAG_ONE_WAY` only
+//! from above, and the slopes `FLAG_SLOPE_UP` (/) and `FLAG_SLOPE_DOWN` (\)
+//! are walkable diagonal floors of 45 degrees. The positions and speeds are
+//! fixed-point numbers, a body moves the same way on every machine. The
+//! values of the cartridges that aren't finite are ignored, and the speeds
+//! are limited to `MAX_SPEED`.
+
+use std::collections::HashMap;
+
+use unicorn::fixed::{Fixed, ONE, ZERO};
+
+/// Flag 0 of the sprite
+pub const FLAG_SOLID: u8 = 1;
+/// Flag 1 of the sprite
+pub const FLAG_ONE_WAY: u8 = 2;
+/// Flag 2 of the sprite: the floor goes up to the right
+pub const FLAG_SLOPE_UP: u8 = 4;
+/// Flag 3 of the sprite: the floor goes down to the right
+pub const FLAG_SLOPE_DOWN: u8 = 8;
+
+const TILE: i32 = 8;
+/// Pixels a body on the ground follows a floor going down, for the slopes
+const STICK_DISTANCE: Fixed = Fixed(4 << 16);
+const TWO: Fixed = Fixed(2 << 16);
+/// Fastest move of a body in a frame, in pixels, so a move checks a bounded
+/// number of tiles
+pub const MAX_SPEED: Fixed = Fixed(64 << 16);
+
+/// Fixed-point number of a value of the cartridge, None for NaN and the
+/// infinities
+pub fn finite(value: f64) -> Option<Fixed> {
+    if value.is_finite() {
+        Some(Fixed::from_f64(value))
+    } else {
+        None
+    }
+}
+
+fn clamp_speed(value: Fixed) -> Fixed {
+    value.max(-MAX_SPEED).min(MAX_SPEED)
+}
+
+fn div_floor(a: i32, b: i32) -> i32 {
+    let d = a / b;
+    if a % b != 0 && (a < 0) != (b < 0) {
+        d - 1
+    } else {
+        d
+    }
+}
+
+fn is_slope(flags: u8) -> bool {
+    flags & (FLAG_SLOPE_UP | FLAG_SLOPE_DOWN) != 0
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Body {
+    pub x: Fixed,
+    pub y: Fixed,
+    pub w: Fixed,
+    pub h: Fixed,
+    pub vx: Fixed,
+    pub vy: Fixed,
+    /// Pixels per frame added to `vy` at each move
+    pub gravity: Fixed,
+    pub max_fall: Fixed,
+    pub on_ground: bool,
+}
+
+impl Body {
+    pub fn new(x: Fixed, y: Fixed, w: Fixed, h: Fixed) -> Body {
+        Body {
+            x: x,
+            y: y,
+            w: w.max(ONE),
+            h: h.max(ONE),
+            vx: ZERO,
+            vy: ZERO,
+            gravity: Fixed(0x4000),
+            max_fall: Fixed(4 << 16),
+            on_ground: false,
+        }
+    }
+
+    /// Body of the values of the cartridge, None when one isn't finite
+    pub fn from_f64(x: f64, y: f64, w: f64, h: f64) -> Option<Body> {
+        match (finite(x), finite(y), finite(w), finite(h)) {
+            (Some(x), Some(y), Some(w), Some(h)) => Some(Body::new(x, y, w, h)),
+            _ => None,
+        }
+    }
+
+    /// Position and speeds of `body_set`, the values missing or not finite
+    /// are kept
+    pub fn set(&mut self, x: Option<f64>, y: Option<f64>, vx: Option<f64>, vy: Option<f64>) {
+        self.x = x.and_then(finite).unwrap_or(self.x);
+        self.y = y.and_then(finite).unwrap_or(self.y);
+        self.vx = clamp_speed(vx.and_then(finite).unwrap_or(self.vx));
+        self.vy = clamp_speed(vy.and_then(finite).unwrap_or(self.vy));
+    }
+
+    pub fn set_gravity(&mut self, gravity: f64, max_fall: Option<f64>) {
+        self.gravity = clamp_speed(finite(gravity).unwrap_or(self.gravity));
+        self.max_fall = clamp_speed(max_fall.and_then(finite).unwrap_or(self.max_fall));
+    }
+
+    fn hits_solid<F>(&self, x: Fixed, y: Fixed, tile: &mut F) -> bool
+        where F: FnMut(i32, i32) -> u8
+    {
+        let x0 = div_floor(x.to_int(), TILE);
+        let x1 = div_floor((x + self.w).ceil().to_int() - 1, TILE);
+        let y0 = div_floor(y.to_int(), TILE);
+        let y1 = div_floor((y + self.h).ceil().to_int() - 1, TILE);
+
+        for cy in y0..y1 + 1 {
+            for cx in x0..x1 + 1 {
+                let flags = tile(cx, cy);
+                if flags & FLAG_SOLID != 0 && !is_slope(flags) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Surface of the slope under the middle of the feet, when the bottom
+    /// is below it
+    fn slope_floor<F>(&self, x: Fixed, bottom: Fixed, tile: &mut F) -> Option<Fixed>
+        where F: FnMut(i32, i32) -> u8
+    {
+        let foot = (x + self.w / TWO).to_int();
+        let cx = div_floor(foot, TILE);
+        let cy = div_floor(bottom.ceil().to_int() - 1, TILE);
+
+        let flags = tile(cx, cy);
+        if !is_slope(flags) {
+            return None;
+        }
+
+        let lx = foot - cx * TILE;
+        let surface = Fixed::from_int(if flags & FLAG_SLOPE_UP != 0 {
+                                          cy * TILE + TILE - 1 - lx
+                                      } else {
+                                          cy * TILE + lx
+                                      });
+
+        if bottom > surface { Some(surface) } else { None }
+    }
+
+    /// Top of the one-way tiles crossed by the bottom between the 2 positions
+    fn one_way_floor<F>(&self,
+                        x: Fixed,
+                        old_bottom: Fixed,
+                        bottom: Fixed,
+                        tile: &mut F)
+                        -> Option<Fixed>
+        where F: FnMut(i32, i32) -> u8
+    {
+        let cy = div_floor(bottom.ceil().to_int() - 1, TILE);
+        let top = Fixed::from_int(cy * TILE);
+        if old_bottom > top || bottom <= top {
+            return None;
+        }
+
+        let x0 = div_floor(x.to_int(), TILE);
+        let x1 = div_floor((x + self.w).ceil().to_int() - 1, TILE);
+        for cx in x0..x1 + 1 {
+            if tile(cx, cy) & FLAG_ONE_WAY != 0 {
+                return Some(top);
+            }
+        }
+        None
+    }
+
+    /// The middle of the feet is on a slope
+    fn on_slope<F>(&self, tile: &mut F) -> bool
+        where F: FnMut(i32, i32) -> u8
+    {
+        let foot = (self.x + self.w / TWO).to_int();
+        let bottom = (self.y + self.h).ceil().to_int();
+        is_slope(tile(div_floor(foot, TILE), div_floor(bottom - 1, TILE))) ||
+        is_slope(tile(div_floor(foot, TILE), div_floor(bottom, TILE)))
+    }
+
+    fn move_x<F>(&mut self, dx: Fixed, tile: &mut F)
+        where F: FnMut(i32, i32) -> u8
+    {
+        let mut remaining = clamp_speed(dx);
+        while remaining != ZERO {
+            let step = remaining.max(-ONE).min(ONE);
+            let x = self.x + step;
+
+            // At the end of a slope, the side of the body is already in the
+            // tile where the middle of the feet will be
+            if self.on_ground && self.hits_solid(x, self.y, tile) && self.on_slope(tile) {
+                let mut climb = ONE;
+                while climb <= (self.w / TWO).ceil() + ONE {
+                    if !self.hits_solid(x, self.y - climb, tile) {
+                        self.y = self.y - climb;
+                        break;
+                    }
+                    climb = climb + ONE;
+                }
+            }
+
+            if self.hits_solid(x, self.y, tile) {
+                self.x = if step > ZERO {
+                    (x + self.w).ceil() - ONE - self.w
+                } else {
+                    x.floor() + ONE
+                };
+                self.vx = ZERO;
+                return;
+            }
+
+            self.x = x;
+            remaining = remaining - step;
+
+            // Walk up the slopes
+            if let Some(surface) = self.slope_floor(self.x, self.y + self.h, tile) {
+                if !self.hits_solid(self.x, surface - self.h, tile) {
+                    self.y = surface - self.h;
+                    self.on_ground = true;
+                }
+            }
+        }
+    }
+
+    /// Move down or up, true when the body landed
+    fn move_y<F>(&mut self, dy: Fixed, tile: &mut F) -> bool
+        where F: FnMut(i32, i32) -> u8
+    {
+        let mut remaining = clamp_speed(dy);
+        while remaining != ZERO {
+            let step = remaining.max(-ONE).min(ONE);
+            let y = self.y + step;
+
+            if step < ZERO {
+                if self.hits_solid(self.x, y, tile) {
+                    self.y = y.floor() + ONE;
+                    self.vy = ZERO;
+                    return false;
+                }
+            } else {
+                let old_bottom = self.y + self.h;
+                let bottom = y + self.h;
+
+                let floor = if self.hits_solid(self.x, y, tile) {
+                    Some(bottom.ceil() - ONE)
+                } else {
+                    self.slope_floor(self.x, bottom, tile)
+                        .or_else(|| self.one_way_floor(self.x, old_bottom, bottom, tile))
+                };
+
+                if let Some(floor) = floor {
+                    self.y = floor - self.h;
+                    self.vy = ZERO;
+                    self.on_ground = true;
+                    return true;
+                }
+            }
+
+            self.y = y;
+            remaining = remaining - step;
+        }
+        false
+    }
+
+    /// One frame: the gravity, then the horizontal and the vertical moves
+    pub fn step<F>(&mut self, tile: &mut F)
+        where F: FnMut(i32, i32) -> u8
+    {
+        let was_on_ground = self.on_ground;
+
+        self.vx = clamp_speed(self.vx);
+        self.vy = clamp_speed((clamp_speed(self.vy) + clamp_speed(self.gravity)).min(self.max_fall));
+
+        let vx = self.vx;
+        self.move_x(vx, tile);
+
+        self.on_ground = false;
+        let vy = self.vy;
+        let landed = self.move_y(vy, tile);
+
+        // Follow the floor going down instead of falling from each pixel
+        if was_on_ground && !landed && !self.on_ground && self.vy >= ZERO {
+            let (y, vy) = (self.y, self.vy);
+            if !self.move_y(STICK_DISTANCE, tile) {
+                self.y = y;
+                self.vy = vy;
+            }
+        }
+    }
+}
+
+pub struct Physics {
+    next_id: u32,
+    pub bodies: HashMap<u32, Body>,
+}
+
+impl Physics {
+    pub fn new() -> Physics {
+        Physics {
+            next_id: 1,
+            bodies: HashMap::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.bodies.clear();
+    }
+
+    pub fn add(&mut self, body: Body) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        self.bodies.insert(id, body);
+        id
+    }
+
+    pub fn remove(&mut self, id: u32) {
+        self.bodies.remove(&id);
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Body> {
+        self.bodies.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut Body> {
+        self.bodies.get_mut(&id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use unicorn::fixed::{Fixed, ZERO};
+
+    use super::{Body, FLAG_ONE_WAY, FLAG_SLOPE_UP, FLAG_SOLID, MAX_SPEED};
+
+    fn fx(value: i32) -> Fixed {
+        Fixed::from_int(value)
+    }
+
+    /// 'X' solid, '-' one-way, '/' slope, 8x8 pixels each
+    fn level(rows: &'static [&'static str]) -> Box<FnMut(i32, i32) -> u8> {
+        Box::new(move |cx, cy| {
+            if cx < 0 || cy < 0 || cy as usize >= rows.len() {
+                return 0;
+            }
+            match rows[cy as usize].as_bytes().get(cx as usize) {
+                Some(&b'X') => FLAG_SOLID,
+                Some(&b'-') => FLAG_ONE_WAY,
+                Some(&b'/') => FLAG_SLOPE_UP,
+                _ => 0,
+            }
+        })
+    }
+
+    #[test]
+    fn test_body_ground_and_walls() {
+        let mut tile = level(&["     ", "     ", "X    ", "XXXXX"]);
+
+        let mut body = Body::new(fx(10), fx(0), fx(6), fx(8));
+        for _ in 0..60 {
+            body.step(&mut tile);
+        }
+        assert!(body.on_ground);
+        assert_eq!(body.y, fx(16));
+
+        // The wall on the left
+        body.vx = fx(-3);
+        for _ in 0..10 {
+            body.step(&mut tile);
+        }
+        assert_eq!(body.x, fx(8));
+        assert!(body.on_ground);
+
+        // Jump in the ceiling
+        body.vy = fx(-3);
+        body.step(&mut tile);
+        assert!(!body.on_ground);
+    }
+
+    #[test]
+    fn test_body_fixed() {
+        let mut tile = level(&["     ", "     ", "     ", "XXXXX"]);
+
+        // The fractions of the gravity add up exactly
+        let mut body = Body::new(fx(0), fx(0), fx(4), fx(4));
+        body.vx = Fixed::from_f64(0.1);
+        for _ in 0..4 {
+            body.step(&mut tile);
+        }
+        assert_eq!(body.vy, fx(1));
+        assert_eq!(body.y, Fixed::from_f64(2.5));
+        assert_eq!(body.x, Fixed(4 * 6554));
+    }
+
+    #[test]
+    fn test_body_one_way() {
+        let mut tile = level(&["     ", "  -  ", "     ", "XXXXX"]);
+
+        // From above, it is a floor
+        let mut body = Body::new(fx(16), fx(0), fx(6), fx(6));
+        for _ in 0..30 {
+            body.step(&mut tile);
+        }
+        assert_eq!(body.y + body.h, fx(8));
+
+        // From below, the body goes through it
+        let mut body = Body::new(fx(16), fx(18), fx(6), fx(6));
+        body.gravity = ZERO;
+        body.vy = fx(-2);
+        for _ in 0..10 {
+            body.step(&mut tile);
+        }
+        assert!(body.y < fx(8));
+    }
+
+    #[test]
+    fn test_body_slope() {
+        let mut tile = level(&["     ", "     ", "  /X ", "XXXXX"]);
+
+        let mut body = Body::new(fx(4), fx(10), fx(4), fx(6));
+        for _ in 0..10 {
+            body.step(&mut tile);
+        }
+        assert_eq!(body.y + body.h, fx(24));
+
+        // Walk up the slope to the top of the solid tile
+        body.vx = fx(1);
+        for _ in 0..20 {
+            body.step(&mut tile);
+        }
+        assert!(body.on_ground);
+        assert_eq!(body.y + body.h, fx(16));
+    }
+
+    #[test]
+    fn test_body_values() {
+        let mut tile = level(&["     ", "     ", "     ", "XXXXX"]);
+
+        assert!(Body::from_f64(0.0, ::std::f64::NAN, 4.0, 4.0).is_none());
+        let mut body = Body::from_f64(0.0, 0.0, 4.0, 4.0).unwrap();
+
+        body.set(Some(::std::f64::INFINITY), None, Some(::std::f64::NAN), Some(1.0e9));
+        assert_eq!(body.x, fx(0));
+        assert_eq!(body.vx, ZERO);
+        assert_eq!(body.vy, MAX_SPEED);
+
+        // The fall stops on the floor instead of going through it
+        body.set_gravity(::std::f64::NEG_INFINITY, Some(1.0e9));
+        assert_eq!(body.max_fall, MAX_SPEED);
+        body.step(&mut tile);
+        assert!(body.on_ground);
+        assert_eq!(body.y + body.h, fx(24));
+
+        // Fast moves are cut to MAX_SPEED
+        body.vx = Fixed(::std::i32::MAX);
+        body.step(&mut tile);
+        assert_eq!(body.x, MAX_SPEED);
+    }
+}
@@ -0,0 +1,229 @@
+//! Live preview of the highlighted cartridge in the cart browser: its code
+//! runs on a screen of its own, muted, without inputs and sandboxed, at 15
+//! FPS, and the frames are drawn in a tile next to the list. Only the Lua
+//! cartridges are run (the Python ones would share the interpreter of the
+//! console), the label is shown for the others. The labels of the
+//! cartridges saved or exported without one are rendered the same way.
+
+use std::sync::{mpsc, Arc, Mutex};
+
+use config::Players;
+use gfx::{Screen, DEFAULT_MODE};
+use plugins::lua_plugin::plugin::LuaPlugin;
+use sound::sound::Sound;
+use cartridge::{Cartridge, CartridgeLabel, LABEL_SIZE};
+
+use unicorn::UnicornCartridge;
+use unicorn::info::Info;
+use unicorn::record::CONSOLE_FPS;
+use unicorn::noise::Noise;
+use unicorn::sandbox::{SAFE_MAX_INSTRUCTIONS, SAFE_MAX_MEMORY_KB};
+
+/// Frames of the browser for each frame of the cartridge
+const FRAME_SKIP: u32 = 4;
+/// Frames on the same cartridge before its preview starts, so scrolling the
+/// list does not load each cartridge
+const START_DELAY: u32 = 20;
+/// Frames run after `_init` before capturing the label of a cartridge
+pub const LABEL_FRAMES: u32 = 30;
+
+pub struct Preview {
+    /// Index of the previewed cartridge in the list
+    idx: Option<usize>,
+    frames: u32,
+    running: bool,
+    /// Colors of the label, 128x128
+    label: Vec<u8>,
+    plugin: LuaPlugin,
+    screen: Arc<Mutex<Screen>>,
+    info: Arc<Mutex<Info>>,
+    players: Arc<Mutex<Players>>,
+    noise: Arc<Mutex<Noise>>,
+    sound: Arc<Mutex<Sound>>,
+    /// The sounds of the cartridge are dropped
+    sound_queue: mpsc::Receiver<Vec<u8>>,
+}
+
+impl Preview {
+    pub fn new(width: usize, height: usize) -> Preview {
+        let (csend, sound_queue) = mpsc::channel();
+
+        Preview {
+            idx: None,
+            frames: 0,
+            running: false,
+            label: Vec::new(),
+            plugin: LuaPlugin::new(),
+            screen: Arc::new(Mutex::new(Screen::new(width, height))),
+            info: Arc::new(Mutex::new(Info::new())),
+            players: Arc::new(Mutex::new(Players::new())),
+            noise: Arc::new(Mutex::new(Noise::new())),
+            sound: Arc::new(Mutex::new(Sound::new(csend))),
+            sound_queue: sound_queue,
+        }
+    }
+
+    /// Preview the cartridge `idx` of the list, after `START_DELAY` frames
+    pub fn select(&mut self, idx: usize) {
+        if self.idx != Some(idx) {
+            self.stop();
+            self.idx = Some(idx);
+        }
+    }
+
+    /// Free the state of the cartridge
+    pub fn stop(&mut self) {
+        if self.running {
+            info!("[Unicorn][PREVIEW] Stop {:?}", self.idx);
+        }
+
+        self.idx = None;
+        self.frames = 0;
+        self.running = false;
+        self.label.clear();
+        self.plugin = LuaPlugin::new();
+    }
+
+    fn start(&mut self, cartridge: &mut UnicornCartridge) {
+        self.label = cartridge.cartridge.label.pixels.clone();
+
+        if cartridge.cartridge.code.get_name() != "lua" {
+            return;
+        }
+
+        info!("[Unicorn][PREVIEW] Start {:?}", cartridge.filename);
+        self.running = self.run(&mut cartridge.cartridge);
+    }
+
+    /// Load the code of a Lua cartridge and call its `_init`
+    fn run(&mut self, cartridge: &mut Cartridge) -> bool {
+        {
+            let mut screen = self.screen.lock().unwrap();
+            // The previous cartridge could have changed the resolution
+            screen.mode(DEFAULT_MODE.0, DEFAULT_MODE.1);
+            screen.init();
+            screen.cls(-1);
+            screen.set_sprites(cartridge.gfx.sprites.clone());
+            screen.set_sprites_flags(cartridge.gff.flags.clone());
+            screen.set_map(cartridge.map.map.clone());
+        }
+        *self.info.lock().unwrap() = Info::new();
+
+        self.plugin = LuaPlugin::new();
+        self.plugin
+            .load(self.players.clone(),
+                  self.info.clone(),
+                  self.screen.clone(),
+                  self.noise.clone(),
+                  self.sound.clone());
+        // Before the sandbox, which removes `package`
+        for &(ref value, ref code) in cartridge.libs.embedded.iter() {
+            self.plugin.preload(value, code);
+        }
+        self.plugin.sandbox(SAFE_MAX_INSTRUCTIONS, SAFE_MAX_MEMORY_KB);
+
+        if !self.plugin.load_code(cartridge.code.get_data()) {
+            return false;
+        }
+        self.plugin.init();
+        true
+    }
+
+    /// Update and draw a frame of the cartridge, false when it failed
+    fn step(&mut self, seconds: f64) -> bool {
+        {
+            let mut info = self.info.lock().unwrap();
+            info.update_game(seconds);
+            info.timers.update(seconds);
+        }
+
+        let running = self.plugin.update() && self.plugin.draw();
+        while self.sound_queue.try_recv().is_ok() {}
+        running
+    }
+
+    pub fn update(&mut self, cartridges: &mut Vec<UnicornCartridge>) {
+        let idx = match self.idx {
+            Some(idx) if idx < cartridges.len() => idx,
+            _ => return,
+        };
+
+        self.frames += 1;
+        if self.frames == START_DELAY {
+            self.start(&mut cartridges[idx]);
+        }
+
+        if !self.running || self.frames < START_DELAY || self.frames % FRAME_SKIP != 0 {
+            return;
+        }
+
+        self.running = self.step(FRAME_SKIP as f64 / CONSOLE_FPS as f64);
+    }
+
+    /// Tile in the top-right corner of `screen`, a quarter of its size (or a
+    /// half on the small screens)
+    pub fn draw(&mut self, screen: &mut Screen) {
+        if self.idx.is_none() || self.frames < START_DELAY {
+            return;
+        }
+
+        let divisor = if screen.width >= 256 { 4 } else { 2 };
+        let width = screen.width / divisor;
+        let height = screen.height / divisor;
+        let x0 = (screen.width - width - 2) as i32;
+        let y0 = 10;
+
+        let (width, height) = if self.running {
+            let preview = self.screen.lock().unwrap();
+            for y in 0..height {
+                for x in 0..width {
+                    let src = x * preview.width / width + (y * preview.height / height) * preview.width;
+                    let value = preview.display_color(preview.frame_buffer[src]);
+                    screen.putpixel_direct(x0 + x as i32, y0 + y as i32, value);
+                }
+            }
+            (width, height)
+        } else if self.label.len() == LABEL_SIZE * LABEL_SIZE {
+            // The label keeps its aspect ratio
+            let size = height.min(width);
+            for y in 0..size {
+                for x in 0..size {
+                    let src = x * LABEL_SIZE / size + (y * LABEL_SIZE / size) * LABEL_SIZE;
+                    screen.putpixel_direct(x0 + x as i32, y0 + y as i32, self.label[src] as u32);
+                }
+            }
+            (size, size)
+        } else {
+            return;
+        };
+
+        screen.rect(x0 - 1, y0 - 1, x0 + width as i32, y0 + height as i32, 7);
+    }
+}
+
+/// Label of a cartridge saved or exported without one: its screen after
+/// `_init` and `LABEL_FRAMES` frames, run on an instance of its own like the
+/// previews, so a running game keeps its state. None for the cartridges not
+/// in Lua, or failing before the end.
+pub fn render_label(cartridge: &mut Cartridge) -> Option<CartridgeLabel> {
+    if cartridge.code.get_name() != "lua" {
+        return None;
+    }
+
+    info!("[Unicorn][PREVIEW] Rendering the label after {} frames", LABEL_FRAMES);
+
+    let mut preview = Preview::new(DEFAULT_MODE.0, DEFAULT_MODE.1);
+    if !preview.run(cartridge) {
+        return None;
+    }
+    for _ in 0..LABEL_FRAMES {
+        if !preview.step(1.0 / CONSOLE_FPS as f64) {
+            return None;
+        }
+    }
+
+    let screen = preview.screen.lock().unwrap();
+    let colors: Vec<u32> = screen.frame_buffer.iter().map(|value| screen.display_color(*value)).collect();
+    let label = CartridgeLabel::from_frame_buffer(&colors, screen.width, screen.height);
+    if label.is_empty() { None } else { Some(label) }
+}
@@ -0,0 +1,108 @@
+use std::fs;
+use std::path::PathBuf;
+
+pub const DEFAULT_PROFILE: &'static str = "default";
+
+/// User of the console: the saves, scores and editor settings are stored
+/// in <root>/<name>, so several people could share the same machine
+#[derive(Clone, Debug)]
+pub struct Profile {
+    pub name: String,
+    pub root: PathBuf,
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Profile {
+            name: DEFAULT_PROFILE.to_string(),
+            root: PathBuf::from("profiles"),
+        }
+    }
+
+    /// Letters, digits, '-' and '_', the name is used as a directory
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty() && name.len() <= 32 &&
+        name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    }
+
+    pub fn set(&mut self, root: &str, name: &str) -> Result<(), String> {
+        if !Profile::is_valid_name(name) {
+            return Err(format!("Invalid profile name {:?}", name));
+        }
+
+        info!("[Unicorn][PROFILE] Profile {:?} in {:?}", name, root);
+
+        self.root = PathBuf::from(root);
+        self.name = name.to_string();
+        Ok(())
+    }
+
+    pub fn dir(&self) -> PathBuf {
+        self.root.join(&self.name)
+    }
+
+    /// Path of a file of the profile, the missing directories are created
+    pub fn path(&self, filename: &str) -> String {
+        let path = self.dir().join(filename);
+
+        if let Some(parent) = path.parent() {
+            if let Err(e) = fs::create_dir_all(parent) {
+                error!("[Unicorn][PROFILE] Failed to create {:?}: {}", parent, e);
+            }
+        }
+
+        path.to_string_lossy().into_owned()
+    }
+
+    /// Names of the existing profiles
+    pub fn list(root: &str) -> Vec<String> {
+        let mut names = Vec::new();
+
+        if let Ok(entries) = fs::read_dir(root) {
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                if entry.path().is_dir() {
+                    if let Some(name) = entry.file_name().to_str() {
+                        if Profile::is_valid_name(name) {
+                            names.push(name.to_string());
+                        }
+                    }
+                }
+            }
+        }
+
+        names.sort();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::path::Path;
+
+    use super::Profile;
+
+    #[test]
+    fn test_profile_name() {
+        assert!(Profile::is_valid_name("alice"));
+        assert!(Profile::is_valid_name("class-2_b"));
+        assert!(!Profile::is_valid_name(""));
+        assert!(!Profile::is_valid_name("../bob"));
+        assert!(!Profile::is_valid_name("a b"));
+    }
+
+    #[test]
+    fn test_profile_path() {
+        let root = env::temp_dir().join("unicorn-test-profiles");
+        let root = root.to_str().unwrap();
+
+        let mut profile = Profile::new();
+        assert!(profile.set(root, "../bob").is_err());
+        assert!(profile.set(root, "alice").is_ok());
+
+        let path = profile.path("saves/game.dat");
+        assert!(path.ends_with("game.dat"));
+        assert!(Path::new(&path).parent().unwrap().is_dir());
+        assert!(Profile::list(root).contains(&"alice".to_string()));
+    }
+}
@@ -0,0 +1,303 @@
+//! GIF recording (F4): the frames are kept in a ring buffer of the last
+//! `max_seconds`, captured at `fps` and written `scale` times bigger (0.5 for
+//! a smaller GIF). The settings are read in <profile>/record.json, or the
+//! global record.json, and changed by the cartridges with
+//! `extcmd("rec_len"|"rec_fps"|"rec_scale", value)`:
+//! ```text
+//! {"max_seconds": 10, "fps": 15, "scale": 1, "screenshot_scaled": false,
+//!  "captures_dir": "/home/me/captures"}
+//! ```
+//! The captures of the keys of the console (screenshots, GIFs, videos, frame
+//! dumps) are written in `captures_dir`, Pictures/PX8 in the home directory
+//! by default, and named after the cartridge and the date.
+
+use std::collections::VecDeque;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde_json;
+
+/// Frames per second of the console, the cartridges could ask for another
+/// rate in their metadata
+pub const CONSOLE_FPS: u32 = 60;
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct RecordConfig {
+    /// Seconds kept, the oldest frames are dropped
+    pub max_seconds: f64,
+    /// Frames captured per second, 60 at most
+    pub fps: u32,
+    /// Size of the GIF, relative to the screen
+    pub scale: f64,
+    /// Screenshots (F3) scaled like the window, inverted with Shift+F3
+    pub screenshot_scaled: bool,
+    /// Directory of the captures, empty for the default one
+    pub captures_dir: String,
+}
+
+impl Default for RecordConfig {
+    fn default() -> RecordConfig {
+        RecordConfig {
+            max_seconds: 10.0,
+            fps: 15,
+            scale: 1.0,
+            screenshot_scaled: false,
+            captures_dir: "".to_string(),
+        }
+    }
+}
+
+impl RecordConfig {
+    pub fn from_str(data: &str) -> Result<RecordConfig, String> {
+        match serde_json::from_str::<RecordConfig>(data) {
+            Ok(config) => Ok(config.clamp()),
+            Err(e) => Err(format!("Invalid record config: {}", e)),
+        }
+    }
+
+    pub fn from_file(filename: &str) -> Result<RecordConfig, String> {
+        let mut data = String::new();
+        match File::open(filename) {
+            Ok(mut f) => {
+                if let Err(e) = f.read_to_string(&mut data) {
+                    return Err(format!("Failed to read {:?}: {}", filename, e));
+                }
+            }
+            Err(e) => return Err(format!("Failed to open {:?}: {}", filename, e)),
+        }
+
+        RecordConfig::from_str(&data)
+    }
+
+    /// The values out of range are replaced by the nearest valid ones
+    pub fn clamp(mut self) -> RecordConfig {
+        if !(self.max_seconds > 0.0) {
+            self.max_seconds = RecordConfig::default().max_seconds;
+        }
+        self.max_seconds = self.max_seconds.min(600.0);
+        self.fps = self.fps.max(1).min(CONSOLE_FPS);
+        if !(self.scale > 0.0) {
+            self.scale = 1.0;
+        }
+        self.scale = self.scale.max(0.25).min(8.0);
+        self
+    }
+
+    /// `captures_dir`, or Pictures/PX8 in the home directory
+    pub fn captures_dir(&self) -> PathBuf {
+        if !self.captures_dir.is_empty() {
+            return PathBuf::from(&self.captures_dir);
+        }
+
+        match env::var_os("HOME").or(env::var_os("USERPROFILE")) {
+            Some(home) => PathBuf::from(home).join("Pictures").join("PX8"),
+            None => PathBuf::from("."),
+        }
+    }
+
+    pub fn max_frames(&self) -> usize {
+        ((self.max_seconds * self.fps as f64).ceil() as usize).max(1)
+    }
+
+    /// Delay of each frame of the GIF, in hundredths of second
+    pub fn delay(&self) -> u16 {
+        ((100.0 / self.fps as f64).round() as u16).max(1)
+    }
+}
+
+pub struct Record {
+    pub recording: bool,
+    pub filename: String,
+    pub width: usize,
+    pub height: usize,
+    /// RGB frames, row by row
+    frames: VecDeque<Vec<u8>>,
+    /// Frames of the console since the last capture, times the fps
+    ticks: u32,
+    /// Frames per second of the running cartridge
+    pub console_fps: u32,
+}
+
+impl Record {
+    pub fn new() -> Record {
+        Record {
+            recording: false,
+            filename: "".to_string(),
+            width: 0,
+            height: 0,
+            frames: VecDeque::new(),
+            ticks: 0,
+            console_fps: CONSOLE_FPS,
+        }
+    }
+
+    pub fn start(&mut self, filename: &str, width: usize, height: usize) {
+        self.recording = true;
+        self.filename = filename.to_string();
+        self.width = width;
+        self.height = height;
+        self.frames.clear();
+        // The first frame is captured
+        self.ticks = self.console_fps;
+    }
+
+    /// Called at each frame of the console, true when it must be captured
+    pub fn tick(&mut self, config: &RecordConfig) -> bool {
+        let capture = self.ticks >= self.console_fps;
+        if capture {
+            self.ticks -= self.console_fps;
+        }
+        self.ticks += config.fps.min(self.console_fps);
+        capture
+    }
+
+    pub fn push(&mut self, frame: Vec<u8>, config: &RecordConfig) {
+        self.frames.push_back(frame);
+        while self.frames.len() > config.max_frames() {
+            self.frames.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn stop(&mut self) -> Vec<Vec<u8>> {
+        self.recording = false;
+        self.frames.drain(..).collect()
+    }
+
+    /// Size of the frames of the GIF
+    pub fn scaled_size(&self, scale: f64) -> (usize, usize) {
+        (((self.width as f64 * scale).round() as usize).max(1),
+         ((self.height as f64 * scale).round() as usize).max(1))
+    }
+
+    /// Frame resized to `scaled_size`, with the nearest pixels
+    pub fn scale_frame(&self, frame: &[u8], scale: f64) -> Vec<u8> {
+        let (width, height) = self.scaled_size(scale);
+        if width == self.width && height == self.height {
+            return frame.to_vec();
+        }
+
+        let mut pixels = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            let src_y = (y * self.height / height).min(self.height - 1);
+            for x in 0..width {
+                let src_x = (x * self.width / width).min(self.width - 1);
+                let idx = (src_x + src_y * self.width) * 3;
+                pixels.extend_from_slice(&frame[idx..idx + 3]);
+            }
+        }
+        pixels
+    }
+}
+
+/// "<kind>-<cartridge>-<stamp>.<extension>", the name of the cartridge is
+/// its file name without the extension, and the characters that do not fit
+/// in a file name are replaced by '_'
+pub fn capture_filename(kind: &str, cartridge: &str, stamp: &str, extension: &str) -> String {
+    let name = match Path::new(cartridge).file_stem() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => "".to_string(),
+    };
+    let name: String = name.chars()
+        .map(|c| if (c.is_alphanumeric() && c.is_ascii()) || c == '-' || c == '_' {
+                 c
+             } else {
+                 '_'
+             })
+        .collect();
+
+    if name.is_empty() {
+        format!("{}-{}.{}", kind, stamp, extension)
+    } else {
+        format!("{}-{}-{}.{}", kind, name, stamp, extension)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{capture_filename, Record, RecordConfig};
+
+    #[test]
+    fn test_record_config() {
+        let config = RecordConfig::from_str(r#"{"fps": 120, "scale": 0.5}"#).unwrap();
+        assert_eq!(config.fps, 60);
+        assert_eq!(config.scale, 0.5);
+        assert_eq!(config.max_seconds, 10.0);
+        assert_eq!(config.max_frames(), 600);
+        assert_eq!(config.delay(), 2);
+
+        assert!(RecordConfig::from_str("{").is_err());
+        assert_eq!(RecordConfig::from_str(r#"{"max_seconds": -1}"#).unwrap(),
+                   RecordConfig::default());
+    }
+
+    #[test]
+    fn test_captures() {
+        assert_eq!(capture_filename("screenshot", "games/my game.uc", "2018-01-02", "png"),
+                   "screenshot-my_game-2018-01-02.png");
+        assert_eq!(capture_filename("record", "", "2018-01-02", "gif"),
+                   "record-2018-01-02.gif");
+
+        let config = RecordConfig::from_str(r#"{"captures_dir": "/tmp/captures"}"#).unwrap();
+        assert_eq!(config.captures_dir(), PathBuf::from("/tmp/captures"));
+    }
+
+    #[test]
+    fn test_record_ring_buffer() {
+        let config = RecordConfig {
+            max_seconds: 1.0,
+            fps: 15,
+            scale: 1.0,
+            screenshot_scaled: false,
+            captures_dir: "".to_string(),
+        };
+
+        let mut record = Record::new();
+        record.start("record.gif", 2, 1);
+
+        let mut captured = 0;
+        for frame in 0..120 {
+            if record.tick(&config) {
+                record.push(vec![frame as u8; 6], &config);
+                captured += 1;
+            }
+        }
+
+        // 15 frames by second, only the last second is kept
+        assert_eq!(captured, 30);
+        assert_eq!(record.len(), 15);
+
+        let frames = record.stop();
+        assert!(!record.recording);
+        assert_eq!(frames[0][0], 60);
+        assert_eq!(record.len(), 0);
+
+        // A cartridge at 30 FPS, each frame is captured
+        record.console_fps = 30;
+        record.start("record.gif", 2, 1);
+        let config = RecordConfig { fps: 60, ..config };
+        assert!(record.tick(&config));
+        assert!(record.tick(&config));
+    }
+
+    #[test]
+    fn test_record_scale() {
+        let mut record = Record::new();
+        record.start("record.gif", 2, 2);
+
+        let frame = vec![1, 1, 1, 2, 2, 2, 3, 3, 3, 4, 4, 4];
+        assert_eq!(record.scaled_size(0.5), (1, 1));
+        assert_eq!(record.scale_frame(&frame, 0.5), vec![1, 1, 1]);
+        assert_eq!(record.scaled_size(2.0), (4, 4));
+        assert_eq!(&record.scale_frame(&frame, 2.0)[..12], &[1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2]);
+        assert_eq!(record.scale_frame(&frame, 1.0), frame);
+    }
+}
@@ -0,0 +1,461 @@
+//! Remote control of the console by the local tools (editor plugins, "run"
+//! buttons, exhibitions): `--remote-port <port>` serves a small HTTP API on
+//! 127.0.0.1 only. The requests are read by a thread per connection and run
+//! by the console between two frames:
+//! ```text
+//! POST /status                                 state, cartridge and FPS
+//! POST /load?file=games/jump.uni               load and run a cartridge
+//! POST /screenshot?file=shot.png               screenshot in the captures
+//! POST /input?player=0&button=a&frames=10      hold a button for 10 frames
+//! POST /stat?name=real_time                    like stat(name) in the carts
+//! ```
+//! Each request gives the token of the session, printed at startup, in the
+//! `X-Unicorn-Token` header. The pages of a browser can't send it without
+//! asking first, and the requests with an `Origin` (the ones of the pages)
+//! are refused.
+//!
+//! The answers are JSON: `{"ok": true, ...}`, or `{"ok": false, "error": ...}`
+//! with the status 400, 403 or 404.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::str;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use rand;
+use serde_json::{self, Map, Value};
+
+use config::keys::PX8Key;
+
+/// Longest wait of a request for the console, it could be loading a cartridge
+const REPLY_TIMEOUT: u64 = 10;
+/// Longest hold of a button by `/input`, 10 seconds at 60 FPS
+pub const MAX_INPUT_FRAMES: u32 = 600;
+/// Longest wait of the request of a client, the other ones are served
+/// meanwhile
+const READ_TIMEOUT: u64 = 2;
+/// Longest line of a request, the request line or a header
+const MAX_LINE_SIZE: u64 = 8 * 1024;
+const MAX_HEADERS: usize = 64;
+pub const TOKEN_HEADER: &'static str = "x-unicorn-token";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RemoteCommand {
+    Status,
+    Load(String),
+    Screenshot(String),
+    Input {
+        player: u8,
+        key: PX8Key,
+        frames: u32,
+    },
+    Stat(String),
+}
+
+/// Fields of the answer, or the error
+pub type RemoteReply = Result<Map<String, Value>, String>;
+
+pub struct RemoteRequest {
+    pub command: RemoteCommand,
+    reply: mpsc::Sender<RemoteReply>,
+}
+
+impl RemoteRequest {
+    pub fn answer(&self, reply: RemoteReply) {
+        // The client could be gone after the timeout
+        let _ = self.reply.send(reply);
+    }
+}
+
+pub struct Remote {
+    pub port: u16,
+    /// Secret of the session, given by the clients
+    pub token: String,
+    requests: mpsc::Receiver<RemoteRequest>,
+    /// Buttons held by `/input`, with their frames left
+    held: Vec<(u8, PX8Key, u32)>,
+}
+
+impl Remote {
+    /// Listen on 127.0.0.1:`port`, the connections are served by a thread
+    pub fn start(port: u16) -> Result<Remote, String> {
+        let listener = match TcpListener::bind(("127.0.0.1", port)) {
+            Ok(listener) => listener,
+            Err(e) => return Err(format!("Failed to listen on the port {:?}: {}", port, e)),
+        };
+
+        // The port given could be 0, for any free one
+        let port = listener.local_addr().map(|addr| addr.port()).unwrap_or(port);
+        info!("[Unicorn][REMOTE] Listening on 127.0.0.1:{:?}", port);
+
+        let token = new_token();
+
+        let (sender, requests) = mpsc::channel();
+        let server_token = token.clone();
+        thread::spawn(move || for stream in listener.incoming() {
+                          match stream {
+                              Ok(stream) => {
+                                  let sender = sender.clone();
+                                  let token = server_token.clone();
+                                  thread::spawn(move || serve(stream, port, &token, &sender));
+                              }
+                              Err(e) => error!("[Unicorn][REMOTE] Failed connection: {}", e),
+                          }
+                      });
+
+        Ok(Remote {
+               port: port,
+               token: token,
+               requests: requests,
+               held: Vec::new(),
+           })
+    }
+
+    /// Requests received since the last frame
+    pub fn poll(&mut self) -> Vec<RemoteRequest> {
+        let mut requests = Vec::new();
+        while let Ok(request) = self.requests.try_recv() {
+            requests.push(request);
+        }
+        requests
+    }
+
+    /// Hold the button for `frames` frames, again from now if it is held
+    pub fn hold(&mut self, player: u8, key: PX8Key, frames: u32) {
+        self.held.retain(|&(p, k, _)| p != player || k != key);
+        self.held.push((player, key, frames));
+    }
+
+    /// Next frame, the buttons to release
+    pub fn update(&mut self) -> Vec<(u8, PX8Key)> {
+        for held in self.held.iter_mut() {
+            held.2 = held.2.saturating_sub(1);
+        }
+
+        let released = self.held
+            .iter()
+            .filter(|&&(_, _, frames)| frames == 0)
+            .map(|&(player, key, _)| (player, key))
+            .collect();
+        self.held.retain(|&(_, _, frames)| frames > 0);
+        released
+    }
+}
+
+/// Secret of a session, printed at startup for the clients
+pub fn new_token() -> String {
+    format!("{:016x}{:016x}", rand::random::<u64>(), rand::random::<u64>())
+}
+
+/// `%20` and `+` of the values of a query
+fn url_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'+' => decoded.push(b' '),
+            b'%' if idx + 2 < bytes.len() => {
+                let hex = str::from_utf8(&bytes[idx + 1..idx + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        idx += 2;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        idx += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Command of the request line, "POST /input?player=0&button=a HTTP/1.1",
+/// or the status and the error
+pub fn parse_request(line: &str) -> Result<RemoteCommand, (u16, String)> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    if parts.len() < 2 || parts[0] != "POST" {
+        return Err((400, format!("Invalid request {:?}, only POST is accepted", line)));
+    }
+
+    let (path, query) = match parts[1].find('?') {
+        Some(idx) => (&parts[1][..idx], &parts[1][idx + 1..]),
+        None => (parts[1], ""),
+    };
+
+    let mut params = HashMap::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = match pair.find('=') {
+            Some(idx) => (&pair[..idx], &pair[idx + 1..]),
+            None => (pair, ""),
+        };
+        params.insert(url_decode(name), url_decode(value));
+    }
+
+    let param = |name: &str| -> Result<String, (u16, String)> {
+        match params.get(name) {
+            Some(value) if !value.is_empty() => Ok(value.clone()),
+            _ => Err((400, format!("Missing parameter {:?}", name))),
+        }
+    };
+
+    match path {
+        "/status" => Ok(RemoteCommand::Status),
+        "/load" => Ok(RemoteCommand::Load(try!(param("file")))),
+        "/screenshot" => {
+            Ok(RemoteCommand::Screenshot(param("file").unwrap_or("remote.png".to_string())))
+        }
+        "/stat" => Ok(RemoteCommand::Stat(try!(param("name")))),
+        "/input" => {
+            let button = try!(param("button"));
+            let key = match PX8Key::from_name(&button) {
+                Some(key) => key,
+                None => return Err((400, format!("Unknown button {:?}", button))),
+            };
+            let player = match param("player").unwrap_or("0".to_string()).parse::<u8>() {
+                Ok(player) => player,
+                Err(_) => return Err((400, "Invalid player".to_string())),
+            };
+            let frames = match param("frames").unwrap_or("1".to_string()).parse::<u32>() {
+                Ok(frames) if frames > 0 && frames <= MAX_INPUT_FRAMES => frames,
+                _ => return Err((400, format!("The frames are 1 to {:?}", MAX_INPUT_FRAMES))),
+            };
+
+            Ok(RemoteCommand::Input {
+                   player: player,
+                   key: key,
+                   frames: frames,
+               })
+        }
+        _ => Err((404, format!("Unknown command {:?}", path))),
+    }
+}
+
+/// Only the pages of this machine, against the other sites of the browser
+/// reaching the port with another name (DNS rebinding)
+fn is_local_host(host: &str, port: u16) -> bool {
+    host == format!("127.0.0.1:{}", port) || host == format!("localhost:{}", port)
+}
+
+/// Comparison in the same time for all the tokens of the same length, the
+/// time of the answers doesn't give the first bytes matching
+pub fn same_token(value: &str, token: &str) -> bool {
+    if value.len() != token.len() {
+        return false;
+    }
+    value.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Headers of a request, by their lowercase name
+pub fn check_headers(headers: &HashMap<String, String>, port: u16, token: &str) -> Result<(), (u16, String)> {
+    let host = headers.get("host").map(|host| host.as_str()).unwrap_or("");
+    if !is_local_host(host, port) {
+        return Err((400, format!("Invalid host {:?}", host)));
+    }
+    if headers.contains_key("origin") {
+        return Err((403, "The requests of the web pages are refused".to_string()));
+    }
+    match headers.get(TOKEN_HEADER) {
+        Some(value) if same_token(value, token) => Ok(()),
+        _ => Err((403, "Missing or invalid token".to_string())),
+    }
+}
+
+/// Next line of the request, or None at the end, on an error or for a line
+/// too long
+fn read_line<R: BufRead>(reader: &mut R) -> Option<String> {
+    let mut line = String::new();
+    match reader.take(MAX_LINE_SIZE).read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) if !line.ends_with('\n') => {
+            error!("[Unicorn][REMOTE] Line of more than {:?} bytes", MAX_LINE_SIZE);
+            None
+        }
+        Ok(_) => Some(line),
+    }
+}
+
+fn serve(stream: TcpStream, port: u16, token: &str, sender: &mpsc::Sender<RemoteRequest>) {
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(READ_TIMEOUT)));
+
+    let mut reader = BufReader::new(&stream);
+    let request_line = match read_line(&mut reader) {
+        Some(line) => line,
+        None => return,
+    };
+
+    let mut headers = HashMap::new();
+    loop {
+        let header = match read_line(&mut reader) {
+            Some(header) => header,
+            None => return,
+        };
+
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if headers.len() >= MAX_HEADERS {
+            error!("[Unicorn][REMOTE] More than {:?} headers", MAX_HEADERS);
+            return;
+        }
+        if let Some(idx) = header.find(':') {
+            headers.insert(header[..idx].trim().to_lowercase(), header[idx + 1..].trim().to_string());
+        }
+    }
+
+    debug!("[Unicorn][REMOTE] {:?}", request_line.trim());
+
+    let answer = match check_headers(&headers, port, token) {
+        Err(e) => Err(e),
+        Ok(()) => match parse_request(&request_line) {
+            Ok(command) => {
+                let (reply, replies) = mpsc::channel();
+                let request = RemoteRequest {
+                    command: command,
+                    reply: reply,
+                };
+
+                if sender.send(request).is_err() {
+                    return;
+                }
+                match replies.recv_timeout(Duration::from_secs(REPLY_TIMEOUT)) {
+                    Ok(Ok(fields)) => Ok(fields),
+                    Ok(Err(e)) => Err((400, e)),
+                    Err(_) => Err((400, "No answer of the console".to_string())),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    };
+
+    let (status, mut fields) = match answer {
+        Ok(fields) => (200, fields),
+        Err((status, e)) => {
+            let mut fields = Map::new();
+            fields.insert("error".to_string(), Value::String(e));
+            (status, fields)
+        }
+    };
+    fields.insert("ok".to_string(), Value::Bool(status == 200));
+
+    let body = serde_json::to_string(&Value::Object(fields)).unwrap_or("{}".to_string());
+    let reason = match status {
+        200 => "OK",
+        403 => "Forbidden",
+        404 => "Not Found",
+        _ => "Bad Request",
+    };
+
+    let mut stream = &stream;
+    let response = format!("HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: \
+                            {}\r\nConnection: close\r\n\r\n{}",
+                           status,
+                           reason,
+                           body.len(),
+                           body);
+    if let Err(e) = stream.write_all(response.as_bytes()) {
+        error!("[Unicorn][REMOTE] Failed to answer: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    use config::keys::PX8Key;
+
+    use super::{check_headers, is_local_host, parse_request, read_line, same_token, url_decode,
+                Remote, RemoteCommand, MAX_LINE_SIZE, TOKEN_HEADER};
+
+    #[test]
+    fn test_parse_request() {
+        assert_eq!(parse_request("POST /status HTTP/1.1"), Ok(RemoteCommand::Status));
+        assert_eq!(parse_request("POST /load?file=my%20games/jump.uni HTTP/1.1"),
+                   Ok(RemoteCommand::Load("my games/jump.uni".to_string())));
+        assert_eq!(parse_request("POST /screenshot HTTP/1.1"),
+                   Ok(RemoteCommand::Screenshot("remote.png".to_string())));
+        assert_eq!(parse_request("POST /input?button=left&player=1&frames=5 HTTP/1.1"),
+                   Ok(RemoteCommand::Input {
+                          player: 1,
+                          key: PX8Key::Left,
+                          frames: 5,
+                      }));
+        assert_eq!(parse_request("POST /input?button=a HTTP/1.1"),
+                   Ok(RemoteCommand::Input {
+                          player: 0,
+                          key: PX8Key::A,
+                          frames: 1,
+                      }));
+
+        assert_eq!(parse_request("POST /load HTTP/1.1").unwrap_err().0, 400);
+        assert_eq!(parse_request("POST /input?button=jump HTTP/1.1").unwrap_err().0, 400);
+        assert_eq!(parse_request("POST /input?button=a&frames=0 HTTP/1.1").unwrap_err().0, 400);
+        assert_eq!(parse_request("POST /reboot HTTP/1.1").unwrap_err().0, 404);
+        // The state changes over GET could be sent by any page
+        assert_eq!(parse_request("GET /load?file=cart.uni HTTP/1.1").unwrap_err().0, 400);
+        assert_eq!(parse_request("DELETE /status HTTP/1.1").unwrap_err().0, 400);
+
+        assert_eq!(url_decode("a+b%2Fc%"), "a b/c%");
+        assert!(is_local_host("localhost:8080", 8080));
+        assert!(!is_local_host("evil.example:8080", 8080));
+    }
+
+    #[test]
+    fn test_check_headers() {
+        let mut headers = HashMap::new();
+        headers.insert("host".to_string(), "127.0.0.1:8080".to_string());
+        assert_eq!(check_headers(&headers, 8080, "secret").unwrap_err().0, 403);
+
+        headers.insert(TOKEN_HEADER.to_string(), "guess".to_string());
+        assert_eq!(check_headers(&headers, 8080, "secret").unwrap_err().0, 403);
+        headers.insert(TOKEN_HEADER.to_string(), "secret".to_string());
+        assert_eq!(check_headers(&headers, 8080, "secret"), Ok(()));
+        assert_eq!(check_headers(&headers, 8081, "secret").unwrap_err().0, 400);
+
+        headers.insert("origin".to_string(), "http://127.0.0.1:8080".to_string());
+        assert_eq!(check_headers(&headers, 8080, "secret").unwrap_err().0, 403);
+
+        assert!(same_token("secret", "secret"));
+        assert!(!same_token("secreT", "secret"));
+        assert!(!same_token("secret2", "secret"));
+        assert!(!same_token("", "secret"));
+    }
+
+    #[test]
+    fn test_read_line() {
+        let mut reader = Cursor::new("POST /status HTTP/1.1\r\nHost: 127.0.0.1\r\n".as_bytes());
+        assert_eq!(read_line(&mut reader), Some("POST /status HTTP/1.1\r\n".to_string()));
+        assert_eq!(read_line(&mut reader), Some("Host: 127.0.0.1\r\n".to_string()));
+        assert_eq!(read_line(&mut reader), None);
+
+        // Not buffered past the limit
+        let long = vec![b'a'; MAX_LINE_SIZE as usize * 2];
+        assert_eq!(read_line(&mut Cursor::new(&long[..])), None);
+    }
+
+    #[test]
+    fn test_remote_input() {
+        let mut remote = Remote::start(0).unwrap();
+        assert!(remote.poll().is_empty());
+        assert_eq!(remote.token.len(), 32);
+        assert!(remote.port != 0);
+
+        remote.hold(0, PX8Key::A, 2);
+        remote.hold(1, PX8Key::B, 1);
+        assert_eq!(remote.update(), vec![(1, PX8Key::B)]);
+        // Held again, from now
+        remote.hold(0, PX8Key::A, 2);
+        assert!(remote.update().is_empty());
+        assert_eq!(remote.update(), vec![(0, PX8Key::A)]);
+        assert!(remote.update().is_empty());
+    }
+}
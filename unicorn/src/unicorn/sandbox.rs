@@ -0,0 +1,154 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Number of Lua instructions allowed for each call of _init/_update/_draw
+pub const SAFE_MAX_INSTRUCTIONS: i32 = 50_000_000;
+/// Memory allowed for the Lua state, in KB
+pub const SAFE_MAX_MEMORY_KB: i32 = 64 * 1024;
+
+/// Restrictions applied when running an untrusted cartridge (--safe)
+#[derive(Clone, Debug)]
+pub struct Sandbox {
+    pub enabled: bool,
+    pub save_dir: PathBuf,
+    pub max_instructions: i32,
+    pub max_memory_kb: i32,
+}
+
+impl Sandbox {
+    pub fn new() -> Sandbox {
+        Sandbox {
+            enabled: false,
+            save_dir: env::temp_dir().join("unicorn"),
+            max_instructions: SAFE_MAX_INSTRUCTIONS,
+            max_memory_kb: SAFE_MAX_MEMORY_KB,
+        }
+    }
+
+    pub fn set_enabled(&mut self, value: bool) {
+        info!("[Unicorn][SANDBOX] Safe mode = {:?}, save area {:?}",
+              value,
+              self.save_dir);
+
+        self.enabled = value;
+    }
+
+    /// Destination of a file written by the console (screenshot, gif, ...):
+    /// untouched in normal mode, moved into the temporary save area otherwise
+    pub fn output_path(&self, filename: &str) -> String {
+        if !self.enabled {
+            return filename.to_string();
+        }
+
+        if let Err(e) = fs::create_dir_all(&self.save_dir) {
+            error!("[Unicorn][SANDBOX] Failed to create {:?}: {}",
+                   self.save_dir,
+                   e);
+        }
+
+        let name = match Path::new(filename).file_name() {
+            Some(name) => name.to_os_string(),
+            None => "output".into(),
+        };
+
+        let path = self.save_dir.join(name);
+        let filename = path.to_string_lossy().into_owned();
+
+        // A link left in the save area is replaced, not followed
+        if !self.is_write_allowed(&filename) {
+            error!("[Unicorn][SANDBOX] {:?} is outside of the save area, removed",
+                   filename);
+            if let Err(e) = fs::remove_file(&path) {
+                error!("[Unicorn][SANDBOX] Failed to remove {:?}: {}", filename, e);
+            }
+        }
+
+        filename
+    }
+
+    /// True when the path, once resolved (`..`, links), is in the save
+    /// area, or in normal mode
+    pub fn is_write_allowed(&self, filename: &str) -> bool {
+        if !self.enabled {
+            return true;
+        }
+
+        let save_dir = match fs::canonicalize(&self.save_dir) {
+            Ok(save_dir) => save_dir,
+            Err(_) => return false,
+        };
+
+        let path = Path::new(filename);
+        let resolved = if fs::symlink_metadata(path).is_ok() {
+            fs::canonicalize(path)
+        } else {
+            // A new file, in an existing directory
+            let parent = match path.parent() {
+                Some(parent) if parent != Path::new("") => parent,
+                _ => Path::new("."),
+            };
+            match path.file_name() {
+                Some(name) => fs::canonicalize(parent).map(|parent| parent.join(name)),
+                None => return false,
+            }
+        };
+
+        match resolved {
+            Ok(resolved) => resolved.starts_with(&save_dir),
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use super::Sandbox;
+
+    #[test]
+    fn test_is_write_allowed() {
+        let mut sandbox = Sandbox::new();
+        sandbox.save_dir = env::temp_dir().join("unicorn-test-sandbox");
+        assert!(sandbox.is_write_allowed("/etc/passwd"));
+
+        sandbox.enabled = true;
+        let output = sandbox.output_path("../../screenshot.png");
+        assert_eq!(output,
+                   sandbox.save_dir.join("screenshot.png").to_string_lossy().into_owned());
+        assert!(sandbox.is_write_allowed(&output));
+
+        let save_dir = sandbox.save_dir.to_string_lossy().into_owned();
+        assert!(!sandbox.is_write_allowed(&format!("{}/../screenshot.png", save_dir)));
+        assert!(!sandbox.is_write_allowed(&format!("{}/missing/screenshot.png", save_dir)));
+        assert!(!sandbox.is_write_allowed("/etc/passwd"));
+        assert!(!sandbox.is_write_allowed(&save_dir[..save_dir.len() - 1]));
+
+        fs::remove_dir_all(&sandbox.save_dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_output_path_link() {
+        use std::os::unix::fs::symlink;
+
+        let mut sandbox = Sandbox::new();
+        sandbox.save_dir = env::temp_dir().join("unicorn-test-sandbox-link");
+        sandbox.enabled = true;
+        let _ = fs::remove_dir_all(&sandbox.save_dir);
+        fs::create_dir_all(&sandbox.save_dir).unwrap();
+
+        let link = sandbox.save_dir.join("record.gif");
+        symlink(env::temp_dir(), &link).unwrap();
+        assert!(!sandbox.is_write_allowed(&link.to_string_lossy()));
+
+        // The link is removed, the file is written in the save area
+        let output = sandbox.output_path("record.gif");
+        assert!(fs::symlink_metadata(&link).is_err());
+        assert!(sandbox.is_write_allowed(&output));
+
+        fs::remove_dir_all(&sandbox.save_dir).unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+//! Scores signed with a key of the cartridge (`score_sign`/`score_verify`),
+//! so the submissions to an online leaderboard are a bit harder to forge.
+//! The blob is "<value>:<HMAC-SHA256 of the value in hexa>".
+
+const K: [u32; 64] = [0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1,
+                      0x923f82a4, 0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3,
+                      0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786,
+                      0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+                      0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147,
+                      0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13,
+                      0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+                      0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+                      0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a,
+                      0x5b9cca4f, 0x682e6ff3, 0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208,
+                      0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2];
+
+const BLOCK_SIZE: usize = 64;
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut h: [u32; 8] = [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c,
+                           0x1f83d9ab, 0x5be0cd19];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % BLOCK_SIZE != 56 {
+        message.push(0);
+    }
+    let bits = (data.len() as u64).wrapping_mul(8);
+    for i in 0..8 {
+        message.push((bits >> (56 - i * 8)) as u8);
+    }
+
+    for block in message.chunks(BLOCK_SIZE) {
+        let mut w = [0u32; 64];
+        for i in 0..16 {
+            w[i] = ((block[i * 4] as u32) << 24) | ((block[i * 4 + 1] as u32) << 16) |
+                   ((block[i * 4 + 2] as u32) << 8) | (block[i * 4 + 3] as u32);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+
+        let mut v = h;
+        for i in 0..64 {
+            let s1 = v[4].rotate_right(6) ^ v[4].rotate_right(11) ^ v[4].rotate_right(25);
+            let ch = (v[4] & v[5]) ^ (!v[4] & v[6]);
+            let t1 = v[7].wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i]);
+            let s0 = v[0].rotate_right(2) ^ v[0].rotate_right(13) ^ v[0].rotate_right(22);
+            let maj = (v[0] & v[1]) ^ (v[0] & v[2]) ^ (v[1] & v[2]);
+            let t2 = s0.wrapping_add(maj);
+
+            v[7] = v[6];
+            v[6] = v[5];
+            v[5] = v[4];
+            v[4] = v[3].wrapping_add(t1);
+            v[3] = v[2];
+            v[2] = v[1];
+            v[1] = v[0];
+            v[0] = t1.wrapping_add(t2);
+        }
+
+        for i in 0..8 {
+            h[i] = h[i].wrapping_add(v[i]);
+        }
+    }
+
+    let mut digest = [0u8; 32];
+    for i in 0..8 {
+        for j in 0..4 {
+            digest[i * 4 + j] = (h[i] >> (24 - j * 8)) as u8;
+        }
+    }
+    digest
+}
+
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut block_key = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block_key[..32].copy_from_slice(&sha256(key));
+    } else {
+        block_key[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner: Vec<u8> = block_key.iter().map(|b| b ^ 0x36).collect();
+    inner.extend_from_slice(data);
+
+    let mut outer: Vec<u8> = block_key.iter().map(|b| b ^ 0x5c).collect();
+    outer.extend_from_slice(&sha256(&inner));
+
+    sha256(&outer)
+}
+
+pub fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Key of a cartridge, from its UUID
+pub fn cartridge_key(uuid: &str) -> Vec<u8> {
+    format!("unicorn-score:{}", uuid).into_bytes()
+}
+
+pub fn score_sign(key: &[u8], value: i64) -> String {
+    let value = value.to_string();
+    format!("{}:{}", value, to_hex(&hmac_sha256(key, value.as_bytes())))
+}
+
+/// The value of the blob when its signature is valid
+pub fn score_verify(key: &[u8], blob: &str) -> Option<i64> {
+    let mut parts = blob.splitn(2, ':');
+    let value = parts.next().unwrap_or("");
+    let signature = parts.next().unwrap_or("");
+
+    let expected = to_hex(&hmac_sha256(key, value.as_bytes()));
+
+    // Compare all the characters, whatever the first difference
+    let same = expected.len() == signature.len() &&
+               expected.bytes().zip(signature.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0;
+    if !same {
+        return None;
+    }
+
+    value.parse::<i64>().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cartridge_key, hmac_sha256, score_sign, score_verify, sha256, to_hex};
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(to_hex(&sha256(b"abc")),
+                   "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(to_hex(&sha256(b"")),
+                   "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(to_hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+                   "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+
+    #[test]
+    fn test_score() {
+        let key = cartridge_key("1234");
+        let blob = score_sign(&key, 4200);
+        assert!(blob.starts_with("4200:"));
+        assert_eq!(score_verify(&key, &blob), Some(4200));
+
+        assert_eq!(score_verify(&key, &blob.replacen("4200", "9999", 1)), None);
+        assert_eq!(score_verify(&cartridge_key("5678"), &blob), None);
+        assert_eq!(score_verify(&key, "4200"), None);
+    }
+}
@@ -0,0 +1,171 @@
+//! Compiled code of the cartridges (Lua bytecode, Python code objects),
+//! stored in <profile>/cache so the next runs of a big cartridge (previews of
+//! the browser, restarts of the watch mode) skip the compilation. The files
+//! are named from the SHA-256 of the code and of the version of the console:
+//! an edited cartridge gets a new entry instead of an outdated one. The cache is
+//! not used in safe mode, where a modified bytecode could escape the sandbox.
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use unicorn::score::{sha256, to_hex};
+
+/// Entries kept in the directory, the oldest ones are removed
+const MAX_ENTRIES: usize = 64;
+
+#[derive(Clone, Debug)]
+pub struct ScriptCache {
+    pub dir: Option<PathBuf>,
+}
+
+impl ScriptCache {
+    /// Nothing is read or written
+    pub fn disabled() -> ScriptCache {
+        ScriptCache { dir: None }
+    }
+
+    pub fn new(dir: PathBuf) -> ScriptCache {
+        ScriptCache { dir: Some(dir) }
+    }
+
+    /// Same on all the builds of a version, unlike the `Hash` of the std
+    pub fn key(code: &str) -> String {
+        let mut data = env!("CARGO_PKG_VERSION").as_bytes().to_vec();
+        data.push(b'\n');
+        data.extend_from_slice(code.as_bytes());
+        to_hex(&sha256(&data))
+    }
+
+    /// File of the compiled `code`, `extension` is the language
+    pub fn path(&self, code: &str, extension: &str) -> Option<PathBuf> {
+        match self.dir {
+            Some(ref dir) => Some(dir.join(format!("{}.{}", ScriptCache::key(code), extension))),
+            None => None,
+        }
+    }
+
+    pub fn load(&self, code: &str, extension: &str) -> Option<Vec<u8>> {
+        let path = match self.path(code, extension) {
+            Some(path) => path,
+            None => return None,
+        };
+
+        let mut data = Vec::new();
+        match File::open(&path) {
+            Ok(mut f) => {
+                if let Err(e) = f.read_to_end(&mut data) {
+                    warn!("[Unicorn][CACHE] Failed to read {:?}: {}", path, e);
+                    return None;
+                }
+            }
+            Err(_) => return None,
+        }
+
+        debug!("[Unicorn][CACHE] Hit {:?}", path);
+        Some(data)
+    }
+
+    pub fn store(&self, code: &str, extension: &str, data: &[u8]) {
+        let path = match self.path(code, extension) {
+            Some(path) => path,
+            None => return,
+        };
+
+        if let Some(parent) = path.parent() {
+            if let Err(e) = fs::create_dir_all(parent) {
+                error!("[Unicorn][CACHE] Failed to create {:?}: {}", parent, e);
+                return;
+            }
+        }
+
+        match File::create(&path) {
+            Ok(mut f) => {
+                if let Err(e) = f.write_all(data) {
+                    error!("[Unicorn][CACHE] Failed to write {:?}: {}", path, e);
+                    return;
+                }
+            }
+            Err(e) => {
+                error!("[Unicorn][CACHE] Failed to create {:?}: {}", path, e);
+                return;
+            }
+        }
+
+        debug!("[Unicorn][CACHE] Stored {:?}", path);
+        self.prune();
+    }
+
+    /// Keep the `MAX_ENTRIES` last written files
+    pub fn prune(&self) {
+        let dir = match self.dir {
+            Some(ref dir) => dir,
+            None => return,
+        };
+
+        let mut entries: Vec<(PathBuf, u64)> = match fs::read_dir(dir) {
+            Ok(entries) => {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| {
+                        let modified = entry
+                            .metadata()
+                            .and_then(|metadata| metadata.modified())
+                            .ok()
+                            .and_then(|time| time.elapsed().ok())
+                            .map(|elapsed| elapsed.as_secs())
+                            .unwrap_or(0);
+                        (entry.path(), modified)
+                    })
+                    .collect()
+            }
+            Err(_) => return,
+        };
+
+        if entries.len() <= MAX_ENTRIES {
+            return;
+        }
+
+        // The youngest first
+        entries.sort_by_key(|&(_, age)| age);
+        for &(ref path, _) in entries[MAX_ENTRIES..].iter() {
+            if let Err(e) = fs::remove_file(path) {
+                warn!("[Unicorn][CACHE] Failed to remove {:?}: {}", path, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use super::ScriptCache;
+
+    #[test]
+    fn test_script_cache() {
+        let dir = env::temp_dir().join(format!("unicorn-script-cache-{}", &ScriptCache::key("test")[..16]));
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(ScriptCache::key("print(1)").len(), 64);
+        assert_eq!(ScriptCache::key("print(1)"), ScriptCache::key("print(1)"));
+        assert!(ScriptCache::key("print(1)") != ScriptCache::key("print(2)"));
+
+        let cache = ScriptCache::new(dir.clone());
+        assert_eq!(cache.load("print(1)", "luac"), None);
+
+        cache.store("print(1)", "luac", &[1, 2, 3]);
+        assert_eq!(cache.load("print(1)", "luac"), Some(vec![1, 2, 3]));
+        // Another code or language
+        assert_eq!(cache.load("print(2)", "luac"), None);
+        assert_eq!(cache.load("print(1)", "pyc"), None);
+
+        let disabled = ScriptCache::disabled();
+        disabled.store("print(1)", "luac", &[1]);
+        assert_eq!(disabled.load("print(1)", "luac"), None);
+        assert_eq!(disabled.path("print(1)", "luac"), None);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,290 @@
+//! Timers (`after`/`every`) and tweens (`tween`) of the cartridges, updated
+//! with the time of the game: they are frozen in the pause menu and follow
+//! `timer_scale` for the slow-motion. The callbacks stay in the script, it
+//! gets the timers fired and the values of the tweens before `_update`.
+
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Easing {
+    Linear,
+    InQuad,
+    OutQuad,
+    InOutQuad,
+    InCubic,
+    OutCubic,
+    InOutCubic,
+    InOutSine,
+    OutBack,
+    OutBounce,
+}
+
+impl Easing {
+    pub fn from_name(name: &str) -> Option<Easing> {
+        match name {
+            "linear" => Some(Easing::Linear),
+            "in_quad" => Some(Easing::InQuad),
+            "out_quad" => Some(Easing::OutQuad),
+            "in_out_quad" => Some(Easing::InOutQuad),
+            "in_cubic" => Some(Easing::InCubic),
+            "out_cubic" => Some(Easing::OutCubic),
+            "in_out_cubic" => Some(Easing::InOutCubic),
+            "in_out_sine" => Some(Easing::InOutSine),
+            "out_back" => Some(Easing::OutBack),
+            "out_bounce" => Some(Easing::OutBounce),
+            _ => None,
+        }
+    }
+
+    /// Progress of the tween for `t` between 0 and 1
+    pub fn apply(&self, t: f64) -> f64 {
+        match *self {
+            Easing::Linear => t,
+            Easing::InQuad => t * t,
+            Easing::OutQuad => t * (2.0 - t),
+            Easing::InOutQuad => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    -1.0 + (4.0 - 2.0 * t) * t
+                }
+            }
+            Easing::InCubic => t * t * t,
+            Easing::OutCubic => {
+                let t = t - 1.0;
+                t * t * t + 1.0
+            }
+            Easing::InOutCubic => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    let t = 2.0 * t - 2.0;
+                    0.5 * t * t * t + 1.0
+                }
+            }
+            Easing::InOutSine => -0.5 * ((PI * t).cos() - 1.0),
+            Easing::OutBack => {
+                let s = 1.70158;
+                let t = t - 1.0;
+                t * t * ((s + 1.0) * t + s) + 1.0
+            }
+            Easing::OutBounce => {
+                if t < 1.0 / 2.75 {
+                    7.5625 * t * t
+                } else if t < 2.0 / 2.75 {
+                    let t = t - 1.5 / 2.75;
+                    7.5625 * t * t + 0.75
+                } else if t < 2.5 / 2.75 {
+                    let t = t - 2.25 / 2.75;
+                    7.5625 * t * t + 0.9375
+                } else {
+                    let t = t - 2.625 / 2.75;
+                    7.5625 * t * t + 0.984375
+                }
+            }
+        }
+    }
+}
+
+struct Timer {
+    id: u32,
+    delay: f64,
+    remaining: f64,
+    repeat: bool,
+}
+
+struct Tween {
+    id: u32,
+    from: f64,
+    to: f64,
+    duration: f64,
+    elapsed: f64,
+    easing: Easing,
+}
+
+impl Tween {
+    fn value(&self) -> f64 {
+        if self.duration <= 0.0 || self.elapsed >= self.duration {
+            return self.to;
+        }
+        self.from + (self.to - self.from) * self.easing.apply(self.elapsed / self.duration)
+    }
+
+    fn is_done(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+pub struct Timers {
+    next_id: u32,
+    timers: Vec<Timer>,
+    tweens: Vec<Tween>,
+    /// Factor of the time, 0.5 for a slow-motion
+    pub scale: f64,
+    /// Timers fired since the script asked, with true for the last time
+    fired: VecDeque<(u32, bool)>,
+    /// Current values of the tweens, with true when they are finished
+    values: VecDeque<(u32, f64, bool)>,
+}
+
+impl Timers {
+    pub fn new() -> Timers {
+        Timers {
+            next_id: 1,
+            timers: Vec::new(),
+            tweens: Vec::new(),
+            scale: 1.0,
+            fired: VecDeque::new(),
+            values: VecDeque::new(),
+        }
+    }
+
+    /// Forget everything, when another cartridge is loaded
+    pub fn clear(&mut self) {
+        self.timers.clear();
+        self.tweens.clear();
+        self.fired.clear();
+        self.values.clear();
+        self.scale = 1.0;
+    }
+
+    fn new_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        id
+    }
+
+    /// Timer fired once after `delay` seconds, or every `delay` seconds
+    pub fn timer(&mut self, delay: f64, repeat: bool) -> u32 {
+        let id = self.new_id();
+        self.timers.push(Timer {
+                             id: id,
+                             delay: delay,
+                             remaining: delay,
+                             repeat: repeat,
+                         });
+        id
+    }
+
+    pub fn tween(&mut self, from: f64, to: f64, duration: f64, easing: Easing) -> u32 {
+        let id = self.new_id();
+        self.tweens.push(Tween {
+                             id: id,
+                             from: from,
+                             to: to,
+                             duration: duration,
+                             elapsed: 0.0,
+                             easing: easing,
+                         });
+        id
+    }
+
+    pub fn cancel(&mut self, id: u32) {
+        self.timers.retain(|timer| timer.id != id);
+        self.tweens.retain(|tween| tween.id != id);
+        self.fired.retain(|&(value, _)| value != id);
+        self.values.retain(|&(value, _, _)| value != id);
+    }
+
+    pub fn update(&mut self, dt: f64) {
+        let dt = dt * self.scale;
+
+        for timer in self.timers.iter_mut() {
+            timer.remaining -= dt;
+            if timer.remaining > 0.0 {
+                continue;
+            }
+
+            if !timer.repeat {
+                self.fired.push_back((timer.id, true));
+            } else if timer.delay <= 0.0 {
+                // Every frame
+                self.fired.push_back((timer.id, false));
+                timer.remaining = 0.0;
+            } else {
+                while timer.remaining <= 0.0 {
+                    self.fired.push_back((timer.id, false));
+                    timer.remaining += timer.delay;
+                }
+            }
+        }
+        self.timers.retain(|timer| timer.repeat || timer.remaining > 0.0);
+
+        // Only the last value of each tween is kept until the script reads it
+        self.values.clear();
+        for tween in self.tweens.iter_mut() {
+            tween.elapsed += dt;
+            self.values.push_back((tween.id, tween.value(), tween.is_done()));
+        }
+        self.tweens.retain(|tween| !tween.is_done());
+    }
+
+    pub fn next_fired(&mut self) -> Option<(u32, bool)> {
+        self.fired.pop_front()
+    }
+
+    pub fn next_value(&mut self) -> Option<(u32, f64, bool)> {
+        self.values.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Easing, Timers};
+
+    #[test]
+    fn test_easing() {
+        for name in ["linear", "in_quad", "out_quad", "in_out_quad", "in_cubic", "out_cubic",
+                     "in_out_cubic", "in_out_sine", "out_back", "out_bounce"]
+                    .iter() {
+            let easing = Easing::from_name(name).unwrap();
+            assert!(easing.apply(0.0).abs() < 1e-9);
+            assert!((easing.apply(1.0) - 1.0).abs() < 1e-9);
+        }
+        assert_eq!(Easing::from_name("elastic"), None);
+        assert_eq!(Easing::InQuad.apply(0.5), 0.25);
+    }
+
+    #[test]
+    fn test_timers() {
+        let mut timers = Timers::new();
+        let after = timers.timer(1.0, false);
+        let every = timers.timer(0.5, true);
+
+        timers.update(0.6);
+        assert_eq!(timers.next_fired(), Some((every, false)));
+        assert_eq!(timers.next_fired(), None);
+
+        timers.update(0.6);
+        assert_eq!(timers.next_fired(), Some((after, true)));
+        assert_eq!(timers.next_fired(), Some((every, false)));
+        assert_eq!(timers.next_fired(), None);
+
+        // Slow-motion
+        timers.scale = 0.5;
+        timers.update(0.4);
+        assert_eq!(timers.next_fired(), None);
+
+        timers.cancel(every);
+        timers.update(10.0);
+        assert_eq!(timers.next_fired(), None);
+    }
+
+    #[test]
+    fn test_tweens() {
+        let mut timers = Timers::new();
+        let id = timers.tween(10.0, 20.0, 2.0, Easing::Linear);
+
+        timers.update(1.0);
+        timers.update(0.5);
+        assert_eq!(timers.next_value(), Some((id, 17.5, false)));
+        assert_eq!(timers.next_value(), None);
+
+        timers.update(1.0);
+        assert_eq!(timers.next_value(), Some((id, 20.0, true)));
+
+        timers.update(1.0);
+        assert_eq!(timers.next_value(), None);
+    }
+}
@@ -0,0 +1,205 @@
+//! Execution trace of `--trace <file>`, in the Chrome trace format: the spans
+//! of the console in each frame (update, draw, audio, blit) and the sections
+//! of the cartridge between `trace_begin(name)` and `trace_end()`. The file
+//! is written at the exit, to open in chrome://tracing or ui.perfetto.dev.
+
+use std::fs::File;
+use std::io::Write;
+use std::time::Instant;
+
+use serde_json;
+
+/// About 4 hours at 60 FPS with a few sections, the recording stops after
+pub const MAX_TRACE_EVENTS: usize = 1000000;
+/// Sections begun and not ended, against a missing `trace_end` in a loop
+pub const MAX_TRACE_DEPTH: usize = 64;
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct TraceEvent {
+    pub name: String,
+    /// "console" or "cart"
+    pub cat: &'static str,
+    /// Complete event, with its duration
+    pub ph: &'static str,
+    /// Microseconds since the start of the trace
+    pub ts: f64,
+    pub dur: f64,
+    pub pid: u32,
+    pub tid: u32,
+}
+
+#[derive(Serialize)]
+struct TraceFile<'a> {
+    #[serde(rename = "traceEvents")]
+    trace_events: &'a [TraceEvent],
+    #[serde(rename = "displayTimeUnit")]
+    display_time_unit: &'static str,
+}
+
+pub struct Trace {
+    /// File written at the end, none when the trace is off
+    pub filename: Option<String>,
+    pub events: Vec<TraceEvent>,
+    start: Instant,
+    /// Name and start of the sections of the cartridge not ended yet
+    sections: Vec<(String, f64)>,
+}
+
+impl Trace {
+    pub fn new() -> Trace {
+        Trace {
+            filename: None,
+            events: Vec::new(),
+            start: Instant::now(),
+            sections: Vec::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.filename.is_some()
+    }
+
+    pub fn start(&mut self, filename: &str) {
+        info!("[Unicorn][TRACE] Recording in {:?}", filename);
+
+        self.filename = Some(filename.to_string());
+        self.events.clear();
+        self.sections.clear();
+        self.start = Instant::now();
+    }
+
+    fn micros(&self, instant: Instant) -> f64 {
+        if instant < self.start {
+            return 0.0;
+        }
+
+        let duration = instant.duration_since(self.start);
+        duration.as_secs() as f64 * 1000000.0 + duration.subsec_nanos() as f64 / 1000.0
+    }
+
+    fn push(&mut self, name: &str, cat: &'static str, ts: f64, dur: f64) {
+        if self.events.len() >= MAX_TRACE_EVENTS {
+            return;
+        }
+
+        self.events.push(TraceEvent {
+                             name: name.to_string(),
+                             cat: cat,
+                             ph: "X",
+                             ts: ts,
+                             dur: dur.max(0.0),
+                             pid: 1,
+                             tid: 1,
+                         });
+
+        if self.events.len() == MAX_TRACE_EVENTS {
+            warn!("[Unicorn][TRACE] {:?} events, the recording stops", MAX_TRACE_EVENTS);
+        }
+    }
+
+    /// Span of the console, measured by the frontend
+    pub fn span(&mut self, name: &str, start: Instant, end: Instant) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let ts = self.micros(start);
+        let dur = self.micros(end) - ts;
+        self.push(name, "console", ts, dur);
+    }
+
+    /// Section of the cartridge, until the next `end`
+    pub fn begin(&mut self, name: &str) {
+        if !self.is_enabled() || self.sections.len() >= MAX_TRACE_DEPTH {
+            return;
+        }
+
+        let ts = self.micros(Instant::now());
+        self.sections.push((name.to_string(), ts));
+    }
+
+    /// End of the last section begun, none without it
+    pub fn end(&mut self) {
+        let (name, ts) = match self.sections.pop() {
+            Some(section) => section,
+            None => return,
+        };
+
+        let dur = self.micros(Instant::now()) - ts;
+        self.push(&name, "cart", ts, dur);
+    }
+
+    pub fn to_json(&self) -> String {
+        let file = TraceFile {
+            trace_events: &self.events,
+            display_time_unit: "ms",
+        };
+        serde_json::to_string(&file).unwrap_or("{}".to_string())
+    }
+
+    /// End the sections still open and write the file, the trace is off after
+    pub fn stop(&mut self) -> Result<String, String> {
+        while !self.sections.is_empty() {
+            self.end();
+        }
+
+        let filename = match self.filename.take() {
+            Some(filename) => filename,
+            None => return Err("No trace".to_string()),
+        };
+
+        let mut file = match File::create(&filename) {
+            Ok(file) => file,
+            Err(e) => return Err(format!("Failed to create {:?}: {}", filename, e)),
+        };
+        if let Err(e) = file.write_all(self.to_json().as_bytes()) {
+            return Err(format!("Failed to write {:?}: {}", filename, e));
+        }
+
+        self.events.clear();
+        Ok(filename)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Trace, MAX_TRACE_DEPTH};
+
+    #[test]
+    fn test_trace() {
+        let mut trace = Trace::new();
+        trace.begin("off");
+        trace.end();
+        trace.span("update", Instant::now(), Instant::now());
+        assert!(trace.events.is_empty());
+
+        trace.start("trace.json");
+        let start = Instant::now();
+        trace.span("draw", start, start + Duration::from_millis(2));
+        assert_eq!(trace.events[0].name, "draw");
+        assert_eq!(trace.events[0].cat, "console");
+        assert!((trace.events[0].dur - 2000.0).abs() < 0.01);
+
+        trace.begin("physics");
+        trace.begin("collisions");
+        trace.end();
+        trace.end();
+        // Without a section
+        trace.end();
+        assert_eq!(trace.events.len(), 3);
+        assert_eq!(trace.events[1].name, "collisions");
+        assert_eq!(trace.events[2].name, "physics");
+        assert_eq!(trace.events[2].cat, "cart");
+        assert!(trace.events[2].ts <= trace.events[1].ts);
+
+        for _ in 0..MAX_TRACE_DEPTH + 10 {
+            trace.begin("loop");
+        }
+        for _ in 0..MAX_TRACE_DEPTH + 10 {
+            trace.end();
+        }
+        assert_eq!(trace.events.len(), 3 + MAX_TRACE_DEPTH);
+    }
+}
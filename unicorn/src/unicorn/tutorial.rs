@@ -0,0 +1,236 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde_json;
+
+use gfx::Screen;
+
+/// Number of frames of a step before displaying its hint
+pub const HINT_FRAMES: u32 = 600;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TutorialStep {
+    pub title: String,
+    #[serde(default)]
+    pub text: String,
+    /// Functions of the API the code must call to complete the step
+    #[serde(default)]
+    pub calls: Vec<String>,
+    #[serde(default)]
+    pub hint: String,
+}
+
+/// Lessons validated while the code of the cartridge runs:
+/// ```text
+/// {
+///     "title": "Shapes",
+///     "cart": "shapes.uni",
+///     "steps": [
+///         {"title": "A circle", "text": "Draw a circle in _draw", "calls": ["circfill"],
+///          "hint": "circfill(x, y, radius, color)"}
+///     ]
+/// }
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+pub struct Tutorial {
+    pub title: String,
+    #[serde(default)]
+    pub cart: Option<String>,
+    pub steps: Vec<TutorialStep>,
+    #[serde(skip)]
+    pub dir: PathBuf,
+    #[serde(skip)]
+    pub step: usize,
+    #[serde(skip)]
+    pub frames: u32,
+}
+
+/// Split the text in lines of `max_chars` at most, between the words
+pub fn wrap_text(text: &str, max_chars: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > max_chars {
+            lines.push(line);
+            line = String::new();
+        }
+
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+impl Tutorial {
+    pub fn from_str(data: &str, dir: &Path) -> Result<Tutorial, String> {
+        match serde_json::from_str::<Tutorial>(data) {
+            Ok(mut tutorial) => {
+                tutorial.dir = dir.to_path_buf();
+                Ok(tutorial)
+            }
+            Err(e) => Err(format!("Invalid tutorial: {}", e)),
+        }
+    }
+
+    pub fn from_file(filename: &str) -> Result<Tutorial, String> {
+        let mut data = String::new();
+        match File::open(filename) {
+            Ok(mut f) => {
+                if let Err(e) = f.read_to_string(&mut data) {
+                    return Err(format!("Failed to read {:?}: {}", filename, e));
+                }
+            }
+            Err(e) => return Err(format!("Failed to open {:?}: {}", filename, e)),
+        }
+
+        let dir = Path::new(filename).parent().unwrap_or(Path::new(""));
+        Tutorial::from_str(&data, dir)
+    }
+
+    /// Cartridge opened in the editor with the tutorial, relative to the tutorial
+    pub fn cart_path(&self) -> Option<String> {
+        self.cart.as_ref().map(|cart| self.dir.join(cart).to_string_lossy().into_owned())
+    }
+
+    /// All the functions used by the steps, to trace their calls
+    pub fn calls(&self) -> Vec<String> {
+        let mut calls = Vec::new();
+        for step in self.steps.iter() {
+            for call in step.calls.iter() {
+                if !calls.contains(call) {
+                    calls.push(call.clone());
+                }
+            }
+        }
+        calls
+    }
+
+    pub fn current(&self) -> Option<&TutorialStep> {
+        self.steps.get(self.step)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.step >= self.steps.len()
+    }
+
+    /// Move to the next step when the code called all the functions of the
+    /// current one, `count` returns the number of calls of a function
+    pub fn update<F>(&mut self, mut count: F) -> bool
+        where F: FnMut(&str) -> u32
+    {
+        let done = match self.current() {
+            Some(step) => step.calls.iter().all(|call| count(call) > 0),
+            None => return false,
+        };
+
+        self.frames += 1;
+
+        if done {
+            info!("[Unicorn][TUTORIAL] Step {:?} done", self.step);
+            self.step += 1;
+            self.frames = 0;
+        }
+
+        done
+    }
+
+    pub fn get_lines(&self, max_chars: usize) -> Vec<(String, i32)> {
+        let mut lines = Vec::new();
+
+        match self.current() {
+            Some(step) => {
+                lines.push((format!("{} {}/{}: {}",
+                                    self.title,
+                                    self.step + 1,
+                                    self.steps.len(),
+                                    step.title),
+                            10));
+                for line in wrap_text(&step.text, max_chars) {
+                    lines.push((line, 7));
+                }
+                if !step.hint.is_empty() && self.frames >= HINT_FRAMES {
+                    for line in wrap_text(&format!("HINT: {}", step.hint), max_chars) {
+                        lines.push((line, 12));
+                    }
+                }
+            }
+            None => lines.push((format!("{}: COMPLETE!", self.title), 11)),
+        }
+
+        lines
+    }
+
+    /// Banner at the bottom of the screen, above the game and the editor
+    pub fn draw(&self, screen: &mut Screen) {
+        let line_height = 8;
+        let max_chars = ((screen.width as i32 - 4) / screen.text_width("X")) as usize;
+
+        let lines = self.get_lines(max_chars);
+
+        let screen_width = screen.width as i32;
+        let height = lines.len() as i32 * line_height + 2;
+        let y = screen.height as i32 - height;
+
+        for j in 0..height {
+            for i in 0..screen_width {
+                screen.putpixel_direct(i, y + j, 1);
+            }
+        }
+
+        for (idx, &(ref line, color)) in lines.iter().enumerate() {
+            screen.force_print(line.clone(), 2, y + 2 + idx as i32 * line_height, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    use super::{wrap_text, Tutorial, HINT_FRAMES};
+
+    #[test]
+    fn test_wrap_text() {
+        assert_eq!(wrap_text("draw a circle with circfill", 12),
+                   vec!["draw a", "circle with", "circfill"]);
+        assert!(wrap_text("", 12).is_empty());
+    }
+
+    #[test]
+    fn test_tutorial_steps() {
+        let data = r#"{"title": "Shapes", "cart": "shapes.uni", "steps": [
+                        {"title": "Clear", "calls": ["cls"]},
+                        {"title": "Circle", "calls": ["cls", "circfill"], "hint": "circfill(x, y, r, col)"}
+                      ]}"#;
+        let mut tutorial = Tutorial::from_str(data, Path::new("lessons")).unwrap();
+        assert_eq!(tutorial.calls(), vec!["cls".to_string(), "circfill".to_string()]);
+        assert_eq!(tutorial.cart_path(),
+                   Some(Path::new("lessons").join("shapes.uni").to_string_lossy().into_owned()));
+
+        let mut counts = HashMap::new();
+        assert!(!tutorial.update(|name| *counts.get(name).unwrap_or(&0)));
+
+        counts.insert("cls", 1);
+        assert!(tutorial.update(|name| *counts.get(name).unwrap_or(&0)));
+        assert_eq!(tutorial.step, 1);
+
+        for _ in 0..HINT_FRAMES {
+            tutorial.update(|name| *counts.get(name).unwrap_or(&0));
+        }
+        assert!(tutorial.get_lines(40).iter().any(|line| line.0.starts_with("HINT")));
+
+        counts.insert("circfill", 3);
+        assert!(tutorial.update(|name| *counts.get(name).unwrap_or(&0)));
+        assert!(tutorial.is_finished());
+        assert!(!tutorial.update(|_| 1));
+    }
+}
@@ -0,0 +1,203 @@
+//! Video recording (F9): the frames are piped to ffmpeg as they are drawn,
+//! at the FPS of the cartridge and without the limit of the GIF records. The format follows
+//! the extension of the file, MP4 (H.264) or WebM (VP9), and the size the
+//! `scale` of the record config. ffmpeg is searched in the PATH, or given
+//! by the UNICORN_FFMPEG variable.
+
+use std::env;
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VideoFormat {
+    MP4,
+    WebM,
+}
+
+impl VideoFormat {
+    pub fn from_filename(filename: &str) -> Option<VideoFormat> {
+        let filename = filename.to_lowercase();
+        if filename.ends_with(".mp4") {
+            Some(VideoFormat::MP4)
+        } else if filename.ends_with(".webm") {
+            Some(VideoFormat::WebM)
+        } else {
+            None
+        }
+    }
+
+    fn codec_args(&self) -> Vec<&'static str> {
+        match *self {
+            VideoFormat::MP4 => {
+                vec!["-c:v", "libx264", "-preset", "veryfast", "-crf", "18", "-pix_fmt", "yuv420p"]
+            }
+            VideoFormat::WebM => {
+                vec!["-c:v", "libvpx-vp9", "-deadline", "realtime", "-crf", "20", "-b:v", "0",
+                     "-pix_fmt", "yuv420p"]
+            }
+        }
+    }
+}
+
+/// The encoders need an even width and height
+pub fn even_size(width: usize, height: usize, scale: f64) -> (usize, usize) {
+    let width = ((width as f64 * scale).round() as usize).max(2);
+    let height = ((height as f64 * scale).round() as usize).max(2);
+    (width + width % 2, height + height % 2)
+}
+
+/// Arguments of ffmpeg: the raw RGB frames are read on stdin and resized
+/// with the nearest pixels
+pub fn ffmpeg_args(format: VideoFormat,
+                   width: usize,
+                   height: usize,
+                   scale: f64,
+                   fps: u32,
+                   filename: &str)
+                   -> Vec<String> {
+    let (video_width, video_height) = even_size(width, height, scale);
+
+    let mut args: Vec<String> = vec!["-y", "-loglevel", "error", "-f", "rawvideo", "-pix_fmt",
+                                     "rgb24"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+
+    args.push("-s".to_string());
+    args.push(format!("{}x{}", width, height));
+    args.push("-r".to_string());
+    args.push(format!("{}", fps));
+    args.push("-i".to_string());
+    args.push("-".to_string());
+    args.push("-vf".to_string());
+    args.push(format!("scale={}:{}:flags=neighbor", video_width, video_height));
+    args.extend(format.codec_args().iter().map(|arg| arg.to_string()));
+    args.push(filename.to_string());
+    args
+}
+
+pub struct VideoRecord {
+    child: Option<Child>,
+    pub filename: String,
+    pub frames: u32,
+}
+
+impl VideoRecord {
+    pub fn new() -> VideoRecord {
+        VideoRecord {
+            child: None,
+            filename: "".to_string(),
+            frames: 0,
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.child.is_some()
+    }
+
+    pub fn start(&mut self,
+                 filename: &str,
+                 width: usize,
+                 height: usize,
+                 scale: f64,
+                 fps: u32)
+                 -> Result<(), String> {
+        let format = match VideoFormat::from_filename(filename) {
+            Some(format) => format,
+            None => return Err(format!("Unknown video format {:?}, .mp4 or .webm", filename)),
+        };
+
+        let ffmpeg = env::var("UNICORN_FFMPEG").unwrap_or("ffmpeg".to_string());
+        let child = Command::new(&ffmpeg)
+            .args(&ffmpeg_args(format, width, height, scale, fps, filename))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn();
+
+        match child {
+            Ok(child) => {
+                self.child = Some(child);
+                self.filename = filename.to_string();
+                self.frames = 0;
+                Ok(())
+            }
+            Err(e) => Err(format!("Failed to run {:?}: {}", ffmpeg, e)),
+        }
+    }
+
+    /// RGB frame, row by row
+    pub fn push(&mut self, frame: &[u8]) -> Result<(), String> {
+        let result = match self.child {
+            Some(ref mut child) => {
+                match child.stdin.as_mut() {
+                    Some(stdin) => stdin.write_all(frame),
+                    None => return Err("No input for ffmpeg".to_string()),
+                }
+            }
+            None => return Ok(()),
+        };
+
+        match result {
+            Ok(_) => {
+                self.frames += 1;
+                Ok(())
+            }
+            Err(e) => {
+                // ffmpeg stopped, the video is finished with the frames sent
+                let _ = self.stop();
+                Err(format!("Failed to send the frame to ffmpeg: {}", e))
+            }
+        }
+    }
+
+    /// Close the input of ffmpeg and wait for the end of the file
+    pub fn stop(&mut self) -> Result<(), String> {
+        let mut child = match self.child.take() {
+            Some(child) => child,
+            None => return Ok(()),
+        };
+
+        drop(child.stdin.take());
+        match child.wait() {
+            Ok(status) => {
+                if status.success() {
+                    Ok(())
+                } else {
+                    Err(format!("ffmpeg failed with {}", status))
+                }
+            }
+            Err(e) => Err(format!("Failed to wait for ffmpeg: {}", e)),
+        }
+    }
+}
+
+impl Drop for VideoRecord {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{even_size, ffmpeg_args, VideoFormat};
+
+    #[test]
+    fn test_video_format() {
+        assert_eq!(VideoFormat::from_filename("record.MP4"), Some(VideoFormat::MP4));
+        assert_eq!(VideoFormat::from_filename("record.webm"), Some(VideoFormat::WebM));
+        assert_eq!(VideoFormat::from_filename("record.gif"), None);
+    }
+
+    #[test]
+    fn test_ffmpeg_args() {
+        assert_eq!(even_size(128, 127, 1.0), (128, 128));
+        assert_eq!(even_size(3, 3, 0.25), (2, 2));
+
+        let args = ffmpeg_args(VideoFormat::WebM, 128, 128, 2.0, 30, "record.webm");
+        assert!(args.contains(&"128x128".to_string()));
+        assert!(args.contains(&"scale=256:256:flags=neighbor".to_string()));
+        assert!(args.contains(&"libvpx-vp9".to_string()));
+        assert!(args.contains(&"30".to_string()));
+        assert_eq!(args.last(), Some(&"record.webm".to_string()));
+    }
+}
@@ -0,0 +1,176 @@
+//! Watch expressions: Lua or Python expressions of the cartridge given by
+//! `--watch`, by `:watch` in the developer console or by the `watch` command
+//! of the editors, evaluated after each `_update` and shown in a panel at the
+//! bottom of the screen while the game runs. An expression which fails shows
+//! its error, in another color.
+
+use gfx::Screen;
+
+/// Expressions watched at once
+pub const MAX_WATCHES: usize = 16;
+pub const MAX_EXPRESSION_LEN: usize = 256;
+/// Longest value kept, a big table is cut
+pub const MAX_VALUE_LEN: usize = 256;
+
+const LINE_HEIGHT: i32 = 8;
+
+// Dithered background, like the help overlay
+const DITHER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Watch {
+    pub expression: String,
+    /// Value of the last evaluation, or its error
+    pub value: Result<String, String>,
+}
+
+pub struct Watches {
+    pub watches: Vec<Watch>,
+    pub background: u32,
+    pub color: u32,
+    pub error_color: u32,
+}
+
+/// The first chars of the text
+fn truncate(text: &str, len: usize) -> String {
+    text.chars().take(len).collect()
+}
+
+impl Watches {
+    pub fn new() -> Watches {
+        Watches {
+            watches: Vec::new(),
+            background: 0,
+            color: 7,
+            error_color: 8,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watches.is_empty()
+    }
+
+    /// Watch the expression, already watched ones are kept once
+    pub fn add(&mut self, expression: &str) -> Result<(), String> {
+        let expression = expression.trim();
+        if expression.is_empty() {
+            return Err("Empty expression".to_string());
+        }
+        if expression.len() > MAX_EXPRESSION_LEN || expression.contains('\0') {
+            return Err(format!("Invalid expression {:?}", truncate(expression, 32)));
+        }
+
+        if self.watches.iter().any(|watch| watch.expression == expression) {
+            return Ok(());
+        }
+        if self.watches.len() >= MAX_WATCHES {
+            return Err(format!("No more than {} watches", MAX_WATCHES));
+        }
+
+        self.watches.push(Watch {
+                              expression: expression.to_string(),
+                              value: Ok("".to_string()),
+                          });
+        Ok(())
+    }
+
+    pub fn remove(&mut self, expression: &str) -> bool {
+        let len = self.watches.len();
+        self.watches.retain(|watch| watch.expression != expression.trim());
+        self.watches.len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.watches.clear();
+    }
+
+    /// New values of all the expressions, from `eval` of the plugin
+    pub fn evaluate<F>(&mut self, mut eval: F)
+        where F: FnMut(&str) -> Result<String, String>
+    {
+        for watch in self.watches.iter_mut() {
+            watch.value = match eval(&watch.expression) {
+                Ok(value) => Ok(truncate(&value, MAX_VALUE_LEN)),
+                Err(e) => Err(truncate(e.lines().next().unwrap_or(""), MAX_VALUE_LEN)),
+            };
+        }
+    }
+
+    /// "expression = value" for each watch, cut to `columns` chars, and
+    /// whether it's an error
+    pub fn get_lines(&self, columns: usize) -> Vec<(String, bool)> {
+        self.watches
+            .iter()
+            .map(|watch| match watch.value {
+                     Ok(ref value) => {
+                         (truncate(&format!("{} = {}", watch.expression, value), columns), false)
+                     }
+                     Err(ref e) => {
+                         (truncate(&format!("{} ! {}", watch.expression, e), columns), true)
+                     }
+                 })
+            .collect()
+    }
+
+    pub fn draw(&self, screen: &mut Screen) {
+        if self.watches.is_empty() {
+            return;
+        }
+
+        let char_width = screen.text_width("0").max(1);
+        let columns = ((screen.width as i32 - 4) / char_width).max(1) as usize;
+        let lines = self.get_lines(columns);
+
+        let screen_width = screen.width as i32;
+        let screen_height = screen.height as i32;
+        let top = screen_height - 2 - lines.len() as i32 * LINE_HEIGHT;
+        for j in top.max(0)..screen_height {
+            for i in 0..screen_width {
+                if DITHER[(j % 4) as usize][(i % 4) as usize] < 12 {
+                    screen.putpixel_direct(i, j, self.background);
+                }
+            }
+        }
+
+        for (idx, &(ref line, error)) in lines.iter().enumerate() {
+            let color = if error { self.error_color } else { self.color };
+            screen.force_print(line.clone(), 2, top + 1 + idx as i32 * LINE_HEIGHT, color as i32);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Watches, MAX_WATCHES};
+
+    #[test]
+    fn test_watches() {
+        let mut watches = Watches::new();
+        assert!(watches.add("  ").is_err());
+        assert!(watches.add("player.x").is_ok());
+        assert!(watches.add(" player.x ").is_ok());
+        assert!(watches.add("nope()").is_ok());
+        assert_eq!(watches.watches.len(), 2);
+
+        watches.evaluate(|expression| if expression == "player.x" {
+                             Ok("12".to_string())
+                         } else {
+                             Err("attempt to call a nil value\nstack traceback:".to_string())
+                         });
+        assert_eq!(watches.get_lines(32),
+                   vec![("player.x = 12".to_string(), false),
+                        ("nope() ! attempt to call a nil v".to_string(), true)]);
+
+        assert!(watches.remove("nope()"));
+        assert!(!watches.remove("nope()"));
+
+        for idx in 0..MAX_WATCHES {
+            let _ = watches.add(&format!("x{}", idx));
+        }
+        assert_eq!(watches.watches.len(), MAX_WATCHES);
+        assert!(watches.add("y").is_err());
+
+        watches.clear();
+        assert!(watches.is_empty());
+    }
+}